@@ -75,6 +75,7 @@ impl AppStoreConnectClient {
         if response.status().is_success() {
             Ok(response)
         } else {
+            let status = response.status().as_u16();
             let body = response.bytes()?;
 
             let message = if let Ok(value) = serde_json::from_slice::<Value>(body.as_ref()) {
@@ -84,6 +85,7 @@ impl AppStoreConnectClient {
             };
 
             Err(AppStoreConnectError {
+                status,
                 method,
                 url,
                 message,
@@ -96,7 +98,15 @@ impl AppStoreConnectClient {
 #[derive(Clone, Debug, Error)]
 #[error("appstore connect error:\n{method} {url}\n{message}")]
 pub struct AppStoreConnectError {
+    status: u16,
     method: String,
     url: String,
     message: String,
 }
+
+impl AppStoreConnectError {
+    /// The HTTP status code of the failed response.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+}
@@ -127,6 +127,14 @@ impl SubmissionResponse {
     }
 }
 
+/// The notary service’s response to a request for a list of previous submissions.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubmissionListResponse {
+    pub data: Vec<SubmissionResponseData>,
+    pub meta: Value,
+}
+
 #[derive(Clone, Copy, Debug, Error)]
 #[error("notarization {0}")]
 pub struct NotarizationError(SubmissionResponseStatus);
@@ -155,6 +163,95 @@ pub struct SubmissionLogResponse {
     pub meta: Value,
 }
 
+/// A file whose notarization ticket is recorded in a notarization log.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotarizationLogTicketContent {
+    pub path: String,
+    pub digest_algorithm: Option<String>,
+    pub cdhash: Option<String>,
+    pub arch: Option<String>,
+}
+
+/// A problem found by the notary service.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotarizationLogIssue {
+    /// Severity of the issue. e.g. `error` or `warning`.
+    pub severity: String,
+    pub code: Option<i64>,
+    /// Path of the offending file within the submission.
+    pub path: Option<String>,
+    pub message: String,
+    pub doc_url: Option<String>,
+    /// Architecture of the offending Mach-O binary.
+    pub architecture: Option<String>,
+}
+
+impl NotarizationLogIssue {
+    /// Whether the issue is an error, which causes notarization to fail.
+    pub fn is_error(&self) -> bool {
+        self.severity.eq_ignore_ascii_case("error")
+    }
+}
+
+impl std::fmt::Display for NotarizationLogIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.severity)?;
+        if let Some(path) = &self.path {
+            write!(f, ": {path}")?;
+        }
+        if let Some(arch) = &self.architecture {
+            write!(f, " ({arch})")?;
+        }
+        write!(f, ": {}", self.message)?;
+        if let Some(url) = &self.doc_url {
+            write!(f, " (see {url})")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The developer log of a completed submission.
+///
+/// Fields not described here are ignored.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotarizationLog {
+    pub job_id: String,
+    pub status: SubmissionResponseStatus,
+    pub status_summary: Option<String>,
+    pub status_code: Option<i64>,
+    pub archive_filename: Option<String>,
+    pub upload_date: Option<String>,
+    pub sha256: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub ticket_contents: Vec<NotarizationLogTicketContent>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub issues: Vec<NotarizationLogIssue>,
+}
+
+impl NotarizationLog {
+    /// Parse a log from the JSON document returned by the notary service.
+    pub fn from_value(value: Value) -> serde_json::Result<Self> {
+        serde_json::from_value(value)
+    }
+
+    /// Issues that cause notarization to fail.
+    pub fn errors(&self) -> impl Iterator<Item = &NotarizationLogIssue> {
+        self.issues.iter().filter(|issue| issue.is_error())
+    }
+}
+
+fn null_as_default<'de, D, T>(deserializer: D) -> std::result::Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
 impl AppStoreConnectClient {
     /// Create a submission to the Notary API.
     pub fn create_submission(
@@ -195,6 +292,22 @@ impl AppStoreConnectClient {
         Ok(self.send_request(req)?.json()?)
     }
 
+    /// Fetch a list of previous Notary API submissions.
+    ///
+    /// This is a cheap authenticated request and can be used to validate that
+    /// API credentials are accepted by the Notary API.
+    pub fn list_submissions(&self) -> Result<SubmissionListResponse> {
+        let token = self.get_token()?;
+
+        let req = self
+            .client
+            .get(APPLE_NOTARY_SUBMIT_SOFTWARE_URL)
+            .bearer_auth(token)
+            .header("Accept", "application/json");
+
+        Ok(self.send_request(req)?.json()?)
+    }
+
     /// Fetch details about a single completed notarization.
     pub fn get_submission_log(&self, submission_id: &str) -> Result<Value> {
         let token = self.get_token()?;
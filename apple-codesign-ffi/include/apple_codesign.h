@@ -0,0 +1,223 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+/* Generated by cbindgen from the apple-codesign-ffi crate. Do not edit. */
+
+#ifndef APPLE_CODESIGN_H
+#define APPLE_CODESIGN_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Outcome of a fallible function.
+ */
+typedef enum AcsStatus {
+  /**
+   * The operation succeeded.
+   */
+  ACS_STATUS_OK = 0,
+  /**
+   * An argument was NULL or not valid UTF-8.
+   */
+  ACS_STATUS_INVALID_ARGUMENT = 1,
+  /**
+   * The operation failed.
+   */
+  ACS_STATUS_ERROR = 2,
+  /**
+   * The operation panicked. This is a bug.
+   */
+  ACS_STATUS_PANIC = 3,
+} AcsStatus;
+
+/**
+ * Parsed Mach-O data: a single binary or each binary of a universal binary.
+ */
+typedef struct AcsMachO AcsMachO;
+
+/**
+ * Problems found by [acs_verify_path()].
+ */
+typedef struct AcsVerifyResult AcsVerifyResult;
+
+/**
+ * Options for [acs_sign_path()]. NULL fields use defaults.
+ *
+ * Fields may be added to the end in later versions. Callers must set
+ * `struct_size` so fields they don't know about are treated as NULL.
+ */
+typedef struct AcsSignOptions {
+  /**
+   * Must be `sizeof(AcsSignOptions)`.
+   */
+  size_t struct_size;
+  /**
+   * PKCS#12 file holding the signing certificate and key. NULL signs ad-hoc.
+   */
+  const char *p12_path;
+  /**
+   * Password decrypting `p12_path`. NULL is the empty password.
+   */
+  const char *p12_password;
+  /**
+   * Team identifier to record. NULL derives it from the certificate.
+   */
+  const char *team_id;
+  /**
+   * Time-stamp server URL. NULL uses Apple's server. An empty string
+   * disables time-stamping.
+   */
+  const char *time_stamp_url;
+} AcsSignOptions;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Message describing why the last failed call on this thread failed.
+ *
+ * Returns NULL if the last call succeeded. The string is valid until the
+ * next call into this library on the same thread.
+ */
+const char *acs_last_error_message(void);
+
+/**
+ * Parse `len` bytes of Mach-O data at `data`.
+ *
+ * Universal binaries are split into their architectures. On success `*out`
+ * receives a handle to release with [acs_macho_free()]. The data is not
+ * referenced after this returns.
+ *
+ * # Safety
+ *
+ * `data` must point to `len` readable bytes. `out` must be a valid pointer.
+ */
+AcsStatus acs_macho_parse(const uint8_t *data, size_t len, AcsMachO **out);
+
+/**
+ * Release a handle obtained from [acs_macho_parse()]. NULL is ignored.
+ *
+ * # Safety
+ *
+ * `macho` must be NULL or a handle that hasn't been freed.
+ */
+void acs_macho_free(AcsMachO *macho);
+
+/**
+ * Number of binaries in parsed Mach-O data. Returns 0 if `macho` is NULL.
+ *
+ * # Safety
+ *
+ * `macho` must be NULL or a live handle.
+ */
+size_t acs_macho_count(const AcsMachO *macho);
+
+/**
+ * Whether the binary at `index` has an embedded code signature.
+ *
+ * # Safety
+ *
+ * `macho` must be NULL or a live handle.
+ */
+bool acs_macho_is_signed(const AcsMachO *macho, size_t index);
+
+/**
+ * Signing identifier of the binary at `index`.
+ *
+ * Returns NULL if the binary isn't signed or `index` is out of range.
+ *
+ * # Safety
+ *
+ * `macho` must be NULL or a live handle.
+ */
+const char *acs_macho_identifier(const AcsMachO *macho, size_t index);
+
+/**
+ * Team identifier of the binary at `index`.
+ *
+ * Returns NULL if the signature doesn't record a team identifier.
+ *
+ * # Safety
+ *
+ * `macho` must be NULL or a live handle.
+ */
+const char *acs_macho_team_id(const AcsMachO *macho, size_t index);
+
+/**
+ * Hex encoded cdhash of the primary code directory of the binary at `index`.
+ *
+ * Returns NULL if the binary isn't signed or `index` is out of range.
+ *
+ * # Safety
+ *
+ * `macho` must be NULL or a live handle.
+ */
+const char *acs_macho_cdhash(const AcsMachO *macho, size_t index);
+
+/**
+ * Verify the signature of the Mach-O binary at `path`.
+ *
+ * On success `*out` receives the problems found, to release with
+ * [acs_verify_result_free()]. Problems with the signature are not failures of
+ * this function: check [acs_verify_result_count()].
+ *
+ * # Safety
+ *
+ * `path` must be a NUL terminated string. `out` must be a valid pointer.
+ */
+AcsStatus acs_verify_path(const char *path, AcsVerifyResult **out);
+
+/**
+ * Release a result obtained from [acs_verify_path()]. NULL is ignored.
+ *
+ * # Safety
+ *
+ * `result` must be NULL or a result that hasn't been freed.
+ */
+void acs_verify_result_free(AcsVerifyResult *result);
+
+/**
+ * Number of problems found. 0 means verification found no problems.
+ *
+ * # Safety
+ *
+ * `result` must be NULL or a live result.
+ */
+size_t acs_verify_result_count(const AcsVerifyResult *result);
+
+/**
+ * Human readable description of the problem at `index`.
+ *
+ * Returns NULL if `index` is out of range.
+ *
+ * # Safety
+ *
+ * `result` must be NULL or a live result.
+ */
+const char *acs_verify_result_message(const AcsVerifyResult *result, size_t index);
+
+/**
+ * Sign the entity at `input_path` and write the result to `output_path`.
+ *
+ * Mach-O binaries, bundles, DMGs, and flat packages are supported. The paths
+ * may be the same to sign in place. A NULL `options` signs ad-hoc.
+ *
+ * # Safety
+ *
+ * The paths must be NUL terminated strings. `options` must be NULL or point
+ * to an [AcsSignOptions] initialized up to its `struct_size`.
+ */
+AcsStatus acs_sign_path(const char *input_path,
+                        const char *output_path,
+                        const AcsSignOptions *options);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* APPLE_CODESIGN_H */
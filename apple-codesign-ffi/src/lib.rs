@@ -0,0 +1,791 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! C interface to Apple code signing.
+//!
+//! This crate exposes signature inspection, verification, and signing as
+//! `extern "C"` functions so non-Rust build systems can link against
+//! [apple_codesign] directly. The C declarations are in
+//! `include/apple_codesign.h`, which is generated by cbindgen.
+//!
+//! Fallible functions return an [AcsStatus]. When it isn't
+//! [AcsStatus::Ok], [acs_last_error_message()] describes the failure.
+//!
+//! Strings returned by this interface are borrowed from the handle they were
+//! obtained from and remain valid until that handle is freed. Panics never
+//! unwind across the interface: they are reported as [AcsStatus::Panic].
+
+use {
+    apple_codesign::{
+        AppleCodesignError, IdentityResolver, MachFile, SigningIdentity, SigningSettings,
+        UnifiedSigner, APPLE_TIMESTAMP_URL,
+    },
+    std::{
+        cell::RefCell,
+        ffi::{c_char, CStr, CString},
+        panic::{self, AssertUnwindSafe},
+        ptr,
+    },
+};
+
+/// Outcome of a fallible function.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AcsStatus {
+    /// The operation succeeded.
+    Ok = 0,
+    /// An argument was NULL or not valid UTF-8.
+    InvalidArgument = 1,
+    /// The operation failed.
+    Error = 2,
+    /// The operation panicked. This is a bug.
+    Panic = 3,
+}
+
+/// Reason a function failed, recorded as the thread's last error.
+struct Failure {
+    status: AcsStatus,
+    message: String,
+}
+
+impl Failure {
+    fn invalid_argument(message: impl ToString) -> Self {
+        Self {
+            status: AcsStatus::InvalidArgument,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<AppleCodesignError> for Failure {
+    fn from(e: AppleCodesignError) -> Self {
+        Self {
+            status: AcsStatus::Error,
+            message: e.to_string(),
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Convert a message to a C string, escaping interior NUL bytes.
+fn message_cstring(message: &str) -> CString {
+    CString::new(message.replace('\0', "\\0")).expect("NUL bytes were removed")
+}
+
+fn set_last_error(message: Option<String>) {
+    LAST_ERROR.with(|last| {
+        *last.borrow_mut() = message.map(|s| message_cstring(&s));
+    });
+}
+
+/// Run `f`, recording its failure as the last error.
+fn run(f: impl FnOnce() -> Result<(), Failure>) -> AcsStatus {
+    set_last_error(None);
+
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => AcsStatus::Ok,
+        Ok(Err(failure)) => {
+            set_last_error(Some(failure.message));
+            failure.status
+        }
+        Err(_) => {
+            set_last_error(Some(
+                "panic in apple-codesign (please report this issue)".into(),
+            ));
+            AcsStatus::Panic
+        }
+    }
+}
+
+/// Resolve a required string argument.
+unsafe fn str_arg<'a>(name: &str, value: *const c_char) -> Result<&'a str, Failure> {
+    optional_str_arg(name, value)?
+        .ok_or_else(|| Failure::invalid_argument(format!("{name} must not be NULL")))
+}
+
+/// Resolve a string argument that may be NULL.
+unsafe fn optional_str_arg<'a>(
+    name: &str,
+    value: *const c_char,
+) -> Result<Option<&'a str>, Failure> {
+    if value.is_null() {
+        Ok(None)
+    } else {
+        CStr::from_ptr(value)
+            .to_str()
+            .map(Some)
+            .map_err(|_| Failure::invalid_argument(format!("{name} is not valid UTF-8")))
+    }
+}
+
+/// Resolve a required out parameter.
+unsafe fn out_arg<'a, T>(name: &str, out: *mut *mut T) -> Result<&'a mut *mut T, Failure> {
+    out.as_mut()
+        .ok_or_else(|| Failure::invalid_argument(format!("{name} must not be NULL")))
+}
+
+fn optional_cstring(value: Option<impl AsRef<str>>) -> Option<CString> {
+    value.and_then(|s| CString::new(s.as_ref()).ok())
+}
+
+fn cstring_ptr(value: Option<&CString>) -> *const c_char {
+    value.map(|s| s.as_ptr()).unwrap_or(ptr::null())
+}
+
+/// Message describing why the last failed call on this thread failed.
+///
+/// Returns NULL if the last call succeeded. The string is valid until the
+/// next call into this library on the same thread.
+#[no_mangle]
+pub extern "C" fn acs_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| cstring_ptr(last.borrow().as_ref()))
+}
+
+/// Signature metadata of a single Mach-O binary.
+struct Binary {
+    signed: bool,
+    identifier: Option<CString>,
+    team_id: Option<CString>,
+    cdhash: Option<CString>,
+}
+
+/// Parsed Mach-O data: a single binary or each binary of a universal binary.
+pub struct AcsMachO {
+    binaries: Vec<Binary>,
+}
+
+impl AcsMachO {
+    fn parse(data: &[u8]) -> Result<Self, AppleCodesignError> {
+        let mut binaries = vec![];
+
+        for macho in MachFile::parse(data)?.iter_macho() {
+            let binary = if let Some(signature) = macho.code_signature()? {
+                let cd = signature.code_directory()?;
+                let cdhash = signature.cdhashes()?.into_iter().next();
+
+                Binary {
+                    signed: true,
+                    identifier: optional_cstring(cd.as_ref().map(|cd| &cd.ident)),
+                    team_id: optional_cstring(cd.as_ref().and_then(|cd| cd.team_name.as_ref())),
+                    cdhash: optional_cstring(cdhash.map(|(_, digest)| hex::encode(digest))),
+                }
+            } else {
+                Binary {
+                    signed: false,
+                    identifier: None,
+                    team_id: None,
+                    cdhash: None,
+                }
+            };
+
+            binaries.push(binary);
+        }
+
+        Ok(Self { binaries })
+    }
+
+    unsafe fn binary<'a>(macho: *const Self, index: usize) -> Option<&'a Binary> {
+        macho.as_ref().and_then(|macho| macho.binaries.get(index))
+    }
+}
+
+/// Parse `len` bytes of Mach-O data at `data`.
+///
+/// Universal binaries are split into their architectures. On success `*out`
+/// receives a handle to release with [acs_macho_free()]. The data is not
+/// referenced after this returns.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes. `out` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn acs_macho_parse(
+    data: *const u8,
+    len: usize,
+    out: *mut *mut AcsMachO,
+) -> AcsStatus {
+    run(|| {
+        let out = out_arg("out", out)?;
+
+        if data.is_null() {
+            return Err(Failure::invalid_argument("data must not be NULL"));
+        }
+
+        let macho = AcsMachO::parse(std::slice::from_raw_parts(data, len))?;
+        *out = Box::into_raw(Box::new(macho));
+
+        Ok(())
+    })
+}
+
+/// Release a handle obtained from [acs_macho_parse()]. NULL is ignored.
+///
+/// # Safety
+///
+/// `macho` must be NULL or a handle that hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn acs_macho_free(macho: *mut AcsMachO) {
+    if !macho.is_null() {
+        drop(Box::from_raw(macho));
+    }
+}
+
+/// Number of binaries in parsed Mach-O data. Returns 0 if `macho` is NULL.
+///
+/// # Safety
+///
+/// `macho` must be NULL or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn acs_macho_count(macho: *const AcsMachO) -> usize {
+    macho
+        .as_ref()
+        .map(|macho| macho.binaries.len())
+        .unwrap_or(0)
+}
+
+/// Whether the binary at `index` has an embedded code signature.
+///
+/// # Safety
+///
+/// `macho` must be NULL or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn acs_macho_is_signed(macho: *const AcsMachO, index: usize) -> bool {
+    AcsMachO::binary(macho, index)
+        .map(|binary| binary.signed)
+        .unwrap_or(false)
+}
+
+/// Signing identifier of the binary at `index`.
+///
+/// Returns NULL if the binary isn't signed or `index` is out of range.
+///
+/// # Safety
+///
+/// `macho` must be NULL or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn acs_macho_identifier(
+    macho: *const AcsMachO,
+    index: usize,
+) -> *const c_char {
+    cstring_ptr(AcsMachO::binary(macho, index).and_then(|binary| binary.identifier.as_ref()))
+}
+
+/// Team identifier of the binary at `index`.
+///
+/// Returns NULL if the signature doesn't record a team identifier.
+///
+/// # Safety
+///
+/// `macho` must be NULL or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn acs_macho_team_id(macho: *const AcsMachO, index: usize) -> *const c_char {
+    cstring_ptr(AcsMachO::binary(macho, index).and_then(|binary| binary.team_id.as_ref()))
+}
+
+/// Hex encoded cdhash of the primary code directory of the binary at `index`.
+///
+/// Returns NULL if the binary isn't signed or `index` is out of range.
+///
+/// # Safety
+///
+/// `macho` must be NULL or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn acs_macho_cdhash(macho: *const AcsMachO, index: usize) -> *const c_char {
+    cstring_ptr(AcsMachO::binary(macho, index).and_then(|binary| binary.cdhash.as_ref()))
+}
+
+/// Problems found by [acs_verify_path()].
+pub struct AcsVerifyResult {
+    messages: Vec<CString>,
+}
+
+impl AcsVerifyResult {
+    /// Record one message per problem.
+    fn new<'a>(messages: impl IntoIterator<Item = &'a str>) -> Self {
+        Self {
+            messages: messages.into_iter().map(message_cstring).collect(),
+        }
+    }
+}
+
+/// Verify the signature of the Mach-O binary at `path`.
+///
+/// On success `*out` receives the problems found, to release with
+/// [acs_verify_result_free()]. Problems with the signature are not failures of
+/// this function: check [acs_verify_result_count()].
+///
+/// # Safety
+///
+/// `path` must be a NUL terminated string. `out` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn acs_verify_path(
+    path: *const c_char,
+    out: *mut *mut AcsVerifyResult,
+) -> AcsStatus {
+    run(|| {
+        let out = out_arg("out", out)?;
+        let path = str_arg("path", path)?;
+
+        let messages = apple_codesign::verify_path(path)
+            .iter()
+            .map(|problem| problem.to_string())
+            .collect::<Vec<_>>();
+        let result = AcsVerifyResult::new(messages.iter().map(|message| message.as_str()));
+        *out = Box::into_raw(Box::new(result));
+
+        Ok(())
+    })
+}
+
+/// Release a result obtained from [acs_verify_path()]. NULL is ignored.
+///
+/// # Safety
+///
+/// `result` must be NULL or a result that hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn acs_verify_result_free(result: *mut AcsVerifyResult) {
+    if !result.is_null() {
+        drop(Box::from_raw(result));
+    }
+}
+
+/// Number of problems found. 0 means verification found no problems.
+///
+/// # Safety
+///
+/// `result` must be NULL or a live result.
+#[no_mangle]
+pub unsafe extern "C" fn acs_verify_result_count(result: *const AcsVerifyResult) -> usize {
+    result
+        .as_ref()
+        .map(|result| result.messages.len())
+        .unwrap_or(0)
+}
+
+/// Human readable description of the problem at `index`.
+///
+/// Returns NULL if `index` is out of range.
+///
+/// # Safety
+///
+/// `result` must be NULL or a live result.
+#[no_mangle]
+pub unsafe extern "C" fn acs_verify_result_message(
+    result: *const AcsVerifyResult,
+    index: usize,
+) -> *const c_char {
+    cstring_ptr(
+        result
+            .as_ref()
+            .and_then(|result| result.messages.get(index)),
+    )
+}
+
+/// Options for [acs_sign_path()]. NULL fields use defaults.
+///
+/// Fields may be added to the end in later versions. Callers must set
+/// `struct_size` so fields they don't know about are treated as NULL.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct AcsSignOptions {
+    /// Must be `sizeof(AcsSignOptions)`.
+    pub struct_size: usize,
+    /// PKCS#12 file holding the signing certificate and key. NULL signs ad-hoc.
+    pub p12_path: *const c_char,
+    /// Password decrypting `p12_path`. NULL is the empty password.
+    pub p12_password: *const c_char,
+    /// Team identifier to record. NULL derives it from the certificate.
+    pub team_id: *const c_char,
+    /// Time-stamp server URL. NULL uses Apple's server. An empty string
+    /// disables time-stamping.
+    pub time_stamp_url: *const c_char,
+}
+
+impl AcsSignOptions {
+    /// The `struct_size` of each version of this struct.
+    ///
+    /// Each version ends after one of the fields, in declaration order.
+    fn versioned_sizes() -> [usize; 5] {
+        fn end<T>(base: *const AcsSignOptions, field: *const T) -> usize {
+            field as usize - base as usize + std::mem::size_of::<T>()
+        }
+
+        let options = std::mem::MaybeUninit::<Self>::uninit();
+        let base = options.as_ptr();
+
+        // SAFETY: only field addresses are computed; nothing is read.
+        unsafe {
+            [
+                end(base, ptr::addr_of!((*base).struct_size)),
+                end(base, ptr::addr_of!((*base).p12_path)),
+                end(base, ptr::addr_of!((*base).p12_password)),
+                end(base, ptr::addr_of!((*base).team_id)),
+                end(base, ptr::addr_of!((*base).time_stamp_url)),
+            ]
+        }
+    }
+
+    /// Copy options initialized by a caller built against any version of this struct.
+    ///
+    /// Fields beyond `struct_size` are NULL. A `struct_size` larger than this
+    /// version knows about is accepted and the unknown fields are ignored.
+    unsafe fn read(options: *const Self) -> Result<Self, Failure> {
+        let struct_size = ptr::read_unaligned(options.cast::<usize>());
+
+        if struct_size < std::mem::size_of::<Self>()
+            && !Self::versioned_sizes().contains(&struct_size)
+        {
+            return Err(Failure::invalid_argument(
+                "options struct_size must be sizeof(AcsSignOptions)",
+            ));
+        }
+
+        let mut copy = Self {
+            struct_size,
+            p12_path: ptr::null(),
+            p12_password: ptr::null(),
+            team_id: ptr::null(),
+            time_stamp_url: ptr::null(),
+        };
+        ptr::copy_nonoverlapping(
+            options.cast::<u8>(),
+            ptr::addr_of_mut!(copy).cast::<u8>(),
+            struct_size.min(std::mem::size_of::<Self>()),
+        );
+
+        Ok(copy)
+    }
+
+    unsafe fn to_sign_options(self) -> Result<SignOptions, Failure> {
+        let mut options = SignOptions::default();
+
+        if let Some(path) = optional_str_arg("p12_path", self.p12_path)? {
+            let password = optional_str_arg("p12_password", self.p12_password)?.unwrap_or("");
+
+            options.identity = Some(p12_identity(path, password)?);
+        }
+
+        if let Some(team_id) = optional_str_arg("team_id", self.team_id)? {
+            options.team_id = Some(team_id.to_string());
+        }
+
+        if let Some(url) = optional_str_arg("time_stamp_url", self.time_stamp_url)? {
+            options.time_stamp_url = Some(url.to_string());
+        }
+
+        Ok(options)
+    }
+}
+
+/// Load the first identity having a private key from a PKCS#12 file.
+fn p12_identity(path: &str, password: &str) -> Result<SigningIdentity, Failure> {
+    let mut resolver = IdentityResolver::default();
+    resolver.add_p12_file(path, password)?;
+
+    let (identities, _) = resolver.into_parts();
+
+    identities
+        .into_iter()
+        .find(|identity| identity.private_key().is_some())
+        .ok_or_else(|| {
+            AppleCodesignError::CertificateNotFound(
+                "no certificate paired with a private key".into(),
+            )
+            .into()
+        })
+}
+
+/// Signing options resolved from an [AcsSignOptions]. The default signs ad-hoc.
+#[derive(Default)]
+struct SignOptions {
+    identity: Option<SigningIdentity>,
+    team_id: Option<String>,
+    /// `None` uses Apple's server. An empty string disables time-stamping.
+    time_stamp_url: Option<String>,
+}
+
+impl SignOptions {
+    fn signing_settings(&self) -> Result<SigningSettings<'_>, Failure> {
+        let mut settings = SigningSettings::default();
+
+        if let Some(identity) = &self.identity {
+            let key = identity
+                .private_key()
+                .expect("identity should have private key");
+
+            settings.set_signing_key(key.as_key_info_signer(), identity.certificate().clone());
+            settings.chain_apple_certificates();
+            settings.set_team_id_from_signing_certificate();
+
+            match self.time_stamp_url.as_deref() {
+                Some("") => {}
+                Some(url) => settings.set_time_stamp_url(url)?,
+                None => settings.set_time_stamp_url(APPLE_TIMESTAMP_URL)?,
+            }
+        }
+
+        if let Some(team_id) = &self.team_id {
+            settings.set_team_id(team_id);
+        }
+
+        Ok(settings)
+    }
+}
+
+/// Sign the entity at `input_path` and write the result to `output_path`.
+///
+/// Mach-O binaries, bundles, DMGs, and flat packages are supported. The paths
+/// may be the same to sign in place. A NULL `options` signs ad-hoc.
+///
+/// # Safety
+///
+/// The paths must be NUL terminated strings. `options` must be NULL or point
+/// to an [AcsSignOptions] initialized up to its `struct_size`.
+#[no_mangle]
+pub unsafe extern "C" fn acs_sign_path(
+    input_path: *const c_char,
+    output_path: *const c_char,
+    options: *const AcsSignOptions,
+) -> AcsStatus {
+    run(|| {
+        let input_path = str_arg("input_path", input_path)?;
+        let output_path = str_arg("output_path", output_path)?;
+
+        let options = if options.is_null() {
+            SignOptions::default()
+        } else {
+            AcsSignOptions::read(options)?.to_sign_options()?
+        };
+
+        Ok(UnifiedSigner::new(options.signing_settings()?).sign_path(input_path, output_path)?)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Emit a minimal 64-bit x86-64 executable with `__TEXT` and `__LINKEDIT` segments.
+    fn macho() -> Vec<u8> {
+        apple_codesign::test_support::SyntheticMachO::default().build()
+    }
+
+    fn cstr(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    unsafe fn last_error() -> String {
+        CStr::from_ptr(acs_last_error_message())
+            .to_string_lossy()
+            .to_string()
+    }
+
+    unsafe fn parse(data: &[u8]) -> (AcsStatus, *mut AcsMachO) {
+        let mut macho = ptr::null_mut();
+        let status = acs_macho_parse(data.as_ptr(), data.len(), &mut macho);
+
+        (status, macho)
+    }
+
+    #[test]
+    fn parse_errors() {
+        unsafe {
+            let (status, macho) = parse(b"not a mach-o");
+            assert_eq!(status, AcsStatus::Error);
+            assert!(macho.is_null());
+            assert!(!last_error().is_empty());
+
+            let status = acs_macho_parse(ptr::null(), 0, &mut ptr::null_mut());
+            assert_eq!(status, AcsStatus::InvalidArgument);
+            assert_eq!(last_error(), "data must not be NULL");
+
+            let status = acs_macho_parse(b"".as_ptr(), 0, ptr::null_mut());
+            assert_eq!(status, AcsStatus::InvalidArgument);
+            assert_eq!(last_error(), "out must not be NULL");
+
+            // NULL handles are tolerated.
+            assert_eq!(acs_macho_count(ptr::null()), 0);
+            assert!(acs_macho_identifier(ptr::null(), 0).is_null());
+            acs_macho_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn sign_parse_verify() {
+        let td = tempfile::tempdir().unwrap();
+        let unsigned_path = td.path().join("unsigned");
+        let signed_path = td.path().join("signed");
+        std::fs::write(&unsigned_path, macho()).unwrap();
+
+        unsafe {
+            let (status, macho) = parse(&std::fs::read(&unsigned_path).unwrap());
+            assert_eq!(status, AcsStatus::Ok);
+            assert!(acs_last_error_message().is_null());
+            assert_eq!(acs_macho_count(macho), 1);
+            assert!(!acs_macho_is_signed(macho, 0));
+            assert!(acs_macho_cdhash(macho, 0).is_null());
+            acs_macho_free(macho);
+
+            let input = cstr(unsigned_path.to_str().unwrap());
+            let output = cstr(signed_path.to_str().unwrap());
+            let team_id = cstr("team1");
+            let options = AcsSignOptions {
+                struct_size: std::mem::size_of::<AcsSignOptions>(),
+                p12_path: ptr::null(),
+                p12_password: ptr::null(),
+                team_id: team_id.as_ptr(),
+                time_stamp_url: ptr::null(),
+            };
+            assert_eq!(
+                acs_sign_path(input.as_ptr(), output.as_ptr(), &options),
+                AcsStatus::Ok
+            );
+
+            let (status, macho) = parse(&std::fs::read(&signed_path).unwrap());
+            assert_eq!(status, AcsStatus::Ok);
+            assert!(acs_macho_is_signed(macho, 0));
+            assert!(!acs_macho_is_signed(macho, 1));
+            assert!(!acs_macho_identifier(macho, 0).is_null());
+            assert_eq!(
+                CStr::from_ptr(acs_macho_team_id(macho, 0)),
+                team_id.as_c_str()
+            );
+            assert_eq!(
+                CStr::from_ptr(acs_macho_cdhash(macho, 0)).to_bytes().len(),
+                40
+            );
+            acs_macho_free(macho);
+
+            // Ad-hoc signatures lack a cryptographic signature.
+            let mut result = ptr::null_mut();
+            assert_eq!(acs_verify_path(output.as_ptr(), &mut result), AcsStatus::Ok);
+            assert_eq!(acs_verify_result_count(result), 1);
+            assert!(!acs_verify_result_message(result, 0).is_null());
+            assert!(acs_verify_result_message(result, 1).is_null());
+            acs_verify_result_free(result);
+        }
+    }
+
+    #[test]
+    fn verify_messages_with_nul() {
+        let result = AcsVerifyResult::new(["first", "embedded\0nul", ""]);
+
+        unsafe {
+            assert_eq!(acs_verify_result_count(&result), 3);
+            assert_eq!(
+                CStr::from_ptr(acs_verify_result_message(&result, 1)).to_str(),
+                Ok("embedded\\0nul")
+            );
+        }
+    }
+
+    #[test]
+    fn sign_errors() {
+        unsafe {
+            let path = cstr("/does/not/exist");
+
+            assert_eq!(
+                acs_sign_path(ptr::null(), path.as_ptr(), ptr::null()),
+                AcsStatus::InvalidArgument
+            );
+            assert_eq!(last_error(), "input_path must not be NULL");
+
+            assert_eq!(
+                acs_sign_path(path.as_ptr(), path.as_ptr(), ptr::null()),
+                AcsStatus::Error
+            );
+
+            let invalid = [0xffu8, 0];
+            assert_eq!(
+                acs_sign_path(invalid.as_ptr().cast(), path.as_ptr(), ptr::null()),
+                AcsStatus::InvalidArgument
+            );
+            assert_eq!(last_error(), "input_path is not valid UTF-8");
+
+            let options = AcsSignOptions {
+                struct_size: 0,
+                p12_path: ptr::null(),
+                p12_password: ptr::null(),
+                team_id: ptr::null(),
+                time_stamp_url: ptr::null(),
+            };
+            assert_eq!(
+                acs_sign_path(path.as_ptr(), path.as_ptr(), &options),
+                AcsStatus::InvalidArgument
+            );
+        }
+    }
+
+    #[test]
+    fn sign_options_size() {
+        /// The options as declared by a caller built against an older version.
+        #[repr(C)]
+        struct Truncated {
+            struct_size: usize,
+            p12_path: *const c_char,
+        }
+
+        let team_id = cstr("team1");
+        let mut options = AcsSignOptions {
+            struct_size: std::mem::size_of::<AcsSignOptions>(),
+            p12_path: ptr::null(),
+            p12_password: ptr::null(),
+            team_id: team_id.as_ptr(),
+            time_stamp_url: ptr::null(),
+        };
+
+        unsafe {
+            assert!(!AcsSignOptions::read(&options)
+                .ok()
+                .unwrap()
+                .team_id
+                .is_null());
+
+            options.struct_size = std::mem::size_of::<Truncated>();
+            assert!(AcsSignOptions::read(&options)
+                .ok()
+                .unwrap()
+                .team_id
+                .is_null());
+
+            let truncated = Truncated {
+                struct_size: std::mem::size_of::<Truncated>(),
+                p12_path: ptr::null(),
+            };
+            let copy = AcsSignOptions::read(ptr::addr_of!(truncated).cast())
+                .ok()
+                .unwrap();
+            assert_eq!(copy.struct_size, std::mem::size_of::<Truncated>());
+            assert!(copy.p12_password.is_null());
+            assert!(copy.time_stamp_url.is_null());
+
+            // Sizes ending partway through a field are rejected.
+            for size in [
+                1,
+                std::mem::size_of::<usize>() + 1,
+                std::mem::size_of::<Truncated>() - 1,
+                std::mem::size_of::<AcsSignOptions>() - 1,
+            ] {
+                options.struct_size = size;
+                assert!(matches!(
+                    AcsSignOptions::read(&options),
+                    Err(Failure {
+                        status: AcsStatus::InvalidArgument,
+                        ..
+                    })
+                ));
+            }
+
+            options.struct_size = std::mem::size_of::<AcsSignOptions>() + 8;
+            assert!(!AcsSignOptions::read(&options)
+                .ok()
+                .unwrap()
+                .team_id
+                .is_null());
+        }
+    }
+}
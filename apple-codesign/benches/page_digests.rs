@@ -0,0 +1,44 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Compares serial and parallel digesting of code pages.
+//!
+//! Run with `cargo bench -p apple-codesign --bench page_digests --features
+//! parallel-digests`. Parallel digesting can only be faster on machines with
+//! multiple cores.
+
+use {
+    apple_codesign::{test_support::digest_pages, DigestType},
+    criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput},
+};
+
+/// Page size of code directories of modern binaries.
+const PAGE_SIZE: usize = 4096;
+
+fn page_digests(c: &mut Criterion) {
+    let mut group = c.benchmark_group("page_digests");
+    group.sample_size(10);
+
+    for size_mib in [16usize, 256] {
+        let data = (0..size_mib * 1024 * 1024)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        group.throughput(Throughput::Bytes(data.len() as u64));
+
+        for (name, parallel) in [("serial", false), ("parallel", true)] {
+            group.bench_with_input(
+                BenchmarkId::new(name, format!("{size_mib}MiB")),
+                &data,
+                |b, data| {
+                    b.iter(|| digest_pages(data, DigestType::Sha256, PAGE_SIZE, parallel).unwrap())
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, page_digests);
+criterion_main!(benches);
@@ -0,0 +1,17 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_main]
+
+use {
+    apple_codesign::{Blob, CodeDirectoryBlob},
+    libfuzzer_sys::fuzz_target,
+};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(cd) = CodeDirectoryBlob::from_blob_bytes(data) {
+        let _ = cd.code_page_range(cd.code_digests.len().saturating_sub(1), cd.code_limit as _);
+        let _ = cd.to_blob_bytes();
+    }
+});
@@ -0,0 +1,14 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_main]
+
+use {apple_codesign::CodeRequirementExpression, libfuzzer_sys::fuzz_target};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok((expression, _)) = CodeRequirementExpression::from_bytes(data) {
+        let _ = expression.to_string();
+        let _ = expression.to_bytes();
+    }
+});
@@ -0,0 +1,30 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_main]
+
+use {apple_codesign::EmbeddedSignature, libfuzzer_sys::fuzz_target};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(signature) = EmbeddedSignature::from_bytes(data) else {
+        assert!(EmbeddedSignature::from_bytes_strict(data).is_err());
+        return;
+    };
+
+    // Strict parsing only rejects layouts. It never yields different blobs.
+    if let Ok(strict) = EmbeddedSignature::from_bytes_strict(data) {
+        assert_eq!(strict.blobs.len(), signature.blobs.len());
+        for (a, b) in strict.blobs.iter().zip(signature.blobs.iter()) {
+            assert_eq!((a.slot, a.data), (b.slot, b.data));
+        }
+    }
+
+    let _ = signature.all_code_directories();
+    let _ = signature.cdhashes();
+    let _ = signature.entitlements();
+    let _ = signature.entitlements_der();
+    let _ = signature.code_requirements();
+    let _ = signature.designated_requirement();
+    let _ = signature.signed_data();
+});
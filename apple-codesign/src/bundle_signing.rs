@@ -8,11 +8,12 @@ use {
     crate::{
         code_directory::CodeDirectoryBlob,
         code_requirement::{CodeRequirementExpression, RequirementType},
-        code_resources::{CodeResourcesBuilder, CodeResourcesRule},
+        code_resources::{CodeResources, CodeResourcesBuilder},
         embedded_signature::{Blob, BlobData, DigestType},
         error::AppleCodesignError,
         macho::MachFile,
         macho_signing::{write_macho_file, MachOSigner},
+        mapped_file::MappedFile,
         signing_settings::{SettingsScope, SigningSettings},
     },
     apple_bundles::{BundlePackageType, DirectoryBundle, DirectoryBundleFile},
@@ -248,12 +249,7 @@ impl SignedMachOInfo {
 
         let digest_type: u8 = cd.digest_type.into();
 
-        let mut digest = cd.digest_with(cd.digest_type)?;
-
-        // Digests appear to be truncated at 20 bytes / 40 characters.
-        digest.truncate(20);
-
-        let digest = hex::encode(digest);
+        let digest = hex::encode(cd.cdhash()?);
 
         // Unsure what the leading `2/` means.
         Ok(format!("2/{digest_type}/{digest}"))
@@ -333,31 +329,36 @@ impl<'a, 'key> BundleFileHandler for SingleBundleHandler<'a, 'key> {
     ) -> Result<SignedMachOInfo, AppleCodesignError> {
         info!("signing Mach-O file {}", file.relative_path().display());
 
-        let macho_data = std::fs::read(file.absolute_path())?;
+        // SAFETY: the mapping is dropped before the signed file is written.
+        let macho_data = unsafe { MappedFile::open(file.absolute_path())? };
         let signer = MachOSigner::new(&macho_data)?;
 
         let mut settings = self
             .settings
             .as_bundle_macho_settings(file.relative_path().to_string_lossy().as_ref());
 
+        settings.load_info_plist_paths(file.absolute_path())?;
         settings.import_settings_from_macho(&macho_data)?;
 
         // If there isn't a defined binary identifier, derive one from the file name so one is set
         // and we avoid a signing error due to missing identifier.
         // TODO do we need to check the nested Mach-O settings?
         if settings.binary_identifier(SettingsScope::Main).is_none() {
-            let identifier = file
+            let file_name = file
                 .relative_path()
                 .file_name()
                 .expect("failure to extract filename (this should never happen)")
                 .to_string_lossy();
 
-            let identifier = identifier
-                .strip_suffix(".dylib")
-                .unwrap_or_else(|| identifier.as_ref());
+            let mach = MachFile::parse(&macho_data)?;
+            let macho = mach.nth_macho(0)?;
+            let file_type = settings
+                .macho_file_type(SettingsScope::Main)
+                .unwrap_or_else(|| macho.file_type());
+            let identifier = macho.default_identifier(file_type, &file_name);
 
             info!(
-                "Mach-O is missing binary identifier; setting to {} based on file name",
+                "Mach-O is missing binary identifier; setting to {} based on file name or install name",
                 identifier
             );
             settings.set_binary_identifier(SettingsScope::Main, identifier);
@@ -366,6 +367,10 @@ impl<'a, 'key> BundleFileHandler for SingleBundleHandler<'a, 'key> {
         let mut new_data = Vec::<u8>::with_capacity(macho_data.len() + 2_usize.pow(17));
         signer.write_signed_binary(&settings, &mut new_data)?;
 
+        // Unmap the input before we possibly overwrite it.
+        drop(signer);
+        drop(macho_data);
+
         let dest_path = self.dest_dir.join(file.relative_path());
 
         info!("writing Mach-O to {}", dest_path.display());
@@ -473,7 +478,8 @@ impl SingleBundleSigner {
             .find(|f| matches!(f.is_main_executable(), Ok(true)));
 
         if let Some(exe) = &main_exe {
-            let macho_data = std::fs::read(exe.absolute_path())?;
+            // SAFETY: we only read the main executable here.
+            let macho_data = unsafe { MappedFile::open(exe.absolute_path())? };
             let mach = MachFile::parse(&macho_data)?;
 
             for macho in mach.iter_macho() {
@@ -493,34 +499,29 @@ impl SingleBundleSigner {
 
         warn!("collecting code resources files");
 
-        // The set of rules to use is determined by whether the bundle *can* have a
-        // `Resources/`, not whether it necessarily does. The exact rules for this are not
-        // known. Essentially we want to test for the result of CFBundleCopyResourcesDirectoryURL().
-        // We assume that we can use the resources rules when there is a `Resources` directory
-        // (this seems obvious!) or when the bundle isn't shallow, as a non-shallow bundle should
-        // be an app bundle and app bundles can always have resources (we think).
-        let mut resources_builder =
-            if self.bundle.resolve_path("Resources").is_dir() || !self.bundle.shallow() {
-                CodeResourcesBuilder::default_resources_rules()?
-            } else {
-                CodeResourcesBuilder::default_no_resources_rules()?
-            };
+        let mut resources_builder = CodeResourcesBuilder::for_bundle(&self.bundle)?;
 
         // Ensure emitted digests match what we're configured to emit.
         resources_builder.set_digests(resources_digests.into_iter());
 
-        // Exclude code signature files we'll write.
-        resources_builder.add_exclusion_rule(CodeResourcesRule::new("^_CodeSignature/")?.exclude());
-        // Ignore notarization ticket.
-        resources_builder.add_exclusion_rule(CodeResourcesRule::new("^CodeResources$")?.exclude());
+        if settings.reuse_resource_seals() {
+            let previous_path = self.bundle.resolve_path("_CodeSignature/CodeResources");
+
+            if let Ok(metadata) = std::fs::metadata(&previous_path) {
+                warn!(
+                    "reusing seals of resources unchanged since {} was written",
+                    previous_path.display()
+                );
+                let previous = CodeResources::from_xml(&std::fs::read(&previous_path)?)?;
+                resources_builder.set_previous_resources(previous, metadata.modified()?);
+            }
+        }
 
         let handler = SingleBundleHandler {
             dest_dir: dest_dir_root.clone(),
             settings,
         };
 
-        let mut info_plist_data = None;
-
         // Iterate files in this bundle and register as code resources.
         //
         // Traversing into nested bundles seems wrong but it is correct. The resources builder
@@ -531,30 +532,22 @@ impl SingleBundleSigner {
         // bundles. For example, you could have a framework bundle with an embedded app bundle in
         // `Resources/MyApp.app`! In this case, the framework's CodeResources encapsulates the
         // content of `Resources/My.app` per the processing rules.
-        for file in self
-            .bundle
-            .files(true)
-            .map_err(AppleCodesignError::DirectoryBundle)?
-        {
-            // The main executable is special and handled below.
-            if file
-                .is_main_executable()
-                .map_err(AppleCodesignError::DirectoryBundle)?
-            {
-                continue;
-            } else if file.is_info_plist() {
-                // The Info.plist is digested specially. But it may also be handled by
-                // the resources handler. So always feed it through.
-                info!(
-                    "{} is the Info.plist file; handling specially",
-                    file.relative_path().display()
-                );
-                resources_builder.process_file(&file, &handler)?;
-                info_plist_data = Some(std::fs::read(file.absolute_path())?);
-            } else {
-                resources_builder.process_file(&file, &handler)?;
-            }
-        }
+        //
+        // The main executable is special and handled below.
+        resources_builder.seal_bundle_files(
+            &self.bundle,
+            &handler,
+            settings.resource_digest_threads(),
+        )?;
+
+        // The Info.plist is digested specially. But it may also be handled by
+        // the resources handler, which is why it was fed through above.
+        let info_plist_path = self.bundle.info_plist_path();
+        let info_plist_data = if info_plist_path.is_file() {
+            Some(std::fs::read(&info_plist_path)?)
+        } else {
+            None
+        };
 
         // Seal code directory digests of any nested bundles.
         //
@@ -594,7 +587,8 @@ impl SingleBundleSigner {
         if let Some(exe) = main_exe {
             warn!("signing main executable {}", exe.relative_path().display());
 
-            let macho_data = std::fs::read(exe.absolute_path())?;
+            // SAFETY: the mapping is dropped before the signed file is written.
+            let macho_data = unsafe { MappedFile::open(exe.absolute_path())? };
             let signer = MachOSigner::new(&macho_data)?;
 
             let mut settings = settings.clone();
@@ -622,6 +616,10 @@ impl SingleBundleSigner {
             let mut new_data = Vec::<u8>::with_capacity(macho_data.len() + 2_usize.pow(17));
             signer.write_signed_binary(&settings, &mut new_data)?;
 
+            // Unmap the input before we possibly overwrite it.
+            drop(signer);
+            drop(macho_data);
+
             let dest_path = dest_dir_root.join(exe.relative_path());
             info!("writing signed main executable to {}", dest_path.display());
             write_macho_file(exe.absolute_path(), &dest_path, &new_data)?;
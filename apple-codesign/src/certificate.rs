@@ -16,8 +16,9 @@ use {
         str::FromStr,
     },
     x509_certificate::{
-        certificate::KeyUsage, rfc4519::OID_COUNTRY_NAME, CapturedX509Certificate,
-        InMemorySigningKeyPair, KeyAlgorithm, X509CertificateBuilder,
+        asn1time::Time, certificate::KeyUsage, rfc2986::CertificationRequest,
+        rfc4519::OID_COUNTRY_NAME, CapturedX509Certificate, InMemorySigningKeyPair, KeyAlgorithm,
+        KeyInfoSigner, X509CertificateBuilder,
     },
 };
 
@@ -805,6 +806,74 @@ impl FromStr for CertificateProfile {
     }
 }
 
+/// Describes the distribution channel that signed content is intended for.
+///
+/// Apple issues different certificates for different distribution channels. This
+/// type is used to validate that a signing certificate is appropriate for the
+/// channel content is being signed for.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SigningDistribution {
+    /// Distribution outside the Mac App Store via Developer ID.
+    DeveloperId,
+
+    /// Development builds running on registered devices.
+    Development,
+
+    /// Distribution through the Mac App Store.
+    MacAppStore,
+}
+
+impl SigningDistribution {
+    /// Obtain the string values that variants are recognized as.
+    pub fn str_names() -> [&'static str; 3] {
+        ["developer-id", "development", "mac-app-store"]
+    }
+}
+
+impl Display for SigningDistribution {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DeveloperId => f.write_str("developer-id"),
+            Self::Development => f.write_str("development"),
+            Self::MacAppStore => f.write_str("mac-app-store"),
+        }
+    }
+}
+
+impl FromStr for SigningDistribution {
+    type Err = AppleCodesignError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "developer-id" => Ok(Self::DeveloperId),
+            "development" => Ok(Self::Development),
+            "mac-app-store" => Ok(Self::MacAppStore),
+            _ => Err(AppleCodesignError::UnknownSigningDistribution(
+                s.to_string(),
+            )),
+        }
+    }
+}
+
+/// Describes the kind of entity a signing certificate is used to sign.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SignedEntityKind {
+    /// Code: Mach-O binaries, bundles, and DMGs.
+    Code,
+
+    /// Installer packages (flat packages / XAR archives).
+    InstallerPackage,
+}
+
+impl Display for SignedEntityKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Code => f.write_str("code"),
+            Self::InstallerPackage => f.write_str("installer packages"),
+        }
+    }
+}
+
 /// Extends functionality of [CapturedX509Certificate] with Apple specific certificate knowledge.
 pub trait AppleCertificate: Sized {
     /// Whether this is a known Apple root certificate authority.
@@ -876,6 +945,22 @@ pub trait AppleCertificate: Sized {
     /// certificates as the Organizational Unit field of the subject. So this
     /// function is just a shortcut for retrieving that.
     fn apple_team_id(&self) -> Option<String>;
+
+    /// Validate that this certificate is suitable for signing an entity.
+    ///
+    /// This verifies the certificate is within its validity window at `now`, has the
+    /// extended key usage required to sign entities of kind `kind`, and, if a
+    /// `distribution` is given, has the Apple code signing extensions required
+    /// by that distribution channel.
+    ///
+    /// Errors describe which requirement was not met and what kind of certificate
+    /// should be used instead.
+    fn apple_validate_for_signing(
+        &self,
+        kind: SignedEntityKind,
+        distribution: Option<SigningDistribution>,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<(), AppleCodesignError>;
 }
 
 impl AppleCertificate for CapturedX509Certificate {
@@ -1009,6 +1094,121 @@ impl AppleCertificate for CapturedX509Certificate {
             ))
             .unwrap_or(None)
     }
+
+    fn apple_validate_for_signing(
+        &self,
+        kind: SignedEntityKind,
+        distribution: Option<SigningDistribution>,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<(), AppleCodesignError> {
+        let cert: &x509_certificate::rfc5280::Certificate = self.as_ref();
+        let validity = &cert.tbs_certificate.validity;
+
+        let not_before = asn1_time_to_datetime(&validity.not_before);
+        let not_after = asn1_time_to_datetime(&validity.not_after);
+
+        if now < not_before {
+            return Err(AppleCodesignError::CertificateNotYetValid(
+                not_before.to_rfc3339(),
+            ));
+        }
+        if now > not_after {
+            return Err(AppleCodesignError::CertificateExpired(
+                not_after.to_rfc3339(),
+            ));
+        }
+
+        let ekus = self.apple_extended_key_usage_purposes();
+        let extensions = self.apple_code_signing_extensions();
+
+        match kind {
+            SignedEntityKind::Code => {
+                if !ekus.contains(&ExtendedKeyUsagePurpose::CodeSigning) {
+                    return Err(AppleCodesignError::CertificateMissingExtendedKeyUsage(
+                        ExtendedKeyUsagePurpose::CodeSigning,
+                        kind,
+                        "use a Developer ID Application, Apple Development, or Apple \
+                        Distribution certificate",
+                    ));
+                }
+
+                let (required, guidance) = match distribution {
+                    None => (&[][..], ""),
+                    Some(SigningDistribution::DeveloperId) => (
+                        &[CodeSigningCertificateExtension::DeveloperIdApplication][..],
+                        "use a Developer ID Application certificate",
+                    ),
+                    Some(SigningDistribution::Development) => (
+                        &[
+                            CodeSigningCertificateExtension::MacDeveloper,
+                            CodeSigningCertificateExtension::IPhoneDeveloper,
+                        ][..],
+                        "use an Apple Development certificate",
+                    ),
+                    Some(SigningDistribution::MacAppStore) => (
+                        &[
+                            CodeSigningCertificateExtension::AppleDeveloperCertificateSubmission,
+                            CodeSigningCertificateExtension::AppleMacAppSigningSubmission,
+                            CodeSigningCertificateExtension::AppleMacAppStoreCodeSigning,
+                        ][..],
+                        "use an Apple Distribution certificate",
+                    ),
+                };
+
+                if let Some(distribution) = distribution {
+                    if !required.iter().any(|ext| extensions.contains(ext)) {
+                        return Err(AppleCodesignError::CertificateUnsuitableForDistribution(
+                            distribution,
+                            kind,
+                            guidance,
+                        ));
+                    }
+                }
+            }
+            SignedEntityKind::InstallerPackage => {
+                let (allowed, guidance) = match distribution {
+                    None => (
+                        &[
+                            ExtendedKeyUsagePurpose::DeveloperIdInstaller,
+                            ExtendedKeyUsagePurpose::ThirdPartyMacDeveloperInstaller,
+                        ][..],
+                        "use a Developer ID Installer or Mac Installer Distribution certificate",
+                    ),
+                    Some(SigningDistribution::DeveloperId) => (
+                        &[ExtendedKeyUsagePurpose::DeveloperIdInstaller][..],
+                        "use a Developer ID Installer certificate",
+                    ),
+                    Some(SigningDistribution::MacAppStore) => (
+                        &[ExtendedKeyUsagePurpose::ThirdPartyMacDeveloperInstaller][..],
+                        "use a Mac Installer Distribution certificate",
+                    ),
+                    Some(SigningDistribution::Development) => {
+                        return Err(AppleCodesignError::CertificateUnsuitableForDistribution(
+                            SigningDistribution::Development,
+                            kind,
+                            "Apple doesn't issue installer certificates for development; use \
+                            developer-id or mac-app-store distribution",
+                        ));
+                    }
+                };
+
+                if !allowed.iter().any(|eku| ekus.contains(eku)) {
+                    return Err(AppleCodesignError::CertificateMissingExtendedKeyUsage(
+                        allowed[0], kind, guidance,
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn asn1_time_to_datetime(time: &Time) -> chrono::DateTime<chrono::Utc> {
+    match time {
+        Time::UtcTime(t) => **t,
+        Time::GeneralTime(t) => t.clone().into(),
+    }
 }
 
 /// Extensions to [X509CertificateBuilder] specializing in Apple certificate behavior.
@@ -1343,14 +1543,59 @@ pub fn create_self_signed_code_signing_certificate(
     Ok(builder.create_with_random_keypair()?)
 }
 
+/// Create a certificate signing request (CSR) for obtaining a code signing certificate.
+///
+/// The subject mirrors what Keychain Access produces via *Request a Certificate
+/// From a Certificate Authority*: an optional email address, a common name, and
+/// an optional country. Apple ignores the subject when issuing certificates, so
+/// these values only serve to identify the request.
+///
+/// The resulting request can be uploaded at
+/// <https://developer.apple.com/account/resources/certificates/add> to obtain a
+/// certificate bound to `signer`'s key. Apple expects RSA 2048 keys.
+pub fn create_certificate_signing_request(
+    signer: &dyn KeyInfoSigner,
+    email_address: Option<&str>,
+    common_name: &str,
+    country: Option<&str>,
+) -> Result<CertificationRequest, AppleCodesignError> {
+    let key_algorithm = signer.key_algorithm().ok_or_else(|| {
+        AppleCodesignError::CertificateBuildError(
+            "unable to determine key algorithm of private key".into(),
+        )
+    })?;
+
+    let mut builder = X509CertificateBuilder::new(key_algorithm);
+
+    if let Some(address) = email_address {
+        builder.apple_email_address(address)?;
+    }
+
+    builder
+        .subject()
+        .append_common_name_utf8_string(common_name)
+        .map_err(|e| AppleCodesignError::CertificateBuildError(format!("{e:?}")))?;
+
+    if let Some(country) = country {
+        builder
+            .subject()
+            .append_country_utf8_string(country)
+            .map_err(|e| AppleCodesignError::CertificateBuildError(format!("{e:?}")))?;
+    }
+
+    Ok(builder.create_certificate_signing_request(signer)?)
+}
+
 #[cfg(test)]
 mod tests {
     use {
         super::*,
-        cryptographic_message_syntax::{SignedData, SignedDataBuilder, SignerBuilder},
-        x509_certificate::EcdsaCurve,
+        x509_certificate::{EcdsaCurve, Sign},
     };
 
+    #[cfg(feature = "full")]
+    use cryptographic_message_syntax::{SignedData, SignedDataBuilder, SignerBuilder};
+
     #[test]
     fn generate_self_signed_certificate_ecdsa() {
         for curve in EcdsaCurve::all() {
@@ -1379,6 +1624,52 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn certificate_signing_request() -> Result<(), AppleCodesignError> {
+        let (_, key, _) = create_self_signed_code_signing_certificate(
+            KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1),
+            CertificateProfile::DeveloperIdApplication,
+            "team1",
+            "Joe Developer",
+            "US",
+            chrono::Duration::hours(1),
+        )?;
+
+        let request = create_certificate_signing_request(
+            &key,
+            Some("joe@example.com"),
+            "Joe Developer",
+            Some("US"),
+        )?;
+        let der = request.encode_der()?;
+
+        let request =
+            bcder::decode::Constructed::decode(der.as_slice(), bcder::Mode::Der, |cons| {
+                CertificationRequest::take_from(cons)
+            })
+            .unwrap();
+        let info = &request.certificate_request_info;
+
+        assert_eq!(
+            info.subject.user_friendly_str().unwrap(),
+            "CN=Joe Developer, C=US"
+        );
+        assert_eq!(
+            info.subject
+                .find_first_attribute_string(Oid(OID_EMAIL_ADDRESS.as_ref().into()))
+                .unwrap(),
+            Some("joe@example.com".to_string())
+        );
+        assert_eq!(
+            info.subject_public_key_info
+                .subject_public_key
+                .octet_bytes(),
+            key.public_key_data()
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn generate_all_profiles() {
         for profile in CertificateProfile::all() {
@@ -1395,6 +1686,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "full")]
     fn cms_self_signed_certificate_signing_ecdsa() {
         for curve in EcdsaCurve::all() {
             let (cert, signing_key, _) = create_self_signed_code_signing_certificate(
@@ -1427,6 +1719,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "full")]
     fn cms_self_signed_certificate_signing_ed25519() {
         let (cert, signing_key, _) = create_self_signed_code_signing_certificate(
             KeyAlgorithm::Ed25519,
@@ -1760,4 +2053,99 @@ mod tests {
         assert!(!built.chains_to_apple_root_ca());
         assert!(built.apple_root_certificate_chain().is_none());
     }
+
+    #[test]
+    fn validate_for_signing() -> Result<(), AppleCodesignError> {
+        let create = |profile| {
+            create_self_signed_code_signing_certificate(
+                KeyAlgorithm::Ed25519,
+                profile,
+                "team",
+                "Joe Developer",
+                "Wakanda",
+                chrono::Duration::hours(1),
+            )
+            .map(|(cert, _, _)| cert)
+        };
+
+        let now = chrono::Utc::now();
+
+        let cert = create(CertificateProfile::DeveloperIdApplication)?;
+        cert.apple_validate_for_signing(SignedEntityKind::Code, None, now)?;
+        cert.apple_validate_for_signing(
+            SignedEntityKind::Code,
+            Some(SigningDistribution::DeveloperId),
+            now,
+        )?;
+        assert!(matches!(
+            cert.apple_validate_for_signing(
+                SignedEntityKind::Code,
+                Some(SigningDistribution::MacAppStore),
+                now
+            ),
+            Err(AppleCodesignError::CertificateUnsuitableForDistribution(..))
+        ));
+        assert!(matches!(
+            cert.apple_validate_for_signing(SignedEntityKind::InstallerPackage, None, now),
+            Err(AppleCodesignError::CertificateMissingExtendedKeyUsage(..))
+        ));
+        assert!(matches!(
+            cert.apple_validate_for_signing(
+                SignedEntityKind::Code,
+                None,
+                now + chrono::Duration::hours(2)
+            ),
+            Err(AppleCodesignError::CertificateExpired(_))
+        ));
+        assert!(matches!(
+            cert.apple_validate_for_signing(
+                SignedEntityKind::Code,
+                None,
+                now - chrono::Duration::hours(1)
+            ),
+            Err(AppleCodesignError::CertificateNotYetValid(_))
+        ));
+
+        let cert = create(CertificateProfile::AppleDevelopment)?;
+        cert.apple_validate_for_signing(
+            SignedEntityKind::Code,
+            Some(SigningDistribution::Development),
+            now,
+        )?;
+
+        let cert = create(CertificateProfile::AppleDistribution)?;
+        cert.apple_validate_for_signing(
+            SignedEntityKind::Code,
+            Some(SigningDistribution::MacAppStore),
+            now,
+        )?;
+
+        let cert = create(CertificateProfile::DeveloperIdInstaller)?;
+        cert.apple_validate_for_signing(
+            SignedEntityKind::InstallerPackage,
+            Some(SigningDistribution::DeveloperId),
+            now,
+        )?;
+        assert!(matches!(
+            cert.apple_validate_for_signing(
+                SignedEntityKind::InstallerPackage,
+                Some(SigningDistribution::MacAppStore),
+                now
+            ),
+            Err(AppleCodesignError::CertificateMissingExtendedKeyUsage(..))
+        ));
+        assert!(matches!(
+            cert.apple_validate_for_signing(SignedEntityKind::Code, None, now),
+            Err(AppleCodesignError::CertificateMissingExtendedKeyUsage(..))
+        ));
+
+        let cert = create(CertificateProfile::MacInstallerDistribution)?;
+        cert.apple_validate_for_signing(
+            SignedEntityKind::InstallerPackage,
+            Some(SigningDistribution::MacAppStore),
+            now,
+        )?;
+
+        Ok(())
+    }
 }
@@ -5,15 +5,21 @@
 use {
     crate::{
         certificate::{
-            create_self_signed_code_signing_certificate, AppleCertificate, CertificateProfile,
+            create_certificate_signing_request, create_self_signed_code_signing_certificate,
+            AppleCertificate, CertificateProfile, SigningDistribution,
         },
+        cloud_kms::kms_backend_from_uri,
         code_directory::{CodeDirectoryBlob, CodeSignatureFlags},
-        code_requirement::CodeRequirements,
-        cryptography::{parse_pfx_data, InMemoryPrivateKey, PrivateKey},
+        code_requirement::{CodeRequirements, DesignatedRequirement},
+        code_resources::CodeResources,
+        codesign_display::CodesignDisplay,
+        cryptography::{InMemoryPrivateKey, PrivateKey},
         embedded_signature::{Blob, CodeSigningSlot, DigestType, RequirementSetBlob},
         error::AppleCodesignError,
-        macho::MachFile,
-        reader::SignatureReader,
+        macho::{MachFile, MachOFileType},
+        ocsp::OcspChecker,
+        reader::{CodeDirectory, SignatureReader},
+        release::{ReleaseConfig, ReleasePipeline},
         remote_signing::{
             session_negotiation::{
                 create_session_joiner, PublicKeyInitiator, SessionInitiatePeer, SessionJoinState,
@@ -21,17 +27,27 @@ use {
             },
             RemoteSignError, UnjoinedSigningClient,
         },
+        signature_recommendations::signature_recommendations,
         signing::UnifiedSigner,
-        signing_settings::{SettingsScope, SigningSettings},
+        signing_identity::IdentityResolver,
+        signing_settings::{SettingsScope, SigningSettings, APPLE_TIMESTAMP_URL},
+        verify::VerificationFailure,
+        watch::WatchSigner,
     },
     base64::{engine::general_purpose::STANDARD as STANDARD_ENGINE, Engine},
     clap::{ArgAction, Args, Parser},
     cryptographic_message_syntax::SignedData,
     difference::{Changeset, Difference},
     log::{error, warn, LevelFilter},
+    pkcs8::EncodePrivateKey,
     spki::EncodePublicKey,
-    std::{io::Write, path::PathBuf, str::FromStr},
-    x509_certificate::{CapturedX509Certificate, EcdsaCurve, KeyAlgorithm, X509CertificateBuilder},
+    std::{
+        io::{Read, Write},
+        path::{Path, PathBuf},
+        str::FromStr,
+        time::Duration,
+    },
+    x509_certificate::{CapturedX509Certificate, EcdsaCurve, KeyAlgorithm},
 };
 
 #[cfg(feature = "notarize")]
@@ -43,6 +59,9 @@ use {
     yubikey::{PinPolicy, TouchPolicy},
 };
 
+#[cfg(any(target_os = "macos", feature = "yubikey"))]
+use crate::signing_identity::{IdentitySource, SigningIdentity};
+
 #[cfg(target_os = "macos")]
 use crate::macos::{
     keychain_find_code_signing_certificates, macos_keychain_find_certificate_chain, KeychainDomain,
@@ -52,11 +71,16 @@ const EXTRACT_ABOUT: &str = "\
 Extract code signature data from a Mach-O binary.
 
 Given the path to a Mach-O binary (including fat/universal) binaries, this
-command will parse and print requested data to stdout.
+command will parse and print requested data to stdout. Use --output to
+write it to a file instead.
 
 The --data argument controls which data to extract and how to print it.
 Possible values are:
 
+blob-raw
+   Raw binary data of the blob in the signature slot given by --slot.
+   Slots are numbered as in Apple's headers, e.g. 5 for entitlements or
+   0x10000 for the CMS signature.
 blobs
    Low-level information on the records in the embedded code signature.
 cms-info
@@ -69,6 +93,8 @@ cms-raw
    encoded ASN.1 data. (This will print binary to stdout.)
 cms
    Print the ASN.1 decoded CMS data.
+code-directory-json
+   Information on the main code directory data structure as JSON.
 code-directory-raw
    Raw binary data composing the code directory data structure.
 code-directory
@@ -79,6 +105,9 @@ code-directory-serialized
 code-directory-serialized-raw
    Reserialize the parsed code directory and emit its binary. Useful
    for comparing round-tripping of code directory data.
+entitlements
+   Entitlements as an XML plist. If only DER encoded entitlements are
+   present, they are decoded and printed as XML.
 linkedit-info
    Information about the __LINKEDIT Mach-O segment in the binary.
 linkedit-segment-raw
@@ -145,11 +174,10 @@ data begins with header magic `0xfade0c00`.
 The default output format is the Code Signing Requirement Language. But the
 output format can be changed via the --format argument.
 
-Our Code Signing Requirement Language output may differ from Apple's. For
-example, `and` and `or` expressions always have their sub-expressions surrounded
-by parentheses (e.g. `(a) and (b)` instead of `a and b`) and strings are always
-quoted. The differences, however, should not matter to the parser or result
-in a different binary serialization.
+The Code Signing Requirement Language output mimics what `codesign -d -r-`
+prints: parentheses are only emitted where needed and strings are only quoted
+when necessary. The output can be compiled back to the same binary
+serialization.
 ";
 
 const SIGN_ABOUT: &str = "\
@@ -170,6 +198,14 @@ If the input is a bundle, the bundle will be recursively signed. If the
 bundle contains nested bundles or Mach-O binaries, those will be signed
 automatically.
 
+If the input path is `-`, a Mach-O binary is read from stdin. If the output
+path is `-`, the signed Mach-O binary is written to stdout. Signing data read
+from stdin writes to stdout unless an output path is given. Only Mach-O
+binaries can be signed this way. Data read from stdin can be raw bytes or hex
+text, such as produced by `xxd -p`. When reading from stdin, the binary
+identifier comes from --binary-identifier, the binary's existing signature,
+the CFBundleIdentifier of its Info.plist, or the install name of a dylib.
+
 # Settings Scope
 
 The following signing settings are global and apply to all signed entities:
@@ -220,8 +256,17 @@ The following named CPU architectures are recognized:
 * arm
 * arm64
 * arm64_32
+* arm64e
+* armv7
+* armv7s
+* i386
 * x86_64
 
+`arm64` applies to both `arm64` and `arm64e` Mach-O binaries, as they share a
+CPU type. `arm64e` only applies to `arm64e` binaries and takes precedence.
+Likewise, `arm` applies to all 32-bit ARM binaries and `armv7` and `armv7s`
+only apply to binaries with that CPU subtype.
+
 Signing will traverse into nested entities:
 
 * A fat Mach-O binary will traverse into the multiple Mach-O binaries within.
@@ -245,20 +290,36 @@ settings ignored include (but may not be limited to):
 
 # Designated Code Requirements
 
-When using Apple issued code signing certificates, we will attempt to apply
-an appropriate designated requirement automatically during signing which
-matches the behavior of what `codesign` would do. We do not yet support all
-signing certificates and signing targets for this, however. So you may
-need to provide your own requirements.
+When signing with a certificate, a designated requirement is derived
+automatically, matching what `codesign` would do. Apple Development, Apple
+Distribution, and Developer ID Application certificates get requirements
+binding the identifier to the Apple CA chain and the certificate's common name
+or team ID. Other certificates chaining to an Apple root CA require
+`anchor apple generic` and the certificate's team ID. Certificates not issued by Apple are pinned by the SHA-1 hash
+of the highest certificate in the chain sharing the signing certificate's
+organization. If this doesn't match your needs, provide your own requirements.
 
 Designated code requirements can be specified via --code-requirements-path.
 
-This file MUST contain a binary/compiled code requirements expression. We do
-not (yet) support parsing the human-friendly code requirements DSL. A
-binary/compiled file can be produced via Apple's `csreq` tool. e.g.
-`csreq -r '=<expression>' -b /output/path`. If code requirements data is
-specified, it will be parsed and displayed as part of signing to ensure it
-is well-formed.
+This file can contain a binary/compiled code requirements expression, such as
+one produced via Apple's `csreq` tool. e.g.
+`csreq -r '=<expression>' -b /output/path`. Or it can contain the
+human-friendly code requirements language source, e.g.
+`anchor apple generic and identifier \"com.example.app\"`, which will be
+compiled. If code requirements data is specified, it will be parsed and
+displayed as part of signing to ensure it is well-formed.
+
+# Re-signing
+
+When signing an already signed Mach-O binary, the binary identifier, team ID,
+code signature flags, runtime version, entitlements and library constraints
+of the existing signature are preserved unless set explicitly. A team ID
+derived from the signing certificate takes precedence.
+
+The designated requirement is only preserved when
+--preserve-designated-requirement is given, as it often references the
+certificate that made the existing signature. Use this when rotating
+certificates without changing the identity of the signed code.
 
 # Code Signing Key Pair
 
@@ -360,8 +421,6 @@ To exclude all nested bundles from being signed and only sign the main bundle
 (the default behavior of ``codesign`` without ``--deep``), use `--exclude '**'`.
 ";
 
-const APPLE_TIMESTAMP_URL: &str = "http://timestamp.apple.com/ts01";
-
 const SUPPORTED_HASHES: [&str; 6] = [
     "none",
     "sha1",
@@ -373,6 +432,96 @@ const SUPPORTED_HASHES: [&str; 6] = [
 
 const KEYCHAIN_DOMAINS: [&str; 4] = ["user", "system", "common", "dynamic"];
 
+/// Configure the Time-Stamp Protocol server from a `--timestamp-url` value.
+///
+/// `none` and `offline` disable time-stamping so signing doesn't need network access.
+fn set_time_stamp_url(
+    settings: &mut SigningSettings,
+    value: &str,
+) -> Result<(), AppleCodesignError> {
+    if value == "none" || value == "offline" {
+        warn!("not time-stamping signatures");
+        settings.clear_time_stamp_url();
+    } else {
+        warn!("using time-stamp protocol server {}", value);
+        settings.set_time_stamp_url(value)?;
+    }
+
+    Ok(())
+}
+
+/// Register resolved keys and certificates with signing settings.
+///
+/// The private key, if any, is paired with the first certificate. Remaining
+/// certificates are chained. Returns whether a signing key was registered.
+fn register_signing_certificates<'key>(
+    settings: &mut SigningSettings<'key>,
+    private_keys: &'key [Box<dyn PrivateKey>],
+    mut public_certificates: Vec<CapturedX509Certificate>,
+    timestamp_url: &str,
+) -> Result<bool, AppleCodesignError> {
+    if private_keys.len() > 1 {
+        error!("at most 1 PRIVATE KEY can be present; aborting");
+        return Err(AppleCodesignError::CliBadArgument);
+    }
+
+    let signing_key = private_keys.first();
+
+    if let Some(signing_key) = signing_key {
+        if public_certificates.is_empty() {
+            error!("a PRIVATE KEY requires a corresponding CERTIFICATE to pair with it");
+            return Err(AppleCodesignError::CliBadArgument);
+        }
+
+        let cert = public_certificates.remove(0);
+
+        warn!("registering signing key");
+        settings.set_signing_key(signing_key.as_key_info_signer(), cert);
+        if let Some(certs) = settings.chain_apple_certificates() {
+            for cert in certs {
+                warn!(
+                    "automatically registered Apple CA certificate: {}",
+                    cert.subject_common_name()
+                        .unwrap_or_else(|| "default".into())
+                );
+            }
+        }
+
+        set_time_stamp_url(settings, timestamp_url)?;
+    }
+
+    if let Some(team_id) = settings.set_team_id_from_signing_certificate() {
+        warn!(
+            "automatically setting team ID from signing certificate: {}",
+            team_id
+        );
+    }
+
+    for cert in public_certificates {
+        warn!("registering extra X.509 certificate");
+        settings.chain_certificate(cert);
+    }
+
+    Ok(signing_key.is_some())
+}
+
+/// Parse a `--code-signature-flags` value, which may be prefixed with a settings scope.
+fn parse_code_signature_flags(s: &str) -> Result<(SettingsScope, CodeSignatureFlags), String> {
+    let (scope, value) = parse_scoped_value(s).map_err(|e| e.to_string())?;
+
+    let allowed = CodeSignatureFlags::all_user_configurable();
+    if !allowed.contains(&value) {
+        return Err(format!(
+            "unknown code signature flag {value}; possible values: {}",
+            allowed.join(", ")
+        ));
+    }
+
+    let flags = CodeSignatureFlags::from_str(value).map_err(|e| e.to_string())?;
+
+    Ok((scope, flags))
+}
+
 fn parse_scoped_value(s: &str) -> Result<(SettingsScope, &str), AppleCodesignError> {
     let parts = s.splitn(2, ':').collect::<Vec<_>>();
 
@@ -413,12 +562,16 @@ struct CertificateSource {
     #[arg(long = "p12-file", alias = "pfx-file")]
     p12_path: Option<String>,
 
-    /// The password to use to open the --p12-file file
+    /// Path to a directory containing .p12/PFX files to load
+    #[arg(long = "p12-directory")]
+    p12_directory: Option<String>,
+
+    /// The password to use to open the --p12-file/--p12-directory files
     #[arg(long, alias = "pfx-password", group = "p12-password")]
     p12_password: Option<String>,
 
     // TODO conflicts with p12_password
-    /// Path to file containing password for opening --p12-file file
+    /// Path to file containing password for opening --p12-file/--p12-directory files
     #[arg(long, alias = "pfx-password-file", group = "p12-password")]
     p12_password_file: Option<String>,
 
@@ -445,112 +598,143 @@ struct CertificateSource {
     /// URL of a remote code signing server
     #[arg(long, default_value = crate::remote_signing::DEFAULT_SERVER_URL)]
     remote_signing_url: String,
+
+    /// Signing identity to use (SHA-256 fingerprint prefix, common name, or team ID)
+    #[arg(long)]
+    identity: Option<String>,
+
+    /// URI of a cloud KMS key to sign with (certificate via --pem-source or --der-source)
+    #[arg(long)]
+    kms_key: Option<String>,
 }
 
 impl CertificateSource {
-    fn resolve_certificates(
+    fn p12_password(&self) -> Result<String, AppleCodesignError> {
+        Ok(if let Some(password) = &self.p12_password {
+            password.to_string()
+        } else if let Some(path) = &self.p12_password_file {
+            std::fs::read_to_string(path)?
+                .lines()
+                .next()
+                .expect("should get a single line")
+                .to_string()
+        } else {
+            dialoguer::Password::new()
+                .with_prompt("Please enter password for p12 file")
+                .interact()?
+        })
+    }
+
+    /// Resolve signing identities from local sources.
+    fn resolve_identities(
         &self,
         scan_smartcard: bool,
-    ) -> Result<(Vec<Box<dyn PrivateKey>>, Vec<CapturedX509Certificate>), AppleCodesignError> {
-        let mut keys: Vec<Box<dyn PrivateKey>> = vec![];
-        let mut certs = vec![];
+    ) -> Result<IdentityResolver, AppleCodesignError> {
+        let mut resolver = IdentityResolver::default();
 
-        if let Some(p12_path) = &self.p12_path {
-            let p12_data = std::fs::read(p12_path)?;
-
-            let p12_password = if let Some(password) = &self.p12_password {
-                password.to_string()
-            } else if let Some(path) = &self.p12_password_file {
-                std::fs::read_to_string(path)?
-                    .lines()
-                    .next()
-                    .expect("should get a single line")
-                    .to_string()
-            } else {
-                dialoguer::Password::new()
-                    .with_prompt("Please enter password for p12 file")
-                    .interact()?
-            };
+        if self.p12_path.is_some() || self.p12_directory.is_some() {
+            let p12_password = self.p12_password()?;
 
-            let (cert, key) = parse_pfx_data(&p12_data, &p12_password)?;
+            if let Some(p12_path) = &self.p12_path {
+                resolver.add_p12_file(p12_path, &p12_password)?;
+            }
 
-            keys.push(Box::new(key));
-            certs.push(cert);
+            if let Some(p12_directory) = &self.p12_directory {
+                warn!("scanning {} for p12 files", p12_directory);
+                let count = resolver.add_p12_directory(p12_directory, &p12_password)?;
+                warn!("loaded {} p12 files", count);
+            }
         }
 
         for pem_source in &self.pem_source {
             warn!("reading PEM data from {}", pem_source);
-            let pem_data = std::fs::read(pem_source)?;
-
-            for pem in pem::parse_many(pem_data).map_err(AppleCodesignError::CertificatePem)? {
-                match pem.tag() {
-                    "CERTIFICATE" => {
-                        certs.push(CapturedX509Certificate::from_der(pem.contents())?);
-                    }
-                    "PRIVATE KEY" => {
-                        keys.push(Box::new(InMemoryPrivateKey::from_pkcs8_der(
-                            pem.contents(),
-                        )?));
-                    }
-                    "RSA PRIVATE KEY" => {
-                        keys.push(Box::new(InMemoryPrivateKey::from_pkcs1_der(
-                            pem.contents(),
-                        )?));
-                    }
-                    tag => warn!("(unhandled PEM tag {}; ignoring)", tag),
-                }
-            }
+            resolver.add_pem_file(pem_source)?;
         }
 
         for der_source in &self.der_source {
             warn!("reading DER file {}", der_source);
-            let der_data = std::fs::read(der_source)?;
-
-            certs.push(CapturedX509Certificate::from_der(der_data)?);
+            resolver.add_der_file(der_source)?;
         }
 
-        self.find_certificates_in_keychain(&mut keys, &mut certs)?;
+        self.find_certificates_in_keychain(&mut resolver)?;
+
+        if let Some(uri) = &self.kms_key {
+            warn!("using cloud KMS key {}", uri);
+            resolver.add_kms_key(uri, kms_backend_from_uri(uri)?.into())?;
+        }
 
         if scan_smartcard {
             if let Some(slot) = &self.smartcard_slot {
                 handle_smartcard_sign_slot(
                     slot,
                     self.smartcard_pin_env.as_ref().map(|x| x.as_str()),
-                    &mut keys,
-                    &mut certs,
+                    &mut resolver,
                 )?;
             }
         }
 
-        let remote_signing_url = if self.remote_signer {
-            Some(self.remote_signing_url.clone())
-        } else {
-            None
-        };
-
-        if let Some(remote_signing_url) = remote_signing_url {
+        if self.remote_signer {
             let initiator = self.get_remote_signing_initiator()?;
 
             let client = UnjoinedSigningClient::new_initiator(
-                remote_signing_url,
+                self.remote_signing_url.clone(),
                 initiator,
                 Some(print_session_join),
             )?;
 
             // As part of the handshake we obtained the public certificates from the signer.
             // So make them the canonical set.
-            if !certs.is_empty() {
+            if !resolver.identities().is_empty() {
                 warn!(
                     "ignoring {} local certificates and using remote signer's certificate(s)",
-                    certs.len()
+                    resolver.identities().len()
                 );
             }
 
-            certs = vec![client.signing_certificate().clone()];
-            certs.extend(client.certificate_chain().iter().cloned());
+            resolver = IdentityResolver::default();
+            resolver.add_remote_signer(&self.remote_signing_url, client);
+        }
+
+        Ok(resolver)
+    }
+
+    fn resolve_certificates(
+        &self,
+        scan_smartcard: bool,
+    ) -> Result<(Vec<Box<dyn PrivateKey>>, Vec<CapturedX509Certificate>), AppleCodesignError> {
+        let resolver = self.resolve_identities(scan_smartcard)?;
+
+        let mut keys: Vec<Box<dyn PrivateKey>> = vec![];
+        let mut certs = vec![];
+
+        if let Some(selector) = &self.identity {
+            let index = resolver.position(selector)?;
+            let (mut identities, _) = resolver.into_parts();
+            let identity = identities.remove(index);
+
+            let descriptor = identity.descriptor()?;
+            warn!("using signing identity {}", descriptor);
+
+            let (cert, key) = identity.into_parts();
+            keys.extend(key);
+            certs.push(cert);
+
+            // Certificates without private keys may constitute the certificate chain.
+            for identity in identities {
+                if identity.private_key().is_none() {
+                    certs.push(identity.into_parts().0);
+                }
+            }
+        } else {
+            let (identities, unpaired_keys) = resolver.into_parts();
+
+            for identity in identities {
+                let (cert, key) = identity.into_parts();
+                keys.extend(key);
+                certs.push(cert);
+            }
 
-            // The client implements Sign, so we just use it as the private key.
-            keys = vec![Box::new(client)];
+            keys.extend(unpaired_keys.into_iter().map(|(_, key)| key));
         }
 
         Ok((keys, certs))
@@ -559,8 +743,7 @@ impl CertificateSource {
     #[cfg(target_os = "macos")]
     fn find_certificates_in_keychain(
         &self,
-        private_keys: &mut Vec<Box<dyn PrivateKey>>,
-        public_certificates: &mut Vec<CapturedX509Certificate>,
+        resolver: &mut IdentityResolver,
     ) -> Result<(), AppleCodesignError> {
         // No arguments pertinent to keychains. Don't even speak to the
         // keychain API since this could only error.
@@ -592,12 +775,16 @@ impl CertificateSource {
 
                     wanted_fingerprint.to_ascii_lowercase() == got_fingerprint.to_ascii_lowercase()
                 } else {
-                    false
+                    // Candidates are filtered later when selecting by --identity.
+                    self.identity.is_some()
                 };
 
                 if matches {
-                    public_certificates.push(cert.as_captured_x509_certificate());
-                    private_keys.push(Box::new(cert));
+                    resolver.add_identity(SigningIdentity::new(
+                        IdentitySource::Keychain(format!("{domain:?}").to_ascii_lowercase()),
+                        cert.as_captured_x509_certificate(),
+                        Some(Box::new(cert)),
+                    ));
                 }
             }
         }
@@ -608,8 +795,7 @@ impl CertificateSource {
     #[cfg(not(target_os = "macos"))]
     fn find_certificates_in_keychain(
         &self,
-        _private_keys: &mut [Box<dyn PrivateKey>],
-        _public_certificates: &mut [CapturedX509Certificate],
+        _resolver: &mut IdentityResolver,
     ) -> Result<(), AppleCodesignError> {
         if !self.keychain_domain.is_empty() || self.keychain_fingerprint.is_some() {
             error!(
@@ -877,8 +1063,7 @@ fn prompt_smartcard_pin() -> Result<Vec<u8>, AppleCodesignError> {
 fn handle_smartcard_sign_slot(
     slot: &str,
     pin_env_var: Option<&str>,
-    private_keys: &mut Vec<Box<dyn PrivateKey>>,
-    public_certificates: &mut Vec<CapturedX509Certificate>,
+    resolver: &mut IdentityResolver,
 ) -> Result<(), AppleCodesignError> {
     let slot_id = ::yubikey::piv::SlotId::from_str(slot)?;
     let formatted = hex::encode([u8::from(slot_id)]);
@@ -901,8 +1086,11 @@ fn handle_smartcard_sign_slot(
 
     if let Some(cert) = yk.get_certificate_signer(slot_id)? {
         warn!("using certificate in smartcard slot {}", formatted);
-        public_certificates.push(cert.certificate().clone());
-        private_keys.push(Box::new(cert));
+        resolver.add_identity(SigningIdentity::new(
+            IdentitySource::Smartcard(formatted),
+            cert.certificate().clone(),
+            Some(Box::new(cert)),
+        ));
 
         Ok(())
     } else {
@@ -914,8 +1102,7 @@ fn handle_smartcard_sign_slot(
 fn handle_smartcard_sign_slot(
     _slot: &str,
     _pin_env_var: Option<&str>,
-    _private_keys: &mut [Box<dyn PrivateKey>],
-    _public_certificates: &mut [CapturedX509Certificate],
+    _resolver: &mut IdentityResolver,
 ) -> Result<(), AppleCodesignError> {
     error!("smartcard support not available; ignoring --smartcard-slot");
 
@@ -941,6 +1128,392 @@ fn command_analyze_certificate(args: &AnalyzeCertificate) -> Result<(), AppleCod
     Ok(())
 }
 
+const CHECK_RECOMMENDATIONS_ABOUT: &str = "\
+Check existing signatures for practices Apple is phasing out.
+
+Apple periodically tightens which signatures its operating systems accept.
+This command examines the signatures of a Mach-O binary or of every Mach-O
+binary in a bundle and reports practices that are deprecated or discouraged,
+so they can be fixed before an OS update starts rejecting them.
+
+The following are reported:
+
+* Signatures only having SHA-1 code directories.
+* Executables whose entitlements are only present as XML and lack a DER
+  encoding.
+* Executables launched by launchd (those in a bundle's
+  Contents/Library/LaunchAgents, Contents/Library/LaunchDaemons, or
+  Contents/Library/LaunchServices directories) lacking launch constraints,
+  which macOS 14 recommends.
+
+Each finding is printed on its own line. The command exits with an error if
+anything is reported.
+";
+
+#[derive(Parser)]
+struct CheckRecommendations {
+    /// Path to Mach-O binary or bundle to check
+    path: PathBuf,
+}
+
+fn command_check_recommendations(args: &CheckRecommendations) -> Result<(), AppleCodesignError> {
+    let recommendations = signature_recommendations(&args.path)?;
+
+    for recommendation in &recommendations {
+        println!("{recommendation}");
+    }
+
+    if recommendations.is_empty() {
+        eprintln!("no deprecated signature practices found");
+        Ok(())
+    } else {
+        Err(AppleCodesignError::CliGeneralError(format!(
+            "{} deprecated signature practices found",
+            recommendations.len()
+        )))
+    }
+}
+
+const CODESIGN_COMPAT_ABOUT: &str = "\
+Sign, display, or verify code using Apple's `codesign` command syntax.
+
+This command accepts a subset of the arguments of Apple's `codesign` tool
+and translates them to the equivalent operations of this tool. It is
+intended to ease adoption in existing build scripts invoking `codesign`.
+
+The following `codesign` arguments are recognized:
+
+-s, --sign <IDENTITY>
+   Sign the given paths. `-` requests ad-hoc signing. Any other value
+   selects a signing identity by SHA-256 fingerprint prefix, common name,
+   or team ID (see `--identity` of the `sign` command). On macOS, the
+   user keychain is searched if no other certificate source is given.
+-f, --force
+   Replace existing signatures. Without this, signing content that is
+   already signed is an error.
+--entitlements <PATH>
+   Path to a plist file containing entitlements.
+--deep
+   Also sign nested bundles. Without this, only the main bundle is signed.
+-o, --options <FLAGS>
+   Comma delimited code signature flags to set (e.g. `runtime`).
+-i, --identifier <IDENTIFIER>
+   Identifier string for the binary.
+-d, --display
+   Print information about signatures of the given paths. `-v` may be
+   specified to increase verbosity (e.g. `-dvvv`).
+--verify
+   Verify the signatures of the given paths. `-v` without any other
+   operation is also interpreted as a request to verify.
+
+Certificate source arguments of the `sign` command can also be passed to
+control where signing identities are resolved from.
+";
+
+#[derive(Parser)]
+struct CodesignCompat {
+    /// Sign with the given identity (`-` for ad-hoc signing)
+    #[arg(short = 's', long = "sign", group = "operation")]
+    sign_identity: Option<String>,
+
+    /// Replace existing signatures
+    #[arg(short = 'f', long)]
+    force: bool,
+
+    /// Path to a plist file containing entitlements
+    #[arg(long)]
+    entitlements: Option<String>,
+
+    /// Sign nested bundles
+    #[arg(long)]
+    deep: bool,
+
+    /// Comma delimited code signature flags to set
+    #[arg(short = 'o', long)]
+    options: Option<String>,
+
+    /// Identifier string for binary
+    #[arg(short = 'i', long)]
+    identifier: Option<String>,
+
+    /// Display signature information
+    #[arg(short = 'd', long, group = "operation")]
+    display: bool,
+
+    /// Verify signatures
+    #[arg(long, group = "operation")]
+    verify: bool,
+
+    /// Remove signatures from Mach-O binaries
+    #[arg(long, group = "operation")]
+    remove_signature: bool,
+
+    /// Paths to operate on
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    #[command(flatten)]
+    certificate: CertificateSource,
+}
+
+/// Whether a path already has a code signature.
+fn path_is_signed(path: &std::path::Path) -> Result<bool, AppleCodesignError> {
+    if path.is_dir() {
+        return Ok(path.join("_CodeSignature").exists()
+            || path.join("Contents").join("_CodeSignature").exists());
+    }
+
+    let data = std::fs::read(path)?;
+
+    Ok(match MachFile::parse(&data) {
+        Ok(mach) => mach
+            .iter_macho()
+            .any(|macho| matches!(macho.code_signature(), Ok(Some(_)))),
+        Err(_) => false,
+    })
+}
+
+/// Render signature information of a path in the layout of `codesign -d`.
+fn codesign_display(path: &Path, verbose: u8) -> Result<String, AppleCodesignError> {
+    let (executable, bundle_format, info_plist, code_resources) = if path.is_dir() {
+        let bundle = apple_bundles::DirectoryBundle::new_from_path(path)
+            .map_err(AppleCodesignError::DirectoryBundle)?;
+
+        let main = bundle
+            .main_executable()
+            .map_err(AppleCodesignError::DirectoryBundle)?;
+        let executable = match main {
+            Some(main) if bundle.shallow() => bundle.resolve_path(main),
+            Some(main) => bundle.resolve_path(format!("MacOS/{main}")),
+            None => {
+                return Err(AppleCodesignError::CliGeneralError(format!(
+                    "{}: bundle has no main executable",
+                    path.display()
+                )))
+            }
+        };
+
+        let code_resources =
+            match std::fs::read(bundle.resolve_path("_CodeSignature/CodeResources")) {
+                Ok(data) => Some(CodeResources::from_xml(&data)?),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e.into()),
+            };
+
+        (
+            executable,
+            Some(match bundle.package_type() {
+                apple_bundles::BundlePackageType::App => "app bundle",
+                apple_bundles::BundlePackageType::Framework => "framework bundle",
+                apple_bundles::BundlePackageType::Bundle => "bundle",
+            }),
+            Some(bundle.info_plist().clone()),
+            code_resources,
+        )
+    } else {
+        (path.to_path_buf(), None, None, None)
+    };
+
+    let data = std::fs::read(&executable)?;
+    let mach = MachFile::parse(&data)?;
+
+    let format = if mach.is_fat() {
+        format!(
+            "Mach-O universal ({})",
+            mach.iter_macho()
+                .filter_map(|macho| goblin::mach::cputype::get_arch_name_from_types(
+                    macho.macho.header.cputype(),
+                    macho.macho.header.cpusubtype()
+                ))
+                .collect::<Vec<_>>()
+                .join(" ")
+        )
+    } else {
+        let macho = mach.nth_macho(0)?;
+        format!(
+            "Mach-O thin ({})",
+            goblin::mach::cputype::get_arch_name_from_types(
+                macho.macho.header.cputype(),
+                macho.macho.header.cpusubtype()
+            )
+            .unwrap_or("unknown")
+        )
+    };
+    let format = match bundle_format {
+        Some(bundle_format) => format!("{bundle_format} with {format}"),
+        None => format,
+    };
+
+    // Like codesign, describe the first architecture of universal binaries.
+    let macho = mach.nth_macho(0)?;
+    let mut display = CodesignDisplay::from_macho(macho)?
+        .executable(executable.display())
+        .format(format)
+        .verbosity(verbose);
+
+    if let Some(info_plist) = info_plist {
+        display = display.info_plist(info_plist);
+    }
+    if let Some(code_resources) = code_resources {
+        display = display.code_resources(code_resources);
+    }
+
+    display.render()
+}
+
+impl CodesignCompat {
+    /// Resolve the certificate sources for signing with `identity`.
+    ///
+    /// Ad-hoc signing (`-`) ignores all certificate sources. Otherwise
+    /// `identity` is selected from the given sources. Like `codesign`, the user
+    /// keychain is searched if `default_keychain` is set and no other source is
+    /// given.
+    fn signing_certificate_source(
+        &self,
+        identity: &str,
+        default_keychain: bool,
+    ) -> CertificateSource {
+        let mut certificate = self.certificate.clone();
+
+        if identity == "-" {
+            warn!("ad-hoc signing requested; ignoring certificate sources");
+            certificate.smartcard_slot = None;
+            certificate.keychain_domain.clear();
+            certificate.keychain_fingerprint = None;
+            certificate.pem_source.clear();
+            certificate.der_source.clear();
+            certificate.p12_path = None;
+            certificate.p12_directory = None;
+            certificate.remote_signer = false;
+            certificate.identity = None;
+            certificate.kms_key = None;
+        } else {
+            if default_keychain
+                && certificate.keychain_domain.is_empty()
+                && certificate.pem_source.is_empty()
+                && certificate.der_source.is_empty()
+                && certificate.p12_path.is_none()
+                && certificate.p12_directory.is_none()
+                && certificate.smartcard_slot.is_none()
+                && certificate.kms_key.is_none()
+                && !certificate.remote_signer
+            {
+                certificate.keychain_domain = vec!["user".to_string()];
+            }
+
+            certificate.identity = Some(identity.to_string());
+        }
+
+        certificate
+    }
+}
+
+fn command_codesign_compat(args: &CodesignCompat, verbose: u8) -> Result<(), AppleCodesignError> {
+    if let Some(identity) = &args.sign_identity {
+        let certificate = args.signing_certificate_source(identity, cfg!(target_os = "macos"));
+
+        for path in &args.paths {
+            if !args.force && path_is_signed(path)? {
+                return Err(AppleCodesignError::CliGeneralError(format!(
+                    "{}: is already signed (use -f to replace the existing signature)",
+                    path.display()
+                )));
+            }
+
+            let sign = Sign {
+                binary_identifier: args.identifier.iter().cloned().collect(),
+                code_requirements_path: vec![],
+                code_resources: vec![],
+                code_signature_flags: args
+                    .options
+                    .iter()
+                    .flat_map(|options| options.split(','))
+                    .filter(|flag| !flag.is_empty())
+                    .map(|flag| {
+                        parse_code_signature_flags(flag.trim())
+                            .map_err(AppleCodesignError::CliGeneralError)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+                digest: None,
+                extra_digest: vec![],
+                entitlements_xml_path: args.entitlements.iter().cloned().collect(),
+                library_constraints_path: vec![],
+                runtime_version: vec![],
+                macho_file_type: vec![],
+                info_plist_path: vec![],
+                team_name: None,
+                timestamp_url: if identity == "-" {
+                    "none".to_string()
+                } else {
+                    APPLE_TIMESTAMP_URL.to_string()
+                },
+                exclude: if args.deep {
+                    vec![]
+                } else {
+                    vec!["**".to_string()]
+                },
+                distribution: None,
+                skip_certificate_validation: false,
+                reuse_resource_seals: false,
+                preserve_designated_requirement: false,
+                input_path: path.clone(),
+                output_path: None,
+                certificate: certificate.clone(),
+            };
+
+            command_sign(&sign)?;
+        }
+
+        Ok(())
+    } else if args.display {
+        for path in &args.paths {
+            print!("{}", codesign_display(path, verbose)?);
+        }
+
+        Ok(())
+    } else if args.remove_signature {
+        for path in &args.paths {
+            if path.is_dir() {
+                return Err(AppleCodesignError::CliGeneralError(format!(
+                    "{}: removing signatures from bundles is not supported",
+                    path.display()
+                )));
+            }
+
+            let data = std::fs::read(path)?;
+            let unsigned = crate::macho_signing::remove_signature(&data)?;
+            crate::macho_signing::write_macho_file(path, path, &unsigned)?;
+        }
+
+        Ok(())
+    } else if args.verify || verbose > 0 {
+        let mut have_problems = false;
+
+        for path in &args.paths {
+            let problems = crate::verify::verify_path(path);
+
+            for problem in &problems {
+                println!("{problem}");
+            }
+
+            if problems.is_empty() {
+                eprintln!("{}: valid on disk", path.display());
+            } else {
+                have_problems = true;
+            }
+        }
+
+        if have_problems {
+            Err(AppleCodesignError::VerificationProblems)
+        } else {
+            Ok(())
+        }
+    } else {
+        error!("one of -s, -d, --verify, or --remove-signature is required");
+        Err(AppleCodesignError::CliBadArgument)
+    }
+}
+
 #[derive(Parser)]
 struct ComputeCodeHashes {
     /// Path to Mach-O binary to examine.
@@ -1095,21 +1668,24 @@ fn command_encode_app_store_connect_api_key(
 }
 
 fn print_signed_data(
+    out: &mut dyn Write,
     prefix: &str,
     signed_data: &SignedData,
     external_content: Option<Vec<u8>>,
 ) -> Result<(), AppleCodesignError> {
-    println!(
+    writeln!(
+        out,
         "{}signed content (embedded): {:?}",
         prefix,
         signed_data.signed_content().map(hex::encode)
-    );
-    println!(
+    )?;
+    writeln!(
+        out,
         "{}signed content (external): {:?}... ({} bytes)",
         prefix,
         external_content.as_ref().map(|x| hex::encode(&x[0..40])),
         external_content.as_ref().map(|x| x.len()).unwrap_or(0),
-    );
+    )?;
 
     let content = if let Some(v) = signed_data.signed_content() {
         Some(v)
@@ -1118,151 +1694,177 @@ fn print_signed_data(
     };
 
     if let Some(content) = content {
-        println!(
+        writeln!(
+            out,
             "{}signed content SHA-1:   {}",
             prefix,
             hex::encode(DigestType::Sha1.digest_data(content)?)
-        );
-        println!(
+        )?;
+        writeln!(
+            out,
             "{}signed content SHA-256: {}",
             prefix,
             hex::encode(DigestType::Sha256.digest_data(content)?)
-        );
-        println!(
+        )?;
+        writeln!(
+            out,
             "{}signed content SHA-384: {}",
             prefix,
             hex::encode(DigestType::Sha384.digest_data(content)?)
-        );
-        println!(
+        )?;
+        writeln!(
+            out,
             "{}signed content SHA-512: {}",
             prefix,
             hex::encode(DigestType::Sha512.digest_data(content)?)
-        );
+        )?;
     }
-    println!(
+    writeln!(
+        out,
         "{}certificate count: {}",
         prefix,
         signed_data.certificates().count()
-    );
+    )?;
     for (i, cert) in signed_data.certificates().enumerate() {
-        println!(
+        writeln!(
+            out,
             "{}certificate #{}: subject CN={}; self signed={}",
             prefix,
             i,
             cert.subject_common_name()
                 .unwrap_or_else(|| "<unknown>".to_string()),
             cert.subject_is_issuer()
-        );
+        )?;
     }
-    println!("{}signer count: {}", prefix, signed_data.signers().count());
+    writeln!(
+        out,
+        "{}signer count: {}",
+        prefix,
+        signed_data.signers().count()
+    )?;
     for (i, signer) in signed_data.signers().enumerate() {
-        println!(
+        writeln!(
+            out,
             "{}signer #{}: digest algorithm: {:?}",
             prefix,
             i,
             signer.digest_algorithm()
-        );
-        println!(
+        )?;
+        writeln!(
+            out,
             "{}signer #{}: signature algorithm: {:?}",
             prefix,
             i,
             signer.signature_algorithm()
-        );
+        )?;
 
         if let Some(sa) = signer.signed_attributes() {
-            println!(
+            writeln!(
+                out,
                 "{}signer #{}: content type: {}",
                 prefix,
                 i,
                 sa.content_type()
-            );
-            println!(
+            )?;
+            writeln!(
+                out,
                 "{}signer #{}: message digest: {}",
                 prefix,
                 i,
                 hex::encode(sa.message_digest())
-            );
-            println!(
+            )?;
+            writeln!(
+                out,
                 "{}signer #{}: signing time: {:?}",
                 prefix,
                 i,
                 sa.signing_time()
-            );
+            )?;
         }
 
         let digested_data = signer.signed_content_with_signed_data(signed_data);
 
-        println!(
+        writeln!(
+            out,
             "{}signer #{}: signature content SHA-1:   {}",
             prefix,
             i,
             hex::encode(DigestType::Sha1.digest_data(&digested_data)?)
-        );
-        println!(
+        )?;
+        writeln!(
+            out,
             "{}signer #{}: signature content SHA-256: {}",
             prefix,
             i,
             hex::encode(DigestType::Sha256.digest_data(&digested_data)?)
-        );
-        println!(
+        )?;
+        writeln!(
+            out,
             "{}signer #{}: signature content SHA-384: {}",
             prefix,
             i,
             hex::encode(DigestType::Sha384.digest_data(&digested_data)?)
-        );
-        println!(
+        )?;
+        writeln!(
+            out,
             "{}signer #{}: signature content SHA-512: {}",
             prefix,
             i,
             hex::encode(DigestType::Sha512.digest_data(&digested_data)?)
-        );
+        )?;
 
         if signed_data.signed_content().is_some() {
-            println!(
+            writeln!(
+                out,
                 "{}signer #{}: digest valid: {}",
                 prefix,
                 i,
                 signer
                     .verify_message_digest_with_signed_data(signed_data)
                     .is_ok()
-            );
+            )?;
         }
-        println!(
+        writeln!(
+            out,
             "{}signer #{}: signature valid: {}",
             prefix,
             i,
             signer
                 .verify_signature_with_signed_data(signed_data)
                 .is_ok()
-        );
+        )?;
 
-        println!(
+        writeln!(
+            out,
             "{}signer #{}: time-stamp token present: {}",
             prefix,
             i,
             signer.time_stamp_token_signed_data()?.is_some()
-        );
+        )?;
 
         if let Some(tsp_signed_data) = signer.time_stamp_token_signed_data()? {
             let prefix = format!("{prefix}signer #{i}: time-stamp token: ");
 
-            print_signed_data(&prefix, &tsp_signed_data, None)?;
+            print_signed_data(out, &prefix, &tsp_signed_data, None)?;
         }
     }
 
     Ok(())
 }
 
-const EXTRACT_DATA: [&str; 21] = [
+const EXTRACT_DATA: [&str; 24] = [
+    "blob-raw",
     "blobs",
     "cms-info",
     "cms-pem",
     "cms-raw",
     "cms",
+    "code-directory-json",
     "code-directory-raw",
     "code-directory-serialized-raw",
     "code-directory-serialized",
     "code-directory",
+    "entitlements",
     "linkedit-info",
     "linkedit-segment-raw",
     "macho-load-commands",
@@ -1283,12 +1885,37 @@ struct Extract {
     path: PathBuf,
 
     /// Which data to extract and how to format it
-    #[arg(long, value_parser = EXTRACT_DATA, default_value = "linkedit-info")]
+    #[arg(
+        long,
+        value_parser = EXTRACT_DATA,
+        default_value = "linkedit-info",
+        requires_if("blob-raw", "slot")
+    )]
     data: String,
 
     /// Index of Mach-O binary to operate on within a universal/fat binary
     #[arg(long, default_value = "0")]
     universal_index: usize,
+
+    /// Signature slot to extract with `--data blob-raw` (e.g. `5` or `0x10000`)
+    #[arg(long, value_parser = parse_slot)]
+    slot: Option<CodeSigningSlot>,
+
+    /// Write extracted data to this file instead of stdout
+    #[arg(long, short = 'o')]
+    output: Option<PathBuf>,
+}
+
+fn parse_slot(s: &str) -> Result<CodeSigningSlot, String> {
+    let value = if let Some(hex) = s.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16)
+    } else {
+        s.parse::<u32>()
+    };
+
+    value
+        .map(CodeSigningSlot::from)
+        .map_err(|e| format!("invalid slot number: {e}"))
 }
 
 fn command_extract(args: &Extract) -> Result<(), AppleCodesignError> {
@@ -1296,7 +1923,24 @@ fn command_extract(args: &Extract) -> Result<(), AppleCodesignError> {
     let mach = MachFile::parse(&data)?;
     let macho = mach.nth_macho(args.universal_index)?;
 
+    // Output is buffered so nothing is written if there is no data to extract.
+    let mut out = vec![];
+
     match args.data.as_str() {
+        "blob-raw" => {
+            let slot = args.slot.ok_or_else(|| {
+                AppleCodesignError::CliGeneralError("--data blob-raw requires --slot".into())
+            })?;
+            let embedded = macho
+                .code_signature()?
+                .ok_or(AppleCodesignError::BinaryNoCodeSignature)?;
+
+            if let Some(blob) = embedded.find_slot(slot) {
+                out.write_all(blob.data)?;
+            } else {
+                eprintln!("no blob in slot {slot}");
+            }
+        }
         "blobs" => {
             let embedded = macho
                 .code_signature()?
@@ -1304,7 +1948,7 @@ fn command_extract(args: &Extract) -> Result<(), AppleCodesignError> {
 
             for blob in embedded.blobs {
                 let parsed = blob.into_parsed_blob()?;
-                println!("{parsed:#?}");
+                writeln!(out, "{parsed:#?}")?;
             }
         }
         "cms-info" => {
@@ -1321,7 +1965,7 @@ fn command_extract(args: &Extract) -> Result<(), AppleCodesignError> {
                     None
                 };
 
-                print_signed_data("", &signed_data, cd_data)?;
+                print_signed_data(&mut out, "", &signed_data, cd_data)?;
             } else {
                 eprintln!("no CMS data");
             }
@@ -1332,7 +1976,11 @@ fn command_extract(args: &Extract) -> Result<(), AppleCodesignError> {
                 .ok_or(AppleCodesignError::BinaryNoCodeSignature)?;
 
             if let Some(cms) = embedded.signature_data()? {
-                print!("{}", pem::encode(&pem::Pem::new("PKCS7", cms.to_vec())));
+                write!(
+                    out,
+                    "{}",
+                    pem::encode(&pem::Pem::new("PKCS7", cms.to_vec()))
+                )?;
             } else {
                 eprintln!("no CMS data");
             }
@@ -1343,7 +1991,7 @@ fn command_extract(args: &Extract) -> Result<(), AppleCodesignError> {
                 .ok_or(AppleCodesignError::BinaryNoCodeSignature)?;
 
             if let Some(cms) = embedded.signature_data()? {
-                std::io::stdout().write_all(cms)?;
+                out.write_all(cms)?;
             } else {
                 eprintln!("no CMS data");
             }
@@ -1354,18 +2002,30 @@ fn command_extract(args: &Extract) -> Result<(), AppleCodesignError> {
                 .ok_or(AppleCodesignError::BinaryNoCodeSignature)?;
 
             if let Some(signed_data) = embedded.signed_data()? {
-                println!("{signed_data:#?}");
+                writeln!(out, "{signed_data:#?}")?;
             } else {
                 eprintln!("no CMS data");
             }
         }
+        "code-directory-json" => {
+            let embedded = macho
+                .code_signature()?
+                .ok_or(AppleCodesignError::BinaryNoCodeSignature)?;
+
+            if let Some(cd) = embedded.code_directory()? {
+                serde_json::to_writer_pretty(&mut out, &CodeDirectory::try_from(*cd)?)?;
+                writeln!(out)?;
+            } else {
+                eprintln!("no code directory");
+            }
+        }
         "code-directory-raw" => {
             let embedded = macho
                 .code_signature()?
                 .ok_or(AppleCodesignError::BinaryNoCodeSignature)?;
 
             if let Some(blob) = embedded.find_slot(CodeSigningSlot::CodeDirectory) {
-                std::io::stdout().write_all(blob.data)?;
+                out.write_all(blob.data)?;
             } else {
                 eprintln!("no code directory");
             }
@@ -1376,7 +2036,7 @@ fn command_extract(args: &Extract) -> Result<(), AppleCodesignError> {
                 .ok_or(AppleCodesignError::BinaryNoCodeSignature)?;
 
             if let Ok(Some(cd)) = embedded.code_directory() {
-                std::io::stdout().write_all(&cd.to_blob_bytes()?)?;
+                out.write_all(&cd.to_blob_bytes()?)?;
             } else {
                 eprintln!("no code directory");
             }
@@ -1388,7 +2048,11 @@ fn command_extract(args: &Extract) -> Result<(), AppleCodesignError> {
 
             if let Ok(Some(cd)) = embedded.code_directory() {
                 let serialized = cd.to_blob_bytes()?;
-                println!("{:#?}", CodeDirectoryBlob::from_blob_bytes(&serialized)?);
+                writeln!(
+                    out,
+                    "{:#?}",
+                    CodeDirectoryBlob::from_blob_bytes(&serialized)?
+                )?;
             }
         }
         "code-directory" => {
@@ -1397,57 +2061,94 @@ fn command_extract(args: &Extract) -> Result<(), AppleCodesignError> {
                 .ok_or(AppleCodesignError::BinaryNoCodeSignature)?;
 
             if let Some(cd) = embedded.code_directory()? {
-                println!("{cd:#?}");
+                writeln!(out, "{cd:#?}")?;
             } else {
                 eprintln!("no code directory");
             }
         }
+        "entitlements" => {
+            let embedded = macho
+                .code_signature()?
+                .ok_or(AppleCodesignError::BinaryNoCodeSignature)?;
+
+            if let Some(entitlements) = embedded.entitlements()? {
+                writeln!(out, "{}", entitlements.as_str().trim_end())?;
+            } else if let Some(entitlements) = embedded.entitlements_der()? {
+                entitlements
+                    .parse_der()?
+                    .to_writer_xml(&mut out)
+                    .map_err(AppleCodesignError::PlistSerializeXml)?;
+                writeln!(out)?;
+            } else {
+                eprintln!("no entitlements");
+            }
+        }
         "linkedit-info" => {
             let sig = macho
                 .find_signature_data()?
                 .ok_or(AppleCodesignError::BinaryNoCodeSignature)?;
-            println!("__LINKEDIT segment index: {}", sig.linkedit_segment_index);
-            println!(
+            writeln!(
+                out,
+                "__LINKEDIT segment index: {}",
+                sig.linkedit_segment_index
+            )?;
+            writeln!(
+                out,
                 "__LINKEDIT segment start offset: {}",
                 sig.linkedit_segment_start_offset
-            );
-            println!(
+            )?;
+            writeln!(
+                out,
                 "__LINKEDIT segment end offset: {}",
                 sig.linkedit_segment_end_offset
-            );
-            println!(
+            )?;
+            writeln!(
+                out,
                 "__LINKEDIT segment size: {}",
                 sig.linkedit_segment_data.len()
-            );
-            println!(
+            )?;
+            writeln!(
+                out,
                 "__LINKEDIT signature global start offset: {}",
                 sig.linkedit_signature_start_offset
-            );
-            println!(
+            )?;
+            writeln!(
+                out,
                 "__LINKEDIT signature global end offset: {}",
                 sig.linkedit_signature_end_offset
-            );
-            println!(
+            )?;
+            writeln!(
+                out,
                 "__LINKEDIT signature local segment start offset: {}",
                 sig.signature_start_offset
-            );
-            println!(
+            )?;
+            writeln!(
+                out,
                 "__LINKEDIT signature local segment end offset: {}",
                 sig.signature_end_offset
-            );
-            println!("__LINKEDIT signature size: {}", sig.signature_data.len());
+            )?;
+            writeln!(
+                out,
+                "__LINKEDIT signature size: {}",
+                sig.signature_data.len()
+            )?;
         }
         "linkedit-segment-raw" => {
             let sig = macho
                 .find_signature_data()?
                 .ok_or(AppleCodesignError::BinaryNoCodeSignature)?;
-            std::io::stdout().write_all(sig.linkedit_segment_data)?;
+            out.write_all(sig.linkedit_segment_data)?;
         }
         "macho-load-commands" => {
-            println!("load command count: {}", macho.macho.load_commands.len());
+            writeln!(
+                out,
+                "load command count: {}",
+                macho.macho.load_commands.len()
+            )?;
 
             for command in &macho.macho.load_commands {
-                println!(
+                writeln!(
+                    out,
                     "{}; offsets=0x{:x}-0x{:x} ({}-{}); size={}",
                     goblin::mach::load_command::cmd_to_str(command.command.cmd()),
                     command.offset,
@@ -1455,15 +2156,16 @@ fn command_extract(args: &Extract) -> Result<(), AppleCodesignError> {
                     command.offset,
                     command.offset + command.command.cmdsize(),
                     command.command.cmdsize(),
-                );
+                )?;
             }
         }
         "macho-segments" => {
-            println!("segments count: {}", macho.macho.segments.len());
+            writeln!(out, "segments count: {}", macho.macho.segments.len())?;
             for (segment_index, segment) in macho.macho.segments.iter().enumerate() {
                 let sections = segment.sections()?;
 
-                println!(
+                writeln!(
+                    out,
                     "segment #{}; {}; offsets=0x{:x}-0x{:x}; vm/file size {}/{}; section count {}",
                     segment_index,
                     segment.name()?,
@@ -1472,9 +2174,10 @@ fn command_extract(args: &Extract) -> Result<(), AppleCodesignError> {
                     segment.vmsize,
                     segment.filesize,
                     sections.len()
-                );
+                )?;
                 for (section_index, (section, _)) in sections.into_iter().enumerate() {
-                    println!(
+                    writeln!(
+                        out,
                         "segment #{}; section #{}: {}; segment offsets=0x{:x}-0x{:x} size {}",
                         segment_index,
                         section_index,
@@ -1482,17 +2185,17 @@ fn command_extract(args: &Extract) -> Result<(), AppleCodesignError> {
                         section.offset,
                         section.offset as u64 + section.size,
                         section.size
-                    );
+                    )?;
                 }
             }
         }
         "macho-target" => {
             if let Some(target) = macho.find_targeting()? {
-                println!("Platform: {}", target.platform);
-                println!("Minimum OS: {}", target.minimum_os_version);
-                println!("SDK: {}", target.sdk_version);
+                writeln!(out, "Platform: {}", target.platform)?;
+                writeln!(out, "Minimum OS: {}", target.minimum_os_version)?;
+                writeln!(out, "SDK: {}", target.sdk_version)?;
             } else {
-                println!("Unable to resolve Mach-O targeting from load commands");
+                writeln!(out, "Unable to resolve Mach-O targeting from load commands")?;
             }
         }
         "requirements-raw" => {
@@ -1501,7 +2204,7 @@ fn command_extract(args: &Extract) -> Result<(), AppleCodesignError> {
                 .ok_or(AppleCodesignError::BinaryNoCodeSignature)?;
 
             if let Some(blob) = embedded.find_slot(CodeSigningSlot::RequirementSet) {
-                std::io::stdout().write_all(blob.data)?;
+                out.write_all(blob.data)?;
             } else {
                 eprintln!("no requirements");
             }
@@ -1514,7 +2217,7 @@ fn command_extract(args: &Extract) -> Result<(), AppleCodesignError> {
             if let Some(reqs) = embedded.code_requirements()? {
                 for (typ, req) in &reqs.requirements {
                     for expr in req.parse_expressions()?.iter() {
-                        println!("{typ} => {expr:#?}");
+                        writeln!(out, "{typ} => {expr:#?}")?;
                     }
                 }
             } else {
@@ -1527,7 +2230,7 @@ fn command_extract(args: &Extract) -> Result<(), AppleCodesignError> {
                 .ok_or(AppleCodesignError::BinaryNoCodeSignature)?;
 
             if let Some(reqs) = embedded.code_requirements()? {
-                std::io::stdout().write_all(&reqs.to_blob_bytes()?)?;
+                out.write_all(&reqs.to_blob_bytes()?)?;
             } else {
                 eprintln!("no requirements");
             }
@@ -1539,7 +2242,11 @@ fn command_extract(args: &Extract) -> Result<(), AppleCodesignError> {
 
             if let Some(reqs) = embedded.code_requirements()? {
                 let serialized = reqs.to_blob_bytes()?;
-                println!("{:#?}", RequirementSetBlob::from_blob_bytes(&serialized)?);
+                writeln!(
+                    out,
+                    "{:#?}",
+                    RequirementSetBlob::from_blob_bytes(&serialized)?
+                )?;
             } else {
                 eprintln!("no requirements");
             }
@@ -1552,7 +2259,7 @@ fn command_extract(args: &Extract) -> Result<(), AppleCodesignError> {
             if let Some(reqs) = embedded.code_requirements()? {
                 for (typ, req) in &reqs.requirements {
                     for expr in req.parse_expressions()?.iter() {
-                        println!("{typ} => {expr}");
+                        writeln!(out, "{typ} => {}", expr.to_requirement_source())?;
                     }
                 }
             } else {
@@ -1563,7 +2270,7 @@ fn command_extract(args: &Extract) -> Result<(), AppleCodesignError> {
             let sig = macho
                 .find_signature_data()?
                 .ok_or(AppleCodesignError::BinaryNoCodeSignature)?;
-            std::io::stdout().write_all(sig.signature_data)?;
+            out.write_all(sig.signature_data)?;
         }
         "superblob" => {
             let sig = macho
@@ -1573,70 +2280,135 @@ fn command_extract(args: &Extract) -> Result<(), AppleCodesignError> {
                 .code_signature()?
                 .ok_or(AppleCodesignError::BinaryNoCodeSignature)?;
 
-            println!("file start offset: {}", sig.linkedit_signature_start_offset);
-            println!("file end offset: {}", sig.linkedit_signature_end_offset);
-            println!("__LINKEDIT start offset: {}", sig.signature_start_offset);
-            println!("__LINKEDIT end offset: {}", sig.signature_end_offset);
-            println!("length: {}", embedded.length);
-            println!("blob count: {}", embedded.count);
-            println!("blobs:");
+            writeln!(
+                out,
+                "file start offset: {}",
+                sig.linkedit_signature_start_offset
+            )?;
+            writeln!(
+                out,
+                "file end offset: {}",
+                sig.linkedit_signature_end_offset
+            )?;
+            writeln!(
+                out,
+                "__LINKEDIT start offset: {}",
+                sig.signature_start_offset
+            )?;
+            writeln!(out, "__LINKEDIT end offset: {}", sig.signature_end_offset)?;
+            writeln!(out, "length: {}", embedded.length)?;
+            writeln!(out, "blob count: {}", embedded.count)?;
+            writeln!(out, "blobs:")?;
             for blob in embedded.blobs {
-                println!("- index: {}", blob.index);
-                println!(
+                writeln!(out, "- index: {}", blob.index)?;
+                writeln!(
+                    out,
                     "  offsets: 0x{:x}-0x{:x} ({}-{})",
                     blob.offset,
                     blob.offset + blob.length - 1,
                     blob.offset,
                     blob.offset + blob.length - 1
-                );
-                println!("  length: {}", blob.length);
-                println!("  slot: {:?}", blob.slot);
-                println!("  magic: {:?} (0x{:x})", blob.magic, u32::from(blob.magic));
-                println!(
+                )?;
+                writeln!(out, "  length: {}", blob.length)?;
+                writeln!(out, "  slot: {:?}", blob.slot)?;
+                writeln!(
+                    out,
+                    "  magic: {:?} (0x{:x})",
+                    blob.magic,
+                    u32::from(blob.magic)
+                )?;
+                writeln!(
+                    out,
                     "  sha1: {}",
                     hex::encode(blob.digest_with(DigestType::Sha1)?)
-                );
-                println!(
+                )?;
+                writeln!(
+                    out,
                     "  sha256: {}",
                     hex::encode(blob.digest_with(DigestType::Sha256)?)
-                );
-                println!(
+                )?;
+                writeln!(
+                    out,
                     "  sha256-truncated: {}",
                     hex::encode(blob.digest_with(DigestType::Sha256Truncated)?)
-                );
-                println!(
+                )?;
+                writeln!(
+                    out,
                     "  sha384: {}",
                     hex::encode(blob.digest_with(DigestType::Sha384)?),
-                );
-                println!(
+                )?;
+                writeln!(
+                    out,
                     "  sha512: {}",
                     hex::encode(blob.digest_with(DigestType::Sha512)?),
-                );
-                println!(
+                )?;
+                writeln!(
+                    out,
                     "  sha1-base64: {}",
                     STANDARD_ENGINE.encode(blob.digest_with(DigestType::Sha1)?)
-                );
-                println!(
+                )?;
+                writeln!(
+                    out,
                     "  sha256-base64: {}",
                     STANDARD_ENGINE.encode(blob.digest_with(DigestType::Sha256)?)
-                );
-                println!(
+                )?;
+                writeln!(
+                    out,
                     "  sha256-truncated-base64: {}",
                     STANDARD_ENGINE.encode(blob.digest_with(DigestType::Sha256Truncated)?)
-                );
-                println!(
+                )?;
+                writeln!(
+                    out,
                     "  sha384-base64: {}",
                     STANDARD_ENGINE.encode(blob.digest_with(DigestType::Sha384)?)
-                );
-                println!(
+                )?;
+                writeln!(
+                    out,
                     "  sha512-base64: {}",
                     STANDARD_ENGINE.encode(blob.digest_with(DigestType::Sha512)?)
-                );
+                )?;
             }
         }
         x => panic!("unhandled format: {x}"),
     }
 
+    match &args.output {
+        Some(path) if out.is_empty() => {
+            warn!(
+                "no {} data extracted; not writing {}",
+                args.data,
+                path.display()
+            );
+        }
+        Some(path) => {
+            warn!("writing {} to {}", args.data, path.display());
+            std::fs::write(path, &out)?;
+        }
+        None => std::io::stdout().write_all(&out)?,
+    }
+
+    Ok(())
+}
+
+/// Write private key material to a file only readable by the current user.
+fn write_private_key(path: &Path, data: &[u8]) -> Result<(), AppleCodesignError> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+        options.mode(0o600);
+        let fh = options.open(path)?;
+        // The mode only applies to new files.
+        fh.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        (&fh).write_all(data)?;
+    }
+
+    #[cfg(not(unix))]
+    options.open(path)?.write_all(data)?;
+
     Ok(())
 }
 
@@ -1644,7 +2416,26 @@ fn command_extract(args: &Extract) -> Result<(), AppleCodesignError> {
 struct GenerateCertificateSigningRequest {
     /// Path to file to write PEM encoded CSR to
     #[arg(long)]
-    csr_pem_path: Option<PathBuf>,
+    csr_pem_path: Option<PathBuf>,
+
+    /// Generate a new RSA 2048 private key and write it to this path as PEM
+    ///
+    /// Use this when you don't already have a private key. The written key
+    /// can later be used with --pem-source alongside the issued certificate.
+    #[arg(long)]
+    new_key_pem_path: Option<PathBuf>,
+
+    /// Email address to record in the CSR subject
+    #[arg(long)]
+    email_address: Option<String>,
+
+    /// Common name to record in the CSR subject
+    #[arg(long, default_value = "Apple Code Signing CSR")]
+    common_name: String,
+
+    /// Country code to record in the CSR subject
+    #[arg(long)]
+    country_name: Option<String>,
 
     #[command(flatten)]
     certificate: CertificateSource,
@@ -1653,7 +2444,33 @@ struct GenerateCertificateSigningRequest {
 fn command_generate_certificate_signing_request(
     args: &GenerateCertificateSigningRequest,
 ) -> Result<(), AppleCodesignError> {
-    let private_keys = args.certificate.resolve_certificates(true)?.0;
+    let mut private_keys = args.certificate.resolve_certificates(true)?.0;
+
+    if let Some(key_path) = &args.new_key_pem_path {
+        if !private_keys.is_empty() {
+            error!("--new-key-pem-path cannot be combined with an existing private key");
+            return Err(AppleCodesignError::CliBadArgument);
+        }
+
+        warn!("generating new RSA 2048 private key");
+        let key = InMemoryPrivateKey::generate_rsa(2048)?;
+        let key_pem = pem::encode(&pem::Pem::new(
+            "PRIVATE KEY",
+            key.to_pkcs8_der()
+                .map_err(|e| AppleCodesignError::CertificateGeneric(format!("{e}")))?
+                .as_bytes()
+                .to_vec(),
+        ));
+
+        if let Some(parent) = key_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        warn!("writing PEM encoded private key to {}", key_path.display());
+        write_private_key(key_path, key_pem.as_bytes())?;
+
+        private_keys.push(Box::new(key));
+    }
 
     let private_key = if private_keys.is_empty() {
         error!("no private keys found; a private key is required to sign a certificate signing request");
@@ -1668,21 +2485,14 @@ fn command_generate_certificate_signing_request(
         private_keys.into_iter().next().expect("checked size above")
     };
 
-    let key_algorithm = private_key.key_algorithm().ok_or_else(|| {
-        error!("unable to determine key algorithm of private key (please report this issue)");
-        AppleCodesignError::CliBadArgument
-    })?;
-
-    let mut builder = X509CertificateBuilder::new(key_algorithm);
-    builder
-        .subject()
-        .append_common_name_utf8_string("Apple Code Signing CSR")
-        .map_err(|e| AppleCodesignError::CertificateBuildError(format!("{e:?}")))?;
-
     warn!("generating CSR; you may be prompted to enter credentials to unlock the signing key");
-    let pem = builder
-        .create_certificate_signing_request(private_key.as_key_info_signer())?
-        .encode_pem()?;
+    let pem = create_certificate_signing_request(
+        private_key.as_key_info_signer(),
+        args.email_address.as_deref(),
+        &args.common_name,
+        args.country_name.as_deref(),
+    )?
+    .encode_pem()?;
 
     if let Some(dest_path) = &args.csr_pem_path {
         if let Some(parent) = dest_path.parent() {
@@ -1766,7 +2576,7 @@ fn command_generate_self_signed_certificate(
         println!("writing public certificate to {}", cert_path.display());
         std::fs::write(&cert_path, cert_pem.as_bytes())?;
         println!("writing private signing key to {}", key_path.display());
-        std::fs::write(&key_path, key_pem.as_bytes())?;
+        write_private_key(&key_path, key_pem.as_bytes())?;
 
         wrote_file = true;
     }
@@ -1881,6 +2691,26 @@ fn command_keychain_print_certificates(
     ))
 }
 
+#[derive(Parser)]
+struct ListIdentities {
+    #[command(flatten)]
+    certificate: CertificateSource,
+}
+
+fn command_list_identities(args: &ListIdentities) -> Result<(), AppleCodesignError> {
+    let resolver = args.certificate.resolve_identities(true)?;
+
+    for descriptor in resolver.descriptors()? {
+        println!("{descriptor}");
+    }
+
+    for (source, _) in resolver.unpaired_private_keys() {
+        warn!("private key from {} has no matching certificate", source);
+    }
+
+    Ok(())
+}
+
 #[cfg(feature = "notarize")]
 const NOTARIZE_ABOUT: &str = "\
 Submit a notarization request to Apple.
@@ -1931,6 +2761,21 @@ To automatically staple an asset after server-side processing has finished,
 specify `--staple`. This implies `--wait`.
 ";
 
+#[cfg(feature = "notarize")]
+const NOTARY_VALIDATE_CREDENTIALS_ABOUT: &str = "\
+Verify App Store Connect API credentials are accepted by the Notary API.
+
+This command performs a cheap authenticated request against Apple's Notary
+API (listing previous submissions) using the given API Key. It can be used
+to detect misconfigured credentials early, before a long-running build
+reaches the step where assets are uploaded for notarization.
+
+The command exits with an error if the API Key can't be used to produce an
+authentication token (e.g. because the private key is malformed), if the
+server rejects the credentials, or if the API Key doesn't have permission
+to use the Notary API.
+";
+
 #[cfg(feature = "notarize")]
 #[derive(Parser)]
 struct NotaryLog {
@@ -2030,6 +2875,26 @@ fn command_notary_wait(args: &NotaryWait) -> Result<(), AppleCodesignError> {
     Ok(())
 }
 
+#[cfg(feature = "notarize")]
+#[derive(Parser)]
+struct NotaryValidateCredentials {
+    #[command(flatten)]
+    api: NotaryApi,
+}
+
+#[cfg(feature = "notarize")]
+fn command_notary_validate_credentials(
+    args: &NotaryValidateCredentials,
+) -> Result<(), AppleCodesignError> {
+    let notarizer = args.api.notarizer()?;
+
+    let count = notarizer.validate_credentials()?;
+
+    println!("credentials accepted by Notary API ({count} previous submissions visible)");
+
+    Ok(())
+}
+
 #[derive(Parser)]
 struct ParseCodeSigningRequirement {
     /// Output format
@@ -2050,7 +2915,7 @@ fn command_parse_code_signing_requirement(
     for requirement in requirements.iter() {
         match args.format.as_str() {
             "csrl" => {
-                println!("{requirement}");
+                println!("{}", requirement.to_requirement_source());
             }
             "expression-tree" => {
                 println!("{requirement:#?}");
@@ -2064,6 +2929,10 @@ fn command_parse_code_signing_requirement(
 
 #[derive(Parser)]
 struct PrintSignatureInfo {
+    /// Output format
+    #[arg(long, value_parser = ["yaml", "json"], default_value = "yaml")]
+    format: String,
+
     /// Filesystem path to entity whose info to print
     path: PathBuf,
 }
@@ -2072,7 +2941,109 @@ fn command_print_signature_info(args: &PrintSignatureInfo) -> Result<(), AppleCo
     let reader = SignatureReader::from_path(&args.path)?;
 
     let entities = reader.entities()?;
-    serde_yaml::to_writer(std::io::stdout(), &entities)?;
+
+    match args.format.as_str() {
+        "json" => {
+            serde_json::to_writer_pretty(std::io::stdout(), &entities)?;
+            println!();
+        }
+        "yaml" => serde_yaml::to_writer(std::io::stdout(), &entities)?,
+        format => {
+            return Err(AppleCodesignError::CliGeneralError(format!(
+                "unsupported output format: {format}"
+            )))
+        }
+    }
+
+    Ok(())
+}
+
+const RELEASE_ABOUT: &str = "\
+Sign, package, notarize, and staple a release in one operation.
+
+This command performs the steps typically needed to release software for
+macOS, as described by a YAML configuration file:
+
+1. Every bundle and Mach-O binary in the staging directory is signed in place.
+2. Optionally, the staging directory is packaged into a DMG, which is signed.
+   Creating DMGs requires macOS.
+3. The DMG (or the bundles in the staging directory if there is no DMG) is
+   notarized.
+4. Notarization tickets are stapled.
+5. Optionally, a JSON report recording everything that was done is written.
+
+The configuration file looks like:
+
+    staging_dir: build/staging
+    dmg:
+      path: dist/MyApp.dmg
+      volume_name: MyApp
+    notarize: true
+    staple: true
+    notarize_wait_seconds: 600
+    report_path: dist/release-report.json
+
+Relative paths are relative to the directory of the configuration file. Only
+`staging_dir` is required.
+
+Signing certificates are specified the same way as with the `sign` command.
+Notarization requires an App Store Connect API Key, specified the same way as
+with the `notary-submit` command.
+";
+
+#[derive(Parser)]
+struct Release {
+    /// Path to the YAML release configuration file
+    config_path: PathBuf,
+
+    /// URL of timestamp server to use to obtain a token of the CMS signature. `none` or `offline` disables time-stamping
+    #[arg(long, default_value = APPLE_TIMESTAMP_URL)]
+    timestamp_url: String,
+
+    #[command(flatten)]
+    certificate: CertificateSource,
+
+    #[cfg(feature = "notarize")]
+    #[command(flatten)]
+    api: NotaryApi,
+}
+
+fn command_release(args: &Release) -> Result<(), AppleCodesignError> {
+    let config = ReleaseConfig::from_path(&args.config_path)?;
+
+    let mut settings = SigningSettings::default();
+
+    let (private_keys, public_certificates) = args.certificate.resolve_certificates(true)?;
+
+    if !register_signing_certificates(
+        &mut settings,
+        &private_keys,
+        public_certificates,
+        &args.timestamp_url,
+    )? {
+        warn!("no signing key specified; signing will be ad-hoc");
+    }
+
+    #[allow(unused_mut)]
+    let mut pipeline = ReleasePipeline::new(config.clone(), settings);
+
+    #[cfg(feature = "notarize")]
+    if config.notarize {
+        pipeline.set_notarizer(args.api.notarizer()?);
+    }
+
+    let report = pipeline.run()?;
+
+    if let Some(private) = private_keys.first() {
+        private.finish()?;
+    }
+
+    warn!(
+        "released {} signed entities; {} notarized; {} stapled",
+        report.signed.len() + usize::from(report.dmg.is_some()),
+        report.notarizations.len(),
+        report.stapled.len()
+    );
 
     Ok(())
 }
@@ -2175,7 +3146,7 @@ struct Sign {
     #[arg(long)]
     binary_identifier: Vec<String>,
 
-    /// Path to a file containing binary code requirements data to be used as designated requirements
+    /// Path to a file containing binary code requirements data or code requirements language source to be used as designated requirements
     #[arg(long)]
     code_requirements_path: Vec<String>,
 
@@ -2183,9 +3154,9 @@ struct Sign {
     #[arg(long)]
     code_resources: Vec<String>,
 
-    /// Code signature flags to set
-    #[arg(long, value_parser = CodeSignatureFlags::all_user_configurable())]
-    code_signature_flags: Vec<String>,
+    /// Code signature flag to set, optionally prefixed with a scope (e.g. `@main:runtime`). Flags given multiple times for the same scope are combined
+    #[arg(long, value_parser = parse_code_signature_flags)]
+    code_signature_flags: Vec<(SettingsScope, CodeSignatureFlags)>,
 
     /// Digest algorithm to use
     #[arg(long, value_parser = SUPPORTED_HASHES)]
@@ -2199,10 +3170,18 @@ struct Sign {
     #[arg(short = 'e', long)]
     entitlements_xml_path: Vec<String>,
 
+    /// Path to a plist file containing constraints on libraries that may be loaded
+    #[arg(long)]
+    library_constraints_path: Vec<String>,
+
     /// Hardened runtime version to use (defaults to SDK version used to build binary)
     #[arg(long)]
     runtime_version: Vec<String>,
 
+    /// Mach-O file type to sign as (defaults to the type in the Mach-O header)
+    #[arg(long)]
+    macho_file_type: Vec<String>,
+
     /// Path to an Info.plist file whose digest to include in Mach-O signature
     #[arg(long)]
     info_plist_path: Vec<String>,
@@ -2211,7 +3190,7 @@ struct Sign {
     #[arg(long)]
     team_name: Option<String>,
 
-    /// URL of timestamp server to use to obtain a token of the CMS signature
+    /// URL of timestamp server to use to obtain a token of the CMS signature. `none` or `offline` disables time-stamping
     #[arg(long, default_value = APPLE_TIMESTAMP_URL)]
     timestamp_url: String,
 
@@ -2219,10 +3198,26 @@ struct Sign {
     #[arg(long)]
     exclude: Vec<String>,
 
-    /// Path to Mach-O binary to sign
+    /// Distribution channel to validate the signing certificate against
+    #[arg(long, value_parser = SigningDistribution::str_names())]
+    distribution: Option<String>,
+
+    /// Do not validate the signing certificate is suitable for signing
+    #[arg(long)]
+    skip_certificate_validation: bool,
+
+    /// Reuse digests of bundle resources not modified since the bundle was last signed
+    #[arg(long)]
+    reuse_resource_seals: bool,
+
+    /// Preserve the designated requirement of existing signatures when re-signing
+    #[arg(long)]
+    preserve_designated_requirement: bool,
+
+    /// Path to Mach-O binary to sign (`-` for stdin)
     input_path: PathBuf,
 
-    /// Path to signed Mach-O binary to write
+    /// Path to signed Mach-O binary to write (`-` for stdout)
     output_path: Option<PathBuf>,
 
     #[command(flatten)]
@@ -2232,60 +3227,26 @@ struct Sign {
 fn command_sign(args: &Sign) -> Result<(), AppleCodesignError> {
     let mut settings = SigningSettings::default();
 
-    let (private_keys, mut public_certificates) = args.certificate.resolve_certificates(true)?;
-
-    if private_keys.len() > 1 {
-        error!("at most 1 PRIVATE KEY can be present; aborting");
-        return Err(AppleCodesignError::CliBadArgument);
-    }
-
-    let private = if private_keys.is_empty() {
-        None
-    } else {
-        Some(&private_keys[0])
-    };
-
-    if let Some(signing_key) = &private {
-        if public_certificates.is_empty() {
-            error!("a PRIVATE KEY requires a corresponding CERTIFICATE to pair with it");
-            return Err(AppleCodesignError::CliBadArgument);
-        }
-
-        let cert = public_certificates.remove(0);
-
-        warn!("registering signing key");
-        settings.set_signing_key(signing_key.as_key_info_signer(), cert);
-        if let Some(certs) = settings.chain_apple_certificates() {
-            for cert in certs {
-                warn!(
-                    "automatically registered Apple CA certificate: {}",
-                    cert.subject_common_name()
-                        .unwrap_or_else(|| "default".into())
-                );
-            }
-        }
+    let (private_keys, public_certificates) = args.certificate.resolve_certificates(true)?;
 
-        if args.timestamp_url != "none" {
-            warn!("using time-stamp protocol server {}", args.timestamp_url);
-            settings.set_time_stamp_url(&args.timestamp_url)?;
-        }
-    }
+    register_signing_certificates(
+        &mut settings,
+        &private_keys,
+        public_certificates,
+        &args.timestamp_url,
+    )?;
 
-    if let Some(team_id) = settings.set_team_id_from_signing_certificate() {
-        warn!(
-            "automatically setting team ID from signing certificate: {}",
-            team_id
-        );
+    if let Some(team_name) = &args.team_name {
+        settings.set_team_id(team_name);
     }
 
-    for cert in public_certificates {
-        warn!("registering extra X.509 certificate");
-        settings.chain_certificate(cert);
+    if let Some(value) = &args.distribution {
+        settings.set_signing_distribution(Some(SigningDistribution::from_str(value)?));
     }
 
-    if let Some(team_name) = &args.team_name {
-        settings.set_team_id(team_name);
-    }
+    settings.set_skip_certificate_validation(args.skip_certificate_validation);
+    settings.set_reuse_resource_seals(args.reuse_resource_seals);
+    settings.set_preserve_designated_requirement(args.preserve_designated_requirement);
 
     if let Some(value) = &args.digest {
         let digest_type = DigestType::try_from(value.as_str())?;
@@ -2311,13 +3272,28 @@ fn command_sign(args: &Sign) -> Result<(), AppleCodesignError> {
         let (scope, path) = parse_scoped_value(value)?;
 
         let code_requirements_data = std::fs::read(path)?;
-        let reqs = CodeRequirements::parse_blob(&code_requirements_data)?.0;
-        for expr in reqs.iter() {
+
+        // Compiled requirements begin with blob magic. Anything else is
+        // treated as requirement language source.
+        if code_requirements_data.starts_with(&[0xfa, 0xde, 0x0c, 0x00]) {
+            let reqs = CodeRequirements::parse_blob(&code_requirements_data)?.0;
+            for expr in reqs.iter() {
+                warn!(
+                    "setting designated code requirements for {}: {}",
+                    scope, expr
+                );
+                settings.set_designated_requirement_expression(scope.clone(), expr)?;
+            }
+        } else {
+            let source = String::from_utf8(code_requirements_data)
+                .map_err(|_| AppleCodesignError::RequirementSyntax("source is not UTF-8".into()))?;
+            let requirement = DesignatedRequirement::from_requirement_text(&source)?;
+            let expr = requirement.expression()?;
             warn!(
                 "setting designated code requirements for {}: {}",
                 scope, expr
             );
-            settings.set_designated_requirement_expression(scope.clone(), expr)?;
+            settings.set_designated_requirement_expression(scope.clone(), &expr)?;
         }
     }
 
@@ -2332,11 +3308,8 @@ fn command_sign(args: &Sign) -> Result<(), AppleCodesignError> {
         settings.set_code_resources_data(scope, code_resources_data);
     }
 
-    for value in &args.code_signature_flags {
-        let (scope, value) = parse_scoped_value(value)?;
-
-        let flags = CodeSignatureFlags::from_str(value)?;
-        settings.set_code_signature_flags(scope, flags);
+    for (scope, flags) in &args.code_signature_flags {
+        settings.add_code_signature_flags(scope.clone(), *flags);
     }
 
     for value in &args.entitlements_xml_path {
@@ -2347,6 +3320,17 @@ fn command_sign(args: &Sign) -> Result<(), AppleCodesignError> {
         settings.set_entitlements_xml(scope, entitlements_data)?;
     }
 
+    for value in &args.library_constraints_path {
+        let (scope, path) = parse_scoped_value(value)?;
+
+        warn!(
+            "setting library constraints for {} from path {}",
+            scope, path
+        );
+        let constraints_data = std::fs::read_to_string(path)?;
+        settings.set_library_constraints_xml(scope, constraints_data)?;
+    }
+
     for value in &args.runtime_version {
         let (scope, value) = parse_scoped_value(value)?;
 
@@ -2354,16 +3338,67 @@ fn command_sign(args: &Sign) -> Result<(), AppleCodesignError> {
         settings.set_runtime_version(scope, version);
     }
 
+    for value in &args.macho_file_type {
+        let (scope, value) = parse_scoped_value(value)?;
+
+        let file_type = MachOFileType::from_str(value)?;
+        settings.set_macho_file_type(scope, file_type);
+    }
+
     for value in &args.info_plist_path {
         let (scope, value) = parse_scoped_value(value)?;
 
-        let content = std::fs::read(value)?;
-        settings.set_info_plist_data(scope, content);
+        // Relative paths are relative to the current directory, not the binary.
+        settings.set_info_plist_path(scope, std::env::current_dir()?.join(value));
     }
 
     let signer = UnifiedSigner::new(settings);
 
-    if let Some(output_path) = &args.output_path {
+    let stdin = args.input_path == Path::new("-");
+    let stdout = args.output_path.as_deref() == Some(Path::new("-"))
+        || (stdin && args.output_path.is_none());
+
+    if stdin || stdout {
+        // There is no file name to derive an identifier from when reading stdin.
+        let (data, default_identifier) = if stdin {
+            warn!("reading Mach-O from stdin");
+            (read_stdin_macho()?, None)
+        } else {
+            let file_name = args
+                .input_path
+                .file_name()
+                .ok_or_else(|| {
+                    AppleCodesignError::CliGeneralError(
+                        "unable to resolve file name of binary".into(),
+                    )
+                })?
+                .to_string_lossy()
+                .to_string();
+
+            (std::fs::read(&args.input_path)?, Some(file_name))
+        };
+
+        let signed = match signer.sign_macho_data(&data, default_identifier.as_deref()) {
+            Err(AppleCodesignError::NoIdentifier) if stdin => {
+                error!("--binary-identifier is required when signing Mach-O data from stdin lacking an identifier");
+                return Err(AppleCodesignError::CliBadArgument);
+            }
+            res => res?,
+        };
+
+        match &args.output_path {
+            Some(output_path) if !stdout => {
+                warn!("writing Mach-O to {}", output_path.display());
+                std::fs::write(output_path, signed)?;
+            }
+            _ => {
+                warn!("writing Mach-O to stdout");
+                let mut fh = std::io::stdout().lock();
+                fh.write_all(&signed)?;
+                fh.flush()?;
+            }
+        }
+    } else if let Some(output_path) = &args.output_path {
         warn!(
             "signing {} to {}",
             args.input_path.display(),
@@ -2375,13 +3410,37 @@ fn command_sign(args: &Sign) -> Result<(), AppleCodesignError> {
         signer.sign_path_in_place(&args.input_path)?;
     }
 
-    if let Some(private) = &private {
+    if let Some(private) = private_keys.first() {
         private.finish()?;
     }
 
     Ok(())
 }
 
+/// Read Mach-O data from stdin.
+///
+/// The data can be raw bytes or hex text, such as produced by `xxd -p`.
+fn read_stdin_macho() -> Result<Vec<u8>, AppleCodesignError> {
+    let mut data = vec![];
+    std::io::stdin().read_to_end(&mut data)?;
+
+    // Mach-O magics aren't hex digits, so text consisting only of hex digits and
+    // whitespace must be hex encoded.
+    if !data.is_empty()
+        && data
+            .iter()
+            .all(|b| b.is_ascii_hexdigit() || b.is_ascii_whitespace())
+    {
+        data.retain(|b| !b.is_ascii_whitespace());
+
+        hex::decode(&data).map_err(|e| {
+            AppleCodesignError::CliGeneralError(format!("invalid hex data on stdin: {e}"))
+        })
+    } else {
+        Ok(data)
+    }
+}
+
 #[cfg(feature = "yubikey")]
 fn command_smartcard_scan() -> Result<(), AppleCodesignError> {
     let mut ctx = ::yubikey::reader::Context::open()?;
@@ -2556,30 +3615,174 @@ fn command_staple(args: &Staple) -> Result<(), AppleCodesignError> {
     Ok(())
 }
 
+const VERIFY_ABOUT: &str = "\
+Verifies code signature data.
+
+Every problem found is printed. The exit code describes the most severe class
+of problem:
+
+* 0 - No problems were found.
+* 1 - The input couldn't be read or another error occurred.
+* 3 - The signature is missing, malformed, or doesn't bind the code.
+* 4 - Code or resources were modified after signing.
+* 5 - The signature isn't made by a trusted, unrevoked certificate chain.
+  Ad-hoc signatures report this.
+
+Signature blobs not laid out like Apple's tools do (e.g. blobs overlapping the
+blob index or extending beyond the advertised signature length) are reported
+but tolerated unless --strict is given, in which case they are treated as an
+invalid signature. This matches `codesign --verify --strict`. Signature data
+not at the end of __LINKEDIT, or __LINKEDIT not being the last segment, is
+always treated as an invalid signature.
+
+We do not verify everything Apple does. A successful verification does not
+mean the signature meets Apple's standards.
+";
+
+/// Exit code of `verify` when finding problems of a class.
+fn verification_exit_code(failure: VerificationFailure) -> i32 {
+    match failure {
+        VerificationFailure::SignatureInvalid | VerificationFailure::Layout => 3,
+        VerificationFailure::ResourcesModified => 4,
+        VerificationFailure::ChainUntrusted => 5,
+    }
+}
+
 #[derive(Parser)]
 struct Verify {
-    /// Path of Mach-O binary to examine
+    /// Write results as a JUnit XML report to this path
+    #[arg(long)]
+    junit_xml: Option<PathBuf>,
+
+    /// Verify against the signature in this file instead of the embedded signature
+    #[arg(long)]
+    detached: Option<PathBuf>,
+
+    /// Check whether signing certificates were revoked via OCSP
+    ///
+    /// This queries the OCSP responders of Apple issued certificates, like
+    /// Gatekeeper does, and requires network access. By default, failures to
+    /// obtain a revocation status are logged and otherwise ignored.
+    #[arg(long, conflicts_with = "detached")]
+    check_revocation: bool,
+
+    /// Treat failures to obtain a revocation status as problems
+    #[arg(long, requires = "check_revocation")]
+    revocation_strict: bool,
+
+    /// Treat signature blobs not laid out like Apple's tools do as invalid
+    #[arg(long)]
+    strict: bool,
+
+    /// Path of Mach-O binary or XAR archive (`.pkg` installer) to examine (`-` for stdin)
     path: PathBuf,
 }
 
 fn command_verify(args: &Verify) -> Result<(), AppleCodesignError> {
-    let data = std::fs::read(&args.path)?;
+    let (name, data) = if args.path == Path::new("-") {
+        ("<stdin>".to_string(), read_stdin_macho()?)
+    } else {
+        (args.path.display().to_string(), std::fs::read(&args.path)?)
+    };
+
+    let mut artifact = if let Some(signature_path) = &args.detached {
+        crate::junit::ArtifactVerification::from_data_detached(
+            name,
+            &data,
+            std::fs::read(signature_path)?,
+        )
+    } else {
+        crate::junit::ArtifactVerification::from_data(name, &data)
+    };
+
+    if args.check_revocation {
+        let mut checker = OcspChecker::new()?;
+        checker.set_strict(args.revocation_strict);
+
+        artifact.check_revocation(&data, &checker);
+    }
+
+    if let Some(path) = &args.junit_xml {
+        warn!("writing JUnit XML report to {}", path.display());
+        crate::junit::write_junit_xml(
+            std::slice::from_ref(&artifact),
+            std::fs::File::create(path)?,
+        )?;
+    }
+
+    let mut exit_code = 0;
 
-    let problems = crate::verify::verify_macho_data(data);
+    for problem in &artifact.problems {
+        let failure = problem.problem.failure();
+
+        if failure == VerificationFailure::Layout && !args.strict {
+            println!("{problem} (tolerated without --strict)");
+            continue;
+        }
 
-    for problem in &problems {
         println!("{problem}");
+
+        let code = verification_exit_code(failure);
+        if exit_code == 0 || code < exit_code {
+            exit_code = code;
+        }
     }
 
-    if problems.is_empty() {
+    if exit_code == 0 {
         eprintln!("no problems detected!");
         eprintln!("(we do not verify everything so please do not assume that the signature meets Apple standards)");
         Ok(())
     } else {
-        Err(AppleCodesignError::VerificationProblems)
+        Err(AppleCodesignError::VerificationExitCode(exit_code))
     }
 }
 
+const WATCH_ABOUT: &str = "\
+Watch a directory and ad-hoc sign Mach-O binaries as they change.
+
+On Apple Silicon, macOS refuses to run unsigned code and kills the process
+(\"killed: 9\"). Linkers not from Apple may not sign their output. This
+command makes iterative local development with such linkers painless:
+point it at a build output directory and binaries are re-signed after
+every rebuild.
+
+Files are polled for changes every --poll-interval-ms milliseconds. A
+changed file is signed once it has gone unmodified for --settle-ms
+milliseconds so binaries still being written aren't signed prematurely.
+
+By default, only binaries changing after the command starts are signed.
+Use --sign-existing to also sign binaries already present.
+
+The command runs until interrupted.
+";
+
+#[derive(Parser)]
+struct Watch {
+    /// Milliseconds between checks for changed files
+    #[arg(long, default_value = "500")]
+    poll_interval_ms: u64,
+
+    /// Milliseconds a changed file must go unmodified before it is signed
+    #[arg(long, default_value = "1000")]
+    settle_ms: u64,
+
+    /// Also sign Mach-O binaries present when watching begins
+    #[arg(long)]
+    sign_existing: bool,
+
+    /// Directory to watch
+    path: PathBuf,
+}
+
+fn command_watch(args: &Watch) -> Result<(), AppleCodesignError> {
+    let mut watcher = WatchSigner::new(SigningSettings::default(), &args.path);
+    watcher.set_poll_interval(Duration::from_millis(args.poll_interval_ms));
+    watcher.set_settle_delay(Duration::from_millis(args.settle_ms));
+    watcher.set_sign_existing(args.sign_existing);
+
+    watcher.watch(|| false)
+}
+
 fn command_x509_oids() -> Result<(), AppleCodesignError> {
     println!("# Extended Key Usage (EKU) Extension OIDs");
     println!();
@@ -2615,6 +3818,14 @@ enum Subcommands {
     /// properties related to certificates.
     AnalyzeCertificate(AnalyzeCertificate),
 
+    /// Check signatures for practices Apple is phasing out
+    #[command(long_about = CHECK_RECOMMENDATIONS_ABOUT)]
+    CheckRecommendations(CheckRecommendations),
+
+    /// Sign, display, or verify code using Apple's `codesign` command syntax
+    #[command(long_about = CODESIGN_COMPAT_ABOUT)]
+    CodesignCompat(CodesignCompat),
+
     /// Compute code hashes for a binary
     ComputeCodeHashes(ComputeCodeHashes),
 
@@ -2643,6 +3854,14 @@ enum Subcommands {
     /// Print information about certificates in the macOS keychain
     KeychainPrintCertificates(KeychainPrintCertificates),
 
+    /// Print signing identities available from certificate sources
+    ///
+    /// Each identity is printed with its SHA-256 fingerprint, subject common name,
+    /// team ID, and source. Any of the fingerprint (or a prefix of it), common
+    /// name, or team ID can be passed to --identity to select an identity to
+    /// sign with.
+    ListIdentities(ListIdentities),
+
     #[cfg(feature = "notarize")]
     /// Fetch the notarization log for a previous submission
     NotaryLog(NotaryLog),
@@ -2652,6 +3871,11 @@ enum Subcommands {
     #[command(long_about = NOTARIZE_ABOUT, alias = "notarize")]
     NotarySubmit(NotarySubmit),
 
+    /// Verify App Store Connect API credentials are accepted by the Notary API
+    #[cfg(feature = "notarize")]
+    #[command(long_about = NOTARY_VALIDATE_CREDENTIALS_ABOUT)]
+    NotaryValidateCredentials(NotaryValidateCredentials),
+
     /// Wait for completion of a previous submission
     #[cfg(feature = "notarize")]
     NotaryWait(NotaryWait),
@@ -2672,6 +3896,10 @@ enum Subcommands {
     /// Import a code signing certificate and key into a smartcard
     SmartcardImport(SmartcardImport),
 
+    /// Sign, package, notarize, and staple a release in one operation
+    #[command(long_about = RELEASE_ABOUT)]
+    Release(Release),
+
     /// Create signatures initiated from a remote signing operation
     RemoteSign(RemoteSign),
 
@@ -2683,8 +3911,13 @@ enum Subcommands {
     Staple(Staple),
 
     /// Verifies code signature data
+    #[command(long_about = VERIFY_ABOUT)]
     Verify(Verify),
 
+    /// Watch a directory and ad-hoc sign Mach-O binaries as they change
+    #[command(long_about = WATCH_ABOUT)]
+    Watch(Watch),
+
     /// Print information about X.509 OIDs related to Apple code signing
     X509Oids,
 }
@@ -2732,6 +3965,8 @@ pub fn main_impl() -> Result<(), AppleCodesignError> {
 
     match &cli.command {
         Subcommands::AnalyzeCertificate(args) => command_analyze_certificate(args),
+        Subcommands::CheckRecommendations(args) => command_check_recommendations(args),
+        Subcommands::CodesignCompat(args) => command_codesign_compat(args, cli.verbose),
         Subcommands::ComputeCodeHashes(args) => command_compute_code_hashes(args),
         Subcommands::DiffSignatures(args) => command_diff_signatures(args),
         #[cfg(feature = "notarize")]
@@ -2749,11 +3984,14 @@ pub fn main_impl() -> Result<(), AppleCodesignError> {
             command_keychain_export_certificate_chain(args)
         }
         Subcommands::KeychainPrintCertificates(args) => command_keychain_print_certificates(args),
+        Subcommands::ListIdentities(args) => command_list_identities(args),
         #[cfg(feature = "notarize")]
         Subcommands::NotaryLog(args) => command_notary_log(args),
         #[cfg(feature = "notarize")]
         Subcommands::NotarySubmit(args) => command_notary_submit(args),
         #[cfg(feature = "notarize")]
+        Subcommands::NotaryValidateCredentials(args) => command_notary_validate_credentials(args),
+        #[cfg(feature = "notarize")]
         Subcommands::NotaryWait(args) => command_notary_wait(args),
         Subcommands::ParseCodeSigningRequirement(args) => {
             command_parse_code_signing_requirement(args)
@@ -2762,10 +4000,64 @@ pub fn main_impl() -> Result<(), AppleCodesignError> {
         Subcommands::SmartcardScan => command_smartcard_scan(),
         Subcommands::SmartcardGenerateKey(args) => command_smartcard_generate_key(args),
         Subcommands::SmartcardImport(args) => command_smartcard_import(args),
+        Subcommands::Release(args) => command_release(args),
         Subcommands::RemoteSign(args) => command_remote_sign(args),
         Subcommands::Sign(args) => command_sign(args),
         Subcommands::Staple(args) => command_staple(args),
         Subcommands::Verify(args) => command_verify(args),
+        Subcommands::Watch(args) => command_watch(args),
         Subcommands::X509Oids => command_x509_oids(),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn codesign_compat(args: &[&str]) -> CodesignCompat {
+        CodesignCompat::try_parse_from(["codesign"].iter().chain(args)).unwrap()
+    }
+
+    #[test]
+    fn codesign_compat_certificate_source() {
+        let args = codesign_compat(&["-s", "Developer ID", "binary"]);
+
+        let certificate = args.signing_certificate_source("Developer ID", true);
+        assert_eq!(certificate.keychain_domain, vec!["user".to_string()]);
+        assert_eq!(certificate.identity.as_deref(), Some("Developer ID"));
+
+        let certificate = args.signing_certificate_source("Developer ID", false);
+        assert!(certificate.keychain_domain.is_empty());
+        assert_eq!(certificate.identity.as_deref(), Some("Developer ID"));
+
+        // Any other certificate source disables the default keychain.
+        for source in [
+            &["--kms-key", "awskms:///alias/signing"][..],
+            &["--remote-signer"],
+            &["--p12-file", "identity.p12"],
+            &["--pem-source", "identity.pem"],
+        ] {
+            let args = codesign_compat(&[&["-s", "Developer ID"], source, &["binary"]].concat());
+            let certificate = args.signing_certificate_source("Developer ID", true);
+            assert!(certificate.keychain_domain.is_empty());
+        }
+    }
+
+    #[test]
+    fn codesign_compat_adhoc_certificate_source() {
+        let args = codesign_compat(&[
+            "-s",
+            "-",
+            "--kms-key",
+            "awskms:///alias/signing",
+            "--remote-signer",
+            "binary",
+        ]);
+
+        let certificate = args.signing_certificate_source("-", true);
+        assert!(certificate.keychain_domain.is_empty());
+        assert!(certificate.kms_key.is_none());
+        assert!(!certificate.remote_signer);
+        assert!(certificate.identity.is_none());
+    }
+}
@@ -0,0 +1,682 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Signing with keys held by cloud key management services.
+//!
+//! AWS KMS, Google Cloud KMS, and Azure Key Vault can hold private keys in
+//! managed HSMs and expose an API to sign a digest. Private key material
+//! never leaves the service.
+//!
+//! [KmsSigner] pairs a [KmsBackend] talking to one of these services with the
+//! X.509 certificate issued for the key. The certificate itself isn't stored
+//! in the service and must be obtained separately.
+//!
+//! Backends are identified by URIs. See [kms_backend_from_uri()].
+//!
+//! Credentials are resolved as follows:
+//!
+//! * AWS: the default credential and region provider chains of the AWS SDK
+//!   (environment variables, shared config and credentials files, SSO, web
+//!   identity tokens, and container or instance metadata).
+//! * Google Cloud: `GOOGLE_OAUTH_ACCESS_TOKEN` (e.g. the output of
+//!   `gcloud auth print-access-token`).
+//! * Azure: `AZURE_ACCESS_TOKEN` (e.g. the output of
+//!   `az account get-access-token --resource https://vault.azure.net`).
+
+use {
+    crate::{
+        cryptography::PrivateKey,
+        remote_signing::{session_negotiation::PublicKeyPeerDecrypt, RemoteSignError},
+        AppleCodesignError,
+    },
+    aws_credential_types::provider::{ProvideCredentials, SharedCredentialsProvider},
+    base64::{
+        engine::general_purpose::{STANDARD as STANDARD_ENGINE, URL_SAFE_NO_PAD},
+        Engine,
+    },
+    bytes::Bytes,
+    log::{info, warn},
+    reqwest::blocking::{Client, RequestBuilder},
+    serde_json::{json, Value},
+    signature::Signer,
+    std::{sync::Arc, time::SystemTime},
+    x509_certificate::{
+        CapturedX509Certificate, DigestAlgorithm, EcdsaCurve, KeyAlgorithm, KeyInfoSigner, Sign,
+        Signature, SignatureAlgorithm, X509CertificateError,
+    },
+};
+
+/// A signature algorithm performed by a cloud KMS.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum KmsSigningAlgorithm {
+    /// RSASSA-PKCS1-v1_5 over a SHA-256 digest.
+    RsaPkcs1Sha256,
+
+    /// ECDSA on the P-256 curve over a SHA-256 digest.
+    EcdsaP256Sha256,
+
+    /// ECDSA on the P-384 curve over a SHA-384 digest.
+    EcdsaP384Sha384,
+}
+
+impl KmsSigningAlgorithm {
+    /// Resolve the algorithm to use for the key in a certificate.
+    ///
+    /// RSA keys always sign SHA-256 digests, as Apple requires.
+    pub fn for_certificate(cert: &CapturedX509Certificate) -> Result<Self, AppleCodesignError> {
+        match cert.key_algorithm() {
+            Some(KeyAlgorithm::Rsa) => Ok(Self::RsaPkcs1Sha256),
+            Some(KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1)) => Ok(Self::EcdsaP256Sha256),
+            Some(KeyAlgorithm::Ecdsa(EcdsaCurve::Secp384r1)) => Ok(Self::EcdsaP384Sha384),
+            Some(algorithm) => Err(AppleCodesignError::CertificateUnsupportedKeyAlgorithm(
+                algorithm,
+            )),
+            None => Err(X509CertificateError::UnknownKeyAlgorithm(format!(
+                "{:?}",
+                cert.key_algorithm_oid()
+            ))
+            .into()),
+        }
+    }
+
+    /// The digest algorithm of data being signed.
+    pub fn digest_algorithm(&self) -> DigestAlgorithm {
+        match self {
+            Self::RsaPkcs1Sha256 | Self::EcdsaP256Sha256 => DigestAlgorithm::Sha256,
+            Self::EcdsaP384Sha384 => DigestAlgorithm::Sha384,
+        }
+    }
+
+    /// The X.509 signature algorithm of produced signatures.
+    pub fn signature_algorithm(&self) -> SignatureAlgorithm {
+        match self {
+            Self::RsaPkcs1Sha256 => SignatureAlgorithm::RsaSha256,
+            Self::EcdsaP256Sha256 => SignatureAlgorithm::EcdsaSha256,
+            Self::EcdsaP384Sha384 => SignatureAlgorithm::EcdsaSha384,
+        }
+    }
+
+    /// The name of this algorithm in the AWS KMS API.
+    pub fn aws_name(&self) -> &'static str {
+        match self {
+            Self::RsaPkcs1Sha256 => "RSASSA_PKCS1_V1_5_SHA_256",
+            Self::EcdsaP256Sha256 => "ECDSA_SHA_256",
+            Self::EcdsaP384Sha384 => "ECDSA_SHA_384",
+        }
+    }
+
+    /// The name of this algorithm in the Azure Key Vault API.
+    pub fn azure_name(&self) -> &'static str {
+        match self {
+            Self::RsaPkcs1Sha256 => "RS256",
+            Self::EcdsaP256Sha256 => "ES256",
+            Self::EcdsaP384Sha384 => "ES384",
+        }
+    }
+
+    fn is_ecdsa(&self) -> bool {
+        !matches!(self, Self::RsaPkcs1Sha256)
+    }
+}
+
+/// Convert an ECDSA signature from the fixed size `r || s` form to DER.
+///
+/// Some services emit signatures in the IEEE P1363 form. CMS wants DER
+/// encoded `Ecdsa-Sig-Value` structures.
+pub fn ecdsa_p1363_to_der(signature: &[u8]) -> Result<Vec<u8>, AppleCodesignError> {
+    if signature.is_empty() || signature.len() % 2 != 0 || signature.len() > 132 {
+        return Err(AppleCodesignError::KmsBadResponse(
+            "ECDSA signature has invalid length".into(),
+        ));
+    }
+
+    let integer = |value: &[u8]| {
+        let start = value
+            .iter()
+            .position(|b| *b != 0)
+            .unwrap_or(value.len() - 1);
+        let value = &value[start..];
+
+        let mut res = vec![0x02];
+        if value[0] & 0x80 != 0 {
+            res.push(value.len() as u8 + 1);
+            res.push(0);
+        } else {
+            res.push(value.len() as u8);
+        }
+        res.extend_from_slice(value);
+
+        res
+    };
+
+    let (r, s) = signature.split_at(signature.len() / 2);
+    let content = [integer(r), integer(s)].concat();
+
+    let mut res = vec![0x30];
+    if content.len() >= 0x80 {
+        res.push(0x81);
+    }
+    res.push(content.len() as u8);
+    res.extend(content);
+
+    Ok(res)
+}
+
+/// A cloud service able to sign digests with a private key it holds.
+pub trait KmsBackend: Send + Sync {
+    /// A human readable description of the key, for logging.
+    fn description(&self) -> String;
+
+    /// Sign a digest.
+    ///
+    /// Returned signatures must be encoded as CMS expects: PKCS#1 v1.5 for
+    /// RSA and DER for ECDSA.
+    fn sign_digest(
+        &self,
+        algorithm: KmsSigningAlgorithm,
+        digest: &[u8],
+    ) -> Result<Vec<u8>, AppleCodesignError>;
+}
+
+/// Resolve a [KmsBackend] from a URI.
+///
+/// The following forms are recognized:
+///
+/// * `awskms:<key ID or ARN>`
+/// * `gcpkms:projects/<project>/locations/<location>/keyRings/<ring>/cryptoKeys/<key>/cryptoKeyVersions/<version>`
+/// * `azurekv:https://<vault>.vault.azure.net/keys/<name>/<version>`
+pub fn kms_backend_from_uri(uri: &str) -> Result<Box<dyn KmsBackend>, AppleCodesignError> {
+    let (scheme, key) = uri
+        .split_once(':')
+        .ok_or_else(|| AppleCodesignError::KmsBadUri(uri.to_string()))?;
+
+    match scheme {
+        "awskms" => Ok(Box::new(AwsKms::from_env(key)?)),
+        "gcpkms" => Ok(Box::new(GcpKms::from_env(key)?)),
+        "azurekv" => Ok(Box::new(AzureKeyVault::from_env(key)?)),
+        _ => Err(AppleCodesignError::KmsBadUri(uri.to_string())),
+    }
+}
+
+fn env_credential(name: &'static str) -> Result<String, AppleCodesignError> {
+    std::env::var(name).map_err(|_| AppleCodesignError::KmsMissingCredential(name))
+}
+
+/// Send a JSON request and parse the JSON response, surfacing service errors.
+fn send_json(request: RequestBuilder, body: &Value) -> Result<Value, AppleCodesignError> {
+    let response = request.json(body).send()?;
+    let status = response.status();
+
+    // Error bodies aren't necessarily JSON (e.g. from proxies or load balancers).
+    if !status.is_success() {
+        return Err(AppleCodesignError::KmsRequest(
+            status.as_u16(),
+            response.text()?,
+        ));
+    }
+
+    Ok(response.json::<Value>()?)
+}
+
+/// Decode a base64 encoded field in a JSON response.
+fn response_base64(
+    response: &Value,
+    key: &str,
+    engine: &impl Engine,
+) -> Result<Vec<u8>, AppleCodesignError> {
+    let value = response[key].as_str().ok_or_else(|| {
+        AppleCodesignError::KmsBadResponse(format!("response lacks string field {key}"))
+    })?;
+
+    engine
+        .decode(value)
+        .map_err(|e| AppleCodesignError::KmsBadResponse(format!("bad base64 in {key}: {e}")))
+}
+
+/// A key in AWS KMS.
+pub struct AwsKms {
+    client: Client,
+    key_id: String,
+    region: String,
+    credentials: SharedCredentialsProvider,
+    runtime: tokio::runtime::Runtime,
+}
+
+impl AwsKms {
+    /// Construct an instance using the default AWS SDK configuration.
+    ///
+    /// The region is derived from the key if it is an ARN.
+    pub fn from_env(key_id: &str) -> Result<Self, AppleCodesignError> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let config = runtime.block_on(aws_config::load_from_env());
+
+        let region = match key_id.strip_prefix("arn:") {
+            Some(arn) => arn
+                .split(':')
+                .nth(2)
+                .filter(|region| !region.is_empty())
+                .ok_or_else(|| AppleCodesignError::KmsBadUri(key_id.to_string()))?
+                .to_string(),
+            None => config
+                .region()
+                .map(|region| region.to_string())
+                .ok_or_else(|| AppleCodesignError::KmsAwsConfig("no region configured".into()))?,
+        };
+        let credentials = config.credentials_provider().cloned().ok_or_else(|| {
+            AppleCodesignError::KmsAwsConfig("no credentials provider configured".into())
+        })?;
+
+        Ok(Self {
+            client: Client::new(),
+            key_id: key_id.to_string(),
+            region,
+            credentials,
+            runtime,
+        })
+    }
+}
+
+impl KmsBackend for AwsKms {
+    fn description(&self) -> String {
+        format!("AWS KMS key {}", self.key_id)
+    }
+
+    fn sign_digest(
+        &self,
+        algorithm: KmsSigningAlgorithm,
+        digest: &[u8],
+    ) -> Result<Vec<u8>, AppleCodesignError> {
+        let credentials = self
+            .runtime
+            .block_on(self.credentials.provide_credentials())
+            .map_err(|e| AppleCodesignError::KmsAwsConfig(e.to_string()))?;
+
+        let url = format!("https://kms.{}.amazonaws.com/", self.region);
+        let body = json!({
+            "KeyId": self.key_id,
+            "Message": STANDARD_ENGINE.encode(digest),
+            "MessageType": "DIGEST",
+            "SigningAlgorithm": algorithm.aws_name(),
+        });
+        let body_data = serde_json::to_vec(&body)?;
+
+        let mut headers = http::HeaderMap::new();
+        headers.insert(
+            "content-type",
+            http::HeaderValue::from_static("application/x-amz-json-1.1"),
+        );
+        headers.insert(
+            "x-amz-target",
+            http::HeaderValue::from_static("TrentService.Sign"),
+        );
+        headers.insert(
+            "host",
+            http::HeaderValue::from_str(&format!("kms.{}.amazonaws.com", self.region))
+                .map_err(|_| AppleCodesignError::KmsBadUri(self.region.clone()))?,
+        );
+
+        let uri = url
+            .parse::<http::Uri>()
+            .map_err(|_| AppleCodesignError::KmsBadUri(url.clone()))?;
+
+        let mut params = aws_sigv4::SigningParams::builder()
+            .access_key(credentials.access_key_id())
+            .secret_key(credentials.secret_access_key())
+            .region(&self.region)
+            .service_name("kms")
+            .time(SystemTime::now())
+            .settings(aws_sigv4::http_request::SigningSettings::default());
+        if let Some(token) = credentials.session_token() {
+            params = params.security_token(token);
+        }
+        let params = params
+            .build()
+            .map_err(|e| AppleCodesignError::KmsSigV4(e.to_string()))?;
+
+        let (instructions, _) = aws_sigv4::http_request::sign(
+            aws_sigv4::http_request::SignableRequest::new(
+                &http::Method::POST,
+                &uri,
+                &headers,
+                aws_sigv4::http_request::SignableBody::Bytes(&body_data),
+            ),
+            &params,
+        )
+        .map_err(|e| AppleCodesignError::KmsSigV4(e.to_string()))?
+        .into_parts();
+
+        if let Some(signing_headers) = instructions.headers() {
+            for (name, value) in signing_headers {
+                headers.insert(name, value.clone());
+            }
+        }
+        headers.remove("host");
+
+        let response = send_json(self.client.post(url).headers(headers), &body)?;
+
+        // Signatures are PKCS#1 v1.5 or DER encoded ECDSA, which is what we want.
+        response_base64(&response, "Signature", &STANDARD_ENGINE)
+    }
+}
+
+/// A key version in Google Cloud KMS.
+pub struct GcpKms {
+    client: Client,
+    key_version: String,
+    access_token: String,
+}
+
+impl GcpKms {
+    /// Construct an instance using an access token from the environment.
+    pub fn from_env(key_version: &str) -> Result<Self, AppleCodesignError> {
+        if !key_version.starts_with("projects/") || !key_version.contains("/cryptoKeyVersions/") {
+            return Err(AppleCodesignError::KmsBadUri(key_version.to_string()));
+        }
+
+        Ok(Self {
+            client: Client::new(),
+            key_version: key_version.to_string(),
+            access_token: env_credential("GOOGLE_OAUTH_ACCESS_TOKEN")?,
+        })
+    }
+}
+
+impl KmsBackend for GcpKms {
+    fn description(&self) -> String {
+        format!("Google Cloud KMS key {}", self.key_version)
+    }
+
+    fn sign_digest(
+        &self,
+        algorithm: KmsSigningAlgorithm,
+        digest: &[u8],
+    ) -> Result<Vec<u8>, AppleCodesignError> {
+        let digest_field = match algorithm.digest_algorithm() {
+            DigestAlgorithm::Sha384 => "sha384",
+            _ => "sha256",
+        };
+
+        let response = send_json(
+            self.client
+                .post(format!(
+                    "https://cloudkms.googleapis.com/v1/{}:asymmetricSign",
+                    self.key_version
+                ))
+                .bearer_auth(&self.access_token),
+            &json!({
+                "digest": {
+                    digest_field: STANDARD_ENGINE.encode(digest),
+                },
+            }),
+        )?;
+
+        // Signatures are PKCS#1 v1.5 or DER encoded ECDSA, which is what we want.
+        response_base64(&response, "signature", &STANDARD_ENGINE)
+    }
+}
+
+/// A key in Azure Key Vault.
+pub struct AzureKeyVault {
+    client: Client,
+    key_url: String,
+    access_token: String,
+}
+
+impl AzureKeyVault {
+    /// Construct an instance using an access token from the environment.
+    ///
+    /// `key_url` is the key identifier URL, including the key version.
+    pub fn from_env(key_url: &str) -> Result<Self, AppleCodesignError> {
+        if !key_url.starts_with("https://") || !key_url.contains("/keys/") {
+            return Err(AppleCodesignError::KmsBadUri(key_url.to_string()));
+        }
+
+        Ok(Self {
+            client: Client::new(),
+            key_url: key_url.trim_end_matches('/').to_string(),
+            access_token: env_credential("AZURE_ACCESS_TOKEN")?,
+        })
+    }
+}
+
+impl KmsBackend for AzureKeyVault {
+    fn description(&self) -> String {
+        format!("Azure Key Vault key {}", self.key_url)
+    }
+
+    fn sign_digest(
+        &self,
+        algorithm: KmsSigningAlgorithm,
+        digest: &[u8],
+    ) -> Result<Vec<u8>, AppleCodesignError> {
+        let response = send_json(
+            self.client
+                .post(format!("{}/sign?api-version=7.4", self.key_url))
+                .bearer_auth(&self.access_token),
+            &json!({
+                "alg": algorithm.azure_name(),
+                "value": URL_SAFE_NO_PAD.encode(digest),
+            }),
+        )?;
+
+        let signature = response_base64(&response, "value", &URL_SAFE_NO_PAD)?;
+
+        // ECDSA signatures are in the r || s form.
+        if algorithm.is_ecdsa() {
+            ecdsa_p1363_to_der(&signature)
+        } else {
+            Ok(signature)
+        }
+    }
+}
+
+/// Entity for creating signatures using a key held by a cloud KMS.
+#[derive(Clone)]
+pub struct KmsSigner {
+    backend: Arc<dyn KmsBackend>,
+    cert: CapturedX509Certificate,
+    algorithm: KmsSigningAlgorithm,
+}
+
+impl KmsSigner {
+    /// Construct an instance from a backend and the certificate of its key.
+    pub fn new(
+        backend: Arc<dyn KmsBackend>,
+        cert: CapturedX509Certificate,
+    ) -> Result<Self, AppleCodesignError> {
+        let algorithm = KmsSigningAlgorithm::for_certificate(&cert)?;
+
+        Ok(Self {
+            backend,
+            cert,
+            algorithm,
+        })
+    }
+
+    pub fn certificate(&self) -> &CapturedX509Certificate {
+        &self.cert
+    }
+
+    pub fn algorithm(&self) -> KmsSigningAlgorithm {
+        self.algorithm
+    }
+}
+
+impl Signer<Signature> for KmsSigner {
+    fn try_sign(&self, message: &[u8]) -> Result<Signature, signature::Error> {
+        let digest = self.algorithm.digest_algorithm().digest_data(message);
+
+        info!(
+            "requesting {:?} signature from {}",
+            self.algorithm,
+            self.backend.description()
+        );
+
+        self.backend
+            .sign_digest(self.algorithm, &digest)
+            .map(Signature::from)
+            .map_err(signature::Error::from_source)
+    }
+}
+
+impl Sign for KmsSigner {
+    fn sign(&self, message: &[u8]) -> Result<(Vec<u8>, SignatureAlgorithm), X509CertificateError> {
+        Ok((self.try_sign(message)?.into(), self.signature_algorithm()?))
+    }
+
+    fn key_algorithm(&self) -> Option<KeyAlgorithm> {
+        self.cert.key_algorithm()
+    }
+
+    fn public_key_data(&self) -> Bytes {
+        self.cert.public_key_data()
+    }
+
+    fn signature_algorithm(&self) -> Result<SignatureAlgorithm, X509CertificateError> {
+        Ok(self.algorithm.signature_algorithm())
+    }
+
+    fn private_key_data(&self) -> Option<Vec<u8>> {
+        // Private keys never leave the service.
+        None
+    }
+
+    fn rsa_primes(&self) -> Result<Option<(Vec<u8>, Vec<u8>)>, X509CertificateError> {
+        Ok(None)
+    }
+}
+
+impl KeyInfoSigner for KmsSigner {}
+
+impl PublicKeyPeerDecrypt for KmsSigner {
+    fn decrypt(&self, _ciphertext: &[u8]) -> Result<Vec<u8>, RemoteSignError> {
+        warn!("cloud KMS keys can't be used as remote signing session peers");
+        Err(RemoteSignError::Crypto(
+            "decryption not implemented for cloud KMS keys".into(),
+        ))
+    }
+}
+
+impl PrivateKey for KmsSigner {
+    fn as_key_info_signer(&self) -> &dyn KeyInfoSigner {
+        self
+    }
+
+    fn to_public_key_peer_decrypt(
+        &self,
+    ) -> Result<Box<dyn PublicKeyPeerDecrypt>, AppleCodesignError> {
+        Ok(Box::new(self.clone()))
+    }
+
+    fn finish(&self) -> Result<(), AppleCodesignError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::certificate::{create_self_signed_code_signing_certificate, CertificateProfile},
+        std::sync::Mutex,
+    };
+
+    /// A backend returning a fixed signature, recording requests.
+    struct LocalBackend {
+        requests: Mutex<Vec<(KmsSigningAlgorithm, Vec<u8>)>>,
+    }
+
+    impl KmsBackend for LocalBackend {
+        fn description(&self) -> String {
+            "local".into()
+        }
+
+        fn sign_digest(
+            &self,
+            algorithm: KmsSigningAlgorithm,
+            digest: &[u8],
+        ) -> Result<Vec<u8>, AppleCodesignError> {
+            self.requests
+                .lock()
+                .unwrap()
+                .push((algorithm, digest.to_vec()));
+
+            Ok(vec![42])
+        }
+    }
+
+    #[test]
+    fn signer_digests_message() -> Result<(), AppleCodesignError> {
+        let (cert, _, _) = create_self_signed_code_signing_certificate(
+            KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1),
+            CertificateProfile::DeveloperIdApplication,
+            "team1",
+            "KMS",
+            "US",
+            chrono::Duration::hours(1),
+        )?;
+
+        let backend = Arc::new(LocalBackend {
+            requests: Mutex::new(vec![]),
+        });
+        let signer = KmsSigner::new(backend.clone(), cert.clone())?;
+
+        assert_eq!(signer.algorithm(), KmsSigningAlgorithm::EcdsaP256Sha256);
+        assert_eq!(
+            signer.signature_algorithm()?,
+            SignatureAlgorithm::EcdsaSha256
+        );
+        assert_eq!(signer.public_key_data(), cert.public_key_data());
+
+        let signature: Vec<u8> = signer.try_sign(b"hello").unwrap().into();
+        assert_eq!(signature, vec![42]);
+        assert_eq!(
+            backend.requests.lock().unwrap().as_slice(),
+            &[(
+                KmsSigningAlgorithm::EcdsaP256Sha256,
+                DigestAlgorithm::Sha256.digest_data(b"hello")
+            )]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn p1363_to_der() -> Result<(), AppleCodesignError> {
+        let mut signature = vec![0u8; 64];
+        signature[31] = 0x01;
+        signature[32] = 0x80;
+
+        assert_eq!(
+            ecdsa_p1363_to_der(&signature)?,
+            [
+                &[0x30, 0x26, 0x02, 0x01, 0x01, 0x02, 0x21, 0x00, 0x80][..],
+                &[0u8; 31][..]
+            ]
+            .concat()
+        );
+
+        assert!(ecdsa_p1363_to_der(&[0u8; 63]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn backend_uris() {
+        assert!(matches!(
+            kms_backend_from_uri("unknown:key"),
+            Err(AppleCodesignError::KmsBadUri(_))
+        ));
+        assert!(matches!(
+            kms_backend_from_uri("gcpkms:not-a-key"),
+            Err(AppleCodesignError::KmsBadUri(_))
+        ));
+        assert!(matches!(
+            kms_backend_from_uri("azurekv:http://insecure/keys/x/y"),
+            Err(AppleCodesignError::KmsBadUri(_))
+        ));
+    }
+}
@@ -15,9 +15,15 @@ use {
     },
     scroll::{IOwrite, Pread},
     semver::Version,
-    std::{borrow::Cow, collections::HashMap, io::Write, str::FromStr},
+    serde::{Serialize, Serializer},
+    std::{borrow::Cow, collections::BTreeMap, io::Write, str::FromStr},
 };
 
+/// Length in bytes of a cdhash.
+///
+/// cdhashes are code directory digests truncated to this length.
+pub const CS_CDHASH_LEN: usize = 20;
+
 bitflags::bitflags! {
     /// Code signature flags.
     ///
@@ -50,6 +56,45 @@ bitflags::bitflags! {
     }
 }
 
+/// Names of code signature flags, in bit order.
+///
+/// Names match those accepted by [CodeSignatureFlags::from_str()] where possible.
+const CODE_SIGNATURE_FLAG_NAMES: [(CodeSignatureFlags, &str); 10] = [
+    (CodeSignatureFlags::HOST, "host"),
+    (CodeSignatureFlags::ADHOC, "adhoc"),
+    (CodeSignatureFlags::FORCE_HARD, "hard"),
+    (CodeSignatureFlags::FORCE_KILL, "kill"),
+    (CodeSignatureFlags::FORCE_EXPIRATION, "expires"),
+    (CodeSignatureFlags::RESTRICT, "restrict"),
+    (CodeSignatureFlags::ENFORCEMENT, "enforcement"),
+    (CodeSignatureFlags::LIBRARY_VALIDATION, "library"),
+    (CodeSignatureFlags::RUNTIME, "runtime"),
+    (CodeSignatureFlags::LINKER_SIGNED, "linker-signed"),
+];
+
+impl std::fmt::Display for CodeSignatureFlags {
+    /// Formats like `codesign`. e.g. `0x10002(adhoc,runtime)`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = CODE_SIGNATURE_FLAG_NAMES
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>();
+
+        if names.is_empty() {
+            write!(f, "0x{:x}(none)", self.bits())
+        } else {
+            write!(f, "0x{:x}({})", self.bits(), names.join(","))
+        }
+    }
+}
+
+impl Serialize for CodeSignatureFlags {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
 impl FromStr for CodeSignatureFlags {
     type Err = AppleCodesignError;
 
@@ -116,6 +161,12 @@ bitflags::bitflags! {
     }
 }
 
+impl Serialize for ExecutableSegmentFlags {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.bits())
+    }
+}
+
 impl FromStr for ExecutableSegmentFlags {
     type Err = AppleCodesignError;
 
@@ -148,23 +199,220 @@ pub enum CodeDirectoryVersion {
     SupportsLinkage = 0x20600,
 }
 
+/// An entry in a code directory's scatter vector.
+///
+/// Scatter vectors describe signed code that isn't a single contiguous range
+/// of the file. Each entry maps a run of `count` code digest slots starting at
+/// slot `base` to pages starting at file offset `target_offset`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
 #[repr(C)]
 pub struct Scatter {
     /// Number of pages. 0 for sentinel only.
-    count: u32,
+    pub count: u32,
     /// First page number.
-    base: u32,
+    pub base: u32,
     /// Offset in target.
-    target_offset: u64,
+    pub target_offset: u64,
     /// Reserved.
-    spare: u64,
+    pub spare: u64,
+}
+
+impl Scatter {
+    /// Size in bytes of a serialized entry.
+    pub const SIZE: usize = 24;
+
+    /// Construct a new entry covering `count` pages.
+    pub fn new(count: u32, base: u32, target_offset: u64) -> Self {
+        Self {
+            count,
+            base,
+            target_offset,
+            spare: 0,
+        }
+    }
+}
+
+/// Read a sentinel terminated scatter vector.
+fn get_scatter(data: &[u8], mut offset: usize) -> Result<Vec<Scatter>, AppleCodesignError> {
+    let mut res = vec![];
+
+    loop {
+        let entry = data
+            .get(offset..offset.saturating_add(Scatter::SIZE))
+            .ok_or(AppleCodesignError::CodeDirectoryMalformedScatter)?;
+        offset += Scatter::SIZE;
+
+        let entry = Scatter {
+            count: entry.pread_with(0, scroll::BE)?,
+            base: entry.pread_with(4, scroll::BE)?,
+            target_offset: entry.pread_with(8, scroll::BE)?,
+            spare: entry.pread_with(16, scroll::BE)?,
+        };
+
+        if entry.count == 0 {
+            return Ok(res);
+        }
+
+        res.push(entry);
+    }
 }
 
-fn get_hashes(data: &[u8], offset: usize, count: usize, hash_size: usize) -> Vec<Digest<'_>> {
-    data[offset..offset + (count * hash_size)]
+/// Resolve the region of `data` holding `count` hashes of `hash_size` bytes.
+fn get_hash_region(
+    data: &[u8],
+    offset: usize,
+    count: usize,
+    hash_size: usize,
+) -> Result<&[u8], AppleCodesignError> {
+    // The count comes from untrusted data. So validate it against the data we
+    // have before allocating anything.
+    count
+        .checked_mul(hash_size)
+        .and_then(|len| offset.checked_add(len))
+        .and_then(|end| data.get(offset..end))
+        .ok_or(AppleCodesignError::CodeDirectoryMalformedDigests)
+}
+
+fn get_hashes(
+    data: &[u8],
+    offset: usize,
+    count: usize,
+    hash_size: usize,
+) -> Result<Vec<Digest<'_>>, AppleCodesignError> {
+    let data = get_hash_region(data, offset, count, hash_size)?;
+
+    if hash_size == 0 {
+        return Ok(vec![]);
+    }
+
+    Ok(data
         .chunks(hash_size)
         .map(|data| Digest { data: data.into() })
-        .collect()
+        .collect())
+}
+
+/// The code digests of a code directory.
+///
+/// Digests are stored in a single contiguous buffer. Parsed code directories
+/// borrow the digest region of the blob data, so parsing doesn't allocate per
+/// code page and individual digests are only materialized on access.
+#[derive(Clone, Default)]
+pub struct CodeDigests<'a> {
+    data: Cow<'a, [u8]>,
+    digest_size: usize,
+}
+
+impl<'a> CodeDigests<'a> {
+    /// Construct an instance from a buffer of concatenated digests.
+    ///
+    /// Errors if the buffer length isn't a multiple of the digest size.
+    pub fn from_bytes(
+        data: impl Into<Cow<'a, [u8]>>,
+        digest_size: usize,
+    ) -> Result<Self, AppleCodesignError> {
+        let data = data.into();
+
+        if digest_size == 0 && !data.is_empty() || digest_size != 0 && data.len() % digest_size != 0
+        {
+            return Err(AppleCodesignError::CodeDirectoryMalformedDigests);
+        }
+
+        Ok(Self { data, digest_size })
+    }
+
+    /// The number of digests.
+    pub fn len(&self) -> usize {
+        self.data.len().checked_div(self.digest_size).unwrap_or(0)
+    }
+
+    /// Whether there are no digests.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The size in bytes of each digest.
+    pub fn digest_size(&self) -> usize {
+        self.digest_size
+    }
+
+    /// Obtain the raw bytes of all digests.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Obtain the digest at a given index.
+    pub fn get(&self, index: usize) -> Option<Digest<'_>> {
+        if self.digest_size == 0 {
+            return None;
+        }
+
+        let start = index.checked_mul(self.digest_size)?;
+
+        self.data
+            .get(start..start.checked_add(self.digest_size)?)
+            .map(|data| Digest { data: data.into() })
+    }
+
+    /// Iterate over digests.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = Digest<'_>> {
+        self.data
+            .chunks_exact(self.digest_size.max(1))
+            .map(|data| Digest { data: data.into() })
+    }
+
+    /// Append a digest.
+    ///
+    /// Errors if the digest's length doesn't match existing digests.
+    pub fn push(&mut self, digest: &[u8]) -> Result<(), AppleCodesignError> {
+        if self.is_empty() {
+            self.digest_size = digest.len();
+        } else if digest.len() != self.digest_size {
+            return Err(AppleCodesignError::CodeDirectoryMalformedDigests);
+        }
+
+        self.data.to_mut().extend_from_slice(digest);
+
+        Ok(())
+    }
+
+    /// Convert to an instance owning its digest data.
+    ///
+    /// Digests borrowed from a parsed blob are copied.
+    pub fn into_owned_digests(self) -> CodeDigests<'static> {
+        CodeDigests {
+            data: Cow::Owned(self.data.into_owned()),
+            digest_size: self.digest_size,
+        }
+    }
+}
+
+impl<'a> std::fmt::Debug for CodeDigests<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a> Serialize for CodeDigests<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'a, 'b> TryFrom<Vec<Digest<'b>>> for CodeDigests<'a> {
+    type Error = AppleCodesignError;
+
+    /// Collect digests into a new instance.
+    ///
+    /// Errors if digests don't have the same length.
+    fn try_from(v: Vec<Digest<'b>>) -> Result<Self, Self::Error> {
+        let mut res = Self::default();
+
+        for digest in v {
+            res.push(&digest.data)?;
+        }
+
+        Ok(res)
+    }
 }
 
 /// Represents a code directory blob entry.
@@ -175,7 +423,7 @@ fn get_hashes(data: &[u8], offset: usize, count: usize, hash_size: usize) -> Vec
 ///
 /// The parser will set `Option<T>` fields to `None` for instances
 /// where the version is lower than the version that field was introduced in.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize)]
 pub struct CodeDirectoryBlob<'a> {
     /// Compatibility version.
     pub version: u32,
@@ -196,12 +444,17 @@ pub struct CodeDirectoryBlob<'a> {
     /// Platform identifier. 0 if not platform binary.
     pub platform: u8,
     /// Page size in bytes. (stored as log u8)
+    ///
+    /// 0 denotes a single page covering all code.
     pub page_size: u32,
     /// Unused (must be 0).
     pub spare2: u32,
     // Version 0x20100
-    /// Offset of optional scatter vector.
-    pub scatter_offset: Option<u32>,
+    /// Optional scatter vector, excluding the terminating sentinel.
+    ///
+    /// When present, code digests cover the file ranges described by these
+    /// entries instead of consecutive pages from the start of the file.
+    pub scatter: Option<Vec<Scatter>>,
     // Version 0x20200
     // team_offset not stored because it is redundant with derived stored str.
     // Version 0x20300
@@ -229,8 +482,8 @@ pub struct CodeDirectoryBlob<'a> {
     // End of blob header data / start of derived data.
     pub ident: Cow<'a, str>,
     pub team_name: Option<Cow<'a, str>>,
-    pub code_digests: Vec<Digest<'a>>,
-    pub special_digests: HashMap<CodeSigningSlot, Digest<'a>>,
+    pub code_digests: CodeDigests<'a>,
+    pub special_digests: BTreeMap<CodeSigningSlot, Digest<'a>>,
 }
 
 impl<'a> Blob<'a> for CodeDirectoryBlob<'a> {
@@ -257,11 +510,17 @@ impl<'a> Blob<'a> for CodeDirectoryBlob<'a> {
         let digest_type = data.gread_with::<u8>(offset, scroll::BE)?.into();
         let platform = data.gread_with(offset, scroll::BE)?;
         let page_size = data.gread_with::<u8>(offset, scroll::BE)?;
-        let page_size = 2u32.pow(page_size as u32);
+        // 0 denotes a single page covering all code.
+        let page_size = match page_size {
+            0 => 0,
+            v => 2u32
+                .checked_pow(v as u32)
+                .ok_or(AppleCodesignError::CodeDirectoryMalformedPageSize)?,
+        };
         let spare2 = data.gread_with(offset, scroll::BE)?;
 
         let scatter_offset = if version >= CodeDirectoryVersion::SupportsScatter as u32 {
-            let v = data.gread_with(offset, scroll::BE)?;
+            let v = data.gread_with::<u32>(offset, scroll::BE)?;
 
             if v != 0 {
                 Some(v)
@@ -335,7 +594,9 @@ impl<'a> Blob<'a> for CodeDirectoryBlob<'a> {
             };
 
         // Find trailing null in identifier string.
-        let ident = match data[ident_offset as usize..]
+        let ident = match data
+            .get(ident_offset as usize..)
+            .ok_or(AppleCodesignError::CodeDirectoryMalformedIdentifier)?
             .split(|&b| b == 0)
             .map(std::str::from_utf8)
             .next()
@@ -349,7 +610,9 @@ impl<'a> Blob<'a> for CodeDirectoryBlob<'a> {
         };
 
         let team_name = if let Some(team_offset) = team_offset {
-            match data[team_offset as usize..]
+            match data
+                .get(team_offset as usize..)
+                .ok_or(AppleCodesignError::CodeDirectoryMalformedTeam)?
                 .split(|&b| b == 0)
                 .map(std::str::from_utf8)
                 .next()
@@ -367,19 +630,41 @@ impl<'a> Blob<'a> for CodeDirectoryBlob<'a> {
             None
         };
 
-        let code_digests = get_hashes(
-            data,
-            digest_offset as usize,
-            n_code_slots as usize,
+        let code_digests = CodeDigests::from_bytes(
+            get_hash_region(
+                data,
+                digest_offset as usize,
+                n_code_slots as usize,
+                digest_size as usize,
+            )?,
             digest_size as usize,
-        );
+        )?;
+
+        let scatter = if let Some(offset) = scatter_offset {
+            let scatter = get_scatter(data, offset as usize)?;
+
+            // Every page described by the scatter vector needs a code digest.
+            let pages = scatter
+                .iter()
+                .try_fold(0u32, |acc, entry| acc.checked_add(entry.count));
+            if pages.map_or(true, |pages| pages > n_code_slots) {
+                return Err(AppleCodesignError::CodeDirectoryMalformedScatter);
+            }
+
+            Some(scatter)
+        } else {
+            None
+        };
 
         let special_digests = get_hashes(
             data,
-            (digest_offset - (digest_size as u32 * n_special_slots)) as usize,
+            (digest_size as u32)
+                .checked_mul(n_special_slots)
+                .and_then(|len| digest_offset.checked_sub(len))
+                .ok_or(AppleCodesignError::CodeDirectoryMalformedDigests)? as usize,
             n_special_slots as usize,
             digest_size as usize,
-        )
+        )?
         .into_iter()
         .enumerate()
         .map(|(i, h)| (CodeSigningSlot::from(n_special_slots - i as u32), h))
@@ -394,7 +679,7 @@ impl<'a> Blob<'a> for CodeDirectoryBlob<'a> {
             platform,
             page_size,
             spare2,
-            scatter_offset,
+            scatter,
             spare3,
             code_limit_64,
             exec_seg_base,
@@ -433,7 +718,7 @@ impl<'a> Blob<'a> for CodeDirectoryBlob<'a> {
         // that offset. Digests are also at the index of their CodeSigningSlot constant.
         // e.g. Code Directory is the first element in the specials array because
         // it is slot 0. This means we need to write out empty digests for missing
-        // special slots. Our local specials HashMap may not have all entries. So compute
+        // special slots. Our local specials map may not have all entries. So compute
         // how many specials there should be and write that here. We'll insert placeholder
         // digests later.
         let highest_slot = self
@@ -449,7 +734,11 @@ impl<'a> Blob<'a> for CodeDirectoryBlob<'a> {
         cursor.iowrite_with(self.digest_size, scroll::BE)?;
         cursor.iowrite_with(u8::from(self.digest_type), scroll::BE)?;
         cursor.iowrite_with(self.platform, scroll::BE)?;
-        cursor.iowrite_with(self.page_size.trailing_zeros() as u8, scroll::BE)?;
+        let page_size = match self.page_size {
+            0 => 0,
+            v => v.trailing_zeros() as u8,
+        };
+        cursor.iowrite_with(page_size, scroll::BE)?;
         assert_eq!(cursor.position(), 0x20);
         cursor.iowrite_with(self.spare2, scroll::BE)?;
 
@@ -458,7 +747,7 @@ impl<'a> Blob<'a> for CodeDirectoryBlob<'a> {
 
         if self.version >= CodeDirectoryVersion::SupportsScatter as u32 {
             scatter_offset_cursor_position = Some(cursor.position());
-            cursor.iowrite_with(self.scatter_offset.unwrap_or(0), scroll::BE)?;
+            cursor.iowrite_with(0u32, scroll::BE)?;
 
             if self.version >= CodeDirectoryVersion::SupportsTeamId as u32 {
                 team_offset_cursor_position = Some(cursor.position());
@@ -509,6 +798,20 @@ impl<'a> Blob<'a> for CodeDirectoryBlob<'a> {
 
         // We've written all the struct fields. Now write variable length fields.
 
+        // Like Apple's tooling, the scatter vector immediately follows the fixed
+        // fields.
+        let scatter_offset = cursor.position();
+        if scatter_offset_cursor_position.is_some() {
+            if let Some(scatter) = &self.scatter {
+                for entry in scatter.iter().chain(std::iter::once(&Scatter::default())) {
+                    cursor.iowrite_with(entry.count, scroll::BE)?;
+                    cursor.iowrite_with(entry.base, scroll::BE)?;
+                    cursor.iowrite_with(entry.target_offset, scroll::BE)?;
+                    cursor.iowrite_with(entry.spare, scroll::BE)?;
+                }
+            }
+        }
+
         let identity_offset = cursor.position();
         cursor.write_all(self.ident.as_bytes())?;
         cursor.write_all(b"\0")?;
@@ -546,11 +849,7 @@ impl<'a> Blob<'a> for CodeDirectoryBlob<'a> {
 
         let code_digests_start_offset = cursor.position();
 
-        for digest in &self.code_digests {
-            cursor.write_all(&digest.data)?;
-        }
-
-        // TODO write out scatter vector.
+        cursor.write_all(self.code_digests.as_bytes())?;
 
         // Now go back and update the placeholder offsets. We need to add 8 to account
         // for the blob header, which isn't present in this buffer.
@@ -560,8 +859,11 @@ impl<'a> Blob<'a> for CodeDirectoryBlob<'a> {
         cursor.set_position(ident_offset_cursor_position);
         cursor.iowrite_with(identity_offset as u32 + 8, scroll::BE)?;
 
-        if scatter_offset_cursor_position.is_some() && self.scatter_offset.is_some() {
-            return Err(AppleCodesignError::Unimplemented("scatter offset"));
+        if let Some(offset) = scatter_offset_cursor_position {
+            if self.scatter.is_some() {
+                cursor.set_position(offset);
+                cursor.iowrite_with(scatter_offset as u32 + 8, scroll::BE)?;
+            }
         }
 
         if let Some(offset) = team_offset_cursor_position {
@@ -576,8 +878,42 @@ impl<'a> Blob<'a> for CodeDirectoryBlob<'a> {
 }
 
 impl<'a> CodeDirectoryBlob<'a> {
+    /// Compute the cdhash of this code directory.
+    ///
+    /// This is the digest of the serialized blob using the code directory's own
+    /// digest type, truncated to [CS_CDHASH_LEN] bytes. Use
+    /// [crate::EmbeddedSignature::cdhashes()] to digest the original bytes of a
+    /// parsed signature instead.
+    pub fn cdhash(&self) -> Result<Vec<u8>, AppleCodesignError> {
+        let mut digest = self.digest_with(self.digest_type)?;
+        digest.truncate(CS_CDHASH_LEN);
+
+        Ok(digest)
+    }
+
+    /// Resolve the file range `(start, end)` covered by the code digest at `index`.
+    ///
+    /// Ranges are derived from the page size and, if present, the scatter vector.
+    /// Returns `None` if the slot doesn't correspond to data before `code_limit`.
+    pub fn code_page_range(&self, index: usize, code_limit: u64) -> Option<(u64, u64)> {
+        let page_size = self.page_size as u64;
+
+        let start_offset = match &self.scatter {
+            _ if page_size == 0 => return (index == 0).then_some((0, code_limit)),
+            Some(scatter) => scatter.iter().find_map(|entry| {
+                let page = (index as u64).checked_sub(entry.base as u64)?;
+
+                (page < entry.count as u64).then(|| entry.target_offset + page * page_size)
+            })?,
+            None => page_size * index as u64,
+        };
+
+        (start_offset < code_limit)
+            .then(|| (start_offset, (start_offset + page_size).min(code_limit)))
+    }
+
     /// Obtain the mapping of slots to digests.
-    pub fn slot_digests(&self) -> &HashMap<CodeSigningSlot, Digest<'a>> {
+    pub fn slot_digests(&self) -> &BTreeMap<CodeSigningSlot, Digest<'a>> {
         &self.special_digests
     }
 
@@ -613,17 +949,30 @@ impl<'a> CodeDirectoryBlob<'a> {
         Ok(())
     }
 
+    /// Set the digest for a given slot by digesting a blob.
+    ///
+    /// The blob is digested with this code directory's digest type, as is
+    /// required for special slots.
+    pub fn set_slot_blob_digest<'b>(
+        &mut self,
+        slot: CodeSigningSlot,
+        blob: &impl Blob<'b>,
+    ) -> Result<(), AppleCodesignError> {
+        let digest = blob.digest_with(self.digest_type)?;
+
+        self.set_slot_digest(slot, digest)
+    }
+
     /// Adjust the version of the data structure according to what fields are set.
     ///
     /// Returns the old version.
     pub fn adjust_version(&mut self, target: Option<MachoTarget>) -> u32 {
         let old_version = self.version;
 
-        let mut minimum_version = CodeDirectoryVersion::Initial;
+        // Apple's tooling never emits a version older than scatter support and
+        // Security rejects versions older than 0x20001. So that is our floor.
+        let mut minimum_version = CodeDirectoryVersion::SupportsScatter;
 
-        if self.scatter_offset.is_some() {
-            minimum_version = CodeDirectoryVersion::SupportsScatter;
-        }
         if self.team_name.is_some() {
             minimum_version = CodeDirectoryVersion::SupportsTeamId;
         }
@@ -692,7 +1041,7 @@ impl<'a> CodeDirectoryBlob<'a> {
     /// version to None.
     pub fn clear_newer_fields(&mut self) {
         if self.version < CodeDirectoryVersion::SupportsScatter as u32 {
-            self.scatter_offset = None;
+            self.scatter = None;
         }
         if self.version < CodeDirectoryVersion::SupportsTeamId as u32 {
             self.team_name = None;
@@ -729,7 +1078,7 @@ impl<'a> CodeDirectoryBlob<'a> {
             platform: self.platform,
             page_size: self.page_size,
             spare2: self.spare2,
-            scatter_offset: self.scatter_offset,
+            scatter: self.scatter.clone(),
             spare3: self.spare3,
             code_limit_64: self.code_limit_64,
             exec_seg_base: self.exec_seg_base,
@@ -747,16 +1096,12 @@ impl<'a> CodeDirectoryBlob<'a> {
                 .team_name
                 .as_ref()
                 .map(|x| Cow::Owned(x.clone().into_owned())),
-            code_digests: self
-                .code_digests
-                .iter()
-                .map(|h| h.to_owned())
-                .collect::<Vec<_>>(),
+            code_digests: self.code_digests.clone().into_owned_digests(),
             special_digests: self
                 .special_digests
                 .iter()
                 .map(|(k, v)| (k.to_owned(), v.to_owned()))
-                .collect::<HashMap<_, _>>(),
+                .collect::<BTreeMap<_, _>>(),
         }
     }
 }
@@ -796,4 +1141,289 @@ mod tests {
             CodeSignatureFlags::LINKER_SIGNED
         );
     }
+
+    #[test]
+    fn code_signature_flags_display() {
+        assert_eq!(CodeSignatureFlags::empty().to_string(), "0x0(none)");
+        assert_eq!(
+            (CodeSignatureFlags::ADHOC | CodeSignatureFlags::RUNTIME).to_string(),
+            "0x10002(adhoc,runtime)"
+        );
+        assert_eq!(
+            CodeSignatureFlags::from_bits_retain(0x40000 | 0x2000).to_string(),
+            "0x42000(library)"
+        );
+
+        // Names of user configurable flags round trip.
+        for name in CodeSignatureFlags::all_user_configurable() {
+            let flags = CodeSignatureFlags::from_str(name).unwrap();
+            assert_eq!(flags.to_string(), format!("0x{:x}({})", flags.bits(), name));
+        }
+    }
+
+    #[test]
+    fn special_digests_ordering() -> Result<(), AppleCodesignError> {
+        let mut cd = CodeDirectoryBlob {
+            digest_size: 32,
+            digest_type: DigestType::Sha256,
+            ..Default::default()
+        };
+
+        for slot in [
+            CodeSigningSlot::Entitlements,
+            CodeSigningSlot::Info,
+            CodeSigningSlot::RequirementSet,
+        ] {
+            cd.set_slot_digest(slot, vec![u32::from(slot) as u8; 32])?;
+        }
+
+        assert_eq!(
+            cd.slot_digests().keys().copied().collect::<Vec<_>>(),
+            vec![
+                CodeSigningSlot::Info,
+                CodeSigningSlot::RequirementSet,
+                CodeSigningSlot::Entitlements
+            ]
+        );
+
+        let debug = format!("{:?}", cd.special_digests);
+        let info = debug.find("Info").unwrap();
+        let requirements = debug.find("RequirementSet").unwrap();
+        let entitlements = debug.find("Entitlements").unwrap();
+        assert!(info < requirements && requirements < entitlements);
+
+        Ok(())
+    }
+
+    #[test]
+    fn untrusted_slot_counts() -> Result<(), AppleCodesignError> {
+        let cd = CodeDirectoryBlob {
+            digest_size: 32,
+            digest_type: DigestType::Sha256,
+            page_size: 4096,
+            ident: "com.example.untrusted".into(),
+            ..Default::default()
+        };
+        let data = cd.to_blob_bytes()?;
+        assert!(CodeDirectoryBlob::from_blob_bytes(&data).is_ok());
+
+        // n_special_slots and n_code_slots are at offsets 0x18 and 0x1c.
+        for offset in [0x18, 0x1c] {
+            let mut data = data.clone();
+            data[offset..offset + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+
+            assert!(matches!(
+                CodeDirectoryBlob::from_blob_bytes(&data),
+                Err(AppleCodesignError::CodeDirectoryMalformedDigests)
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn code_digests() -> Result<(), AppleCodesignError> {
+        let cd = CodeDirectoryBlob {
+            digest_size: 32,
+            digest_type: DigestType::Sha256,
+            page_size: 4096,
+            ident: "com.example.digests".into(),
+            code_digests: (0..3u8)
+                .map(|i| Digest::from(vec![i; 32]))
+                .collect::<Vec<_>>()
+                .try_into()?,
+            ..Default::default()
+        };
+        let data = cd.to_blob_bytes()?;
+
+        // Parsing borrows the digest region of the blob.
+        let parsed = CodeDirectoryBlob::from_blob_bytes(&data)?;
+        assert!(matches!(parsed.code_digests.data, Cow::Borrowed(_)));
+        assert_eq!(parsed.code_digests.len(), 3);
+        assert_eq!(parsed.code_digests.digest_size(), 32);
+        assert_eq!(parsed.code_digests.get(2).unwrap().to_vec(), vec![2; 32]);
+        assert!(parsed.code_digests.get(3).is_none());
+        assert_eq!(
+            parsed
+                .code_digests
+                .iter()
+                .map(|digest| digest.data[0])
+                .collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+        assert_eq!(parsed.code_digests.as_bytes(), cd.code_digests.as_bytes());
+
+        let mut digests = parsed.code_digests.clone().into_owned_digests();
+        assert!(matches!(digests.data, Cow::Owned(_)));
+        assert!(digests.push(&[0; 20]).is_err());
+        digests.push(&[3; 32])?;
+        assert_eq!(digests.len(), 4);
+
+        assert!(matches!(
+            CodeDigests::try_from(vec![Digest::from(vec![0; 32]), Digest::from(vec![0; 20])]),
+            Err(AppleCodesignError::CodeDirectoryMalformedDigests)
+        ));
+
+        assert!(CodeDigests::from_bytes(&[0u8; 33][..], 32).is_err());
+        assert!(CodeDigests::from_bytes(&[0u8; 1][..], 0).is_err());
+        assert!(CodeDigests::from_bytes(&[][..], 0)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn scatter() -> Result<(), AppleCodesignError> {
+        let mut cd = CodeDirectoryBlob {
+            version: CodeDirectoryVersion::SupportsScatter as u32,
+            digest_size: 32,
+            digest_type: DigestType::Sha256,
+            page_size: 4096,
+            ident: "com.example.scatter".into(),
+            code_digests: (0..3)
+                .map(|_| Digest::from(vec![0u8; 32]))
+                .collect::<Vec<_>>()
+                .try_into()?,
+            scatter: Some(vec![Scatter::new(1, 0, 0), Scatter::new(2, 1, 0x3000)]),
+            ..Default::default()
+        };
+
+        let data = cd.to_blob_bytes()?;
+        // The scatter vector and its sentinel follow the fixed header.
+        assert_eq!(
+            u32::from_be_bytes(data[0x2c..0x30].try_into().unwrap()),
+            0x30
+        );
+        assert_eq!(
+            &data[0x30 + 2 * Scatter::SIZE..0x30 + 3 * Scatter::SIZE],
+            &[0; 24]
+        );
+
+        let parsed = CodeDirectoryBlob::from_blob_bytes(&data)?;
+        assert_eq!(parsed.scatter, cd.scatter);
+        assert_eq!(parsed.ident, "com.example.scatter");
+        assert_eq!(parsed.code_page_range(0, 0x5000), Some((0, 0x1000)));
+        assert_eq!(parsed.code_page_range(1, 0x5000), Some((0x3000, 0x4000)));
+        assert_eq!(parsed.code_page_range(2, 0x4800), Some((0x4000, 0x4800)));
+        assert_eq!(parsed.code_page_range(3, 0x5000), None);
+
+        // Scatter vectors referencing more pages than code digests are rejected.
+        cd.code_digests = (0..2)
+            .map(|_| Digest::from(vec![0u8; 32]))
+            .collect::<Vec<_>>()
+            .try_into()?;
+        assert!(matches!(
+            CodeDirectoryBlob::from_blob_bytes(&cd.to_blob_bytes()?),
+            Err(AppleCodesignError::CodeDirectoryMalformedScatter)
+        ));
+
+        // As are scatter vectors without a sentinel.
+        let mut data = data;
+        let offset = data.len() as u32 - 8;
+        data[0x2c..0x30].copy_from_slice(&offset.to_be_bytes());
+        assert!(matches!(
+            CodeDirectoryBlob::from_blob_bytes(&data),
+            Err(AppleCodesignError::CodeDirectoryMalformedScatter)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn version_roundtrip() -> Result<(), AppleCodesignError> {
+        for version in [
+            CodeDirectoryVersion::Initial,
+            CodeDirectoryVersion::SupportsScatter,
+            CodeDirectoryVersion::SupportsTeamId,
+            CodeDirectoryVersion::SupportsCodeLimit64,
+            CodeDirectoryVersion::SupportsExecutableSegment,
+            CodeDirectoryVersion::SupportsRuntime,
+            CodeDirectoryVersion::SupportsLinkage,
+        ] {
+            let mut cd = CodeDirectoryBlob {
+                version: version as u32,
+                flags: CodeSignatureFlags::ADHOC | CodeSignatureFlags::RUNTIME,
+                code_limit: 0x4000,
+                digest_size: 32,
+                digest_type: DigestType::Sha256,
+                page_size: 4096,
+                code_limit_64: Some(0x1_0000_0000),
+                exec_seg_base: Some(0),
+                exec_seg_limit: Some(0x4000),
+                exec_seg_flags: Some(ExecutableSegmentFlags::MAIN_BINARY),
+                runtime: Some(0x000d_0000),
+                linkage_hash_type: Some(2),
+                linkage_offset: Some(0x80),
+                linkage_size: Some(0x20),
+                ident: "com.example.roundtrip".into(),
+                team_name: Some("TEAMID".into()),
+                code_digests: vec![vec![1; 32].into(), vec![2; 32].into()].try_into()?,
+                ..Default::default()
+            };
+            cd.set_slot_digest(CodeSigningSlot::Info, vec![3; 32])?;
+            cd.set_slot_digest(CodeSigningSlot::Entitlements, vec![5; 32])?;
+            cd.clear_newer_fields();
+
+            let data = cd.to_blob_bytes()?;
+            let parsed = CodeDirectoryBlob::from_blob_bytes(&data)?;
+
+            assert_eq!(parsed.version, version as u32);
+            assert_eq!(parsed.flags, cd.flags);
+            assert_eq!(parsed.ident, cd.ident);
+            assert_eq!(parsed.team_name, cd.team_name);
+            let digests = |cd: &CodeDirectoryBlob| {
+                (
+                    cd.code_digests
+                        .iter()
+                        .map(|digest| digest.to_vec())
+                        .collect::<Vec<_>>(),
+                    // Unset slots are serialized as null digests.
+                    cd.slot_digests()
+                        .iter()
+                        .filter(|(_, digest)| !digest.is_null())
+                        .map(|(slot, digest)| (*slot, digest.to_vec()))
+                        .collect::<Vec<_>>(),
+                )
+            };
+            assert_eq!(digests(&parsed), digests(&cd));
+            assert_eq!(
+                parsed.code_limit_64.filter(|x| *x != 0),
+                cd.code_limit_64,
+                "{version:?}"
+            );
+            assert_eq!(parsed.runtime.filter(|x| *x != 0), cd.runtime);
+            assert_eq!(parsed.linkage_size.filter(|x| *x != 0), cd.linkage_size);
+            assert_eq!(parsed.to_blob_bytes()?, data);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn slot_blob_digest() -> Result<(), AppleCodesignError> {
+        use crate::embedded_signature::EntitlementsBlob;
+
+        let blob = EntitlementsBlob::from_string("<plist/>");
+
+        for digest_type in [DigestType::Sha1, DigestType::Sha256] {
+            let mut cd = CodeDirectoryBlob {
+                digest_size: digest_type.hash_len()? as u8,
+                digest_type,
+                ..Default::default()
+            };
+
+            cd.set_slot_blob_digest(CodeSigningSlot::Entitlements, &blob)?;
+            assert_eq!(
+                cd.slot_digest(CodeSigningSlot::Entitlements)
+                    .unwrap()
+                    .to_vec(),
+                digest_type.digest_data(&blob.to_blob_bytes()?)?
+            );
+
+            assert!(cd
+                .set_slot_blob_digest(CodeSigningSlot::CodeDirectory, &blob)
+                .is_err());
+        }
+
+        Ok(())
+    }
 }
@@ -25,8 +25,10 @@ on 4 byte boundaries.
 
 use {
     crate::{
+        certificate::AppleCertificate,
         embedded_signature::{
-            read_and_validate_blob_header, CodeSigningMagic, RequirementBlob, RequirementSetBlob,
+            read_and_validate_blob_header, CodeSigningMagic, DigestType, RequirementBlob,
+            RequirementSetBlob,
         },
         error::AppleCodesignError,
     },
@@ -39,9 +41,45 @@ use {
         fmt::{Debug, Display},
         io::Write,
         ops::{Deref, DerefMut},
+        str::FromStr,
     },
+    x509_certificate::CapturedX509Certificate,
 };
 
+/// Maximum nesting depth of code requirement expressions we parse.
+///
+/// Expressions are parsed recursively. Binary data nesting expressions deeper
+/// than this is rejected so crafted input can't exhaust the stack.
+pub const MAX_REQUIREMENT_EXPRESSION_DEPTH: usize = 64;
+
+thread_local! {
+    static EXPRESSION_DEPTH: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Tracks the nesting depth of expressions being parsed on the current thread.
+struct ExpressionDepthGuard;
+
+impl ExpressionDepthGuard {
+    fn enter() -> Result<Self, AppleCodesignError> {
+        EXPRESSION_DEPTH.with(|depth| {
+            if depth.get() >= MAX_REQUIREMENT_EXPRESSION_DEPTH {
+                Err(AppleCodesignError::RequirementMalformed(
+                    "expressions nested too deeply",
+                ))
+            } else {
+                depth.set(depth.get() + 1);
+                Ok(Self)
+            }
+        })
+    }
+}
+
+impl Drop for ExpressionDepthGuard {
+    fn drop(&mut self) {
+        EXPRESSION_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
 const OPCODE_FLAG_MASK: u32 = 0xff000000;
 const OPCODE_VALUE_MASK: u32 = 0x00ffffff;
 
@@ -123,18 +161,23 @@ impl std::fmt::Display for RequirementType {
 }
 
 fn read_data(data: &[u8]) -> Result<(&[u8], &[u8]), AppleCodesignError> {
-    let length = data.pread_with::<u32>(0, scroll::BE)?;
-    let value = &data[4..4 + length as usize];
+    let length = data.pread_with::<u32>(0, scroll::BE)? as usize;
+    let value = data[4..]
+        .get(..length)
+        .ok_or(AppleCodesignError::RequirementMalformed(
+            "data length exceeds available data",
+        ))?;
 
-    // Next element is aligned on next 4 byte boundary.
-    let offset = 4 + length as usize;
+    // Next element is aligned on next 4 byte boundary. Tolerate missing
+    // padding at the end of the data.
+    let offset = 4 + length;
 
     let offset = match offset % 4 {
         0 => offset,
         extra => offset + 4 - extra,
     };
 
-    let remaining = &data[offset..];
+    let remaining = &data[offset.min(data.len())..];
 
     Ok((value, remaining))
 }
@@ -164,6 +207,66 @@ fn format_certificate_slot(slot: i32) -> String {
     }
 }
 
+/// Format a string for code requirement language source, quoting it if necessary.
+fn quote_requirement_string(value: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "absent",
+        "always",
+        "and",
+        "anchor",
+        "apple",
+        "cdhash",
+        "cert",
+        "certificate",
+        "designated",
+        "entitlement",
+        "exists",
+        "false",
+        "generic",
+        "identifier",
+        "info",
+        "leaf",
+        "legacy",
+        "never",
+        "not",
+        "notarized",
+        "or",
+        "platform",
+        "root",
+        "timestamp",
+        "true",
+        "trusted",
+    ];
+
+    if !value.is_empty()
+        && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !value.chars().all(|c| c.is_ascii_digit())
+        && !KEYWORDS.contains(&value)
+    {
+        value.to_string()
+    } else {
+        escape_requirement_string(value)
+    }
+}
+
+/// Format a string for code requirement language source as a quoted string.
+fn escape_requirement_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Format a key in `[]` for code requirement language source.
+fn quote_requirement_key(key: &str) -> String {
+    if !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+    {
+        key.to_string()
+    } else {
+        format!("\"{}\"", key.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
 /// A value in a code requirement expression.
 ///
 /// The value can be various primitive types. This type exists to make it
@@ -216,6 +319,16 @@ impl<'a> Display for CodeRequirementValue<'a> {
 }
 
 impl<'a> CodeRequirementValue<'a> {
+    /// Express this value in code requirement language source.
+    ///
+    /// Strings are only quoted if necessary and binary data is expressed as `H"<hex>"`.
+    pub fn to_requirement_source(&self) -> String {
+        match self {
+            Self::String(s) => quote_requirement_string(s),
+            Self::Bytes(data) => format!("H\"{}\"", hex::encode(data)),
+        }
+    }
+
     /// Write the encoded version of this value somewhere.
     ///
     /// Binary encoding is u32 of length, then raw bytes, then NULL padding to next u32.
@@ -299,6 +412,42 @@ impl RequirementOpCode {
     pub fn parse_payload<'a>(
         &self,
         data: &'a [u8],
+    ) -> Result<(CodeRequirementExpression<'a>, &'a [u8]), AppleCodesignError> {
+        match self {
+            Self::And => {
+                let (a, data) = CodeRequirementExpression::from_bytes(data)?;
+                let (b, data) = CodeRequirementExpression::from_bytes(data)?;
+
+                Ok((
+                    CodeRequirementExpression::And(Box::new(a), Box::new(b)),
+                    data,
+                ))
+            }
+            Self::Or => {
+                let (a, data) = CodeRequirementExpression::from_bytes(data)?;
+                let (b, data) = CodeRequirementExpression::from_bytes(data)?;
+
+                Ok((
+                    CodeRequirementExpression::Or(Box::new(a), Box::new(b)),
+                    data,
+                ))
+            }
+            Self::Not => {
+                let (expr, data) = CodeRequirementExpression::from_bytes(data)?;
+
+                Ok((CodeRequirementExpression::Not(Box::new(expr)), data))
+            }
+            _ => self.parse_operands(data),
+        }
+    }
+
+    /// Parse the payload of an opcode not containing nested expressions.
+    ///
+    /// This is separate from [Self::parse_payload()] to keep the stack frames of
+    /// recursive expression parsing small.
+    fn parse_operands<'a>(
+        &self,
+        data: &'a [u8],
     ) -> Result<(CodeRequirementExpression<'a>, &'a [u8]), AppleCodesignError> {
         match self {
             Self::False => Ok((CodeRequirementExpression::False, data)),
@@ -314,12 +463,11 @@ impl RequirementOpCode {
             Self::AnchorApple => Ok((CodeRequirementExpression::AnchorApple, data)),
             Self::AnchorCertificateHash => {
                 let slot = data.pread_with::<i32>(0, scroll::BE)?;
-                let digest_length = data.pread_with::<u32>(4, scroll::BE)?;
-                let digest = &data[8..8 + digest_length as usize];
+                let (digest, data) = read_data(&data[4..])?;
 
                 Ok((
                     CodeRequirementExpression::AnchorCertificateHash(slot, digest.into()),
-                    &data[8 + digest_length as usize..],
+                    data,
                 ))
             }
             Self::InfoKeyValueLegacy => {
@@ -340,24 +488,6 @@ impl RequirementOpCode {
                     data,
                 ))
             }
-            Self::And => {
-                let (a, data) = CodeRequirementExpression::from_bytes(data)?;
-                let (b, data) = CodeRequirementExpression::from_bytes(data)?;
-
-                Ok((
-                    CodeRequirementExpression::And(Box::new(a), Box::new(b)),
-                    data,
-                ))
-            }
-            Self::Or => {
-                let (a, data) = CodeRequirementExpression::from_bytes(data)?;
-                let (b, data) = CodeRequirementExpression::from_bytes(data)?;
-
-                Ok((
-                    CodeRequirementExpression::Or(Box::new(a), Box::new(b)),
-                    data,
-                ))
-            }
             Self::CodeDirectoryHash => {
                 let (value, data) = read_data(data)?;
 
@@ -366,11 +496,6 @@ impl RequirementOpCode {
                     data,
                 ))
             }
-            Self::Not => {
-                let (expr, data) = CodeRequirementExpression::from_bytes(data)?;
-
-                Ok((CodeRequirementExpression::Not(Box::new(expr)), data))
-            }
             Self::InfoPlistExpression => {
                 let (key, data) = read_data(data)?;
 
@@ -486,6 +611,9 @@ impl RequirementOpCode {
                 ))
             }
             Self::LegacyDeveloperId => Ok((CodeRequirementExpression::LegacyDeveloperId, data)),
+            Self::And | Self::Or | Self::Not => {
+                unreachable!("nested expressions are parsed by parse_payload()")
+            }
         }
     }
 }
@@ -530,7 +658,8 @@ pub enum CodeRequirementExpression<'a> {
 
     /// Info.plist key value (legacy).
     ///
-    /// `info[<key>] = <value>`
+    /// `info[<key>] = <value>`. Expressed as `legacy info[<key>] = <value>` in
+    /// source, as the former compiles to [Self::InfoPlistKeyField].
     ///
     /// 2 pairs of (length + value).
     InfoKeyValueLegacy(Cow<'a, str>, Cow<'a, str>),
@@ -636,7 +765,7 @@ pub enum CodeRequirementExpression<'a> {
 
     /// Named code.
     ///
-    /// `(<name>)`
+    /// `(<name>)`. Expressed as `("<name>")` in source.
     ///
     /// 4 bytes name length, name bytes.
     NamedCode(Cow<'a, str>),
@@ -779,10 +908,144 @@ impl<'a> From<&CodeRequirementExpression<'a>> for RequirementOpCode {
 }
 
 impl<'a> CodeRequirementExpression<'a> {
+    /// Express this expression in the code requirement language.
+    ///
+    /// This emits the form `codesign -d -r-` prints. Unlike the [Display]
+    /// implementation, parentheses are only emitted where needed to preserve the
+    /// structure of the expression tree and strings are only quoted when necessary.
+    /// The output can be compiled back to an equivalent expression.
+    pub fn to_requirement_source(&self) -> String {
+        self.source_with_precedence(0)
+    }
+
+    /// Express this expression in source, parenthesized if binding weaker than `precedence`.
+    ///
+    /// `or` has precedence 0, `and` 1, and everything else 2. Both operators are
+    /// left associative, so only right operands of the same operator are
+    /// parenthesized.
+    fn source_with_precedence(&self, precedence: u8) -> String {
+        let (own, source) = match self {
+            Self::Or(a, b) => (
+                0,
+                format!(
+                    "{} or {}",
+                    a.source_with_precedence(0),
+                    b.source_with_precedence(1)
+                ),
+            ),
+            Self::And(a, b) => (
+                1,
+                format!(
+                    "{} and {}",
+                    a.source_with_precedence(1),
+                    b.source_with_precedence(2)
+                ),
+            ),
+            Self::Not(expr) => (2, format!("! {}", expr.source_with_precedence(2))),
+            Self::False => (2, "never".to_string()),
+            Self::True => (2, "always".to_string()),
+            Self::Identifier(value) => {
+                (2, format!("identifier {}", quote_requirement_string(value)))
+            }
+            Self::AnchorApple => (2, "anchor apple".to_string()),
+            Self::AnchorCertificateHash(slot, digest) => (
+                2,
+                format!(
+                    "certificate {} = H\"{}\"",
+                    format_certificate_slot(*slot),
+                    hex::encode(digest)
+                ),
+            ),
+            Self::InfoKeyValueLegacy(key, value) => (
+                2,
+                format!(
+                    "legacy info[{}] = {}",
+                    quote_requirement_key(key),
+                    quote_requirement_string(value)
+                ),
+            ),
+            Self::CodeDirectoryHash(digest) => (2, format!("cdhash H\"{}\"", hex::encode(digest))),
+            Self::InfoPlistKeyField(key, m) => (
+                2,
+                format!(
+                    "info[{}] {}",
+                    quote_requirement_key(key),
+                    m.to_requirement_source()
+                ),
+            ),
+            Self::CertificateField(slot, field, m) => (
+                2,
+                format!(
+                    "certificate {}[{}] {}",
+                    format_certificate_slot(*slot),
+                    field,
+                    m.to_requirement_source()
+                ),
+            ),
+            Self::CertificateTrusted(slot) => (
+                2,
+                format!("certificate {} trusted", format_certificate_slot(*slot)),
+            ),
+            Self::AnchorTrusted => (2, "anchor trusted".to_string()),
+            Self::CertificateGeneric(slot, oid, m) => (
+                2,
+                format!(
+                    "certificate {}[field.{}] {}",
+                    format_certificate_slot(*slot),
+                    oid,
+                    m.to_requirement_source()
+                ),
+            ),
+            Self::AnchorAppleGeneric => (2, "anchor apple generic".to_string()),
+            Self::EntitlementsKey(key, m) => (
+                2,
+                format!(
+                    "entitlement[{}] {}",
+                    quote_requirement_key(key),
+                    m.to_requirement_source()
+                ),
+            ),
+            Self::CertificatePolicy(slot, oid, m) => (
+                2,
+                format!(
+                    "certificate {}[policy.{}] {}",
+                    format_certificate_slot(*slot),
+                    oid,
+                    m.to_requirement_source()
+                ),
+            ),
+            Self::NamedAnchor(name) => (
+                2,
+                format!("anchor apple {}", quote_requirement_string(name)),
+            ),
+            Self::NamedCode(name) => (2, format!("({})", escape_requirement_string(name))),
+            Self::Platform(platform) => (2, format!("platform = {platform}")),
+            Self::Notarized => (2, "notarized".to_string()),
+            Self::CertificateFieldDate(slot, oid, m) => (
+                2,
+                format!(
+                    "certificate {}[timestamp.{}] {}",
+                    format_certificate_slot(*slot),
+                    oid,
+                    m.to_requirement_source()
+                ),
+            ),
+            Self::LegacyDeveloperId => (2, "legacy".to_string()),
+        };
+
+        if own < precedence {
+            format!("({source})")
+        } else {
+            source
+        }
+    }
+
     /// Construct an expression element by reading from a slice.
     ///
     /// Returns the newly constructed element and remaining data in the slice.
     pub fn from_bytes(data: &'a [u8]) -> Result<(Self, &'a [u8]), AppleCodesignError> {
+        let _depth = ExpressionDepthGuard::enter()?;
+
         let opcode_raw = data.pread_with::<u32>(0, scroll::BE)?;
 
         let _flags = opcode_raw & OPCODE_FLAG_MASK;
@@ -1207,6 +1470,29 @@ impl<'a> From<&CodeRequirementMatchExpression<'a>> for MatchType {
 }
 
 impl<'a> CodeRequirementMatchExpression<'a> {
+    /// Express this match expression in code requirement language source.
+    ///
+    /// Values are formatted as in [CodeRequirementValue::to_requirement_source()].
+    pub fn to_requirement_source(&self) -> String {
+        match self {
+            Self::Exists => "/* exists */".to_string(),
+            Self::Equal(value) => format!("= {}", value.to_requirement_source()),
+            Self::Contains(value) => format!("~ {}", value.to_requirement_source()),
+            Self::BeginsWith(value) => format!("= {}*", value.to_requirement_source()),
+            Self::EndsWith(value) => format!("= *{}", value.to_requirement_source()),
+            Self::LessThan(value) => format!("< {}", value.to_requirement_source()),
+            Self::GreaterThan(value) => format!("> {}", value.to_requirement_source()),
+            Self::LessThanEqual(value) => format!("<= {}", value.to_requirement_source()),
+            Self::GreaterThanEqual(value) => format!(">= {}", value.to_requirement_source()),
+            Self::On(value) => format!("= timestamp \"{value}\""),
+            Self::Before(value) => format!("< timestamp \"{value}\""),
+            Self::After(value) => format!("> timestamp \"{value}\""),
+            Self::OnOrBefore(value) => format!("<= timestamp \"{value}\""),
+            Self::OnOrAfter(value) => format!(">= timestamp \"{value}\""),
+            Self::Absent => "absent".to_string(),
+        }
+    }
+
     /// Parse a match expression from bytes.
     ///
     /// The slice should begin with the match type u32.
@@ -1287,6 +1573,14 @@ impl<'a> CodeRequirements<'a> {
         let count = data.pread_with::<u32>(0, scroll::BE)?;
         let mut data = &data[4..];
 
+        // Every expression occupies at least 4 bytes. So the count can't
+        // legitimately exceed that. Check before allocating.
+        if count as usize > data.len() / 4 {
+            return Err(AppleCodesignError::RequirementMalformed(
+                "expression count exceeds data length",
+            ));
+        }
+
         let mut elements = Vec::with_capacity(count as usize);
 
         for _ in 0..count {
@@ -1357,6 +1651,95 @@ impl<'a> CodeRequirements<'a> {
     }
 }
 
+/// The designated requirement of signed code.
+///
+/// The designated requirement identifies code across versions and is what
+/// policies such as TCC, MDM profiles, and Santa rules typically reference.
+/// Unlike [CodeRequirements], instances own their data and can outlive the
+/// signature they were obtained from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DesignatedRequirement {
+    /// Binary serialization of the requirement, without blob header.
+    data: Vec<u8>,
+}
+
+impl DesignatedRequirement {
+    /// Construct an instance from the binary serialization of code requirements.
+    ///
+    /// This is the payload of a requirement blob, without the blob header.
+    pub fn from_binary(data: &[u8]) -> Result<Self, AppleCodesignError> {
+        let (_, remaining) = CodeRequirements::parse_binary(data)?;
+
+        Ok(Self {
+            data: data[..data.len() - remaining.len()].to_vec(),
+        })
+    }
+
+    /// Construct an instance from a code requirement blob, which begins with header magic.
+    ///
+    /// This can be used to parse the output generated by `csreq -b`.
+    pub fn from_blob(data: &[u8]) -> Result<Self, AppleCodesignError> {
+        let data = read_and_validate_blob_header(
+            data,
+            u32::from(CodeSigningMagic::Requirement),
+            "code requirement blob",
+        )
+        .map_err(|_| AppleCodesignError::RequirementMalformed("blob header"))?;
+
+        Self::from_binary(data)
+    }
+
+    /// Construct an instance by compiling code requirement language source.
+    ///
+    /// See [compile_requirement_text()] for the accepted source.
+    pub fn from_requirement_text(source: &str) -> Result<Self, AppleCodesignError> {
+        Self::from_binary(&compile_requirement_text(source)?)
+    }
+
+    /// Construct an instance from a single expression.
+    pub fn from_expression(expr: &CodeRequirementExpression) -> Result<Self, AppleCodesignError> {
+        let mut data = vec![];
+        CodeRequirements(vec![expr.clone()]).write_to(&mut data)?;
+
+        Ok(Self { data })
+    }
+
+    /// Obtain the parsed expressions constituting this requirement.
+    pub fn expressions(&self) -> Result<CodeRequirements<'_>, AppleCodesignError> {
+        Ok(CodeRequirements::parse_binary(&self.data)?.0)
+    }
+
+    /// Obtain the parsed expression of this requirement.
+    ///
+    /// Errors if the requirement doesn't consist of exactly 1 expression.
+    pub fn expression(&self) -> Result<CodeRequirementExpression<'_>, AppleCodesignError> {
+        let mut expressions = self.expressions()?.0;
+
+        if expressions.len() == 1 {
+            Ok(expressions.remove(0))
+        } else {
+            Err(AppleCodesignError::RequirementMalformed(
+                "designated requirement should have a single expression",
+            ))
+        }
+    }
+
+    /// Express this requirement in the code requirement language.
+    ///
+    /// The returned text can be compiled with `csreq -r` and is the form used
+    /// by TCC and most policy formats.
+    pub fn to_requirement_text(&self) -> Result<String, AppleCodesignError> {
+        Ok(self.expression()?.to_string())
+    }
+
+    /// Obtain the compiled blob representation of this requirement.
+    ///
+    /// The data should be equivalent to what `csreq -b` would produce.
+    pub fn to_blob_data(&self) -> Result<Vec<u8>, AppleCodesignError> {
+        self.expressions()?.to_blob_data()
+    }
+}
+
 impl<'a> TryFrom<&CodeRequirements<'a>> for RequirementBlob<'static> {
     type Error = AppleCodesignError;
 
@@ -1370,6 +1753,951 @@ impl<'a> TryFrom<&CodeRequirements<'a>> for RequirementBlob<'static> {
     }
 }
 
+/// A lexical token in code requirement language source.
+#[derive(Clone, Debug, PartialEq)]
+enum RequirementToken {
+    /// A bare word, such as a keyword or unquoted value.
+    Word(String),
+    /// A quoted string.
+    String(String),
+    /// A hex encoded hash, `H"<hex>"`.
+    Hash(Vec<u8>),
+    /// An integer.
+    Integer(i64),
+    /// The content of `[...]`.
+    Bracket(String),
+    /// Punctuation.
+    Symbol(&'static str),
+}
+
+impl Display for RequirementToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Word(s) => f.write_str(s),
+            Self::String(s) => f.write_fmt(format_args!("\"{s}\"")),
+            Self::Hash(data) => f.write_fmt(format_args!("H\"{}\"", hex::encode(data))),
+            Self::Integer(v) => f.write_fmt(format_args!("{v}")),
+            Self::Bracket(s) => f.write_fmt(format_args!("[{s}]")),
+            Self::Symbol(s) => f.write_str(s),
+        }
+    }
+}
+
+/// Punctuation recognized by the tokenizer, longest first.
+const REQUIREMENT_SYMBOLS: &[&str] = &[
+    "&&", "||", "<=", ">=", "=>", "(", ")", "!", "=", "~", "<", ">", "*",
+];
+
+fn requirement_syntax_error(message: impl Display) -> AppleCodesignError {
+    AppleCodesignError::RequirementSyntax(message.to_string())
+}
+
+/// Read a quoted string whose opening quote has already been consumed.
+///
+/// Returns the unescaped value and the remaining input after the closing quote.
+fn read_quoted(source: &str) -> Result<(String, &str), AppleCodesignError> {
+    let mut value = String::new();
+    let mut chars = source.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, &source[i + 1..])),
+            '\\' => match chars.next() {
+                Some((_, c)) => value.push(c),
+                None => break,
+            },
+            c => value.push(c),
+        }
+    }
+
+    Err(requirement_syntax_error("unterminated string"))
+}
+
+/// Split code requirement language source into tokens.
+fn tokenize_requirement(source: &str) -> Result<Vec<RequirementToken>, AppleCodesignError> {
+    let is_word_char = |c: char| c.is_ascii_alphanumeric() || "_.-/+$".contains(c);
+
+    let mut tokens = vec![];
+    let mut rest = source;
+
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            let end = comment
+                .find("*/")
+                .ok_or_else(|| requirement_syntax_error("unterminated comment"))?;
+            rest = &comment[end + 2..];
+        } else if rest.starts_with("//") || c == '#' {
+            rest = rest.find('\n').map(|end| &rest[end..]).unwrap_or("");
+        } else if let Some(hash) = rest.strip_prefix("H\"") {
+            let (value, remaining) = read_quoted(hash)?;
+            tokens.push(RequirementToken::Hash(hex::decode(&value).map_err(
+                |_| requirement_syntax_error(format!("invalid hash H\"{value}\"")),
+            )?));
+            rest = remaining;
+        } else if let Some(quoted) = rest.strip_prefix('"') {
+            let (value, remaining) = read_quoted(quoted)?;
+            tokens.push(RequirementToken::String(value));
+            rest = remaining;
+        } else if let Some(bracket) = rest.strip_prefix('[') {
+            let end = bracket
+                .find(']')
+                .ok_or_else(|| requirement_syntax_error("unterminated ["))?;
+            let value = bracket[..end].trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            tokens.push(RequirementToken::Bracket(value.to_string()));
+            rest = &bracket[end + 1..];
+        } else if is_word_char(c) {
+            let end = rest.find(|c| !is_word_char(c)).unwrap_or(rest.len());
+            let value = &rest[..end];
+            tokens.push(match value.parse::<i64>() {
+                Ok(v) => RequirementToken::Integer(v),
+                Err(_) => RequirementToken::Word(value.to_string()),
+            });
+            rest = &rest[end..];
+        } else if let Some(symbol) = REQUIREMENT_SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+            tokens.push(RequirementToken::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else {
+            return Err(requirement_syntax_error(format!(
+                "unexpected character {c:?}"
+            )));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser of code requirement language tokens.
+///
+/// `and` binds tighter than `or` and both are left associative, matching the
+/// expression trees emitted by `csreq`. e.g. `a and b and c` compiles to
+/// `(a and b) and c`.
+///
+/// Each operand of a chain of `and` or `or` adds a level to the expression tree.
+/// So operators count towards the nesting depth like parentheses do.
+struct RequirementParser<'a> {
+    tokens: &'a [RequirementToken],
+    /// Encoded OIDs of `[field.*]`, `[policy.*]`, and `[timestamp.*]` tokens, by token index.
+    oids: &'a [Option<Vec<u8>>],
+    position: usize,
+    depth: usize,
+}
+
+impl<'a> RequirementParser<'a> {
+    fn peek(&self) -> Option<&'a RequirementToken> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<&'a RequirementToken> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn error(&self, expected: &str) -> AppleCodesignError {
+        match self.peek() {
+            Some(token) => requirement_syntax_error(format!("expected {expected}; found {token}")),
+            None => requirement_syntax_error(format!("expected {expected}; found end of input")),
+        }
+    }
+
+    fn peek_word(&self, words: &[&str]) -> bool {
+        matches!(self.peek(), Some(RequirementToken::Word(w)) if words.contains(&w.as_str()))
+    }
+
+    fn peek_symbol(&self, symbols: &[&str]) -> bool {
+        matches!(self.peek(), Some(RequirementToken::Symbol(s)) if symbols.contains(s))
+    }
+
+    fn eat_word(&mut self, words: &[&str]) -> bool {
+        let found = self.peek_word(words);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn eat_symbol(&mut self, symbols: &[&str]) -> bool {
+        let found = self.peek_symbol(symbols);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn parse_expression(&mut self) -> Result<CodeRequirementExpression<'a>, AppleCodesignError> {
+        self.depth += 1;
+        if self.depth > MAX_REQUIREMENT_EXPRESSION_DEPTH {
+            return Err(requirement_syntax_error("expression nested too deeply"));
+        }
+
+        let depth = self.depth;
+
+        let mut res = self.parse_and()?;
+
+        while self.eat_word(&["or"]) || self.eat_symbol(&["||"]) {
+            self.enter_operator()?;
+            res = CodeRequirementExpression::Or(Box::new(res), Box::new(self.parse_and()?));
+        }
+
+        self.depth = depth - 1;
+
+        Ok(res)
+    }
+
+    fn parse_and(&mut self) -> Result<CodeRequirementExpression<'a>, AppleCodesignError> {
+        let depth = self.depth;
+
+        let mut res = self.parse_unary()?;
+
+        while self.eat_word(&["and"]) || self.eat_symbol(&["&&"]) {
+            self.enter_operator()?;
+            res = CodeRequirementExpression::And(Box::new(res), Box::new(self.parse_unary()?));
+        }
+
+        self.depth = depth;
+
+        Ok(res)
+    }
+
+    /// Account for the expression tree level added by a binary operator.
+    fn enter_operator(&mut self) -> Result<(), AppleCodesignError> {
+        self.depth += 1;
+        if self.depth > MAX_REQUIREMENT_EXPRESSION_DEPTH {
+            return Err(requirement_syntax_error("expression nested too deeply"));
+        }
+
+        Ok(())
+    }
+
+    fn parse_unary(&mut self) -> Result<CodeRequirementExpression<'a>, AppleCodesignError> {
+        if self.eat_symbol(&["!"]) || self.eat_word(&["not"]) {
+            self.depth += 1;
+            if self.depth > MAX_REQUIREMENT_EXPRESSION_DEPTH {
+                return Err(requirement_syntax_error("expression nested too deeply"));
+            }
+            let expr = self.parse_unary()?;
+            self.depth -= 1;
+
+            Ok(CodeRequirementExpression::Not(Box::new(expr)))
+        } else {
+            self.parse_primary()
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<CodeRequirementExpression<'a>, AppleCodesignError> {
+        // A quoted string in parentheses is named code, not a nested expression.
+        if let (
+            Some(RequirementToken::Symbol("(")),
+            Some(RequirementToken::String(name)),
+            Some(RequirementToken::Symbol(")")),
+        ) = (
+            self.peek(),
+            self.tokens.get(self.position + 1),
+            self.tokens.get(self.position + 2),
+        ) {
+            self.position += 3;
+            return Ok(CodeRequirementExpression::NamedCode(name.as_str().into()));
+        }
+
+        if self.eat_symbol(&["("]) {
+            let expr = self.parse_expression()?;
+
+            return if self.eat_symbol(&[")"]) {
+                Ok(expr)
+            } else {
+                Err(self.error(")"))
+            };
+        }
+
+        let keyword = match self.peek() {
+            Some(RequirementToken::Word(word)) => word.as_str(),
+            _ => return Err(self.error("expression")),
+        };
+        self.position += 1;
+
+        match keyword {
+            "always" | "true" => Ok(CodeRequirementExpression::True),
+            "never" | "false" => Ok(CodeRequirementExpression::False),
+            "notarized" => Ok(CodeRequirementExpression::Notarized),
+            "legacy" if self.eat_word(&["info"]) => {
+                let key = self.parse_bracket()?;
+                if !self.eat_symbol(&["="]) {
+                    return Err(self.error("="));
+                }
+                match self.parse_value()? {
+                    CodeRequirementValue::String(value) => Ok(
+                        CodeRequirementExpression::InfoKeyValueLegacy(key.into(), value),
+                    ),
+                    CodeRequirementValue::Bytes(_) => Err(requirement_syntax_error(
+                        "legacy info values must be strings",
+                    )),
+                }
+            }
+            "legacy" => Ok(CodeRequirementExpression::LegacyDeveloperId),
+            "identifier" => {
+                self.eat_symbol(&["="]);
+                match self.next() {
+                    Some(RequirementToken::String(s) | RequirementToken::Word(s)) => {
+                        Ok(CodeRequirementExpression::Identifier(s.as_str().into()))
+                    }
+                    _ => {
+                        self.position -= 1;
+                        Err(self.error("identifier string"))
+                    }
+                }
+            }
+            "cdhash" => {
+                self.eat_symbol(&["="]);
+                Ok(CodeRequirementExpression::CodeDirectoryHash(
+                    self.parse_hash()?.into(),
+                ))
+            }
+            "platform" => {
+                self.eat_symbol(&["="]);
+                match self.next() {
+                    Some(RequirementToken::Integer(v)) => Ok(CodeRequirementExpression::Platform(
+                        u32::try_from(*v)
+                            .map_err(|_| requirement_syntax_error("invalid platform"))?,
+                    )),
+                    _ => {
+                        self.position -= 1;
+                        Err(self.error("platform integer"))
+                    }
+                }
+            }
+            "anchor" => self.parse_anchor(),
+            "certificate" | "cert" => {
+                let slot = self.parse_slot()?;
+                self.parse_certificate(slot)
+            }
+            "info" => {
+                let key = self.parse_bracket()?;
+                Ok(CodeRequirementExpression::InfoPlistKeyField(
+                    key.into(),
+                    self.parse_match()?,
+                ))
+            }
+            "entitlement" => {
+                let key = self.parse_bracket()?;
+                Ok(CodeRequirementExpression::EntitlementsKey(
+                    key.into(),
+                    self.parse_match()?,
+                ))
+            }
+            _ => {
+                self.position -= 1;
+                Err(self.error("expression"))
+            }
+        }
+    }
+
+    fn parse_anchor(&mut self) -> Result<CodeRequirementExpression<'a>, AppleCodesignError> {
+        if self.eat_word(&["apple"]) {
+            if self.eat_word(&["generic"]) {
+                return Ok(CodeRequirementExpression::AnchorAppleGeneric);
+            }
+
+            return match self.peek() {
+                Some(RequirementToken::String(name)) => {
+                    self.position += 1;
+                    Ok(CodeRequirementExpression::NamedAnchor(name.as_str().into()))
+                }
+                Some(RequirementToken::Word(name))
+                    if !["and", "or", "not"].contains(&name.as_str()) =>
+                {
+                    self.position += 1;
+                    Ok(CodeRequirementExpression::NamedAnchor(name.as_str().into()))
+                }
+                _ => Ok(CodeRequirementExpression::AnchorApple),
+            };
+        }
+
+        if self.eat_word(&["trusted"]) {
+            return Ok(CodeRequirementExpression::AnchorTrusted);
+        }
+
+        // `anchor` is shorthand for the root certificate. But `anchor <slot> H"..."`
+        // is also how anchor hashes of other slots are expressed.
+        let slot = match self.peek() {
+            Some(RequirementToken::Integer(_)) => self.parse_slot()?,
+            Some(RequirementToken::Word(w)) if w == "leaf" || w == "root" => self.parse_slot()?,
+            _ => -1,
+        };
+
+        self.parse_certificate(slot)
+    }
+
+    fn parse_slot(&mut self) -> Result<i32, AppleCodesignError> {
+        match self.next() {
+            Some(RequirementToken::Word(w)) if w == "leaf" => Ok(0),
+            Some(RequirementToken::Word(w)) if w == "root" || w == "anchor" => Ok(-1),
+            Some(RequirementToken::Integer(v)) => {
+                i32::try_from(*v).map_err(|_| requirement_syntax_error("invalid certificate slot"))
+            }
+            _ => {
+                self.position -= 1;
+                Err(self.error("certificate position"))
+            }
+        }
+    }
+
+    /// Parse what follows a certificate position.
+    fn parse_certificate(
+        &mut self,
+        slot: i32,
+    ) -> Result<CodeRequirementExpression<'a>, AppleCodesignError> {
+        if self.eat_word(&["trusted"]) {
+            return Ok(CodeRequirementExpression::CertificateTrusted(slot));
+        }
+
+        if self.eat_symbol(&["="]) || matches!(self.peek(), Some(RequirementToken::Hash(_))) {
+            return Ok(CodeRequirementExpression::AnchorCertificateHash(
+                slot,
+                self.parse_hash()?.into(),
+            ));
+        }
+
+        let index = self.position;
+        let field = self.parse_bracket()?;
+
+        let oid = || {
+            Oid(self.oids[index]
+                .as_deref()
+                .expect("OIDs of field tokens are encoded in advance"))
+        };
+
+        Ok(if field.starts_with("field.") {
+            CodeRequirementExpression::CertificateGeneric(slot, oid(), self.parse_match()?)
+        } else if field.starts_with("policy.") {
+            CodeRequirementExpression::CertificatePolicy(slot, oid(), self.parse_match()?)
+        } else if field.starts_with("timestamp.") {
+            CodeRequirementExpression::CertificateFieldDate(slot, oid(), self.parse_match()?)
+        } else {
+            CodeRequirementExpression::CertificateField(slot, field.into(), self.parse_match()?)
+        })
+    }
+
+    fn parse_hash(&mut self) -> Result<&'a [u8], AppleCodesignError> {
+        match self.next() {
+            Some(RequirementToken::Hash(data)) => Ok(data),
+            _ => {
+                self.position -= 1;
+                Err(self.error("H\"<hex>\" hash"))
+            }
+        }
+    }
+
+    fn parse_bracket(&mut self) -> Result<&'a str, AppleCodesignError> {
+        match self.next() {
+            Some(RequirementToken::Bracket(s)) => Ok(s),
+            _ => {
+                self.position -= 1;
+                Err(self.error("[<key>]"))
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<CodeRequirementValue<'a>, AppleCodesignError> {
+        match self.next() {
+            Some(RequirementToken::String(s) | RequirementToken::Word(s)) => {
+                Ok(CodeRequirementValue::String(s.as_str().into()))
+            }
+            Some(RequirementToken::Integer(v)) => {
+                Ok(CodeRequirementValue::String(Cow::Owned(v.to_string())))
+            }
+            Some(RequirementToken::Hash(data)) => Ok(CodeRequirementValue::Bytes(data.into())),
+            _ => {
+                self.position -= 1;
+                Err(self.error("value"))
+            }
+        }
+    }
+
+    fn parse_timestamp(&mut self) -> Result<chrono::DateTime<chrono::Utc>, AppleCodesignError> {
+        let value = match self.next() {
+            Some(RequirementToken::String(s)) => s,
+            _ => {
+                self.position -= 1;
+                return Err(self.error("timestamp string"));
+            }
+        };
+
+        if let Ok(t) = chrono::DateTime::parse_from_rfc3339(value) {
+            return Ok(t.with_timezone(&chrono::Utc));
+        }
+
+        chrono::NaiveDateTime::parse_from_str(value.trim_end_matches(" UTC"), "%Y-%m-%d %H:%M:%S")
+            .map(|t| chrono::Utc.from_utc_datetime(&t))
+            .map_err(|_| requirement_syntax_error(format!("invalid timestamp \"{value}\"")))
+    }
+
+    fn parse_match(&mut self) -> Result<CodeRequirementMatchExpression<'a>, AppleCodesignError> {
+        if self.eat_word(&["exists"]) {
+            return Ok(CodeRequirementMatchExpression::Exists);
+        }
+        if self.eat_word(&["absent"]) {
+            return Ok(CodeRequirementMatchExpression::Absent);
+        }
+
+        let op = match self.peek() {
+            Some(RequirementToken::Symbol(s)) if ["=", "~", "<", ">", "<=", ">="].contains(s) => *s,
+            // Field references without an operator test for existence.
+            _ => return Ok(CodeRequirementMatchExpression::Exists),
+        };
+        self.position += 1;
+
+        if self.eat_word(&["timestamp"]) {
+            let t = self.parse_timestamp()?;
+
+            return Ok(match op {
+                "=" => CodeRequirementMatchExpression::On(t),
+                "<" => CodeRequirementMatchExpression::Before(t),
+                ">" => CodeRequirementMatchExpression::After(t),
+                "<=" => CodeRequirementMatchExpression::OnOrBefore(t),
+                ">=" => CodeRequirementMatchExpression::OnOrAfter(t),
+                _ => {
+                    return Err(requirement_syntax_error(
+                        "timestamps can't be matched with ~",
+                    ))
+                }
+            });
+        }
+
+        let leading_star = op == "=" && self.eat_symbol(&["*"]);
+        let value = self.parse_value()?;
+        let trailing_star = op == "=" && self.eat_symbol(&["*"]);
+
+        Ok(match (op, leading_star, trailing_star) {
+            ("=", true, true) | ("~", _, _) => CodeRequirementMatchExpression::Contains(value),
+            ("=", true, false) => CodeRequirementMatchExpression::EndsWith(value),
+            ("=", false, true) => CodeRequirementMatchExpression::BeginsWith(value),
+            ("=", false, false) => CodeRequirementMatchExpression::Equal(value),
+            ("<", _, _) => CodeRequirementMatchExpression::LessThan(value),
+            (">", _, _) => CodeRequirementMatchExpression::GreaterThan(value),
+            ("<=", _, _) => CodeRequirementMatchExpression::LessThanEqual(value),
+            _ => CodeRequirementMatchExpression::GreaterThanEqual(value),
+        })
+    }
+}
+
+/// Compile code requirement language source to binary.
+///
+/// This is the inverse of formatting a [CodeRequirementExpression]. The source is a
+/// single requirement, such as `anchor apple generic and identifier "com.example"`,
+/// optionally prefixed with `designated =>`.
+///
+/// Returns the binary serialization of code requirements, without a blob header.
+/// This is the data accepted by [CodeRequirements::parse_binary()]. Use
+/// [DesignatedRequirement::from_requirement_text()] to obtain what `csreq -b` emits.
+pub fn compile_requirement_text(source: &str) -> Result<Vec<u8>, AppleCodesignError> {
+    let tokens = tokenize_requirement(source)?;
+
+    let oids = tokens
+        .iter()
+        .map(|token| match token {
+            RequirementToken::Bracket(field) => {
+                match ["field.", "policy.", "timestamp."]
+                    .iter()
+                    .find_map(|prefix| field.strip_prefix(prefix))
+                {
+                    Some(oid) => Oid::<Vec<u8>>::from_str(oid)
+                        .map(|oid| Some(oid.0))
+                        .map_err(|_| requirement_syntax_error(format!("invalid OID [{field}]"))),
+                    None => Ok(None),
+                }
+            }
+            _ => Ok(None),
+        })
+        .collect::<Result<Vec<_>, AppleCodesignError>>()?;
+
+    let mut parser = RequirementParser {
+        tokens: &tokens,
+        oids: &oids,
+        position: 0,
+        depth: 0,
+    };
+
+    if parser.peek_word(&["designated"]) {
+        parser.position += 1;
+        if !parser.eat_symbol(&["=>"]) {
+            return Err(parser.error("=>"));
+        }
+    }
+
+    let expr = parser.parse_expression()?;
+
+    if parser.peek().is_some() {
+        return Err(parser.error("end of requirement"));
+    }
+
+    let mut dest = vec![];
+    CodeRequirements::from(vec![expr]).write_to(&mut dest)?;
+
+    Ok(dest)
+}
+
+/// Properties of signed code that code requirements are evaluated against.
+#[derive(Clone, Debug, Default)]
+pub struct RequirementContext {
+    /// Signing identifier of the code.
+    pub identifier: Option<String>,
+
+    /// cdhashes of the code directories of the code.
+    pub cdhashes: Vec<Vec<u8>>,
+
+    /// Platform identifier recorded in the code directory.
+    pub platform: u32,
+
+    /// Certificate chain of the signer, leaf first and anchor last.
+    ///
+    /// Empty for ad-hoc signed code.
+    pub certificates: Vec<CapturedX509Certificate>,
+
+    /// The code's `Info.plist`.
+    pub info_plist: Option<plist::Dictionary>,
+
+    /// The code's entitlements.
+    pub entitlements: Option<plist::Dictionary>,
+}
+
+impl RequirementContext {
+    /// Resolve the certificate in a requirement certificate slot.
+    ///
+    /// 0 is the leaf, -1 the anchor. Other positive values count from the leaf
+    /// and negative values from the anchor.
+    fn certificate(&self, slot: i32) -> Option<&CapturedX509Certificate> {
+        let index = if slot < 0 {
+            self.certificates
+                .len()
+                .checked_sub(slot.unsigned_abs() as usize)?
+        } else {
+            slot as usize
+        };
+
+        self.certificates.get(index)
+    }
+}
+
+/// Resolve the OID of a distinguished name attribute in a certificate field name.
+fn certificate_field_oid(name: &str) -> Option<&'static [u8]> {
+    Some(match name {
+        "CN" => x509_certificate::rfc4519::OID_COMMON_NAME.0,
+        "C" => x509_certificate::rfc4519::OID_COUNTRY_NAME.0,
+        "L" => x509_certificate::rfc4519::OID_LOCALITY_NAME.0,
+        "ST" => x509_certificate::rfc4519::OID_STATE_PROVINCE_NAME.0,
+        "O" => x509_certificate::rfc4519::OID_ORGANIZATION_NAME.0,
+        "OU" => x509_certificate::rfc4519::OID_ORGANIZATIONAL_UNIT_NAME.0,
+        "emailAddress" => &[42, 134, 72, 134, 247, 13, 1, 9, 1],
+        "UID" => &[9, 146, 38, 137, 147, 242, 44, 100, 1, 1],
+        _ => return None,
+    })
+}
+
+/// Compare strings, treating runs of digits as numbers.
+///
+/// This mirrors how Apple compares values so `1.10` is greater than `1.9`.
+fn compare_numerically(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let take_number = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut digits = String::new();
+                    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+                        digits.push(c);
+                    }
+                    digits.trim_start_matches('0').to_string()
+                };
+
+                let (x, y) = (take_number(&mut a), take_number(&mut b));
+                match x.len().cmp(&y.len()).then_with(|| x.cmp(&y)) {
+                    Ordering::Equal => {}
+                    ordering => return ordering,
+                }
+            }
+            (Some(x), Some(y)) => {
+                match x.cmp(&y) {
+                    Ordering::Equal => {}
+                    ordering => return ordering,
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+impl<'a> CodeRequirementValue<'a> {
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::String(s) => s.as_bytes(),
+            Self::Bytes(data) => data,
+        }
+    }
+}
+
+impl<'a> CodeRequirementMatchExpression<'a> {
+    /// Evaluate whether a value satisfies this match expression.
+    ///
+    /// `value` is `None` if the value being matched doesn't exist.
+    pub fn evaluate(&self, value: Option<&[u8]>) -> Result<bool, AppleCodesignError> {
+        let value = match (self, value) {
+            (Self::Exists, value) => return Ok(value.is_some()),
+            (Self::Absent, value) => return Ok(value.is_none()),
+            (_, None) => return Ok(false),
+            (_, Some(value)) => value,
+        };
+
+        let compare = |expected: &CodeRequirementValue| {
+            compare_numerically(
+                &String::from_utf8_lossy(value),
+                &String::from_utf8_lossy(expected.as_bytes()),
+            )
+        };
+
+        Ok(match self {
+            Self::Equal(expected) => value == expected.as_bytes(),
+            Self::Contains(expected) => {
+                let expected = expected.as_bytes();
+                expected.is_empty() || value.windows(expected.len()).any(|x| x == expected)
+            }
+            Self::BeginsWith(expected) => value.starts_with(expected.as_bytes()),
+            Self::EndsWith(expected) => value.ends_with(expected.as_bytes()),
+            Self::LessThan(expected) => compare(expected) == Ordering::Less,
+            Self::GreaterThan(expected) => compare(expected) == Ordering::Greater,
+            Self::LessThanEqual(expected) => compare(expected) != Ordering::Greater,
+            Self::GreaterThanEqual(expected) => compare(expected) != Ordering::Less,
+            Self::On(_)
+            | Self::Before(_)
+            | Self::After(_)
+            | Self::OnOrBefore(_)
+            | Self::OnOrAfter(_) => {
+                return Err(AppleCodesignError::RequirementUnsupported(self.to_string()))
+            }
+            Self::Exists | Self::Absent => unreachable!(),
+        })
+    }
+}
+
+/// Obtain the value of a plist dictionary key as matched by code requirements.
+fn plist_requirement_value(dict: Option<&plist::Dictionary>, key: &str) -> Option<Vec<u8>> {
+    match dict?.get(key)? {
+        plist::Value::String(s) => Some(s.as_bytes().to_vec()),
+        plist::Value::Boolean(v) => Some(v.to_string().into_bytes()),
+        plist::Value::Integer(v) => Some(v.to_string().into_bytes()),
+        plist::Value::Data(v) => Some(v.clone()),
+        _ => Some(vec![]),
+    }
+}
+
+impl<'a> CodeRequirementExpression<'a> {
+    /// Evaluate whether code satisfies this requirement.
+    ///
+    /// Requirements are evaluated against the code's properties alone. So
+    /// expressions that consult system state, such as `anchor trusted`,
+    /// `notarized`, and named anchors, can't be evaluated and yield
+    /// [AppleCodesignError::RequirementUnsupported]. Apple anchors are
+    /// recognized from the certificates known to this crate. `anchor apple`
+    /// additionally requires the certificate issued by the anchor to be
+    /// Apple's own code signing certificate authority, as Apple does.
+    pub fn evaluate(&self, context: &RequirementContext) -> Result<bool, AppleCodesignError> {
+        let unsupported = || Err(AppleCodesignError::RequirementUnsupported(self.to_string()));
+
+        let anchor_is_apple = || {
+            context
+                .certificate(-1)
+                .map(|cert| cert.is_apple_root_ca())
+                .unwrap_or(false)
+        };
+
+        // Apple's own code is issued by this intermediate. Developer ID and
+        // other third party certificates chain to different intermediates.
+        let issued_by_apple_code_signing_ca = || -> Result<bool, AppleCodesignError> {
+            let name = match context.certificate(-2) {
+                Some(cert) => cert.subject_name(),
+                None => return Ok(false),
+            };
+
+            let attribute = |oid: &'static [u8]| {
+                name.find_first_attribute_string(Oid(bytes::Bytes::from_static(oid)))
+                    .map_err(AppleCodesignError::CertificateDecode)
+            };
+
+            Ok(
+                attribute(x509_certificate::rfc4519::OID_COMMON_NAME.0)?.as_deref()
+                    == Some("Apple Code Signing Certification Authority")
+                    && attribute(x509_certificate::rfc4519::OID_ORGANIZATION_NAME.0)?.as_deref()
+                        == Some("Apple Inc."),
+            )
+        };
+
+        match self {
+            Self::False => Ok(false),
+            Self::True => Ok(true),
+            Self::Identifier(value) => Ok(context.identifier.as_deref() == Some(value.as_ref())),
+            Self::AnchorApple => Ok(anchor_is_apple() && issued_by_apple_code_signing_ca()?),
+            Self::AnchorAppleGeneric => Ok(anchor_is_apple()),
+            Self::AnchorCertificateHash(slot, digest) => Ok(context
+                .certificate(*slot)
+                .map(|cert| {
+                    DigestType::Sha1
+                        .digest_data(cert.constructed_data())
+                        .map(|x| x == digest.as_ref())
+                })
+                .transpose()?
+                .unwrap_or(false)),
+            Self::InfoKeyValueLegacy(key, value) => {
+                Ok(
+                    plist_requirement_value(context.info_plist.as_ref(), key).as_deref()
+                        == Some(value.as_bytes()),
+                )
+            }
+            Self::And(a, b) => {
+                // Either side being false decides the result, even if the other
+                // side can't be evaluated.
+                let a = a.evaluate(context);
+                if matches!(a, Ok(false)) {
+                    return Ok(false);
+                }
+                if !b.evaluate(context)? {
+                    return Ok(false);
+                }
+
+                a
+            }
+            Self::Or(a, b) => {
+                let a = a.evaluate(context);
+                if matches!(a, Ok(true)) {
+                    return Ok(true);
+                }
+                if b.evaluate(context)? {
+                    return Ok(true);
+                }
+
+                a
+            }
+            Self::CodeDirectoryHash(digest) => Ok(context
+                .cdhashes
+                .iter()
+                .any(|x| x.as_slice() == digest.as_ref())),
+            Self::Not(expr) => Ok(!expr.evaluate(context)?),
+            Self::InfoPlistKeyField(key, m) => {
+                m.evaluate(plist_requirement_value(context.info_plist.as_ref(), key).as_deref())
+            }
+            Self::CertificateField(slot, field, m) => {
+                let cert = context.certificate(*slot);
+
+                let (name, attribute) = match field.split_once('.') {
+                    Some(("subject", attribute)) => {
+                        (cert.map(|cert| cert.subject_name()), attribute)
+                    }
+                    Some(("issuer", attribute)) => (cert.map(|cert| cert.issuer_name()), attribute),
+                    _ => return unsupported(),
+                };
+
+                let oid = match certificate_field_oid(attribute) {
+                    Some(oid) => Oid(bytes::Bytes::from_static(oid)),
+                    None => return unsupported(),
+                };
+
+                let value = match name {
+                    Some(name) => name
+                        .find_first_attribute_string(oid)
+                        .map_err(AppleCodesignError::CertificateDecode)?,
+                    None => None,
+                };
+
+                m.evaluate(value.as_deref().map(|x| x.as_bytes()))
+            }
+            Self::CertificateGeneric(slot, oid, m) => {
+                let value = context.certificate(*slot).and_then(|cert| {
+                    cert.iter_extensions()
+                        .find(|ext| ext.id.as_ref() == oid.as_ref())
+                        .map(|ext| ext.value.to_bytes())
+                });
+
+                m.evaluate(value.as_deref())
+            }
+            Self::CertificatePolicy(slot, oid, m) => {
+                if !matches!(
+                    m,
+                    CodeRequirementMatchExpression::Exists | CodeRequirementMatchExpression::Absent
+                ) {
+                    return unsupported();
+                }
+
+                let present = match context.certificate(*slot) {
+                    Some(cert) => certificate_has_policy(cert, oid.as_ref())?,
+                    None => false,
+                };
+
+                m.evaluate(if present { Some(&[]) } else { None })
+            }
+            Self::Platform(platform) => Ok(context.platform == *platform),
+            Self::EntitlementsKey(key, m) => {
+                m.evaluate(plist_requirement_value(context.entitlements.as_ref(), key).as_deref())
+            }
+            Self::CertificateTrusted(_)
+            | Self::AnchorTrusted
+            | Self::NamedAnchor(_)
+            | Self::NamedCode(_)
+            | Self::Notarized
+            | Self::CertificateFieldDate(..)
+            | Self::LegacyDeveloperId => unsupported(),
+        }
+    }
+}
+
+/// Whether a certificate's certificate policies extension contains a policy.
+fn certificate_has_policy(
+    cert: &CapturedX509Certificate,
+    policy: &[u8],
+) -> Result<bool, AppleCodesignError> {
+    // id-ce-certificatePolicies.
+    const OID_CERTIFICATE_POLICIES: &[u8] = &[85, 29, 32];
+
+    let ext = match cert
+        .iter_extensions()
+        .find(|ext| ext.id.as_ref() == OID_CERTIFICATE_POLICIES)
+    {
+        Some(ext) => ext,
+        None => return Ok(false),
+    };
+
+    let policies =
+        bcder::decode::Constructed::decode(ext.value.to_bytes(), bcder::Mode::Der, |cons| {
+            cons.take_sequence(|cons| {
+                let mut policies = vec![];
+
+                while let Some(oid) = cons.take_opt_sequence(|cons| {
+                    let oid = Oid::take_from(cons)?;
+                    // Policy qualifiers are irrelevant.
+                    cons.skip_all()?;
+
+                    Ok(oid)
+                })? {
+                    policies.push(oid);
+                }
+
+                Ok(policies)
+            })
+        })
+        .map_err(AppleCodesignError::CertificateDecode)?;
+
+    Ok(policies.iter().any(|oid| oid.as_ref() == policy))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -2047,4 +3375,469 @@ mod test {
         assert!(data.is_empty());
         verify_roundtrip(&els, &source);
     }
+
+    #[test]
+    fn parse_untrusted_nesting() {
+        fn nested_not(depth: usize) -> Vec<u8> {
+            let mut data = 1u32.to_be_bytes().to_vec();
+            for _ in 0..depth {
+                data.extend(9u32.to_be_bytes());
+            }
+            data.extend(1u32.to_be_bytes());
+
+            data
+        }
+
+        let source = nested_not(MAX_REQUIREMENT_EXPRESSION_DEPTH - 1);
+        let (els, data) = CodeRequirements::parse_binary(&source).unwrap();
+        assert!(data.is_empty());
+        verify_roundtrip(&els, &source);
+
+        assert!(matches!(
+            CodeRequirements::parse_binary(&nested_not(100_000)),
+            Err(AppleCodesignError::RequirementMalformed(_))
+        ));
+
+        // The depth is reset after failures.
+        assert!(CodeRequirements::parse_binary(&source).is_ok());
+
+        // Counts exceeding the data are rejected without allocating.
+        assert!(matches!(
+            CodeRequirements::parse_binary(&[0xff, 0xff, 0xff, 0xff, 0, 0, 0, 1]),
+            Err(AppleCodesignError::RequirementMalformed(_))
+        ));
+    }
+
+    #[test]
+    fn designated_requirement() -> Result<(), AppleCodesignError> {
+        // identifier "foo.bar" and anchor apple
+        let source =
+            hex::decode("00000001000000060000000200000007666f6f2e6261720000000003").unwrap();
+
+        let mut data = source.clone();
+        data.extend_from_slice(b"trailing");
+        let req = DesignatedRequirement::from_binary(&data)?;
+
+        assert_eq!(
+            req.expression()?,
+            CodeRequirementExpression::And(
+                Box::new(CodeRequirementExpression::Identifier("foo.bar".into())),
+                Box::new(CodeRequirementExpression::AnchorApple),
+            )
+        );
+        assert_eq!(
+            req.to_requirement_text()?,
+            "(identifier \"foo.bar\") and (anchor apple)"
+        );
+
+        let blob = req.to_blob_data()?;
+        assert_eq!(&blob[8..], source.as_slice());
+        assert_eq!(DesignatedRequirement::from_blob(&blob)?, req);
+
+        assert!(DesignatedRequirement::from_blob(&source).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn compile_text() -> Result<(), AppleCodesignError> {
+        let compile = DesignatedRequirement::from_requirement_text;
+
+        assert_eq!(
+            compile("designated => identifier \"foo.bar\" and anchor apple")?.expression()?,
+            CodeRequirementExpression::And(
+                Box::new(CodeRequirementExpression::Identifier("foo.bar".into())),
+                Box::new(CodeRequirementExpression::AnchorApple),
+            )
+        );
+
+        // Chains of `and` nest to the left like `csreq -b` output:
+        // and(and(identifier, anchor apple generic), certificate leaf[subject.OU]).
+        assert_eq!(
+            compile_requirement_text(
+                "identifier \"com.example.app\" and anchor apple generic \
+                and certificate leaf[subject.OU] = TEAM"
+            )?,
+            hex::decode(concat!(
+                "00000001000000060000000600000002",
+                "0000000f636f6d2e6578616d706c652e",
+                "617070000000000f0000000b00000000",
+                "0000000a7375626a6563742e4f550000",
+                "00000001000000045445414d",
+            ))
+            .unwrap()
+        );
+
+        // Produces the same data as what we derive for Developer ID signing.
+        #[cfg(feature = "full")]
+        {
+            let developer_id = crate::policy::requirement_developer_id(Some("TEAM"));
+            let mut expected = vec![];
+            CodeRequirements::from(vec![developer_id.clone()]).write_to(&mut expected)?;
+            assert_eq!(
+                compile_requirement_text(&developer_id.to_requirement_source())?,
+                expected
+            );
+        }
+
+        // `and` binds tighter than `or`.
+        assert_eq!(
+            compile("always or never and ! true")?.expression()?,
+            CodeRequirementExpression::Or(
+                Box::new(CodeRequirementExpression::True),
+                Box::new(CodeRequirementExpression::And(
+                    Box::new(CodeRequirementExpression::False),
+                    Box::new(CodeRequirementExpression::Not(Box::new(
+                        CodeRequirementExpression::True
+                    ))),
+                )),
+            )
+        );
+
+        assert_eq!(
+            compile("info[CFBundleVersion] = 1.* and entitlement[\"com.apple.foo\"] exists")?
+                .expression()?,
+            CodeRequirementExpression::And(
+                Box::new(CodeRequirementExpression::InfoPlistKeyField(
+                    "CFBundleVersion".into(),
+                    CodeRequirementMatchExpression::BeginsWith("1.".into()),
+                )),
+                Box::new(CodeRequirementExpression::EntitlementsKey(
+                    "com.apple.foo".into(),
+                    CodeRequirementMatchExpression::Exists,
+                )),
+            )
+        );
+
+        assert_eq!(
+            compile("anchor H\"0102\" or cdhash H\"ff\"")?.expression()?,
+            CodeRequirementExpression::Or(
+                Box::new(CodeRequirementExpression::AnchorCertificateHash(
+                    -1,
+                    [1u8, 2].as_ref().into()
+                )),
+                Box::new(CodeRequirementExpression::CodeDirectoryHash(
+                    [0xffu8].as_ref().into()
+                )),
+            )
+        );
+
+        // Expressions in source form compile back to the same thing.
+        for source in [
+            "platform 1 and notarized",
+            "anchor apple generic and certificate leaf[subject.CN] = \"Apple Development: Foo\"",
+            "anchor trusted or certificate 1 trusted or anchor apple \"named\"",
+            "certificate leaf[timestamp.1.2.3] < timestamp \"2022-01-01 00:00:00 UTC\"",
+            "certificate root[policy.1.2.3] ~ foo or info[Key] = *bar",
+            "(identifier a or identifier b) and not legacy",
+            "(\"named\") or (identifier a)",
+            "legacy info[CFBundleVersion] = \"1.0\" and legacy",
+        ] {
+            let req = compile(source)?;
+            let expr = req.expression()?;
+            assert_eq!(
+                compile(&expr.to_requirement_source())?.expression()?,
+                expr,
+                "{source}"
+            );
+        }
+
+        for source in [
+            "",
+            "identifier",
+            "anchor apple and",
+            "(always",
+            "always never",
+            "identifier \"unterminated",
+            "certificate leaf[field.x.y] exists",
+            "cdhash H\"zz\"",
+            "bogus",
+        ] {
+            assert!(
+                matches!(
+                    compile_requirement_text(source),
+                    Err(AppleCodesignError::RequirementSyntax(_))
+                ),
+                "{source}"
+            );
+        }
+
+        assert!(compile_requirement_text(&"(".repeat(10000)).is_err());
+
+        // Expressions without a dedicated keyword survive a round trip through source.
+        for expr in [
+            CodeRequirementExpression::NamedCode("always".into()),
+            CodeRequirementExpression::NamedCode("com.example \"code\"".into()),
+            CodeRequirementExpression::InfoKeyValueLegacy("CFBundleVersion".into(), "1.0".into()),
+            CodeRequirementExpression::InfoKeyValueLegacy("Key Name".into(), "value".into()),
+        ] {
+            let source = expr.to_requirement_source();
+            assert_eq!(compile(&source)?.expression()?, expr, "{source}");
+        }
+        assert_eq!(
+            compile("info[CFBundleVersion] = \"1.0\"")?.expression()?,
+            CodeRequirementExpression::InfoPlistKeyField(
+                "CFBundleVersion".into(),
+                CodeRequirementMatchExpression::Equal(CodeRequirementValue::String("1.0".into())),
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn requirement_source() -> Result<(), AppleCodesignError> {
+        // The form `codesign -d -r-` prints for Developer ID signed code.
+        let source = "identifier \"com.example.app\" and anchor apple generic and \
+            certificate 1[field.1.2.840.113635.100.6.2.6] /* exists */ and \
+            certificate leaf[field.1.2.840.113635.100.6.1.13] /* exists */ and \
+            certificate leaf[subject.OU] = SKMME9E2Y8";
+        assert_eq!(
+            DesignatedRequirement::from_requirement_text(source)?
+                .expression()?
+                .to_requirement_source(),
+            source
+        );
+
+        let a = || Box::new(CodeRequirementExpression::Identifier("a".into()));
+        let b = || Box::new(CodeRequirementExpression::Identifier("b".into()));
+        let c = || Box::new(CodeRequirementExpression::Notarized);
+
+        // Parentheses are only emitted when needed to preserve the tree.
+        for (expr, source) in [
+            (
+                CodeRequirementExpression::Or(
+                    a(),
+                    Box::new(CodeRequirementExpression::And(b(), c())),
+                ),
+                "identifier a or identifier b and notarized",
+            ),
+            (
+                CodeRequirementExpression::And(
+                    Box::new(CodeRequirementExpression::Or(a(), b())),
+                    c(),
+                ),
+                "(identifier a or identifier b) and notarized",
+            ),
+            (
+                CodeRequirementExpression::Or(
+                    Box::new(CodeRequirementExpression::Or(a(), b())),
+                    c(),
+                ),
+                "identifier a or identifier b or notarized",
+            ),
+            (
+                CodeRequirementExpression::Or(
+                    a(),
+                    Box::new(CodeRequirementExpression::Or(b(), c())),
+                ),
+                "identifier a or (identifier b or notarized)",
+            ),
+            (
+                CodeRequirementExpression::Not(Box::new(CodeRequirementExpression::And(a(), b()))),
+                "! (identifier a and identifier b)",
+            ),
+            (
+                CodeRequirementExpression::InfoPlistKeyField(
+                    "CFBundleVersion".into(),
+                    CodeRequirementMatchExpression::BeginsWith("1.".into()),
+                ),
+                "info[CFBundleVersion] = \"1.\"*",
+            ),
+            (
+                CodeRequirementExpression::EntitlementsKey(
+                    "com.apple.security.app-sandbox".into(),
+                    CodeRequirementMatchExpression::Equal("true".into()),
+                ),
+                "entitlement[\"com.apple.security.app-sandbox\"] = \"true\"",
+            ),
+            (
+                CodeRequirementExpression::AnchorCertificateHash(-1, [0xab_u8].as_ref().into()),
+                "certificate root = H\"ab\"",
+            ),
+            (
+                CodeRequirementExpression::CertificateField(
+                    2,
+                    "subject.CN".into(),
+                    CodeRequirementMatchExpression::Contains(CodeRequirementValue::Bytes(
+                        [0u8, 1].as_ref().into(),
+                    )),
+                ),
+                "certificate 2[subject.CN] ~ H\"0001\"",
+            ),
+            (
+                CodeRequirementExpression::Identifier("say \"hi\"".into()),
+                "identifier \"say \\\"hi\\\"\"",
+            ),
+            (
+                CodeRequirementExpression::Identifier("007".into()),
+                "identifier \"007\"",
+            ),
+        ] {
+            assert_eq!(expr.to_requirement_source(), source);
+
+            // And the source compiles back to the same expression.
+            let req = DesignatedRequirement::from_requirement_text(source)?;
+            assert_eq!(req.expression()?, expr, "{source}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_operands() -> Result<(), AppleCodesignError> {
+        // A Developer ID style designated requirement. As when compiled by
+        // `csreq`, the `and` chain is left associative.
+        let source = hex::decode(concat!(
+            "0000000100000006000000060000000600000006000000020000000f636f6d2e",
+            "6578616d706c652e617070000000000f0000000e000000010000000a2a864886",
+            "f763640602060000000000000000000e000000000000000a2a864886f7636406",
+            "010d0000000000000000000b000000000000000a7375626a6563742e4f550000",
+            "000000010000000a534b4d4d4539453259380000",
+        ))
+        .unwrap();
+
+        let (reqs, data) = CodeRequirements::parse_binary(&source)?;
+        assert!(data.is_empty());
+        verify_roundtrip(&reqs, &source);
+
+        // Flatten the left-associative `and` chain.
+        let mut terms = vec![];
+        let mut expr = &reqs[0];
+        while let CodeRequirementExpression::And(a, b) = expr {
+            terms.push(b.as_ref());
+            expr = a.as_ref();
+        }
+        terms.push(expr);
+        terms.reverse();
+
+        assert_eq!(terms.len(), 5);
+        assert_eq!(
+            terms[0],
+            &CodeRequirementExpression::Identifier("com.example.app".into())
+        );
+        assert_eq!(terms[1], &CodeRequirementExpression::AnchorAppleGeneric);
+        match terms[2] {
+            CodeRequirementExpression::CertificateGeneric(
+                1,
+                oid,
+                CodeRequirementMatchExpression::Exists,
+            ) => assert_eq!(oid.to_string(), "1.2.840.113635.100.6.2.6"),
+            expr => panic!("unexpected expression: {expr:?}"),
+        }
+        match terms[3] {
+            CodeRequirementExpression::CertificateGeneric(
+                0,
+                oid,
+                CodeRequirementMatchExpression::Exists,
+            ) => assert_eq!(oid.to_string(), "1.2.840.113635.100.6.1.13"),
+            expr => panic!("unexpected expression: {expr:?}"),
+        }
+        assert_eq!(
+            terms[4],
+            &CodeRequirementExpression::CertificateField(
+                0,
+                "subject.OU".into(),
+                CodeRequirementMatchExpression::Equal("SKMME9E2Y8".into()),
+            )
+        );
+
+        // Truncated operands are errors, not panics.
+        for end in 0..source.len() - 2 {
+            assert!(
+                CodeRequirements::parse_binary(&source[..end]).is_err(),
+                "{end}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn evaluate() -> Result<(), AppleCodesignError> {
+        let mut info_plist = plist::Dictionary::new();
+        info_plist.insert("CFBundleVersion".into(), "1.10".into());
+        let mut entitlements = plist::Dictionary::new();
+        entitlements.insert("com.apple.security.app-sandbox".into(), true.into());
+
+        let context = RequirementContext {
+            identifier: Some("com.example.app".into()),
+            cdhashes: vec![vec![0x42; 20]],
+            platform: 0,
+            certificates: vec![],
+            info_plist: Some(info_plist),
+            entitlements: Some(entitlements),
+        };
+
+        let evaluate = |source: &str| -> Result<bool, AppleCodesignError> {
+            DesignatedRequirement::from_requirement_text(source)?
+                .expression()?
+                .evaluate(&context)
+        };
+
+        for (source, expected) in [
+            (r#"identifier "com.example.app""#, true),
+            (r#"identifier "com.example.other""#, false),
+            (r#"identifier "com.example.app" and ! platform 1"#, true),
+            (&format!("cdhash H\"{}\"", "42".repeat(20)), true),
+            (&format!("cdhash H\"{}\"", "43".repeat(20)), false),
+            (r#"info[CFBundleVersion] >= "1.9""#, true),
+            (r#"info[CFBundleVersion] < "1.9""#, false),
+            (r#"info[CFBundleVersion] = "1."*"#, true),
+            (r#"info[CFBundleName] exists"#, false),
+            (
+                r#"entitlement["com.apple.security.app-sandbox"] = "true""#,
+                true,
+            ),
+            (r#"anchor apple generic"#, false),
+            (r#"certificate leaf[subject.CN] exists"#, false),
+            // Decided by the other operand even though `anchor trusted` can't be evaluated.
+            (r#"anchor trusted or identifier "com.example.app""#, true),
+            (
+                r#"anchor trusted and identifier "com.example.other""#,
+                false,
+            ),
+        ] {
+            assert_eq!(evaluate(source)?, expected, "{source}");
+        }
+
+        // A Developer ID chain is anchored by Apple but isn't Apple's own code.
+        let leaf = CapturedX509Certificate::from_der(
+            include_bytes!("testdata/apple-signed-developer-id-application.cer").to_vec(),
+        )?;
+        let developer_id = RequirementContext {
+            certificates: leaf.apple_root_certificate_chain().unwrap(),
+            ..context.clone()
+        };
+        assert!(developer_id.certificate(-1).unwrap().is_apple_root_ca());
+        for (source, expected) in [("anchor apple generic", true), ("anchor apple", false)] {
+            assert_eq!(
+                DesignatedRequirement::from_requirement_text(source)?
+                    .expression()?
+                    .evaluate(&developer_id)?,
+                expected,
+                "{source}"
+            );
+        }
+
+        assert!(matches!(
+            evaluate(r#"anchor trusted and identifier "com.example.app""#),
+            Err(AppleCodesignError::RequirementUnsupported(_))
+        ));
+        assert!(matches!(
+            evaluate("notarized"),
+            Err(AppleCodesignError::RequirementUnsupported(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn numeric_comparison() {
+        assert_eq!(compare_numerically("1.10", "1.9"), Ordering::Greater);
+        assert_eq!(compare_numerically("1.09", "1.9"), Ordering::Equal);
+        assert_eq!(compare_numerically("1.9a", "1.9b"), Ordering::Less);
+        assert_eq!(compare_numerically("2", "10"), Ordering::Less);
+        assert_eq!(compare_numerically("1.0", "1"), Ordering::Greater);
+    }
 }
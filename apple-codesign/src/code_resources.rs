@@ -26,12 +26,44 @@ use {
     plist::{Dictionary, Value},
     std::{
         cmp::Ordering,
-        collections::BTreeMap,
+        collections::{BTreeMap, BTreeSet, HashMap},
         io::Write,
         path::{Path, PathBuf},
+        time::SystemTime,
     },
+    unicode_normalization::UnicodeNormalization,
 };
 
+#[cfg(feature = "parallel-digests")]
+use rayon::prelude::*;
+
+/// Convert a path relative to a bundle to the string recorded in `CodeResources`.
+///
+/// `CodeResources` records paths as UTF-8 strings with `/` separators. Like
+/// Apple's tooling, names are recorded as they exist on disk, without changing
+/// their Unicode normalization form. Paths that aren't valid UTF-8 can't be
+/// represented and are rejected, as a lossy conversion would seal a file that
+/// doesn't exist.
+pub fn resource_path_string(path: impl AsRef<Path>) -> Result<String, AppleCodesignError> {
+    let path = path.as_ref();
+
+    let s = path
+        .to_str()
+        .ok_or_else(|| AppleCodesignError::ResourcesPathNotUtf8(path.to_path_buf()))?;
+
+    Ok(s.replace('\\', "/"))
+}
+
+/// Whether two `CodeResources` paths refer to the same file.
+///
+/// macOS filesystems don't distinguish names differing only in Unicode
+/// normalization. e.g. `é` as a single code point (NFC) and as `e` followed by a
+/// combining accent (NFD), as commonly found in zip files created on macOS.
+/// This comparison does the same.
+pub fn resource_paths_equivalent(a: &str, b: &str) -> bool {
+    a == b || a.nfc().eq(b.nfc())
+}
+
 #[derive(Clone, PartialEq)]
 enum FilesValue {
     Required(Vec<u8>),
@@ -397,6 +429,16 @@ impl TryFrom<&Value> for Rules2Value {
     type Error = AppleCodesignError;
 
     fn try_from(v: &Value) -> Result<Self, Self::Error> {
+        // Rules without any attributes are serialized as `<true/>`.
+        if let Value::Boolean(true) = v {
+            return Ok(Self {
+                nested: None,
+                omit: None,
+                optional: None,
+                weight: None,
+            });
+        }
+
         let dict = v.as_dictionary().ok_or_else(|| {
             AppleCodesignError::ResourcesPlistParse("rules2 value should be a dict".to_string())
         })?;
@@ -617,6 +659,8 @@ pub struct CodeResources {
     files2: BTreeMap<String, Files2Value>,
     rules: BTreeMap<String, RulesValue>,
     rules2: BTreeMap<String, Rules2Value>,
+    /// Recorded paths in `files` and `files2`, keyed by their NFC form.
+    normalized_paths: HashMap<String, BTreeSet<String>>,
 }
 
 impl CodeResources {
@@ -689,12 +733,86 @@ impl CodeResources {
             }
         }
 
-        Ok(Self {
-            files,
-            files2,
+        let mut res = Self {
+            files: BTreeMap::new(),
+            files2: BTreeMap::new(),
             rules,
             rules2,
-        })
+            normalized_paths: HashMap::new(),
+        };
+
+        for (path, value) in files {
+            res.insert_files(path, value);
+        }
+        for (path, value) in files2 {
+            res.insert_files2(path, value);
+        }
+
+        Ok(res)
+    }
+
+    fn insert_files(&mut self, path: String, value: FilesValue) {
+        self.index_path(&path);
+        self.files.insert(path, value);
+    }
+
+    fn insert_files2(&mut self, path: String, value: Files2Value) {
+        self.index_path(&path);
+        self.files2.insert(path, value);
+    }
+
+    fn index_path(&mut self, path: &str) {
+        self.normalized_paths
+            .entry(path.nfc().collect())
+            .or_default()
+            .insert(path.to_string());
+    }
+
+    /// Find the recorded path of the seal for a file.
+    ///
+    /// Paths are compared with [resource_paths_equivalent()], so a path from a
+    /// filesystem using a different Unicode normalization than the signer finds
+    /// its seal. Returns the path as recorded in this instance.
+    pub fn find_sealed_path(&self, path: &str) -> Option<&str> {
+        if let Some(key) = self
+            .files2
+            .get_key_value(path)
+            .map(|(key, _)| key)
+            .or_else(|| self.files.get_key_value(path).map(|(key, _)| key))
+        {
+            return Some(key.as_str());
+        }
+
+        self.normalized_paths
+            .get(&path.nfc().collect::<String>())
+            .and_then(|paths| paths.iter().next())
+            .map(|key| key.as_str())
+    }
+
+    /// Summarize the seal as `(version, rules, files)` like `codesign -d` does.
+    ///
+    /// Version 2 counts the `<rules2>` and `<files2>` sections and is reported
+    /// if `<rules2>` is present. Otherwise the version 1 sections are counted.
+    pub fn seal_summary(&self) -> (u32, usize, usize) {
+        if self.rules2.is_empty() {
+            (1, self.rules.len(), self.files.len())
+        } else {
+            (2, self.rules2.len(), self.files2.len())
+        }
+    }
+
+    /// Warn if a path is equivalent to but different from an already sealed path.
+    ///
+    /// Both files would be the same file on macOS.
+    fn warn_equivalent_path(&self, path: &str) {
+        if let Some(existing) = self.find_sealed_path(path) {
+            if existing != path {
+                warn!(
+                    "{} and {} differ only in Unicode normalization; macOS treats them as the same file",
+                    existing, path
+                );
+            }
+        }
     }
 
     /// Serialize an instance to XML.
@@ -759,11 +877,35 @@ impl CodeResources {
         content: impl AsRef<[u8]>,
         optional: bool,
     ) -> Result<(), AppleCodesignError> {
+        let content = content.as_ref();
+
+        self.seal_regular_file_digests(files_flavor, path, optional, |digest| {
+            digest.digest_data(content)
+        })
+    }
+
+    /// Seal a regular file given a function resolving its digests.
+    fn seal_regular_file_digests(
+        &mut self,
+        files_flavor: FilesFlavor,
+        path: impl ToString,
+        optional: bool,
+        digest: impl Fn(DigestType) -> Result<Vec<u8>, AppleCodesignError>,
+    ) -> Result<(), AppleCodesignError> {
+        let path = path.to_string();
+
+        if matches!(
+            files_flavor,
+            FilesFlavor::Rules2 | FilesFlavor::Rules2WithSha1
+        ) {
+            self.warn_equivalent_path(&path);
+        }
+
         match files_flavor {
             FilesFlavor::Rules => {
-                let digest = DigestType::Sha1.digest_data(content.as_ref())?;
-                self.files.insert(
-                    path.to_string(),
+                let digest = digest(DigestType::Sha1)?;
+                self.insert_files(
+                    path,
                     if optional {
                         FilesValue::Optional(digest)
                     } else {
@@ -774,10 +916,10 @@ impl CodeResources {
                 Ok(())
             }
             FilesFlavor::Rules2 => {
-                let hash2 = Some(DigestType::Sha256.digest_data(content.as_ref())?);
+                let hash2 = Some(digest(DigestType::Sha256)?);
 
-                self.files2.insert(
-                    path.to_string(),
+                self.insert_files2(
+                    path,
                     Files2Value {
                         cdhash: None,
                         hash: None,
@@ -791,11 +933,11 @@ impl CodeResources {
                 Ok(())
             }
             FilesFlavor::Rules2WithSha1 => {
-                let hash = Some(DigestType::Sha1.digest_data(content.as_ref())?);
-                let hash2 = Some(DigestType::Sha256.digest_data(content.as_ref())?);
+                let hash = Some(digest(DigestType::Sha1)?);
+                let hash2 = Some(digest(DigestType::Sha256)?);
 
-                self.files2.insert(
-                    path.to_string(),
+                self.insert_files2(
+                    path,
                     Files2Value {
                         cdhash: None,
                         hash,
@@ -811,12 +953,88 @@ impl CodeResources {
         }
     }
 
+    /// Seal a regular file using the digests recorded by another instance.
+    ///
+    /// This avoids reading and digesting the content of a file known to be
+    /// unchanged since `previous` was produced. Returns false, leaving this instance
+    /// untouched, if `previous` doesn't have a seal for `path` with the digests
+    /// needed by `files_flavor`. The caller should then call
+    /// [CodeResources::seal_regular_file].
+    pub fn reuse_regular_file_seal(
+        &mut self,
+        previous: &CodeResources,
+        files_flavor: FilesFlavor,
+        path: &str,
+        optional: bool,
+    ) -> bool {
+        match files_flavor {
+            FilesFlavor::Rules => {
+                let digest = match previous.files.get(path) {
+                    Some(FilesValue::Required(digest) | FilesValue::Optional(digest)) => {
+                        digest.clone()
+                    }
+                    None => return false,
+                };
+
+                self.insert_files(
+                    path.to_string(),
+                    if optional {
+                        FilesValue::Optional(digest)
+                    } else {
+                        FilesValue::Required(digest)
+                    },
+                );
+
+                true
+            }
+            FilesFlavor::Rules2 | FilesFlavor::Rules2WithSha1 => {
+                let Some(value) = previous.files2.get(path) else {
+                    return false;
+                };
+
+                // Only seals of regular files can be reused.
+                if value.cdhash.is_some() || value.symlink.is_some() || value.hash2.is_none() {
+                    return false;
+                }
+
+                let hash = if matches!(files_flavor, FilesFlavor::Rules2WithSha1) {
+                    if value.hash.is_none() {
+                        return false;
+                    }
+
+                    value.hash.clone()
+                } else {
+                    None
+                };
+
+                self.warn_equivalent_path(path);
+
+                self.insert_files2(
+                    path.to_string(),
+                    Files2Value {
+                        cdhash: None,
+                        hash,
+                        hash2: value.hash2.clone(),
+                        optional: if optional { Some(true) } else { None },
+                        requirement: None,
+                        symlink: None,
+                    },
+                );
+
+                true
+            }
+        }
+    }
+
     /// Seal a symlink file.
     ///
     /// `path` is the path of the symlink and `target` is the path it points to.
     pub fn seal_symlink(&mut self, path: impl ToString, target: impl ToString) {
-        self.files2.insert(
-            path.to_string(),
+        let path = path.to_string();
+        self.warn_equivalent_path(&path);
+
+        self.insert_files2(
+            path,
             Files2Value {
                 cdhash: None,
                 hash: None,
@@ -837,8 +1055,11 @@ impl CodeResources {
         info: &SignedMachOInfo,
         optional: bool,
     ) -> Result<(), AppleCodesignError> {
-        self.files2.insert(
-            path.to_string(),
+        let path = path.to_string();
+        self.warn_equivalent_path(&path);
+
+        self.insert_files2(
+            path,
             Files2Value {
                 cdhash: Some(DigestType::Sha256Truncated.digest_data(&info.code_directory_blob)?),
                 hash: None,
@@ -932,6 +1153,24 @@ enum RulesEvaluation {
     NoRule,
 }
 
+/// Digests of a regular file computed ahead of sealing it.
+#[derive(Clone, Debug)]
+struct RegularFileDigests(Vec<(DigestType, Vec<u8>)>);
+
+impl RegularFileDigests {
+    fn digest(&self, digest: DigestType) -> Result<Vec<u8>, AppleCodesignError> {
+        self.0
+            .iter()
+            .find(|(typ, _)| *typ == digest)
+            .map(|(_, data)| data.clone())
+            .ok_or_else(|| {
+                AppleCodesignError::LogicError(format!(
+                    "{digest:?} digest of resource file not computed"
+                ))
+            })
+    }
+}
+
 /// Interface for constructing a `CodeResources` instance.
 ///
 /// This type is used during bundle signing to construct a `CodeResources` instance.
@@ -943,6 +1182,8 @@ pub struct CodeResourcesBuilder {
     rules2: Vec<CodeResourcesRule>,
     resources: CodeResources,
     digests: Vec<DigestType>,
+    previous: Option<(CodeResources, SystemTime)>,
+    file_digests: HashMap<PathBuf, RegularFileDigests>,
 }
 
 impl Default for CodeResourcesBuilder {
@@ -952,6 +1193,8 @@ impl Default for CodeResourcesBuilder {
             rules2: vec![],
             resources: CodeResources::default(),
             digests: vec![DigestType::Sha256],
+            previous: None,
+            file_digests: HashMap::new(),
         }
     }
 }
@@ -1048,11 +1291,204 @@ impl CodeResourcesBuilder {
         Ok(slf)
     }
 
+    /// Obtain an instance with the default rules for a given bundle.
+    ///
+    /// The rules for bundles with a `Resources/` directory are used if the bundle
+    /// has one or isn't shallow. Exclusion rules are added for files written by
+    /// signing, namely `_CodeSignature/` and a stapled notarization ticket.
+    pub fn for_bundle(bundle: &DirectoryBundle) -> Result<Self, AppleCodesignError> {
+        // The set of rules to use is determined by whether the bundle *can* have a
+        // `Resources/`, not whether it necessarily does. The exact rules for this are not
+        // known. Essentially we want to test for the result of CFBundleCopyResourcesDirectoryURL().
+        // We assume that we can use the resources rules when there is a `Resources` directory
+        // (this seems obvious!) or when the bundle isn't shallow, as a non-shallow bundle should
+        // be an app bundle and app bundles can always have resources (we think).
+        let mut slf = if bundle.resolve_path("Resources").is_dir() || !bundle.shallow() {
+            Self::default_resources_rules()?
+        } else {
+            Self::default_no_resources_rules()?
+        };
+
+        // Exclude code signature files we'll write.
+        slf.add_exclusion_rule(CodeResourcesRule::new("^_CodeSignature/")?.exclude());
+        // Ignore notarization ticket.
+        slf.add_exclusion_rule(CodeResourcesRule::new("^CodeResources$")?.exclude());
+
+        Ok(slf)
+    }
+
     /// Set the digests to record in this instance.
     pub fn set_digests(&mut self, digests: impl Iterator<Item = DigestType>) {
         self.digests = digests.collect::<Vec<_>>();
     }
 
+    /// Register the resources sealed by a previous signing operation.
+    ///
+    /// Regular files last modified before `sealed_at` are assumed unchanged and
+    /// reuse their digests from `previous` instead of being read and digested
+    /// again. This makes re-sealing a bundle after changes to a few files much
+    /// faster. `sealed_at` is typically the modification time of the previous
+    /// `CodeResources` file.
+    ///
+    /// Files changed without updating their modification time will be sealed
+    /// with stale digests. So only use this when modification times are
+    /// trustworthy.
+    pub fn set_previous_resources(&mut self, previous: CodeResources, sealed_at: SystemTime) {
+        self.previous = Some((previous, sealed_at));
+    }
+
+    /// Resolve previously sealed resources usable for a file.
+    ///
+    /// Returns `None` if there are no previous resources or the file may have
+    /// changed since they were sealed.
+    fn previous_resources_for<'a>(
+        previous: &'a Option<(CodeResources, SystemTime)>,
+        file: &DirectoryBundleFile,
+    ) -> Option<&'a CodeResources> {
+        let (previous, sealed_at) = previous.as_ref()?;
+
+        let modified = std::fs::metadata(file.absolute_path())
+            .and_then(|metadata| metadata.modified())
+            .ok()?;
+
+        if modified < *sealed_at {
+            Some(previous)
+        } else {
+            None
+        }
+    }
+
+    /// Digest regular files in parallel ahead of processing them.
+    ///
+    /// Files sealed as regular files by the rules are read and digested using
+    /// `threads` threads, or one per CPU if `None`. [Self::process_file()] then
+    /// uses these digests instead of reading the files itself. Since files are
+    /// still processed one at a time and in order, the produced `CodeResources`
+    /// content doesn't depend on the number of threads.
+    ///
+    /// Without the `parallel-digests` feature, files are digested one at a time
+    /// and `threads` is ignored.
+    ///
+    /// Only the digests the seals need are computed: SHA-256 plus SHA-1, if it is
+    /// one of the configured digests, for `<files2>` and SHA-1 for `<files>`.
+    ///
+    /// Files whose seals can be reused from previous resources aren't digested.
+    /// Neither are files sealed as nested code, as they are signed before being
+    /// sealed.
+    pub fn digest_files(
+        &mut self,
+        files: &[DirectoryBundleFile],
+        threads: Option<usize>,
+    ) -> Result<(), AppleCodesignError> {
+        let mut paths = vec![];
+
+        for file in files {
+            let symlink_target = file
+                .symlink_target()
+                .map_err(AppleCodesignError::DirectoryBundle)?;
+
+            if symlink_target.is_some()
+                || file
+                    .is_main_executable()
+                    .map_err(AppleCodesignError::DirectoryBundle)?
+                || Self::previous_resources_for(&self.previous, file).is_some()
+            {
+                continue;
+            }
+
+            let mut digests = vec![];
+
+            match Self::evaluate_rules(&self.rules2, file.relative_path(), None)? {
+                RulesEvaluation::SealNested(..) => continue,
+                RulesEvaluation::SealRegularFile(..) => {
+                    digests.push(DigestType::Sha256);
+
+                    if self.digests.contains(&DigestType::Sha1) {
+                        digests.push(DigestType::Sha1);
+                    }
+                }
+                _ => {}
+            }
+
+            if matches!(
+                Self::evaluate_rules(&self.rules, file.relative_path(), None)?,
+                RulesEvaluation::SealRegularFile(..)
+            ) && !digests.contains(&DigestType::Sha1)
+            {
+                digests.push(DigestType::Sha1);
+            }
+
+            if !digests.is_empty() {
+                paths.push((file.absolute_path().to_path_buf(), digests));
+            }
+        }
+
+        let digest =
+            |(path, digests): &(PathBuf, Vec<DigestType>)| -> Result<_, AppleCodesignError> {
+                let data = std::fs::read(path)?;
+
+                Ok((
+                    path.clone(),
+                    RegularFileDigests(
+                        digests
+                            .iter()
+                            .map(|digest| Ok((*digest, digest.digest_data(&data)?)))
+                            .collect::<Result<Vec<_>, AppleCodesignError>>()?,
+                    ),
+                ))
+            };
+
+        #[cfg(feature = "parallel-digests")]
+        let digests = if let Some(threads) = threads {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .map_err(|e| {
+                    AppleCodesignError::LogicError(format!("failed to create thread pool: {e}"))
+                })?
+                .install(|| paths.par_iter().map(digest).collect::<Result<Vec<_>, _>>())?
+        } else {
+            paths
+                .par_iter()
+                .map(digest)
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        #[cfg(not(feature = "parallel-digests"))]
+        let digests = {
+            let _ = threads;
+
+            paths.iter().map(digest).collect::<Result<Vec<_>, _>>()?
+        };
+
+        self.file_digests.extend(digests);
+
+        Ok(())
+    }
+
+    /// Seal a regular file, using its digests from [Self::digest_files()] if available.
+    fn seal_regular_file(
+        &mut self,
+        files_flavor: FilesFlavor,
+        file: &DirectoryBundleFile,
+        relative_path: String,
+        optional: bool,
+    ) -> Result<(), AppleCodesignError> {
+        if let Some(digests) = self.file_digests.get(file.absolute_path()) {
+            self.resources.seal_regular_file_digests(
+                files_flavor,
+                relative_path,
+                optional,
+                |digest| digests.digest(digest),
+            )
+        } else {
+            let data = std::fs::read(file.absolute_path())?;
+
+            self.resources
+                .seal_regular_file(files_flavor, relative_path, data, optional)
+        }
+    }
+
     /// Add a rule to this instance in the `<rules>` section.
     pub fn add_rule(&mut self, rule: CodeResourcesRule) {
         self.rules.push(rule.clone());
@@ -1090,39 +1526,23 @@ impl CodeResourcesBuilder {
     /// Directories are special. If an exclusion rule matches a directory, that directory
     /// tree should be ignored. There are also default rules for handling nested bundles.
     /// These rules take precedence over directory exclusion rules.
+    ///
+    /// Rules may be written in a different Unicode normalization than names on disk.
+    /// So a rule matches if it matches the path in any normalization form.
     fn find_rule(rules: &[CodeResourcesRule], path: &str) -> Option<CodeResourcesRule> {
-        let parts = path.split('/').collect::<Vec<_>>();
+        let mut paths = vec![path.to_string()];
+        for normalized in [path.nfc().collect::<String>(), path.nfd().collect()] {
+            if !paths.contains(&normalized) {
+                paths.push(normalized);
+            }
+        }
 
         let mut exclude_override = false;
 
         let rule = rules.iter().find(|rule| {
-            // Nested rules matching leaf-most directory with `.` result in match.
-            // But we treat as exclusion, as these are treated as nested bundles,
-            // which are handled externally.
-            if rule.nested {
-                for last_part in 1..parts.len() - 1 {
-                    let parent = parts[0..last_part].join("/");
-
-                    if rule.re.is_match(&parent) && parts[last_part - 1].contains('.') {
-                        exclude_override = true;
-                        return true;
-                    }
-                }
-            }
-
-            // Directory exclusions match entire directory tree. So walk the parents and yield
-            // this rule if matches.
-            if rule.exclude {
-                for last_part in 1..parts.len() - 1 {
-                    let parent = parts[0..last_part].join("/");
-
-                    if rule.re.is_match(&parent) {
-                        return true;
-                    }
-                }
-            }
-
-            rule.re.is_match(path)
+            paths
+                .iter()
+                .any(|path| Self::rule_matches(rule, path, &mut exclude_override))
         });
 
         if let Some(rule) = rule {
@@ -1138,13 +1558,48 @@ impl CodeResourcesBuilder {
         }
     }
 
+    /// Whether a rule matches a path.
+    ///
+    /// `exclude_override` is set if the match should be treated as an exclusion.
+    fn rule_matches(rule: &CodeResourcesRule, path: &str, exclude_override: &mut bool) -> bool {
+        let parts = path.split('/').collect::<Vec<_>>();
+
+        // Nested rules matching leaf-most directory with `.` result in match.
+        // But we treat as exclusion, as these are treated as nested bundles,
+        // which are handled externally.
+        if rule.nested {
+            for last_part in 1..parts.len() - 1 {
+                let parent = parts[0..last_part].join("/");
+
+                if rule.re.is_match(&parent) && parts[last_part - 1].contains('.') {
+                    *exclude_override = true;
+                    return true;
+                }
+            }
+        }
+
+        // Directory exclusions match entire directory tree. So walk the parents and yield
+        // this rule if matches.
+        if rule.exclude {
+            for last_part in 1..parts.len() - 1 {
+                let parent = parts[0..last_part].join("/");
+
+                if rule.re.is_match(&parent) {
+                    return true;
+                }
+            }
+        }
+
+        rule.re.is_match(path)
+    }
+
     fn evaluate_rules(
         rules: &[CodeResourcesRule],
         relative_path: impl AsRef<Path>,
         symlink_target: Option<PathBuf>,
     ) -> Result<RulesEvaluation, AppleCodesignError> {
         // Always use UNIX style directory separators.
-        let relative_path = relative_path.as_ref().to_string_lossy().replace('\\', "/");
+        let relative_path = resource_path_string(relative_path)?;
 
         // The Contents/ prefix is also removed for pattern matching and references in the
         // resources file.
@@ -1153,7 +1608,8 @@ impl CodeResourcesBuilder {
             .unwrap_or(&relative_path)
             .to_string();
 
-        match Self::find_rule(rules, relative_path.as_ref()) {
+        // Sealed paths retain the on-disk Unicode normalization.
+        match Self::find_rule(rules, &relative_path) {
             Some(rule) => {
                 debug!(
                     "{} matches {} rule {}",
@@ -1175,7 +1631,7 @@ impl CodeResourcesBuilder {
                     // been processed by the nested bundle.
                     Ok(RulesEvaluation::Exclude)
                 } else if let Some(target) = symlink_target {
-                    let target = target.to_string_lossy().replace('\\', "/");
+                    let target = resource_path_string(target)?;
 
                     Ok(RulesEvaluation::SealSymlink(relative_path, target))
                 } else if rule.nested {
@@ -1233,17 +1689,26 @@ impl CodeResourcesBuilder {
                     .seal_macho(relative_path, &macho_info, optional)
             }
             RulesEvaluation::SealRegularFile(relative_path, optional) => {
-                info!("sealing regular file {}", relative_path);
-                let data = std::fs::read(file.absolute_path())?;
-
                 let flavor = if self.digests.contains(&DigestType::Sha1) {
                     FilesFlavor::Rules2WithSha1
                 } else {
                     FilesFlavor::Rules2
                 };
 
-                self.resources
-                    .seal_regular_file(flavor, relative_path, data, optional)?;
+                if let Some(previous) = Self::previous_resources_for(&self.previous, file) {
+                    if self.resources.reuse_regular_file_seal(
+                        previous,
+                        flavor,
+                        &relative_path,
+                        optional,
+                    ) {
+                        info!("reusing seal of unchanged regular file {}", relative_path);
+                        return file_handler.install_file(file);
+                    }
+                }
+
+                info!("sealing regular file {}", relative_path);
+                self.seal_regular_file(flavor, file, relative_path, optional)?;
                 file_handler.install_file(file)
             }
         }
@@ -1267,10 +1732,18 @@ impl CodeResourcesBuilder {
             | RulesEvaluation::SealSymlink(..)
             | RulesEvaluation::SealNested(..) => Ok(()),
             RulesEvaluation::SealRegularFile(relative_path, optional) => {
-                let data = std::fs::read(file.absolute_path())?;
+                if let Some(previous) = Self::previous_resources_for(&self.previous, file) {
+                    if self.resources.reuse_regular_file_seal(
+                        previous,
+                        FilesFlavor::Rules,
+                        &relative_path,
+                        optional,
+                    ) {
+                        return Ok(());
+                    }
+                }
 
-                self.resources
-                    .seal_regular_file(FilesFlavor::Rules, relative_path, data, optional)
+                self.seal_regular_file(FilesFlavor::Rules, file, relative_path, optional)
             }
         }
     }
@@ -1288,6 +1761,44 @@ impl CodeResourcesBuilder {
         self.process_file_rules(file)
     }
 
+    /// Walk the files of a bundle and process them for resource handling.
+    ///
+    /// Files are digested with [Self::digest_files()] using `threads` and then
+    /// fed through [Self::process_file()] in order. The bundle's main executable
+    /// is skipped, as it is signed with the sealed resources and not sealed by
+    /// them.
+    ///
+    /// Files of nested bundles are traversed and evaluated against the rules like
+    /// any other file. Nested bundles themselves still need to be registered with
+    /// [Self::process_nested_bundle()] after they are signed.
+    pub fn seal_bundle_files(
+        &mut self,
+        bundle: &DirectoryBundle,
+        file_handler: &dyn BundleFileHandler,
+        threads: Option<usize>,
+    ) -> Result<(), AppleCodesignError> {
+        let files = bundle
+            .files(true)
+            .map_err(AppleCodesignError::DirectoryBundle)?;
+
+        // Reading and digesting files dominates sealing large bundles. So do that
+        // in parallel up front. Files are still processed in order below.
+        self.digest_files(&files, threads)?;
+
+        for file in files {
+            if file
+                .is_main_executable()
+                .map_err(AppleCodesignError::DirectoryBundle)?
+            {
+                continue;
+            }
+
+            self.process_file(&file, file_handler)?;
+        }
+
+        Ok(())
+    }
+
     /// Process a nested bundle for inclusion in resource handling.
     ///
     /// This will attempt to seal the main digest of the bundle into this resources file.
@@ -1344,6 +1855,11 @@ impl CodeResourcesBuilder {
         Ok(())
     }
 
+    /// Obtain the resources sealed so far.
+    pub fn resources(&self) -> &CodeResources {
+        &self.resources
+    }
+
     /// Write CodeResources XML content to a writer.
     pub fn write_code_resources(&self, writer: impl Write) -> Result<(), AppleCodesignError> {
         self.resources.to_writer_xml(writer)
@@ -1453,4 +1969,296 @@ mod tests {
 
         assert_eq!(resources, resources2);
     }
+
+    const NFC: &str = "Resources/caf\u{e9}.txt";
+    const NFD: &str = "Resources/cafe\u{301}.txt";
+
+    #[test]
+    fn resource_paths() -> Result<(), AppleCodesignError> {
+        assert_eq!(
+            resource_path_string(Path::new("Contents\\Resources\\file"))?,
+            "Contents/Resources/file"
+        );
+        assert_eq!(resource_path_string(Path::new(NFD))?, NFD);
+
+        assert!(resource_paths_equivalent(NFC, NFD));
+        assert!(!resource_paths_equivalent(NFC, "Resources/cafe.txt"));
+
+        #[cfg(unix)]
+        {
+            use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+            assert!(matches!(
+                resource_path_string(Path::new(OsStr::from_bytes(b"Resources/\xff"))),
+                Err(AppleCodesignError::ResourcesPathNotUtf8(_))
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn normalization_insensitive_rules() -> Result<(), AppleCodesignError> {
+        let rules = vec![
+            CodeResourcesRule::new("^Resources/caf\u{e9}\\.txt$")?.omit(),
+            CodeResourcesRule::new("^Resources/")?,
+        ];
+
+        // Rules match regardless of normalization. The on-disk form is sealed.
+        for path in [NFC, NFD] {
+            assert!(matches!(
+                CodeResourcesBuilder::evaluate_rules(&rules, format!("Contents/{path}"), None)?,
+                RulesEvaluation::Omit
+            ));
+        }
+        assert!(matches!(
+            CodeResourcesBuilder::evaluate_rules(&rules[1..], format!("Contents/{NFD}"), None)?,
+            RulesEvaluation::SealRegularFile(path, false) if path == NFD
+        ));
+
+        let mut resources = CodeResources::default();
+        resources.seal_regular_file(FilesFlavor::Rules2, NFD, b"content", false)?;
+        assert_eq!(resources.find_sealed_path(NFC), Some(NFD));
+        assert_eq!(resources.find_sealed_path("Resources/other"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reuse_seals() -> Result<(), AppleCodesignError> {
+        // Previous resources are typically parsed from our own output.
+        let mut xml = vec![];
+        CodeResourcesBuilder::default_resources_rules()?.write_code_resources(&mut xml)?;
+        let mut previous = CodeResources::from_xml(&xml)?;
+
+        previous.seal_regular_file(FilesFlavor::Rules, "file", b"content", false)?;
+        previous.seal_regular_file(FilesFlavor::Rules2, "sha256", b"content", false)?;
+        previous.seal_regular_file(FilesFlavor::Rules2WithSha1, "both", b"content", true)?;
+        previous.seal_symlink("link", "file");
+
+        let mut expected = CodeResources::default();
+        expected.seal_regular_file(FilesFlavor::Rules, "file", b"content", true)?;
+        expected.seal_regular_file(FilesFlavor::Rules2, "sha256", b"content", false)?;
+        expected.seal_regular_file(FilesFlavor::Rules2WithSha1, "both", b"content", false)?;
+
+        let mut resources = CodeResources::default();
+        assert!(resources.reuse_regular_file_seal(&previous, FilesFlavor::Rules, "file", true));
+        assert!(resources.reuse_regular_file_seal(&previous, FilesFlavor::Rules2, "sha256", false));
+        assert!(resources.reuse_regular_file_seal(
+            &previous,
+            FilesFlavor::Rules2WithSha1,
+            "both",
+            false
+        ));
+        assert_eq!(resources, expected);
+
+        // Seals lacking a needed digest or not of regular files can't be reused.
+        assert!(!resources.reuse_regular_file_seal(
+            &previous,
+            FilesFlavor::Rules2WithSha1,
+            "sha256",
+            false
+        ));
+        assert!(!resources.reuse_regular_file_seal(&previous, FilesFlavor::Rules2, "link", false));
+        assert!(!resources.reuse_regular_file_seal(
+            &previous,
+            FilesFlavor::Rules2,
+            "missing",
+            false
+        ));
+        assert_eq!(resources, expected);
+
+        Ok(())
+    }
+
+    struct InstallNothing;
+
+    impl BundleFileHandler for InstallNothing {
+        fn install_file(&self, _: &DirectoryBundleFile) -> Result<(), AppleCodesignError> {
+            Ok(())
+        }
+
+        fn sign_and_install_macho(
+            &self,
+            _: &DirectoryBundleFile,
+        ) -> Result<SignedMachOInfo, AppleCodesignError> {
+            Err(AppleCodesignError::LogicError("unexpected Mach-O".into()))
+        }
+    }
+
+    #[test]
+    fn parallel_digests() -> Result<(), AppleCodesignError> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().join("App.app");
+        std::fs::create_dir_all(root.join("Resources/en.lproj"))?;
+        std::fs::write(
+            root.join("Info.plist"),
+            br#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><dict><key>CFBundleIdentifier</key><string>com.example.app</string></dict></plist>"#,
+        )?;
+        for i in 0..64 {
+            std::fs::write(
+                root.join("Resources").join(format!("file{i}")),
+                format!("content {i}"),
+            )?;
+        }
+        std::fs::write(root.join("Resources/en.lproj/strings"), b"strings")?;
+
+        let bundle =
+            DirectoryBundle::new_from_path(&root).map_err(AppleCodesignError::DirectoryBundle)?;
+        let files = bundle
+            .files(true)
+            .map_err(AppleCodesignError::DirectoryBundle)?;
+
+        let seal = |threads: Option<Option<usize>>| -> Result<Vec<u8>, AppleCodesignError> {
+            let mut builder = CodeResourcesBuilder::default_resources_rules()?;
+            builder.set_digests([DigestType::Sha1, DigestType::Sha256].into_iter());
+
+            if let Some(threads) = threads {
+                builder.digest_files(&files, threads)?;
+                assert_eq!(builder.file_digests.len(), 65);
+            }
+
+            for file in &files {
+                builder.process_file(file, &InstallNothing)?;
+            }
+
+            let mut xml = vec![];
+            builder.write_code_resources(&mut xml)?;
+
+            Ok(xml)
+        };
+
+        let sequential = seal(None)?;
+        assert_eq!(seal(Some(Some(1)))?, sequential);
+        assert_eq!(seal(Some(Some(4)))?, sequential);
+        assert_eq!(seal(Some(None))?, sequential);
+
+        Ok(())
+    }
+
+    #[test]
+    fn digest_files_selection() -> Result<(), AppleCodesignError> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().join("App.app");
+        std::fs::create_dir_all(root.join("Resources"))?;
+        std::fs::create_dir_all(root.join("MacOS"))?;
+        std::fs::create_dir_all(root.join("data"))?;
+        std::fs::write(
+            root.join("Info.plist"),
+            br#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><dict><key>CFBundleIdentifier</key><string>com.example.app</string></dict></plist>"#,
+        )?;
+        std::fs::write(root.join("Resources/file"), b"resource")?;
+        std::fs::write(root.join("data/other"), b"other")?;
+        std::fs::write(root.join("MacOS/tool"), b"tool")?;
+
+        let bundle =
+            DirectoryBundle::new_from_path(&root).map_err(AppleCodesignError::DirectoryBundle)?;
+        let files = bundle
+            .files(true)
+            .map_err(AppleCodesignError::DirectoryBundle)?;
+
+        let mut builder = CodeResourcesBuilder::default_resources_rules()?;
+        builder.digest_files(&files, Some(1))?;
+
+        let digests = |path: &str| {
+            builder
+                .file_digests
+                .get(&root.join(path))
+                .map(|digests| digests.0.iter().map(|(typ, _)| *typ).collect::<Vec<_>>())
+        };
+
+        // `<files>` always records SHA-1. `<files2>` only does if configured.
+        assert_eq!(
+            digests("Resources/file"),
+            Some(vec![DigestType::Sha256, DigestType::Sha1])
+        );
+        assert_eq!(digests("data/other"), Some(vec![DigestType::Sha256]));
+        // Nested code is signed before being sealed.
+        assert_eq!(digests("MacOS/tool"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn seal_bundle() -> Result<(), AppleCodesignError> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().join("App.app");
+        std::fs::create_dir_all(root.join("Resources/en.lproj"))?;
+        std::fs::create_dir_all(root.join("_CodeSignature"))?;
+        std::fs::write(
+            root.join("Info.plist"),
+            br#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><dict><key>CFBundleIdentifier</key><string>com.example.app</string></dict></plist>"#,
+        )?;
+        std::fs::write(root.join("PkgInfo"), b"APPL????")?;
+        std::fs::write(root.join("Resources/file"), b"content")?;
+        std::fs::write(root.join("Resources/.DS_Store"), b"junk")?;
+        std::fs::write(root.join("Resources/en.lproj/strings"), b"strings")?;
+        std::fs::write(
+            root.join("Resources/en.lproj/locversion.plist"),
+            b"locversion",
+        )?;
+        std::fs::write(root.join("_CodeSignature/CodeResources"), b"stale")?;
+        std::fs::write(root.join("CodeResources"), b"ticket")?;
+
+        let bundle =
+            DirectoryBundle::new_from_path(&root).map_err(AppleCodesignError::DirectoryBundle)?;
+
+        let mut builder = CodeResourcesBuilder::for_bundle(&bundle)?;
+        builder.set_digests([DigestType::Sha1, DigestType::Sha256].into_iter());
+        builder.seal_bundle_files(&bundle, &InstallNothing, Some(2))?;
+
+        let resources = builder.resources();
+
+        // <rules> seals resources with SHA-1 digests.
+        assert_eq!(
+            resources.files.keys().collect::<Vec<_>>(),
+            vec![
+                "Resources/.DS_Store",
+                "Resources/en.lproj/strings",
+                "Resources/file"
+            ]
+        );
+        assert_eq!(
+            resources.files.get("Resources/en.lproj/strings"),
+            Some(&FilesValue::Optional(
+                DigestType::Sha1.digest_data(b"strings")?
+            ))
+        );
+        assert_eq!(
+            resources.files.get("Resources/file"),
+            Some(&FilesValue::Required(
+                DigestType::Sha1.digest_data(b"content")?
+            ))
+        );
+
+        // <rules2> seals everything not omitted or excluded with both digests.
+        assert_eq!(
+            resources.files2.keys().collect::<Vec<_>>(),
+            vec!["Resources/en.lproj/strings", "Resources/file"]
+        );
+        let strings = resources.files2.get("Resources/en.lproj/strings").unwrap();
+        assert_eq!(
+            strings.hash,
+            Some(DigestType::Sha1.digest_data(b"strings")?)
+        );
+        assert_eq!(
+            strings.hash2,
+            Some(DigestType::Sha256.digest_data(b"strings")?)
+        );
+        assert_eq!(strings.optional, Some(true));
+        assert_eq!(
+            resources.files2.get("Resources/file").unwrap().optional,
+            None
+        );
+
+        // The emitted plist round trips.
+        let mut xml = vec![];
+        builder.write_code_resources(&mut xml)?;
+        assert_eq!(&CodeResources::from_xml(&xml)?, resources);
+
+        Ok(())
+    }
 }
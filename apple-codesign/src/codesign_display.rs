@@ -0,0 +1,465 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Rendering of signature information in the layout of `codesign -d`.
+//!
+//! Apple's `codesign -d` prints signature properties as `Key=value` lines, with
+//! more lines as verbosity increases. Rendering our view of a signature in the
+//! same layout allows diffing it against Apple's tooling.
+
+use {
+    crate::{
+        code_resources::CodeResources,
+        embedded_signature::{CmsSignerDetails, CodeSigningSlot, EmbeddedSignature},
+        error::AppleCodesignError,
+        macho::MachOBinary,
+        verify::verify_time_stamp_token,
+    },
+    goblin::mach::cputype::get_arch_name_from_types,
+    std::fmt::Write,
+    x509_certificate::CapturedX509Certificate,
+};
+
+/// Renders an [EmbeddedSignature] in the layout of `codesign -d`.
+///
+/// Verbosity corresponds to the number of `-v` arguments passed to `codesign`.
+/// At verbosity 0 only the executable path is printed. At verbosity 3 (`-dvvv`)
+/// digests of the code directories are included.
+///
+/// Times are rendered in UTC, whereas `codesign` uses the local time zone.
+pub struct CodesignDisplay<'a> {
+    signature: EmbeddedSignature<'a>,
+    executable: Option<String>,
+    format: Option<String>,
+    info_plist: Option<plist::Dictionary>,
+    code_resources: Option<CodeResources>,
+    verbosity: u8,
+}
+
+impl<'a> CodesignDisplay<'a> {
+    /// Construct an instance rendering a signature at verbosity 3.
+    pub fn new(signature: EmbeddedSignature<'a>) -> Self {
+        Self {
+            signature,
+            executable: None,
+            format: None,
+            info_plist: None,
+            code_resources: None,
+            verbosity: 3,
+        }
+    }
+
+    /// Construct an instance from the signature of a Mach-O binary.
+    ///
+    /// The format and embedded `Info.plist` are resolved from the binary.
+    pub fn from_macho(macho: &'a MachOBinary<'a>) -> Result<Self, AppleCodesignError> {
+        let signature = macho
+            .code_signature()?
+            .ok_or(AppleCodesignError::BinaryNoCodeSignature)?;
+
+        let mut display = Self::new(signature);
+
+        if let Some(arch) = get_arch_name_from_types(
+            macho.macho.header.cputype(),
+            macho.macho.header.cpusubtype(),
+        ) {
+            display.format = Some(format!("Mach-O thin ({arch})"));
+        }
+
+        if let Some(data) = macho.embedded_info_plist()? {
+            display.info_plist =
+                Some(plist::from_bytes(&data).map_err(AppleCodesignError::PlistParseXml)?);
+        }
+
+        Ok(display)
+    }
+
+    /// Set the path of the executable.
+    pub fn executable(mut self, path: impl ToString) -> Self {
+        self.executable = Some(path.to_string());
+        self
+    }
+
+    /// Set the description of the format of the signed entity.
+    ///
+    /// e.g. `Mach-O universal (x86_64 arm64)` or `app bundle with Mach-O thin (arm64)`.
+    pub fn format(mut self, format: impl ToString) -> Self {
+        self.format = Some(format.to_string());
+        self
+    }
+
+    /// Set the `Info.plist` bound to the signature.
+    pub fn info_plist(mut self, info_plist: plist::Dictionary) -> Self {
+        self.info_plist = Some(info_plist);
+        self
+    }
+
+    /// Set the sealed resources bound to the signature.
+    pub fn code_resources(mut self, code_resources: CodeResources) -> Self {
+        self.code_resources = Some(code_resources);
+        self
+    }
+
+    /// Set the verbosity, as the number of `-v` arguments.
+    pub fn verbosity(mut self, verbosity: u8) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Render the signature as `codesign -d` would.
+    pub fn render(&self) -> Result<String, AppleCodesignError> {
+        let mut lines = vec![];
+
+        if let Some(path) = &self.executable {
+            lines.push(format!("Executable={path}"));
+        }
+
+        if self.verbosity == 0 {
+            return Ok(join_lines(lines));
+        }
+
+        let cd = self
+            .signature
+            .code_directory()?
+            .ok_or(AppleCodesignError::BinaryNoCodeDirectory)?;
+
+        lines.push(format!("Identifier={}", cd.ident));
+        if let Some(format) = &self.format {
+            lines.push(format!("Format={format}"));
+        }
+
+        let cd_size = self
+            .signature
+            .find_slot(CodeSigningSlot::CodeDirectory)
+            .map(|entry| entry.data.len())
+            .unwrap_or_default();
+        let special_slots = cd
+            .special_digests
+            .keys()
+            .map(|slot| u32::from(*slot))
+            .max()
+            .unwrap_or(0);
+        lines.push(format!(
+            "CodeDirectory v={:x} size={} flags={} hashes={}+{} location=embedded",
+            cd.version,
+            cd_size,
+            cd.flags,
+            cd.code_digests.len(),
+            special_slots
+        ));
+
+        if cd.platform != 0 {
+            lines.push(format!("Platform identifier={}", cd.platform));
+        }
+
+        if self.verbosity >= 3 {
+            self.render_digests(&mut lines)?;
+        }
+
+        let signed_data = self.signature.signed_data()?;
+        let signer = signed_data
+            .as_ref()
+            .and_then(|signed_data| signed_data.signers().next());
+
+        match self.signature.signature_data()? {
+            Some(data) if signed_data.is_some() => {
+                lines.push(format!("Signature size={}", data.len()))
+            }
+            _ => lines.push("Signature=adhoc".to_string()),
+        }
+
+        if let (Some(signed_data), Some(signer)) = (&signed_data, signer) {
+            let details = CmsSignerDetails::from_signed_data(signed_data);
+
+            if self.verbosity >= 2 {
+                for cert in details.iter().flat_map(|x| x.certificate_chain.iter()) {
+                    lines.push(format!("Authority={}", authority_name(cert)));
+                }
+            }
+
+            if let Ok(Some(time_stamp)) = verify_time_stamp_token(signer) {
+                lines.push(format!("Timestamp={}", format_time(&time_stamp.time)));
+            } else if let Some(time) = details.and_then(|x| x.signing_time) {
+                lines.push(format!("Signed Time={}", format_time(&time)));
+            }
+        }
+
+        match &self.info_plist {
+            Some(info_plist) => lines.push(format!("Info.plist entries={}", info_plist.len())),
+            None => lines.push("Info.plist=not bound".to_string()),
+        }
+
+        lines.push(format!(
+            "TeamIdentifier={}",
+            cd.team_name.as_deref().unwrap_or("not set")
+        ));
+
+        if let Some(runtime) = cd.runtime {
+            lines.push(format!(
+                "Runtime Version={}.{}.{}",
+                runtime >> 16,
+                (runtime >> 8) & 0xff,
+                runtime & 0xff
+            ));
+        }
+
+        match &self.code_resources {
+            Some(resources) => {
+                let (version, rules, files) = resources.seal_summary();
+                lines.push(format!(
+                    "Sealed Resources version={version} rules={rules} files={files}"
+                ));
+            }
+            None => lines.push("Sealed Resources=none".to_string()),
+        }
+
+        match (
+            self.signature.code_requirements()?,
+            self.signature.find_slot(CodeSigningSlot::RequirementSet),
+        ) {
+            (Some(requirements), Some(entry)) => lines.push(format!(
+                "Internal requirements count={} size={}",
+                requirements.requirements.len(),
+                entry.data.len()
+            )),
+            _ => lines.push("Internal requirements=none".to_string()),
+        }
+
+        Ok(join_lines(lines))
+    }
+
+    /// Render the lines describing code directory digests, shown at `-dvvv`.
+    fn render_digests(&self, lines: &mut Vec<String>) -> Result<(), AppleCodesignError> {
+        let cds = self.signature.all_code_directories()?;
+        let cdhashes = self.signature.cdhashes()?;
+
+        // codesign prefers the strongest digest type it knows.
+        let (best_slot, best) = cds
+            .iter()
+            .max_by_key(|(_, cd)| u8::from(cd.digest_type))
+            .ok_or(AppleCodesignError::BinaryNoCodeDirectory)?;
+
+        lines.push(format!(
+            "Hash type={} size={}",
+            best.digest_type, best.digest_size
+        ));
+
+        for (digest_type, cdhash) in &cdhashes {
+            lines.push(format!(
+                "CandidateCDHash {}={}",
+                digest_type,
+                hex::encode(cdhash)
+            ));
+        }
+
+        let mut full_digests = vec![];
+        for (slot, cd) in &cds {
+            let entry = self
+                .signature
+                .find_slot(*slot)
+                .expect("code directory should have a blob entry");
+            let digest = entry.digest_with(cd.digest_type)?;
+
+            lines.push(format!(
+                "CandidateCDHashFull {}={}",
+                cd.digest_type,
+                hex::encode(&digest)
+            ));
+            full_digests.push((*slot, digest));
+        }
+
+        lines.push(format!(
+            "Hash choices={}",
+            cds.iter()
+                .map(|(_, cd)| cd.digest_type.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        ));
+
+        if let Some((_, digest)) = full_digests.iter().find(|(slot, _)| slot == best_slot) {
+            lines.push(format!("CMSDigest={}", hex::encode(digest)));
+            lines.push(format!("CMSDigestType={}", u8::from(best.digest_type)));
+        }
+
+        if let Some(base) = best.exec_seg_base {
+            lines.push(format!("Executable Segment base={base}"));
+        }
+        if let Some(limit) = best.exec_seg_limit {
+            lines.push(format!("Executable Segment limit={limit}"));
+        }
+        if let Some(flags) = best.exec_seg_flags {
+            lines.push(format!("Executable Segment flags=0x{:x}", flags.bits()));
+        }
+
+        match best.page_size {
+            0 => lines.push("Page size=none".to_string()),
+            page_size => lines.push(format!("Page size={page_size}")),
+        }
+
+        if let Some((_, cdhash)) = cds
+            .iter()
+            .zip(cdhashes.iter())
+            .find(|((slot, _), _)| slot == best_slot)
+            .map(|(_, cdhash)| cdhash)
+        {
+            lines.push(format!("CDHash={}", hex::encode(cdhash)));
+        }
+
+        Ok(())
+    }
+}
+
+/// The name `codesign` prints for a certificate in the signing chain.
+fn authority_name(cert: &CapturedX509Certificate) -> String {
+    cert.subject_common_name()
+        .unwrap_or_else(|| cert.subject_name().user_friendly_str().unwrap_or_default())
+}
+
+/// Format a time like `codesign` does, e.g. `Oct 18, 2026 at 9:30:00 AM`.
+fn format_time(time: &chrono::DateTime<chrono::Utc>) -> String {
+    time.format("%b %-d, %Y at %-I:%M:%S %p").to_string()
+}
+
+fn join_lines(lines: Vec<String>) -> String {
+    lines.into_iter().fold(String::new(), |mut s, line| {
+        writeln!(s, "{line}").expect("writing to string should not fail");
+        s
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            certificate::{create_self_signed_code_signing_certificate, CertificateProfile},
+            code_directory::CodeSignatureFlags,
+            macho_signing::MachOSigner,
+            signing_settings::{SettingsScope, SigningSettings},
+        },
+        x509_certificate::{EcdsaCurve, KeyAlgorithm},
+    };
+
+    fn sign(settings: &SigningSettings) -> Result<Vec<u8>, AppleCodesignError> {
+        let data = crate::test_support::SyntheticMachO::default().build();
+
+        let mut signed = vec![];
+        MachOSigner::new(&data)?.write_signed_binary(settings, &mut signed)?;
+
+        Ok(signed)
+    }
+
+    #[test]
+    fn adhoc() -> Result<(), AppleCodesignError> {
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(SettingsScope::Main, "com.example.display");
+
+        let signed = sign(&settings)?;
+        let macho = MachOBinary::parse(&signed)?;
+        let signature = macho.code_signature()?.unwrap();
+        let cdhash = hex::encode(&signature.cdhashes()?[0].1);
+        let full = hex::encode(
+            signature
+                .find_slot(CodeSigningSlot::CodeDirectory)
+                .unwrap()
+                .digest_with(crate::embedded_signature::DigestType::Sha256)?,
+        );
+
+        let display = CodesignDisplay::from_macho(&macho)?.executable("/tmp/display");
+
+        assert_eq!(
+            display.render()?,
+            [
+                "Executable=/tmp/display".to_string(),
+                "Identifier=com.example.display".to_string(),
+                "Format=Mach-O thin (x86_64)".to_string(),
+                "CodeDirectory v=20400 size=332 flags=0x2(adhoc) hashes=5+2 location=embedded"
+                    .to_string(),
+                "Hash type=sha256 size=32".to_string(),
+                format!("CandidateCDHash sha256={cdhash}"),
+                format!("CandidateCDHashFull sha256={full}"),
+                "Hash choices=sha256".to_string(),
+                format!("CMSDigest={full}"),
+                "CMSDigestType=2".to_string(),
+                "Executable Segment base=0".to_string(),
+                "Executable Segment limit=16384".to_string(),
+                "Executable Segment flags=0x1".to_string(),
+                "Page size=4096".to_string(),
+                format!("CDHash={cdhash}"),
+                "Signature=adhoc".to_string(),
+                "Info.plist=not bound".to_string(),
+                "TeamIdentifier=not set".to_string(),
+                "Sealed Resources=none".to_string(),
+                "Internal requirements count=0 size=12".to_string(),
+                "".to_string(),
+            ]
+            .join("\n")
+        );
+
+        assert_eq!(
+            CodesignDisplay::from_macho(&macho)?
+                .executable("/tmp/display")
+                .verbosity(0)
+                .render()?,
+            "Executable=/tmp/display\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn verbosity() -> Result<(), AppleCodesignError> {
+        let (cert, key, _) = create_self_signed_code_signing_certificate(
+            KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1),
+            CertificateProfile::DeveloperIdApplication,
+            "team1",
+            "Joe Developer",
+            "US",
+            chrono::Duration::hours(1),
+        )?;
+
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(SettingsScope::Main, "com.example.display");
+        settings.set_team_id("team1");
+        settings.set_code_signature_flags(SettingsScope::Main, CodeSignatureFlags::RUNTIME);
+        settings.set_signing_key(&key, cert);
+
+        let signed = sign(&settings)?;
+        let macho = MachOBinary::parse(&signed)?;
+
+        let keys = |verbosity| -> Result<Vec<String>, AppleCodesignError> {
+            Ok(CodesignDisplay::from_macho(&macho)?
+                .verbosity(verbosity)
+                .render()?
+                .lines()
+                .map(|line| line.split('=').next().unwrap().to_string())
+                .collect())
+        };
+
+        assert_eq!(
+            keys(1)?,
+            vec![
+                "Identifier",
+                "Format",
+                "CodeDirectory v",
+                "Signature size",
+                "Signed Time",
+                "Info.plist",
+                "TeamIdentifier",
+                "Sealed Resources",
+                "Internal requirements count",
+            ]
+        );
+        assert!(keys(2)?.contains(&"Authority".to_string()));
+        assert!(!keys(2)?.contains(&"CDHash".to_string()));
+        assert!(keys(3)?.contains(&"CDHash".to_string()));
+
+        let output = CodesignDisplay::from_macho(&macho)?.render()?;
+        assert!(output.contains("flags=0x10000(runtime)"));
+        assert!(output.contains("\nAuthority=Developer ID Application: Joe Developer (team1)\n"));
+        assert!(output.contains("\nTeamIdentifier=team1\n"));
+
+        Ok(())
+    }
+}
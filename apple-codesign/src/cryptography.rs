@@ -23,8 +23,9 @@ use {
     pkcs8::{EncodePrivateKey, ObjectIdentifier, PrivateKeyInfo},
     ring::signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, RsaKeyPair},
     rsa::{
-        algorithms::mgf1_xor, pkcs1::DecodeRsaPrivateKey, BigUint, Oaep,
-        RsaPrivateKey as RsaConstructedKey,
+        algorithms::mgf1_xor,
+        pkcs1::{DecodeRsaPrivateKey, EncodeRsaPrivateKey},
+        BigUint, Oaep, RsaPrivateKey as RsaConstructedKey,
     },
     signature::Signer,
     spki::AlgorithmIdentifier,
@@ -438,6 +439,21 @@ impl PrivateKey for InMemoryPrivateKey {
 }
 
 impl InMemoryPrivateKey {
+    /// Generate a new random RSA private key with the given modulus size in bits.
+    ///
+    /// Apple expects 2048 bit RSA keys in certificate signing requests.
+    pub fn generate_rsa(bits: usize) -> Result<Self, AppleCodesignError> {
+        let key = RsaConstructedKey::new(&mut rand::thread_rng(), bits).map_err(|e| {
+            AppleCodesignError::CertificateGeneric(format!("error generating RSA key: {e}"))
+        })?;
+
+        let der = key.to_pkcs1_der().map_err(|e| {
+            AppleCodesignError::CertificateGeneric(format!("error encoding RSA key: {e}"))
+        })?;
+
+        Self::from_pkcs1_der(der.as_bytes())
+    }
+
     /// Construct an instance by parsing PKCS#1 DER data.
     pub fn from_pkcs1_der(data: impl AsRef<[u8]>) -> Result<Self, AppleCodesignError> {
         let key = InMemoryRsaKey::from_der(data.as_ref()).map_err(|e| {
@@ -700,6 +716,17 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn rsa_generate() -> Result<(), AppleCodesignError> {
+        let key = InMemoryPrivateKey::generate_rsa(2048)?;
+
+        assert_eq!(key.key_algorithm(), Some(KeyAlgorithm::Rsa));
+        InMemoryPrivateKey::from_pkcs8_der(key.to_pkcs8_der().unwrap().as_bytes())?;
+        InMemorySigningKeyPair::try_from(key)?;
+
+        Ok(())
+    }
+
     #[test]
     fn ed25519_key_operations() -> Result<(), AppleCodesignError> {
         let pki = PrivateKeyInfo::from_der(ED25519_PKCS8_DER).unwrap();
@@ -0,0 +1,532 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! DER encoding of property lists.
+
+Apple uses an ASN.1 DER encoding of property lists in various places in code
+signatures, notably DER entitlements and launch constraints. This module
+implements encoding and decoding of this format.
+
+The encoded form is an `[APPLICATION 16]` sequence holding a version integer
+(always `1`) followed by the encoded root value. Values are encoded as follows:
+
+* Booleans as `BOOLEAN`.
+* Integers as `INTEGER`.
+* Strings as `UTF8String`.
+* Data as `OCTET STRING`.
+* Arrays as `SEQUENCE` of values.
+* Dictionaries as `[CONTEXT 16]` holding a `SEQUENCE { UTF8String, value }` for
+  each entry, sorted by key.
+
+Dates, reals, and UIDs are not supported.
+*/
+
+use {
+    crate::AppleCodesignError,
+    plist::Value,
+    rasn::{
+        ber::enc::{Encoder as DerEncoder, Error as DerError},
+        enc::Error,
+        types::{Class, Tag},
+        Encoder,
+    },
+    std::collections::BTreeMap,
+};
+
+/// Version of the DER plist encoding we support.
+const DER_PLIST_VERSION: i64 = 1;
+
+const TAG_BOOLEAN: u8 = 0x01;
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_UTF8_STRING: u8 = 0x0c;
+const TAG_SEQUENCE: u8 = 0x30;
+/// `[APPLICATION 16]` constructed.
+const TAG_ENVELOPE: u8 = 0x70;
+/// `[CONTEXT 16]` constructed.
+const TAG_DICTIONARY: u8 = 0xb0;
+
+/// Maximum nesting depth of decoded values.
+///
+/// Prevents stack exhaustion when decoding untrusted data.
+const MAX_DEPTH: usize = 64;
+
+/// Encode a [Value] to DER, writing to an encoder.
+fn der_encode_value(encoder: &mut DerEncoder, value: &Value) -> Result<(), DerError> {
+    match value {
+        Value::Boolean(v) => encoder.encode_bool(Tag::BOOL, *v),
+        Value::Integer(v) => {
+            let v = v
+                .as_signed()
+                .ok_or_else(|| DerError::custom("integer value out of range"))?;
+            encoder.encode_integer(Tag::INTEGER, &rasn::types::Integer::from(v))
+        }
+        Value::String(string) => encoder.encode_utf8_string(Tag::UTF8_STRING, string),
+        Value::Data(data) => encoder.encode_octet_string(Tag::OCTET_STRING, data),
+        Value::Array(array) => encoder.encode_sequence(Tag::SEQUENCE, |encoder| {
+            for v in array {
+                der_encode_value(encoder, v)?;
+            }
+            Ok(())
+        }),
+        Value::Dictionary(dict) => {
+            // make sure it's sorted alphabetically
+            let map = dict.into_iter().collect::<BTreeMap<_, _>>();
+            encoder.encode_sequence(Tag::new(Class::Context, 16), |encoder| {
+                for (k, v) in map {
+                    encoder.encode_sequence(Tag::SEQUENCE, |encoder| {
+                        encoder.encode_utf8_string(Tag::UTF8_STRING, k)?;
+                        der_encode_value(encoder, v)?;
+                        Ok(())
+                    })?;
+                }
+                Ok(())
+            })
+        }
+        Value::Date(_) => Err(DerError::custom("encoding of date values not supported")),
+        Value::Real(_) => Err(DerError::custom("encoding of real values not supported")),
+        Value::Uid(_) => Err(DerError::custom("encoding of uid values not supported")),
+        _ => Err(DerError::custom(
+            "encoding of unknown value type not supported",
+        )),
+    }
+}
+
+/// Encode a plist [Value] to Apple's DER plist encoding.
+pub fn der_encode_plist(value: &Value) -> Result<Vec<u8>, AppleCodesignError> {
+    rasn::der::encode_scope(|encoder| {
+        encoder.encode_sequence(Tag::new(Class::Application, 16), |encoder| {
+            encoder.encode_integer(Tag::INTEGER, &rasn::types::Integer::from(DER_PLIST_VERSION))?;
+            der_encode_value(encoder, value)?;
+            Ok(())
+        })
+    })
+    .map_err(|e| AppleCodesignError::DerPlistEncode(format!("{e}")))
+}
+
+/// Decode Apple's DER plist encoding to a plist [Value].
+pub fn der_decode_plist(data: &[u8]) -> Result<Value, AppleCodesignError> {
+    let mut reader = DerReader::new(data);
+
+    let (tag, content) = reader.read_tlv()?;
+    reader.finish()?;
+
+    if tag != TAG_ENVELOPE {
+        return Err(decode_error(format!(
+            "expected [APPLICATION 16] envelope; got tag 0x{tag:02x}"
+        )));
+    }
+
+    let mut reader = DerReader::new(content);
+
+    let (tag, version) = reader.read_tlv()?;
+    if tag != TAG_INTEGER {
+        return Err(decode_error(format!(
+            "expected version INTEGER; got tag 0x{tag:02x}"
+        )));
+    }
+    let version = decode_integer(version)?;
+    if version != DER_PLIST_VERSION {
+        return Err(decode_error(format!("unsupported version {version}")));
+    }
+
+    let value = reader.read_value(0)?;
+    reader.finish()?;
+
+    Ok(value)
+}
+
+fn decode_error(message: impl ToString) -> AppleCodesignError {
+    AppleCodesignError::DerPlistDecode(message.to_string())
+}
+
+fn decode_integer(data: &[u8]) -> Result<i64, AppleCodesignError> {
+    if data.is_empty() || data.len() > 8 {
+        return Err(decode_error(format!(
+            "INTEGER of {} bytes not supported",
+            data.len()
+        )));
+    }
+
+    // Sign extend from the leading byte.
+    let mut buf = if data[0] & 0x80 != 0 {
+        [0xff; 8]
+    } else {
+        [0; 8]
+    };
+    buf[8 - data.len()..].copy_from_slice(data);
+
+    Ok(i64::from_be_bytes(buf))
+}
+
+/// A minimal reader of DER tag-length-value records.
+struct DerReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> DerReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn finish(&self) -> Result<(), AppleCodesignError> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(decode_error(format!(
+                "{} bytes of trailing data",
+                self.data.len()
+            )))
+        }
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], AppleCodesignError> {
+        if self.data.len() < count {
+            return Err(decode_error("unexpected end of data"));
+        }
+
+        let (head, tail) = self.data.split_at(count);
+        self.data = tail;
+
+        Ok(head)
+    }
+
+    /// Read a tag and its content.
+    fn read_tlv(&mut self) -> Result<(u8, &'a [u8]), AppleCodesignError> {
+        let tag = self.take(1)?[0];
+
+        // We only support low tag numbers, which are all the format uses.
+        if tag & 0x1f == 0x1f {
+            return Err(decode_error("high tag numbers not supported"));
+        }
+
+        let first = self.take(1)?[0];
+
+        let length = if first & 0x80 == 0 {
+            first as usize
+        } else {
+            let count = (first & 0x7f) as usize;
+            if count == 0 || count > std::mem::size_of::<usize>() {
+                return Err(decode_error("unsupported length encoding"));
+            }
+
+            self.take(count)?
+                .iter()
+                .fold(0usize, |acc, b| (acc << 8) | *b as usize)
+        };
+
+        Ok((tag, self.take(length)?))
+    }
+
+    /// Read a plist value.
+    fn read_value(&mut self, depth: usize) -> Result<Value, AppleCodesignError> {
+        if depth > MAX_DEPTH {
+            return Err(decode_error("maximum nesting depth exceeded"));
+        }
+
+        let (tag, content) = self.read_tlv()?;
+
+        match tag {
+            TAG_BOOLEAN => match content {
+                [0] => Ok(Value::Boolean(false)),
+                [0xff] => Ok(Value::Boolean(true)),
+                _ => Err(decode_error("invalid BOOLEAN")),
+            },
+            TAG_INTEGER => Ok(Value::Integer(decode_integer(content)?.into())),
+            TAG_UTF8_STRING => Ok(Value::String(
+                String::from_utf8(content.to_vec()).map_err(decode_error)?,
+            )),
+            TAG_OCTET_STRING => Ok(Value::Data(content.to_vec())),
+            TAG_SEQUENCE => {
+                let mut reader = DerReader::new(content);
+                let mut values = vec![];
+
+                while !reader.is_empty() {
+                    values.push(reader.read_value(depth + 1)?);
+                }
+
+                Ok(Value::Array(values))
+            }
+            TAG_DICTIONARY => {
+                let mut reader = DerReader::new(content);
+                let mut dict = plist::Dictionary::new();
+
+                while !reader.is_empty() {
+                    let (tag, entry) = reader.read_tlv()?;
+                    if tag != TAG_SEQUENCE {
+                        return Err(decode_error(format!(
+                            "expected dictionary entry SEQUENCE; got tag 0x{tag:02x}"
+                        )));
+                    }
+
+                    let mut entry = DerReader::new(entry);
+
+                    let key = match entry.read_value(depth + 1)? {
+                        Value::String(key) => key,
+                        _ => return Err(decode_error("dictionary key isn't a UTF8String")),
+                    };
+                    let value = entry.read_value(depth + 1)?;
+                    entry.finish()?;
+
+                    dict.insert(key, value);
+                }
+
+                Ok(Value::Dictionary(dict))
+            }
+            _ => Err(decode_error(format!("unsupported tag 0x{tag:02x}"))),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use {
+        super::*,
+        anyhow::Result,
+        plist::{Date, Uid},
+        std::time::SystemTime,
+    };
+
+    // The following were obtained from signatures produced by Apple's `codesign`
+    // using `--generate-entitlement-der`. Each is a dictionary with a single `key`
+    // entry unless noted otherwise.
+
+    pub(crate) const DER_EMPTY_DICT: &[u8] = &[112, 5, 2, 1, 1, 176, 0];
+    pub(crate) const DER_BOOL_FALSE: &[u8] = &[
+        112, 15, 2, 1, 1, 176, 10, 48, 8, 12, 3, 107, 101, 121, 1, 1, 0,
+    ];
+    pub(crate) const DER_BOOL_TRUE: &[u8] = &[
+        112, 15, 2, 1, 1, 176, 10, 48, 8, 12, 3, 107, 101, 121, 1, 1, 255,
+    ];
+    pub(crate) const DER_INTEGER_0: &[u8] = &[
+        112, 15, 2, 1, 1, 176, 10, 48, 8, 12, 3, 107, 101, 121, 2, 1, 0,
+    ];
+    pub(crate) const DER_INTEGER_NEG1: &[u8] = &[
+        112, 15, 2, 1, 1, 176, 10, 48, 8, 12, 3, 107, 101, 121, 2, 1, 255,
+    ];
+    pub(crate) const DER_INTEGER_1: &[u8] = &[
+        112, 15, 2, 1, 1, 176, 10, 48, 8, 12, 3, 107, 101, 121, 2, 1, 1,
+    ];
+    pub(crate) const DER_INTEGER_42: &[u8] = &[
+        112, 15, 2, 1, 1, 176, 10, 48, 8, 12, 3, 107, 101, 121, 2, 1, 42,
+    ];
+    pub(crate) const DER_STRING_EMPTY: &[u8] =
+        &[112, 14, 2, 1, 1, 176, 9, 48, 7, 12, 3, 107, 101, 121, 12, 0];
+    pub(crate) const DER_STRING_VALUE: &[u8] = &[
+        112, 19, 2, 1, 1, 176, 14, 48, 12, 12, 3, 107, 101, 121, 12, 5, 118, 97, 108, 117, 101,
+    ];
+    pub(crate) const DER_ARRAY_EMPTY: &[u8] =
+        &[112, 14, 2, 1, 1, 176, 9, 48, 7, 12, 3, 107, 101, 121, 48, 0];
+    pub(crate) const DER_ARRAY_FALSE: &[u8] = &[
+        112, 17, 2, 1, 1, 176, 12, 48, 10, 12, 3, 107, 101, 121, 48, 3, 1, 1, 0,
+    ];
+    pub(crate) const DER_ARRAY_TRUE_FOO: &[u8] = &[
+        112, 22, 2, 1, 1, 176, 17, 48, 15, 12, 3, 107, 101, 121, 48, 8, 1, 1, 255, 12, 3, 102, 111,
+        111,
+    ];
+    pub(crate) const DER_DICT_EMPTY: &[u8] = &[
+        112, 14, 2, 1, 1, 176, 9, 48, 7, 12, 3, 107, 101, 121, 176, 0,
+    ];
+    pub(crate) const DER_DICT_BOOL: &[u8] = &[
+        112, 26, 2, 1, 1, 176, 21, 48, 19, 12, 3, 107, 101, 121, 176, 12, 48, 10, 12, 5, 105, 110,
+        110, 101, 114, 1, 1, 0,
+    ];
+    /// Keys `key`, `key2`, and `key3`.
+    pub(crate) const DER_MULTIPLE_KEYS: &[u8] = &[
+        112, 37, 2, 1, 1, 176, 32, 48, 8, 12, 3, 107, 101, 121, 1, 1, 0, 48, 9, 12, 4, 107, 101,
+        121, 50, 1, 1, 255, 48, 9, 12, 4, 107, 101, 121, 51, 2, 1, 42,
+    ];
+
+    pub(crate) const ALL_SAMPLES: &[&[u8]] = &[
+        DER_EMPTY_DICT,
+        DER_BOOL_FALSE,
+        DER_BOOL_TRUE,
+        DER_INTEGER_0,
+        DER_INTEGER_NEG1,
+        DER_INTEGER_1,
+        DER_INTEGER_42,
+        DER_STRING_EMPTY,
+        DER_STRING_VALUE,
+        DER_ARRAY_EMPTY,
+        DER_ARRAY_FALSE,
+        DER_ARRAY_TRUE_FOO,
+        DER_DICT_EMPTY,
+        DER_DICT_BOOL,
+        DER_MULTIPLE_KEYS,
+    ];
+
+    fn key_dict(value: Value) -> Value {
+        let mut d = plist::Dictionary::new();
+        d.insert("key".into(), value);
+        Value::Dictionary(d)
+    }
+
+    #[test]
+    fn decode_samples() -> Result<()> {
+        assert_eq!(
+            der_decode_plist(DER_EMPTY_DICT)?,
+            Value::Dictionary(Default::default())
+        );
+        assert_eq!(
+            der_decode_plist(DER_BOOL_FALSE)?,
+            key_dict(Value::Boolean(false))
+        );
+        assert_eq!(
+            der_decode_plist(DER_BOOL_TRUE)?,
+            key_dict(Value::Boolean(true))
+        );
+        assert_eq!(
+            der_decode_plist(DER_INTEGER_0)?,
+            key_dict(Value::Integer(0.into()))
+        );
+        assert_eq!(
+            der_decode_plist(DER_INTEGER_NEG1)?,
+            key_dict(Value::Integer((-1).into()))
+        );
+        assert_eq!(
+            der_decode_plist(DER_INTEGER_1)?,
+            key_dict(Value::Integer(1.into()))
+        );
+        assert_eq!(
+            der_decode_plist(DER_INTEGER_42)?,
+            key_dict(Value::Integer(42.into()))
+        );
+        assert_eq!(
+            der_decode_plist(DER_STRING_EMPTY)?,
+            key_dict(Value::String("".into()))
+        );
+        assert_eq!(
+            der_decode_plist(DER_STRING_VALUE)?,
+            key_dict(Value::String("value".into()))
+        );
+        assert_eq!(
+            der_decode_plist(DER_ARRAY_EMPTY)?,
+            key_dict(Value::Array(vec![]))
+        );
+        assert_eq!(
+            der_decode_plist(DER_ARRAY_FALSE)?,
+            key_dict(Value::Array(vec![Value::Boolean(false)]))
+        );
+        assert_eq!(
+            der_decode_plist(DER_ARRAY_TRUE_FOO)?,
+            key_dict(Value::Array(vec![
+                Value::Boolean(true),
+                Value::String("foo".into())
+            ]))
+        );
+        assert_eq!(
+            der_decode_plist(DER_DICT_EMPTY)?,
+            key_dict(Value::Dictionary(Default::default()))
+        );
+
+        let mut inner = plist::Dictionary::new();
+        inner.insert("inner".into(), Value::Boolean(false));
+        assert_eq!(
+            der_decode_plist(DER_DICT_BOOL)?,
+            key_dict(Value::Dictionary(inner))
+        );
+
+        let mut d = plist::Dictionary::new();
+        d.insert("key".into(), Value::Boolean(false));
+        d.insert("key2".into(), Value::Boolean(true));
+        d.insert("key3".into(), Value::Integer(42.into()));
+        assert_eq!(der_decode_plist(DER_MULTIPLE_KEYS)?, Value::Dictionary(d));
+
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_samples() -> Result<()> {
+        for sample in ALL_SAMPLES {
+            let value = der_decode_plist(sample)?;
+            assert_eq!(&der_encode_plist(&value)?, sample);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_values() -> Result<()> {
+        let mut inner = plist::Dictionary::new();
+        inner.insert("b".into(), Value::Data(vec![]));
+        inner.insert("a".into(), Value::Data(b"foo".to_vec()));
+
+        let mut d = plist::Dictionary::new();
+        d.insert("data".into(), Value::Data((0..=255).collect::<Vec<u8>>()));
+        d.insert("dict".into(), Value::Dictionary(inner));
+        d.insert("max".into(), Value::Integer(i64::MAX.into()));
+        d.insert("min".into(), Value::Integer(i64::MIN.into()));
+        d.insert("byte".into(), Value::Integer(128.into()));
+        d.insert("negative".into(), Value::Integer((-129).into()));
+        d.insert("string".into(), Value::String("x".repeat(1000)));
+        d.insert(
+            "array".into(),
+            Value::Array(vec![
+                Value::Array(vec![Value::String("nested".into())]),
+                Value::Dictionary(Default::default()),
+                Value::Boolean(true),
+            ]),
+        );
+
+        for value in [
+            Value::Dictionary(d),
+            Value::Array(vec![]),
+            Value::String("root".into()),
+            Value::Boolean(false),
+            Value::Integer(0.into()),
+            Value::Data(vec![1, 2, 3]),
+        ] {
+            let der = der_encode_plist(&value)?;
+            assert_eq!(der_decode_plist(&der)?, value);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn encode_unsupported() {
+        for value in [
+            Value::Real(1.0),
+            Value::Uid(Uid::new(42)),
+            Value::Date(Date::from(SystemTime::UNIX_EPOCH)),
+            Value::Integer(u64::MAX.into()),
+        ] {
+            assert!(matches!(
+                der_encode_plist(&key_dict(value)),
+                Err(AppleCodesignError::DerPlistEncode(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn decode_malformed() {
+        for data in [
+            // Empty.
+            &[][..],
+            // Truncated.
+            &DER_BOOL_TRUE[0..DER_BOOL_TRUE.len() - 1],
+            // Trailing data.
+            &[112, 5, 2, 1, 1, 176, 0, 0],
+            // Bad version.
+            &[112, 5, 2, 1, 2, 176, 0],
+            // Missing envelope.
+            &[48, 0],
+            // Invalid boolean.
+            &[112, 6, 2, 1, 1, 1, 1, 1],
+            // Non-string dictionary key.
+            &[112, 13, 2, 1, 1, 176, 8, 48, 6, 2, 1, 0, 1, 1, 0][..],
+            // Unsupported tag (REAL).
+            &[112, 5, 2, 1, 1, 9, 0],
+        ] {
+            assert!(matches!(
+                der_decode_plist(data),
+                Err(AppleCodesignError::DerPlistDecode(_))
+            ));
+        }
+    }
+}
@@ -50,6 +50,7 @@ use {
             BlobData, CodeSigningSlot, Digest, DigestType, EmbeddedSignature, RequirementSetBlob,
         },
         embedded_signature_builder::EmbeddedSignatureBuilder,
+        metrics::time_stamped,
         AppleCodesignError, SettingsScope, SigningSettings,
     },
     log::warn,
@@ -340,12 +341,15 @@ impl DmgSigner {
         )?;
 
         if let Some((signing_key, signing_cert)) = settings.signing_key() {
-            builder.create_cms_signature(
-                signing_key,
-                signing_cert,
-                settings.time_stamp_url(),
-                settings.certificate_chain().iter().cloned(),
-            )?;
+            time_stamped(settings, || {
+                builder.create_cms_signature(
+                    signing_key,
+                    signing_cert,
+                    settings.time_stamp_url(),
+                    settings.retry_policy(),
+                    settings.certificate_chain().iter().cloned(),
+                )
+            })?;
         }
 
         builder.create_superblob()
@@ -397,7 +401,7 @@ impl DmgSigner {
             digest_type: *settings.digest_type(),
             page_size: 1,
             ident,
-            code_digests: code_hashes,
+            code_digests: code_hashes.try_into()?,
             ..Default::default()
         };
 
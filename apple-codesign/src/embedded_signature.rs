@@ -30,9 +30,11 @@
 //!   *blob* contains (code directory, entitlements, embedded signature, etc).
 //! * N *blob* sections of varying formats and lengths.
 //!
-//! We only support the [CodeSigningMagic::EmbeddedSignature] magic in the `SuperBlob`,
-//! as this is what is used in the wild. (It is even unclear if other magic values
-//! can occur in `SuperBlob` headers.)
+//! The [CodeSigningMagic::EmbeddedSignature] magic in the `SuperBlob` is what is
+//! used in the wild. Very old versions of macOS (10.5 era) used the
+//! [CodeSigningMagic::EmbeddedSignatureOld] magic for the same `SuperBlob` layout.
+//! We support parsing both. The legacy container can also be written via
+//! [EmbeddedSignatureOldBlob] and the signature builder.
 //!
 //! The `EmbeddedSignature` type represents a lightly parsed `SuperBlob`. It
 //! provides access to `BlobEntry` which describe the *blob* sections within the
@@ -41,23 +43,35 @@
 
 use {
     crate::{
-        code_directory::CodeDirectoryBlob,
-        code_requirement::{CodeRequirements, RequirementType},
+        code_directory::{CodeDirectoryBlob, CS_CDHASH_LEN},
+        code_requirement::{CodeRequirements, DesignatedRequirement, RequirementType},
+        macho::MachOParseError,
         AppleCodesignError,
     },
-    apple_xar::table_of_contents::ChecksumType as XarChecksumType,
-    cryptographic_message_syntax::SignedData,
     scroll::{IOwrite, Pread},
+    serde::{ser::SerializeStruct, Serialize, Serializer},
     std::{
         borrow::Cow,
         cmp::Ordering,
-        collections::HashMap,
+        collections::BTreeMap,
         fmt::{Display, Formatter},
         io::Write,
     },
     x509_certificate::DigestAlgorithm,
 };
 
+#[cfg(feature = "full")]
+use {
+    crate::{
+        certificate::AppleCertificate, code_requirement::CodeRequirementExpression,
+        policy::derive_designated_requirements_from_chain, verify::signer_certificate_chain,
+    },
+    apple_xar::table_of_contents::ChecksumType as XarChecksumType,
+    chrono::{DateTime, Utc},
+    cryptographic_message_syntax::SignedData,
+    x509_certificate::CapturedX509Certificate,
+};
+
 /// Defines header magic for various payloads.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum CodeSigningMagic {
@@ -77,6 +91,8 @@ pub enum CodeSigningMagic {
     Entitlements,
     /// DER encoded entitlements blob.
     EntitlementsDer,
+    /// DER encoded launch or library constraints blob.
+    Constraints,
     /// Multi-arch collection of embedded signatures.
     DetachedSignature,
     /// Generic blob wrapper.
@@ -97,6 +113,7 @@ impl From<u32> for CodeSigningMagic {
             0xfade0b02 => Self::EmbeddedSignatureOld,
             0xfade7171 => Self::Entitlements,
             0xfade7172 => Self::EntitlementsDer,
+            0xfade8181 => Self::Constraints,
             0xfade0cc1 => Self::DetachedSignature,
             0xfade0b01 => Self::BlobWrapper,
             _ => Self::Unknown(v),
@@ -114,6 +131,7 @@ impl From<CodeSigningMagic> for u32 {
             CodeSigningMagic::EmbeddedSignatureOld => 0xfade0b02,
             CodeSigningMagic::Entitlements => 0xfade7171,
             CodeSigningMagic::EntitlementsDer => 0xfade7172,
+            CodeSigningMagic::Constraints => 0xfade8181,
             CodeSigningMagic::DetachedSignature => 0xfade0cc1,
             CodeSigningMagic::BlobWrapper => 0xfade0b01,
             CodeSigningMagic::Unknown(v) => v,
@@ -121,6 +139,12 @@ impl From<CodeSigningMagic> for u32 {
     }
 }
 
+impl Serialize for CodeSigningMagic {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{:x}", u32::from(*self)))
+    }
+}
+
 /// A well-known slot within code signing data.
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub enum CodeSigningSlot {
@@ -139,6 +163,14 @@ pub enum CodeSigningSlot {
     RepSpecific,
     /// Entitlements DER encoded plist.
     EntitlementsDer,
+    /// Launch constraints on the process itself.
+    LaunchConstraintsSelf,
+    /// Launch constraints on the parent process.
+    LaunchConstraintsParent,
+    /// Launch constraints on the process responsible for the launch.
+    LaunchConstraintsResponsible,
+    /// Constraints on libraries that may be loaded.
+    LibraryConstraints,
     // Everything from here is a slot not encoded in the code directory hashes list.
     // REMEMBER TO UPDATE is_code_directory_specials_expressible() if adding a new slot
     // here!
@@ -158,8 +190,20 @@ pub enum CodeSigningSlot {
     Unknown(u32),
 }
 
+impl Serialize for CodeSigningSlot {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{self:?}"))
+    }
+}
+
 impl std::fmt::Debug for CodeSigningSlot {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for CodeSigningSlot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::CodeDirectory => {
                 f.write_fmt(format_args!("CodeDirectory ({})", u32::from(*self)))
@@ -175,6 +219,21 @@ impl std::fmt::Debug for CodeSigningSlot {
             Self::EntitlementsDer => {
                 f.write_fmt(format_args!("DER Entitlements ({})", u32::from(*self)))
             }
+            Self::LaunchConstraintsSelf => f.write_fmt(format_args!(
+                "Launch Constraints Self ({})",
+                u32::from(*self)
+            )),
+            Self::LaunchConstraintsParent => f.write_fmt(format_args!(
+                "Launch Constraints Parent ({})",
+                u32::from(*self)
+            )),
+            Self::LaunchConstraintsResponsible => f.write_fmt(format_args!(
+                "Launch Constraints Responsible ({})",
+                u32::from(*self)
+            )),
+            Self::LibraryConstraints => {
+                f.write_fmt(format_args!("Library Constraints ({})", u32::from(*self)))
+            }
             Self::AlternateCodeDirectory0 => f.write_fmt(format_args!(
                 "CodeDirectory Alternate #0 ({})",
                 u32::from(*self)
@@ -216,6 +275,10 @@ impl From<u32> for CodeSigningSlot {
             5 => Self::Entitlements,
             6 => Self::RepSpecific,
             7 => Self::EntitlementsDer,
+            8 => Self::LaunchConstraintsSelf,
+            9 => Self::LaunchConstraintsParent,
+            10 => Self::LaunchConstraintsResponsible,
+            11 => Self::LibraryConstraints,
             0x1000 => Self::AlternateCodeDirectory0,
             0x1001 => Self::AlternateCodeDirectory1,
             0x1002 => Self::AlternateCodeDirectory2,
@@ -240,6 +303,10 @@ impl From<CodeSigningSlot> for u32 {
             CodeSigningSlot::Entitlements => 5,
             CodeSigningSlot::RepSpecific => 6,
             CodeSigningSlot::EntitlementsDer => 7,
+            CodeSigningSlot::LaunchConstraintsSelf => 8,
+            CodeSigningSlot::LaunchConstraintsParent => 9,
+            CodeSigningSlot::LaunchConstraintsResponsible => 10,
+            CodeSigningSlot::LibraryConstraints => 11,
             CodeSigningSlot::AlternateCodeDirectory0 => 0x1000,
             CodeSigningSlot::AlternateCodeDirectory1 => 0x1001,
             CodeSigningSlot::AlternateCodeDirectory2 => 0x1002,
@@ -285,7 +352,7 @@ impl CodeSigningSlot {
 
     /// Whether this slot's digest is expressed in code directories list of special slot digests.
     pub fn is_code_directory_specials_expressible(&self) -> bool {
-        *self >= Self::Info && *self <= Self::EntitlementsDer
+        *self >= Self::Info && *self <= Self::LibraryConstraints
     }
 }
 
@@ -394,6 +461,12 @@ impl Display for DigestType {
     }
 }
 
+impl Serialize for DigestType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
 impl TryFrom<&str> for DigestType {
     type Error = AppleCodesignError;
 
@@ -410,6 +483,7 @@ impl TryFrom<&str> for DigestType {
     }
 }
 
+#[cfg(feature = "full")]
 impl TryFrom<XarChecksumType> for DigestType {
     type Error = AppleCodesignError;
 
@@ -427,7 +501,13 @@ impl TryFrom<XarChecksumType> for DigestType {
 impl DigestType {
     /// Obtain the size of hashes for this hash type.
     pub fn hash_len(&self) -> Result<usize, AppleCodesignError> {
-        Ok(self.digest_data(&[])?.len())
+        match self {
+            Self::Sha1 | Self::Sha256Truncated => Ok(20),
+            Self::Sha256 => Ok(32),
+            Self::Sha384 => Ok(48),
+            Self::Sha512 => Ok(64),
+            Self::None | Self::Unknown(_) => Err(AppleCodesignError::DigestUnknownAlgorithm),
+        }
     }
 
     /// Obtain a hasher for this digest type.
@@ -492,6 +572,12 @@ impl<'a> std::fmt::Debug for Digest<'a> {
     }
 }
 
+impl<'a> Serialize for Digest<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.as_hex())
+    }
+}
+
 impl<'a> From<Vec<u8>> for Digest<'a> {
     fn from(v: Vec<u8>) -> Self {
         Self { data: v.into() }
@@ -508,17 +594,42 @@ fn read_blob_header(data: &[u8]) -> Result<(u32, usize, &[u8]), scroll::Error> {
     Ok((magic, length as usize, &data[8..]))
 }
 
+/// Read the header from a Blob and validate it describes the given data.
+///
+/// The magic must match `expected_magic` and the self-reported length must
+/// equal the length of `data`. Returns the data following the header.
 pub(crate) fn read_and_validate_blob_header<'a>(
     data: &'a [u8],
     expected_magic: u32,
     what: &'static str,
 ) -> Result<&'a [u8], AppleCodesignError> {
-    let (magic, _, data) = read_blob_header(data)?;
+    let (magic, length, payload) =
+        read_blob_header(data).map_err(|_| AppleCodesignError::TruncatedBlob {
+            what,
+            required: 8,
+            available: data.len(),
+        })?;
 
     if magic != expected_magic {
-        Err(AppleCodesignError::BadMagic(what))
+        Err(AppleCodesignError::MagicMismatch {
+            what,
+            expected: expected_magic,
+            actual: magic,
+        })
+    } else if length > data.len() {
+        Err(AppleCodesignError::TruncatedBlob {
+            what,
+            required: length,
+            available: data.len(),
+        })
+    } else if length != data.len() {
+        Err(AppleCodesignError::LengthMismatch {
+            what,
+            declared: length,
+            actual: data.len(),
+        })
     } else {
-        Ok(data)
+        Ok(payload)
     }
 }
 
@@ -608,6 +719,23 @@ impl<'a> std::fmt::Debug for BlobEntry<'a> {
     }
 }
 
+impl<'a> Serialize for BlobEntry<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let sha256 = self
+            .digest_with(DigestType::Sha256)
+            .map_err(serde::ser::Error::custom)?;
+
+        let mut s = serializer.serialize_struct("BlobEntry", 6)?;
+        s.serialize_field("index", &self.index)?;
+        s.serialize_field("slot", &self.slot)?;
+        s.serialize_field("offset", &self.offset)?;
+        s.serialize_field("magic", &self.magic)?;
+        s.serialize_field("length", &self.length)?;
+        s.serialize_field("sha256", &hex::encode(sha256))?;
+        s.end()
+    }
+}
+
 impl<'a> BlobEntry<'a> {
     /// Attempt to convert to a [ParsedBlob].
     pub fn into_parsed_blob(self) -> Result<ParsedBlob<'a>, AppleCodesignError> {
@@ -720,7 +848,7 @@ impl<'a> RequirementBlob<'a> {
 /// A Requirement set blob contains nested Requirement blobs.
 #[derive(Debug, Default)]
 pub struct RequirementSetBlob<'a> {
-    pub requirements: HashMap<RequirementType, RequirementBlob<'a>>,
+    pub requirements: BTreeMap<RequirementType, RequirementBlob<'a>>,
 }
 
 impl<'a> Blob<'a> for RequirementSetBlob<'a> {
@@ -737,6 +865,14 @@ impl<'a> Blob<'a> for RequirementSetBlob<'a> {
         let offset = &mut 8;
         let count = data.gread_with::<u32>(offset, scroll::BE)?;
 
+        // Don't trust the count to size allocations: validate it against the
+        // data holding the index entries first.
+        if count as usize > (data.len() - *offset) / 8 {
+            return Err(AppleCodesignError::RequirementMalformed(
+                "requirement set index extends beyond blob data",
+            ));
+        }
+
         let mut indices = Vec::with_capacity(count as usize);
         for _ in 0..count {
             indices.push((
@@ -745,7 +881,7 @@ impl<'a> Blob<'a> for RequirementSetBlob<'a> {
             ));
         }
 
-        let mut requirements = HashMap::with_capacity(indices.len());
+        let mut requirements = BTreeMap::new();
 
         for (i, (flavor, offset)) in indices.iter().enumerate() {
             let typ = RequirementType::from(*flavor);
@@ -756,7 +892,20 @@ impl<'a> Blob<'a> for RequirementSetBlob<'a> {
                 indices[i + 1].1 as usize
             };
 
-            let requirement_data = &data[*offset as usize..end_offset];
+            let requirement_data = data.get(*offset as usize..end_offset).ok_or(
+                AppleCodesignError::RequirementMalformed(
+                    "requirement set offsets extend beyond blob data",
+                ),
+            )?;
+
+            // Like super blobs, tolerate padding between requirement blobs by
+            // honoring the self-reported length when it is shorter than the data.
+            let requirement_data = match read_blob_header(requirement_data) {
+                Ok((_, length, _)) if length < requirement_data.len() => {
+                    &requirement_data[0..length]
+                }
+                _ => requirement_data,
+            };
 
             requirements.insert(typ, RequirementBlob::from_blob_bytes(requirement_data)?);
         }
@@ -797,7 +946,7 @@ impl<'a> RequirementSetBlob<'a> {
                 .requirements
                 .iter()
                 .map(|(flavor, blob)| (*flavor, blob.to_owned()))
-                .collect::<HashMap<_, _>>(),
+                .collect::<BTreeMap<_, _>>(),
         }
     }
 
@@ -830,9 +979,15 @@ impl<'a> Blob<'a> for EmbeddedSignatureBlob<'a> {
 }
 
 /// An old embedded signature.
-#[derive(Debug)]
+///
+/// This is the legacy `CSMAGIC_EMBEDDED_SIGNATURE_OLD` container. It has the same
+/// `SuperBlob` layout as [EmbeddedSignatureBlob]: a count of blobs, an array of
+/// (slot, offset) indices, and the blobs themselves. Nested blobs are parsed
+/// when the instance is constructed.
+#[derive(Debug, Default)]
 pub struct EmbeddedSignatureOldBlob<'a> {
-    data: &'a [u8],
+    /// Blobs within this container, in the order they are indexed.
+    pub blobs: Vec<(CodeSigningSlot, BlobData<'a>)>,
 }
 
 impl<'a> Blob<'a> for EmbeddedSignatureOldBlob<'a> {
@@ -841,17 +996,58 @@ impl<'a> Blob<'a> for EmbeddedSignatureOldBlob<'a> {
     }
 
     fn from_blob_bytes(data: &'a [u8]) -> Result<Self, AppleCodesignError> {
-        Ok(Self {
-            data: read_and_validate_blob_header(
-                data,
-                Self::magic(),
-                "old embedded signature blob",
-            )?,
-        })
+        read_and_validate_blob_header(data, Self::magic(), "old embedded signature blob")?;
+
+        let signature = EmbeddedSignature::from_bytes(data)?;
+
+        let blobs = signature
+            .blobs
+            .into_iter()
+            .map(|entry| {
+                let parsed = entry.into_parsed_blob()?;
+
+                Ok((parsed.blob_entry.slot, parsed.blob))
+            })
+            .collect::<Result<Vec<_>, AppleCodesignError>>()?;
+
+        Ok(Self { blobs })
     }
 
     fn serialize_payload(&self) -> Result<Vec<u8>, AppleCodesignError> {
-        Ok(self.data.to_vec())
+        let blobs = self
+            .blobs
+            .iter()
+            .map(|(slot, blob)| Ok((*slot, blob.to_blob_bytes()?)))
+            .collect::<Result<Vec<_>, AppleCodesignError>>()?;
+
+        // The superblob helper emits the magic and length header. Strip it off since
+        // the blob trait adds it back.
+        let mut data = create_superblob(CodeSigningMagic::EmbeddedSignatureOld, blobs.iter())?;
+        data.drain(0..8);
+
+        Ok(data)
+    }
+}
+
+impl<'a> EmbeddedSignatureOldBlob<'a> {
+    /// Find the blob occupying the specified slot.
+    pub fn find_slot(&self, slot: CodeSigningSlot) -> Option<&BlobData<'a>> {
+        self.blobs
+            .iter()
+            .find_map(|(s, blob)| if *s == slot { Some(blob) } else { None })
+    }
+
+    /// Register a blob into a slot.
+    ///
+    /// If the slot is already occupied, its blob is replaced. Otherwise the blob is
+    /// inserted so slots remain sorted.
+    pub fn set_blob(&mut self, slot: CodeSigningSlot, blob: BlobData<'a>) {
+        if let Some(entry) = self.blobs.iter_mut().find(|(s, _)| *s == slot) {
+            entry.1 = blob;
+        } else {
+            let index = self.blobs.partition_point(|(s, _)| *s < slot);
+            self.blobs.insert(index, (slot, blob));
+        }
     }
 }
 
@@ -936,14 +1132,122 @@ impl<'a> EntitlementsDerBlob<'a> {
 
         Ok(Self { der: der.into() })
     }
+
+    /// Decode the DER entitlements to a [plist::Value].
+    pub fn parse_der(&self) -> Result<plist::Value, AppleCodesignError> {
+        crate::der_plist::der_decode_plist(&self.der)
+    }
+}
+
+/// Launch or library constraints, as a DER encoded plist.
+///
+/// The plist is a dictionary holding the constraint category (`ccat`),
+/// compatibility version (`comp`), requirements (`reqs`), and version (`vers`).
+/// The requirements are the dictionary of facts and operators `codesign`
+/// reads from constraint plist files.
+#[derive(Debug)]
+pub struct ConstraintsDerBlob<'a> {
+    der: Cow<'a, [u8]>,
+}
+
+impl<'a> Blob<'a> for ConstraintsDerBlob<'a> {
+    fn magic() -> u32 {
+        u32::from(CodeSigningMagic::Constraints)
+    }
+
+    fn from_blob_bytes(data: &'a [u8]) -> Result<Self, AppleCodesignError> {
+        let der = read_and_validate_blob_header(data, Self::magic(), "DER constraints blob")?;
+
+        Ok(Self { der: der.into() })
+    }
+
+    fn serialize_payload(&self) -> Result<Vec<u8>, AppleCodesignError> {
+        Ok(self.der.to_vec())
+    }
+}
+
+impl<'a> ConstraintsDerBlob<'a> {
+    /// Construct an instance from constraint requirements.
+    ///
+    /// `requirements` is the dictionary of a constraint plist file. It is
+    /// wrapped in the structure `codesign` emits.
+    pub fn from_requirements(requirements: &plist::Value) -> Result<Self, AppleCodesignError> {
+        let mut dict = plist::Dictionary::new();
+        dict.insert("ccat".into(), 0.into());
+        dict.insert("comp".into(), 1.into());
+        dict.insert("reqs".into(), requirements.clone());
+        dict.insert("vers".into(), 1.into());
+
+        Ok(Self {
+            der: crate::der_plist::der_encode_plist(&dict.into())?.into(),
+        })
+    }
+
+    /// Decode the DER constraints to a [plist::Value].
+    pub fn parse_der(&self) -> Result<plist::Value, AppleCodesignError> {
+        crate::der_plist::der_decode_plist(&self.der)
+    }
+
+    /// Obtain the constraint requirements.
+    ///
+    /// This is the inverse of [Self::from_requirements()].
+    pub fn requirements(&self) -> Result<Option<plist::Value>, AppleCodesignError> {
+        Ok(match self.parse_der()? {
+            plist::Value::Dictionary(mut dict) => dict.remove("reqs"),
+            _ => None,
+        })
+    }
 }
 
 /// A detached signature.
+///
+/// This is a super blob holding an embedded signature super blob for each
+/// architecture of the signed binary, keyed by Mach-O CPU type. It is what
+/// `codesign --detached` writes when signing multiple architectures.
 #[derive(Debug)]
 pub struct DetachedSignatureBlob<'a> {
     data: &'a [u8],
 }
 
+impl<'a> DetachedSignatureBlob<'a> {
+    /// Resolve the embedded signature for each architecture.
+    ///
+    /// Entries are `(cputype, signature)` in the order they are recorded.
+    pub fn signatures(&self) -> Result<Vec<(u32, EmbeddedSignature<'a>)>, AppleCodesignError> {
+        let data = self.data;
+        let offset = &mut 0;
+
+        let count = data.gread_with::<u32>(offset, scroll::BE)?;
+
+        if count as usize > (data.len() - *offset) / 8 {
+            return Err(AppleCodesignError::SuperblobMalformed);
+        }
+
+        let mut indices = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            indices.push(data.gread_with::<BlobIndex>(offset, scroll::BE)?);
+        }
+
+        indices
+            .iter()
+            .map(|index| {
+                // Offsets are relative to the start of the blob, which includes the
+                // 8 byte header not present in our data.
+                let signature_data = (index.offset as usize)
+                    .checked_sub(8)
+                    .and_then(|start| data.get(start..))
+                    .ok_or(AppleCodesignError::SuperblobMalformed)?;
+                let (_, length, _) = read_blob_header(signature_data)?;
+                let signature_data = signature_data
+                    .get(0..length)
+                    .ok_or(AppleCodesignError::SuperblobMalformed)?;
+
+                Ok((index.typ, EmbeddedSignature::from_bytes(signature_data)?))
+            })
+            .collect()
+    }
+}
+
 impl<'a> Blob<'a> for DetachedSignatureBlob<'a> {
     fn magic() -> u32 {
         u32::from(CodeSigningMagic::DetachedSignature)
@@ -1052,6 +1356,7 @@ pub enum BlobData<'a> {
     EmbeddedSignatureOld(Box<EmbeddedSignatureOldBlob<'a>>),
     Entitlements(Box<EntitlementsBlob<'a>>),
     EntitlementsDer(Box<EntitlementsDerBlob<'a>>),
+    Constraints(Box<ConstraintsDerBlob<'a>>),
     DetachedSignature(Box<DetachedSignatureBlob<'a>>),
     BlobWrapper(Box<BlobWrapperBlob<'a>>),
     Other(Box<OtherBlob<'a>>),
@@ -1064,11 +1369,21 @@ impl<'a> Blob<'a> for BlobData<'a> {
 
     /// Parse blob data by reading its magic and feeding into magic-specific parser.
     fn from_blob_bytes(data: &'a [u8]) -> Result<Self, AppleCodesignError> {
-        let (magic, length, _) = read_blob_header(data)?;
-
-        // This should be a no-op. But it could (correctly) cause a panic if the
-        // advertised length is incorrect and we would incur a buffer overrun.
-        let data = &data[0..length];
+        let (magic, length, _) =
+            read_blob_header(data).map_err(|_| AppleCodesignError::TruncatedBlob {
+                what: "blob",
+                required: 8,
+                available: data.len(),
+            })?;
+
+        // This should be a no-op. But the advertised length could be incorrect.
+        let data = data
+            .get(0..length)
+            .ok_or(AppleCodesignError::TruncatedBlob {
+                what: "blob",
+                required: length,
+                available: data.len(),
+            })?;
 
         let magic = CodeSigningMagic::from(magic);
 
@@ -1094,6 +1409,9 @@ impl<'a> Blob<'a> for BlobData<'a> {
             CodeSigningMagic::EntitlementsDer => {
                 Self::EntitlementsDer(Box::new(EntitlementsDerBlob::from_blob_bytes(data)?))
             }
+            CodeSigningMagic::Constraints => {
+                Self::Constraints(Box::new(ConstraintsDerBlob::from_blob_bytes(data)?))
+            }
             CodeSigningMagic::DetachedSignature => {
                 Self::DetachedSignature(Box::new(DetachedSignatureBlob::from_blob_bytes(data)?))
             }
@@ -1113,6 +1431,7 @@ impl<'a> Blob<'a> for BlobData<'a> {
             Self::EmbeddedSignatureOld(b) => b.serialize_payload(),
             Self::Entitlements(b) => b.serialize_payload(),
             Self::EntitlementsDer(b) => b.serialize_payload(),
+            Self::Constraints(b) => b.serialize_payload(),
             Self::DetachedSignature(b) => b.serialize_payload(),
             Self::BlobWrapper(b) => b.serialize_payload(),
             Self::Other(b) => b.serialize_payload(),
@@ -1128,6 +1447,7 @@ impl<'a> Blob<'a> for BlobData<'a> {
             Self::EmbeddedSignatureOld(b) => b.to_blob_bytes(),
             Self::Entitlements(b) => b.to_blob_bytes(),
             Self::EntitlementsDer(b) => b.to_blob_bytes(),
+            Self::Constraints(b) => b.to_blob_bytes(),
             Self::DetachedSignature(b) => b.to_blob_bytes(),
             Self::BlobWrapper(b) => b.to_blob_bytes(),
             Self::Other(b) => b.to_blob_bytes(),
@@ -1177,6 +1497,12 @@ impl<'a> From<EntitlementsDerBlob<'a>> for BlobData<'a> {
     }
 }
 
+impl<'a> From<ConstraintsDerBlob<'a>> for BlobData<'a> {
+    fn from(b: ConstraintsDerBlob<'a>) -> Self {
+        Self::Constraints(Box::new(b))
+    }
+}
+
 impl<'a> From<DetachedSignatureBlob<'a>> for BlobData<'a> {
     fn from(b: DetachedSignatureBlob<'a>) -> Self {
         Self::DetachedSignature(Box::new(b))
@@ -1216,7 +1542,12 @@ impl<'a> TryFrom<BlobEntry<'a>> for ParsedBlob<'a> {
     type Error = AppleCodesignError;
 
     fn try_from(blob_entry: BlobEntry<'a>) -> Result<Self, Self::Error> {
-        let blob = BlobData::from_blob_bytes(blob_entry.data)?;
+        let blob =
+            BlobData::from_blob_bytes(blob_entry.data).map_err(|e| MachOParseError::Blob {
+                slot: blob_entry.slot,
+                offset: blob_entry.offset as _,
+                error: Box::new(e),
+            })?;
 
         Ok(Self { blob_entry, blob })
     }
@@ -1226,6 +1557,9 @@ impl<'a> TryFrom<BlobEntry<'a>> for ParsedBlob<'a> {
 ///
 /// This type represents a lightly parsed `SuperBlob` with [CodeSigningMagic::EmbeddedSignature].
 /// It is the most common embedded signature data format you are likely to encounter.
+///
+/// The legacy [CodeSigningMagic::EmbeddedSignatureOld] `SuperBlob` is also accepted.
+/// The [Self::magic] field can be used to distinguish between the two.
 pub struct EmbeddedSignature<'a> {
     /// Magic value from header.
     pub magic: CodeSigningMagic,
@@ -1253,65 +1587,172 @@ impl<'a> std::fmt::Debug for EmbeddedSignature<'a> {
 }
 
 // There are other impl blocks for this structure in other modules.
+/// Serializes the blob entries along with the parsed code directories,
+/// cdhashes, entitlements, and designated requirement.
+impl<'a> Serialize for EmbeddedSignature<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::Error;
+
+        let code_directories = self
+            .all_code_directories()
+            .map_err(S::Error::custom)?
+            .into_iter()
+            .collect::<BTreeMap<_, _>>();
+        let cdhashes = self
+            .cdhashes()
+            .map_err(S::Error::custom)?
+            .into_iter()
+            .map(|(digest_type, cdhash)| (digest_type.to_string(), hex::encode(cdhash)))
+            .collect::<BTreeMap<_, _>>();
+        let entitlements = self
+            .entitlements()
+            .map_err(S::Error::custom)?
+            .map(|blob| blob.as_str().to_string());
+        let designated_requirement = self
+            .designated_requirement()
+            .map_err(S::Error::custom)?
+            .map(|requirement| requirement.to_requirement_text())
+            .transpose()
+            .map_err(S::Error::custom)?;
+
+        let mut s = serializer.serialize_struct("EmbeddedSignature", 8)?;
+        s.serialize_field("magic", &self.magic)?;
+        s.serialize_field("length", &self.length)?;
+        s.serialize_field("count", &self.count)?;
+        s.serialize_field("blobs", &self.blobs)?;
+        s.serialize_field("code_directories", &code_directories)?;
+        s.serialize_field("cdhashes", &cdhashes)?;
+        s.serialize_field("entitlements", &entitlements)?;
+        s.serialize_field("designated_requirement", &designated_requirement)?;
+        s.end()
+    }
+}
+
 impl<'a> EmbeddedSignature<'a> {
     /// Attempt to parse an embedded signature super blob from data.
     ///
     /// The argument to this function is likely the subset of the
     /// `__LINKEDIT` Mach-O section that the `LC_CODE_SIGNATURE` load instructions
     /// points it.
+    ///
+    /// Blob indices must be sorted by offset and blobs must not overlap each
+    /// other. Blobs overlapping the index or extending beyond the length
+    /// advertised by the super blob are tolerated. Use
+    /// [Self::from_bytes_strict()] to reject such layouts.
     pub fn from_bytes(data: &'a [u8]) -> Result<Self, AppleCodesignError> {
+        Self::parse(data, false)
+    }
+
+    /// Attempt to parse an embedded signature super blob, validating its layout.
+    ///
+    /// In addition to the checks performed by [Self::from_bytes()], blobs must
+    /// not overlap the index or extend beyond the length advertised by the
+    /// super blob. Signatures produced by Apple's tooling and this crate satisfy
+    /// these constraints.
+    pub fn from_bytes_strict(data: &'a [u8]) -> Result<Self, AppleCodesignError> {
+        Self::parse(data, true)
+    }
+
+    fn parse(data: &'a [u8], strict: bool) -> Result<Self, AppleCodesignError> {
         let offset = &mut 0;
 
         // Parse the 3 fields from the SuperBlob.
         let magic = data.gread_with::<u32>(offset, scroll::BE)?.into();
 
-        if magic != CodeSigningMagic::EmbeddedSignature {
-            return Err(AppleCodesignError::BadMagic(
-                "embedded signature super blob",
-            ));
+        if !matches!(
+            magic,
+            CodeSigningMagic::EmbeddedSignature | CodeSigningMagic::EmbeddedSignatureOld
+        ) {
+            return Err(AppleCodesignError::MagicMismatch {
+                what: "embedded signature super blob",
+                expected: u32::from(CodeSigningMagic::EmbeddedSignature),
+                actual: u32::from(magic),
+            });
         }
 
-        let length = data.gread_with(offset, scroll::BE)?;
-        let count = data.gread_with(offset, scroll::BE)?;
+        let length = data.gread_with::<u32>(offset, scroll::BE)?;
+        let count = data.gread_with::<u32>(offset, scroll::BE)?;
 
         // Following the SuperBlob header is an array of .count BlobIndex defining
         // the Blob that follow.
         //
-        // The BlobIndex doesn't declare the length of each Blob. However, it appears
-        // the first 8 bytes of each blob contain the u32 magic and u32 length.
-        // We do parse those here and set the blob length/slice accordingly. However,
-        // we take an extra level of precaution by first computing a slice that doesn't
-        // overrun into the next blob or past the end of the input buffer. This
-        // helps detect invalid length advertisements in the blob payload.
+        // The BlobIndex doesn't declare the length of each Blob. However, the
+        // first 8 bytes of each blob contain the u32 magic and u32 length. We
+        // parse those here and set the blob length/slice accordingly. The
+        // self-reported length must fit within the input buffer.
+        //
+        // The count comes from untrusted data. Validate it against the space
+        // available for index entries before allocating.
+        if count as usize > (data.len() - *offset) / 8 {
+            return Err(AppleCodesignError::SuperblobMalformed);
+        }
+
         let mut blob_indices = Vec::with_capacity(count as usize);
         for _ in 0..count {
             blob_indices.push(data.gread_with::<BlobIndex>(offset, scroll::BE)?);
         }
 
+        let end_offset = if strict {
+            if length as usize > data.len() {
+                return Err(AppleCodesignError::SuperblobMalformed);
+            }
+
+            length as usize
+        } else {
+            data.len()
+        };
+
         let mut blobs = Vec::with_capacity(blob_indices.len());
+        // End of the previous blob. Strict parsing also rejects blobs overlapping
+        // the index.
+        let mut previous_end = if strict { *offset } else { 0 };
 
         for (i, index) in blob_indices.iter().enumerate() {
-            let end_offset = if i == blob_indices.len() - 1 {
-                data.len()
-            } else {
-                blob_indices[i + 1].offset as usize
+            let slot = CodeSigningSlot::from(index.typ);
+            let out_of_bounds = || MachOParseError::BlobOutOfBounds {
+                index: i,
+                slot,
+                offset: index.offset as _,
             };
 
-            let full_slice = &data[index.offset as usize..end_offset];
-            let (magic, blob_length, _) = read_blob_header(full_slice)?;
+            let full_slice = data
+                .get(index.offset as usize..end_offset)
+                .ok_or_else(out_of_bounds)?;
+            let (magic, blob_length, _) =
+                read_blob_header(full_slice).map_err(|_| out_of_bounds())?;
 
             // Self-reported length can't be greater than the data we have.
             let blob_data = match blob_length.cmp(&full_slice.len()) {
                 Ordering::Greater => {
-                    return Err(AppleCodesignError::SuperblobMalformed);
+                    return Err(out_of_bounds().into());
                 }
                 Ordering::Equal => full_slice,
                 Ordering::Less => &full_slice[0..blob_length],
             };
 
+            if i > 0 && index.offset <= blob_indices[i - 1].offset {
+                return Err(MachOParseError::BlobIndexOutOfOrder {
+                    index: i,
+                    slot,
+                    offset: index.offset as _,
+                }
+                .into());
+            }
+            if (index.offset as usize) < previous_end {
+                return Err(MachOParseError::BlobOverlap {
+                    index: i,
+                    slot,
+                    offset: index.offset as _,
+                    previous_end: previous_end as _,
+                }
+                .into());
+            }
+
+            previous_end = index.offset as usize + blob_length;
+
             blobs.push(BlobEntry {
                 index: i,
-                slot: index.typ.into(),
+                slot,
                 offset: index.offset as usize,
                 magic: magic.into(),
                 length: blob_length,
@@ -1328,6 +1769,11 @@ impl<'a> EmbeddedSignature<'a> {
         })
     }
 
+    /// Whether this signature uses the legacy `SuperBlob` magic.
+    pub fn is_legacy(&self) -> bool {
+        self.magic == CodeSigningMagic::EmbeddedSignatureOld
+    }
+
     /// Find the first occurrence of the specified slot.
     pub fn find_slot(&self, slot: CodeSigningSlot) -> Option<&BlobEntry<'a>> {
         self.blobs.iter().find(|e| e.slot == slot)
@@ -1355,7 +1801,10 @@ impl<'a> EmbeddedSignature<'a> {
             if let BlobData::CodeDirectory(cd) = parsed.blob {
                 Ok(Some(cd))
             } else {
-                Err(AppleCodesignError::BadMagic("code directory blob"))
+                Err(AppleCodesignError::WrongBlobType {
+                    slot: CodeSigningSlot::CodeDirectory,
+                    expected: "code directory blob",
+                })
             }
         } else {
             Ok(None)
@@ -1384,9 +1833,10 @@ impl<'a> EmbeddedSignature<'a> {
                 if let BlobData::CodeDirectory(cd) = parsed.blob {
                     res.push((slot, cd));
                 } else {
-                    return Err(AppleCodesignError::BadMagic(
-                        "wrong blob magic in alternative code directory slot",
-                    ));
+                    return Err(AppleCodesignError::WrongBlobType {
+                        slot,
+                        expected: "code directory blob",
+                    });
                 }
             }
         }
@@ -1409,6 +1859,28 @@ impl<'a> EmbeddedSignature<'a> {
         Ok(res)
     }
 
+    /// Resolve the cdhash of every code directory in this signature.
+    ///
+    /// A cdhash is the digest of the code directory's raw blob data using the code
+    /// directory's own digest type, truncated to [CS_CDHASH_LEN] bytes. Results are in the order of
+    /// [Self::all_code_directories()].
+    pub fn cdhashes(&self) -> Result<Vec<(DigestType, Vec<u8>)>, AppleCodesignError> {
+        let mut res = vec![];
+
+        for (slot, cd) in self.all_code_directories()? {
+            let entry = self
+                .find_slot(slot)
+                .expect("code directory should have a blob entry");
+
+            let mut digest = entry.digest_with(cd.digest_type)?;
+            digest.truncate(CS_CDHASH_LEN);
+
+            res.push((cd.digest_type, digest));
+        }
+
+        Ok(res)
+    }
+
     /// Attempt to resolve a code directory containing digests of the specified type.
     pub fn code_directory_for_digest(
         &self,
@@ -1452,13 +1924,70 @@ impl<'a> EmbeddedSignature<'a> {
             if let BlobData::Entitlements(entitlements) = parsed.blob {
                 Ok(Some(entitlements))
             } else {
-                Err(AppleCodesignError::BadMagic("entitlements blob"))
+                Err(AppleCodesignError::WrongBlobType {
+                    slot: CodeSigningSlot::Entitlements,
+                    expected: "entitlements blob",
+                })
             }
         } else {
             Ok(None)
         }
     }
 
+    /// Attempt to resolve a parsed [EntitlementsDerBlob] for this signature data.
+    ///
+    /// Returns Err on data parsing error or if the blob slot didn't contain a DER
+    /// entitlements blob.
+    ///
+    /// Returns `Ok(None)` if there is no DER entitlements slot.
+    pub fn entitlements_der(
+        &self,
+    ) -> Result<Option<Box<EntitlementsDerBlob<'a>>>, AppleCodesignError> {
+        if let Some(parsed) = self.find_slot_parsed(CodeSigningSlot::EntitlementsDer)? {
+            if let BlobData::EntitlementsDer(entitlements) = parsed.blob {
+                Ok(Some(entitlements))
+            } else {
+                Err(AppleCodesignError::WrongBlobType {
+                    slot: CodeSigningSlot::EntitlementsDer,
+                    expected: "DER entitlements blob",
+                })
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Attempt to resolve a parsed [ConstraintsDerBlob] in a constraints slot.
+    ///
+    /// Returns Err on data parsing error or if the blob slot didn't contain a
+    /// constraints blob.
+    ///
+    /// Returns `Ok(None)` if there is no such slot.
+    pub fn constraints(
+        &self,
+        slot: CodeSigningSlot,
+    ) -> Result<Option<Box<ConstraintsDerBlob<'a>>>, AppleCodesignError> {
+        if let Some(parsed) = self.find_slot_parsed(slot)? {
+            if let BlobData::Constraints(constraints) = parsed.blob {
+                Ok(Some(constraints))
+            } else {
+                Err(AppleCodesignError::WrongBlobType {
+                    slot,
+                    expected: "DER constraints blob",
+                })
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Attempt to resolve the [ConstraintsDerBlob] constraining loaded libraries.
+    pub fn library_constraints(
+        &self,
+    ) -> Result<Option<Box<ConstraintsDerBlob<'a>>>, AppleCodesignError> {
+        self.constraints(CodeSigningSlot::LibraryConstraints)
+    }
+
     /// Attempt to resolve a parsed [RequirementSetBlob] for this signature data.
     ///
     /// Returns Err on data parsing error or if the blob slot didn't contain a requirements
@@ -1472,13 +2001,34 @@ impl<'a> EmbeddedSignature<'a> {
             if let BlobData::RequirementSet(reqs) = parsed.blob {
                 Ok(Some(reqs))
             } else {
-                Err(AppleCodesignError::BadMagic("requirements blob"))
+                Err(AppleCodesignError::WrongBlobType {
+                    slot: CodeSigningSlot::RequirementSet,
+                    expected: "requirement set blob",
+                })
             }
         } else {
             Ok(None)
         }
     }
 
+    /// Attempt to resolve the explicit designated requirement of this signature.
+    ///
+    /// Returns `Ok(None)` if the signature doesn't have an explicit designated
+    /// requirement, as is common for ad-hoc signatures. The operating system
+    /// derives an implicit designated requirement for these signatures. See
+    /// `implicit_designated_requirement()`, which needs the `full` feature.
+    pub fn designated_requirement(
+        &self,
+    ) -> Result<Option<DesignatedRequirement>, AppleCodesignError> {
+        if let Some(requirements) = self.code_requirements()? {
+            if let Some(blob) = requirements.requirements.get(&RequirementType::Designated) {
+                return Ok(Some(DesignatedRequirement::from_binary(&blob.data)?));
+            }
+        }
+
+        Ok(None)
+    }
+
     /// Attempt to resolve raw CMS signature data.
     ///
     /// The returned data is likely DER PKCS#7 with the root object
@@ -1495,6 +2045,7 @@ impl<'a> EmbeddedSignature<'a> {
     }
 
     /// Obtain the parsed CMS [SignedData].
+    #[cfg(feature = "full")]
     pub fn signed_data(&self) -> Result<Option<SignedData>, AppleCodesignError> {
         if let Some(data) = self.signature_data()? {
             // Sometime we get an empty data slice. This has been observed on DMG signatures.
@@ -1510,4 +2061,522 @@ impl<'a> EmbeddedSignature<'a> {
             Ok(None)
         }
     }
+
+    /// Obtain a summary of the CMS signer.
+    ///
+    /// Returns `Ok(None)` for signatures without CMS data, such as ad-hoc signatures.
+    #[cfg(feature = "full")]
+    pub fn signer_details(&self) -> Result<Option<CmsSignerDetails>, AppleCodesignError> {
+        Ok(self
+            .signed_data()?
+            .and_then(|signed_data| CmsSignerDetails::from_signed_data(&signed_data)))
+    }
+
+    /// Derive the designated requirement the operating system uses in the absence
+    /// of an explicit one.
+    ///
+    /// Like `codesign`, ad-hoc signatures are designated by the cdhash of their
+    /// preferred code directory. Signatures with a CMS signature are designated by
+    /// their identifier and signing certificate chain, as described by
+    /// [crate::derive_designated_requirements_from_chain()].
+    ///
+    /// This ignores any explicit designated requirement. Returns `Ok(None)` if there
+    /// is no code directory or no requirement can be derived for the signing
+    /// certificate.
+    #[cfg(feature = "full")]
+    pub fn implicit_designated_requirement(
+        &self,
+    ) -> Result<Option<DesignatedRequirement>, AppleCodesignError> {
+        let cd = match self.preferred_code_directory() {
+            Ok(cd) => cd,
+            Err(AppleCodesignError::BinaryNoCodeDirectory) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let expr = if let Some(signer) = self.signer_details()? {
+            let (cert, chain) = match signer.certificate_chain.split_first() {
+                Some(certs) => certs,
+                None => return Ok(None),
+            };
+
+            match derive_designated_requirements_from_chain(
+                cert,
+                chain,
+                Some(cd.ident.to_string()),
+            )? {
+                Some(expr) => expr,
+                None => return Ok(None),
+            }
+        } else {
+            match self
+                .cdhashes()?
+                .into_iter()
+                .find(|(digest_type, _)| *digest_type == cd.digest_type)
+            {
+                Some((_, cdhash)) => CodeRequirementExpression::CodeDirectoryHash(cdhash.into()),
+                None => return Ok(None),
+            }
+        };
+
+        Ok(Some(DesignatedRequirement::from_expression(&expr)?))
+    }
+}
+
+/// Commonly needed properties of the signer of a CMS signature.
+///
+/// This saves callers from walking the [SignedData] and its signer info
+/// themselves.
+#[cfg(feature = "full")]
+#[derive(Clone, Debug)]
+pub struct CmsSignerDetails {
+    /// Common name (CN) of the signing certificate.
+    pub common_name: Option<String>,
+
+    /// Team ID (the OU of the signing certificate).
+    pub team_id: Option<String>,
+
+    /// Time the signer claims to have signed at.
+    ///
+    /// This comes from the signed attributes and isn't attested by a
+    /// time-stamp server.
+    pub signing_time: Option<DateTime<Utc>>,
+
+    /// Digest algorithm used by the signer.
+    pub digest_algorithm: DigestAlgorithm,
+
+    /// Certificate chain of the signer, leaf first.
+    ///
+    /// Certificates not present in the CMS data are resolved from Apple's
+    /// known certificates.
+    pub certificate_chain: Vec<CapturedX509Certificate>,
+}
+
+#[cfg(feature = "full")]
+impl CmsSignerDetails {
+    /// Resolve details of the first signer in [SignedData].
+    ///
+    /// Returns `None` if there are no signers.
+    pub fn from_signed_data(signed_data: &SignedData) -> Option<Self> {
+        let signer = signed_data.signers().next()?;
+        let certificate_chain = signer_certificate_chain(signed_data, signer);
+        let leaf = certificate_chain.first();
+
+        Some(Self {
+            common_name: leaf.and_then(|cert| cert.subject_common_name()),
+            team_id: leaf.and_then(|cert| cert.apple_team_id()),
+            signing_time: signer
+                .signed_attributes()
+                .and_then(|attributes| attributes.signing_time())
+                .copied(),
+            digest_algorithm: signer.digest_algorithm(),
+            certificate_chain,
+        })
+    }
+
+    /// The signing (leaf) certificate.
+    pub fn signing_certificate(&self) -> Option<&CapturedX509Certificate> {
+        self.certificate_chain.first()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn embedded_signature_old_roundtrip() -> Result<(), AppleCodesignError> {
+        let mut blob = EmbeddedSignatureOldBlob::default();
+        blob.set_blob(
+            CodeSigningSlot::Entitlements,
+            EntitlementsBlob::from_string("<plist/>").into(),
+        );
+        blob.set_blob(
+            CodeSigningSlot::RequirementSet,
+            RequirementSetBlob::default().into(),
+        );
+
+        assert_eq!(blob.blobs[0].0, CodeSigningSlot::RequirementSet);
+        assert_eq!(blob.blobs[1].0, CodeSigningSlot::Entitlements);
+
+        let data = blob.to_blob_bytes()?;
+
+        let signature = EmbeddedSignature::from_bytes(&data)?;
+        assert!(signature.is_legacy());
+        assert_eq!(signature.count, 2);
+        assert_eq!(signature.length as usize, data.len());
+        assert_eq!(signature.entitlements()?.unwrap().as_str(), "<plist/>");
+
+        let parsed = EmbeddedSignatureOldBlob::from_blob_bytes(&data)?;
+        assert_eq!(parsed.blobs.len(), 2);
+        assert!(matches!(
+            parsed.find_slot(CodeSigningSlot::Entitlements),
+            Some(BlobData::Entitlements(_))
+        ));
+        assert_eq!(parsed.to_blob_bytes()?, data);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "full")]
+    fn cdhashes() -> Result<(), AppleCodesignError> {
+        let mut builder = crate::embedded_signature_builder::EmbeddedSignatureBuilder::default();
+
+        for digest_type in [DigestType::Sha256, DigestType::Sha384] {
+            let cd = CodeDirectoryBlob {
+                digest_size: digest_type.hash_len()? as u8,
+                digest_type,
+                page_size: 4096,
+                ident: "com.example.cdhash".into(),
+                ..Default::default()
+            };
+
+            if digest_type == DigestType::Sha256 {
+                builder.add_code_directory(CodeSigningSlot::CodeDirectory, cd)?;
+            } else {
+                builder.add_alternative_code_directory(cd)?;
+            }
+        }
+
+        let data = builder.create_superblob()?;
+        let signature = EmbeddedSignature::from_bytes(&data)?;
+
+        let cdhashes = signature.cdhashes()?;
+        assert_eq!(cdhashes.len(), 2);
+        assert_eq!(cdhashes[0].0, DigestType::Sha256);
+        assert_eq!(cdhashes[1].0, DigestType::Sha384);
+
+        let entry = signature
+            .find_slot(CodeSigningSlot::AlternateCodeDirectory0)
+            .unwrap();
+        assert_eq!(
+            cdhashes[1].1,
+            DigestType::Sha384.digest_data(entry.data)?[0..CS_CDHASH_LEN]
+        );
+
+        // Code directories serialize back to their original bytes.
+        for ((_, cd), (_, cdhash)) in signature.all_code_directories()?.iter().zip(&cdhashes) {
+            assert_eq!(&cd.cdhash()?, cdhash);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn requirement_set_ordering() -> Result<(), AppleCodesignError> {
+        let blob = |data: &'static [u8]| RequirementBlob {
+            data: Cow::Borrowed(data),
+        };
+
+        let mut a = RequirementSetBlob::default();
+        a.set_requirements(RequirementType::Designated, blob(&[0, 0, 0, 1]));
+        a.set_requirements(RequirementType::Host, blob(&[0, 0, 0, 2]));
+
+        let mut b = RequirementSetBlob::default();
+        b.set_requirements(RequirementType::Host, blob(&[0, 0, 0, 2]));
+        b.set_requirements(RequirementType::Designated, blob(&[0, 0, 0, 1]));
+
+        let data = a.to_blob_bytes()?;
+        assert_eq!(data, b.to_blob_bytes()?);
+
+        let parsed = RequirementSetBlob::from_blob_bytes(&data)?;
+        assert_eq!(
+            parsed.requirements.keys().copied().collect::<Vec<_>>(),
+            vec![RequirementType::Host, RequirementType::Designated]
+        );
+        assert_eq!(format!("{parsed:?}"), format!("{a:?}"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn untrusted_counts() {
+        // SuperBlob claiming u32::MAX blobs.
+        let data = [0xfa, 0xde, 0x0c, 0xc0, 0, 0, 0, 12, 0xff, 0xff, 0xff, 0xff];
+        assert!(matches!(
+            EmbeddedSignature::from_bytes(&data),
+            Err(AppleCodesignError::SuperblobMalformed)
+        ));
+
+        // SuperBlob with a blob offset beyond the data.
+        let data = [
+            0xfa, 0xde, 0x0c, 0xc0, 0, 0, 0, 20, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0xff, 0xff,
+        ];
+        assert!(matches!(
+            EmbeddedSignature::from_bytes(&data),
+            Err(AppleCodesignError::MachOParse(
+                MachOParseError::BlobOutOfBounds {
+                    index: 0,
+                    slot: CodeSigningSlot::CodeDirectory,
+                    offset: 0xffff,
+                }
+            ))
+        ));
+
+        // Requirement set claiming u32::MAX requirements.
+        let data = [0xfa, 0xde, 0x0c, 0x01, 0, 0, 0, 12, 0xff, 0xff, 0xff, 0xff];
+        assert!(matches!(
+            RequirementSetBlob::from_blob_bytes(&data),
+            Err(AppleCodesignError::RequirementMalformed(_))
+        ));
+    }
+
+    #[test]
+    fn strict_layout() -> Result<(), AppleCodesignError> {
+        // Two 12 byte entitlements blobs following a 28 byte header and index.
+        let superblob = |offsets: [u32; 2], first_length: u32, length: u32| {
+            let mut data = vec![];
+            for v in [0xfade0cc0, length, 2, 5, offsets[0], 7, offsets[1]] {
+                data.extend(u32::to_be_bytes(v));
+            }
+            for (blob_length, payload) in [(first_length, b"abcd"), (12, b"efgh")] {
+                data.extend(0xfade7171u32.to_be_bytes());
+                data.extend(blob_length.to_be_bytes());
+                data.extend(payload);
+            }
+
+            data
+        };
+
+        let data = superblob([28, 40], 12, 52);
+        let strict = EmbeddedSignature::from_bytes_strict(&data)?;
+        assert_eq!(strict.blobs.len(), 2);
+        assert_eq!(strict.blobs[1].data, &data[40..52]);
+
+        // Indices out of order, both indices referencing the same blob, and the
+        // first blob overlapping the second are rejected by both parsers.
+        for (data, expected) in [
+            (
+                superblob([40, 28], 12, 52),
+                MachOParseError::BlobIndexOutOfOrder {
+                    index: 1,
+                    slot: CodeSigningSlot::EntitlementsDer,
+                    offset: 28,
+                },
+            ),
+            (
+                superblob([28, 28], 12, 52),
+                MachOParseError::BlobIndexOutOfOrder {
+                    index: 1,
+                    slot: CodeSigningSlot::EntitlementsDer,
+                    offset: 28,
+                },
+            ),
+            (
+                superblob([28, 40], 16, 52),
+                MachOParseError::BlobOverlap {
+                    index: 1,
+                    slot: CodeSigningSlot::EntitlementsDer,
+                    offset: 40,
+                    previous_end: 44,
+                },
+            ),
+        ] {
+            for res in [
+                EmbeddedSignature::from_bytes(&data),
+                EmbeddedSignature::from_bytes_strict(&data),
+            ] {
+                match res {
+                    Err(AppleCodesignError::MachOParse(e)) => {
+                        assert_eq!(e.to_string(), expected.to_string())
+                    }
+                    res => panic!("unexpected result: {:?}", res.map(|_| ())),
+                }
+            }
+        }
+
+        // Blob overlapping the index entry referencing it.
+        let data = [0xfade0cc0u32, 24, 1, 5, 16, 8]
+            .into_iter()
+            .flat_map(u32::to_be_bytes)
+            .collect::<Vec<_>>();
+        assert_eq!(EmbeddedSignature::from_bytes(&data)?.blobs[0].length, 8);
+        assert!(matches!(
+            EmbeddedSignature::from_bytes_strict(&data),
+            Err(AppleCodesignError::MachOParse(
+                MachOParseError::BlobOverlap {
+                    index: 0,
+                    offset: 16,
+                    previous_end: 20,
+                    ..
+                }
+            ))
+        ));
+
+        // Blob data extending past the advertised super blob length.
+        let data = superblob([28, 40], 12, 48);
+        assert_eq!(EmbeddedSignature::from_bytes(&data)?.blobs.len(), 2);
+        assert!(matches!(
+            EmbeddedSignature::from_bytes_strict(&data),
+            Err(AppleCodesignError::MachOParse(
+                MachOParseError::BlobOutOfBounds {
+                    index: 1,
+                    slot: CodeSigningSlot::EntitlementsDer,
+                    offset: 40,
+                }
+            ))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn blob_header_errors() -> Result<(), AppleCodesignError> {
+        let data = EntitlementsBlob::from_string("<plist/>").to_blob_bytes()?;
+        assert!(EntitlementsBlob::from_blob_bytes(&data).is_ok());
+
+        assert!(matches!(
+            RequirementBlob::from_blob_bytes(&data),
+            Err(AppleCodesignError::MagicMismatch {
+                expected: 0xfade0c00,
+                actual: 0xfade7171,
+                ..
+            })
+        ));
+        assert!(matches!(
+            EntitlementsBlob::from_blob_bytes(&data[0..4]),
+            Err(AppleCodesignError::TruncatedBlob {
+                required: 8,
+                available: 4,
+                ..
+            })
+        ));
+        assert!(matches!(
+            EntitlementsBlob::from_blob_bytes(&data[0..10]),
+            Err(AppleCodesignError::TruncatedBlob {
+                required: 16,
+                available: 10,
+                ..
+            })
+        ));
+
+        let mut padded = data.clone();
+        padded.extend_from_slice(&[0; 4]);
+        assert!(matches!(
+            EntitlementsBlob::from_blob_bytes(&padded),
+            Err(AppleCodesignError::LengthMismatch {
+                declared: 16,
+                actual: 20,
+                ..
+            })
+        ));
+
+        let superblob = create_superblob(
+            CodeSigningMagic::EmbeddedSignature,
+            [(CodeSigningSlot::CodeDirectory, data)].iter(),
+        )?;
+        let signature = EmbeddedSignature::from_bytes(&superblob)?;
+        assert!(matches!(
+            signature.code_directory(),
+            Err(AppleCodesignError::WrongBlobType {
+                slot: CodeSigningSlot::CodeDirectory,
+                ..
+            })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "full")]
+    fn designated_requirement() -> Result<(), AppleCodesignError> {
+        use crate::{
+            code_requirement::CodeRequirementExpression,
+            macho::MachFile,
+            macho_signing::MachOSigner,
+            signing_settings::{SettingsScope, SigningSettings},
+        };
+
+        let macho = crate::test_support::SyntheticMachO::default().build();
+
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(SettingsScope::Main, "binary");
+
+        let mut signed = vec![];
+        MachOSigner::new(&macho)?.write_signed_binary(&settings, &mut signed)?;
+        let mach = MachFile::parse(&signed)?;
+        let signature = mach.nth_macho(0)?.code_signature()?.unwrap();
+        assert!(signature.designated_requirement()?.is_none());
+
+        // Ad-hoc signatures are implicitly designated by their cdhash.
+        let (_, cdhash) = signature.cdhashes()?.remove(0);
+        let implicit = signature.implicit_designated_requirement()?.unwrap();
+        assert_eq!(
+            implicit.to_requirement_text()?,
+            format!("cdhash H\"{}\"", hex::encode(&cdhash))
+        );
+        assert_eq!(
+            DesignatedRequirement::from_requirement_text(&implicit.to_requirement_text()?)?,
+            implicit
+        );
+
+        settings.set_designated_requirement_expression(
+            SettingsScope::Main,
+            &CodeRequirementExpression::Identifier("binary".into()),
+        )?;
+
+        let mut signed = vec![];
+        MachOSigner::new(&macho)?.write_signed_binary(&settings, &mut signed)?;
+        let mach = MachFile::parse(&signed)?;
+        let signature = mach.nth_macho(0)?.code_signature()?.unwrap();
+        let requirement = signature.designated_requirement()?.unwrap();
+        assert_eq!(requirement.to_requirement_text()?, "identifier \"binary\"");
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "full")]
+    fn serialize() -> Result<(), AppleCodesignError> {
+        let mut builder = crate::embedded_signature_builder::EmbeddedSignatureBuilder::default();
+        builder.add_blob(
+            CodeSigningSlot::Entitlements,
+            EntitlementsBlob::from_string("<plist/>").into(),
+        )?;
+        builder.add_code_directory(
+            CodeSigningSlot::CodeDirectory,
+            CodeDirectoryBlob {
+                flags: crate::code_directory::CodeSignatureFlags::ADHOC,
+                digest_size: 32,
+                digest_type: DigestType::Sha256,
+                page_size: 4096,
+                ident: "com.example.serialize".into(),
+                code_digests: vec![vec![0x42; 32].into()].try_into()?,
+                ..Default::default()
+            },
+        )?;
+
+        let data = builder.create_superblob()?;
+        let signature = EmbeddedSignature::from_bytes(&data)?;
+        let cdhash = hex::encode(&signature.cdhashes()?[0].1);
+
+        let value = serde_json::to_value(&signature).unwrap();
+
+        assert_eq!(value["magic"], "fade0cc0");
+        assert_eq!(value["count"], signature.blobs.len());
+        assert_eq!(value["blobs"][0]["slot"], "CodeDirectory (0)");
+        assert_eq!(value["blobs"][0]["magic"], "fade0c02");
+        assert_eq!(
+            value["blobs"][0]["sha256"],
+            hex::encode(signature.blobs[0].digest_with(DigestType::Sha256)?)
+        );
+        assert_eq!(value["cdhashes"]["sha256"], cdhash);
+        assert_eq!(value["entitlements"], "<plist/>");
+        assert_eq!(value["designated_requirement"], serde_json::Value::Null);
+
+        let cd = &value["code_directories"]["CodeDirectory (0)"];
+        assert_eq!(cd["ident"], "com.example.serialize");
+        assert_eq!(cd["flags"], "0x2(adhoc)");
+        assert_eq!(cd["digest_type"], "sha256");
+        assert_eq!(cd["code_digests"][0], "42".repeat(32));
+        assert_eq!(
+            cd["special_digests"]["Entitlements (5)"]
+                .as_str()
+                .unwrap()
+                .len(),
+            64
+        );
+
+        Ok(())
+    }
 }
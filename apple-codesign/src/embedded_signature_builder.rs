@@ -12,15 +12,26 @@ use {
             EmbeddedSignature,
         },
         error::AppleCodesignError,
+        retry::RetryPolicy,
+    },
+    bcder::{
+        encode::{PrimitiveContent, Values},
+        Oid,
     },
-    bcder::{encode::PrimitiveContent, Oid},
     bytes::Bytes,
-    cryptographic_message_syntax::{asn1::rfc5652::OID_ID_DATA, SignedDataBuilder, SignerBuilder},
+    cryptographic_message_syntax::{
+        asn1::{
+            rfc3161::OID_TIME_STAMP_TOKEN,
+            rfc5652::{self, UnsignedAttributes, OID_ID_DATA},
+        },
+        time_stamp_message_http, CmsError, SignedDataBuilder, SignerBuilder, TimeStampError,
+    },
     log::{info, warn},
     reqwest::Url,
     std::collections::BTreeMap,
     x509_certificate::{
-        rfc5652::AttributeValue, CapturedX509Certificate, DigestAlgorithm, KeyInfoSigner,
+        rfc5652::{Attribute, AttributeValue},
+        CapturedX509Certificate, DigestAlgorithm, KeyInfoSigner,
     },
 };
 
@@ -162,9 +173,7 @@ impl<'a> EmbeddedSignatureBuilder<'a> {
                 continue;
             }
 
-            let digest = blob.digest_with(cd.digest_type)?;
-
-            cd.set_slot_digest(*slot, digest)?;
+            cd.set_slot_blob_digest(*slot, blob)?;
         }
 
         self.blobs.insert(cd_slot, cd.into());
@@ -204,7 +213,8 @@ impl<'a> EmbeddedSignatureBuilder<'a> {
     /// cryptographic signature.
     ///
     /// `time_stamp_url` is an optional time-stamp protocol server to use to record
-    /// the signature in.
+    /// the signature in. Failed requests to it are retried according to
+    /// `retry_policy`.
     ///
     /// `certificates` are extra X.509 certificates to register in the signing chain.
     ///
@@ -214,85 +224,41 @@ impl<'a> EmbeddedSignatureBuilder<'a> {
         signing_key: &dyn KeyInfoSigner,
         signing_cert: &CapturedX509Certificate,
         time_stamp_url: Option<&Url>,
+        retry_policy: &RetryPolicy,
         certificates: impl Iterator<Item = CapturedX509Certificate>,
     ) -> Result<(), AppleCodesignError> {
-        let main_cd = self
-            .code_directory()
-            .ok_or(AppleCodesignError::SignatureBuilder(
+        if self.code_directory().is_none() {
+            return Err(AppleCodesignError::SignatureBuilder(
                 "cannot create CMS signature unless code directory is present",
-            ))?;
-
-        if let Some(cn) = signing_cert.subject_common_name() {
-            warn!("creating cryptographic signature with certificate {}", cn);
+            ));
         }
 
-        let mut cdhashes = vec![];
-        let mut attributes = vec![];
-
-        for (slot, blob) in &self.blobs {
-            if *slot == CodeSigningSlot::CodeDirectory || slot.is_alternative_code_directory() {
+        // The primary code directory sorts first, followed by alternatives.
+        let code_directories = self
+            .blobs
+            .iter()
+            .filter(|(slot, _)| {
+                **slot == CodeSigningSlot::CodeDirectory || slot.is_alternative_code_directory()
+            })
+            .map(|(_, blob)| {
                 if let BlobData::CodeDirectory(cd) = blob {
-                    // plist digests use the native digest of the code directory but always
-                    // truncated at 20 bytes.
-                    let mut digest = cd.digest_with(cd.digest_type)?;
-                    digest.truncate(20);
-                    cdhashes.push(plist::Value::Data(digest));
-
-                    // ASN.1 values are a SEQUENCE of (OID, OctetString) with the native
-                    // digest.
-                    let digest = cd.digest_with(cd.digest_type)?;
-                    let alg = DigestAlgorithm::try_from(cd.digest_type)?;
-
-                    attributes.push(AttributeValue::new(bcder::Captured::from_values(
-                        bcder::Mode::Der,
-                        bcder::encode::sequence((
-                            Oid::from(alg).encode_ref(),
-                            bcder::OctetString::new(digest.into()).encode_ref(),
-                        )),
-                    )));
+                    Ok(cd.as_ref())
                 } else {
-                    return Err(AppleCodesignError::SignatureBuilder(
+                    Err(AppleCodesignError::SignatureBuilder(
                         "unexpected blob type in code directory slot",
-                    ));
+                    ))
                 }
-            }
-        }
-
-        let mut plist_dict = plist::Dictionary::new();
-        plist_dict.insert("cdhashes".to_string(), plist::Value::Array(cdhashes));
-
-        let mut plist_xml = vec![];
-        plist::Value::from(plist_dict)
-            .to_writer_xml(&mut plist_xml)
-            .map_err(AppleCodesignError::CodeDirectoryPlist)?;
-        // We also need to include a trailing newline to conform with Apple's XML
-        // writer.
-        plist_xml.push(b'\n');
-
-        let signer = SignerBuilder::new(signing_key, signing_cert.clone())
-            .message_id_content(main_cd.to_blob_bytes()?)
-            .signed_attribute_octet_string(
-                Oid(Bytes::copy_from_slice(CD_DIGESTS_PLIST_OID.as_ref())),
-                &plist_xml,
-            );
-
-        let signer = signer.signed_attribute(Oid(CD_DIGESTS_OID.as_ref().into()), attributes);
-
-        let signer = if let Some(time_stamp_url) = time_stamp_url {
-            info!("Using time-stamp server {}", time_stamp_url);
-            signer.time_stamp_url(time_stamp_url.clone())?
-        } else {
-            signer
-        };
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
-        let der = SignedDataBuilder::default()
-            // The default is `signed-data`. But Apple appears to use the `data` content-type,
-            // in violation of RFC 5652 Section 5, which says `signed-data` should be
-            // used when there are signatures.
-            .content_type(Oid(OID_ID_DATA.as_ref().into()))
-            .signer(signer)
-            .certificates(certificates)
-            .build_der()?;
+        let der = create_code_directory_signature(
+            &code_directories,
+            signing_key,
+            signing_cert,
+            time_stamp_url,
+            retry_policy,
+            certificates,
+        )?;
 
         self.blobs.insert(
             CodeSigningSlot::Signature,
@@ -321,6 +287,22 @@ impl<'a> EmbeddedSignatureBuilder<'a> {
 
     /// Create the embedded signature "superblob" data.
     pub fn create_superblob(&self) -> Result<Vec<u8>, AppleCodesignError> {
+        self.create_superblob_with_magic(CodeSigningMagic::EmbeddedSignature)
+    }
+
+    /// Create the embedded signature "superblob" data using the legacy magic.
+    ///
+    /// The emitted data uses [CodeSigningMagic::EmbeddedSignatureOld] as the `SuperBlob`
+    /// magic. This format is only understood by very old versions of macOS and should
+    /// only be used when interoperating with those.
+    pub fn create_legacy_superblob(&self) -> Result<Vec<u8>, AppleCodesignError> {
+        self.create_superblob_with_magic(CodeSigningMagic::EmbeddedSignatureOld)
+    }
+
+    fn create_superblob_with_magic(
+        &self,
+        magic: CodeSigningMagic,
+    ) -> Result<Vec<u8>, AppleCodesignError> {
         if matches!(self.state, BlobsState::Empty | BlobsState::SpecialAdded) {
             return Err(AppleCodesignError::SignatureBuilder(
                 "code directory required in order to materialize superblob",
@@ -337,6 +319,362 @@ impl<'a> EmbeddedSignatureBuilder<'a> {
             })
             .collect::<Result<Vec<_>, AppleCodesignError>>()?;
 
-        create_superblob(CodeSigningMagic::EmbeddedSignature, blobs.iter())
+        create_superblob(magic, blobs.iter())
+    }
+}
+
+/// Create the CMS signature of code directories.
+///
+/// Returns the DER encoded `SignedData` to place in the [CodeSigningSlot::Signature]
+/// slot. The first code directory is the primary one and is signed as the message
+/// content. Digests of all code directories are recorded in the signed attributes
+/// Apple uses for hash agility: a plist of truncated digests under
+/// [CD_DIGESTS_PLIST_OID] and full digests under [CD_DIGESTS_OID].
+///
+/// `signing_key` and `signing_cert` denote the keypair being used to produce a
+/// cryptographic signature.
+///
+/// `time_stamp_url` is an optional time-stamp protocol server to use to record
+/// the signature in. Only the request to the time-stamp server is retried
+/// according to `retry_policy`. The signature is created once.
+///
+/// `certificates` are extra X.509 certificates to register in the signing chain.
+pub fn create_code_directory_signature(
+    code_directories: &[&CodeDirectoryBlob],
+    signing_key: &dyn KeyInfoSigner,
+    signing_cert: &CapturedX509Certificate,
+    time_stamp_url: Option<&Url>,
+    retry_policy: &RetryPolicy,
+    certificates: impl Iterator<Item = CapturedX509Certificate>,
+) -> Result<Vec<u8>, AppleCodesignError> {
+    let main_cd = code_directories
+        .first()
+        .ok_or(AppleCodesignError::SignatureBuilder(
+            "cannot create CMS signature without a code directory",
+        ))?;
+
+    if let Some(cn) = signing_cert.subject_common_name() {
+        warn!("creating cryptographic signature with certificate {}", cn);
+    }
+
+    let mut cdhashes = vec![];
+    let mut attributes = vec![];
+
+    for cd in code_directories {
+        // plist digests are the cdhashes of the code directories.
+        cdhashes.push(plist::Value::Data(cd.cdhash()?));
+
+        // ASN.1 values are a SEQUENCE of (OID, OctetString) with the native
+        // digest.
+        let digest = cd.digest_with(cd.digest_type)?;
+        let alg = DigestAlgorithm::try_from(cd.digest_type)?;
+
+        attributes.push(AttributeValue::new(bcder::Captured::from_values(
+            bcder::Mode::Der,
+            bcder::encode::sequence((
+                Oid::from(alg).encode_ref(),
+                bcder::OctetString::new(digest.into()).encode_ref(),
+            )),
+        )));
+    }
+
+    let mut plist_dict = plist::Dictionary::new();
+    plist_dict.insert("cdhashes".to_string(), plist::Value::Array(cdhashes));
+
+    let mut plist_xml = vec![];
+    plist::Value::from(plist_dict)
+        .to_writer_xml(&mut plist_xml)
+        .map_err(AppleCodesignError::CodeDirectoryPlist)?;
+    // We also need to include a trailing newline to conform with Apple's XML
+    // writer.
+    plist_xml.push(b'\n');
+
+    let signer = SignerBuilder::new(signing_key, signing_cert.clone())
+        .message_id_content(main_cd.to_blob_bytes()?)
+        .signed_attribute_octet_string(
+            Oid(Bytes::copy_from_slice(CD_DIGESTS_PLIST_OID.as_ref())),
+            &plist_xml,
+        );
+
+    let signer = signer.signed_attribute(Oid(CD_DIGESTS_OID.as_ref().into()), attributes);
+
+    let der = SignedDataBuilder::default()
+        // The default is `signed-data`. But Apple appears to use the `data` content-type,
+        // in violation of RFC 5652 Section 5, which says `signed-data` should be
+        // used when there are signatures.
+        .content_type(Oid(OID_ID_DATA.as_ref().into()))
+        .signer(signer)
+        .certificates(certificates)
+        .build_der()?;
+
+    if let Some(time_stamp_url) = time_stamp_url {
+        info!("Using time-stamp server {}", time_stamp_url);
+        add_time_stamp_token(&der, time_stamp_url, retry_policy)
+    } else {
+        Ok(der)
+    }
+}
+
+/// Add a time-stamp token for the signature in CMS `SignedData`.
+///
+/// This mirrors what [SignerBuilder] does when given a time-stamp URL. But we
+/// request the token ourselves so failed requests can be retried without
+/// creating the signature again, which may involve hardware or remote signers.
+fn add_time_stamp_token(
+    der: &[u8],
+    time_stamp_url: &Url,
+    retry_policy: &RetryPolicy,
+) -> Result<Vec<u8>, AppleCodesignError> {
+    // Decoding as DER allows re-encoding the captured values as DER.
+    let mut signed_data =
+        bcder::decode::Constructed::decode(der, bcder::Mode::Der, rfc5652::SignedData::decode)
+            .map_err(CmsError::from)?;
+
+    let signer =
+        signed_data
+            .signer_infos
+            .first_mut()
+            .ok_or(AppleCodesignError::SignatureBuilder(
+                "CMS signature has no signers",
+            ))?;
+
+    let digest_algorithm =
+        DigestAlgorithm::try_from(&signer.digest_algorithm.algorithm).map_err(CmsError::from)?;
+    let signature = signer.signature.to_bytes();
+
+    // The message sent to the TSA (via a digest) is the signature of the signed data.
+    let res = retry_policy.run("requesting time-stamp token", || {
+        Ok(
+            time_stamp_message_http(time_stamp_url.clone(), &signature, digest_algorithm)
+                .map_err(CmsError::from)?,
+        )
+    })?;
+
+    if !res.is_success() {
+        return Err(CmsError::from(TimeStampError::Unsuccessful(res.clone())).into());
+    }
+
+    let token = res
+        .signed_data()
+        .map_err(CmsError::from)?
+        .ok_or(CmsError::TimeStampProtocol(TimeStampError::BadResponse))?;
+
+    let mut unsigned_attributes = UnsignedAttributes::default();
+    unsigned_attributes.push(Attribute {
+        typ: Oid(Bytes::copy_from_slice(OID_TIME_STAMP_TOKEN.as_ref())),
+        values: vec![AttributeValue::new(bcder::Captured::from_values(
+            bcder::Mode::Der,
+            token.encode_ref(),
+        ))],
+    });
+    signer.unsigned_attributes = Some(unsigned_attributes);
+
+    let mut der = vec![];
+    signed_data
+        .encode_ref()
+        .write_encoded(bcder::Mode::Der, &mut der)?;
+
+    Ok(der)
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            certificate::{create_self_signed_code_signing_certificate, CertificateProfile},
+            embedded_signature::{DigestType, EntitlementsBlob, RequirementSetBlob},
+        },
+        cryptographic_message_syntax::SignedData,
+        std::ops::Deref,
+        x509_certificate::{EcdsaCurve, KeyAlgorithm},
+    };
+
+    #[test]
+    fn superblob_layout() -> Result<(), AppleCodesignError> {
+        let mut builder = EmbeddedSignatureBuilder::default();
+        assert!(builder.create_superblob().is_err());
+
+        let entitlements = || EntitlementsBlob::from_string("<plist/>");
+        builder.add_blob(
+            CodeSigningSlot::RequirementSet,
+            RequirementSetBlob::default().into(),
+        )?;
+        builder.add_blob(CodeSigningSlot::Entitlements, entitlements().into())?;
+        builder.add_code_directory(
+            CodeSigningSlot::CodeDirectory,
+            CodeDirectoryBlob {
+                digest_type: DigestType::Sha256,
+                digest_size: 32,
+                page_size: 4096,
+                ident: "superblob".into(),
+                ..Default::default()
+            },
+        )?;
+
+        // Special blobs can't be added once a code directory is present.
+        assert!(builder
+            .add_blob(CodeSigningSlot::Entitlements, entitlements().into())
+            .is_err());
+
+        let data = builder.create_superblob()?;
+        let signature = EmbeddedSignature::from_bytes(&data)?;
+
+        assert_eq!(signature.magic, CodeSigningMagic::EmbeddedSignature);
+        assert_eq!(signature.length as usize, data.len());
+        assert_eq!(
+            signature
+                .blobs
+                .iter()
+                .map(|blob| blob.slot)
+                .collect::<Vec<_>>(),
+            vec![
+                CodeSigningSlot::CodeDirectory,
+                CodeSigningSlot::RequirementSet,
+                CodeSigningSlot::Entitlements,
+            ]
+        );
+
+        // Blobs immediately follow the index and each other.
+        let mut offset = 12 + 8 * signature.blobs.len();
+        for blob in &signature.blobs {
+            assert_eq!(blob.offset, offset);
+            offset += blob.length;
+        }
+        assert_eq!(offset, data.len());
+
+        // The code directory records digests of the special blobs.
+        let cd = signature.code_directory()?.unwrap();
+        assert_eq!(
+            cd.slot_digest(CodeSigningSlot::Entitlements)
+                .unwrap()
+                .to_vec(),
+            DigestType::Sha256.digest_data(&entitlements().to_blob_bytes()?)?
+        );
+
+        let legacy = builder.create_legacy_superblob()?;
+        assert_eq!(
+            EmbeddedSignature::from_bytes(&legacy)?.magic,
+            CodeSigningMagic::EmbeddedSignatureOld
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn code_directory_signature() -> Result<(), AppleCodesignError> {
+        let (cert, key, _) = create_self_signed_code_signing_certificate(
+            KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1),
+            CertificateProfile::AppleDevelopment,
+            "team1",
+            "Joe Developer",
+            "US",
+            chrono::Duration::hours(1),
+        )?;
+
+        let cd = |digest_type: DigestType| CodeDirectoryBlob {
+            digest_type,
+            digest_size: digest_type.hash_len().unwrap() as _,
+            page_size: 4096,
+            ident: "signature".into(),
+            ..Default::default()
+        };
+        let primary = cd(DigestType::Sha1);
+        let alternative = cd(DigestType::Sha256);
+
+        assert!(create_code_directory_signature(
+            &[],
+            &key,
+            &cert,
+            None,
+            &RetryPolicy::none(),
+            std::iter::empty()
+        )
+        .is_err());
+
+        let der = create_code_directory_signature(
+            &[&primary, &alternative],
+            &key,
+            &cert,
+            None,
+            &RetryPolicy::none(),
+            std::iter::once(cert.clone()),
+        )?;
+
+        let signed_data = SignedData::parse_ber(&der)?;
+        assert!(signed_data.signed_content().is_none());
+
+        let signer = signed_data.signers().next().unwrap();
+        signer.verify_signature_with_signed_data(&signed_data)?;
+        // The primary code directory is the signed message.
+        signer.verify_message_digest_with_content(&primary.to_blob_bytes()?)?;
+
+        // Every code directory is recorded in the hash agility attributes.
+        let attributes = signer.signed_attributes().unwrap().attributes();
+        let plist = attributes
+            .iter()
+            .find(|attr| attr.typ == CD_DIGESTS_PLIST_OID)
+            .unwrap();
+        let plist = plist.values[0]
+            .deref()
+            .clone()
+            .decode(|cons| Ok(bcder::OctetString::take_from(cons)?.into_bytes()))
+            .unwrap();
+        let plist = plist::Value::from_reader_xml(plist.as_ref()).unwrap();
+        let cdhashes = plist
+            .as_dictionary()
+            .and_then(|d| d.get("cdhashes"))
+            .and_then(|v| v.as_array())
+            .unwrap();
+        assert_eq!(
+            cdhashes,
+            &[primary.digest_with(DigestType::Sha1)?, {
+                let mut digest = alternative.digest_with(DigestType::Sha256)?;
+                digest.truncate(20);
+                digest
+            }]
+            .map(plist::Value::Data)
+        );
+
+        let mut digests = vec![];
+        for value in &attributes
+            .iter()
+            .find(|attr| attr.typ == CD_DIGESTS_OID)
+            .unwrap()
+            .values
+        {
+            // Values are SEQUENCE of (OID, OctetString).
+            value
+                .deref()
+                .clone()
+                .decode(|cons| {
+                    while let Some(digest) = cons.take_opt_sequence(|cons| {
+                        Ok((
+                            Oid::take_from(cons)?,
+                            bcder::OctetString::take_from(cons)?.into_bytes(),
+                        ))
+                    })? {
+                        digests.push(digest);
+                    }
+
+                    Ok(())
+                })
+                .unwrap();
+        }
+        assert_eq!(
+            digests,
+            vec![
+                (
+                    Oid::from(DigestAlgorithm::Sha1),
+                    Bytes::from(primary.digest_with(DigestType::Sha1)?)
+                ),
+                (
+                    Oid::from(DigestAlgorithm::Sha256),
+                    Bytes::from(alternative.digest_with(DigestType::Sha256)?)
+                ),
+            ]
+        );
+
+        Ok(())
     }
 }
@@ -5,67 +5,49 @@
 /*! Code entitlements handling. */
 
 use {
-    crate::{code_directory::ExecutableSegmentFlags, AppleCodesignError},
-    plist::Value,
-    rasn::{
-        ber::enc::{Encoder as DerEncoder, Error as DerError},
-        enc::Error,
-        types::{Class, Tag},
-        Encoder,
+    crate::{
+        code_directory::ExecutableSegmentFlags, der_plist::der_encode_plist, macho::Platform,
+        AppleCodesignError,
     },
-    std::collections::BTreeMap,
+    plist::Value,
 };
 
-/// Encode a [Value] to DER, writing to an encoder.
-fn der_encode_value(encoder: &mut DerEncoder, value: &Value) -> Result<(), DerError> {
+/// Whether a [Value] contains a [Value::Data], which isn't allowed in entitlements.
+fn contains_data(value: &Value) -> bool {
     match value {
-        Value::Boolean(v) => encoder.encode_bool(Tag::BOOL, *v),
-        Value::Integer(v) => {
-            let integer = rasn::types::Integer::from(v.as_signed().unwrap());
-            encoder.encode_integer(Tag::INTEGER, &integer)
-        }
-        Value::String(string) => encoder.encode_utf8_string(Tag::UTF8_STRING, string),
-        Value::Array(array) => encoder.encode_sequence(Tag::SEQUENCE, |encoder| {
-            for v in array {
-                der_encode_value(encoder, v)?;
-            }
-            Ok(())
-        }),
-        Value::Dictionary(dict) => {
-            // make sure it's sorted alphabetically
-            let map = dict.into_iter().collect::<BTreeMap<_, _>>();
-            encoder.encode_sequence(Tag::new(Class::Context, 16), |encoder| {
-                for (k, v) in map {
-                    encoder.encode_sequence(Tag::SEQUENCE, |encoder| {
-                        encoder.encode_utf8_string(Tag::UTF8_STRING, k)?;
-                        der_encode_value(encoder, v)?;
-                        Ok(())
-                    })?;
-                }
-                Ok(())
-            })
-        }
+        Value::Data(_) => true,
+        Value::Array(array) => array.iter().any(contains_data),
+        Value::Dictionary(dict) => dict.values().any(contains_data),
+        _ => false,
+    }
+}
 
-        Value::Data(_) => Err(DerError::custom("encoding of data values not supported")),
-        Value::Date(_) => Err(DerError::custom("encoding of date values not supported")),
-        Value::Real(_) => Err(DerError::custom("encoding of real values not supported")),
-        Value::Uid(_) => Err(DerError::custom("encoding of uid values not supported")),
-        _ => Err(DerError::custom(
-            "encoding of unknown value type not supported",
-        )),
+/// Whether a plist value contains values that can't be encoded in DER entitlements.
+fn contains_non_der_value(value: &Value) -> bool {
+    match value {
+        Value::Boolean(_) | Value::String(_) => false,
+        Value::Integer(v) => v.as_signed().is_none(),
+        Value::Array(array) => array.iter().any(contains_non_der_value),
+        Value::Dictionary(dict) => dict.values().any(contains_non_der_value),
+        _ => true,
     }
 }
 
 /// Encode an entitlements plist to DER.
+///
+/// This is [der_encode_plist()] except data values are rejected, as Apple's
+/// tooling doesn't allow them in entitlements.
 pub fn der_encode_entitlements_plist(value: &Value) -> Result<Vec<u8>, AppleCodesignError> {
-    rasn::der::encode_scope(|encoder| {
-        encoder.encode_sequence(Tag::new(Class::Application, 16), |encoder| {
-            encoder.encode_integer(Tag::INTEGER, &rasn::types::Integer::from(1))?;
-            der_encode_value(encoder, value)?;
-            Ok(())
-        })
+    if contains_data(value) {
+        return Err(AppleCodesignError::EntitlementsDerEncode(
+            "encoding of data values not supported".into(),
+        ));
+    }
+
+    der_encode_plist(value).map_err(|e| match e {
+        AppleCodesignError::DerPlistEncode(msg) => AppleCodesignError::EntitlementsDerEncode(msg),
+        e => e,
     })
-    .map_err(|e| AppleCodesignError::EntitlementsDerEncode(format!("{e}")))
 }
 
 /// Convert an entitlements plist to [ExecutableSegmentFlags].
@@ -114,69 +96,617 @@ pub fn plist_to_executable_segment_flags(value: &Value) -> ExecutableSegmentFlag
     flags
 }
 
+bitflags::bitflags! {
+    /// Platforms an entitlement is meaningful on.
+    #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+    pub struct EntitlementPlatforms: u32 {
+        /// macOS, including Mac Catalyst.
+        const MACOS = 0x0001;
+        /// iOS and iPadOS.
+        const IOS = 0x0002;
+        /// tvOS.
+        const TVOS = 0x0004;
+        /// watchOS.
+        const WATCHOS = 0x0008;
+        /// DriverKit.
+        const DRIVERKIT = 0x0010;
+        /// iOS-like platforms.
+        const IOS_LIKE = Self::IOS.bits() | Self::TVOS.bits() | Self::WATCHOS.bits();
+        /// All Apple platforms other than DriverKit.
+        const APPS = Self::MACOS.bits() | Self::IOS_LIKE.bits();
+    }
+}
+
+impl EntitlementPlatforms {
+    /// Resolve the entitlement platform of a Mach-O platform.
+    ///
+    /// Simulators resolve to the platform they simulate. Platforms entitlements
+    /// aren't cataloged for resolve to an empty set.
+    pub fn from_platform(platform: &Platform) -> Self {
+        match platform {
+            Platform::MacOs | Platform::MacCatalyst => Self::MACOS,
+            Platform::IOs | Platform::IosSimulator => Self::IOS,
+            Platform::TvOs | Platform::TvOsSimulator => Self::TVOS,
+            Platform::WatchOs | Platform::WatchOsSimulator => Self::WATCHOS,
+            Platform::DriverKit => Self::DRIVERKIT,
+            Platform::BridgeOs | Platform::Unknown(_) => Self::empty(),
+        }
+    }
+}
+
+impl std::fmt::Display for EntitlementPlatforms {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = [
+            (Self::MACOS, "macOS"),
+            (Self::IOS, "iOS"),
+            (Self::TVOS, "tvOS"),
+            (Self::WATCHOS, "watchOS"),
+            (Self::DRIVERKIT, "DriverKit"),
+        ]
+        .into_iter()
+        .filter(|(flag, _)| self.contains(*flag))
+        .map(|(_, name)| name)
+        .collect::<Vec<_>>();
+
+        f.write_str(&names.join(", "))
+    }
+}
+
+/// The type of value an entitlement expects.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EntitlementValueType {
+    /// A `<true/>` or `<false/>`.
+    Boolean,
+    /// A `<string>`.
+    String,
+    /// An `<array>` of `<string>`.
+    StringArray,
+}
+
+impl EntitlementValueType {
+    /// Whether a plist value is of this type.
+    pub fn matches(&self, value: &Value) -> bool {
+        match self {
+            Self::Boolean => value.as_boolean().is_some(),
+            Self::String => value.as_string().is_some(),
+            Self::StringArray => value
+                .as_array()
+                .map(|array| array.iter().all(|v| v.as_string().is_some()))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl std::fmt::Display for EntitlementValueType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Boolean => "boolean",
+            Self::String => "string",
+            Self::StringArray => "array of strings",
+        })
+    }
+}
+
+/// Describes an entitlement known to this crate.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct KnownEntitlement {
+    /// The entitlements plist key.
+    pub key: &'static str,
+    /// The type of value the entitlement expects.
+    pub value_type: EntitlementValueType,
+    /// Platforms the entitlement is meaningful on.
+    pub platforms: EntitlementPlatforms,
+}
+
+const fn known(
+    key: &'static str,
+    value_type: EntitlementValueType,
+    platforms: EntitlementPlatforms,
+) -> KnownEntitlement {
+    KnownEntitlement {
+        key,
+        value_type,
+        platforms,
+    }
+}
+
+/// Catalog of publicly documented entitlements.
+///
+/// This isn't exhaustive. Apple adds entitlements with most OS releases.
+const KNOWN_ENTITLEMENTS: &[KnownEntitlement] = {
+    use {EntitlementPlatforms as P, EntitlementValueType as T};
+
+    &[
+        known("application-identifier", T::String, P::IOS_LIKE),
+        known("aps-environment", T::String, P::IOS_LIKE),
+        known("beta-reports-active", T::Boolean, P::IOS_LIKE),
+        known("com.apple.application-identifier", T::String, P::MACOS),
+        known("com.apple.developer.applesignin", T::StringArray, P::APPS),
+        known("com.apple.developer.aps-environment", T::String, P::MACOS),
+        known(
+            "com.apple.developer.associated-domains",
+            T::StringArray,
+            P::APPS,
+        ),
+        known(
+            "com.apple.developer.associated-domains.mdm-managed",
+            T::Boolean,
+            P::APPS,
+        ),
+        known(
+            "com.apple.developer.authentication-services.autofill-credential-provider",
+            T::Boolean,
+            P::APPS,
+        ),
+        known(
+            "com.apple.developer.beta-reports-active",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known(
+            "com.apple.developer.default-data-protection",
+            T::String,
+            P::IOS_LIKE,
+        ),
+        known(
+            "com.apple.developer.devicecheck.appattest-environment",
+            T::String,
+            P::APPS,
+        ),
+        known("com.apple.developer.driverkit", T::Boolean, P::DRIVERKIT),
+        known(
+            "com.apple.developer.endpoint-security.client",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known("com.apple.developer.family-controls", T::Boolean, P::IOS),
+        known("com.apple.developer.game-center", T::Boolean, P::APPS),
+        known("com.apple.developer.healthkit", T::Boolean, P::IOS_LIKE),
+        known("com.apple.developer.homekit", T::Boolean, P::IOS_LIKE),
+        known(
+            "com.apple.developer.icloud-container-environment",
+            T::String,
+            P::APPS,
+        ),
+        known(
+            "com.apple.developer.icloud-container-identifiers",
+            T::StringArray,
+            P::APPS,
+        ),
+        known(
+            "com.apple.developer.icloud-services",
+            T::StringArray,
+            P::APPS,
+        ),
+        known(
+            "com.apple.developer.in-app-payments",
+            T::StringArray,
+            P::APPS,
+        ),
+        known(
+            "com.apple.developer.kernel.extended-virtual-addressing",
+            T::Boolean,
+            P::IOS,
+        ),
+        known(
+            "com.apple.developer.kernel.increased-memory-limit",
+            T::Boolean,
+            P::IOS,
+        ),
+        known(
+            "com.apple.developer.networking.networkextension",
+            T::StringArray,
+            P::APPS,
+        ),
+        known(
+            "com.apple.developer.networking.vpn.api",
+            T::StringArray,
+            P::APPS,
+        ),
+        known(
+            "com.apple.developer.networking.wifi-info",
+            T::Boolean,
+            P::IOS,
+        ),
+        known(
+            "com.apple.developer.nfc.readersession.formats",
+            T::StringArray,
+            P::IOS,
+        ),
+        known(
+            "com.apple.developer.pass-type-identifiers",
+            T::StringArray,
+            P::IOS_LIKE,
+        ),
+        known("com.apple.developer.siri", T::Boolean, P::APPS),
+        known(
+            "com.apple.developer.system-extension.install",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known("com.apple.developer.team-identifier", T::String, P::APPS),
+        known(
+            "com.apple.developer.ubiquity-container-identifiers",
+            T::StringArray,
+            P::APPS,
+        ),
+        known(
+            "com.apple.developer.ubiquity-kvstore-identifier",
+            T::String,
+            P::APPS,
+        ),
+        known(
+            "com.apple.developer.usernotifications.communication",
+            T::Boolean,
+            P::APPS,
+        ),
+        known(
+            "com.apple.developer.usernotifications.time-sensitive",
+            T::Boolean,
+            P::APPS,
+        ),
+        known("com.apple.developer.weatherkit", T::Boolean, P::APPS),
+        known("com.apple.developer.web-browser", T::Boolean, P::IOS),
+        known("com.apple.security.app-sandbox", T::Boolean, P::MACOS),
+        known(
+            "com.apple.security.application-groups",
+            T::StringArray,
+            P::APPS,
+        ),
+        known(
+            "com.apple.security.assets.movies.read-only",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known(
+            "com.apple.security.assets.movies.read-write",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known(
+            "com.apple.security.assets.music.read-only",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known(
+            "com.apple.security.assets.music.read-write",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known(
+            "com.apple.security.assets.pictures.read-only",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known(
+            "com.apple.security.assets.pictures.read-write",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known(
+            "com.apple.security.automation.apple-events",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known(
+            "com.apple.security.cs.allow-dyld-environment-variables",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known("com.apple.security.cs.allow-jit", T::Boolean, P::MACOS),
+        known(
+            "com.apple.security.cs.allow-unsigned-executable-memory",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known("com.apple.security.cs.debugger", T::Boolean, P::MACOS),
+        known(
+            "com.apple.security.cs.disable-executable-page-protection",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known(
+            "com.apple.security.cs.disable-library-validation",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known(
+            "com.apple.security.device.audio-input",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known("com.apple.security.device.bluetooth", T::Boolean, P::MACOS),
+        known("com.apple.security.device.camera", T::Boolean, P::MACOS),
+        known("com.apple.security.device.microphone", T::Boolean, P::MACOS),
+        known("com.apple.security.device.serial", T::Boolean, P::MACOS),
+        known("com.apple.security.device.usb", T::Boolean, P::MACOS),
+        known(
+            "com.apple.security.files.bookmarks.app-scope",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known(
+            "com.apple.security.files.bookmarks.document-scope",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known(
+            "com.apple.security.files.downloads.read-only",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known(
+            "com.apple.security.files.downloads.read-write",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known(
+            "com.apple.security.files.user-selected.executable",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known(
+            "com.apple.security.files.user-selected.read-only",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known(
+            "com.apple.security.files.user-selected.read-write",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known("com.apple.security.get-task-allow", T::Boolean, P::MACOS),
+        known("com.apple.security.hypervisor", T::Boolean, P::MACOS),
+        known("com.apple.security.inherit", T::Boolean, P::MACOS),
+        known("com.apple.security.network.client", T::Boolean, P::MACOS),
+        known("com.apple.security.network.server", T::Boolean, P::MACOS),
+        known(
+            "com.apple.security.personal-information.addressbook",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known(
+            "com.apple.security.personal-information.calendars",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known(
+            "com.apple.security.personal-information.location",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known(
+            "com.apple.security.personal-information.photos-library",
+            T::Boolean,
+            P::MACOS,
+        ),
+        known("com.apple.security.print", T::Boolean, P::MACOS),
+        known("com.apple.security.virtualization", T::Boolean, P::MACOS),
+        known("dynamic-codesigning", T::Boolean, P::IOS_LIKE),
+        known("get-task-allow", T::Boolean, P::IOS_LIKE),
+        known("keychain-access-groups", T::StringArray, P::APPS),
+        known("run-unsigned-code", T::Boolean, P::IOS_LIKE),
+    ]
+};
+
+/// Key namespaces reserved for documented entitlements.
+///
+/// Keys in these namespaces missing from the catalog are reported as unknown.
+const DOCUMENTED_NAMESPACES: &[&str] = &["com.apple.developer.", "com.apple.security."];
+
+/// Key prefixes of documented entitlements whose keys are open-ended.
+const OPEN_ENDED_PREFIXES: &[&str] = &["com.apple.security.temporary-exception."];
+
+/// Maximum edit distance for an unknown key to be considered a misspelling.
+const MAX_MISSPELLING_DISTANCE: usize = 2;
+
+/// Obtain the catalog of entitlements known to this crate.
+///
+/// The catalog is sorted by key.
+pub fn known_entitlements() -> &'static [KnownEntitlement] {
+    KNOWN_ENTITLEMENTS
+}
+
+/// Find a known entitlement by key.
+pub fn find_known_entitlement(key: &str) -> Option<&'static KnownEntitlement> {
+    KNOWN_ENTITLEMENTS
+        .binary_search_by(|entitlement| entitlement.key.cmp(key))
+        .ok()
+        .map(|index| &KNOWN_ENTITLEMENTS[index])
+}
+
+/// Describes a kind of problem with an entitlement.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EntitlementIssueKind {
+    /// The key isn't known.
+    ///
+    /// Holds the known key it is likely a misspelling of, if any.
+    UnknownKey(Option<&'static str>),
+    /// The value doesn't have the type the entitlement expects.
+    WrongValueType(EntitlementValueType),
+    /// The entitlement isn't meaningful on a targeted platform.
+    UnsupportedPlatform(EntitlementPlatforms),
+    /// The value contains data, real, date, or uid values.
+    ///
+    /// These can't be represented in DER entitlements, so signing executables fails.
+    NonDerValue,
+    /// The entitlements plist isn't a dictionary.
+    ///
+    /// Issues of this kind have an empty key.
+    NotDictionary,
+}
+
+/// A problem with an entitlement found by [validate_entitlements()].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EntitlementIssue {
+    /// The entitlement key having the problem.
+    pub key: String,
+    /// The kind of problem.
+    pub kind: EntitlementIssueKind,
+}
+
+impl std::fmt::Display for EntitlementIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            EntitlementIssueKind::UnknownKey(Some(known)) => f.write_fmt(format_args!(
+                "unknown entitlement {}; did you mean {}?",
+                self.key, known
+            )),
+            EntitlementIssueKind::UnknownKey(None) => {
+                f.write_fmt(format_args!("unknown entitlement {}", self.key))
+            }
+            EntitlementIssueKind::WrongValueType(expected) => f.write_fmt(format_args!(
+                "entitlement {} should have a {} value",
+                self.key, expected
+            )),
+            EntitlementIssueKind::UnsupportedPlatform(platforms) => f.write_fmt(format_args!(
+                "entitlement {} isn't meaningful on targeted platform(s) {}",
+                self.key, platforms
+            )),
+            EntitlementIssueKind::NonDerValue => f.write_fmt(format_args!(
+                "entitlement {} has a value that can't be encoded as DER",
+                self.key
+            )),
+            EntitlementIssueKind::NotDictionary => f.write_str("entitlements aren't a dictionary"),
+        }
+    }
+}
+
+/// Validate an entitlements plist against the catalog of known entitlements.
+///
+/// `platforms` are the platforms being targeted. Platform support isn't
+/// validated if it is empty.
+///
+/// Keys not in the catalog are reported if they are in a namespace reserved for
+/// documented entitlements or are likely a misspelling of a known key. Other
+/// keys, like private or vendor-specific entitlements, are ignored.
+///
+/// Structural problems are reported for every key: values that can't be encoded
+/// as DER and a plist that isn't a dictionary.
+///
+/// An empty vector means no problems were found.
+pub fn validate_entitlements(value: &Value, platforms: &[Platform]) -> Vec<EntitlementIssue> {
+    let Some(dict) = value.as_dictionary() else {
+        return vec![EntitlementIssue {
+            key: String::new(),
+            kind: EntitlementIssueKind::NotDictionary,
+        }];
+    };
+
+    let targets = platforms
+        .iter()
+        .map(EntitlementPlatforms::from_platform)
+        .fold(EntitlementPlatforms::empty(), |acc, p| acc | p);
+
+    let mut issues = vec![];
+
+    for (key, value) in dict {
+        if contains_non_der_value(value) {
+            issues.push(EntitlementIssue {
+                key: key.clone(),
+                kind: EntitlementIssueKind::NonDerValue,
+            });
+        }
+
+        let Some(known) = find_known_entitlement(key) else {
+            if OPEN_ENDED_PREFIXES
+                .iter()
+                .any(|prefix| key.starts_with(prefix))
+            {
+                continue;
+            }
+
+            let suggestion = closest_known_key(key);
+
+            if suggestion.is_some()
+                || DOCUMENTED_NAMESPACES
+                    .iter()
+                    .any(|prefix| key.starts_with(prefix))
+            {
+                issues.push(EntitlementIssue {
+                    key: key.clone(),
+                    kind: EntitlementIssueKind::UnknownKey(suggestion),
+                });
+            }
+
+            continue;
+        };
+
+        if !known.value_type.matches(value) {
+            issues.push(EntitlementIssue {
+                key: key.clone(),
+                kind: EntitlementIssueKind::WrongValueType(known.value_type),
+            });
+        }
+
+        let unsupported = targets - known.platforms;
+        if !unsupported.is_empty() {
+            issues.push(EntitlementIssue {
+                key: key.clone(),
+                kind: EntitlementIssueKind::UnsupportedPlatform(unsupported),
+            });
+        }
+    }
+
+    issues
+}
+
+/// Find the known key an unknown key is most likely a misspelling of.
+fn closest_known_key(key: &str) -> Option<&'static str> {
+    KNOWN_ENTITLEMENTS
+        .iter()
+        .map(|known| (edit_distance(&key.to_lowercase(), known.key), known.key))
+        .filter(|(distance, _)| *distance <= MAX_MISSPELLING_DISTANCE)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, key)| key)
+}
+
+/// Compute the Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
 #[cfg(test)]
 mod test {
     use {
         super::*,
+        crate::der_plist::test::*,
+        anyhow::Result,
+        plist::{Date, Uid},
+        std::time::{Duration, SystemTime},
+    };
+
+    #[cfg(feature = "full")]
+    use {
         crate::{
             embedded_signature::{Blob, CodeSigningSlot},
             macho::MachFile,
         },
-        anyhow::{anyhow, Result},
-        plist::{Date, Uid},
-        std::{
-            process::Command,
-            time::{Duration, SystemTime},
-        },
+        anyhow::anyhow,
+        std::process::Command,
     };
 
-    const DER_EMPTY_DICT: &[u8] = &[112, 5, 2, 1, 1, 176, 0];
-    const DER_BOOL_FALSE: &[u8] = &[
-        112, 15, 2, 1, 1, 176, 10, 48, 8, 12, 3, 107, 101, 121, 1, 1, 0,
-    ];
-    const DER_BOOL_TRUE: &[u8] = &[
-        112, 15, 2, 1, 1, 176, 10, 48, 8, 12, 3, 107, 101, 121, 1, 1, 255,
-    ];
-    const DER_INTEGER_0: &[u8] = &[
-        112, 15, 2, 1, 1, 176, 10, 48, 8, 12, 3, 107, 101, 121, 2, 1, 0,
-    ];
-    const DER_INTEGER_NEG1: &[u8] = &[
-        112, 15, 2, 1, 1, 176, 10, 48, 8, 12, 3, 107, 101, 121, 2, 1, 255,
-    ];
-    const DER_INTEGER_1: &[u8] = &[
-        112, 15, 2, 1, 1, 176, 10, 48, 8, 12, 3, 107, 101, 121, 2, 1, 1,
-    ];
-    const DER_INTEGER_42: &[u8] = &[
-        112, 15, 2, 1, 1, 176, 10, 48, 8, 12, 3, 107, 101, 121, 2, 1, 42,
-    ];
-    const DER_STRING_EMPTY: &[u8] = &[112, 14, 2, 1, 1, 176, 9, 48, 7, 12, 3, 107, 101, 121, 12, 0];
-    const DER_STRING_VALUE: &[u8] = &[
-        112, 19, 2, 1, 1, 176, 14, 48, 12, 12, 3, 107, 101, 121, 12, 5, 118, 97, 108, 117, 101,
-    ];
-    const DER_ARRAY_EMPTY: &[u8] = &[112, 14, 2, 1, 1, 176, 9, 48, 7, 12, 3, 107, 101, 121, 48, 0];
-    const DER_ARRAY_FALSE: &[u8] = &[
-        112, 17, 2, 1, 1, 176, 12, 48, 10, 12, 3, 107, 101, 121, 48, 3, 1, 1, 0,
-    ];
-    const DER_ARRAY_TRUE_FOO: &[u8] = &[
-        112, 22, 2, 1, 1, 176, 17, 48, 15, 12, 3, 107, 101, 121, 48, 8, 1, 1, 255, 12, 3, 102, 111,
-        111,
-    ];
-    const DER_DICT_EMPTY: &[u8] = &[
-        112, 14, 2, 1, 1, 176, 9, 48, 7, 12, 3, 107, 101, 121, 176, 0,
-    ];
-    const DER_DICT_BOOL: &[u8] = &[
-        112, 26, 2, 1, 1, 176, 21, 48, 19, 12, 3, 107, 101, 121, 176, 12, 48, 10, 12, 5, 105, 110,
-        110, 101, 114, 1, 1, 0,
-    ];
-    const DER_MULTIPLE_KEYS: &[u8] = &[
-        112, 37, 2, 1, 1, 176, 32, 48, 8, 12, 3, 107, 101, 121, 1, 1, 0, 48, 9, 12, 4, 107, 101,
-        121, 50, 1, 1, 255, 48, 9, 12, 4, 107, 101, 121, 51, 2, 1, 42,
-    ];
-
     /// Signs a binary with custom entitlements XML and retrieves the entitlements DER.
     ///
     /// This uses Apple's `codesign` executable to sign the current binary then uses
     /// our library for extracting the entitlements DER that it generated.
+    #[cfg(feature = "full")]
     #[allow(unused)]
     fn sign_and_get_entitlements_der(value: &Value) -> Result<Vec<u8>> {
         let this_exe = std::env::current_exe()?;
@@ -235,7 +765,7 @@ mod test {
     // This test is failing in CI. Older versions of macOS / codesign likely have
     // a different DER encoding mechanism.
     // #[test]
-    #[cfg(target_os = "macos")]
+    #[cfg(all(target_os = "macos", feature = "full"))]
     #[allow(unused)]
     fn apple_der_entitlements_encoding() -> Result<()> {
         // `codesign` prints "unknown exception" if we attempt to serialize a plist where
@@ -552,4 +1082,99 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn catalog_sorted() {
+        let catalog = known_entitlements();
+
+        assert!(catalog.windows(2).all(|w| w[0].key < w[1].key));
+        assert!(catalog.iter().all(|e| !e.platforms.is_empty()));
+        assert_eq!(
+            find_known_entitlement("com.apple.security.app-sandbox").map(|e| e.value_type),
+            Some(EntitlementValueType::Boolean)
+        );
+        assert!(find_known_entitlement("com.apple.security.app-sandboxed").is_none());
+    }
+
+    #[test]
+    fn validate() {
+        let mut d = plist::Dictionary::new();
+        d.insert(
+            "com.apple.security.app-sandbox".into(),
+            Value::Boolean(true),
+        );
+        d.insert(
+            "com.apple.security.application-groups".into(),
+            Value::Array(vec![Value::String("team.group".into())]),
+        );
+        d.insert(
+            "com.apple.security.temporary-exception.files.absolute-path.read-only".into(),
+            Value::Array(vec![]),
+        );
+        d.insert("com.example.custom".into(), Value::Boolean(true));
+        let value = Value::Dictionary(d.clone());
+
+        assert!(validate_entitlements(&value, &[]).is_empty());
+        assert!(validate_entitlements(&value, &[Platform::MacOs]).is_empty());
+
+        let issues = validate_entitlements(&value, &[Platform::IOs]);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].key, "com.apple.security.app-sandbox");
+        assert_eq!(
+            issues[0].to_string(),
+            "entitlement com.apple.security.app-sandbox isn't meaningful on targeted \
+            platform(s) iOS"
+        );
+
+        let mut d = plist::Dictionary::new();
+        d.insert(
+            "com.apple.security.cs.allow-jitt".into(),
+            Value::Boolean(true),
+        );
+        d.insert("Get-Task-Allow".into(), Value::Boolean(true));
+        d.insert("com.apple.security.made-up".into(), Value::Boolean(true));
+        d.insert(
+            "com.apple.security.network.client".into(),
+            Value::String("true".into()),
+        );
+
+        let issues = validate_entitlements(&Value::Dictionary(d), &[Platform::MacOs]);
+        assert_eq!(
+            issues.iter().map(|i| i.kind).collect::<Vec<_>>(),
+            vec![
+                EntitlementIssueKind::UnknownKey(Some("com.apple.security.cs.allow-jit")),
+                EntitlementIssueKind::UnknownKey(Some("get-task-allow")),
+                EntitlementIssueKind::UnknownKey(None),
+                EntitlementIssueKind::WrongValueType(EntitlementValueType::Boolean),
+            ]
+        );
+
+        let mut d = plist::Dictionary::new();
+        d.insert(
+            "com.apple.security.app-sandbox".into(),
+            Value::Data(vec![1]),
+        );
+        d.insert(
+            "com.example.custom".into(),
+            Value::Array(vec![Value::Real(1.0)]),
+        );
+
+        let issues = validate_entitlements(&Value::Dictionary(d), &[]);
+        assert_eq!(
+            issues.iter().map(|i| i.kind).collect::<Vec<_>>(),
+            vec![
+                EntitlementIssueKind::NonDerValue,
+                EntitlementIssueKind::WrongValueType(EntitlementValueType::Boolean),
+                EntitlementIssueKind::NonDerValue,
+            ]
+        );
+        assert_eq!(
+            issues[2].to_string(),
+            "entitlement com.example.custom has a value that can't be encoded as DER"
+        );
+
+        let issues = validate_entitlements(&Value::Array(vec![]), &[]);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].kind, EntitlementIssueKind::NotDictionary);
+    }
 }
@@ -3,8 +3,12 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use {
-    crate::{macho_universal::UniversalMachOError, remote_signing::RemoteSignError},
-    cryptographic_message_syntax::CmsError,
+    crate::{
+        certificate::{ExtendedKeyUsagePurpose, SignedEntityKind, SigningDistribution},
+        embedded_signature::CodeSigningSlot,
+        macho::MachOParseError,
+        macho_universal::UniversalMachOError,
+    },
     std::path::PathBuf,
     thiserror::Error,
     x509_certificate::{KeyAlgorithm, X509CertificateError},
@@ -43,27 +47,35 @@ pub enum AppleCodesignError {
     #[error("X.509 certificate handler error: {0}")]
     X509(#[from] X509CertificateError),
 
+    #[cfg(feature = "full")]
     #[error("CMS error: {0}")]
-    Cms(#[from] CmsError),
+    Cms(#[from] cryptographic_message_syntax::CmsError),
 
+    #[cfg(feature = "full")]
     #[error("JSON serialization error: {0}")]
     SerdeJson(#[from] serde_json::Error),
 
+    #[cfg(feature = "full")]
     #[error("YAML serialization error: {0}")]
     SerdeYaml(#[from] serde_yaml::Error),
 
+    #[cfg(feature = "full")]
     #[error("glob error: {0}")]
     GlobPattern(#[from] glob::PatternError),
 
     #[error("problems reported during verification")]
     VerificationProblems,
 
+    #[error("problems reported during verification (exit code {0})")]
+    VerificationExitCode(i32),
+
     #[error("certificate error: {0}")]
     CertificateGeneric(String),
 
     #[error("certificate decode error: {0}")]
     CertificateDecode(bcder::decode::DecodeError<std::convert::Infallible>),
 
+    #[cfg(feature = "full")]
     #[error("PEM error: {0}")]
     CertificatePem(pem::PemError),
 
@@ -82,9 +94,11 @@ pub enum AppleCodesignError {
     #[error("error parsing version string: {0}")]
     VersionParse(#[from] semver::Error),
 
+    #[cfg(feature = "full")]
     #[error("XAR error: {0}")]
     Xar(#[from] apple_xar::Error),
 
+    #[cfg(feature = "full")]
     #[error("Apple flat package error: {0}")]
     FlatPackage(#[from] apple_flat_package::Error),
 
@@ -94,8 +108,32 @@ pub enum AppleCodesignError {
     #[error("unable to locate __LINKEDIT segment")]
     MissingLinkedit,
 
-    #[error("bad header magic in {0}")]
-    BadMagic(&'static str),
+    #[error("bad header magic in {what}: expected 0x{expected:08x}, got 0x{actual:08x}")]
+    MagicMismatch {
+        what: &'static str,
+        expected: u32,
+        actual: u32,
+    },
+
+    #[error("{what} is truncated: need {required} bytes but only {available} available")]
+    TruncatedBlob {
+        what: &'static str,
+        required: usize,
+        available: usize,
+    },
+
+    #[error("{what} length mismatch: header declares {declared} bytes but blob has {actual}")]
+    LengthMismatch {
+        what: &'static str,
+        declared: usize,
+        actual: usize,
+    },
+
+    #[error("{slot} slot does not hold a {expected}")]
+    WrongBlobType {
+        slot: CodeSigningSlot,
+        expected: &'static str,
+    },
 
     #[error("data structure parse error: {0}")]
     Scroll(#[from] scroll::Error),
@@ -112,6 +150,15 @@ pub enum AppleCodesignError {
     #[error("malformed team name string in code directory")]
     CodeDirectoryMalformedTeam,
 
+    #[error("code directory digests extend beyond blob data")]
+    CodeDirectoryMalformedDigests,
+
+    #[error("code directory page size is too large")]
+    CodeDirectoryMalformedPageSize,
+
+    #[error("malformed scatter vector in code directory")]
+    CodeDirectoryMalformedScatter,
+
     #[error("plist error in code directory: {0}")]
     CodeDirectoryPlist(plist::Error),
 
@@ -133,6 +180,18 @@ pub enum AppleCodesignError {
     #[error("error when encoding entitlements to DER: {0}")]
     EntitlementsDerEncode(String),
 
+    #[error("error encoding DER plist: {0}")]
+    DerPlistEncode(String),
+
+    #[error("error decoding DER plist: {0}")]
+    DerPlistDecode(String),
+
+    #[error("malformed provisioning profile: {0}")]
+    ProvisioningProfileMalformed(String),
+
+    #[error("provisioning profile not issued by Apple: {0}")]
+    ProvisioningProfileUntrusted(String),
+
     #[error("unknown executable segment flag: {0}")]
     ExecutableSegmentUnknownFlag(String),
 
@@ -145,6 +204,12 @@ pub enum AppleCodesignError {
     #[error("code requirement data malformed: {0}")]
     RequirementMalformed(&'static str),
 
+    #[error("code requirement syntax error: {0}")]
+    RequirementSyntax(String),
+
+    #[error("code requirement can't be evaluated: {0}")]
+    RequirementUnsupported(String),
+
     #[error("plist error in code resources: {0}")]
     ResourcesPlist(plist::Error),
 
@@ -154,6 +219,10 @@ pub enum AppleCodesignError {
     #[error("plist parse error in code resources: {0}")]
     ResourcesPlistParse(String),
 
+    #[error("bundle path is not valid UTF-8: {0}")]
+    ResourcesPathNotUtf8(PathBuf),
+
+    #[cfg(feature = "full")]
     #[error("bad regular expression in code resources: {0}; {1}")]
     ResourcesBadRegex(String, regex::Error),
 
@@ -163,6 +232,12 @@ pub enum AppleCodesignError {
     #[error("__LINKEDIT segment contains data after signature")]
     DataAfterSignature,
 
+    #[error("dyld chained fixups data is outside the __LINKEDIT segment")]
+    ChainedFixupsOutsideLinkedit,
+
+    #[error("dyld chained fixups data overlaps code signature data")]
+    ChainedFixupsOverlapSignature,
+
     #[error("insufficient room to write code signature load command")]
     LoadCommandNoRoom,
 
@@ -181,6 +256,7 @@ pub enum AppleCodesignError {
     #[error("invalid builder operation: {0}")]
     SignatureBuilder(&'static str),
 
+    #[cfg(feature = "full")]
     #[error("HTTP error: {0}")]
     Reqwest(#[from] reqwest::Error),
 
@@ -193,12 +269,19 @@ pub enum AppleCodesignError {
     #[error("unspecified digest error")]
     DigestUnspecified,
 
+    #[cfg(feature = "full")]
     #[error("error interfacing with directory-based bundle: {0}")]
     DirectoryBundle(anyhow::Error),
 
     #[error("nested bundle does not exist: {0}")]
     BundleUnknown(String),
 
+    #[error("error parsing Info.plist: {0}")]
+    InfoPlistParse(plist::Error),
+
+    #[error("Info.plist is not a dictionary")]
+    InfoPlistNotDictionary,
+
     #[error("bundle Info.plist does not define CFBundleIdentifier: {0}")]
     BundleNoIdentifier(PathBuf),
 
@@ -219,7 +302,7 @@ pub enum AppleCodesignError {
     #[error("error parsing PFX data: {0}")]
     PfxParseError(String),
 
-    #[cfg(target_os = "macos")]
+    #[cfg(all(feature = "full", target_os = "macos"))]
     #[error("SecurityFramework error: {0}")]
     SecurityFramework(#[from] security_framework::base::Error),
 
@@ -229,6 +312,9 @@ pub enum AppleCodesignError {
     #[error("failed to find certificate satisfying requirements: {0}")]
     CertificateNotFound(String),
 
+    #[error("signing identity selector {0} is ambiguous: matched {1} identities")]
+    SigningIdentityAmbiguous(String, usize),
+
     #[error("the given OID does not match a recognized Apple certificate authority extension")]
     OidIsntCertificateAuthority,
 
@@ -244,6 +330,24 @@ pub enum AppleCodesignError {
     #[error("unknown certificate profile: {0}")]
     UnknownCertificateProfile(String),
 
+    #[error("unknown signing distribution: {0}")]
+    UnknownSigningDistribution(String),
+
+    #[error("unknown Mach-O file type: {0}")]
+    UnknownMachOFileType(String),
+
+    #[error("signing certificate is not valid until {0}; check the system clock or wait until the certificate becomes valid")]
+    CertificateNotYetValid(String),
+
+    #[error("signing certificate expired on {0}; obtain a new certificate from your Apple Developer account")]
+    CertificateExpired(String),
+
+    #[error("signing certificate lacks the {0} extended key usage required to sign {1}; {2}")]
+    CertificateMissingExtendedKeyUsage(ExtendedKeyUsagePurpose, SignedEntityKind, &'static str),
+
+    #[error("signing certificate isn't suitable for signing {1} for {0} distribution; {2}")]
+    CertificateUnsuitableForDistribution(SigningDistribution, SignedEntityKind, &'static str),
+
     #[error("unknown code execution policy: {0}")]
     UnknownPolicy(String),
 
@@ -253,12 +357,18 @@ pub enum AppleCodesignError {
     #[error("error producing universal Mach-O binary: {0}")]
     UniversalMachO(#[from] UniversalMachOError),
 
+    #[error("malformed Mach-O: {0}")]
+    MachOParse(#[from] MachOParseError),
+
+    #[cfg(feature = "full")]
     #[error("zip error: {0}")]
     ZipError(#[from] zip::result::ZipError),
 
+    #[cfg(feature = "full")]
     #[error("error writing app metadata XML: {0}")]
     AppMetadataXml(xml::writer::Error),
 
+    #[cfg(feature = "full")]
     #[error("error writing XML: {0}")]
     XmlWrite(xml::writer::Error),
 
@@ -277,6 +387,19 @@ pub enum AppleCodesignError {
     #[error("no authentication credentials to perform notarization request")]
     NotarizeNoAuthCredentials,
 
+    #[error("App Store Connect API Key is unusable: {0}")]
+    NotarizeCredentialsBadKey(String),
+
+    #[error(
+        "Notary API rejected the credentials (HTTP 401); verify the API Key ID, Issuer ID, and private key"
+    )]
+    NotarizeCredentialsRejected,
+
+    #[error(
+        "App Store Connect API Key lacks permission to use the Notary API (HTTP 403); verify the key's role"
+    )]
+    NotarizeCredentialsForbidden,
+
     #[error("reached time limit waiting for notarization to complete")]
     NotarizeWaitLimitReached,
 
@@ -310,6 +433,7 @@ pub enum AppleCodesignError {
     #[error("unable to determine app platform from bundle")]
     BundleUnknownAppPlatform,
 
+    #[cfg(feature = "full")]
     #[error("do not support stapling {0:?} bundles")]
     StapleUnsupportedBundleType(apple_bundles::BundlePackageType),
 
@@ -341,17 +465,52 @@ pub enum AppleCodesignError {
     #[error("YubiKey error: {0}")]
     YubiKey(#[from] yubikey::Error),
 
+    #[error("unrecognized cloud KMS key: {0}")]
+    KmsBadUri(String),
+
+    #[error("cloud KMS credential not found in {0} environment variable")]
+    KmsMissingCredential(&'static str),
+
+    #[error("cloud KMS request failed with HTTP {0}: {1}")]
+    KmsRequest(u16, String),
+
+    #[error("bad response from cloud KMS: {0}")]
+    KmsBadResponse(String),
+
+    #[error("unable to resolve AWS configuration: {0}")]
+    KmsAwsConfig(String),
+
+    #[error("AWS request signing error: {0}")]
+    KmsSigV4(String),
+
+    #[error("no certificate without a private key to pair with cloud KMS key {0}")]
+    KmsNoCertificate(String),
+
+    #[error("certificate {0} has no OCSP responder")]
+    OcspNoResponder(String),
+
+    #[error("OCSP responder returned status {0}")]
+    OcspResponseStatus(u8),
+
+    #[error("bad OCSP response: {0}")]
+    OcspBadResponse(String),
+
+    #[error("OCSP response signature could not be verified")]
+    OcspSignatureInvalid,
+
     #[error("poisoned lock")]
     PoisonedLock,
 
     #[error("internal API / logic error: {0}")]
     LogicError(String),
 
+    #[cfg(feature = "full")]
     #[error("zip structs error: {0}")]
     ZipStructs(#[from] zip_structs::zip_error::ZipReadError),
 
+    #[cfg(feature = "full")]
     #[error("remote signing error: {0}")]
-    RemoteSign(#[from] RemoteSignError),
+    RemoteSign(#[from] crate::remote_signing::RemoteSignError),
 
     #[cfg(feature = "notarize")]
     #[error("bytestream creation error: {0}")]
@@ -361,9 +520,16 @@ pub enum AppleCodesignError {
     #[error("s3 upload error: {0}")]
     AwsS3Error(#[from] aws_sdk_s3::Error),
 
+    #[error("release configuration error: {0}")]
+    ReleaseConfig(String),
+
+    #[error("failed to create DMG: {0}")]
+    ReleaseDmgCreate(String),
+
     #[error("bad time value")]
     BadTime,
 
+    #[cfg(feature = "full")]
     #[error("{0}")]
     Anyhow(#[from] anyhow::Error),
 }
@@ -0,0 +1,178 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Runtime description of supported signature formats.
+//!
+//! Tooling wrapping this crate sometimes needs to know whether a feature is
+//! available, e.g. whether launch constraint slots are understood. Rather than
+//! inferring this from the crate version, [format_support()] describes what
+//! the linked build of this crate supports.
+
+use crate::{
+    code_directory::CodeDirectoryVersion,
+    embedded_signature::{CodeSigningSlot, DigestType},
+    reader::PathType,
+    signing::UnifiedSigner,
+    stapling::Stapler,
+    verify::verify_supports_path_type,
+};
+
+/// Operations supported for a kind of artifact.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ArtifactSupport {
+    /// The kind of artifact.
+    pub kind: PathType,
+    /// Whether the artifact can be signed.
+    pub sign: bool,
+    /// Whether the artifact's signature can be verified.
+    pub verify: bool,
+    /// Whether a notarization ticket can be stapled to the artifact.
+    pub staple: bool,
+    /// Whether the artifact can be submitted for notarization.
+    ///
+    /// Always false if the `notarize` feature is disabled.
+    pub notarize: bool,
+}
+
+/// Describes the signature formats supported by this build of the crate.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FormatSupport {
+    /// Code directory versions that can be parsed and serialized.
+    ///
+    /// Signing picks the lowest version able to express the signature.
+    pub code_directory_versions: Vec<CodeDirectoryVersion>,
+    /// Embedded signature slots that are recognized.
+    ///
+    /// Slots not listed are preserved as [CodeSigningSlot::Unknown].
+    pub slots: Vec<CodeSigningSlot>,
+    /// Digest types that can be computed for code directories.
+    pub digest_types: Vec<DigestType>,
+    /// Kinds of artifacts and the operations supported on them.
+    pub artifacts: Vec<ArtifactSupport>,
+}
+
+impl FormatSupport {
+    /// Whether a raw code directory version is supported.
+    pub fn supports_code_directory_version(&self, version: u32) -> bool {
+        self.code_directory_versions
+            .iter()
+            .any(|v| *v as u32 == version)
+    }
+
+    /// Whether a slot is recognized.
+    pub fn supports_slot(&self, slot: CodeSigningSlot) -> bool {
+        self.slots.contains(&slot)
+    }
+
+    /// Whether a digest type can be computed.
+    pub fn supports_digest_type(&self, digest_type: DigestType) -> bool {
+        self.digest_types.contains(&digest_type)
+    }
+
+    /// Resolve the operations supported for a kind of artifact.
+    ///
+    /// Returns `None` if the kind of artifact isn't supported at all.
+    pub fn artifact(&self, kind: PathType) -> Option<&ArtifactSupport> {
+        self.artifacts.iter().find(|a| a.kind == kind)
+    }
+}
+
+/// Describe the signature formats supported by this build of the crate.
+pub fn format_support() -> FormatSupport {
+    FormatSupport {
+        code_directory_versions: vec![
+            CodeDirectoryVersion::Initial,
+            CodeDirectoryVersion::SupportsScatter,
+            CodeDirectoryVersion::SupportsTeamId,
+            CodeDirectoryVersion::SupportsCodeLimit64,
+            CodeDirectoryVersion::SupportsExecutableSegment,
+            CodeDirectoryVersion::SupportsRuntime,
+            CodeDirectoryVersion::SupportsLinkage,
+        ],
+        slots: vec![
+            CodeSigningSlot::CodeDirectory,
+            CodeSigningSlot::Info,
+            CodeSigningSlot::RequirementSet,
+            CodeSigningSlot::ResourceDir,
+            CodeSigningSlot::Application,
+            CodeSigningSlot::Entitlements,
+            CodeSigningSlot::RepSpecific,
+            CodeSigningSlot::EntitlementsDer,
+            CodeSigningSlot::LaunchConstraintsSelf,
+            CodeSigningSlot::LaunchConstraintsParent,
+            CodeSigningSlot::LaunchConstraintsResponsible,
+            CodeSigningSlot::LibraryConstraints,
+            CodeSigningSlot::AlternateCodeDirectory0,
+            CodeSigningSlot::AlternateCodeDirectory1,
+            CodeSigningSlot::AlternateCodeDirectory2,
+            CodeSigningSlot::AlternateCodeDirectory3,
+            CodeSigningSlot::AlternateCodeDirectory4,
+            CodeSigningSlot::Signature,
+            CodeSigningSlot::Identification,
+            CodeSigningSlot::Ticket,
+        ],
+        digest_types: vec![
+            DigestType::Sha1,
+            DigestType::Sha256,
+            DigestType::Sha256Truncated,
+            DigestType::Sha384,
+            DigestType::Sha512,
+        ],
+        artifacts: PathType::all()
+            .into_iter()
+            .map(|kind| ArtifactSupport {
+                kind,
+                sign: UnifiedSigner::supports_path_type(kind),
+                verify: verify_supports_path_type(kind),
+                staple: Stapler::supports_path_type(kind),
+                notarize: notarize_supports_path_type(kind),
+            })
+            .filter(|a| a.sign || a.verify || a.staple || a.notarize)
+            .collect(),
+    }
+}
+
+#[cfg(feature = "notarize")]
+fn notarize_supports_path_type(kind: PathType) -> bool {
+    crate::notarization::Notarizer::supports_path_type(kind)
+}
+
+#[cfg(not(feature = "notarize"))]
+fn notarize_supports_path_type(_: PathType) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn consistent() {
+        let support = format_support();
+
+        for slot in &support.slots {
+            assert!(!matches!(slot, CodeSigningSlot::Unknown(_)));
+            assert_eq!(CodeSigningSlot::from(u32::from(*slot)), *slot);
+        }
+
+        for digest_type in &support.digest_types {
+            assert!(digest_type.digest_data(b"data").is_ok());
+        }
+
+        assert!(support.supports_code_directory_version(0x20400));
+        assert!(!support.supports_code_directory_version(0x20700));
+        assert!(support.supports_slot(CodeSigningSlot::LaunchConstraintsSelf));
+        assert!(!support.supports_slot(CodeSigningSlot::Unknown(0x20)));
+        assert!(!support.supports_digest_type(DigestType::None));
+        assert!(support.artifact(PathType::MachO).unwrap().sign);
+        assert!(!support.artifact(PathType::MachO).unwrap().staple);
+        assert!(support.artifact(PathType::Xar).unwrap().verify);
+        assert!(!support.artifact(PathType::Zip).unwrap().sign);
+        assert_eq!(
+            support.artifact(PathType::Zip).unwrap().notarize,
+            cfg!(feature = "notarize")
+        );
+        assert!(support.artifact(PathType::Other).is_none());
+    }
+}
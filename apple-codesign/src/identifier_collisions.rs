@@ -0,0 +1,427 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Detection of duplicate signing identifiers in bundles.
+//!
+//! Every bundle and Mach-O binary nested in a bundle is signed with an
+//! identifier. When two pieces of nested code share an identifier, XPC service
+//! lookup and library validation can resolve the wrong code. This module
+//! computes the identifiers signing would use so collisions can be reported
+//! before signing begins.
+
+use {
+    crate::{
+        error::AppleCodesignError,
+        macho::MachFile,
+        mapped_file::MappedFile,
+        signing_settings::{SettingsScope, SigningSettings},
+    },
+    apple_bundles::{BundlePackageType, DirectoryBundle},
+    goblin::mach::{
+        fat::FAT_MAGIC,
+        header::{MH_CIGAM, MH_CIGAM_64, MH_MAGIC, MH_MAGIC_64},
+    },
+    std::{
+        collections::{BTreeMap, BTreeSet},
+        fmt::{Display, Formatter},
+        io::Read,
+        path::Path,
+    },
+};
+
+/// A bundle or Mach-O binary within a bundle that receives a signature.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NestedCode {
+    /// Path relative to the root of the main bundle.
+    ///
+    /// Empty for the main bundle.
+    pub path: String,
+    /// Whether this is a bundle as opposed to a standalone Mach-O binary.
+    pub is_bundle: bool,
+    /// The identifier this code would be signed with.
+    pub identifier: String,
+}
+
+/// A signing identifier used by multiple pieces of nested code.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IdentifierCollision {
+    /// The duplicated identifier.
+    pub identifier: String,
+    /// Code using the identifier, sorted by path.
+    pub code: Vec<NestedCode>,
+    /// Suggested unique identifiers, keyed by path.
+    ///
+    /// The first entry in [Self::code] keeps its identifier and has no suggestion.
+    pub suggestions: BTreeMap<String, String>,
+}
+
+impl Display for IdentifierCollision {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let paths = self
+            .code
+            .iter()
+            .map(|code| display_path(&code.path))
+            .collect::<Vec<_>>();
+
+        f.write_fmt(format_args!(
+            "identifier {} is used by {}",
+            self.identifier,
+            paths.join(", ")
+        ))?;
+
+        if !self.suggestions.is_empty() {
+            let suggestions = self
+                .suggestions
+                .iter()
+                .map(|(path, identifier)| format!("{} -> {}", display_path(path), identifier))
+                .collect::<Vec<_>>();
+
+            f.write_fmt(format_args!(" (consider using {})", suggestions.join(", ")))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn display_path(path: &str) -> &str {
+    if path.is_empty() {
+        "<main bundle>"
+    } else {
+        path
+    }
+}
+
+/// Resolve the identifiers that signing the bundle at `path` would use.
+///
+/// Identifiers are resolved the same way the bundle signer resolves them:
+/// bundles use the `CFBundleIdentifier` from their `Info.plist` and Mach-O
+/// binaries use the binary identifier from `settings`, then the identifier in
+/// their existing signature, then their file name. Bundles lacking an
+/// identifier are omitted.
+///
+/// Results are sorted by path.
+pub fn nested_code_identifiers(
+    settings: &SigningSettings,
+    path: impl AsRef<Path>,
+) -> Result<Vec<NestedCode>, AppleCodesignError> {
+    let main_bundle = DirectoryBundle::new_from_path(path.as_ref())
+        .map_err(AppleCodesignError::DirectoryBundle)?;
+
+    let mut bundles = vec![(String::new(), main_bundle.clone(), settings.clone())];
+
+    for (rel, bundle) in main_bundle
+        .nested_bundles(true)
+        .map_err(AppleCodesignError::DirectoryBundle)?
+    {
+        let rel = rel.replace('\\', "/");
+        let bundle_settings = settings.as_nested_bundle_settings(&rel);
+        bundles.push((rel, bundle, bundle_settings));
+    }
+
+    let mut code = vec![];
+
+    for (rel, bundle, bundle_settings) in bundles {
+        // Versioned frameworks aren't signed. Only their versions are.
+        if bundle.package_type() == BundlePackageType::Framework
+            && bundle.root_dir().join("Versions").is_dir()
+        {
+            continue;
+        }
+
+        if let Some(identifier) = bundle
+            .identifier()
+            .map_err(AppleCodesignError::DirectoryBundle)?
+        {
+            code.push(NestedCode {
+                path: rel.clone(),
+                is_bundle: true,
+                identifier,
+            });
+        }
+
+        for file in bundle
+            .files(false)
+            .map_err(AppleCodesignError::DirectoryBundle)?
+        {
+            if file
+                .is_main_executable()
+                .map_err(AppleCodesignError::DirectoryBundle)?
+                || file
+                    .symlink_target()
+                    .map_err(AppleCodesignError::DirectoryBundle)?
+                    .is_some()
+                || !has_macho_magic(file.absolute_path())?
+            {
+                continue;
+            }
+
+            let file_path = file.relative_path().to_string_lossy().replace('\\', "/");
+
+            let macho_settings = bundle_settings.as_bundle_macho_settings(&file_path);
+
+            let identifier = match macho_settings
+                .binary_identifier(SettingsScope::Main)
+                .or_else(|| macho_settings.binary_identifier(SettingsScope::MultiArchIndex(0)))
+            {
+                Some(identifier) => Some(identifier.to_string()),
+                None => existing_identifier(file.absolute_path())?,
+            }
+            .unwrap_or_else(|| {
+                file_name(&file_path)
+                    .strip_suffix(".dylib")
+                    .unwrap_or_else(|| file_name(&file_path))
+                    .to_string()
+            });
+
+            code.push(NestedCode {
+                path: if rel.is_empty() {
+                    file_path
+                } else {
+                    format!("{rel}/{file_path}")
+                },
+                is_bundle: false,
+                identifier,
+            });
+        }
+    }
+
+    code.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(code)
+}
+
+/// Find signing identifiers used by multiple pieces of code in the bundle at `path`.
+///
+/// See [nested_code_identifiers()] for how identifiers are resolved.
+///
+/// An empty vector means every identifier is unique. Collisions are sorted by
+/// identifier.
+pub fn identifier_collisions(
+    settings: &SigningSettings,
+    path: impl AsRef<Path>,
+) -> Result<Vec<IdentifierCollision>, AppleCodesignError> {
+    Ok(find_collisions(nested_code_identifiers(settings, path)?))
+}
+
+fn find_collisions(code: Vec<NestedCode>) -> Vec<IdentifierCollision> {
+    let mut used = code
+        .iter()
+        .map(|code| code.identifier.clone())
+        .collect::<BTreeSet<_>>();
+
+    let mut by_identifier = BTreeMap::<String, Vec<NestedCode>>::new();
+    for code in code {
+        by_identifier
+            .entry(code.identifier.clone())
+            .or_default()
+            .push(code);
+    }
+
+    by_identifier
+        .into_iter()
+        .filter(|(_, code)| code.len() > 1)
+        .map(|(identifier, code)| {
+            let suggestions = code
+                .iter()
+                .skip(1)
+                .map(|code| {
+                    let suggestion = suggest_identifier(&identifier, &code.path, &used);
+                    used.insert(suggestion.clone());
+
+                    (code.path.clone(), suggestion)
+                })
+                .collect();
+
+            IdentifierCollision {
+                identifier,
+                code,
+                suggestions,
+            }
+        })
+        .collect()
+}
+
+/// Whether the file at `path` starts with a Mach-O or fat Mach-O magic.
+fn has_macho_magic(path: &Path) -> Result<bool, AppleCodesignError> {
+    let mut magic = [0u8; 4];
+    if std::fs::File::open(path)?.read_exact(&mut magic).is_err() {
+        return Ok(false);
+    }
+
+    Ok(matches!(
+        u32::from_be_bytes(magic),
+        FAT_MAGIC | MH_MAGIC | MH_CIGAM | MH_MAGIC_64 | MH_CIGAM_64
+    ))
+}
+
+/// Obtain the identifier in the existing signature of the first Mach-O in a file.
+///
+/// Only the code directory is parsed. Unlike
+/// [SigningSettings::import_settings_from_macho()], other signature data and
+/// load commands aren't inspected.
+fn existing_identifier(path: &Path) -> Result<Option<String>, AppleCodesignError> {
+    // SAFETY: the file isn't written while it is mapped.
+    let data = unsafe { MappedFile::open(path)? };
+
+    let mach = MachFile::parse(&data)?;
+    let macho = mach.nth_macho(0)?;
+
+    Ok(match macho.code_signature()? {
+        Some(signature) => signature.code_directory()?.map(|cd| cd.ident.to_string()),
+        None => None,
+    })
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// Derive an unused identifier from a duplicated identifier and the path of its code.
+fn suggest_identifier(identifier: &str, path: &str, used: &BTreeSet<String>) -> String {
+    let name = file_name(path);
+    let stem = name.split_once('.').map(|(stem, _)| stem).unwrap_or(name);
+
+    // Identifiers are restricted to alphanumerics, hyphens, and periods.
+    let component = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect::<String>();
+
+    let base = format!("{identifier}.{component}");
+
+    let mut candidate = base.clone();
+    let mut counter = 2;
+    while used.contains(&candidate) {
+        candidate = format!("{base}-{counter}");
+        counter += 1;
+    }
+
+    candidate
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, std::path::PathBuf};
+
+    fn write_bundle(
+        root: &Path,
+        identifier: &str,
+        executable: &str,
+    ) -> Result<(), AppleCodesignError> {
+        let contents = root.join("Contents");
+        std::fs::create_dir_all(contents.join("MacOS"))?;
+
+        let mut info = plist::Dictionary::new();
+        info.insert("CFBundleIdentifier".into(), identifier.into());
+        info.insert("CFBundleExecutable".into(), executable.into());
+        plist::Value::Dictionary(info)
+            .to_file_xml(contents.join("Info.plist"))
+            .map_err(AppleCodesignError::PlistSerializeXml)?;
+
+        std::fs::write(
+            contents.join("MacOS").join(executable),
+            crate::test_support::SyntheticMachO::default().build(),
+        )?;
+
+        Ok(())
+    }
+
+    fn app() -> Result<(tempfile::TempDir, PathBuf), AppleCodesignError> {
+        let td = tempfile::tempdir()?;
+        let root = td.path().join("MyApp.app");
+
+        write_bundle(&root, "com.example.app", "MyApp")?;
+        write_bundle(
+            &root.join("Contents/Library/LoginItems/Helper.app"),
+            "com.example.app",
+            "Helper",
+        )?;
+        std::fs::write(
+            root.join("Contents/MacOS/tool"),
+            crate::test_support::SyntheticMachO::default().build(),
+        )?;
+
+        Ok((td, root))
+    }
+
+    #[test]
+    fn identifiers() -> Result<(), AppleCodesignError> {
+        let (_td, root) = app()?;
+
+        let code = nested_code_identifiers(&SigningSettings::default(), &root)?;
+        assert_eq!(
+            code.iter()
+                .map(|code| (code.path.as_str(), code.is_bundle, code.identifier.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("", true, "com.example.app"),
+                (
+                    "Contents/Library/LoginItems/Helper.app",
+                    true,
+                    "com.example.app"
+                ),
+                ("Contents/MacOS/tool", false, "tool"),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn collisions() -> Result<(), AppleCodesignError> {
+        let (_td, root) = app()?;
+
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(
+            SettingsScope::Path("Contents/MacOS/tool".into()),
+            "com.example.app",
+        );
+
+        let collisions = identifier_collisions(&settings, &root)?;
+        assert_eq!(collisions.len(), 1);
+        assert_eq!(collisions[0].identifier, "com.example.app");
+        assert_eq!(collisions[0].code.len(), 3);
+        assert_eq!(
+            collisions[0].suggestions,
+            [
+                (
+                    "Contents/Library/LoginItems/Helper.app".to_string(),
+                    "com.example.app.Helper".to_string()
+                ),
+                (
+                    "Contents/MacOS/tool".to_string(),
+                    "com.example.app.tool".to_string()
+                ),
+            ]
+            .into_iter()
+            .collect()
+        );
+        assert_eq!(
+            collisions[0].to_string(),
+            "identifier com.example.app is used by <main bundle>, \
+            Contents/Library/LoginItems/Helper.app, Contents/MacOS/tool (consider using \
+            Contents/Library/LoginItems/Helper.app -> com.example.app.Helper, \
+            Contents/MacOS/tool -> com.example.app.tool)"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn suggestions_are_unique() {
+        let used = ["com.example.tool", "com.example.tool.helper"]
+            .into_iter()
+            .map(String::from)
+            .collect::<BTreeSet<_>>();
+
+        assert_eq!(
+            suggest_identifier("com.example.tool", "Contents/MacOS/helper", &used),
+            "com.example.tool.helper-2"
+        );
+        assert_eq!(
+            suggest_identifier("com.example", "Contents/Frameworks/My Lib.framework", &used),
+            "com.example.My-Lib"
+        );
+    }
+}
@@ -0,0 +1,186 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! `Info.plist` introspection.
+//!
+//! Bundles describe themselves in an `Info.plist` file. Standalone Mach-O
+//! binaries can embed one in a `__TEXT,__info_plist` section. Signatures record
+//! the digest of the file's raw bytes in the Info special slot and commonly use
+//! its `CFBundleIdentifier` as the binary identifier.
+//!
+//! The file may be an XML or a binary plist. The digest is always computed
+//! over the data as it exists on disk.
+
+use {
+    crate::{
+        embedded_signature::DigestType,
+        error::AppleCodesignError,
+        macho::MachOFileType,
+        signing_settings::{SettingsScope, SigningSettings},
+    },
+    log::info,
+    plist::{Dictionary, Value},
+    std::path::Path,
+};
+
+/// A parsed `Info.plist` file.
+#[derive(Clone, Debug)]
+pub struct InfoPlist {
+    data: Vec<u8>,
+    plist: Dictionary,
+}
+
+impl InfoPlist {
+    /// Construct an instance from raw XML or binary plist data.
+    pub fn from_data(data: Vec<u8>) -> Result<Self, AppleCodesignError> {
+        let value = Value::from_reader(std::io::Cursor::new(&data))
+            .map_err(AppleCodesignError::InfoPlistParse)?;
+
+        match value {
+            Value::Dictionary(plist) => Ok(Self { data, plist }),
+            _ => Err(AppleCodesignError::InfoPlistNotDictionary),
+        }
+    }
+
+    /// Construct an instance by reading a file.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, AppleCodesignError> {
+        Self::from_data(std::fs::read(path.as_ref())?)
+    }
+
+    /// The raw data this instance was parsed from.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The parsed plist dictionary.
+    pub fn plist(&self) -> &Dictionary {
+        &self.plist
+    }
+
+    /// Obtain the string value of a key.
+    pub fn string(&self, key: &str) -> Option<&str> {
+        self.plist.get(key).and_then(|v| v.as_string())
+    }
+
+    /// The bundle identifier (`CFBundleIdentifier`).
+    ///
+    /// This is the default binary identifier of the bundle's main executable.
+    pub fn identifier(&self) -> Option<&str> {
+        self.string("CFBundleIdentifier")
+    }
+
+    /// The name of the bundle's main executable (`CFBundleExecutable`).
+    pub fn executable(&self) -> Option<&str> {
+        self.string("CFBundleExecutable")
+    }
+
+    /// The four character bundle type code (`CFBundlePackageType`).
+    pub fn package_type(&self) -> Option<&str> {
+        self.string("CFBundlePackageType")
+    }
+
+    /// The file type the main executable is expected to have given the package type.
+    ///
+    /// Applications and XPC services have executables, frameworks have dynamic
+    /// libraries, and loadable bundles and kernel extensions have bundles.
+    /// Returns `None` for other or missing package types.
+    pub fn macho_file_type(&self) -> Option<MachOFileType> {
+        match self.package_type()? {
+            "APPL" | "XPC!" => Some(MachOFileType::Executable),
+            "FMWK" => Some(MachOFileType::Dylib),
+            "BNDL" => Some(MachOFileType::Bundle),
+            "KEXT" => Some(MachOFileType::KextBundle),
+            _ => None,
+        }
+    }
+
+    /// Compute the digest recorded in the Info special slot.
+    pub fn digest(&self, digest_type: DigestType) -> Result<Vec<u8>, AppleCodesignError> {
+        digest_type.digest_data(&self.data)
+    }
+
+    /// Register this `Info.plist` with signing settings.
+    ///
+    /// The data is registered for digesting in the Info special slot. The
+    /// `CFBundleIdentifier` becomes the binary identifier of `scope` unless one
+    /// is already set.
+    pub fn apply_to_settings(&self, settings: &mut SigningSettings, scope: SettingsScope) {
+        if settings.binary_identifier(&scope).is_none() {
+            if let Some(identifier) = self.identifier() {
+                info!(
+                    "setting binary identifier to {} (derived from CFBundleIdentifier in Info.plist)",
+                    identifier
+                );
+                settings.set_binary_identifier(scope.clone(), identifier);
+            }
+        }
+
+        settings.set_info_plist_data(scope, self.data.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() -> Result<(), AppleCodesignError> {
+        let mut dict = Dictionary::new();
+        dict.insert("CFBundleIdentifier".into(), "com.example.app".into());
+        dict.insert("CFBundleExecutable".into(), "app".into());
+        dict.insert("CFBundlePackageType".into(), "FMWK".into());
+
+        let mut xml = vec![];
+        Value::Dictionary(dict.clone())
+            .to_writer_xml(&mut xml)
+            .map_err(AppleCodesignError::PlistSerializeXml)?;
+        let mut binary = vec![];
+        Value::Dictionary(dict)
+            .to_writer_binary(&mut binary)
+            .map_err(AppleCodesignError::PlistSerializeXml)?;
+
+        for data in [xml, binary] {
+            let info = InfoPlist::from_data(data.clone())?;
+
+            assert_eq!(info.identifier(), Some("com.example.app"));
+            assert_eq!(info.executable(), Some("app"));
+            assert_eq!(info.macho_file_type(), Some(MachOFileType::Dylib));
+            assert_eq!(
+                info.digest(DigestType::Sha256)?,
+                DigestType::Sha256.digest_data(&data)?
+            );
+
+            let mut settings = SigningSettings::default();
+            info.apply_to_settings(&mut settings, SettingsScope::Main);
+            assert_eq!(
+                settings.binary_identifier(SettingsScope::Main),
+                Some("com.example.app")
+            );
+            assert_eq!(
+                settings.info_plist_data(SettingsScope::Main),
+                Some(data.as_slice())
+            );
+
+            // Explicit identifiers take precedence.
+            let mut settings = SigningSettings::default();
+            settings.set_binary_identifier(SettingsScope::Main, "explicit");
+            info.apply_to_settings(&mut settings, SettingsScope::Main);
+            assert_eq!(
+                settings.binary_identifier(SettingsScope::Main),
+                Some("explicit")
+            );
+        }
+
+        assert!(matches!(
+            InfoPlist::from_data(b"<plist version=\"1.0\"><string>x</string></plist>".to_vec()),
+            Err(AppleCodesignError::InfoPlistNotDictionary)
+        ));
+        assert!(matches!(
+            InfoPlist::from_data(b"garbage".to_vec()),
+            Err(AppleCodesignError::InfoPlistParse(_))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,601 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Export of verification results as JUnit XML.
+//!
+//! CI systems render JUnit XML reports natively. This module groups the
+//! problems found by [crate::verify] into a fixed set of checks per artifact
+//! and emits each check as a test case that passed, failed, or was skipped.
+
+use {
+    crate::{
+        error::AppleCodesignError,
+        macho::MachFile,
+        ocsp::OcspChecker,
+        verify::{
+            verify_macho_slices, verify_macho_slices_detached, verify_revocation,
+            verify_xar_data_internal, VerificationContext, VerificationProblem,
+            VerificationProblemType, XAR_MAGIC,
+        },
+    },
+    std::{io::Write, path::Path},
+    xml::writer::{EmitterConfig, EventWriter, XmlEvent},
+};
+
+/// A category of verification performed on a Mach-O binary.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum VerificationCheck {
+    /// The artifact could be read.
+    Read,
+    /// The artifact could be parsed as Mach-O.
+    Parse,
+    /// Signature data is present and can be parsed.
+    SignatureData,
+    /// Signature data is placed where Apple's tools expect it.
+    Layout,
+    /// The CMS signature is valid and binds the code directories.
+    CmsSignature,
+    /// A code directory is present and uses a modern digest.
+    CodeDirectory,
+    /// Code pages match the digests in the code directory.
+    CodeDigests,
+    /// Special slots match the digests in the code directory.
+    SlotDigests,
+    /// The code satisfies its designated requirement.
+    ///
+    /// Only performed by [crate::verify_macho_report()].
+    DesignatedRequirement,
+    /// No certificate in the signing chain is revoked.
+    ///
+    /// Only performed by [ArtifactVerification::check_revocation()].
+    Revocation,
+}
+
+impl VerificationCheck {
+    /// All checks performed by [crate::verify_macho()], in the order they are performed.
+    pub fn all() -> &'static [Self] {
+        &[
+            Self::Read,
+            Self::Parse,
+            Self::SignatureData,
+            Self::Layout,
+            Self::CmsSignature,
+            Self::CodeDirectory,
+            Self::CodeDigests,
+            Self::SlotDigests,
+        ]
+    }
+
+    /// The name of this check.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Parse => "parse",
+            Self::SignatureData => "signature-data",
+            Self::Layout => "layout",
+            Self::CmsSignature => "cms-signature",
+            Self::CodeDirectory => "code-directory",
+            Self::CodeDigests => "code-digests",
+            Self::SlotDigests => "slot-digests",
+            Self::DesignatedRequirement => "designated-requirement",
+            Self::Revocation => "revocation",
+        }
+    }
+
+    /// Resolve the check reporting a problem.
+    pub fn from_problem(problem: &VerificationProblemType) -> Self {
+        match problem {
+            VerificationProblemType::IoError(_) => Self::Read,
+            VerificationProblemType::MachOParseError(_) | VerificationProblemType::XarError(_) => {
+                Self::Parse
+            }
+            VerificationProblemType::NoMachOSignatureData
+            | VerificationProblemType::MachOSignatureError(_)
+            | VerificationProblemType::DetachedSignatureError(_)
+            | VerificationProblemType::DetachedSignatureMissingArch(_) => Self::SignatureData,
+            VerificationProblemType::LinkeditNotLastSegment
+            | VerificationProblemType::SignatureNotLastLinkeditData
+            | VerificationProblemType::SuperblobLayout(_) => Self::Layout,
+            VerificationProblemType::NoCryptographicSignature
+            | VerificationProblemType::CmsError(_)
+            | VerificationProblemType::CmsOldDigestAlgorithm(_)
+            | VerificationProblemType::CmsOldSignatureAlgorithm(_)
+            | VerificationProblemType::CmsHashAgilityError(_)
+            | VerificationProblemType::CmsCdHashMissing(..)
+            | VerificationProblemType::CmsCdHashUnexpected(..)
+            | VerificationProblemType::CmsCodeDirectoryDigestMissing(..)
+            | VerificationProblemType::CmsCodeDirectoryDigestUnexpected(..)
+            | VerificationProblemType::CmsCodeDirectoryDigestMismatch(..)
+            | VerificationProblemType::TimeStampTokenError(_)
+            | VerificationProblemType::TimeStampTokenMalformed(_)
+            | VerificationProblemType::TimeStampImprintMismatch(..)
+            | VerificationProblemType::CmsCertificateChainUntrusted(_)
+            | VerificationProblemType::XarSignatureError(_) => Self::CmsSignature,
+            VerificationProblemType::NoCodeDirectory
+            | VerificationProblemType::CodeDirectoryOldDigestAlgorithm(_) => Self::CodeDirectory,
+            VerificationProblemType::CodeDigestError(_)
+            | VerificationProblemType::CodeDigestMissingEntry(..)
+            | VerificationProblemType::CodeDigestExtraEntry(..)
+            | VerificationProblemType::CodeDigestMismatch(..)
+            | VerificationProblemType::CodeLimitExceedsData(..)
+            | VerificationProblemType::CodeLimitMismatch(..)
+            | VerificationProblemType::XarTableOfContentsChecksumMismatch
+            | VerificationProblemType::XarFileChecksumMismatch(_) => Self::CodeDigests,
+            VerificationProblemType::SlotDigestMissing(_)
+            | VerificationProblemType::ExtraSlotDigest(..)
+            | VerificationProblemType::SlotDigestMismatch(..)
+            | VerificationProblemType::SlotDigestError(_)
+            | VerificationProblemType::EntitlementsMismatch => Self::SlotDigests,
+            VerificationProblemType::DesignatedRequirementUnsatisfied(_)
+            | VerificationProblemType::DesignatedRequirementError(_) => Self::DesignatedRequirement,
+            VerificationProblemType::CertificateRevoked(..)
+            | VerificationProblemType::RevocationCheckError(_) => Self::Revocation,
+        }
+    }
+}
+
+/// The outcome of a verification check.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CheckOutcome {
+    /// No problems were found.
+    Passed,
+    /// Problems were found. Holds their descriptions.
+    Failed(Vec<String>),
+    /// The check couldn't be performed. Holds the reason.
+    Skipped(String),
+}
+
+/// The outcome of a verification check on a Mach-O binary.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CheckResult {
+    /// The check performed.
+    pub check: VerificationCheck,
+    /// Architecture of the Mach-O within a fat binary.
+    pub arch: Option<String>,
+    /// The outcome of the check.
+    pub outcome: CheckOutcome,
+}
+
+impl CheckResult {
+    /// The test case name of this result.
+    pub fn name(&self) -> String {
+        match &self.arch {
+            Some(arch) => format!("{} [{}]", self.check.name(), arch),
+            None => self.check.name().to_string(),
+        }
+    }
+}
+
+/// Verification results of an artifact.
+#[derive(Debug)]
+pub struct ArtifactVerification {
+    /// Name of the artifact. Usually its path.
+    pub name: String,
+    /// The outcome of every check.
+    pub checks: Vec<CheckResult>,
+    /// The problems found.
+    pub problems: Vec<VerificationProblem>,
+}
+
+impl ArtifactVerification {
+    /// Verify the Mach-O binary at a path.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+
+        let context = VerificationContext {
+            path: Some(path.to_path_buf()),
+            ..Default::default()
+        };
+
+        match std::fs::read(path) {
+            Ok(data) => Self::verify(path.display().to_string(), &data, None, context),
+            Err(e) => Self::from_problems(
+                path.display().to_string(),
+                None,
+                vec![VerificationProblem {
+                    context,
+                    problem: VerificationProblemType::IoError(e),
+                }],
+            ),
+        }
+    }
+
+    /// Verify Mach-O data, naming the artifact `name`.
+    pub fn from_data(name: impl ToString, data: impl AsRef<[u8]>) -> Self {
+        Self::verify(
+            name.to_string(),
+            data.as_ref(),
+            None,
+            VerificationContext::default(),
+        )
+    }
+
+    /// Verify Mach-O data against a detached signature, naming the artifact `name`.
+    pub fn from_data_detached(
+        name: impl ToString,
+        data: impl AsRef<[u8]>,
+        signature_data: impl AsRef<[u8]>,
+    ) -> Self {
+        Self::verify(
+            name.to_string(),
+            data.as_ref(),
+            Some(signature_data.as_ref()),
+            VerificationContext::default(),
+        )
+    }
+
+    fn verify(
+        name: String,
+        data: &[u8],
+        signature_data: Option<&[u8]>,
+        context: VerificationContext,
+    ) -> Self {
+        if signature_data.is_none() && data.starts_with(XAR_MAGIC) {
+            return Self::from_problems(name, None, verify_xar_data_internal(data, context));
+        }
+
+        let mach = match MachFile::parse(data) {
+            Ok(mach) => mach,
+            Err(e) => {
+                return Self::from_problems(
+                    name,
+                    None,
+                    vec![VerificationProblem {
+                        context,
+                        problem: VerificationProblemType::MachOParseError(e),
+                    }],
+                );
+            }
+        };
+
+        let mut res = Self {
+            name,
+            checks: vec![],
+            problems: vec![],
+        };
+
+        let slices = match signature_data {
+            Some(signature_data) => verify_macho_slices_detached(mach, signature_data, &context),
+            None => verify_macho_slices(mach, &context),
+        };
+
+        for (context, problems) in slices {
+            res.checks
+                .extend(check_results(context.arch.as_deref(), &problems));
+            res.problems.extend(problems);
+        }
+
+        res
+    }
+
+    /// Check whether the certificates signing the artifact were revoked.
+    ///
+    /// `data` is the artifact's data. See [verify_revocation()].
+    pub fn check_revocation(&mut self, data: impl AsRef<[u8]>, checker: &OcspChecker) {
+        let problems = verify_revocation(data, checker);
+
+        let outcome = if problems.is_empty() {
+            CheckOutcome::Passed
+        } else {
+            CheckOutcome::Failed(problems.iter().map(|problem| problem.to_string()).collect())
+        };
+
+        self.checks.push(CheckResult {
+            check: VerificationCheck::Revocation,
+            arch: None,
+            outcome,
+        });
+        self.problems.extend(problems);
+    }
+
+    fn from_problems(name: String, arch: Option<&str>, problems: Vec<VerificationProblem>) -> Self {
+        Self {
+            name,
+            checks: check_results(arch, &problems),
+            problems,
+        }
+    }
+
+    fn count(&self, filter: impl Fn(&CheckOutcome) -> bool) -> usize {
+        self.checks
+            .iter()
+            .filter(|check| filter(&check.outcome))
+            .count()
+    }
+}
+
+/// Resolve the outcome of every check given the problems found in a Mach-O.
+pub(crate) fn check_results(
+    arch: Option<&str>,
+    problems: &[VerificationProblem],
+) -> Vec<CheckResult> {
+    // Problems that stop verification cause later checks to be skipped.
+    let blocker = problems.iter().find_map(|problem| {
+        let reason = match &problem.problem {
+            VerificationProblemType::IoError(_) => "artifact could not be read",
+            VerificationProblemType::MachOParseError(_) => "artifact is not a Mach-O binary",
+            VerificationProblemType::NoMachOSignatureData
+            | VerificationProblemType::MachOSignatureError(_)
+            | VerificationProblemType::DetachedSignatureError(_)
+            | VerificationProblemType::DetachedSignatureMissingArch(_) => {
+                "signature data could not be read"
+            }
+            _ => return None,
+        };
+
+        Some((VerificationCheck::from_problem(&problem.problem), reason))
+    });
+
+    let no_code_directory = problems
+        .iter()
+        .any(|problem| matches!(problem.problem, VerificationProblemType::NoCodeDirectory));
+
+    VerificationCheck::all()
+        .iter()
+        .map(|check| {
+            let messages = problems
+                .iter()
+                .filter(|problem| {
+                    VerificationCheck::from_problem(&problem.problem) == *check
+                        && !matches!(
+                            problem.problem,
+                            VerificationProblemType::NoCryptographicSignature
+                        )
+                })
+                .map(|problem| problem.to_string())
+                .collect::<Vec<_>>();
+
+            let outcome = if !messages.is_empty() {
+                CheckOutcome::Failed(messages)
+            } else if let Some((_, reason)) = blocker.filter(|(blocking, _)| check > blocking) {
+                CheckOutcome::Skipped(reason.to_string())
+            } else if *check == VerificationCheck::CmsSignature
+                && problems.iter().any(|problem| {
+                    matches!(
+                        problem.problem,
+                        VerificationProblemType::NoCryptographicSignature
+                    )
+                })
+            {
+                CheckOutcome::Skipped("no cryptographic signature (ad-hoc signed)".into())
+            } else if no_code_directory
+                && matches!(
+                    check,
+                    VerificationCheck::CodeDigests | VerificationCheck::SlotDigests
+                )
+            {
+                CheckOutcome::Skipped("no code directory".into())
+            } else {
+                CheckOutcome::Passed
+            };
+
+            CheckResult {
+                check: *check,
+                arch: arch.map(|x| x.to_string()),
+                outcome,
+            }
+        })
+        .collect()
+}
+
+/// Write verification results as a JUnit XML report.
+///
+/// Each artifact becomes a `<testsuite>` and each check a `<testcase>`.
+pub fn write_junit_xml(
+    artifacts: &[ArtifactVerification],
+    writer: impl Write,
+) -> Result<(), AppleCodesignError> {
+    let mut writer = EmitterConfig::new()
+        .perform_indent(true)
+        .create_writer(writer);
+
+    let tests = artifacts
+        .iter()
+        .map(|artifact| artifact.checks.len())
+        .sum::<usize>();
+    let failures = artifacts
+        .iter()
+        .map(|a| a.count(|o| matches!(o, CheckOutcome::Failed(_))))
+        .sum::<usize>();
+    let skipped = artifacts
+        .iter()
+        .map(|a| a.count(|o| matches!(o, CheckOutcome::Skipped(_))))
+        .sum::<usize>();
+
+    write_event(
+        &mut writer,
+        XmlEvent::start_element("testsuites")
+            .attr("name", "rcodesign verify")
+            .attr("tests", &tests.to_string())
+            .attr("failures", &failures.to_string())
+            .attr("skipped", &skipped.to_string()),
+    )?;
+
+    for artifact in artifacts {
+        let tests = artifact.checks.len().to_string();
+        let failures = artifact
+            .count(|o| matches!(o, CheckOutcome::Failed(_)))
+            .to_string();
+        let skipped = artifact
+            .count(|o| matches!(o, CheckOutcome::Skipped(_)))
+            .to_string();
+
+        write_event(
+            &mut writer,
+            XmlEvent::start_element("testsuite")
+                .attr("name", &artifact.name)
+                .attr("tests", &tests)
+                .attr("failures", &failures)
+                .attr("skipped", &skipped),
+        )?;
+
+        for check in &artifact.checks {
+            let name = check.name();
+
+            write_event(
+                &mut writer,
+                XmlEvent::start_element("testcase")
+                    .attr("classname", &artifact.name)
+                    .attr("name", &name),
+            )?;
+
+            match &check.outcome {
+                CheckOutcome::Passed => {}
+                CheckOutcome::Failed(messages) => {
+                    write_event(
+                        &mut writer,
+                        XmlEvent::start_element("failure").attr("message", &messages[0]),
+                    )?;
+                    write_event(&mut writer, XmlEvent::characters(&messages.join("\n")))?;
+                    write_event(&mut writer, XmlEvent::end_element())?;
+                }
+                CheckOutcome::Skipped(reason) => {
+                    write_event(
+                        &mut writer,
+                        XmlEvent::start_element("skipped").attr("message", reason),
+                    )?;
+                    write_event(&mut writer, XmlEvent::end_element())?;
+                }
+            }
+
+            write_event(&mut writer, XmlEvent::end_element())?;
+        }
+
+        write_event(&mut writer, XmlEvent::end_element())?;
+    }
+
+    write_event(&mut writer, XmlEvent::end_element())?;
+
+    Ok(())
+}
+
+fn write_event<'a>(
+    writer: &mut EventWriter<impl Write>,
+    event: impl Into<XmlEvent<'a>>,
+) -> Result<(), AppleCodesignError> {
+    writer.write(event).map_err(AppleCodesignError::XmlWrite)
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{macho_signing::MachOSigner, signing_settings::SigningSettings},
+    };
+
+    fn outcomes(artifact: &ArtifactVerification) -> Vec<(&'static str, &CheckOutcome)> {
+        artifact
+            .checks
+            .iter()
+            .map(|check| (check.check.name(), &check.outcome))
+            .collect()
+    }
+
+    fn skipped(reason: &str) -> CheckOutcome {
+        CheckOutcome::Skipped(reason.into())
+    }
+
+    #[test]
+    fn missing_path() {
+        let artifact = ArtifactVerification::from_path("/does/not/exist");
+
+        assert!(matches!(
+            artifact.checks[0].outcome,
+            CheckOutcome::Failed(_)
+        ));
+        assert!(artifact.checks[1..]
+            .iter()
+            .all(|check| check.outcome == skipped("artifact could not be read")));
+    }
+
+    #[test]
+    fn unsigned() {
+        let data = crate::test_support::SyntheticMachO::default().build();
+        let artifact = ArtifactVerification::from_data("unsigned", data);
+
+        let outcomes = outcomes(&artifact);
+        assert_eq!(outcomes[0], ("read", &CheckOutcome::Passed));
+        assert_eq!(outcomes[1], ("parse", &CheckOutcome::Passed));
+        assert!(matches!(
+            outcomes[2],
+            ("signature-data", CheckOutcome::Failed(_))
+        ));
+        assert!(outcomes[3..]
+            .iter()
+            .all(|(_, outcome)| **outcome == skipped("signature data could not be read")));
+    }
+
+    #[test]
+    fn adhoc() -> Result<(), AppleCodesignError> {
+        let data = crate::test_support::SyntheticMachO::default().build();
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(
+            crate::signing_settings::SettingsScope::Main,
+            "com.example.adhoc",
+        );
+
+        let mut signed = vec![];
+        MachOSigner::new(&data)?.write_signed_binary(&settings, &mut signed)?;
+
+        let artifact = ArtifactVerification::from_data("adhoc", signed);
+        assert_eq!(
+            outcomes(&artifact),
+            vec![
+                ("read", &CheckOutcome::Passed),
+                ("parse", &CheckOutcome::Passed),
+                ("signature-data", &CheckOutcome::Passed),
+                ("layout", &CheckOutcome::Passed),
+                (
+                    "cms-signature",
+                    &skipped("no cryptographic signature (ad-hoc signed)")
+                ),
+                ("code-directory", &CheckOutcome::Passed),
+                ("code-digests", &CheckOutcome::Passed),
+                ("slot-digests", &CheckOutcome::Passed),
+            ]
+        );
+
+        let mut xml = vec![];
+        write_junit_xml(&[artifact], &mut xml)?;
+        let xml = String::from_utf8(xml).unwrap();
+
+        assert!(xml.contains(
+            r#"<testsuites name="rcodesign verify" tests="8" failures="0" skipped="1">"#
+        ));
+        assert!(xml.contains(r#"<testsuite name="adhoc" tests="8" failures="0" skipped="1">"#));
+        assert!(xml.contains(r#"<testcase classname="adhoc" name="layout" />"#));
+        assert!(xml.contains(r#"<skipped message="no cryptographic signature (ad-hoc signed)" />"#));
+
+        Ok(())
+    }
+
+    #[test]
+    fn revocation() -> Result<(), AppleCodesignError> {
+        let data = crate::test_support::SyntheticMachO::default().build();
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(
+            crate::signing_settings::SettingsScope::Main,
+            "com.example.adhoc",
+        );
+
+        let mut signed = vec![];
+        MachOSigner::new(&data)?.write_signed_binary(&settings, &mut signed)?;
+
+        // Without certificates advertising OCSP responders, nothing is queried.
+        let checker = OcspChecker::with_client(reqwest::blocking::Client::new());
+        let mut artifact = ArtifactVerification::from_data("adhoc", &signed);
+        artifact.check_revocation(&signed, &checker);
+
+        assert_eq!(
+            outcomes(&artifact).last(),
+            Some(&("revocation", &CheckOutcome::Passed))
+        );
+        assert!(artifact.problems.iter().all(|problem| matches!(
+            problem.problem,
+            VerificationProblemType::NoCryptographicSignature
+        )));
+
+        Ok(())
+    }
+}
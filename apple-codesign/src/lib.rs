@@ -74,6 +74,17 @@
 //!   value in doing this. This crate could be used to build such functionality
 //!   elsewhere, however.
 //!
+//! # Crate Features
+//!
+//! The `full` feature (enabled by default) provides everything beyond parsing:
+//! signing, verification, bundle and installer handling, remote signing, and the
+//! `rcodesign` executable. Building with `default-features = false` leaves only
+//! the parsing core: [MachOBinary], [EmbeddedSignature], [CodeDirectoryBlob],
+//! code requirements, entitlements, and the certificate helpers. This core does
+//! no filesystem or network I/O and builds for `wasm32-unknown-unknown`.
+//!
+//! The `notarize` and `smartcard` features imply `full`.
+//!
 //! # End-User Documentation
 //!
 //! The end-user documentation is maintained as a Sphinx docs tree in the `docs`
@@ -111,54 +122,144 @@
 
 mod apple_certificates;
 pub use apple_certificates::*;
+#[cfg(feature = "full")]
 mod bundle_signing;
+#[cfg(feature = "full")]
 pub use bundle_signing::*;
 mod certificate;
 pub use certificate::*;
+#[cfg(feature = "full")]
 pub mod cli;
+#[cfg(feature = "full")]
+pub mod cloud_kms;
 mod code_directory;
 pub use code_directory::*;
 pub mod code_requirement;
 pub use code_requirement::*;
+#[cfg(feature = "full")]
 mod code_resources;
+#[cfg(feature = "full")]
 pub use code_resources::*;
+#[cfg(feature = "full")]
+mod codesign_display;
+#[cfg(feature = "full")]
+pub use codesign_display::*;
+#[cfg(feature = "full")]
 pub mod cryptography;
+pub mod der_plist;
+#[cfg(feature = "full")]
 pub mod dmg;
 pub mod embedded_signature;
 pub use embedded_signature::*;
+#[cfg(feature = "full")]
 pub mod embedded_signature_builder;
+#[cfg(feature = "full")]
 pub use embedded_signature_builder::*;
 pub mod entitlements;
 mod error;
 pub use error::*;
+#[cfg(feature = "full")]
+mod format_support;
+#[cfg(feature = "full")]
+pub use format_support::*;
+#[cfg(feature = "full")]
+mod identifier_collisions;
+#[cfg(feature = "full")]
+pub use identifier_collisions::*;
+#[cfg(feature = "full")]
+mod info_plist;
+#[cfg(feature = "full")]
+pub use info_plist::*;
+#[cfg(feature = "full")]
+mod junit;
+#[cfg(feature = "full")]
+pub use junit::*;
 mod macho;
 pub use macho::*;
-#[cfg(target_os = "macos")]
+#[cfg(all(feature = "full", target_os = "macos"))]
 #[allow(non_upper_case_globals)]
 mod macos;
-#[cfg(target_os = "macos")]
+#[cfg(all(feature = "full", target_os = "macos"))]
 pub use macos::*;
+#[cfg(feature = "full")]
 mod macho_signing;
+#[cfg(feature = "full")]
 pub use macho_signing::*;
 mod macho_universal;
-pub use macho_universal::UniversalBinaryBuilder;
+pub use macho_universal::{
+    UniversalBinaryBuilder, UniversalMachO, UniversalMachOError, UniversalSlice,
+};
+#[cfg(feature = "full")]
+mod mapped_file;
+#[cfg(feature = "full")]
+mod metrics;
+#[cfg(feature = "full")]
+pub use metrics::*;
 #[cfg(feature = "notarize")]
 pub mod notarization;
 #[cfg(feature = "notarize")]
 pub use notarization::*;
+#[cfg(feature = "full")]
+pub mod ocsp;
+#[cfg(feature = "full")]
 mod policy;
+#[cfg(feature = "full")]
 pub use policy::*;
+#[cfg(feature = "full")]
+mod provisioning_profile;
+#[cfg(feature = "full")]
+pub use provisioning_profile::*;
+#[cfg(feature = "full")]
 mod reader;
+#[cfg(feature = "full")]
 pub use reader::*;
+#[cfg(feature = "full")]
+mod release;
+#[cfg(feature = "full")]
+pub use release::*;
+#[cfg(feature = "full")]
 pub mod remote_signing;
+#[cfg(feature = "full")]
+mod retry;
+#[cfg(feature = "full")]
+pub use retry::*;
+#[cfg(all(test, feature = "full"))]
+mod security_oracle;
+#[cfg(feature = "full")]
+mod settings_lint;
+#[cfg(feature = "full")]
+pub use settings_lint::*;
+#[cfg(feature = "full")]
+mod signature_recommendations;
+#[cfg(feature = "full")]
+pub use signature_recommendations::*;
+#[cfg(feature = "full")]
 mod signing_settings;
+#[cfg(feature = "full")]
 pub use signing_settings::*;
+#[cfg(feature = "full")]
 mod signing;
+#[cfg(feature = "full")]
 pub use signing::*;
+#[cfg(feature = "full")]
+mod signing_identity;
+#[cfg(feature = "full")]
+pub use signing_identity::*;
 pub mod specification;
+#[cfg(feature = "full")]
 pub mod stapling;
+#[cfg(any(test, feature = "test-support"))]
+#[doc(hidden)]
+pub mod test_support;
+#[cfg(feature = "full")]
 pub mod ticket_lookup;
+#[cfg(feature = "full")]
 mod verify;
+#[cfg(feature = "full")]
 pub use verify::*;
+#[cfg(feature = "full")]
+mod watch;
+#[cfg(feature = "full")]
+pub use watch::*;
 #[cfg(feature = "yubikey")]
 pub mod yubikey;
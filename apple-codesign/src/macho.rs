@@ -14,14 +14,13 @@ data.
 
 use {
     crate::{
-        embedded_signature::{DigestType, EmbeddedSignature},
+        code_directory::Scatter,
+        embedded_signature::{CodeSigningSlot, DigestType, EmbeddedSignature},
         error::AppleCodesignError,
-        signing_settings::{SettingsScope, SigningSettings},
     },
-    cryptographic_message_syntax::time_stamp_message_http,
     goblin::mach::{
         constants::{SEG_LINKEDIT, SEG_TEXT},
-        header::MH_EXECUTE,
+        header::{MH_BUNDLE, MH_DYLIB, MH_DYLIB_STUB, MH_EXECUTE, MH_KEXT_BUNDLE},
         load_command::{
             CommandVariant, LinkeditDataCommand, LC_BUILD_VERSION, SIZEOF_LINKEDIT_DATA_COMMAND,
         },
@@ -29,11 +28,209 @@ use {
         segment::Segment,
         Mach, MachO, SingleArch,
     },
-    rayon::prelude::*,
     scroll::Pread,
-    x509_certificate::DigestAlgorithm,
+    thiserror::Error,
 };
 
+#[cfg(feature = "parallel-digests")]
+use rayon::prelude::*;
+
+#[cfg(feature = "full")]
+use crate::signing_settings::{SettingsScope, SigningSettings};
+
+/// Number of special slot digests reserved in code directory size estimates.
+#[cfg(feature = "full")]
+const SPECIAL_SLOTS_ESTIMATE: usize = 7;
+
+/// Bytes reserved for a time-stamp token in signature size estimates.
+///
+/// Apple's time-stamp server issues tokens of about 5k.
+#[cfg(feature = "full")]
+const TIME_STAMP_TOKEN_ESTIMATE: usize = 8192;
+
+/// Estimated size of the components of an embedded signature.
+///
+/// Obtained from [MachOBinary::estimate_embedded_signature()].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SignatureSizeEstimate {
+    /// Size of all code directories, including their code digests.
+    pub code_directories: usize,
+    /// Size of entitlements blobs.
+    pub entitlements: usize,
+    /// Size of the CMS signature, including certificates.
+    pub cms: usize,
+    /// Size of the time-stamp token in the CMS signature.
+    pub time_stamp: usize,
+}
+
+impl SignatureSizeEstimate {
+    /// Total size to reserve for the signature.
+    ///
+    /// This is the sum of all components rounded up to a 1k boundary.
+    pub fn total(&self) -> usize {
+        let size = self.code_directories + self.entitlements + self.cms + self.time_stamp;
+
+        size + 1024 - size % 1024
+    }
+}
+
+/// The kind of a Mach-O file, as derived from its header's `filetype` field.
+///
+/// The file type influences signing defaults. Only main executables get the
+/// `MAIN_BINARY` executable segment flag and DER encoded entitlements, for example.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MachOFileType {
+    /// A main executable (`MH_EXECUTE`).
+    Executable,
+    /// A dynamic library (`MH_DYLIB`).
+    Dylib,
+    /// A loadable bundle / plugin (`MH_BUNDLE`).
+    Bundle,
+    /// A kernel extension bundle (`MH_KEXT_BUNDLE`).
+    KextBundle,
+    /// A shared library stub without section contents (`MH_DYLIB_STUB`).
+    DylibStub,
+    /// Any other file type.
+    Other(u32),
+}
+
+impl From<u32> for MachOFileType {
+    fn from(v: u32) -> Self {
+        match v {
+            MH_EXECUTE => Self::Executable,
+            MH_DYLIB => Self::Dylib,
+            MH_BUNDLE => Self::Bundle,
+            MH_KEXT_BUNDLE => Self::KextBundle,
+            MH_DYLIB_STUB => Self::DylibStub,
+            _ => Self::Other(v),
+        }
+    }
+}
+
+impl std::fmt::Display for MachOFileType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Executable => f.write_str("executable"),
+            Self::Dylib => f.write_str("dylib"),
+            Self::Bundle => f.write_str("bundle"),
+            Self::KextBundle => f.write_str("kext-bundle"),
+            Self::DylibStub => f.write_str("dylib-stub"),
+            Self::Other(v) => f.write_fmt(format_args!("unknown ({v})")),
+        }
+    }
+}
+
+impl std::str::FromStr for MachOFileType {
+    type Err = AppleCodesignError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "executable" => Ok(Self::Executable),
+            "dylib" => Ok(Self::Dylib),
+            "bundle" => Ok(Self::Bundle),
+            "kext-bundle" => Ok(Self::KextBundle),
+            "dylib-stub" => Ok(Self::DylibStub),
+            _ => Err(AppleCodesignError::UnknownMachOFileType(s.to_string())),
+        }
+    }
+}
+
+impl MachOFileType {
+    /// Whether signatures for this file type describe a main binary.
+    ///
+    /// This controls the `MAIN_BINARY` executable segment flag, executable segment
+    /// flags derived from entitlements, and emission of DER entitlements.
+    pub fn is_main_binary(&self) -> bool {
+        matches!(self, Self::Executable)
+    }
+
+    /// Whether this file type is expected to have a `__TEXT` segment.
+    ///
+    /// Stubs only carry symbol information and may lack any code.
+    pub fn requires_text_segment(&self) -> bool {
+        !matches!(self, Self::DylibStub | Self::Other(_))
+    }
+
+    /// Derive a default binary identifier from a file name.
+    ///
+    /// Executables use the file name verbatim. Libraries and bundles have a well-known
+    /// extension stripped (`libfoo.dylib` becomes `libfoo`).
+    pub fn default_identifier(&self, file_name: &str) -> String {
+        if self.is_main_binary() {
+            return file_name.to_string();
+        }
+
+        [".dylib", ".so", ".bundle"]
+            .into_iter()
+            .find_map(|suffix| file_name.strip_suffix(suffix))
+            .filter(|stem| !stem.is_empty())
+            .unwrap_or(file_name)
+            .to_string()
+    }
+}
+
+/// Inconsistencies in Mach-O data relevant to code signing.
+///
+/// Variants record where the offending data is. File offsets are relative to
+/// the start of the Mach-O binary. Signature offsets are relative to the start
+/// of the embedded signature super blob, which is located by the
+/// `LC_CODE_SIGNATURE` load command.
+#[derive(Debug, Error)]
+pub enum MachOParseError {
+    #[error("code signature data at file offsets 0x{start:x}-0x{end:x} is outside the __LINKEDIT segment at file offsets 0x{linkedit_start:x}-0x{linkedit_end:x}")]
+    SignatureOutsideLinkedit {
+        start: u64,
+        end: u64,
+        linkedit_start: u64,
+        linkedit_end: u64,
+    },
+
+    #[error("code limit 0x{code_limit:x} is beyond the end of the file (0x{file_size:x} bytes)")]
+    CodeLimitOutsideFile { code_limit: u64, file_size: u64 },
+
+    #[error("load command 0x{cmd:x} at file offset 0x{offset:x} is {size} bytes but its structure needs {required}")]
+    LoadCommandTooSmall {
+        cmd: u32,
+        offset: u64,
+        size: u64,
+        required: u64,
+    },
+
+    #[error("segment {segment} has no data at file offset 0x{offset:x}")]
+    SegmentDataOutOfBounds { segment: String, offset: u64 },
+
+    #[error("{slot} blob (index entry {index}) at signature offset 0x{offset:x} extends beyond the signature data")]
+    BlobOutOfBounds {
+        index: usize,
+        slot: CodeSigningSlot,
+        offset: u64,
+    },
+
+    #[error(
+        "index entry {index} for {slot} blob at signature offset 0x{offset:x} is out of order"
+    )]
+    BlobIndexOutOfOrder {
+        index: usize,
+        slot: CodeSigningSlot,
+        offset: u64,
+    },
+
+    #[error("{slot} blob (index entry {index}) at signature offset 0x{offset:x} overlaps data ending at signature offset 0x{previous_end:x}")]
+    BlobOverlap {
+        index: usize,
+        slot: CodeSigningSlot,
+        offset: u64,
+        previous_end: u64,
+    },
+
+    #[error("malformed {slot} blob at signature offset 0x{offset:x}: {error}")]
+    Blob {
+        slot: CodeSigningSlot,
+        offset: u64,
+        error: Box<AppleCodesignError>,
+    },
+}
+
 /// A Mach-O binary.
 pub struct MachOBinary<'a> {
     /// Index within a fat binary this Mach-O resides at.
@@ -101,12 +298,28 @@ impl<'a> MachOBinary<'a> {
             let linkedit_signature_start_offset = linkedit_data_command.dataoff as usize;
             let linkedit_signature_end_offset =
                 linkedit_signature_start_offset + linkedit_data_command.datasize as usize;
-            let signature_start_offset =
-                linkedit_data_command.dataoff as usize - linkedit.fileoff as usize;
-            let signature_end_offset =
-                signature_start_offset + linkedit_data_command.datasize as usize;
 
-            let signature_data = &linkedit.data[signature_start_offset..signature_end_offset];
+            // Offsets come from untrusted load commands. So bounds check them
+            // against the segment data.
+            let (signature_start_offset, signature_end_offset, signature_data) =
+                match linkedit_signature_start_offset
+                    .checked_sub(linkedit_segment_start_offset)
+                    .and_then(|start| {
+                        let end = start + linkedit_data_command.datasize as usize;
+
+                        Some((start, end, linkedit.data.get(start..end)?))
+                    }) {
+                    Some(v) => v,
+                    None => {
+                        return Err(MachOParseError::SignatureOutsideLinkedit {
+                            start: linkedit_signature_start_offset as _,
+                            end: linkedit_signature_end_offset as _,
+                            linkedit_start: linkedit_segment_start_offset as _,
+                            linkedit_end: linkedit_segment_end_offset as _,
+                        }
+                        .into());
+                    }
+                };
 
             Ok(Some(MachOSignatureData {
                 linkedit_segment_index,
@@ -155,6 +368,34 @@ impl<'a> MachOBinary<'a> {
         self.macho.header.filetype == MH_EXECUTE
     }
 
+    /// Resolve the [MachOFileType] of this binary.
+    pub fn file_type(&self) -> MachOFileType {
+        MachOFileType::from(self.macho.header.filetype)
+    }
+
+    /// The install name of a dynamic library, as defined by `LC_ID_DYLIB`.
+    pub fn dylib_install_name(&self) -> Option<&str> {
+        self.macho.name
+    }
+
+    /// Derive a default binary identifier for this binary.
+    ///
+    /// This is like [MachOFileType::default_identifier()] except dylibs having an
+    /// install name derive the identifier from it instead of `file_name`. e.g.
+    /// `@rpath/libfoo.1.dylib` yields `libfoo.1`, even if the file is named
+    /// `libfoo.1.2.3.dylib`.
+    pub fn default_identifier(&self, file_type: MachOFileType, file_name: &str) -> String {
+        let install_name = if file_type == MachOFileType::Dylib {
+            self.dylib_install_name()
+                .and_then(|name| name.rsplit('/').next())
+                .filter(|name| !name.is_empty())
+        } else {
+            None
+        };
+
+        file_type.default_identifier(install_name.unwrap_or(file_name))
+    }
+
     /// The start offset of the code signature data within the __LINKEDIT segment.
     pub fn code_signature_linkedit_start_offset(&self) -> Option<u32> {
         let segment = self
@@ -164,7 +405,9 @@ impl<'a> MachOBinary<'a> {
             .find(|segment| matches!(segment.name(), Ok(SEG_LINKEDIT)));
 
         if let (Some(segment), Some(command)) = (segment, self.code_signature_load_command()) {
-            Some((command.dataoff as u64 - segment.fileoff) as u32)
+            (command.dataoff as u64)
+                .checked_sub(segment.fileoff)
+                .map(|offset| offset as u32)
         } else {
             None
         }
@@ -175,7 +418,7 @@ impl<'a> MachOBinary<'a> {
         let start_offset = self.code_signature_linkedit_start_offset()?;
 
         self.code_signature_load_command()
-            .map(|command| start_offset + command.datasize)
+            .and_then(|command| start_offset.checked_add(command.datasize))
     }
 
     /// Obtain Mach-O segments by file offset order.
@@ -224,7 +467,7 @@ impl<'a> MachOBinary<'a> {
 
         if let Some(segment) = segment {
             if let Some(offset) = self.code_signature_linkedit_start_offset() {
-                Some(&segment.data[0..offset as usize])
+                segment.data.get(0..offset as usize)
             } else {
                 Some(segment.data)
             }
@@ -239,7 +482,13 @@ impl<'a> MachOBinary<'a> {
     pub fn digested_code_data(&self) -> Result<&[u8], AppleCodesignError> {
         let code_limit = self.code_limit_binary_offset()?;
 
-        Ok(&self.data[0..code_limit as _])
+        Ok(self
+            .data
+            .get(0..code_limit as _)
+            .ok_or(MachOParseError::CodeLimitOutsideFile {
+                code_limit,
+                file_size: self.data.len() as _,
+            })?)
     }
 
     /// Obtain the size in bytes of all code digests given a digest type and page size.
@@ -259,20 +508,82 @@ impl<'a> MachOBinary<'a> {
         digest: DigestType,
         page_size: usize,
     ) -> Result<Vec<Vec<u8>>, AppleCodesignError> {
-        let data = self.digested_code_data()?;
-
-        // Premature parallelism can be slower due to overhead of having to spin up threads.
-        // So only do parallel digests if we have enough data to warrant it.
-        if data.len() > 64 * 1024 * 1024 {
-            data.par_chunks(page_size)
-                .map(|c| digest.digest_data(c))
-                .collect::<Result<Vec<_>, AppleCodesignError>>()
-        } else {
-            self.digested_code_data()?
-                .chunks(page_size)
-                .map(|chunk| digest.digest_data(chunk))
-                .collect::<Result<Vec<_>, AppleCodesignError>>()
+        code_page_digests(self.digested_code_data()?, digest, page_size)
+    }
+
+    /// Compute digests over code pages described by a scatter vector.
+    ///
+    /// Use this to populate the code digests of a code directory whose
+    /// [crate::code_directory::CodeDirectoryBlob::scatter] is set.
+    pub fn scatter_code_digests(
+        &self,
+        digest: DigestType,
+        page_size: usize,
+        scatter: &[Scatter],
+    ) -> Result<Vec<Vec<u8>>, AppleCodesignError> {
+        scatter_page_digests(self.digested_code_data()?, digest, page_size, scatter)
+    }
+
+    /// Describe the segments and sections overlapping a range of file offsets.
+    ///
+    /// Returns names like `__TEXT,__text` for sections and `__TEXT` for segment
+    /// data outside any section (such as the Mach-O header and load commands).
+    /// Data not belonging to any segment is described as `<outside segments>`.
+    pub fn file_regions(&self, start: u64, end: u64) -> Vec<String> {
+        let mut regions = vec![];
+        let mut covered = 0;
+
+        for segment in self.segments_by_file_offset() {
+            let segment_start = segment.fileoff;
+            let segment_end = segment.fileoff + segment.filesize;
+
+            if segment_end <= start || segment_start >= end {
+                continue;
+            }
+
+            let segment_name = segment.name().unwrap_or("<unknown>");
+            let overlap = segment_end.min(end) - segment_start.max(start);
+            covered += overlap;
+
+            let sections = segment
+                .sections()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(section, _)| section)
+                .filter(|section| {
+                    // Zero fill sections have no file data.
+                    section.offset != 0
+                        && (section.offset as u64) < end
+                        && section.offset as u64 + section.size > start
+                })
+                .collect::<Vec<_>>();
+
+            let sections_overlap = sections
+                .iter()
+                .map(|section| {
+                    (section.offset as u64 + section.size).min(end)
+                        - (section.offset as u64).max(start)
+                })
+                .sum::<u64>();
+
+            if sections_overlap < overlap {
+                regions.push(segment_name.to_string());
+            }
+
+            for section in sections {
+                regions.push(format!(
+                    "{},{}",
+                    segment_name,
+                    section.name().unwrap_or("<unknown>")
+                ));
+            }
         }
+
+        if covered < end - start {
+            regions.push("<outside segments>".to_string());
+        }
+
+        regions
     }
 
     /// Resolve the load command for the code signature.
@@ -286,6 +597,50 @@ impl<'a> MachOBinary<'a> {
         })
     }
 
+    /// Resolve the load command for dyld chained fixups.
+    pub fn chained_fixups_load_command(&self) -> Option<LinkeditDataCommand> {
+        self.macho.load_commands.iter().find_map(|lc| {
+            if let CommandVariant::DyldChainedFixups(command) = lc.command {
+                Some(command)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Validate the placement of dyld chained fixups data.
+    ///
+    /// Chained fixups data (`LC_DYLD_CHAINED_FIXUPS`) lives in `__LINKEDIT`, often
+    /// immediately before the code signature. Signing rewrites everything in
+    /// `__LINKEDIT` from the signature start onwards. So the fixups data must be
+    /// entirely within `__LINKEDIT` and end at or before the signature start,
+    /// otherwise signing would truncate it or digest it as signature data.
+    pub fn check_chained_fixups_layout(&self) -> Result<(), AppleCodesignError> {
+        let Some(command) = self.chained_fixups_load_command() else {
+            return Ok(());
+        };
+
+        let segment = self
+            .macho
+            .segments
+            .iter()
+            .find(|segment| matches!(segment.name(), Ok(SEG_LINKEDIT)))
+            .ok_or(AppleCodesignError::MissingLinkedit)?;
+
+        let start = command.dataoff as u64;
+        let end = start + command.datasize as u64;
+
+        if start < segment.fileoff || end > segment.fileoff + segment.data.len() as u64 {
+            return Err(AppleCodesignError::ChainedFixupsOutsideLinkedit);
+        }
+
+        if end > self.code_limit_binary_offset()? {
+            return Err(AppleCodesignError::ChainedFixupsOverlapSignature);
+        }
+
+        Ok(())
+    }
+
     /// Attempt to locate embedded Info.plist data.
     pub fn embedded_info_plist(&self) -> Result<Option<Vec<u8>>, AppleCodesignError> {
         // Mach-O binaries can have the Info.plist data in an `__info_plist` section
@@ -327,6 +682,8 @@ impl<'a> MachOBinary<'a> {
             return Err(AppleCodesignError::LinkeditNotLast);
         }
 
+        self.check_chained_fixups_layout()?;
+
         // Rules:
         //
         // 1. If there is an existing signature, there must be no data in
@@ -350,7 +707,9 @@ impl<'a> MachOBinary<'a> {
                 .last()
                 .ok_or_else(|| AppleCodesignError::InvalidBinary("no load commands".into()))?;
 
-            let first_section = self
+            // Zero fill sections have an offset of 0 since they have no file
+            // data. Binaries without sections have data at the next segment.
+            let first_data_offset = self
                 .macho
                 .segments
                 .iter()
@@ -358,13 +717,21 @@ impl<'a> MachOBinary<'a> {
                 .collect::<Result<Vec<_>, _>>()?
                 .into_iter()
                 .flatten()
-                .next()
+                .map(|(section, _)| section.offset as usize)
+                .filter(|offset| *offset != 0)
+                .min()
+                .or_else(|| {
+                    self.segments_by_file_offset()
+                        .into_iter()
+                        .map(|segment| segment.fileoff as usize)
+                        .find(|offset| *offset != 0)
+                })
                 .ok_or_else(|| AppleCodesignError::InvalidBinary("no sections".into()))?;
 
             let load_commands_end_offset =
                 last_load_command.offset + last_load_command.command.cmdsize();
 
-            if first_section.0.offset as usize - load_commands_end_offset
+            if first_data_offset.saturating_sub(load_commands_end_offset)
                 >= SIZEOF_LINKEDIT_DATA_COMMAND
             {
                 Ok(())
@@ -375,71 +742,74 @@ impl<'a> MachOBinary<'a> {
     }
 
     /// Estimate the size in bytes of an embedded code signature.
+    ///
+    /// This is [Self::estimate_embedded_signature()] rounded up to a 1k boundary.
+    #[cfg(feature = "full")]
     pub fn estimate_embedded_signature_size(
         &self,
         settings: &SigningSettings,
     ) -> Result<usize, AppleCodesignError> {
-        let code_directory_count = 1 + settings
-            .extra_digests(SettingsScope::Main)
-            .map(|x| x.len())
-            .unwrap_or_default();
+        Ok(self.estimate_embedded_signature(settings)?.total())
+    }
 
-        // Assume the common data structures are 1024 bytes.
-        let mut size = 1024 * code_directory_count;
+    /// Estimate the size of the components of an embedded code signature.
+    ///
+    /// The estimate only depends on the binary and the settings. No network
+    /// requests are performed. Estimates are upper bounds for all but
+    /// pathological inputs.
+    #[cfg(feature = "full")]
+    pub fn estimate_embedded_signature(
+        &self,
+        settings: &SigningSettings,
+    ) -> Result<SignatureSizeEstimate, AppleCodesignError> {
+        let mut estimate = SignatureSizeEstimate::default();
 
-        // Reserve room for the code digests, which are proportional to binary size.
-        // We could avoid doing the actual digesting work here. But until people
-        // complain, don't worry about it.
-        size += self.code_digests_size(*settings.digest_type(), 4096)?;
+        let digests = std::iter::once(settings.digest_type()).chain(
+            settings
+                .extra_digests(SettingsScope::Main)
+                .into_iter()
+                .flatten(),
+        );
 
-        if let Some(digests) = settings.extra_digests(SettingsScope::Main) {
-            for digest in digests {
-                size += self.code_digests_size(*digest, 4096)?;
-            }
-        }
+        let identifier_len = settings
+            .binary_identifier(SettingsScope::Main)
+            .map(|x| x.len())
+            .unwrap_or(1024);
+        let team_id_len = settings.team_id().map(|x| x.len()).unwrap_or_default();
 
-        // Assume the CMS data will take a fixed size.
-        if settings.signing_key().is_some() {
-            size += 4096;
-        }
+        for digest in digests {
+            let digest_len = digest.digest_data(b"")?.len();
+
+            // Assume the common data structures are 1024 bytes.
+            estimate.code_directories += 1024 + identifier_len + team_id_len;
 
-        // Long certificate chains could blow up the size. Account for those.
-        for cert in settings.certificate_chain() {
-            size += cert.constructed_data().len();
+            // Special slots plus the code digests, which are proportional to
+            // binary size. Inserting a signature may align the code limit into
+            // one more page.
+            estimate.code_directories += (SPECIAL_SLOTS_ESTIMATE + 1) * digest_len
+                + self.code_digests_size(*digest, 4096)?;
         }
 
-        // Add entitlements xml if needed.
+        // Entitlements are present as both XML and DER. DER is more compact.
         if let Some(entitlements) = settings.entitlements_xml(SettingsScope::Main)? {
-            size += entitlements.as_bytes().len()
+            estimate.entitlements += 2 * entitlements.as_bytes().len();
         }
 
-        // Obtain an actual timestamp token of placeholder data and use its length.
-        // This may be excessive to actually query the time-stamp server and issue
-        // a token. But these operations should be "cheap."
-        if let Some(timestamp_url) = settings.time_stamp_url() {
-            let message = b"deadbeef".repeat(32);
+        if let Some((_, cert)) = settings.signing_key() {
+            // Assume the CMS structures take a fixed size.
+            estimate.cms += 4096 + cert.constructed_data().len();
 
-            if let Ok(response) =
-                time_stamp_message_http(timestamp_url.clone(), &message, DigestAlgorithm::Sha256)
-            {
-                if response.is_success() {
-                    if let Some(l) = response.token_content_size() {
-                        size += l;
-                    } else {
-                        size += 8192;
-                    }
-                } else {
-                    size += 8192;
-                }
-            } else {
-                size += 8192;
+            // Long certificate chains could blow up the size. Account for those.
+            for cert in settings.certificate_chain() {
+                estimate.cms += cert.constructed_data().len();
             }
-        }
 
-        // Align on 1k boundaries just because.
-        size += 1024 - size % 1024;
+            if settings.time_stamp_url().is_some() {
+                estimate.time_stamp += TIME_STAMP_TOKEN_ESTIMATE;
+            }
+        }
 
-        Ok(size)
+        Ok(estimate)
     }
 
     /// Attempt to resolve the mach-o targeting settings.
@@ -706,10 +1076,111 @@ impl<'a> IntoIterator for MachFile<'a> {
     }
 }
 
+/// Code data larger than this many bytes is digested in parallel.
+///
+/// Premature parallelism can be slower due to overhead of having to spin up threads.
+/// So only do parallel digests if we have enough data to warrant it.
+const PARALLEL_CODE_DIGEST_THRESHOLD: usize = 64 * 1024 * 1024;
+
+/// Compute digests of `data` in pages of `page_size` bytes.
+///
+/// A page size of 0 digests all data as a single page, as code directories do.
+pub(crate) fn code_page_digests(
+    data: &[u8],
+    digest: DigestType,
+    page_size: usize,
+) -> Result<Vec<Vec<u8>>, AppleCodesignError> {
+    let page_size = if page_size == 0 {
+        data.len().max(1)
+    } else {
+        page_size
+    };
+
+    digest_pages(
+        data,
+        digest,
+        page_size,
+        data.len() > PARALLEL_CODE_DIGEST_THRESHOLD,
+    )
+}
+
+/// Compute digests of the pages of `data` described by a scatter vector.
+///
+/// Digests are returned in code slot order. Scatter entries must describe
+/// consecutive slots and pages must begin within `data`.
+pub(crate) fn scatter_page_digests(
+    data: &[u8],
+    digest: DigestType,
+    page_size: usize,
+    scatter: &[Scatter],
+) -> Result<Vec<Vec<u8>>, AppleCodesignError> {
+    if page_size == 0 {
+        return Err(AppleCodesignError::CodeDirectoryMalformedScatter);
+    }
+
+    let mut digests = vec![];
+
+    for entry in scatter {
+        if entry.base as usize != digests.len() {
+            return Err(AppleCodesignError::CodeDirectoryMalformedScatter);
+        }
+
+        let start = usize::try_from(entry.target_offset)
+            .map_err(|_| AppleCodesignError::CodeDirectoryMalformedScatter)?;
+        let end = (entry.count as usize)
+            .checked_mul(page_size)
+            .and_then(|len| start.checked_add(len))
+            .ok_or(AppleCodesignError::CodeDirectoryMalformedScatter)?
+            .min(data.len());
+
+        let pages = data
+            .get(start..end)
+            .ok_or(AppleCodesignError::CodeDirectoryMalformedScatter)?
+            .chunks(page_size)
+            .map(|chunk| digest.digest_data(chunk))
+            .collect::<Result<Vec<_>, AppleCodesignError>>()?;
+
+        if pages.len() != entry.count as usize {
+            return Err(AppleCodesignError::CodeDirectoryMalformedScatter);
+        }
+
+        digests.extend(pages);
+    }
+
+    Ok(digests)
+}
+
+/// Digest `data` in chunks of `page_size`, optionally in parallel.
+///
+/// Digests are always computed serially without the `parallel-digests` feature.
+pub(crate) fn digest_pages(
+    data: &[u8],
+    digest: DigestType,
+    page_size: usize,
+    parallel: bool,
+) -> Result<Vec<Vec<u8>>, AppleCodesignError> {
+    #[cfg(feature = "parallel-digests")]
+    if parallel {
+        return data
+            .par_chunks(page_size)
+            .map(|chunk| digest.digest_data(chunk))
+            .collect::<Result<Vec<_>, AppleCodesignError>>();
+    }
+
+    #[cfg(not(feature = "parallel-digests"))]
+    let _ = parallel;
+
+    data.chunks(page_size)
+        .map(|chunk| digest.digest_data(chunk))
+        .collect::<Result<Vec<_>, AppleCodesignError>>()
+}
+
 #[cfg(test)]
 mod tests {
+    use {super::*, crate::test_support::SyntheticMachO};
+
+    #[cfg(feature = "full")]
     use {
-        super::*,
         crate::embedded_signature::Blob,
         std::{
             io::Read,
@@ -717,12 +1188,15 @@ mod tests {
         },
     };
 
+    #[cfg(feature = "full")]
     const MACHO_UNIVERSAL_MAGIC: [u8; 4] = [0xca, 0xfe, 0xba, 0xbe];
+    #[cfg(feature = "full")]
     const MACHO_64BIT_MAGIC: [u8; 4] = [0xfe, 0xed, 0xfa, 0xcf];
 
     /// Find files in a directory appearing to be Mach-O by sniffing magic.
     ///
     /// Ignores file I/O errors.
+    #[cfg(feature = "full")]
     fn find_likely_macho_files(path: &Path) -> Vec<PathBuf> {
         let mut res = Vec::new();
 
@@ -745,6 +1219,7 @@ mod tests {
         res
     }
 
+    #[cfg(feature = "full")]
     fn find_apple_embedded_signature<'a>(macho: &'a MachOBinary) -> Option<EmbeddedSignature<'a>> {
         if let Ok(Some(signature)) = macho.code_signature() {
             Some(signature)
@@ -753,6 +1228,7 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "full")]
     fn validate_macho(path: &Path, macho: &MachOBinary) {
         // We found signature data in the binary.
         if let Some(signature) = find_apple_embedded_signature(macho) {
@@ -828,6 +1304,7 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "full")]
     fn validate_macho_in_dir(dir: &Path) {
         for path in find_likely_macho_files(dir).into_iter() {
             if let Ok(file_data) = std::fs::read(&path) {
@@ -840,6 +1317,7 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "full")]
     #[test]
     fn parse_applications_macho_signatures() {
         // This test scans common directories containing Mach-O files on macOS and
@@ -866,6 +1344,181 @@ mod tests {
         }
     }
 
+    #[test]
+    fn chained_fixups_layout() {
+        // Fixups immediately followed by the signature.
+        let data = SyntheticMachO::default()
+            .linkedit_size(0x200)
+            .chained_fixups((0x4000, 0x100))
+            .code_signature((0x4100, 0x100))
+            .build();
+        let macho = MachOBinary::parse(&data).unwrap();
+        assert!(macho.chained_fixups_load_command().is_some());
+        macho.check_signing_capability().unwrap();
+
+        // No signature yet. New signature data is appended after the fixups.
+        let data = SyntheticMachO::default()
+            .chained_fixups((0x4000, 0x100))
+            .build();
+        MachOBinary::parse(&data)
+            .unwrap()
+            .check_signing_capability()
+            .unwrap();
+
+        // Fixups running into the signature.
+        let data = SyntheticMachO::default()
+            .linkedit_size(0x200)
+            .chained_fixups((0x4000, 0x180))
+            .code_signature((0x4100, 0x100))
+            .build();
+        assert!(matches!(
+            MachOBinary::parse(&data)
+                .unwrap()
+                .check_signing_capability(),
+            Err(AppleCodesignError::ChainedFixupsOverlapSignature)
+        ));
+
+        // Fixups outside __LINKEDIT.
+        let data = SyntheticMachO::default()
+            .linkedit_size(0x200)
+            .chained_fixups((0x3f00, 0x100))
+            .code_signature((0x4100, 0x100))
+            .build();
+        assert!(matches!(
+            MachOBinary::parse(&data)
+                .unwrap()
+                .check_signing_capability(),
+            Err(AppleCodesignError::ChainedFixupsOutsideLinkedit)
+        ));
+    }
+
+    #[cfg(feature = "full")]
+    #[test]
+    fn estimate_signature_size() -> Result<(), AppleCodesignError> {
+        let data = SyntheticMachO::default()
+            .chained_fixups((0x4000, 0x100))
+            .build();
+        let macho = MachOBinary::parse(&data)?;
+
+        let (cert, key, _) = crate::create_self_signed_code_signing_certificate(
+            x509_certificate::KeyAlgorithm::Ecdsa(x509_certificate::EcdsaCurve::Secp256r1),
+            crate::CertificateProfile::DeveloperIdApplication,
+            "team1",
+            "Joe Developer",
+            "US",
+            chrono::Duration::hours(1),
+        )?;
+
+        let mut settings = crate::SigningSettings::default();
+        settings.set_binary_identifier(crate::SettingsScope::Main, "com.example.estimate");
+        let adhoc = macho.estimate_embedded_signature(&settings)?;
+        assert_eq!(adhoc.cms, 0);
+        assert_eq!(adhoc.time_stamp, 0);
+        assert_eq!(adhoc.total() % 1024, 0);
+
+        settings.set_signing_key(&key, cert);
+        settings.set_entitlements_xml(
+            crate::SettingsScope::Main,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<plist version=\"1.0\"><dict><key>com.apple.security.app-sandbox</key><true/></dict></plist>",
+        )?;
+        settings.add_extra_digest(crate::SettingsScope::Main, DigestType::Sha384);
+
+        let estimate = macho.estimate_embedded_signature(&settings)?;
+        // Estimation is deterministic.
+        assert_eq!(macho.estimate_embedded_signature(&settings)?, estimate);
+        assert!(estimate.code_directories > adhoc.code_directories);
+        assert!(estimate.entitlements > 0);
+        assert!(estimate.cms > 4096);
+        assert_eq!(
+            macho.estimate_embedded_signature_size(&settings)?,
+            estimate.total()
+        );
+
+        let mut signed = vec![];
+        crate::MachOSigner::new(&data)?.write_signed_binary(&settings, &mut signed)?;
+        let signed = MachOBinary::parse(&signed)?;
+        let signature = signed.code_signature()?.unwrap();
+        assert!(signature.length as usize <= estimate.total());
+        assert_eq!(
+            signed.code_signature_load_command().unwrap().datasize as usize,
+            estimate.total()
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "full")]
+    #[test]
+    fn insert_signature_load_command() -> Result<(), AppleCodesignError> {
+        // Offset of the __text section's file offset field.
+        const SECTION_OFFSET: usize = 32 + 72 + 48;
+
+        let data = SyntheticMachO::default()
+            .linkedit_size(0x104)
+            .chained_fixups((0x4000, 0x100))
+            .build();
+        let macho = MachOBinary::parse(&data)?;
+        let load_commands_end = 32 + macho.macho.header.sizeofcmds;
+
+        let mut settings = crate::SigningSettings::default();
+        settings.set_binary_identifier(crate::SettingsScope::Main, "com.example.unsigned");
+
+        let mut signed = vec![];
+        crate::MachOSigner::new(&data)?.write_signed_binary(&settings, &mut signed)?;
+
+        let macho = MachOBinary::parse(&signed)?;
+        assert_eq!(macho.macho.header.ncmds, 4);
+        // The signature is placed on a 16 byte boundary after __LINKEDIT data.
+        let command = macho.code_signature_load_command().unwrap();
+        assert_eq!(command.dataoff, 0x4110);
+        // Ad-hoc signatures have no CMS signature but are otherwise valid.
+        assert!(crate::verify_macho_data(&signed)
+            .iter()
+            .all(|problem| matches!(
+                problem.problem,
+                crate::VerificationProblemType::NoCryptographicSignature
+            )));
+
+        // Zero fill sections have no file data to collide with.
+        let mut data = data;
+        data[SECTION_OFFSET..SECTION_OFFSET + 4].copy_from_slice(&0u32.to_le_bytes());
+        MachOBinary::parse(&data)?.check_signing_capability()?;
+
+        // No room between the load commands and section data.
+        data[SECTION_OFFSET..SECTION_OFFSET + 4].copy_from_slice(&load_commands_end.to_le_bytes());
+        assert!(matches!(
+            MachOBinary::parse(&data)?.check_signing_capability(),
+            Err(AppleCodesignError::LoadCommandNoRoom)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn file_regions() {
+        let data = SyntheticMachO::default()
+            .linkedit_size(0x200)
+            .chained_fixups((0x4000, 0x100))
+            .code_signature((0x4100, 0x100))
+            .build();
+        let macho = MachOBinary::parse(&data).unwrap();
+
+        assert_eq!(macho.file_regions(0, 0x1000), vec!["__TEXT"]);
+        assert_eq!(
+            macho.file_regions(0x1000, 0x2000),
+            vec!["__TEXT", "__TEXT,__text"]
+        );
+        assert_eq!(macho.file_regions(0x1000, 0x1010), vec!["__TEXT,__text"]);
+        assert_eq!(
+            macho.file_regions(0x3000, 0x4100),
+            vec!["__TEXT", "__LINKEDIT"]
+        );
+        assert_eq!(
+            macho.file_regions(0x4100, 0x5100),
+            vec!["__LINKEDIT", "<outside segments>"]
+        );
+    }
+
     #[test]
     fn version_nibbles() {
         assert_eq!(
@@ -881,4 +1534,214 @@ mod tests {
             12 << 16 | 1 << 8 | 2
         );
     }
+
+    #[cfg(feature = "full")]
+    #[test]
+    fn file_type_defaults() -> Result<(), AppleCodesignError> {
+        use crate::{
+            code_directory::ExecutableSegmentFlags, embedded_signature::CodeSigningSlot,
+            macho_signing::MachOSigner,
+        };
+
+        let sign =
+            |settings: &SigningSettings, filetype: u32| -> Result<Vec<u8>, AppleCodesignError> {
+                let mut data = SyntheticMachO::default()
+                    .chained_fixups((0x4000, 0x100))
+                    .build();
+                data[12..16].copy_from_slice(&filetype.to_le_bytes());
+
+                let mut signed = vec![];
+                MachOSigner::new(&data)?.write_signed_binary(settings, &mut signed)?;
+
+                Ok(signed)
+            };
+
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(SettingsScope::Main, "binary");
+        settings.set_entitlements_xml(
+            SettingsScope::Main,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <plist version=\"1.0\"><dict><key>com.apple.security.get-task-allow</key><true/></dict></plist>",
+        )?;
+
+        for (filetype, expected) in [
+            (MH_EXECUTE, MachOFileType::Executable),
+            (MH_DYLIB, MachOFileType::Dylib),
+            (MH_BUNDLE, MachOFileType::Bundle),
+        ] {
+            let signed = sign(&settings, filetype)?;
+            let macho = MachOBinary::parse(&signed)?;
+            assert_eq!(macho.file_type(), expected);
+
+            let signature = macho.code_signature()?.unwrap();
+            let cd = signature.code_directory()?.unwrap();
+
+            if expected.is_main_binary() {
+                assert!(cd
+                    .exec_seg_flags
+                    .unwrap()
+                    .contains(ExecutableSegmentFlags::MAIN_BINARY));
+                assert!(signature
+                    .find_slot(CodeSigningSlot::EntitlementsDer)
+                    .is_some());
+            } else {
+                assert_eq!(
+                    cd.exec_seg_flags
+                        .unwrap_or_else(ExecutableSegmentFlags::empty),
+                    ExecutableSegmentFlags::empty()
+                );
+                assert!(signature
+                    .find_slot(CodeSigningSlot::EntitlementsDer)
+                    .is_none());
+            }
+
+            // Without an Info.plist, nothing is bound in the Info slot.
+            assert!(cd
+                .slot_digest(CodeSigningSlot::Info)
+                .map_or(true, |digest| digest.is_null()));
+        }
+
+        // An explicit file type overrides the one in the header.
+        settings.set_macho_file_type(SettingsScope::Main, MachOFileType::Executable);
+        let signed = sign(&settings, MH_DYLIB)?;
+        let macho = MachOBinary::parse(&signed)?;
+        let signature = macho.code_signature()?.unwrap();
+        assert!(signature
+            .code_directory()?
+            .unwrap()
+            .exec_seg_flags
+            .is_some());
+        assert!(signature
+            .find_slot(CodeSigningSlot::EntitlementsDer)
+            .is_some());
+
+        assert_eq!(MachOFileType::from(MH_DYLIB_STUB), MachOFileType::DylibStub);
+        assert_eq!(MachOFileType::from(1), MachOFileType::Other(1));
+        assert_eq!("dylib".parse::<MachOFileType>()?, MachOFileType::Dylib);
+        assert!("object".parse::<MachOFileType>().is_err());
+        assert_eq!(
+            MachOFileType::Executable.default_identifier("tool.dylib"),
+            "tool.dylib"
+        );
+        assert_eq!(
+            MachOFileType::Dylib.default_identifier("libfoo.dylib"),
+            "libfoo"
+        );
+        assert_eq!(
+            MachOFileType::Bundle.default_identifier("plugin.bundle"),
+            "plugin"
+        );
+        assert_eq!(MachOFileType::Bundle.default_identifier(".so"), ".so");
+
+        Ok(())
+    }
+
+    #[test]
+    fn dylib_install_name_identifier() -> Result<(), AppleCodesignError> {
+        let mut data = SyntheticMachO::default()
+            .chained_fixups((0x4000, 0x100))
+            .build();
+        data[12..16].copy_from_slice(&MH_DYLIB.to_le_bytes());
+
+        let macho = MachOBinary::parse(&data)?;
+        assert_eq!(macho.dylib_install_name(), None);
+        assert_eq!(
+            macho.default_identifier(MachOFileType::Dylib, "libfoo.1.2.dylib"),
+            "libfoo.1.2"
+        );
+
+        // Append an LC_ID_DYLIB load command after the existing ones.
+        let name = b"@rpath/libfoo.1.dylib\0\0\0";
+        let ncmds = u32::from_le_bytes(data[16..20].try_into().unwrap());
+        let sizeofcmds = u32::from_le_bytes(data[20..24].try_into().unwrap());
+        let offset = 32 + sizeofcmds as usize;
+
+        let mut command = vec![];
+        // cmd, cmdsize, name offset, timestamp, current version, compatibility version.
+        for v in [0xdu32, 24 + name.len() as u32, 24, 0, 0x10000, 0x10000] {
+            command.extend(v.to_le_bytes());
+        }
+        command.extend(name);
+
+        data[offset..offset + command.len()].copy_from_slice(&command);
+        data[16..20].copy_from_slice(&(ncmds + 1).to_le_bytes());
+        data[20..24].copy_from_slice(&(sizeofcmds + command.len() as u32).to_le_bytes());
+
+        let macho = MachOBinary::parse(&data)?;
+        assert_eq!(macho.dylib_install_name(), Some("@rpath/libfoo.1.dylib"));
+        assert_eq!(
+            macho.default_identifier(MachOFileType::Dylib, "libfoo.1.2.dylib"),
+            "libfoo.1"
+        );
+        // Only dylibs use the install name.
+        assert_eq!(
+            macho.default_identifier(MachOFileType::Bundle, "plugin.bundle"),
+            "plugin"
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "full")]
+    #[test]
+    fn executable_segment_derivation() -> Result<(), AppleCodesignError> {
+        use crate::{code_directory::ExecutableSegmentFlags, macho_signing::MachOSigner};
+
+        let data = SyntheticMachO::default()
+            .chained_fixups((0x4000, 0x100))
+            .build();
+
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(SettingsScope::Main, "binary");
+        settings.set_entitlements_xml(
+            SettingsScope::Main,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <plist version=\"1.0\"><dict>\
+            <key>get-task-allow</key><true/>\
+            <key>dynamic-codesigning</key><true/>\
+            </dict></plist>",
+        )?;
+
+        let mut signed = vec![];
+        MachOSigner::new(&data)?.write_signed_binary(&settings, &mut signed)?;
+
+        let macho = MachOBinary::parse(&signed)?;
+        let cd = macho.code_signature()?.unwrap().code_directory()?.unwrap();
+
+        // Boundaries come from the __TEXT segment.
+        assert_eq!(cd.exec_seg_base, Some(0));
+        assert_eq!(cd.exec_seg_limit, Some(0x4000));
+        assert_eq!(
+            cd.exec_seg_flags,
+            Some(
+                ExecutableSegmentFlags::MAIN_BINARY
+                    | ExecutableSegmentFlags::ALLOW_UNSIGNED
+                    | ExecutableSegmentFlags::JIT
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parallel_page_digests() -> Result<(), AppleCodesignError> {
+        let data = (0..10 * 4096 + 100)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+
+        for digest in [DigestType::Sha1, DigestType::Sha256] {
+            let serial = digest_pages(&data, digest, 4096, false)?;
+            assert_eq!(serial.len(), 11);
+            assert_eq!(serial[10], digest.digest_data(&data[10 * 4096..])?);
+            assert_eq!(digest_pages(&data, digest, 4096, true)?, serial);
+        }
+
+        assert_eq!(
+            code_page_digests(&data, DigestType::Sha256, 0)?,
+            vec![DigestType::Sha256.digest_data(&data)?]
+        );
+        assert!(code_page_digests(&[], DigestType::Sha256, 0)?.is_empty());
+
+        Ok(())
+    }
 }
@@ -11,15 +11,18 @@ use {
         code_directory::{CodeDirectoryBlob, CodeSignatureFlags, ExecutableSegmentFlags},
         code_requirement::{CodeRequirementExpression, CodeRequirements, RequirementType},
         embedded_signature::{
-            BlobData, CodeSigningSlot, Digest, EntitlementsBlob, EntitlementsDerBlob,
-            RequirementSetBlob,
+            BlobData, CodeSigningSlot, ConstraintsDerBlob, Digest, EntitlementsBlob,
+            EntitlementsDerBlob, RequirementSetBlob,
         },
         embedded_signature_builder::EmbeddedSignatureBuilder,
         entitlements::plist_to_executable_segment_flags,
         error::AppleCodesignError,
-        macho::{semver_to_macho_target_version, MachFile, MachOBinary},
+        macho::{
+            semver_to_macho_target_version, MachFile, MachOBinary, MachOFileType, MachOParseError,
+        },
         macho_universal::create_universal_macho,
-        policy::derive_designated_requirements,
+        metrics::time_stamped,
+        policy::derive_designated_requirements_from_chain,
         signing_settings::{DesignatedRequirementMode, SettingsScope, SigningSettings},
     },
     goblin::mach::{
@@ -32,9 +35,18 @@ use {
     },
     log::{debug, info, warn},
     scroll::{ctx::SizeWith, IOwrite},
-    std::{borrow::Cow, cmp::Ordering, collections::HashMap, io::Write, path::Path},
+    std::{borrow::Cow, cmp::Ordering, collections::BTreeMap, io::Write, path::Path},
 };
 
+/// Resolve the file type to sign a Mach-O binary as.
+///
+/// An explicit file type in settings takes precedence over the one in the Mach-O header.
+fn resolve_file_type(settings: &SigningSettings, macho: &MachOBinary) -> MachOFileType {
+    settings
+        .macho_file_type(SettingsScope::Main)
+        .unwrap_or_else(|| macho.file_type())
+}
+
 /// Derive a new Mach-O binary with new signature data.
 fn create_macho_with_signature(
     macho: &MachOBinary,
@@ -43,27 +55,66 @@ fn create_macho_with_signature(
     // This should have already been called. But we do it again out of paranoia.
     macho.check_signing_capability()?;
 
+    rewrite_macho_signature(macho, Some(signature_data))
+}
+
+/// Derive a new Mach-O binary without a signature.
+///
+/// The `LC_CODE_SIGNATURE` load command is removed and `__LINKEDIT` is truncated
+/// at the start of the signature data.
+fn create_macho_without_signature(macho: &MachOBinary) -> Result<Vec<u8>, AppleCodesignError> {
+    if macho.code_signature_load_command().is_none() {
+        return Ok(macho.data.to_vec());
+    }
+
+    // The same layout constraints as signing apply: we can only truncate the
+    // signature if nothing follows it.
+    macho.check_signing_capability()?;
+
+    rewrite_macho_signature(macho, None)
+}
+
+/// Rewrite a Mach-O binary with new signature data or no signature.
+fn rewrite_macho_signature(
+    macho: &MachOBinary,
+    signature_data: Option<&[u8]>,
+) -> Result<Vec<u8>, AppleCodesignError> {
+    let signature_len = signature_data.map(|data| data.len()).unwrap_or_default();
+
     // The assumption made by checking_signing_capability() is that signature data
     // is at the end of the __LINKEDIT segment. So the replacement segment is the
     // existing segment truncated at the signature start followed by the new signature
     // data.
-    let new_linkedit_segment_size = macho
+    let linkedit_data = macho
         .linkedit_data_before_signature()
-        .ok_or(AppleCodesignError::MissingLinkedit)?
-        .len()
-        + signature_data.len();
+        .ok_or(AppleCodesignError::MissingLinkedit)?;
+
+    // `codesign_allocate` places new signatures on a 16 byte boundary. Existing
+    // signatures are replaced at their current offset.
+    let signature_padding =
+        if signature_data.is_some() && macho.code_signature_load_command().is_none() {
+            match macho.code_limit_binary_offset()? % 16 {
+                0 => 0,
+                x => 16 - x as usize,
+            }
+        } else {
+            0
+        };
+
+    let new_linkedit_segment_size = linkedit_data.len() + signature_padding + signature_len;
 
     // `codesign` rounds up the segment's vmsize to the nearest 16kb boundary.
-    // We emulate that behavior.
-    let remainder = new_linkedit_segment_size % 16384;
+    // 32-bit architectures (i386, armv7) use 4kb pages. We emulate that behavior.
+    let vm_page_size = if macho.macho.is_64 { 16384 } else { 4096 };
+    let remainder = new_linkedit_segment_size % vm_page_size;
     let new_linkedit_segment_vmsize = if remainder == 0 {
         new_linkedit_segment_size
     } else {
-        new_linkedit_segment_size + 16384 - remainder
+        new_linkedit_segment_size + vm_page_size - remainder
     };
 
     assert!(new_linkedit_segment_vmsize >= new_linkedit_segment_size);
-    assert_eq!(new_linkedit_segment_vmsize % 16384, 0);
+    assert_eq!(new_linkedit_segment_vmsize % vm_page_size, 0);
 
     let mut cursor = std::io::Cursor::new(Vec::<u8>::new());
 
@@ -74,11 +125,21 @@ fn create_macho_with_signature(
         .expect("context should have been parsed before");
 
     // If there isn't a code signature presently, we'll need to introduce a load
-    // command for it.
+    // command for it. When removing the signature, the load command goes away.
     let mut header = macho.macho.header;
-    if macho.code_signature_load_command().is_none() {
-        header.ncmds += 1;
-        header.sizeofcmds += SIZEOF_LINKEDIT_DATA_COMMAND as u32;
+    match (
+        macho.code_signature_load_command().is_some(),
+        signature_data.is_some(),
+    ) {
+        (false, true) => {
+            header.ncmds += 1;
+            header.sizeofcmds += SIZEOF_LINKEDIT_DATA_COMMAND as u32;
+        }
+        (true, false) => {
+            header.ncmds -= 1;
+            header.sizeofcmds -= SIZEOF_LINKEDIT_DATA_COMMAND as u32;
+        }
+        _ => {}
     }
 
     cursor.iowrite_with(header, ctx)?;
@@ -87,17 +148,22 @@ fn create_macho_with_signature(
     // to reflect changes to the signature size and __LINKEDIT segment size.
 
     let mut seen_signature_load_command = false;
+    let mut removed_len = 0;
 
     for load_command in &macho.macho.load_commands {
         let original_command_data =
             &macho.data[load_command.offset..load_command.offset + load_command.command.cmdsize()];
 
         let written_len = match &load_command.command {
+            CommandVariant::CodeSignature(_) if signature_data.is_none() => {
+                removed_len += original_command_data.len();
+                continue;
+            }
             CommandVariant::CodeSignature(command) => {
                 seen_signature_load_command = true;
 
                 let mut command = *command;
-                command.datasize = signature_data.len() as _;
+                command.datasize = signature_len as _;
 
                 cursor.iowrite_with(command, ctx.le)?;
 
@@ -144,21 +210,32 @@ fn create_macho_with_signature(
 
         // For the commands we mutated ourselves, there may be more data after the
         // load command header. Write it out if present.
-        cursor.write_all(&original_command_data[written_len..])?;
+        let trailing = original_command_data.get(written_len..).ok_or_else(|| {
+            MachOParseError::LoadCommandTooSmall {
+                cmd: load_command.command.cmd(),
+                offset: load_command.offset as _,
+                size: original_command_data.len() as _,
+                required: written_len as _,
+            }
+        })?;
+        cursor.write_all(trailing)?;
     }
 
     // If we didn't see a signature load command, write one out now.
-    if !seen_signature_load_command {
+    if signature_data.is_some() && !seen_signature_load_command {
         let command = LinkeditDataCommand {
             cmd: LC_CODE_SIGNATURE,
             cmdsize: SIZEOF_LINKEDIT_DATA_COMMAND as _,
-            dataoff: macho.code_limit_binary_offset()? as _,
-            datasize: signature_data.len() as _,
+            dataoff: (macho.code_limit_binary_offset()? as usize + signature_padding) as _,
+            datasize: signature_len as _,
         };
 
         cursor.iowrite_with(command, ctx.le)?;
     }
 
+    // Space of a removed load command is returned to header padding.
+    cursor.write_all(&b"\0".repeat(removed_len))?;
+
     // Write out segments, updating the __LINKEDIT segment when we encounter it.
     for segment in macho.segments_by_file_offset() {
         // The initial __PAGEZERO segment contains no data (it is the magic and load
@@ -200,12 +277,9 @@ fn create_macho_with_signature(
 
         match segment.name() {
             Ok(SEG_LINKEDIT) => {
-                cursor.write_all(
-                    macho
-                        .linkedit_data_before_signature()
-                        .expect("__LINKEDIT segment data should resolve"),
-                )?;
-                cursor.write_all(signature_data)?;
+                cursor.write_all(linkedit_data)?;
+                cursor.write_all(&b"\0".repeat(signature_padding))?;
+                cursor.write_all(signature_data.unwrap_or_default())?;
             }
             _ => {
                 // At least the __TEXT segment has .fileoff = 0, which has it
@@ -215,8 +289,13 @@ fn create_macho_with_signature(
                     if segment.data.is_empty() {
                         continue;
                     }
-                    let remaining =
-                        &segment.data[cursor.position() as usize..segment.filesize as usize];
+                    let remaining = segment
+                        .data
+                        .get(cursor.position() as usize..segment.filesize as usize)
+                        .ok_or_else(|| MachOParseError::SegmentDataOutOfBounds {
+                            segment: segment.name().unwrap_or("<unknown>").to_string(),
+                            offset: cursor.position(),
+                        })?;
                     cursor.write_all(remaining)?;
                 } else {
                     cursor.write_all(segment.data)?;
@@ -225,7 +304,44 @@ fn create_macho_with_signature(
         }
     }
 
-    Ok(cursor.into_inner())
+    let data = cursor.into_inner();
+
+    // The dynamic loader locates chained fixups by file offset. So they must
+    // survive at their original location byte for byte.
+    if let Some(command) = macho.chained_fixups_load_command() {
+        let range = command.dataoff as usize..(command.dataoff + command.datasize) as usize;
+
+        if data.get(range.clone()) != macho.data.get(range) {
+            return Err(AppleCodesignError::MachOWrite(
+                "dyld chained fixups data not preserved (please report this bug)".into(),
+            ));
+        }
+    }
+
+    Ok(data)
+}
+
+/// Remove code signatures from Mach-O data.
+///
+/// Every Mach-O binary in universal data has its `LC_CODE_SIGNATURE` load command
+/// removed and `__LINKEDIT` segment truncated to exclude the signature. This is
+/// equivalent to `codesign --remove-signature`. Unsigned binaries are returned as-is.
+pub fn remove_signature(macho_data: &[u8]) -> Result<Vec<u8>, AppleCodesignError> {
+    let machos = MachFile::parse(macho_data)?.into_iter().collect::<Vec<_>>();
+
+    let binaries = machos
+        .iter()
+        .map(create_macho_without_signature)
+        .collect::<Result<Vec<_>, AppleCodesignError>>()?;
+
+    if machos.iter().any(|macho| macho.index.is_some()) {
+        let mut data = vec![];
+        create_universal_macho(&mut data, binaries.iter().map(|x| x.as_slice()))?;
+
+        Ok(data)
+    } else {
+        Ok(binaries.into_iter().next().unwrap_or_default())
+    }
 }
 
 /// Write Mach-O file content to an output file.
@@ -303,8 +419,9 @@ impl<'data> MachOSigner<'data> {
             .enumerate()
             .map(|(index, original_macho)| {
                 info!("signing Mach-O binary at index {}", index);
+                let header = &original_macho.macho.header;
                 let settings =
-                    settings.as_nested_macho_settings(index, original_macho.macho.header.cputype());
+                    settings.as_nested_macho_settings(index, header.cputype(), header.cpusubtype());
 
                 let signature_len = original_macho.estimate_embedded_signature_size(&settings)?;
 
@@ -339,7 +456,8 @@ impl<'data> MachOSigner<'data> {
             })
             .collect::<Result<Vec<_>, AppleCodesignError>>()?;
 
-        if binaries.len() > 1 {
+        // Universal input is written as universal output, even with a single slice.
+        if self.machos.iter().any(|macho| macho.index.is_some()) {
             create_universal_macho(writer, binaries.iter().map(|x| x.as_slice()))?;
         } else {
             writer.write_all(&binaries[0])?;
@@ -363,7 +481,9 @@ impl<'data> MachOSigner<'data> {
     ) -> Result<Vec<u8>, AppleCodesignError> {
         let mut builder = EmbeddedSignatureBuilder::default();
 
-        for (slot, blob) in self.create_special_blobs(settings, macho.is_executable())? {
+        let file_type = resolve_file_type(settings, macho);
+
+        for (slot, blob) in self.create_special_blobs(settings, file_type.is_main_binary())? {
             builder.add_blob(slot, blob)?;
         }
 
@@ -390,15 +510,22 @@ impl<'data> MachOSigner<'data> {
         }
 
         if let Some((signing_key, signing_cert)) = settings.signing_key() {
-            builder.create_cms_signature(
-                signing_key,
-                signing_cert,
-                settings.time_stamp_url(),
-                settings.certificate_chain().iter().cloned(),
-            )?;
+            time_stamped(settings, || {
+                builder.create_cms_signature(
+                    signing_key,
+                    signing_cert,
+                    settings.time_stamp_url(),
+                    settings.retry_policy(),
+                    settings.certificate_chain().iter().cloned(),
+                )
+            })?;
         }
 
-        builder.create_superblob()
+        if settings.legacy_superblob() {
+            builder.create_legacy_superblob()
+        } else {
+            builder.create_superblob()
+        }
     }
 
     /// Create the `CodeDirectory` for the current configuration.
@@ -459,17 +586,28 @@ impl<'data> MachOSigner<'data> {
         let platform = 0;
         let page_size = 4096u32;
 
-        let (exec_seg_base, exec_seg_limit) = macho.executable_segment_boundary()?;
-        let (exec_seg_base, exec_seg_limit) = (Some(exec_seg_base), Some(exec_seg_limit));
+        let file_type = resolve_file_type(settings, macho);
+        info!("signing Mach-O as file type {}", file_type);
+
+        // Stubs and other auxiliary file types may not have any code. Only insist on
+        // an executable segment for file types that should have one.
+        let (exec_seg_base, exec_seg_limit) = match macho.executable_segment_boundary() {
+            Ok((base, limit)) => (Some(base), Some(limit)),
+            Err(_) if !file_type.requires_text_segment() => {
+                info!("no __TEXT segment; omitting executable segment boundaries");
+                (None, None)
+            }
+            Err(e) => return Err(e),
+        };
 
         // Executable segment flags are wonky.
         //
-        // Foremost, these flags are only present if the Mach-O binary is an executable. So not
-        // matter what the settings say, we don't set these flags unless the Mach-O file type
-        // is proper.
+        // Foremost, these flags are only present if the Mach-O binary is a main executable.
+        // So no matter what the settings say, we don't set these flags unless the Mach-O
+        // file type (or its override in settings) is proper.
         //
         // Executable segment flags are also derived from an associated entitlements plist.
-        let exec_seg_flags = if macho.is_executable() {
+        let exec_seg_flags = if file_type.is_main_binary() {
             if let Some(entitlements) = settings.entitlements_plist(SettingsScope::Main) {
                 let flags = plist_to_executable_segment_flags(entitlements);
 
@@ -519,13 +657,37 @@ impl<'data> MachOSigner<'data> {
             runtime
         };
 
-        let code_hashes = macho
-            .code_digests(*settings.digest_type(), page_size as _)?
+        let scatter = settings.scatter(SettingsScope::Main).map(|x| x.to_vec());
+        if let Some(scatter) = &scatter {
+            info!(
+                "digesting code pages described by {} scatter entries",
+                scatter.len()
+            );
+        }
+
+        let code_data = macho.digested_code_data()?;
+        let code_hashes = settings
+            .metrics()
+            .time_code_digests(
+                *settings.digest_type(),
+                match &scatter {
+                    Some(scatter) => scatter.iter().map(|entry| entry.count as usize).sum(),
+                    None => code_data.chunks(page_size as _).count(),
+                },
+                code_data.len(),
+                || match &scatter {
+                    Some(scatter) => {
+                        macho.scatter_code_digests(*settings.digest_type(), page_size as _, scatter)
+                    }
+                    None => macho.code_digests(*settings.digest_type(), page_size as _),
+                },
+            )?
             .into_iter()
             .map(|v| Digest { data: v.into() })
-            .collect::<Vec<_>>();
+            .collect::<Vec<_>>()
+            .try_into()?;
 
-        let mut special_hashes = HashMap::new();
+        let mut special_hashes = BTreeMap::new();
 
         // There is no corresponding blob for the info plist data since it is provided
         // externally to the embedded signature.
@@ -574,6 +736,7 @@ impl<'data> MachOSigner<'data> {
             ident,
             team_name,
             code_digests: code_hashes,
+            scatter,
             ..Default::default()
         };
 
@@ -616,7 +779,11 @@ impl<'data> MachOSigner<'data> {
                             .to_string(),
                     );
 
-                    if let Some(expr) = derive_designated_requirements(cert, identifier)? {
+                    if let Some(expr) = derive_designated_requirements_from_chain(
+                        cert,
+                        settings.certificate_chain(),
+                        identifier,
+                    )? {
                         requirements.push(expr);
                     }
                 }
@@ -661,6 +828,13 @@ impl<'data> MachOSigner<'data> {
             }
         }
 
+        if let Some(requirements) = settings.library_constraints(SettingsScope::Main) {
+            info!("adding library constraints");
+            let blob = ConstraintsDerBlob::from_requirements(requirements)?;
+
+            res.push((CodeSigningSlot::LibraryConstraints, blob.into()));
+        }
+
         Ok(res)
     }
 }
@@ -2,9 +2,12 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+//! Reading and writing universal (fat) Mach-O binaries.
+
 use {
     anyhow::Result,
     goblin::mach::{
+        cputype::{get_arch_name_from_types, CpuSubType, CPU_SUBTYPE_MASK},
         fat::{FatArch, FAT_MAGIC, SIZEOF_FAT_ARCH, SIZEOF_FAT_HEADER},
         Mach,
     },
@@ -23,15 +26,141 @@ pub enum UniversalMachOError {
 
     #[error("scroll error: {0}")]
     Scroll(#[from] scroll::Error),
+
+    #[error("data is not a universal Mach-O binary")]
+    NotUniversal,
+
+    #[error("universal Mach-O slice {0} extends beyond end of data")]
+    SliceOutOfBounds(usize),
+}
+
+/// Describes a slice of a universal Mach-O binary.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UniversalSlice {
+    /// CPU type of the Mach-O binary in this slice.
+    pub cputype: u32,
+    /// CPU subtype of the Mach-O binary in this slice.
+    ///
+    /// This includes capability bits, such as the pointer authentication ABI
+    /// bits of `arm64e` binaries.
+    pub cpusubtype: u32,
+    /// File offset of the slice.
+    pub offset: u32,
+    /// Size of the slice in bytes.
+    pub size: u32,
+    /// Alignment of the slice, as a power of 2.
+    pub align: u32,
+}
+
+impl UniversalSlice {
+    /// CPU subtype of this slice without capability bits.
+    pub fn cpu_subtype(&self) -> CpuSubType {
+        self.cpusubtype & !CPU_SUBTYPE_MASK
+    }
+
+    /// Name of the architecture of this slice, as `lipo` prints it.
+    ///
+    /// `arm64` and `arm64e` slices are distinguished by their subtype.
+    pub fn arch_name(&self) -> Option<&'static str> {
+        get_arch_name_from_types(self.cputype, self.cpu_subtype())
+    }
+}
+
+impl From<&FatArch> for UniversalSlice {
+    fn from(arch: &FatArch) -> Self {
+        Self {
+            cputype: arch.cputype,
+            cpusubtype: arch.cpusubtype,
+            offset: arch.offset,
+            size: arch.size,
+            align: arch.align,
+        }
+    }
+}
+
+/// A parsed universal Mach-O binary.
+///
+/// This provides `lipo -info` and `lipo -thin` style access to the slices.
+/// Use [UniversalBinaryBuilder] to create universal binaries.
+pub struct UniversalMachO<'a> {
+    data: &'a [u8],
+    slices: Vec<UniversalSlice>,
+}
+
+impl<'a> UniversalMachO<'a> {
+    /// Parse a universal Mach-O binary.
+    ///
+    /// Errors if the data is a single architecture Mach-O binary.
+    pub fn parse(data: &'a [u8]) -> Result<Self, UniversalMachOError> {
+        let multiarch = match Mach::parse(data)? {
+            Mach::Fat(multiarch) => multiarch,
+            Mach::Binary(_) => return Err(UniversalMachOError::NotUniversal),
+        };
+
+        let slices = multiarch
+            .arches()?
+            .iter()
+            .map(UniversalSlice::from)
+            .collect::<Vec<_>>();
+
+        for (index, slice) in slices.iter().enumerate() {
+            if slice.offset as u64 + slice.size as u64 > data.len() as u64 {
+                return Err(UniversalMachOError::SliceOutOfBounds(index));
+            }
+        }
+
+        Ok(Self { data, slices })
+    }
+
+    /// The slices in this binary, in the order of the fat header.
+    pub fn slices(&self) -> &[UniversalSlice] {
+        &self.slices
+    }
+
+    /// Obtain the data of the slice at an index.
+    pub fn slice_data(&self, index: usize) -> Option<&'a [u8]> {
+        self.slices.get(index).map(|slice| {
+            &self.data[slice.offset as usize..slice.offset as usize + slice.size as usize]
+        })
+    }
+
+    /// Obtain the single architecture Mach-O binary for a CPU type.
+    ///
+    /// This is equivalent to `lipo -thin`. If multiple slices have the CPU type,
+    /// such as `arm64` and `arm64e`, the first one is returned. Use
+    /// [Self::thin_subtype()] to select among them.
+    pub fn thin(&self, cputype: u32) -> Option<&'a [u8]> {
+        self.slices
+            .iter()
+            .position(|slice| slice.cputype == cputype)
+            .and_then(|index| self.slice_data(index))
+    }
+
+    /// Obtain the single architecture Mach-O binary for a CPU type and subtype.
+    ///
+    /// Capability bits of the subtype are ignored.
+    pub fn thin_subtype(&self, cputype: u32, cpusubtype: CpuSubType) -> Option<&'a [u8]> {
+        self.slices
+            .iter()
+            .position(|slice| {
+                slice.cputype == cputype && slice.cpu_subtype() == cpusubtype & !CPU_SUBTYPE_MASK
+            })
+            .and_then(|index| self.slice_data(index))
+    }
 }
 
 /// Interface for constructing a universal Mach-O binary.
+///
+/// This is equivalent to `lipo -create`.
 #[derive(Clone, Default)]
 pub struct UniversalBinaryBuilder {
     binaries: Vec<Vec<u8>>,
 }
 
 impl UniversalBinaryBuilder {
+    /// Add Mach-O binaries to the universal binary.
+    ///
+    /// Every slice of universal input is added. Returns the number of binaries added.
     pub fn add_binary(&mut self, data: impl AsRef<[u8]>) -> Result<usize, UniversalMachOError> {
         let data = data.as_ref();
 
@@ -58,6 +187,16 @@ impl UniversalBinaryBuilder {
     pub fn write(&self, writer: &mut impl Write) -> Result<(), UniversalMachOError> {
         create_universal_macho(writer, self.binaries.iter().map(|x| x.as_slice()))
     }
+
+    /// Resolve the slices that [Self::write()] would write.
+    pub fn slices(&self) -> Result<Vec<UniversalSlice>, UniversalMachOError> {
+        Ok(
+            universal_layout(self.binaries.iter().map(|x| x.as_slice()))?
+                .iter()
+                .map(|(arch, _)| UniversalSlice::from(arch))
+                .collect(),
+        )
+    }
 }
 
 /// Create a universal mach-o binary from existing mach-o binaries.
@@ -70,10 +209,42 @@ pub fn create_universal_macho<'a>(
     writer: &mut impl Write,
     binaries: impl Iterator<Item = &'a [u8]>,
 ) -> Result<(), UniversalMachOError> {
-    // Binaries are aligned on page boundaries. x86-64 appears to use
-    // 4k. aarch64 16k. It really doesn't appear to matter unless you want
-    // to minimize binary size, so we always use 16k.
-    const ALIGN_VALUE: u32 = 14;
+    let records = universal_layout(binaries)?;
+    let align = 2u32.pow(ALIGN_VALUE);
+
+    // Fat header is the magic plus the number of records.
+    writer.iowrite_with(FAT_MAGIC, scroll::BE)?;
+    writer.iowrite_with(records.len() as u32, scroll::BE)?;
+
+    for (fat_arch, _) in &records {
+        let mut buffer = [0u8; SIZEOF_FAT_ARCH];
+        buffer.pwrite_with(fat_arch, 0, scroll::BE)?;
+        writer.write_all(&buffer)?;
+    }
+
+    // This input would be nonsensical. Let's not even support it.
+    let mut current_offset = SIZEOF_FAT_HEADER + records.len() * SIZEOF_FAT_ARCH;
+    assert!(current_offset <= align as usize, "too many mach-o entries");
+
+    // Pad NULL until each mach-o binary.
+    for (fat_arch, macho_data) in records {
+        writer.write_all(&b"\0".repeat(fat_arch.offset as usize - current_offset))?;
+        writer.write_all(macho_data)?;
+        current_offset = fat_arch.offset as usize + macho_data.len();
+    }
+
+    Ok(())
+}
+
+/// Binaries are aligned on page boundaries. x86-64 appears to use
+/// 4k. aarch64 16k. It really doesn't appear to matter unless you want
+/// to minimize binary size, so we always use 16k.
+const ALIGN_VALUE: u32 = 14;
+
+/// Resolve the fat header records for binaries in a universal binary.
+fn universal_layout<'a>(
+    binaries: impl Iterator<Item = &'a [u8]>,
+) -> Result<Vec<(FatArch, &'a [u8])>, UniversalMachOError> {
     let align: u32 = 2u32.pow(ALIGN_VALUE);
 
     let mut records = vec![];
@@ -84,13 +255,11 @@ pub fn create_universal_macho<'a>(
         let macho = goblin::mach::MachO::parse(binary, 0)?;
 
         // This will be 0 for the 1st binary.
-        let pad_bytes = match offset % align {
+        offset += match offset % align {
             0 => 0,
             x => align - x,
         };
 
-        offset += pad_bytes;
-
         let arch = FatArch {
             cputype: macho.header.cputype,
             cpusubtype: macho.header.cpusubtype,
@@ -101,30 +270,212 @@ pub fn create_universal_macho<'a>(
 
         offset += arch.size;
 
-        records.push((arch, pad_bytes as usize, binary));
+        records.push((arch, binary));
     }
 
-    // Fat header is the magic plus the number of records.
-    writer.iowrite_with(FAT_MAGIC, scroll::BE)?;
-    writer.iowrite_with(records.len() as u32, scroll::BE)?;
+    Ok(records)
+}
 
-    for (fat_arch, _, _) in &records {
-        let mut buffer = [0u8; SIZEOF_FAT_ARCH];
-        buffer.pwrite_with(fat_arch, 0, scroll::BE)?;
-        writer.write_all(&buffer)?;
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::test_support::SyntheticMachO,
+        goblin::mach::cputype::{
+            CPU_SUBTYPE_ARM64_ALL, CPU_SUBTYPE_X86_64_ALL, CPU_TYPE_ARM64, CPU_TYPE_X86_64,
+        },
+    };
+
+    #[cfg(feature = "full")]
+    use {
+        crate::{
+            macho::MachOBinary, macho_signing::MachOSigner, AppleCodesignError, SettingsScope,
+            SigningSettings,
+        },
+        goblin::mach::cputype::{
+            CPU_SUBTYPE_ARM64_E, CPU_SUBTYPE_ARM_V7, CPU_SUBTYPE_I386_ALL, CPU_TYPE_ARM,
+            CPU_TYPE_I386,
+        },
+    };
+
+    #[cfg(feature = "full")]
+    fn assert_adhoc_valid(data: &[u8]) {
+        // Ad-hoc signatures have no CMS signature but are otherwise valid.
+        assert!(crate::verify::verify_macho_data(data)
+            .iter()
+            .all(|problem| matches!(
+                problem.problem,
+                crate::VerificationProblemType::NoCryptographicSignature
+            )));
     }
 
-    // Pad NULL until first mach-o binary.
-    let current_offset = SIZEOF_FAT_HEADER + records.len() * SIZEOF_FAT_ARCH;
-    writer.write_all(&b"\0".repeat(align as usize - current_offset % align as usize))?;
+    #[test]
+    fn create_and_thin() -> Result<(), UniversalMachOError> {
+        let x86 = SyntheticMachO::new_64(CPU_TYPE_X86_64, CPU_SUBTYPE_X86_64_ALL)
+            .chained_fixups((0x4000, 0x100))
+            .build();
+        let arm = SyntheticMachO::new_64(CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_ALL)
+            .chained_fixups((0x4000, 0x100))
+            .build();
 
-    // This input would be nonsensical. Let's not even support it.
-    assert!(current_offset <= align as usize, "too many mach-o entries");
+        let mut builder = UniversalBinaryBuilder::default();
+        builder.add_binary(&x86)?;
+        builder.add_binary(&arm)?;
 
-    for (_, pad_bytes, macho_data) in records {
-        writer.write_all(&b"\0".repeat(pad_bytes))?;
-        writer.write_all(macho_data)?;
+        let mut data = vec![];
+        builder.write(&mut data)?;
+
+        let universal = UniversalMachO::parse(&data)?;
+        assert_eq!(universal.slices(), builder.slices()?.as_slice());
+
+        let slices = universal.slices();
+        assert_eq!(slices.len(), 2);
+        assert_eq!(slices[0].arch_name(), Some("x86_64"));
+        assert_eq!(slices[0].offset, 0x4000);
+        assert_eq!(slices[0].size, x86.len() as u32);
+        assert_eq!(slices[0].align, 14);
+        assert_eq!(slices[1].arch_name(), Some("arm64"));
+        assert_eq!(slices[1].offset % 0x4000, 0);
+        assert!(slices[1].offset >= slices[0].offset + slices[0].size);
+
+        assert_eq!(universal.thin(CPU_TYPE_X86_64), Some(x86.as_slice()));
+        assert_eq!(universal.thin(CPU_TYPE_ARM64), Some(arm.as_slice()));
+        assert_eq!(universal.slice_data(2), None);
+
+        // Adding a universal binary adds all its slices.
+        let mut builder = UniversalBinaryBuilder::default();
+        assert_eq!(builder.add_binary(&data)?, 2);
+
+        Ok(())
     }
 
-    Ok(())
+    #[test]
+    #[cfg(feature = "full")]
+    fn arm64e() -> Result<(), AppleCodesignError> {
+        // arm64e binaries have the pointer authentication ABI capability bit set.
+        let arm64e_subtype = CPU_SUBTYPE_ARM64_E | 0x8000_0000;
+
+        let arm = SyntheticMachO::new_64(CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_ALL)
+            .chained_fixups((0x4000, 0x100))
+            .build();
+        let arm64e = SyntheticMachO::new_64(CPU_TYPE_ARM64, arm64e_subtype)
+            .chained_fixups((0x4000, 0x100))
+            .build();
+
+        let mut builder = UniversalBinaryBuilder::default();
+        builder.add_binary(&arm)?;
+        builder.add_binary(&arm64e)?;
+
+        let mut data = vec![];
+        builder.write(&mut data)?;
+
+        let universal = UniversalMachO::parse(&data)?;
+        let slices = universal.slices();
+        assert_eq!(slices[0].arch_name(), Some("arm64"));
+        assert_eq!(slices[1].arch_name(), Some("arm64e"));
+        assert_eq!(slices[1].cpusubtype, arm64e_subtype);
+        assert_eq!(slices[1].cpu_subtype(), CPU_SUBTYPE_ARM64_E);
+        assert_eq!(
+            universal.thin_subtype(CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_E),
+            Some(arm64e.as_slice())
+        );
+        assert_eq!(
+            universal.thin_subtype(CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_ALL),
+            Some(arm.as_slice())
+        );
+
+        // Slices are signed with their own settings and keep their subtype.
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(SettingsScope::Main, "arm64");
+        settings.set_binary_identifier(SettingsScope::try_from("@[cpu_type=arm64e]")?, "arm64e");
+
+        let mut signed = vec![];
+        MachOSigner::new(&data)?.write_signed_binary(&settings, &mut signed)?;
+
+        let universal = UniversalMachO::parse(&signed)?;
+        assert_eq!(universal.slices()[1].cpusubtype, arm64e_subtype);
+
+        for (index, ident) in [(0, "arm64"), (1, "arm64e")] {
+            let macho = MachOBinary::parse(universal.slice_data(index).unwrap())?;
+            assert_eq!(
+                macho.macho.header.cpusubtype,
+                universal.slices()[index].cpusubtype
+            );
+
+            let signature = macho.code_signature()?.unwrap();
+            assert_eq!(signature.code_directory()?.unwrap().ident, ident);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "full")]
+    fn legacy_32bit_slices() -> Result<(), AppleCodesignError> {
+        let i386 = SyntheticMachO::new_32(CPU_TYPE_I386, CPU_SUBTYPE_I386_ALL).build();
+        let armv7 = SyntheticMachO::new_32(CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V7).build();
+        let x86 = SyntheticMachO::new_64(CPU_TYPE_X86_64, CPU_SUBTYPE_X86_64_ALL)
+            .chained_fixups((0x4000, 0x100))
+            .build();
+
+        let mut builder = UniversalBinaryBuilder::default();
+        builder.add_binary(&i386)?;
+        builder.add_binary(&armv7)?;
+        builder.add_binary(&x86)?;
+
+        let mut data = vec![];
+        builder.write(&mut data)?;
+
+        let universal = UniversalMachO::parse(&data)?;
+        assert_eq!(
+            universal
+                .slices()
+                .iter()
+                .map(|slice| slice.arch_name())
+                .collect::<Vec<_>>(),
+            vec![Some("i386"), Some("armv7"), Some("x86_64")]
+        );
+
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(SettingsScope::Main, "legacy");
+        settings.set_binary_identifier(SettingsScope::try_from("@[cpu_type=i386]")?, "i386");
+
+        let mut signed = vec![];
+        MachOSigner::new(&data)?.write_signed_binary(&settings, &mut signed)?;
+        assert_adhoc_valid(&signed);
+
+        let universal = UniversalMachO::parse(&signed)?;
+        for (index, ident) in [(0, "i386"), (1, "legacy"), (2, "legacy")] {
+            let macho = MachOBinary::parse(universal.slice_data(index).unwrap())?;
+            assert_eq!(macho.macho.is_64, index == 2);
+
+            let signature = macho.code_signature()?.unwrap();
+            let cd = signature.code_directory()?.unwrap();
+            assert_eq!(cd.ident, ident);
+            assert_eq!(cd.code_limit as u64, macho.code_limit_binary_offset()?);
+
+            let linkedit = macho.macho.segments.iter().last().unwrap();
+            // 32-bit architectures round __LINKEDIT to 4kb rather than 16kb pages.
+            assert_eq!(linkedit.vmsize, if index == 2 { 0x4000 } else { 0x1000 });
+        }
+
+        // Thin 32-bit binaries are signable on their own.
+        let mut signed = vec![];
+        MachOSigner::new(&armv7)?.write_signed_binary(&settings, &mut signed)?;
+        assert_adhoc_valid(&signed);
+
+        Ok(())
+    }
+
+    #[test]
+    fn not_universal() {
+        assert!(matches!(
+            UniversalMachO::parse(
+                &SyntheticMachO::new_64(CPU_TYPE_X86_64, CPU_SUBTYPE_X86_64_ALL)
+                    .chained_fixups((0x4000, 0x100))
+                    .build()
+            ),
+            Err(UniversalMachOError::NotUniversal)
+        ));
+    }
 }
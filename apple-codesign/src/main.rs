@@ -5,6 +5,8 @@
 fn main() {
     let exit_code = match apple_codesign::cli::main_impl() {
         Ok(()) => 0,
+        // Problems were already printed.
+        Err(apple_codesign::AppleCodesignError::VerificationExitCode(code)) => code,
         Err(err) => {
             eprintln!("Error: {err}");
             1
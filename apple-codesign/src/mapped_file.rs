@@ -0,0 +1,110 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Memory-mapped access to input files.
+
+use std::{ops::Deref, path::Path};
+
+/// Read-only access to the content of a file.
+///
+/// The file is memory mapped. Its content is paged in from the filesystem as
+/// it is accessed instead of being copied into an owned buffer, and mapped
+/// pages can be evicted under memory pressure. This keeps peak memory usage
+/// down when digesting the code pages of multi-gigabyte universal binaries.
+///
+/// Files that can't be mapped, such as empty files or pipes, are read into
+/// memory instead.
+pub(crate) struct MappedFile {
+    inner: Inner,
+}
+
+enum Inner {
+    Mapped(memmap2::Mmap),
+    Owned(Vec<u8>),
+}
+
+impl MappedFile {
+    /// Open a file and map its content.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated, by this or any other
+    /// process, while the instance is alive. Changes to the file may be visible
+    /// through the mapping, even though it is private, and accessing a truncated
+    /// region crashes the process. So drop instances before writing to the path
+    /// they were opened from.
+    pub(crate) unsafe fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref();
+
+        let fh = std::fs::File::open(path)?;
+
+        if fh.metadata()?.len() > 0 {
+            // SAFETY: the caller guarantees the file isn't modified while mapped.
+            if let Ok(map) = unsafe { memmap2::Mmap::map(&fh) } {
+                return Ok(Self {
+                    inner: Inner::Mapped(map),
+                });
+            }
+        }
+
+        Ok(Self {
+            inner: Inner::Owned(std::fs::read(path)?),
+        })
+    }
+
+    /// Whether the content is backed by a memory mapping.
+    #[cfg(test)]
+    fn is_mapped(&self) -> bool {
+        matches!(self.inner, Inner::Mapped(_))
+    }
+}
+
+impl Deref for MappedFile {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.inner {
+            Inner::Mapped(map) => map,
+            Inner::Owned(data) => data,
+        }
+    }
+}
+
+impl AsRef<[u8]> for MappedFile {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn open() -> std::io::Result<()> {
+        let td = tempfile::tempdir()?;
+
+        let path = td.path().join("data");
+        let data = (0..100_000u32)
+            .flat_map(|v| v.to_le_bytes())
+            .collect::<Vec<_>>();
+        std::fs::write(&path, &data)?;
+
+        // SAFETY: nothing modifies the files while they are mapped.
+        let mapped = unsafe { MappedFile::open(&path)? };
+        assert!(mapped.is_mapped());
+        assert_eq!(&mapped[..], data.as_slice());
+
+        let path = td.path().join("empty");
+        std::fs::write(&path, b"")?;
+
+        let mapped = unsafe { MappedFile::open(&path)? };
+        assert!(!mapped.is_mapped());
+        assert!(mapped.is_empty());
+
+        assert!(unsafe { MappedFile::open(td.path().join("missing")) }.is_err());
+
+        Ok(())
+    }
+}
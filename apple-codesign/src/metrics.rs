@@ -0,0 +1,304 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Performance metrics for signing operations.
+//!
+//! Services signing lots of code want to know how fast content is digested and
+//! how long requests to Apple's and time-stamp servers take. Implement
+//! [MetricsRecorder] to receive these measurements, e.g. to forward them to a
+//! monitoring system, or use [MetricsCounters] to accumulate them in memory.
+//!
+//! Recorders are registered via [SigningSettings::set_metrics_recorder()],
+//! [Notarizer::set_metrics_recorder()], and [Stapler::set_metrics_recorder()].
+//!
+//! [SigningSettings::set_metrics_recorder()]: crate::SigningSettings::set_metrics_recorder()
+//! [Notarizer::set_metrics_recorder()]: crate::Notarizer::set_metrics_recorder()
+//! [Stapler::set_metrics_recorder()]: crate::stapling::Stapler::set_metrics_recorder()
+
+use {
+    crate::{
+        embedded_signature::DigestType, error::AppleCodesignError,
+        signing_settings::SigningSettings,
+    },
+    std::{
+        collections::BTreeMap,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    },
+};
+
+/// A network service contacted by this crate.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum NetworkService {
+    /// A time-stamp protocol server.
+    ///
+    /// When time-stamping signatures, the duration includes creating the CMS
+    /// signature, as the two can't be separated.
+    TimeStamp,
+    /// Apple's Notary API.
+    NotaryApi,
+    /// The S3 bucket notarization submissions are uploaded to.
+    NotaryUpload,
+    /// Apple's notarization ticket lookup service.
+    TicketLookup,
+}
+
+/// Receives performance measurements.
+///
+/// All methods have empty default implementations so implementations only need
+/// to handle the measurements they care about. Methods may be called
+/// concurrently from multiple threads.
+pub trait MetricsRecorder: Send + Sync {
+    /// Record the computation of code digests.
+    ///
+    /// `pages` pages totaling `bytes` bytes were digested with `digest_type` in
+    /// `elapsed` time.
+    fn record_code_digests(
+        &self,
+        _digest_type: DigestType,
+        _pages: usize,
+        _bytes: usize,
+        _elapsed: Duration,
+    ) {
+    }
+
+    /// Record a request to a network service.
+    ///
+    /// Each attempt of a retried request is recorded separately.
+    fn record_network_request(&self, _service: NetworkService, _elapsed: Duration, _success: bool) {
+    }
+}
+
+/// Handle to an optional [MetricsRecorder].
+///
+/// The default has no recorder and discards measurements.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    recorder: Option<Arc<dyn MetricsRecorder>>,
+}
+
+impl std::fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Metrics")
+            .field("recorder", &self.recorder.is_some())
+            .finish()
+    }
+}
+
+impl Metrics {
+    /// Construct an instance forwarding measurements to a recorder.
+    pub fn new(recorder: Arc<dyn MetricsRecorder>) -> Self {
+        Self {
+            recorder: Some(recorder),
+        }
+    }
+
+    /// Run a function computing code digests, recording its duration.
+    pub fn time_code_digests<T, E>(
+        &self,
+        digest_type: DigestType,
+        pages: usize,
+        bytes: usize,
+        f: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let Some(recorder) = &self.recorder else {
+            return f();
+        };
+
+        let start = Instant::now();
+        let res = f()?;
+        recorder.record_code_digests(digest_type, pages, bytes, start.elapsed());
+
+        Ok(res)
+    }
+
+    /// Run a function performing a network request, recording its duration.
+    pub fn time_request<T, E>(
+        &self,
+        service: NetworkService,
+        f: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let Some(recorder) = &self.recorder else {
+            return f();
+        };
+
+        let start = Instant::now();
+        let res = f();
+        recorder.record_network_request(service, start.elapsed(), res.is_ok());
+
+        res
+    }
+}
+
+/// Run a function creating a CMS signature.
+///
+/// If the signature is time-stamped, the function is recorded as a request to
+/// the time-stamp server.
+pub(crate) fn time_stamped<T>(
+    settings: &SigningSettings,
+    f: impl FnOnce() -> Result<T, AppleCodesignError>,
+) -> Result<T, AppleCodesignError> {
+    if settings.time_stamp_url().is_some() {
+        settings
+            .metrics()
+            .time_request(NetworkService::TimeStamp, f)
+    } else {
+        f()
+    }
+}
+
+/// Aggregated measurements of requests to a network service.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RequestStats {
+    /// Number of requests.
+    pub count: u64,
+    /// Number of failed requests.
+    pub failures: u64,
+    /// Sum of the durations of all requests.
+    pub total_time: Duration,
+}
+
+impl RequestStats {
+    /// The mean duration of a request.
+    pub fn mean_latency(&self) -> Option<Duration> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.total_time / self.count as u32)
+        }
+    }
+}
+
+/// Measurements accumulated by [MetricsCounters].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MetricsSnapshot {
+    /// Total bytes digested for code digests.
+    pub bytes_hashed: u64,
+    /// Total pages digested for code digests.
+    pub pages_hashed: u64,
+    /// Total time spent computing code digests.
+    pub hashing_time: Duration,
+    /// Statistics for each contacted network service.
+    pub requests: BTreeMap<NetworkService, RequestStats>,
+}
+
+impl MetricsSnapshot {
+    /// Bytes digested per second while computing code digests.
+    pub fn bytes_hashed_per_second(&self) -> Option<f64> {
+        per_second(self.bytes_hashed, self.hashing_time)
+    }
+
+    /// Pages digested per second while computing code digests.
+    pub fn pages_hashed_per_second(&self) -> Option<f64> {
+        per_second(self.pages_hashed, self.hashing_time)
+    }
+}
+
+fn per_second(value: u64, time: Duration) -> Option<f64> {
+    if time.is_zero() {
+        None
+    } else {
+        Some(value as f64 / time.as_secs_f64())
+    }
+}
+
+/// A [MetricsRecorder] accumulating measurements in memory.
+#[derive(Debug, Default)]
+pub struct MetricsCounters {
+    state: Mutex<MetricsSnapshot>,
+}
+
+impl MetricsCounters {
+    /// Obtain the measurements accumulated so far.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.state
+            .lock()
+            .expect("lock should not be poisoned")
+            .clone()
+    }
+
+    /// Discard accumulated measurements.
+    pub fn reset(&self) {
+        *self.state.lock().expect("lock should not be poisoned") = MetricsSnapshot::default();
+    }
+}
+
+impl MetricsRecorder for MetricsCounters {
+    fn record_code_digests(
+        &self,
+        _digest_type: DigestType,
+        pages: usize,
+        bytes: usize,
+        elapsed: Duration,
+    ) {
+        let mut state = self.state.lock().expect("lock should not be poisoned");
+        state.bytes_hashed += bytes as u64;
+        state.pages_hashed += pages as u64;
+        state.hashing_time += elapsed;
+    }
+
+    fn record_network_request(&self, service: NetworkService, elapsed: Duration, success: bool) {
+        let mut state = self.state.lock().expect("lock should not be poisoned");
+        let stats = state.requests.entry(service).or_default();
+        stats.count += 1;
+        if !success {
+            stats.failures += 1;
+        }
+        stats.total_time += elapsed;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{macho_signing::MachOSigner, signing_settings::SettingsScope},
+    };
+
+    #[test]
+    fn counters() {
+        let counters = Arc::new(MetricsCounters::default());
+        let metrics = Metrics::new(counters.clone());
+
+        assert_eq!(
+            metrics.time_request(NetworkService::NotaryApi, || Ok::<_, ()>(42)),
+            Ok(42)
+        );
+        assert_eq!(
+            metrics.time_request(NetworkService::NotaryApi, || Err::<(), _>("failed")),
+            Err("failed")
+        );
+
+        let snapshot = counters.snapshot();
+        let stats = snapshot.requests[&NetworkService::NotaryApi];
+        assert_eq!(stats.count, 2);
+        assert_eq!(stats.failures, 1);
+        assert!(stats.mean_latency().is_some());
+        assert!(!snapshot.requests.contains_key(&NetworkService::TimeStamp));
+
+        counters.reset();
+        assert_eq!(counters.snapshot(), MetricsSnapshot::default());
+        assert_eq!(counters.snapshot().bytes_hashed_per_second(), None);
+    }
+
+    #[test]
+    fn signing_records_digests() -> Result<(), crate::AppleCodesignError> {
+        let counters = Arc::new(MetricsCounters::default());
+
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(SettingsScope::Main, "binary");
+        settings.set_metrics_recorder(counters.clone());
+
+        let macho = crate::test_support::SyntheticMachO::default().build();
+        let mut signed = vec![];
+        MachOSigner::new(&macho)?.write_signed_binary(&settings, &mut signed)?;
+
+        let snapshot = counters.snapshot();
+        assert!(snapshot.pages_hashed > 0);
+        assert!(snapshot.bytes_hashed >= snapshot.pages_hashed);
+        assert!(snapshot.requests.is_empty());
+
+        Ok(())
+    }
+}
@@ -15,17 +15,25 @@ and waiting on the availability of a notarization ticket.
 
 pub use app_store_connect::notary_api;
 use {
-    crate::{reader::PathType, AppleCodesignError},
-    app_store_connect::{AppStoreConnectClient, ConnectTokenEncoder, UnifiedApiKey},
+    crate::{
+        metrics::{Metrics, MetricsRecorder, NetworkService},
+        reader::PathType,
+        retry::RetryPolicy,
+        AppleCodesignError,
+    },
+    app_store_connect::{
+        AppStoreConnectClient, AppStoreConnectError, ConnectTokenEncoder, UnifiedApiKey,
+    },
     apple_bundles::DirectoryBundle,
     aws_sdk_s3::config::{Credentials, Region},
     aws_smithy_http::byte_stream::ByteStream,
-    log::{info, warn},
+    log::{error, info, warn},
     sha2::Digest,
     std::{
         fs::File,
         io::{Read, Seek, SeekFrom, Write},
         path::{Path, PathBuf},
+        sync::Arc,
         time::Duration,
     },
 };
@@ -115,6 +123,17 @@ pub fn bundle_to_zip(bundle: &DirectoryBundle) -> Result<Vec<u8>, AppleCodesignE
     Ok(writer.into_inner())
 }
 
+/// The delay before polling a submission's status again.
+///
+/// `poll` is the 0-based number of polls already waited after. Delays never
+/// exceed the `remaining` wait time so the final poll happens at the wait limit.
+fn poll_delay(initial: Duration, max: Duration, poll: u32, remaining: Duration) -> Duration {
+    initial
+        .saturating_mul(2u32.saturating_pow(poll))
+        .min(max)
+        .min(remaining)
+}
+
 /// Represents the result of a notarization upload.
 pub enum NotarizationUpload {
     /// We performed the upload and only have the upload ID / UUID for it.
@@ -140,19 +159,63 @@ enum UploadKind {
 pub struct Notarizer {
     token_encoder: ConnectTokenEncoder,
 
-    /// How long to wait between polling the server for upload status.
+    /// How long to wait before polling the server for upload status again.
+    ///
+    /// The interval doubles after each poll, up to `wait_poll_max_interval`.
     wait_poll_interval: Duration,
+
+    /// The maximum time to wait between polls of the server for upload status.
+    wait_poll_max_interval: Duration,
+
+    /// How to retry failed Notary API requests.
+    retry_policy: RetryPolicy,
+
+    /// Receives performance measurements.
+    metrics: Metrics,
 }
 
 impl Notarizer {
+    /// Whether [Self::notarize_path()] can notarize entities of a given type.
+    pub(crate) fn supports_path_type(kind: PathType) -> bool {
+        match kind {
+            PathType::Bundle | PathType::Dmg | PathType::Xar | PathType::Zip => true,
+            PathType::MachO | PathType::Other => false,
+        }
+    }
+
     /// Construct a new instance.
     fn new(token_encoder: ConnectTokenEncoder) -> Self {
         Self {
             token_encoder,
             wait_poll_interval: Duration::from_secs(3),
+            wait_poll_max_interval: Duration::from_secs(30),
+            retry_policy: RetryPolicy::default(),
+            metrics: Metrics::default(),
         }
     }
 
+    /// Set the policy for retrying failed Notary API requests.
+    ///
+    /// Creating a submission and the upload of content to S3 are not retried,
+    /// as repeating them could create duplicate submissions.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+
+    /// Set the intervals between polls of the server when waiting on a submission.
+    ///
+    /// The first poll happens after `initial`. The interval then doubles after
+    /// each poll, up to `max`. Defaults are 3 and 30 seconds.
+    pub fn set_wait_poll_intervals(&mut self, initial: Duration, max: Duration) {
+        self.wait_poll_interval = initial;
+        self.wait_poll_max_interval = max.max(initial);
+    }
+
+    /// Set a recorder to receive the latency of Notary API requests and uploads.
+    pub fn set_metrics_recorder(&mut self, recorder: Arc<dyn MetricsRecorder>) {
+        self.metrics = Metrics::new(recorder);
+    }
+
     /// Construct an instance from an API issuer ID and API key.
     pub fn from_api_key_id(
         issuer_id: impl ToString,
@@ -169,6 +232,42 @@ impl Notarizer {
         Ok(Self::new(UnifiedApiKey::from_json_path(path)?.try_into()?))
     }
 
+    /// Validate that the configured API credentials are accepted by the Notary API.
+    ///
+    /// This performs a cheap authenticated request (listing previous submissions)
+    /// so credentials problems can be detected before an asset is uploaded.
+    ///
+    /// Returns the number of previous submissions visible to the credentials.
+    pub fn validate_credentials(&self) -> Result<usize, AppleCodesignError> {
+        // Creating a token exercises the private key. Errors here are a problem with
+        // the key material, not with the server.
+        self.token_encoder
+            .new_token(300)
+            .map_err(|e| AppleCodesignError::NotarizeCredentialsBadKey(format!("{e}")))?;
+
+        warn!("validating credentials against Notary API");
+        let client = self.client()?;
+        let submissions = self
+            .retry_policy
+            .run("listing Notary API submissions", || {
+                Ok(self
+                    .metrics
+                    .time_request(NetworkService::NotaryApi, || client.list_submissions())?)
+            })
+            .map_err(|e| match e {
+                AppleCodesignError::Anyhow(e) => {
+                    match e.downcast_ref::<AppStoreConnectError>().map(|e| e.status()) {
+                        Some(401) => AppleCodesignError::NotarizeCredentialsRejected,
+                        Some(403) => AppleCodesignError::NotarizeCredentialsForbidden,
+                        _ => AppleCodesignError::from(e),
+                    }
+                }
+                e => e,
+            })?;
+
+        Ok(submissions.data.len())
+    }
+
     /// Attempt to notarize an asset defined by a filesystem path.
     ///
     /// The type of path is sniffed out and the appropriate notarization routine is called.
@@ -273,7 +372,10 @@ impl Notarizer {
             name, digest
         );
 
-        let submission = client.create_submission(&digest, name)?;
+        // Not retried: creating a submission isn't idempotent.
+        let submission = self.metrics.time_request(NetworkService::NotaryApi, || {
+            client.create_submission(&digest, name)
+        })?;
 
         warn!("created submission ID: {}", submission.data.id);
 
@@ -330,7 +432,9 @@ impl Notarizer {
             .body(bytestream)
             .send();
 
-        rt.block_on(fut).map_err(aws_sdk_s3::Error::from)?;
+        self.metrics
+            .time_request(NetworkService::NotaryUpload, || rt.block_on(fut))
+            .map_err(aws_sdk_s3::Error::from)?;
 
         warn!("S3 upload completed successfully");
 
@@ -361,7 +465,13 @@ impl Notarizer {
         &self,
         submission_id: &str,
     ) -> Result<notary_api::SubmissionResponse, AppleCodesignError> {
-        Ok(self.client()?.get_submission(submission_id)?)
+        let client = self.client()?;
+
+        self.retry_policy.run("fetching Notary API submission", || {
+            Ok(self.metrics.time_request(NetworkService::NotaryApi, || {
+                client.get_submission(submission_id)
+            })?)
+        })
     }
 
     pub fn wait_on_notarization(
@@ -376,6 +486,7 @@ impl Notarizer {
         );
 
         let start_time = std::time::Instant::now();
+        let mut poll = 0;
 
         loop {
             let status = self.get_submission(submission_id)?;
@@ -399,7 +510,13 @@ impl Notarizer {
                 return Err(AppleCodesignError::NotarizeWaitLimitReached);
             }
 
-            std::thread::sleep(self.wait_poll_interval);
+            std::thread::sleep(poll_delay(
+                self.wait_poll_interval,
+                self.wait_poll_max_interval,
+                poll,
+                wait_limit - elapsed,
+            ));
+            poll += 1;
         }
     }
 
@@ -409,13 +526,30 @@ impl Notarizer {
         submission_id: &str,
     ) -> Result<serde_json::Value, AppleCodesignError> {
         warn!("fetching notarization log for {}", submission_id);
-        Ok(self.client()?.get_submission_log(submission_id)?)
+        let client = self.client()?;
+
+        self.retry_policy.run("fetching notarization log", || {
+            Ok(self.metrics.time_request(NetworkService::NotaryApi, || {
+                client.get_submission_log(submission_id)
+            })?)
+        })
+    }
+
+    /// Obtain the processing log from an upload, parsed into typed issues.
+    pub fn fetch_notarization_log_parsed(
+        &self,
+        submission_id: &str,
+    ) -> Result<notary_api::NotarizationLog, AppleCodesignError> {
+        Ok(notary_api::NotarizationLog::from_value(
+            self.fetch_notarization_log(submission_id)?,
+        )?)
     }
 
     /// Waits on an app store package upload and fetches and logs the upload log.
     ///
-    /// This is just a convenience around [Self::wait_on_app_store_package_upload()] and
-    /// [Self::fetch_upload_log()].
+    /// This is just a convenience around [Self::wait_on_notarization()] and
+    /// [Self::fetch_notarization_log()]. Issues found by the notary service are
+    /// logged individually after the raw log.
     pub fn wait_on_notarization_and_fetch_log(
         &self,
         submission_id: &str,
@@ -429,6 +563,90 @@ impl Notarizer {
             warn!("notary log> {}", line);
         }
 
+        match notary_api::NotarizationLog::from_value(log) {
+            Ok(log) => {
+                for issue in &log.issues {
+                    if issue.is_error() {
+                        error!("notary issue> {}", issue);
+                    } else {
+                        warn!("notary issue> {}", issue);
+                    }
+                }
+            }
+            Err(e) => {
+                warn!("unable to parse notarization log: {}", e);
+            }
+        }
+
         Ok(status)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn poll_delay_backoff() {
+        let initial = Duration::from_secs(3);
+        let max = Duration::from_secs(30);
+        let remaining = Duration::from_secs(600);
+
+        assert_eq!(
+            (0..6)
+                .map(|poll| poll_delay(initial, max, poll, remaining).as_secs())
+                .collect::<Vec<_>>(),
+            vec![3, 6, 12, 24, 30, 30]
+        );
+        assert_eq!(poll_delay(initial, max, 100, remaining), max);
+        assert_eq!(
+            poll_delay(initial, max, 4, Duration::from_secs(5)),
+            Duration::from_secs(5)
+        );
+    }
+
+    #[test]
+    fn parse_notarization_log() -> Result<(), AppleCodesignError> {
+        let log = notary_api::NotarizationLog::from_value(serde_json::json!({
+            "logFormatVersion": 1,
+            "jobId": "2efe2717-52ef-43a5-96dc-0797e4ca1041",
+            "status": "Invalid",
+            "statusSummary": "Archive contains critical validation errors",
+            "statusCode": 4000,
+            "archiveFilename": "MyApp.zip",
+            "uploadDate": "2023-01-01T00:00:00.000Z",
+            "sha256": "e5a8d8ee",
+            "ticketContents": null,
+            "issues": [
+                {
+                    "severity": "error",
+                    "code": null,
+                    "path": "MyApp.zip/MyApp.app/Contents/MacOS/MyApp",
+                    "message": "The executable does not have the hardened runtime enabled.",
+                    "docUrl": "https://developer.apple.com/documentation/security/notarizing_macos_software_before_distribution/resolving_common_notarization_issues",
+                    "architecture": "arm64"
+                },
+                {
+                    "severity": "warning",
+                    "code": null,
+                    "path": "MyApp.zip/MyApp.app/Contents/MacOS/MyApp",
+                    "message": "The signature does not include a secure timestamp.",
+                    "docUrl": null,
+                    "architecture": "x86_64"
+                }
+            ]
+        }))?;
+
+        assert_eq!(log.status, notary_api::SubmissionResponseStatus::Invalid);
+        assert!(log.ticket_contents.is_empty());
+        assert_eq!(log.issues.len(), 2);
+        assert_eq!(log.errors().count(), 1);
+        assert_eq!(
+            log.issues[1].to_string(),
+            "warning: MyApp.zip/MyApp.app/Contents/MacOS/MyApp (x86_64): \
+             The signature does not include a secure timestamp."
+        );
+
+        Ok(())
+    }
+}
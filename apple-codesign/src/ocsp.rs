@@ -0,0 +1,834 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Certificate revocation checking via OCSP.
+//!
+//! Apple revokes the certificates of compromised or malicious developers.
+//! Gatekeeper detects this by querying the Online Certificate Status Protocol
+//! (OCSP) responder advertised in each certificate's Authority Information
+//! Access extension. This module implements the client side of OCSP as defined
+//! by RFC 6960 so verification can do the same.
+//!
+//! [OcspChecker] queries responders and caches their responses until they
+//! expire. In soft-fail mode (the default), failures to obtain a status are
+//! logged and otherwise ignored, like Gatekeeper does when offline. In strict
+//! mode, they are errors.
+
+use {
+    crate::{error::AppleCodesignError, ticket_lookup::default_client},
+    bcder::{
+        decode::{Constructed, Content, DecodeError, Source},
+        encode::{PrimitiveContent, Values},
+        BitString, ConstOid, Integer, Mode, OctetString, Oid, Tag,
+    },
+    bytes::Bytes,
+    log::{debug, warn},
+    reqwest::blocking::Client,
+    std::{collections::HashMap, sync::Mutex},
+    x509_certificate::{
+        asn1time::GeneralizedTime,
+        rfc5280::{AlgorithmIdentifier, Certificate},
+        CapturedX509Certificate, DigestAlgorithm, SignatureAlgorithm,
+    },
+};
+
+/// Authority Information Access extension.
+///
+/// 1.3.6.1.5.5.7.1.1
+const OID_AUTHORITY_INFO_ACCESS: ConstOid = Oid(&[43, 6, 1, 5, 5, 7, 1, 1]);
+
+/// OCSP access method in the Authority Information Access extension.
+///
+/// 1.3.6.1.5.5.7.48.1
+const OID_AD_OCSP: ConstOid = Oid(&[43, 6, 1, 5, 5, 7, 48, 1]);
+
+/// Basic OCSP response type.
+///
+/// 1.3.6.1.5.5.7.48.1.1
+const OID_OCSP_BASIC: ConstOid = Oid(&[43, 6, 1, 5, 5, 7, 48, 1, 1]);
+
+/// Extended key usage extension.
+///
+/// 2.5.29.37
+const OID_EXTENDED_KEY_USAGE: ConstOid = Oid(&[85, 29, 37]);
+
+/// OCSP signing extended key usage, authorizing delegated responders.
+///
+/// 1.3.6.1.5.5.7.3.9
+const OID_EKU_OCSP_SIGNING: ConstOid = Oid(&[43, 6, 1, 5, 5, 7, 3, 9]);
+
+/// How long responses without a `nextUpdate` time are cached.
+const DEFAULT_CACHE_DURATION_SECONDS: i64 = 3600;
+
+/// How far in the future response times may be to tolerate clock differences.
+const ALLOWED_CLOCK_SKEW_SECONDS: i64 = 300;
+
+/// The revocation status of a certificate.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CertificateStatus {
+    /// The certificate isn't revoked.
+    Good,
+    /// The certificate was revoked.
+    Revoked {
+        /// When the certificate was revoked.
+        time: chrono::DateTime<chrono::Utc>,
+        /// The CRL reason code, if given.
+        reason: Option<u8>,
+    },
+    /// The responder doesn't know the certificate.
+    Unknown,
+}
+
+/// Identifies a certificate in OCSP requests and responses.
+///
+/// ```ASN.1
+/// CertID ::= SEQUENCE {
+///   hashAlgorithm       AlgorithmIdentifier,
+///   issuerNameHash      OCTET STRING,
+///   issuerKeyHash       OCTET STRING,
+///   serialNumber        CertificateSerialNumber }
+/// ```
+#[derive(Clone, Debug)]
+struct CertId {
+    hash_algorithm: AlgorithmIdentifier,
+    issuer_name_hash: Bytes,
+    issuer_key_hash: Bytes,
+    serial_number: Integer,
+}
+
+impl CertId {
+    /// Construct an instance identifying `cert` issued by `issuer`.
+    fn new(
+        cert: &CapturedX509Certificate,
+        issuer: &CapturedX509Certificate,
+    ) -> Result<Self, AppleCodesignError> {
+        let issuer_name = issuer
+            .subject_name()
+            .encode_ref()
+            .to_captured(Mode::Der)
+            .into_bytes();
+
+        Ok(Self {
+            hash_algorithm: DigestAlgorithm::Sha1.into(),
+            issuer_name_hash: DigestAlgorithm::Sha1.digest_data(&issuer_name).into(),
+            issuer_key_hash: DigestAlgorithm::Sha1
+                .digest_data(&issuer.public_key_data())
+                .into(),
+            serial_number: cert.serial_number_asn1().clone(),
+        })
+    }
+
+    fn take_from<S: Source>(cons: &mut Constructed<S>) -> Result<Self, DecodeError<S::Error>> {
+        cons.take_sequence(|cons| {
+            Ok(Self {
+                hash_algorithm: AlgorithmIdentifier::take_from(cons)?,
+                issuer_name_hash: OctetString::take_from(cons)?.into_bytes(),
+                issuer_key_hash: OctetString::take_from(cons)?.into_bytes(),
+                serial_number: Integer::take_from(cons)?,
+            })
+        })
+    }
+
+    fn encode_ref(&self) -> impl Values + '_ {
+        bcder::encode::sequence((
+            &self.hash_algorithm,
+            OctetString::encode_slice(self.issuer_name_hash.as_ref()),
+            OctetString::encode_slice(self.issuer_key_hash.as_ref()),
+            self.serial_number.encode(),
+        ))
+    }
+
+    /// Whether another instance identifies the same certificate.
+    ///
+    /// Only SHA-1 identifiers are produced, so the hash algorithm isn't compared.
+    fn matches(&self, other: &Self) -> bool {
+        self.issuer_name_hash == other.issuer_name_hash
+            && self.issuer_key_hash == other.issuer_key_hash
+            && self.serial_number == other.serial_number
+    }
+
+    /// Encode an OCSP request for the certificate.
+    ///
+    /// ```ASN.1
+    /// OCSPRequest ::= SEQUENCE {
+    ///   tbsRequest              TBSRequest,
+    ///   optionalSignature   [0] EXPLICIT Signature OPTIONAL }
+    ///
+    /// TBSRequest ::= SEQUENCE {
+    ///   version             [0] EXPLICIT Version DEFAULT v1,
+    ///   requestorName       [1] EXPLICIT GeneralName OPTIONAL,
+    ///   requestList             SEQUENCE OF Request,
+    ///   requestExtensions   [2] EXPLICIT Extensions OPTIONAL }
+    ///
+    /// Request ::= SEQUENCE {
+    ///   reqCert                     CertID,
+    ///   singleRequestExtensions [0] EXPLICIT Extensions OPTIONAL }
+    /// ```
+    fn request_der(&self) -> Vec<u8> {
+        bcder::encode::sequence(bcder::encode::sequence(bcder::encode::sequence(
+            bcder::encode::sequence(self.encode_ref()),
+        )))
+        .to_captured(Mode::Der)
+        .into_bytes()
+        .to_vec()
+    }
+}
+
+/// The status of a certificate as reported by a verified OCSP response.
+#[derive(Clone, Debug)]
+struct SingleResponse {
+    status: CertificateStatus,
+    /// When the status was known to be correct.
+    this_update: chrono::DateTime<chrono::Utc>,
+    /// When the status expires, if the responder says so.
+    next_update: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Parse and verify an OCSP response, obtaining the status of the certificate in `cert_id`.
+///
+/// The response must be signed by `issuer` or by a certificate `issuer` delegated
+/// OCSP signing to. Responses produced or describing a status in the future,
+/// beyond an allowance for clock skew, and expired responses are rejected.
+///
+/// ```ASN.1
+/// OCSPResponse ::= SEQUENCE {
+///   responseStatus         OCSPResponseStatus,
+///   responseBytes      [0] EXPLICIT ResponseBytes OPTIONAL }
+///
+/// ResponseBytes ::= SEQUENCE {
+///   responseType   OBJECT IDENTIFIER,
+///   response       OCTET STRING }
+///
+/// BasicOCSPResponse ::= SEQUENCE {
+///   tbsResponseData      ResponseData,
+///   signatureAlgorithm   AlgorithmIdentifier,
+///   signature            BIT STRING,
+///   certs            [0] EXPLICIT SEQUENCE OF Certificate OPTIONAL }
+/// ```
+fn parse_response(
+    data: &[u8],
+    cert_id: &CertId,
+    issuer: &CapturedX509Certificate,
+) -> Result<SingleResponse, AppleCodesignError> {
+    let (status, response) = Constructed::decode(data, Mode::Der, |cons| {
+        cons.take_sequence(|cons| {
+            let status = cons.take_primitive_if(Tag::ENUMERATED, |prim| prim.to_u8())?;
+            let response = cons.take_opt_constructed_if(Tag::CTX_0, |cons| {
+                cons.take_sequence(|cons| {
+                    let response_type = Oid::take_from(cons)?;
+                    let response = OctetString::take_from(cons)?;
+
+                    Ok((response_type, response))
+                })
+            })?;
+
+            Ok((status, response))
+        })
+    })
+    .map_err(|e| AppleCodesignError::OcspBadResponse(format!("{e}")))?;
+
+    if status != 0 {
+        return Err(AppleCodesignError::OcspResponseStatus(status));
+    }
+
+    let (response_type, response) = response.ok_or_else(|| {
+        AppleCodesignError::OcspBadResponse("successful response lacks response bytes".into())
+    })?;
+    if response_type != OID_OCSP_BASIC {
+        return Err(AppleCodesignError::OcspBadResponse(format!(
+            "unsupported response type {response_type}"
+        )));
+    }
+
+    let (tbs_response_data, signature_algorithm, signature, certs) =
+        Constructed::decode(response.into_bytes(), Mode::Der, |cons| {
+            cons.take_sequence(|cons| {
+                let tbs_response_data = cons.capture_one()?;
+                let signature_algorithm = AlgorithmIdentifier::take_from(cons)?;
+                let signature = BitString::take_from(cons)?;
+                let certs = cons
+                    .take_opt_constructed_if(Tag::CTX_0, |cons| {
+                        cons.take_sequence(|cons| {
+                            let mut certs = vec![];
+                            while let Some(cert) =
+                                cons.take_opt_sequence(Certificate::from_sequence)?
+                            {
+                                certs.push(cert);
+                            }
+
+                            Ok(certs)
+                        })
+                    })?
+                    .unwrap_or_default();
+
+                Ok((tbs_response_data, signature_algorithm, signature, certs))
+            })
+        })
+        .map_err(|e| AppleCodesignError::OcspBadResponse(format!("{e}")))?;
+
+    let signature_algorithm = SignatureAlgorithm::try_from(&signature_algorithm)?;
+    let signature = signature.octet_bytes();
+
+    let verifies = |responder: &CapturedX509Certificate| -> bool {
+        let Some(key_algorithm) = responder.key_algorithm() else {
+            return false;
+        };
+        let Ok(verify_algorithm) =
+            signature_algorithm.resolve_verification_algorithm(key_algorithm)
+        else {
+            return false;
+        };
+
+        responder
+            .verify_signed_data_with_algorithm(
+                tbs_response_data.as_slice(),
+                &signature,
+                verify_algorithm,
+            )
+            .is_ok()
+    };
+
+    let trusted = verifies(issuer)
+        || certs.into_iter().any(|cert| {
+            let der = cert.encode_ref().to_captured(Mode::Der).into_bytes();
+            let Ok(responder) = CapturedX509Certificate::from_der(der.to_vec()) else {
+                return false;
+            };
+
+            responder.verify_signed_by_certificate(issuer).is_ok()
+                && has_ocsp_signing_usage(&responder)
+                && verifies(&responder)
+        });
+
+    if !trusted {
+        return Err(AppleCodesignError::OcspSignatureInvalid);
+    }
+
+    let (produced_at, responses) = tbs_response_data
+        .decode(take_response_data)
+        .map_err(|e| AppleCodesignError::OcspBadResponse(format!("{e}")))?;
+
+    let response = responses
+        .into_iter()
+        .find(|(id, _)| id.matches(cert_id))
+        .map(|(_, response)| response)
+        .ok_or_else(|| {
+            AppleCodesignError::OcspBadResponse("response lacks requested certificate".into())
+        })?;
+
+    let now = chrono::Utc::now();
+    let latest = now + chrono::Duration::seconds(ALLOWED_CLOCK_SKEW_SECONDS);
+
+    if produced_at > latest {
+        return Err(AppleCodesignError::OcspBadResponse(format!(
+            "response produced in the future ({produced_at})"
+        )));
+    }
+    if response.this_update > latest {
+        return Err(AppleCodesignError::OcspBadResponse(format!(
+            "status is for a time in the future ({})",
+            response.this_update
+        )));
+    }
+    if let Some(next_update) = response.next_update {
+        if next_update < now {
+            return Err(AppleCodesignError::OcspBadResponse(format!(
+                "response expired at {next_update}"
+            )));
+        }
+    }
+
+    Ok(response)
+}
+
+/// The `producedAt` time and single responses of a `ResponseData`.
+type ResponseData = (chrono::DateTime<chrono::Utc>, Vec<(CertId, SingleResponse)>);
+
+/// Decode the single responses in a `ResponseData`.
+///
+/// ```ASN.1
+/// ResponseData ::= SEQUENCE {
+///   version              [0] EXPLICIT Version DEFAULT v1,
+///   responderID              ResponderID,
+///   producedAt               GeneralizedTime,
+///   responses                SEQUENCE OF SingleResponse,
+///   responseExtensions   [1] EXPLICIT Extensions OPTIONAL }
+///
+/// SingleResponse ::= SEQUENCE {
+///   certID                       CertID,
+///   certStatus                   CertStatus,
+///   thisUpdate                   GeneralizedTime,
+///   nextUpdate         [0]       EXPLICIT GeneralizedTime OPTIONAL,
+///   singleExtensions   [1]       EXPLICIT Extensions OPTIONAL }
+///
+/// CertStatus ::= CHOICE {
+///   good        [0]     IMPLICIT NULL,
+///   revoked     [1]     IMPLICIT RevokedInfo,
+///   unknown     [2]     IMPLICIT UnknownInfo }
+///
+/// RevokedInfo ::= SEQUENCE {
+///   revocationTime              GeneralizedTime,
+///   revocationReason    [0]     EXPLICIT CRLReason OPTIONAL }
+/// ```
+fn take_response_data<S: Source>(
+    cons: &mut Constructed<S>,
+) -> Result<ResponseData, DecodeError<S::Error>> {
+    cons.take_sequence(|cons| {
+        cons.take_opt_constructed_if(Tag::CTX_0, |cons| cons.skip_all())?;
+        // responderID
+        cons.take_value(|_, content| content.as_constructed()?.skip_all())?;
+        let produced_at = GeneralizedTime::take_from_allow_fractional_z(cons)?;
+
+        let responses = cons.take_sequence(|cons| {
+            let mut responses = vec![];
+
+            while let Some(response) = cons.take_opt_sequence(|cons| {
+                let cert_id = CertId::take_from(cons)?;
+
+                let status = cons.take_value(|tag, content| {
+                    if tag == Tag::CTX_0 {
+                        content.to_null()?;
+                        Ok(CertificateStatus::Good)
+                    } else if tag == Tag::CTX_1 {
+                        let cons = content.as_constructed()?;
+                        let time = GeneralizedTime::take_from_allow_fractional_z(cons)?;
+                        let reason = cons.take_opt_constructed_if(Tag::CTX_0, |cons| {
+                            cons.take_primitive_if(Tag::ENUMERATED, |prim| prim.to_u8())
+                        })?;
+
+                        Ok(CertificateStatus::Revoked {
+                            time: time.into(),
+                            reason,
+                        })
+                    } else if tag == Tag::CTX_2 {
+                        content.to_null()?;
+                        Ok(CertificateStatus::Unknown)
+                    } else {
+                        Err(content.content_err("unknown certificate status"))
+                    }
+                })?;
+
+                let this_update = GeneralizedTime::take_from_allow_fractional_z(cons)?;
+                let next_update = cons.take_opt_constructed_if(Tag::CTX_0, |cons| {
+                    GeneralizedTime::take_from_allow_fractional_z(cons)
+                })?;
+                cons.take_opt_constructed_if(Tag::CTX_1, |cons| cons.skip_all())?;
+
+                Ok((
+                    cert_id,
+                    SingleResponse {
+                        status,
+                        this_update: this_update.into(),
+                        next_update: next_update.map(|time| time.into()),
+                    },
+                ))
+            })? {
+                responses.push(response);
+            }
+
+            Ok(responses)
+        })?;
+
+        cons.take_opt_constructed_if(Tag::CTX_1, |cons| cons.skip_all())?;
+
+        Ok((produced_at.into(), responses))
+    })
+}
+
+/// Whether a certificate is authorized to sign OCSP responses on behalf of its issuer.
+fn has_ocsp_signing_usage(cert: &CapturedX509Certificate) -> bool {
+    cert.iter_extensions()
+        .filter(|ext| ext.id == OID_EXTENDED_KEY_USAGE)
+        .any(|ext| {
+            Constructed::decode(ext.value.to_bytes(), Mode::Der, |cons| {
+                cons.take_sequence(|cons| {
+                    let mut found = false;
+                    while let Some(oid) = Oid::take_opt_from(cons)? {
+                        found |= oid == OID_EKU_OCSP_SIGNING;
+                    }
+
+                    Ok(found)
+                })
+            })
+            .unwrap_or(false)
+        })
+}
+
+/// Obtain the OCSP responder URLs advertised by a certificate.
+pub fn ocsp_responder_urls(cert: &CapturedX509Certificate) -> Vec<String> {
+    cert.iter_extensions()
+        .filter(|ext| ext.id == OID_AUTHORITY_INFO_ACCESS)
+        .filter_map(|ext| {
+            Constructed::decode(ext.value.to_bytes(), Mode::Der, |cons| {
+                cons.take_sequence(|cons| {
+                    let mut urls = vec![];
+
+                    // accessLocation is a GeneralName. The URI variant is an IMPLICIT
+                    // IA5String, which GeneralName's decoder doesn't handle.
+                    while let Some((method, location)) = cons.take_opt_sequence(|cons| {
+                        let method = Oid::take_from(cons)?;
+                        let location = cons.take_value(|tag, content| match content {
+                            Content::Primitive(prim) if tag == Tag::CTX_6 => {
+                                Ok(Some(prim.take_all()?))
+                            }
+                            Content::Primitive(prim) => prim.skip_all().map(|_| None),
+                            Content::Constructed(cons) => cons.skip_all().map(|_| None),
+                        })?;
+
+                        Ok((method, location))
+                    })? {
+                        if let (true, Some(url)) = (method == OID_AD_OCSP, location) {
+                            urls.push(String::from_utf8_lossy(&url).to_string());
+                        }
+                    }
+
+                    Ok(urls)
+                })
+            })
+            .ok()
+        })
+        .flatten()
+        .collect()
+}
+
+/// Checks the revocation status of certificates by querying OCSP responders.
+///
+/// Responses are cached for the lifetime of the instance, or until they expire
+/// if the responder specifies when.
+pub struct OcspChecker {
+    client: Client,
+    strict: bool,
+    cache: Mutex<HashMap<Vec<u8>, SingleResponse>>,
+}
+
+impl OcspChecker {
+    /// Construct a new instance using the default HTTP client.
+    ///
+    /// The instance is in soft-fail mode.
+    pub fn new() -> Result<Self, AppleCodesignError> {
+        Ok(Self::with_client(default_client()?))
+    }
+
+    /// Construct a new instance using a given HTTP client.
+    pub fn with_client(client: Client) -> Self {
+        Self {
+            client,
+            strict: false,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Set whether failures to obtain a revocation status are errors.
+    ///
+    /// When disabled (the default), [Self::check_chain()] logs such failures
+    /// and treats the certificate as not revoked.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Whether failures to obtain a revocation status are errors.
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Obtain the revocation status of `cert`, issued by `issuer`.
+    pub fn check_certificate(
+        &self,
+        cert: &CapturedX509Certificate,
+        issuer: &CapturedX509Certificate,
+    ) -> Result<CertificateStatus, AppleCodesignError> {
+        let name = cert
+            .subject_common_name()
+            .unwrap_or_else(|| "<unknown>".into());
+        let cert_id = CertId::new(cert, issuer)?;
+        let request = cert_id.request_der();
+
+        if let Some(response) = self
+            .cache
+            .lock()
+            .map_err(|_| AppleCodesignError::PoisonedLock)?
+            .get(&request)
+        {
+            if response
+                .next_update
+                .map_or(true, |next| next > chrono::Utc::now())
+            {
+                debug!("using cached OCSP response for {}", name);
+                return Ok(response.status.clone());
+            }
+        }
+
+        let url = ocsp_responder_urls(cert)
+            .into_iter()
+            .next()
+            .ok_or_else(|| AppleCodesignError::OcspNoResponder(name.clone()))?;
+
+        warn!("checking revocation status of {} via {}", name, url);
+        let response = self
+            .client
+            .post(&url)
+            .header("Content-Type", "application/ocsp-request")
+            .header("Accept", "application/ocsp-response")
+            .body(request.clone())
+            .send()?
+            .error_for_status()?
+            .bytes()?;
+
+        let mut response = parse_response(&response, &cert_id, issuer)?;
+        if response.next_update.is_none() {
+            response.next_update = Some(
+                chrono::Utc::now() + chrono::Duration::seconds(DEFAULT_CACHE_DURATION_SECONDS),
+            );
+        }
+
+        let status = response.status.clone();
+        self.cache
+            .lock()
+            .map_err(|_| AppleCodesignError::PoisonedLock)?
+            .insert(request, response);
+
+        Ok(status)
+    }
+
+    /// Check the revocation status of each certificate in a chain.
+    ///
+    /// The chain is leaf first, with each certificate followed by its issuer.
+    /// The final certificate, normally a root CA, isn't checked. Certificates
+    /// not advertising an OCSP responder are skipped.
+    ///
+    /// Returns the status of each checked certificate. In soft-fail mode,
+    /// certificates whose status couldn't be obtained are omitted. In strict
+    /// mode, this is an error.
+    pub fn check_chain<'a>(
+        &self,
+        chain: &'a [CapturedX509Certificate],
+    ) -> Result<Vec<(&'a CapturedX509Certificate, CertificateStatus)>, AppleCodesignError> {
+        let mut statuses = vec![];
+
+        for pair in chain.windows(2) {
+            let (cert, issuer) = (&pair[0], &pair[1]);
+
+            if ocsp_responder_urls(cert).is_empty() {
+                continue;
+            }
+
+            match self.check_certificate(cert, issuer) {
+                Ok(status) => statuses.push((cert, status)),
+                Err(e) if self.strict => return Err(e),
+                Err(e) => {
+                    warn!(
+                        "unable to check revocation status of {}: {}",
+                        cert.subject_common_name()
+                            .unwrap_or_else(|| "<unknown>".into()),
+                        e
+                    );
+                }
+            }
+        }
+
+        Ok(statuses)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::certificate::{create_self_signed_code_signing_certificate, CertificateProfile},
+        bcder::encode::Values,
+        chrono::TimeZone,
+        x509_certificate::{EcdsaCurve, InMemorySigningKeyPair, KeyAlgorithm, KeyInfoSigner},
+    };
+
+    fn generalized_time(time: chrono::DateTime<chrono::Utc>) -> impl Values {
+        OctetString::encode_slice_as(
+            time.format("%Y%m%d%H%M%SZ").to_string().into_bytes(),
+            Tag::GENERALIZED_TIME,
+        )
+    }
+
+    fn certificate() -> (CapturedX509Certificate, InMemorySigningKeyPair) {
+        let (cert, key, _) = create_self_signed_code_signing_certificate(
+            KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1),
+            CertificateProfile::DeveloperIdApplication,
+            "team1",
+            "Joe Developer",
+            "US",
+            chrono::Duration::hours(1),
+        )
+        .unwrap();
+
+        (cert, key)
+    }
+
+    /// Produce a basic OCSP response holding a single response signed by `signer`.
+    fn response(
+        cert_id: &CertId,
+        status: &CertificateStatus,
+        signer: &dyn KeyInfoSigner,
+    ) -> Vec<u8> {
+        response_with_times(cert_id, status, signer, chrono::Utc::now(), None)
+    }
+
+    fn response_with_times(
+        cert_id: &CertId,
+        status: &CertificateStatus,
+        signer: &dyn KeyInfoSigner,
+        this_update: chrono::DateTime<chrono::Utc>,
+        next_update: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Vec<u8> {
+        let now = chrono::Utc::now();
+
+        let status = match status {
+            CertificateStatus::Good => bcder::encode::Choice3::One(().encode_as(Tag::CTX_0)),
+            CertificateStatus::Revoked { time, .. } => bcder::encode::Choice3::Two(
+                bcder::encode::sequence_as(Tag::CTX_1, generalized_time(*time)),
+            ),
+            CertificateStatus::Unknown => bcder::encode::Choice3::Three(().encode_as(Tag::CTX_2)),
+        };
+
+        let tbs = bcder::encode::sequence((
+            bcder::encode::sequence_as(Tag::CTX_2, OctetString::encode_slice(b"key".as_ref())),
+            generalized_time(now),
+            bcder::encode::sequence(bcder::encode::sequence((
+                cert_id.encode_ref(),
+                status,
+                generalized_time(this_update),
+                next_update
+                    .map(|time| bcder::encode::sequence_as(Tag::CTX_0, generalized_time(time))),
+            ))),
+        ))
+        .to_captured(Mode::Der);
+
+        let signature = signer.try_sign(tbs.as_slice()).unwrap();
+        let algorithm: AlgorithmIdentifier = signer.signature_algorithm().unwrap().into();
+
+        let basic = bcder::encode::sequence((
+            &tbs,
+            &algorithm,
+            BitString::new(0, Bytes::from(Vec::<u8>::from(signature))).encode(),
+        ))
+        .to_captured(Mode::Der);
+
+        let response = bcder::encode::sequence((
+            0u8.encode_as(Tag::ENUMERATED),
+            bcder::encode::sequence_as(
+                Tag::CTX_0,
+                bcder::encode::sequence((
+                    Oid(Bytes::copy_from_slice(OID_OCSP_BASIC.as_ref())).encode(),
+                    OctetString::encode_slice(basic.as_slice()),
+                )),
+            ),
+        ))
+        .to_captured(Mode::Der);
+
+        response.into_bytes().to_vec()
+    }
+
+    #[test]
+    fn parse_responses() -> Result<(), AppleCodesignError> {
+        let (issuer, issuer_key) = certificate();
+        let (cert, other_key) = certificate();
+        let cert_id = CertId::new(&cert, &issuer)?;
+
+        let revoked = CertificateStatus::Revoked {
+            time: chrono::Utc.timestamp_opt(1_600_000_000, 0).unwrap(),
+            reason: None,
+        };
+
+        for status in [CertificateStatus::Good, revoked, CertificateStatus::Unknown] {
+            let data = response(&cert_id, &status, &issuer_key);
+            let response = parse_response(&data, &cert_id, &issuer)?;
+            assert_eq!(response.status, status);
+            assert!(response.next_update.is_none());
+        }
+
+        // Responses must be signed by the issuer.
+        let data = response(&cert_id, &CertificateStatus::Good, &other_key);
+        assert!(matches!(
+            parse_response(&data, &cert_id, &issuer),
+            Err(AppleCodesignError::OcspSignatureInvalid)
+        ));
+
+        // And must cover the requested certificate.
+        let other_id = CertId::new(&cert, &cert)?;
+        let data = response(&other_id, &CertificateStatus::Good, &issuer_key);
+        assert!(matches!(
+            parse_response(&data, &cert_id, &issuer),
+            Err(AppleCodesignError::OcspBadResponse(_))
+        ));
+
+        // Statuses must be current.
+        let hour = chrono::Duration::hours(1);
+        let data = response_with_times(
+            &cert_id,
+            &CertificateStatus::Good,
+            &issuer_key,
+            chrono::Utc::now() - hour,
+            Some(chrono::Utc::now() + hour),
+        );
+        let response = parse_response(&data, &cert_id, &issuer)?;
+        assert!(response.next_update.is_some());
+
+        let data = response_with_times(
+            &cert_id,
+            &CertificateStatus::Good,
+            &issuer_key,
+            chrono::Utc::now() - hour * 2,
+            Some(chrono::Utc::now() - hour),
+        );
+        assert!(matches!(
+            parse_response(&data, &cert_id, &issuer),
+            Err(AppleCodesignError::OcspBadResponse(e)) if e.contains("expired")
+        ));
+
+        let data = response_with_times(
+            &cert_id,
+            &CertificateStatus::Good,
+            &issuer_key,
+            chrono::Utc::now() + hour,
+            None,
+        );
+        assert!(matches!(
+            parse_response(&data, &cert_id, &issuer),
+            Err(AppleCodesignError::OcspBadResponse(e)) if e.contains("future")
+        ));
+
+        // Small differences between clocks are tolerated.
+        let data = response_with_times(
+            &cert_id,
+            &CertificateStatus::Good,
+            &issuer_key,
+            chrono::Utc::now() + chrono::Duration::seconds(60),
+            None,
+        );
+        parse_response(&data, &cert_id, &issuer)?;
+
+        // Unsuccessful responses are errors.
+        assert!(matches!(
+            parse_response(&[0x30, 0x03, 0x0a, 0x01, 0x06], &cert_id, &issuer),
+            Err(AppleCodesignError::OcspResponseStatus(6))
+        ));
+
+        // Certificates without responders aren't checked.
+        assert!(ocsp_responder_urls(&cert).is_empty());
+        let checker = OcspChecker::with_client(Client::new());
+        assert!(checker.check_chain(&[cert, issuer])?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn responder_urls() {
+        let cert = CapturedX509Certificate::from_der(
+            include_bytes!("testdata/apple-signed-developer-id-application.cer").to_vec(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            ocsp_responder_urls(&cert),
+            vec!["http://ocsp.apple.com/ocsp03-devid06".to_string()]
+        );
+    }
+}
@@ -21,6 +21,7 @@
 
 use {
     crate::{
+        apple_certificates::KnownCertificate,
         certificate::{
             AppleCertificate, CertificateAuthorityExtension, CertificateProfile,
             CodeSigningCertificateExtension,
@@ -28,11 +29,128 @@ use {
         code_requirement::{CodeRequirementExpression, CodeRequirementMatchExpression},
         error::AppleCodesignError,
     },
+    bcder::Oid,
     once_cell::sync::Lazy,
     std::ops::Deref,
-    x509_certificate::CapturedX509Certificate,
+    x509_certificate::{rfc4519::OID_ORGANIZATION_NAME, CapturedX509Certificate},
 };
 
+/// `anchor apple`
+///
+/// Code signed by Apple itself, such as operating system components.
+pub fn requirement_anchor_apple() -> CodeRequirementExpression<'static> {
+    CodeRequirementExpression::AnchorApple
+}
+
+/// `anchor apple generic`
+///
+/// Code signed with any certificate chaining to Apple's root CA, including
+/// certificates Apple issues to developers.
+pub fn requirement_anchor_apple_generic() -> CodeRequirementExpression<'static> {
+    CodeRequirementExpression::AnchorAppleGeneric
+}
+
+/// Code distributed through the Mac App Store.
+///
+/// `anchor apple generic and certificate leaf[field.1.2.840.113635.100.6.1.9] exists`
+pub fn requirement_mac_app_store() -> CodeRequirementExpression<'static> {
+    CodeRequirementExpression::And(
+        Box::new(requirement_anchor_apple_generic()),
+        Box::new(CodeRequirementExpression::CertificateGeneric(
+            0,
+            CodeSigningCertificateExtension::AppleMacAppStoreCodeSigning.as_oid(),
+            CodeRequirementMatchExpression::Exists,
+        )),
+    )
+}
+
+/// Code signed with a Developer ID Application certificate.
+///
+/// `anchor apple generic and certificate 1[field.1.2.840.113635.100.6.2.6] exists and
+/// certificate leaf[field.1.2.840.113635.100.6.1.13] exists`
+///
+/// If `team_id` is given, the certificate must also belong to that team:
+/// `and certificate leaf[subject.OU] = <team_id>`. This is the form used in
+/// designated requirements.
+pub fn requirement_developer_id(team_id: Option<&str>) -> CodeRequirementExpression<'static> {
+    // A certificate entrusted with Developer ID Application signing rights.
+    let leaf = CodeRequirementExpression::CertificateGeneric(
+        0,
+        CodeSigningCertificateExtension::DeveloperIdApplication.as_oid(),
+        CodeRequirementMatchExpression::Exists,
+    );
+
+    let leaf = if let Some(team_id) = team_id {
+        CodeRequirementExpression::And(
+            Box::new(leaf),
+            // Signed by this team ID.
+            Box::new(CodeRequirementExpression::CertificateField(
+                0,
+                "subject.OU".to_string().into(),
+                CodeRequirementMatchExpression::Equal(team_id.to_string().into()),
+            )),
+        )
+    } else {
+        leaf
+    };
+
+    CodeRequirementExpression::And(
+        // Chains to Apple root CA.
+        Box::new(requirement_anchor_apple_generic()),
+        Box::new(CodeRequirementExpression::And(
+            // Certificate issued by CA with Developer ID extension.
+            Box::new(CodeRequirementExpression::CertificateGeneric(
+                1,
+                CertificateAuthorityExtension::DeveloperId.as_oid(),
+                CodeRequirementMatchExpression::Exists,
+            )),
+            Box::new(leaf),
+        )),
+    )
+}
+
+/// Notarized code signed with a Developer ID Application certificate.
+///
+/// `<developer id> and notarized`, where `<developer id>` is
+/// [requirement_developer_id()].
+///
+/// Code signed before Apple required notarization isn't notarized. With
+/// `legacy_fallback`, such code is also accepted, as Gatekeeper does:
+/// `<developer id> and (notarized or legacy)`.
+pub fn requirement_notarized_developer_id(
+    team_id: Option<&str>,
+    legacy_fallback: bool,
+) -> CodeRequirementExpression<'static> {
+    let notarized = if legacy_fallback {
+        CodeRequirementExpression::Or(
+            Box::new(CodeRequirementExpression::Notarized),
+            Box::new(CodeRequirementExpression::LegacyDeveloperId),
+        )
+    } else {
+        CodeRequirementExpression::Notarized
+    };
+
+    CodeRequirementExpression::And(
+        Box::new(requirement_developer_id(team_id)),
+        Box::new(notarized),
+    )
+}
+
+/// Restrict a requirement to code with the given signing identifier.
+///
+/// `identifier "<identifier>" and <expr>`
+pub fn requirement_with_identifier(
+    identifier: impl ToString,
+    expr: CodeRequirementExpression<'static>,
+) -> CodeRequirementExpression<'static> {
+    CodeRequirementExpression::And(
+        Box::new(CodeRequirementExpression::Identifier(
+            identifier.to_string().into(),
+        )),
+        Box::new(expr),
+    )
+}
+
 /// Code signing requirement for Mac Developer ID.
 ///
 /// `anchor apple generic and certificate 1[field.1.2.840.113635.100.6.2.6] exists and
@@ -182,7 +300,11 @@ impl TryFrom<&str> for ExecutionPolicy {
 
 #[cfg(test)]
 mod test {
-    use super::*;
+    use {
+        super::*,
+        crate::certificate::create_self_signed_code_signing_certificate,
+        x509_certificate::{EcdsaCurve, KeyAlgorithm, X509CertificateBuilder},
+    };
 
     #[test]
     fn get_policies() {
@@ -194,6 +316,111 @@ mod test {
             .to_bytes()
             .unwrap();
     }
+
+    #[test]
+    fn well_known_requirements() {
+        assert_eq!(
+            requirement_mac_app_store().to_string(),
+            "(anchor apple generic) and \
+            (certificate leaf[field.1.2.840.113635.100.6.1.9] /* exists */)"
+        );
+        assert_eq!(
+            requirement_with_identifier(
+                "com.example.app",
+                requirement_notarized_developer_id(Some("team1"), true)
+            )
+            .to_string(),
+            "(identifier \"com.example.app\") and (((anchor apple generic) and \
+            ((certificate 1[field.1.2.840.113635.100.6.2.6] /* exists */) and \
+            ((certificate leaf[field.1.2.840.113635.100.6.1.13] /* exists */) and \
+            (certificate leaf[subject.OU] = \"team1\")))) and ((notarized) or (legacy)))"
+        );
+
+        for expr in [
+            requirement_anchor_apple_generic(),
+            requirement_mac_app_store(),
+            requirement_notarized_developer_id(None, false),
+        ] {
+            let data = expr.to_bytes().unwrap();
+            let (parsed, remaining) = CodeRequirementExpression::from_bytes(&data).unwrap();
+            assert_eq!(parsed, expr);
+            assert!(remaining.is_empty());
+        }
+    }
+
+    #[test]
+    fn derive_from_chain() -> Result<(), AppleCodesignError> {
+        // Certificates with a known profile use the profile's policy.
+        let (cert, _, _) = create_self_signed_code_signing_certificate(
+            KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1),
+            CertificateProfile::DeveloperIdApplication,
+            "team1",
+            "Joe Developer",
+            "US",
+            chrono::Duration::hours(1),
+        )?;
+        assert_eq!(
+            derive_designated_requirements_from_chain(&cert, &[], Some("com.example".into()))?,
+            derive_designated_requirements(&cert, Some("com.example".into()))?
+        );
+
+        // Certificates issued by Apple without a known profile are tied to their
+        // team, not just to any certificate issued by Apple. An Apple intermediate
+        // stands in for such a certificate.
+        let cert = KnownCertificate::WwdrG3.deref().clone();
+        assert!(cert.apple_guess_profile().is_none());
+        assert!(cert.chains_to_apple_root_ca());
+        let team_id = cert.apple_team_id().unwrap();
+
+        assert_eq!(
+            derive_designated_requirements_from_chain(&cert, &[], Some("com.example".into()))?
+                .unwrap()
+                .to_requirement_source(),
+            format!(
+                "identifier \"com.example\" and (anchor apple generic and \
+                certificate leaf[subject.OU] = {team_id})"
+            )
+        );
+
+        // Other certificates are pinned by hash. The root is pinned when it shares
+        // the organization of the signing certificate.
+        let mut builder = X509CertificateBuilder::new(KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1));
+        builder
+            .subject()
+            .append_common_name_utf8_string("Example Signer")
+            .unwrap();
+        builder
+            .subject()
+            .append_organization_utf8_string("Example Org")
+            .unwrap();
+        let cert = builder.create_with_random_keypair()?.0;
+        let hash = hex::encode(cert.sha1_fingerprint()?);
+
+        assert_eq!(
+            derive_designated_requirements_from_chain(&cert, &[], Some("com.example".into()))?
+                .unwrap()
+                .to_requirement_source(),
+            format!("identifier \"com.example\" and certificate root = H\"{hash}\"")
+        );
+
+        // Without an organization, the signing certificate itself is pinned.
+        let mut builder = X509CertificateBuilder::new(KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1));
+        builder
+            .subject()
+            .append_common_name_utf8_string("Example Signer")
+            .unwrap();
+        let cert = builder.create_with_random_keypair()?.0;
+        let hash = hex::encode(cert.sha1_fingerprint()?);
+
+        assert_eq!(
+            derive_designated_requirements_from_chain(&cert, &[], None)?
+                .unwrap()
+                .to_requirement_source(),
+            format!("certificate leaf = H\"{hash}\"")
+        );
+
+        Ok(())
+    }
 }
 
 /// Derive a designated requirements expression given a code signing certificate.
@@ -225,7 +452,7 @@ pub fn derive_designated_requirements(
 
             let expr = CodeRequirementExpression::And(
                 // It chains to Apple root CA.
-                Box::new(CodeRequirementExpression::AnchorAppleGeneric),
+                Box::new(requirement_anchor_apple_generic()),
                 Box::new(CodeRequirementExpression::And(
                     // It was signed by this cert.
                     Box::new(CodeRequirementExpression::CertificateField(
@@ -243,10 +470,7 @@ pub fn derive_designated_requirements(
             );
 
             Ok(Some(if let Some(identifier) = identifier {
-                CodeRequirementExpression::And(
-                    Box::new(CodeRequirementExpression::Identifier(identifier.into())),
-                    Box::new(expr),
-                )
+                requirement_with_identifier(identifier, expr)
             } else {
                 expr
             }))
@@ -258,38 +482,10 @@ pub fn derive_designated_requirements(
                 ))
             })?;
 
-            let expr = CodeRequirementExpression::And(
-                // Chains to Apple root CA.
-                Box::new(CodeRequirementExpression::AnchorAppleGeneric),
-                Box::new(CodeRequirementExpression::And(
-                    // Certificate issued by CA with Developer ID extension.
-                    Box::new(CodeRequirementExpression::CertificateGeneric(
-                        1,
-                        CertificateAuthorityExtension::DeveloperId.as_oid(),
-                        CodeRequirementMatchExpression::Exists,
-                    )),
-                    Box::new(CodeRequirementExpression::And(
-                        // A certificate entrusted with Developer ID Application signing rights.
-                        Box::new(CodeRequirementExpression::CertificateGeneric(
-                            0,
-                            CodeSigningCertificateExtension::DeveloperIdApplication.as_oid(),
-                            CodeRequirementMatchExpression::Exists,
-                        )),
-                        // Signed by this team ID.
-                        Box::new(CodeRequirementExpression::CertificateField(
-                            0,
-                            "subject.OU".to_string().into(),
-                            CodeRequirementMatchExpression::Equal(team_id.into()),
-                        )),
-                    )),
-                )),
-            );
+            let expr = requirement_developer_id(Some(&team_id));
 
             Ok(Some(if let Some(identifier) = identifier {
-                CodeRequirementExpression::And(
-                    Box::new(CodeRequirementExpression::Identifier(identifier.into())),
-                    Box::new(expr),
-                )
+                requirement_with_identifier(identifier, expr)
             } else {
                 expr
             }))
@@ -301,3 +497,85 @@ pub fn derive_designated_requirements(
         }
     }
 }
+
+/// Derive a designated requirements expression given a signing certificate and its chain.
+///
+/// This is like [derive_designated_requirements()] except it also handles
+/// certificates we don't know a signing policy for, mirroring what `codesign`
+/// does by default:
+///
+/// * Certificates chaining to an Apple root CA require `anchor apple generic`
+///   and the team identifier (subject OU) of the signing certificate.
+/// * Other certificates require the SHA-1 hash of the highest certificate in
+///   the chain having the same organization (O) as the signing certificate.
+///   If the entire chain shares the organization, this is the root. If the
+///   signing certificate has no organization, it is pinned itself.
+///
+/// `chain` holds additional certificates to resolve the issuing chain from.
+/// Apple's known certificates are always considered.
+pub fn derive_designated_requirements_from_chain(
+    cert: &CapturedX509Certificate,
+    chain: &[CapturedX509Certificate],
+    identifier: Option<String>,
+) -> Result<Option<CodeRequirementExpression<'static>>, AppleCodesignError> {
+    if cert.apple_guess_profile().is_some() {
+        return derive_designated_requirements(cert, identifier);
+    }
+
+    let mut certs = vec![cert];
+    certs.extend(
+        cert.resolve_signing_chain(chain.iter().chain(KnownCertificate::all().iter().copied())),
+    );
+
+    let expr = if certs.last().map(|x| x.is_apple_root_ca()) == Some(true) {
+        let team_id = cert.apple_team_id().ok_or_else(|| {
+            AppleCodesignError::PolicyFormulationError(
+                "could not find team identifier in signing certificate".into(),
+            )
+        })?;
+
+        CodeRequirementExpression::And(
+            Box::new(requirement_anchor_apple_generic()),
+            Box::new(CodeRequirementExpression::CertificateField(
+                0,
+                "subject.OU".to_string().into(),
+                CodeRequirementMatchExpression::Equal(team_id.into()),
+            )),
+        )
+    } else {
+        let organization = |cert: &CapturedX509Certificate| {
+            cert.subject_name()
+                .find_first_attribute_string(Oid(OID_ORGANIZATION_NAME.as_ref().into()))
+                .unwrap_or(None)
+        };
+
+        let mut index = 0;
+        let slot = if let Some(leaf_organization) = organization(cert) {
+            while index + 1 < certs.len()
+                && organization(certs[index + 1]).as_ref() == Some(&leaf_organization)
+            {
+                index += 1;
+            }
+
+            // A slot of -1 denotes the root certificate.
+            if index == certs.len() - 1 {
+                -1
+            } else {
+                index as i32
+            }
+        } else {
+            0
+        };
+
+        CodeRequirementExpression::AnchorCertificateHash(
+            slot,
+            certs[index].sha1_fingerprint()?.as_ref().to_vec().into(),
+        )
+    };
+
+    Ok(Some(if let Some(identifier) = identifier {
+        requirement_with_identifier(identifier, expr)
+    } else {
+        expr
+    }))
+}
@@ -0,0 +1,631 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Provisioning profiles.
+//!
+//! A provisioning profile authorizes a team to sign applications with an
+//! application identifier and a set of entitlements. Devices refuse to launch
+//! (and often to install) applications whose signature requests entitlements
+//! the embedded profile doesn't grant. This module checks entitlements against
+//! a profile so such problems can be reported before signing.
+//!
+//! Profiles (`.mobileprovision` and `.provisionprofile` files) are a plist
+//! wrapped in a CMS signature issued by Apple.
+
+use {
+    crate::{
+        certificate::AppleCertificate, error::AppleCodesignError, verify::signer_certificate_chain,
+    },
+    chrono::{DateTime, Utc},
+    cryptographic_message_syntax::SignedData,
+    plist::{Dictionary, Value},
+    std::path::Path,
+    x509_certificate::CapturedX509Certificate,
+};
+
+/// Entitlements keys holding the application identifier.
+///
+/// iOS profiles use the former. macOS profiles use the latter.
+const APPLICATION_IDENTIFIER_KEYS: &[&str] =
+    &["application-identifier", "com.apple.application-identifier"];
+
+/// Entitlement key prefix of entitlements not requiring a provisioning profile.
+///
+/// These are the sandbox and hardened runtime entitlements on macOS.
+const UNRESTRICTED_PREFIX: &str = "com.apple.security.";
+
+/// Entitlement key of app groups.
+///
+/// It is in the [UNRESTRICTED_PREFIX] namespace but `group.` identifiers, which
+/// are the only kind iOS supports, must be granted by the profile. Only macOS
+/// groups prefixed with the team identifier are unrestricted.
+const APPLICATION_GROUPS_KEY: &str = "com.apple.security.application-groups";
+
+/// A provisioning profile.
+#[derive(Clone, Debug)]
+pub struct ProvisioningProfile {
+    plist: Dictionary,
+    /// The CMS data the profile was parsed from.
+    cms: Option<Vec<u8>>,
+}
+
+impl ProvisioningProfile {
+    /// Construct an instance from the plist dictionary defining the profile.
+    pub fn from_dictionary(plist: Dictionary) -> Result<Self, AppleCodesignError> {
+        if !matches!(plist.get("Entitlements"), Some(Value::Dictionary(_))) {
+            return Err(AppleCodesignError::ProvisioningProfileMalformed(
+                "Entitlements isn't a dictionary".into(),
+            ));
+        }
+
+        Ok(Self { plist, cms: None })
+    }
+
+    /// Construct an instance from the CMS signed data of a profile file.
+    ///
+    /// The CMS signature isn't verified. Use [Self::verify_signature()] for that.
+    pub fn from_der(data: &[u8]) -> Result<Self, AppleCodesignError> {
+        let signed_data = SignedData::parse_ber(data)?;
+
+        let content = signed_data.signed_content().ok_or_else(|| {
+            AppleCodesignError::ProvisioningProfileMalformed("CMS data has no content".into())
+        })?;
+
+        let mut profile = Self::from_plist_data(content)?;
+        profile.cms = Some(data.to_vec());
+
+        Ok(profile)
+    }
+
+    /// Construct an instance from a `.mobileprovision` or `.provisionprofile` file.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, AppleCodesignError> {
+        Self::from_der(&std::fs::read(path.as_ref())?)
+    }
+
+    /// Construct an instance from plist data defining the profile.
+    pub fn from_plist_data(data: &[u8]) -> Result<Self, AppleCodesignError> {
+        let plist =
+            plist::from_bytes::<Dictionary>(data).map_err(AppleCodesignError::PlistParseXml)?;
+
+        Self::from_dictionary(plist)
+    }
+
+    /// The plist dictionary defining the profile.
+    pub fn plist(&self) -> &Dictionary {
+        &self.plist
+    }
+
+    /// The name of the profile.
+    pub fn name(&self) -> Option<&str> {
+        self.plist.get("Name").and_then(|v| v.as_string())
+    }
+
+    /// The UUID of the profile.
+    pub fn uuid(&self) -> Option<&str> {
+        self.plist.get("UUID").and_then(|v| v.as_string())
+    }
+
+    /// The name of the team the profile was issued to.
+    pub fn team_name(&self) -> Option<&str> {
+        self.plist.get("TeamName").and_then(|v| v.as_string())
+    }
+
+    /// Team identifiers the profile was issued to.
+    pub fn team_identifiers(&self) -> Vec<&str> {
+        self.plist
+            .get("TeamIdentifier")
+            .and_then(|v| v.as_array())
+            .map(|array| array.iter().filter_map(|v| v.as_string()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Certificates allowed to sign with the profile.
+    pub fn certificates(&self) -> Result<Vec<CapturedX509Certificate>, AppleCodesignError> {
+        self.plist
+            .get("DeveloperCertificates")
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+            .map(|v| {
+                let der = v.as_data().ok_or_else(|| {
+                    AppleCodesignError::ProvisioningProfileMalformed(
+                        "DeveloperCertificates holds non-data value".into(),
+                    )
+                })?;
+
+                Ok(CapturedX509Certificate::from_der(der.to_vec())?)
+            })
+            .collect()
+    }
+
+    /// Identifiers of devices the profile allows running on.
+    ///
+    /// Distribution profiles and profiles provisioning all devices have none.
+    pub fn devices(&self) -> Vec<&str> {
+        self.plist
+            .get("ProvisionedDevices")
+            .and_then(|v| v.as_array())
+            .map(|array| array.iter().filter_map(|v| v.as_string()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Whether the profile allows running on all devices.
+    ///
+    /// This is the case for Developer ID and enterprise profiles.
+    pub fn provisions_all_devices(&self) -> bool {
+        matches!(
+            self.plist.get("ProvisionsAllDevices"),
+            Some(Value::Boolean(true))
+        )
+    }
+
+    /// When the profile was created.
+    pub fn creation_date(&self) -> Option<DateTime<Utc>> {
+        self.date("CreationDate")
+    }
+
+    /// When the profile expires.
+    pub fn expiration_date(&self) -> Option<DateTime<Utc>> {
+        self.date("ExpirationDate")
+    }
+
+    /// Whether the profile has expired.
+    pub fn is_expired(&self) -> bool {
+        self.expiration_date()
+            .map(|date| date < Utc::now())
+            .unwrap_or_default()
+    }
+
+    fn date(&self, key: &str) -> Option<DateTime<Utc>> {
+        self.plist
+            .get(key)
+            .and_then(|v| v.as_date())
+            .map(|date| std::time::SystemTime::from(date).into())
+    }
+
+    /// Verify the CMS signature of the profile was issued by Apple.
+    ///
+    /// The signature must be valid and the signing certificate must chain to
+    /// a known Apple root certificate authority. Profiles not constructed from
+    /// CMS data fail verification.
+    pub fn verify_signature(&self) -> Result<(), AppleCodesignError> {
+        self.verify_signature_with_anchor(|cert| cert.is_apple_root_ca())
+    }
+
+    /// Verify the CMS signature of the profile chains to an anchor.
+    ///
+    /// The chain is resolved from the certificates in the CMS data and
+    /// Apple's known certificates.
+    fn verify_signature_with_anchor(
+        &self,
+        is_anchor: impl Fn(&CapturedX509Certificate) -> bool,
+    ) -> Result<(), AppleCodesignError> {
+        let data = self.cms.as_ref().ok_or_else(|| {
+            AppleCodesignError::ProvisioningProfileUntrusted("profile has no CMS signature".into())
+        })?;
+
+        let signed_data = SignedData::parse_ber(data)?;
+
+        let signer = signed_data.signers().next().ok_or_else(|| {
+            AppleCodesignError::ProvisioningProfileUntrusted("CMS data has no signers".into())
+        })?;
+
+        signer.verify_signature_with_signed_data(&signed_data)?;
+
+        let chain = signer_certificate_chain(&signed_data, signer);
+
+        match (chain.first(), chain.last()) {
+            (Some(_), Some(anchor)) if is_anchor(anchor) => Ok(()),
+            (Some(cert), _) => Err(AppleCodesignError::ProvisioningProfileUntrusted(format!(
+                "signing certificate {} doesn't chain to an Apple root certificate authority",
+                cert.subject_common_name().unwrap_or_default()
+            ))),
+            (None, _) => Err(AppleCodesignError::ProvisioningProfileUntrusted(
+                "signing certificate not found in CMS data".into(),
+            )),
+        }
+    }
+
+    /// The entitlements the profile grants.
+    ///
+    /// String values may end in a `*` wildcard.
+    pub fn entitlements(&self) -> &Dictionary {
+        self.plist
+            .get("Entitlements")
+            .and_then(|v| v.as_dictionary())
+            .expect("entitlements validated by constructor")
+    }
+
+    /// The application identifier the profile grants.
+    ///
+    /// This is the team's application identifier prefix followed by a bundle
+    /// identifier that may end in a `*` wildcard. e.g. `ABCDE12345.com.example.*`.
+    pub fn application_identifier(&self) -> Option<&str> {
+        APPLICATION_IDENTIFIER_KEYS
+            .iter()
+            .find_map(|key| self.entitlements().get(key))
+            .and_then(|v| v.as_string())
+    }
+
+    /// Check that entitlements to sign with are granted by this profile.
+    ///
+    /// `identifier` is the bundle identifier being signed and `team_id` the
+    /// team of the signing certificate. Either is only checked if present.
+    ///
+    /// Entitlements in the `com.apple.security.` namespace don't require a
+    /// profile and are only checked if the profile has them. The exception is
+    /// app groups with `group.` identifiers.
+    ///
+    /// An empty vector means the profile grants the entitlements.
+    pub fn check_entitlements(
+        &self,
+        entitlements: &Value,
+        identifier: Option<&str>,
+        team_id: Option<&str>,
+    ) -> Vec<ProvisioningProfileIssue> {
+        let mut issues = vec![];
+
+        if let Some(team_id) = team_id {
+            if !self.team_identifiers().contains(&team_id) {
+                issues.push(ProvisioningProfileIssue::TeamMismatch(team_id.to_string()));
+            }
+        }
+
+        if let (Some(identifier), Some(application_identifier)) =
+            (identifier, self.application_identifier())
+        {
+            let bundle_pattern = application_identifier
+                .split_once('.')
+                .map(|(_, pattern)| pattern)
+                .unwrap_or(application_identifier);
+
+            if !wildcard_matches(bundle_pattern, identifier) {
+                issues.push(ProvisioningProfileIssue::IdentifierMismatch {
+                    identifier: identifier.to_string(),
+                    application_identifier: application_identifier.to_string(),
+                });
+            }
+        }
+
+        // Entitlements that aren't a dictionary are reported by entitlements validation.
+        let Some(entitlements) = entitlements.as_dictionary() else {
+            return issues;
+        };
+
+        let granted = self.entitlements();
+
+        for (key, requested) in entitlements {
+            match granted.get(key) {
+                Some(allowed) if value_allowed(allowed, requested) => {}
+                Some(_) => {
+                    issues.push(ProvisioningProfileIssue::EntitlementValueNotAllowed(
+                        key.clone(),
+                    ));
+                }
+                None if key == APPLICATION_GROUPS_KEY
+                    && requested.as_array().into_iter().flatten().any(
+                        |group| matches!(group.as_string(), Some(g) if g.starts_with("group.")),
+                    ) =>
+                {
+                    issues.push(ProvisioningProfileIssue::EntitlementNotAllowed(key.clone()));
+                }
+                None if key.starts_with(UNRESTRICTED_PREFIX) => {}
+                // Requesting an entitlement be disabled is always allowed.
+                None if matches!(requested, Value::Boolean(false)) => {}
+                None => {
+                    issues.push(ProvisioningProfileIssue::EntitlementNotAllowed(key.clone()));
+                }
+            }
+        }
+
+        issues
+    }
+}
+
+/// A way entitlements aren't granted by a [ProvisioningProfile].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ProvisioningProfileIssue {
+    /// The profile doesn't grant an entitlement.
+    EntitlementNotAllowed(String),
+    /// The profile grants an entitlement but not with the requested value.
+    EntitlementValueNotAllowed(String),
+    /// The signing team isn't a team of the profile.
+    TeamMismatch(String),
+    /// The identifier being signed doesn't match the profile's application identifier.
+    IdentifierMismatch {
+        identifier: String,
+        application_identifier: String,
+    },
+}
+
+impl std::fmt::Display for ProvisioningProfileIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EntitlementNotAllowed(key) => f.write_fmt(format_args!(
+                "entitlement {key} isn't granted by the provisioning profile"
+            )),
+            Self::EntitlementValueNotAllowed(key) => f.write_fmt(format_args!(
+                "value of entitlement {key} isn't granted by the provisioning profile"
+            )),
+            Self::TeamMismatch(team) => f.write_fmt(format_args!(
+                "signing team {team} isn't a team of the provisioning profile"
+            )),
+            Self::IdentifierMismatch {
+                identifier,
+                application_identifier,
+            } => f.write_fmt(format_args!(
+                "identifier {identifier} doesn't match provisioning profile application \
+                identifier {application_identifier}"
+            )),
+        }
+    }
+}
+
+/// Whether a value matches a pattern that may end in a `*` wildcard.
+fn wildcard_matches(pattern: &str, value: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => value.starts_with(prefix),
+        None => pattern == value,
+    }
+}
+
+/// Whether a profile's entitlement value grants a requested value.
+///
+/// Arrays in the profile list the values that may be requested. Requested
+/// arrays must have every member granted.
+fn value_allowed(allowed: &Value, requested: &Value) -> bool {
+    match (allowed, requested) {
+        (_, Value::Boolean(false)) => true,
+        (Value::String(allowed), Value::String(requested)) => wildcard_matches(allowed, requested),
+        (_, Value::Array(requested)) => requested.iter().all(|v| value_allowed(allowed, v)),
+        (Value::Array(allowed), requested) => allowed.iter().any(|v| value_allowed(v, requested)),
+        (allowed, requested) => allowed == requested,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::certificate::{create_self_signed_code_signing_certificate, CertificateProfile},
+        bcder::encode::Values,
+        cryptographic_message_syntax::{SignedDataBuilder, SignerBuilder},
+        x509_certificate::{
+            rfc5280, EcdsaCurve, InMemorySigningKeyPair, KeyAlgorithm, KeyInfoSigner,
+            X509Certificate, X509CertificateBuilder,
+        },
+    };
+
+    const PROFILE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Name</key>
+    <string>Example Development</string>
+    <key>TeamIdentifier</key>
+    <array>
+        <string>ABCDE12345</string>
+    </array>
+    <key>Entitlements</key>
+    <dict>
+        <key>application-identifier</key>
+        <string>ABCDE12345.com.example.*</string>
+        <key>com.apple.developer.team-identifier</key>
+        <string>ABCDE12345</string>
+        <key>keychain-access-groups</key>
+        <array>
+            <string>ABCDE12345.*</string>
+        </array>
+        <key>get-task-allow</key>
+        <true/>
+        <key>aps-environment</key>
+        <string>development</string>
+    </dict>
+</dict>
+</plist>"#;
+
+    /// Re-issue a certificate so it is signed by another certificate.
+    fn issue(
+        cert: CapturedX509Certificate,
+        issuer: &CapturedX509Certificate,
+        issuer_key: &dyn KeyInfoSigner,
+    ) -> CapturedX509Certificate {
+        let mut cert = rfc5280::Certificate::from(cert);
+        cert.tbs_certificate.issuer = issuer.subject_name().clone();
+        cert.tbs_certificate.raw_data = None;
+
+        let mut tbs = vec![];
+        cert.tbs_certificate
+            .encode_ref()
+            .write_encoded(bcder::Mode::Der, &mut tbs)
+            .unwrap();
+        let signature = issuer_key.try_sign(&tbs).unwrap();
+        cert.signature = bcder::BitString::new(0, Vec::<u8>::from(signature).into());
+
+        CapturedX509Certificate::try_from(X509Certificate::from(cert)).unwrap()
+    }
+
+    fn ca(name: &str) -> (CapturedX509Certificate, InMemorySigningKeyPair) {
+        let mut builder = X509CertificateBuilder::new(KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1));
+        builder
+            .subject()
+            .append_common_name_utf8_string(name)
+            .unwrap();
+        let (cert, key, _) = builder.create_with_random_keypair().unwrap();
+
+        (cert, key)
+    }
+
+    fn entitlements(xml: &str) -> Value {
+        Value::from_reader_xml(std::io::Cursor::new(format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict>{xml}</dict></plist>"#
+        )))
+        .unwrap()
+    }
+
+    #[test]
+    fn check_entitlements() -> Result<(), AppleCodesignError> {
+        let profile = ProvisioningProfile::from_plist_data(PROFILE.as_bytes())?;
+        assert_eq!(profile.name(), Some("Example Development"));
+        assert_eq!(profile.team_identifiers(), vec!["ABCDE12345"]);
+        assert_eq!(
+            profile.application_identifier(),
+            Some("ABCDE12345.com.example.*")
+        );
+
+        let granted = entitlements(
+            "<key>application-identifier</key><string>ABCDE12345.com.example.app</string>\
+            <key>keychain-access-groups</key><array><string>ABCDE12345.com.example.app</string></array>\
+            <key>get-task-allow</key><true/>\
+            <key>com.apple.security.app-sandbox</key><true/>\
+            <key>com.apple.security.application-groups</key><array><string>ABCDE12345.shared</string></array>\
+            <key>com.apple.developer.icloud-services</key><false/>",
+        );
+        assert!(profile
+            .check_entitlements(&granted, Some("com.example.app"), Some("ABCDE12345"))
+            .is_empty());
+
+        let denied = entitlements(
+            "<key>application-identifier</key><string>FGHIJ67890.com.example.app</string>\
+            <key>aps-environment</key><string>production</string>\
+            <key>com.apple.security.application-groups</key><array><string>group.com.example</string></array>\
+            <key>com.apple.developer.icloud-services</key><array><string>CloudKit</string></array>",
+        );
+        assert_eq!(
+            profile.check_entitlements(&denied, Some("org.example.app"), Some("FGHIJ67890")),
+            vec![
+                ProvisioningProfileIssue::TeamMismatch("FGHIJ67890".into()),
+                ProvisioningProfileIssue::IdentifierMismatch {
+                    identifier: "org.example.app".into(),
+                    application_identifier: "ABCDE12345.com.example.*".into(),
+                },
+                ProvisioningProfileIssue::EntitlementValueNotAllowed(
+                    "application-identifier".into()
+                ),
+                ProvisioningProfileIssue::EntitlementValueNotAllowed("aps-environment".into()),
+                ProvisioningProfileIssue::EntitlementNotAllowed(
+                    "com.apple.security.application-groups".into()
+                ),
+                ProvisioningProfileIssue::EntitlementNotAllowed(
+                    "com.apple.developer.icloud-services".into()
+                ),
+            ]
+        );
+
+        assert!(matches!(
+            ProvisioningProfile::from_dictionary(Dictionary::new()),
+            Err(AppleCodesignError::ProvisioningProfileMalformed(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_cms() -> Result<(), AppleCodesignError> {
+        let (cert, key, _) = create_self_signed_code_signing_certificate(
+            KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1),
+            CertificateProfile::AppleDevelopment,
+            "ABCDE12345",
+            "Joe Developer",
+            "US",
+            chrono::Duration::hours(1),
+        )?;
+
+        let mut plist = ProvisioningProfile::from_plist_data(PROFILE.as_bytes())?
+            .plist()
+            .clone();
+        plist.insert(
+            "DeveloperCertificates".into(),
+            Value::Array(vec![Value::Data(cert.encode_der()?)]),
+        );
+        plist.insert(
+            "ProvisionedDevices".into(),
+            Value::Array(vec!["00008030-000000000000002E".into()]),
+        );
+        plist.insert(
+            "ExpirationDate".into(),
+            Value::Date(std::time::SystemTime::UNIX_EPOCH.into()),
+        );
+
+        let mut content = vec![];
+        plist::to_writer_xml(&mut content, &plist)
+            .map_err(AppleCodesignError::PlistSerializeXml)?;
+
+        let cms = SignedDataBuilder::default()
+            .certificate(cert.clone())
+            .content_inline(content)
+            .signer(SignerBuilder::new(&key, cert.clone()))
+            .build_der()?;
+
+        let profile = ProvisioningProfile::from_der(&cms)?;
+        assert_eq!(profile.team_identifiers(), vec!["ABCDE12345"]);
+        assert_eq!(profile.certificates()?, vec![cert]);
+        assert_eq!(profile.devices(), vec!["00008030-000000000000002E"]);
+        assert!(!profile.provisions_all_devices());
+        assert_eq!(
+            profile.expiration_date(),
+            Some(DateTime::<Utc>::from(std::time::SystemTime::UNIX_EPOCH))
+        );
+        assert!(profile.is_expired());
+
+        // Self-signed profiles aren't issued by Apple.
+        assert!(matches!(
+            profile.verify_signature(),
+            Err(AppleCodesignError::ProvisioningProfileUntrusted(_))
+        ));
+        assert!(matches!(
+            ProvisioningProfile::from_plist_data(PROFILE.as_bytes())?.verify_signature(),
+            Err(AppleCodesignError::ProvisioningProfileUntrusted(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn verify_signature_chain() -> Result<(), AppleCodesignError> {
+        // Like Apple's profiles, the intermediate is only found in the CMS data.
+        let (root, root_key) = ca("Example Root CA");
+        let (intermediate, intermediate_key) = ca("Example Intermediate CA");
+        let intermediate = issue(intermediate, &root, &root_key);
+        let (leaf, leaf_key, _) = create_self_signed_code_signing_certificate(
+            KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1),
+            CertificateProfile::AppleDevelopment,
+            "ABCDE12345",
+            "Profile Signer",
+            "US",
+            chrono::Duration::hours(1),
+        )?;
+        let leaf = issue(leaf, &intermediate, &intermediate_key);
+
+        let cms = SignedDataBuilder::default()
+            .certificate(leaf.clone())
+            .certificate(intermediate.clone())
+            .certificate(root.clone())
+            .content_inline(PROFILE.as_bytes().to_vec())
+            .signer(SignerBuilder::new(&leaf_key, leaf.clone()))
+            .build_der()?;
+        let profile = ProvisioningProfile::from_der(&cms)?;
+
+        profile.verify_signature_with_anchor(|cert| cert == &root)?;
+        assert!(matches!(
+            profile.verify_signature_with_anchor(|cert| cert == &intermediate),
+            Err(AppleCodesignError::ProvisioningProfileUntrusted(_))
+        ));
+        assert!(matches!(
+            profile.verify_signature(),
+            Err(AppleCodesignError::ProvisioningProfileUntrusted(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(wildcard_matches("*", "com.example.app"));
+        assert!(wildcard_matches("com.example.*", "com.example.app"));
+        assert!(!wildcard_matches("com.example.*", "com.other.app"));
+        assert!(wildcard_matches("com.example.app", "com.example.app"));
+        assert!(!wildcard_matches("com.example.app", "com.example.app2"));
+    }
+}
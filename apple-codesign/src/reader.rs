@@ -8,11 +8,13 @@ use {
     crate::{
         certificate::AppleCertificate,
         code_directory::CodeDirectoryBlob,
+        code_requirement::DesignatedRequirement,
         dmg::{path_is_dmg, DmgReader},
         embedded_signature::{BlobEntry, DigestType, EmbeddedSignature},
         embedded_signature_builder::{CD_DIGESTS_OID, CD_DIGESTS_PLIST_OID},
         error::AppleCodesignError,
         macho::{MachFile, MachOBinary},
+        verify::verify_time_stamp_token,
     },
     apple_bundles::{DirectoryBundle, DirectoryBundleFile},
     apple_xar::{
@@ -22,7 +24,7 @@ use {
         },
     },
     cryptographic_message_syntax::{SignedData, SignerInfo},
-    goblin::mach::{fat::FAT_MAGIC, parse_magic_and_ctx},
+    goblin::mach::{cputype::get_arch_name_from_types, fat::FAT_MAGIC, parse_magic_and_ctx},
     serde::Serialize,
     std::{
         fmt::Debug,
@@ -54,7 +56,9 @@ impl MachOType {
 
         match magic {
             FAT_MAGIC => Ok(Some(Self::Mach)),
-            _ if parse_magic_and_ctx(&header, 0).is_ok() => Ok(Some(Self::MachO)),
+            _ if matches!(parse_magic_and_ctx(&header, 0), Ok((_, Some(_)))) => {
+                Ok(Some(Self::MachO))
+            }
             _ => Ok(None),
         }
     }
@@ -91,6 +95,7 @@ pub fn path_is_zip(path: impl AsRef<Path>) -> Result<bool, AppleCodesignError> {
 /// Describes the type of entity at a path.
 ///
 /// This represents a best guess.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum PathType {
     MachO,
     Dmg,
@@ -101,6 +106,18 @@ pub enum PathType {
 }
 
 impl PathType {
+    /// All path types.
+    pub fn all() -> [Self; 6] {
+        [
+            Self::MachO,
+            Self::Dmg,
+            Self::Bundle,
+            Self::Xar,
+            Self::Zip,
+            Self::Other,
+        ]
+    }
+
     /// Attempt to classify the type of signable entity based on a filesystem path.
     pub fn from_path(path: impl AsRef<Path>) -> Result<Self, AppleCodesignError> {
         let path = path.as_ref();
@@ -263,6 +280,9 @@ pub struct CmsSigner {
     pub signature_verifies: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub time_stamp_token: Option<CmsSignature>,
+    /// Time attested by the Time-Stamp Token, if it verifies.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_stamp: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 impl CmsSigner {
@@ -365,6 +385,10 @@ impl CmsSigner {
                 .is_ok(),
 
             time_stamp_token,
+            time_stamp: verify_time_stamp_token(signer_info)
+                .ok()
+                .flatten()
+                .map(|x| x.time),
         })
     }
 }
@@ -436,7 +460,7 @@ impl<'a> TryFrom<CodeDirectoryBlob<'a>> for CodeDirectory {
 
         Ok(Self {
             version: format!("0x{:X}", cd.version),
-            flags: format!("{:?}", cd.flags),
+            flags: cd.flags.to_string(),
             identifier: cd.ident.to_string(),
             team_name: cd.team_name.map(|x| x.to_string()),
             signed_entity_size: cd.code_limit as _,
@@ -797,6 +821,24 @@ impl FileEntity {
     }
 }
 
+/// A code directory hash (cdhash) of a signed entity.
+///
+/// cdhashes are what notarization tickets, notarization logs, and code requirements
+/// such as `cdhash H"..."` reference to identify signed code.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CdHash {
+    /// Name of the CPU architecture of the Mach-O binary the code directory is for.
+    ///
+    /// Only present for Mach-O binaries.
+    pub arch: Option<String>,
+
+    /// The digest type of the code directory.
+    pub digest_type: DigestType,
+
+    /// The digest of the code directory, truncated to 20 bytes.
+    pub cdhash: Vec<u8>,
+}
+
 /// Entity for reading Apple code signature data.
 pub enum SignatureReader {
     Dmg(PathBuf, Box<DmgReader>),
@@ -847,6 +889,136 @@ impl SignatureReader {
         }
     }
 
+    /// Resolve the cdhashes of the signed entity.
+    ///
+    /// Mach-O binaries report the cdhash of every code directory of every architecture.
+    /// Bundles report the cdhashes of their main executable. DMGs report the cdhashes of
+    /// their embedded signature. Flat packages report the checksum of their table of
+    /// contents, which is what notarization uses in place of a code directory.
+    pub fn cdhashes(&self) -> Result<Vec<CdHash>, AppleCodesignError> {
+        match self {
+            Self::Dmg(_, dmg) => {
+                let signature = dmg
+                    .embedded_signature()?
+                    .ok_or(AppleCodesignError::BinaryNoCodeSignature)?;
+
+                Self::resolve_signature_cdhashes(&signature, None)
+            }
+            Self::MachO(_, data) => Self::resolve_macho_cdhashes(data),
+            Self::Bundle(bundle) => {
+                Self::resolve_macho_cdhashes(&Self::read_bundle_main_executable(bundle)?)
+            }
+            Self::FlatPackage(path) => {
+                let mut xar = XarReader::new(File::open(path)?)?;
+
+                let digest_type = DigestType::try_from(xar.table_of_contents().checksum.style)?;
+                let mut cdhash = xar.checksum_data()?;
+                cdhash.truncate(20);
+
+                Ok(vec![CdHash {
+                    arch: None,
+                    digest_type,
+                    cdhash,
+                }])
+            }
+        }
+    }
+
+    /// Resolve the designated requirement of the signed entity.
+    ///
+    /// Mach-O binaries report the designated requirement of their first
+    /// architecture. Bundles report the designated requirement of their main
+    /// executable. Flat packages don't have designated requirements.
+    ///
+    /// Signatures without an explicit designated requirement report the implicit
+    /// one the operating system derives. See
+    /// [EmbeddedSignature::implicit_designated_requirement()].
+    pub fn designated_requirement(
+        &self,
+    ) -> Result<Option<DesignatedRequirement>, AppleCodesignError> {
+        match self {
+            Self::Dmg(_, dmg) => Self::resolve_signature_designated_requirement(
+                &dmg.embedded_signature()?
+                    .ok_or(AppleCodesignError::BinaryNoCodeSignature)?,
+            ),
+            Self::MachO(_, data) => Self::resolve_macho_designated_requirement(data),
+            Self::Bundle(bundle) => Self::resolve_macho_designated_requirement(
+                &Self::read_bundle_main_executable(bundle)?,
+            ),
+            Self::FlatPackage(_) => Ok(None),
+        }
+    }
+
+    fn read_bundle_main_executable(
+        bundle: &DirectoryBundle,
+    ) -> Result<Vec<u8>, AppleCodesignError> {
+        let main_exe = bundle
+            .files(false)
+            .map_err(AppleCodesignError::DirectoryBundle)?
+            .into_iter()
+            .find(|file| matches!(file.is_main_executable(), Ok(true)))
+            .ok_or_else(|| {
+                AppleCodesignError::BundleNoMainExecutable(bundle.root_dir().to_path_buf())
+            })?;
+
+        Ok(std::fs::read(main_exe.absolute_path())?)
+    }
+
+    fn resolve_macho_designated_requirement(
+        data: &[u8],
+    ) -> Result<Option<DesignatedRequirement>, AppleCodesignError> {
+        Self::resolve_signature_designated_requirement(
+            &MachFile::parse(data)?
+                .nth_macho(0)?
+                .code_signature()?
+                .ok_or(AppleCodesignError::BinaryNoCodeSignature)?,
+        )
+    }
+
+    fn resolve_signature_designated_requirement(
+        signature: &EmbeddedSignature,
+    ) -> Result<Option<DesignatedRequirement>, AppleCodesignError> {
+        match signature.designated_requirement()? {
+            Some(requirement) => Ok(Some(requirement)),
+            None => signature.implicit_designated_requirement(),
+        }
+    }
+
+    fn resolve_macho_cdhashes(data: &[u8]) -> Result<Vec<CdHash>, AppleCodesignError> {
+        let mut res = vec![];
+
+        for macho in MachFile::parse(data)?.into_iter() {
+            let arch = get_arch_name_from_types(
+                macho.macho.header.cputype(),
+                macho.macho.header.cpusubtype(),
+            )
+            .map(|name| name.to_string());
+
+            let signature = macho
+                .code_signature()?
+                .ok_or(AppleCodesignError::BinaryNoCodeSignature)?;
+
+            res.extend(Self::resolve_signature_cdhashes(&signature, arch)?);
+        }
+
+        Ok(res)
+    }
+
+    fn resolve_signature_cdhashes(
+        signature: &EmbeddedSignature,
+        arch: Option<String>,
+    ) -> Result<Vec<CdHash>, AppleCodesignError> {
+        Ok(signature
+            .cdhashes()?
+            .into_iter()
+            .map(|(digest_type, cdhash)| CdHash {
+                arch: arch.clone(),
+                digest_type,
+                cdhash,
+            })
+            .collect::<Vec<_>>())
+    }
+
     fn resolve_dmg_entity(dmg: &DmgReader) -> Result<DmgEntity, AppleCodesignError> {
         let signature = if let Some(sig) = dmg.embedded_signature()? {
             Some(sig.try_into()?)
@@ -1019,3 +1191,27 @@ impl SignatureReader {
         Ok(entities)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn path_type_requires_macho_magic() -> Result<(), AppleCodesignError> {
+        let td = tempfile::tempdir()?;
+
+        let short = td.path().join("short");
+        std::fs::write(&short, b"abcd")?;
+        assert_eq!(PathType::from_path(&short)?, PathType::Other);
+
+        let script = td.path().join("script.sh");
+        std::fs::write(&script, b"#!/bin/sh\necho hello\n")?;
+        assert_eq!(PathType::from_path(&script)?, PathType::Other);
+
+        let macho = td.path().join("binary");
+        std::fs::write(&macho, crate::test_support::SyntheticMachO::default().build())?;
+        assert_eq!(PathType::from_path(&macho)?, PathType::MachO);
+
+        Ok(())
+    }
+}
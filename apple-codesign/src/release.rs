@@ -0,0 +1,587 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! End-to-end release orchestration.
+//!
+//! Releasing macOS software typically involves the same sequence of steps:
+//! sign every Mach-O binary and bundle, package everything into a DMG, sign
+//! the DMG, notarize it, and staple the notarization ticket. [ReleasePipeline]
+//! performs this sequence as a single operation configured by a
+//! [ReleaseConfig], which is usually read from a YAML file:
+//!
+//! ```yaml
+//! # Directory holding the content to release. Relative paths are relative to
+//! # the directory of the configuration file.
+//! staging_dir: build/staging
+//! # Optional. Package the staging directory into a DMG. Requires macOS.
+//! dmg:
+//!   path: dist/MyApp.dmg
+//!   volume_name: MyApp
+//! # Optional. Defaults to true.
+//! notarize: true
+//! # Optional. Defaults to true.
+//! staple: true
+//! # Optional. Defaults to 600.
+//! notarize_wait_seconds: 600
+//! # Optional. Write a JSON audit report of the release.
+//! report_path: dist/release-report.json
+//! ```
+//!
+//! Every performed operation is recorded in a [ReleaseReport], which can serve
+//! as an audit trail of what was released.
+
+use {
+    crate::{
+        error::AppleCodesignError,
+        reader::{PathType, SignatureReader},
+        signing::UnifiedSigner,
+        signing_settings::SigningSettings,
+    },
+    apple_bundles::DirectoryBundle,
+    log::warn,
+    serde::{Deserialize, Serialize},
+    sha2::Digest,
+    std::{
+        path::{Path, PathBuf},
+        process::Command,
+    },
+};
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_notarize_wait_seconds() -> u64 {
+    600
+}
+
+/// Configuration of a DMG to create.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ReleaseDmgConfig {
+    /// Path of the DMG to write.
+    pub path: PathBuf,
+    /// Name of the volume. Defaults to the file name of the DMG without extension.
+    #[serde(default)]
+    pub volume_name: Option<String>,
+}
+
+/// Configuration of a release performed by [ReleasePipeline].
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ReleaseConfig {
+    /// Directory holding the content to release.
+    ///
+    /// Every bundle and Mach-O binary in this directory is signed in place.
+    pub staging_dir: PathBuf,
+    /// DMG to package the staging directory into.
+    #[serde(default)]
+    pub dmg: Option<ReleaseDmgConfig>,
+    /// Whether to notarize the release.
+    ///
+    /// The DMG is notarized if there is one. Otherwise the bundles in the
+    /// staging directory are notarized.
+    #[serde(default = "default_true")]
+    pub notarize: bool,
+    /// Whether to staple notarization tickets. Ignored if not notarizing.
+    #[serde(default = "default_true")]
+    pub staple: bool,
+    /// Maximum time in seconds to wait for notarization to complete.
+    #[serde(default = "default_notarize_wait_seconds")]
+    pub notarize_wait_seconds: u64,
+    /// Path to write a JSON [ReleaseReport] to.
+    #[serde(default)]
+    pub report_path: Option<PathBuf>,
+}
+
+impl ReleaseConfig {
+    /// Construct an instance from YAML.
+    ///
+    /// Relative paths are relative to the current directory.
+    pub fn from_yaml(data: &[u8]) -> Result<Self, AppleCodesignError> {
+        Ok(serde_yaml::from_slice(data)?)
+    }
+
+    /// Construct an instance from a YAML file.
+    ///
+    /// Relative paths are relative to the directory of the file.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, AppleCodesignError> {
+        let path = path.as_ref();
+        let mut config = Self::from_yaml(&std::fs::read(path)?)?;
+
+        if let Some(dir) = path.parent() {
+            config.staging_dir = dir.join(&config.staging_dir);
+
+            if let Some(dmg) = &mut config.dmg {
+                dmg.path = dir.join(&dmg.path);
+            }
+
+            if let Some(report_path) = &mut config.report_path {
+                *report_path = dir.join(&*report_path);
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// A signed artifact recorded in a [ReleaseReport].
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct ReleaseArtifact {
+    /// Path of the artifact.
+    pub path: PathBuf,
+    /// SHA-256 of the file content. Not present for bundles.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Hex encoded cdhashes of the signature.
+    pub cdhashes: Vec<String>,
+    /// The designated requirement of the signature in the code requirement language.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub designated_requirement: Option<String>,
+}
+
+impl ReleaseArtifact {
+    fn from_path(path: &Path) -> Result<Self, AppleCodesignError> {
+        let sha256 = if path.is_file() {
+            Some(hex::encode(sha2::Sha256::digest(std::fs::read(path)?)))
+        } else {
+            None
+        };
+
+        let reader = SignatureReader::from_path(path)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            sha256,
+            cdhashes: reader
+                .cdhashes()?
+                .into_iter()
+                .map(|cdhash| hex::encode(cdhash.cdhash))
+                .collect(),
+            designated_requirement: reader
+                .designated_requirement()?
+                .map(|requirement| requirement.to_requirement_text())
+                .transpose()?,
+        })
+    }
+}
+
+/// A notarization submission recorded in a [ReleaseReport].
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct ReleaseNotarization {
+    /// Path of the notarized artifact.
+    pub path: PathBuf,
+    /// ID of the Notary API submission.
+    pub submission_id: String,
+    /// Final status of the submission.
+    pub status: String,
+}
+
+/// Record of the operations performed by [ReleasePipeline].
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct ReleaseReport {
+    /// Version of this crate performing the release.
+    pub tool_version: String,
+    /// When the release started, in RFC 3339 format.
+    pub started_at: String,
+    /// When the release finished, in RFC 3339 format.
+    pub finished_at: String,
+    /// Bundles and Mach-O binaries signed in the staging directory.
+    pub signed: Vec<ReleaseArtifact>,
+    /// The created and signed DMG.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dmg: Option<ReleaseArtifact>,
+    /// Notarization submissions.
+    pub notarizations: Vec<ReleaseNotarization>,
+    /// Paths notarization tickets were stapled to.
+    pub stapled: Vec<PathBuf>,
+}
+
+/// Performs a release as described by a [ReleaseConfig].
+pub struct ReleasePipeline<'key> {
+    config: ReleaseConfig,
+    settings: SigningSettings<'key>,
+    #[cfg(feature = "notarize")]
+    notarizer: Option<crate::notarization::Notarizer>,
+}
+
+impl<'key> ReleasePipeline<'key> {
+    /// Construct a new instance signing with the given settings.
+    pub fn new(config: ReleaseConfig, settings: SigningSettings<'key>) -> Self {
+        Self {
+            config,
+            settings,
+            #[cfg(feature = "notarize")]
+            notarizer: None,
+        }
+    }
+
+    /// Set the [crate::notarization::Notarizer] used to notarize the release.
+    ///
+    /// Required if the configuration enables notarization.
+    #[cfg(feature = "notarize")]
+    pub fn set_notarizer(&mut self, notarizer: crate::notarization::Notarizer) {
+        self.notarizer = Some(notarizer);
+    }
+
+    /// Perform the release.
+    ///
+    /// Operations are performed in order and the first failure aborts the
+    /// release. If the configuration has a report path, the report is written
+    /// there.
+    pub fn run(&self) -> Result<ReleaseReport, AppleCodesignError> {
+        // Catch configuration problems before modifying anything.
+        if !self.config.staging_dir.is_dir() {
+            return Err(AppleCodesignError::ReleaseConfig(format!(
+                "staging directory {} does not exist",
+                self.config.staging_dir.display()
+            )));
+        }
+
+        if self.config.notarize {
+            #[cfg(feature = "notarize")]
+            if self.notarizer.is_none() {
+                return Err(AppleCodesignError::NotarizeNoAuthCredentials);
+            }
+
+            #[cfg(not(feature = "notarize"))]
+            return Err(AppleCodesignError::ReleaseConfig(
+                "notarization requested but this build lacks notarization support".into(),
+            ));
+        }
+
+        let mut report = ReleaseReport {
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            started_at: chrono::Utc::now().to_rfc3339(),
+            ..Default::default()
+        };
+
+        let signer = UnifiedSigner::new(self.settings.clone());
+
+        for path in self.signable_paths()? {
+            warn!("signing {}", path.display());
+            signer.sign_path_in_place(&path)?;
+            report.signed.push(ReleaseArtifact::from_path(&path)?);
+        }
+
+        if let Some(dmg) = &self.config.dmg {
+            create_dmg(&self.config.staging_dir, dmg)?;
+
+            warn!("signing {}", dmg.path.display());
+            signer.sign_path_in_place(&dmg.path)?;
+            report.dmg = Some(ReleaseArtifact::from_path(&dmg.path)?);
+        }
+
+        #[cfg(feature = "notarize")]
+        if self.config.notarize {
+            self.notarize(&mut report)?;
+        }
+
+        report.finished_at = chrono::Utc::now().to_rfc3339();
+
+        if let Some(report_path) = &self.config.report_path {
+            warn!("writing release report to {}", report_path.display());
+            if let Some(parent) = report_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(report_path, serde_json::to_vec_pretty(&report)?)?;
+        }
+
+        Ok(report)
+    }
+
+    /// Resolve the paths in the staging directory to sign.
+    ///
+    /// Bundles are signed as a whole, so their content isn't traversed.
+    fn signable_paths(&self) -> Result<Vec<PathBuf>, AppleCodesignError> {
+        let mut paths = vec![];
+
+        let mut walker = walkdir::WalkDir::new(&self.config.staging_dir)
+            .min_depth(1)
+            .sort_by_file_name()
+            .into_iter();
+
+        while let Some(entry) = walker.next() {
+            let entry = entry.map_err(std::io::Error::from)?;
+
+            if entry.file_type().is_dir() {
+                // Every directory is a [PathType::Bundle], so look for actual bundles.
+                if DirectoryBundle::new_from_path(entry.path()).is_ok() {
+                    paths.push(entry.into_path());
+                    walker.skip_current_dir();
+                }
+            } else if entry.file_type().is_file()
+                && PathType::from_path(entry.path())? == PathType::MachO
+            {
+                paths.push(entry.into_path());
+            }
+        }
+
+        Ok(paths)
+    }
+
+    /// Notarize and staple the release artifacts.
+    #[cfg(feature = "notarize")]
+    fn notarize(&self, report: &mut ReleaseReport) -> Result<(), AppleCodesignError> {
+        use crate::notarization::NotarizationUpload;
+
+        let notarizer = self
+            .notarizer
+            .as_ref()
+            .ok_or(AppleCodesignError::NotarizeNoAuthCredentials)?;
+        let wait_limit = std::time::Duration::from_secs(self.config.notarize_wait_seconds);
+
+        let paths = if let Some(dmg) = &report.dmg {
+            vec![dmg.path.clone()]
+        } else {
+            report
+                .signed
+                .iter()
+                .map(|artifact| artifact.path.clone())
+                .filter(|path| path.is_dir())
+                .collect::<Vec<_>>()
+        };
+
+        if paths.is_empty() {
+            return Err(AppleCodesignError::ReleaseConfig(
+                "nothing to notarize; configure a DMG or put bundles in the staging directory"
+                    .into(),
+            ));
+        }
+
+        for path in paths {
+            warn!("notarizing {}", path.display());
+
+            match notarizer.notarize_path(&path, Some(wait_limit))? {
+                NotarizationUpload::NotaryResponse(response) => {
+                    report.notarizations.push(ReleaseNotarization {
+                        path: path.clone(),
+                        submission_id: response.data.id,
+                        status: response.data.attributes.status.to_string(),
+                    });
+                }
+                NotarizationUpload::UploadId(_) => {
+                    return Err(AppleCodesignError::LogicError(
+                        "notarization upload should not return ID when waiting".into(),
+                    ));
+                }
+            }
+
+            if self.config.staple {
+                crate::stapling::Stapler::new()?.staple_path(&path)?;
+                report.stapled.push(path);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Create a DMG from the content of a directory.
+///
+/// This uses Apple's `hdiutil` and thus requires macOS.
+fn create_dmg(source: &Path, dmg: &ReleaseDmgConfig) -> Result<(), AppleCodesignError> {
+    let volume_name = dmg.volume_name.clone().unwrap_or_else(|| {
+        dmg.path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "Release".into())
+    });
+
+    if let Some(parent) = dmg.path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    warn!(
+        "creating DMG {} from {}",
+        dmg.path.display(),
+        source.display()
+    );
+
+    let output = Command::new("hdiutil")
+        .arg("create")
+        .arg("-ov")
+        .args(["-format", "UDZO"])
+        .arg("-volname")
+        .arg(&volume_name)
+        .arg("-srcfolder")
+        .arg(source)
+        .arg(&dmg.path)
+        .output()
+        .map_err(|e| {
+            AppleCodesignError::ReleaseDmgCreate(if e.kind() == std::io::ErrorKind::NotFound {
+                "hdiutil not found; creating DMGs requires macOS".into()
+            } else {
+                format!("failed to run hdiutil: {e}")
+            })
+        })?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(AppleCodesignError::ReleaseDmgCreate(format!(
+            "hdiutil failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn config_paths() -> Result<(), AppleCodesignError> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("release.yaml");
+        std::fs::write(
+            &path,
+            "staging_dir: staging\ndmg:\n  path: dist/App.dmg\nreport_path: report.json\n",
+        )?;
+
+        let config = ReleaseConfig::from_path(&path)?;
+        assert_eq!(config.staging_dir, dir.path().join("staging"));
+        assert_eq!(
+            config.dmg,
+            Some(ReleaseDmgConfig {
+                path: dir.path().join("dist/App.dmg"),
+                volume_name: None,
+            })
+        );
+        assert!(config.notarize);
+        assert!(config.staple);
+        assert_eq!(config.notarize_wait_seconds, 600);
+        assert_eq!(config.report_path, Some(dir.path().join("report.json")));
+
+        assert!(ReleaseConfig::from_yaml(b"staging_dir: x\nunknown: true\n").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn sign_staging() -> Result<(), AppleCodesignError> {
+        let dir = tempfile::tempdir()?;
+        let staging = dir.path().join("staging");
+        std::fs::create_dir_all(staging.join("bin"))?;
+        std::fs::write(
+            staging.join("bin").join("tool"),
+            crate::test_support::SyntheticMachO::default().build(),
+        )?;
+        std::fs::write(staging.join("README"), b"not a binary")?;
+
+        let config = ReleaseConfig {
+            staging_dir: staging.clone(),
+            dmg: None,
+            notarize: false,
+            staple: false,
+            notarize_wait_seconds: 0,
+            report_path: Some(dir.path().join("report.json")),
+        };
+
+        let report = ReleasePipeline::new(config, SigningSettings::default()).run()?;
+        assert_eq!(report.signed.len(), 1);
+        assert_eq!(report.signed[0].path, staging.join("bin").join("tool"));
+        assert!(report.signed[0].sha256.is_some());
+        assert_eq!(report.signed[0].cdhashes.len(), 1);
+        assert!(report.notarizations.is_empty());
+
+        let written: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(dir.path().join("report.json"))?)?;
+        assert_eq!(written["signed"].as_array().map(|x| x.len()), Some(1));
+
+        // Notarization requires credentials.
+        let config =
+            ReleaseConfig::from_yaml(format!("staging_dir: {}\n", staging.display()).as_bytes())?;
+        assert!(ReleasePipeline::new(config, SigningSettings::default())
+            .run()
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn dmg_stage() -> Result<(), AppleCodesignError> {
+        let dir = tempfile::tempdir()?;
+        let staging = dir.path().join("staging");
+        std::fs::create_dir_all(&staging)?;
+        std::fs::write(
+            staging.join("tool"),
+            crate::test_support::SyntheticMachO::default().build(),
+        )?;
+
+        let dmg_path = dir.path().join("dist").join("Tool.dmg");
+        let config = ReleaseConfig {
+            staging_dir: staging.clone(),
+            dmg: Some(ReleaseDmgConfig {
+                path: dmg_path.clone(),
+                volume_name: Some("Tool".into()),
+            }),
+            notarize: false,
+            staple: false,
+            notarize_wait_seconds: 0,
+            report_path: None,
+        };
+
+        let res = ReleasePipeline::new(config, SigningSettings::default()).run();
+
+        if cfg!(target_os = "macos") {
+            let report = res?;
+            assert_eq!(report.signed.len(), 1);
+            let dmg = report.dmg.expect("DMG should be in report");
+            assert_eq!(dmg.path, dmg_path);
+            assert!(dmg.sha256.is_some());
+            assert_eq!(PathType::from_path(&dmg_path)?, PathType::Dmg);
+        } else {
+            // Binaries are signed before the DMG is created and hdiutil is macOS only.
+            assert!(matches!(res, Err(AppleCodesignError::ReleaseDmgCreate(_))));
+            assert!(
+                !crate::reader::SignatureReader::from_path(staging.join("tool"))?
+                    .cdhashes()?
+                    .is_empty()
+            );
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "notarize")]
+    #[test]
+    fn notarize_stage() -> Result<(), AppleCodesignError> {
+        let dir = tempfile::tempdir()?;
+        let staging = dir.path().join("staging");
+        std::fs::create_dir_all(&staging)?;
+        std::fs::write(
+            staging.join("tool"),
+            crate::test_support::SyntheticMachO::default().build(),
+        )?;
+
+        // The key is only used once a request is made.
+        let key_path = dir.path().join("key.json");
+        std::fs::write(
+            &key_path,
+            br#"{"issuer_id": "issuer", "key_id": "DEADBEEF42", "private_key": "AAAA"}"#,
+        )?;
+
+        let config = ReleaseConfig {
+            staging_dir: staging,
+            dmg: None,
+            notarize: true,
+            staple: true,
+            notarize_wait_seconds: 0,
+            report_path: None,
+        };
+
+        let mut pipeline = ReleasePipeline::new(config, SigningSettings::default());
+        pipeline.set_notarizer(crate::notarization::Notarizer::from_api_key(&key_path)?);
+
+        // Bare binaries can't be notarized, so without a DMG or bundle there is
+        // nothing to submit.
+        assert!(matches!(
+            pipeline.run(),
+            Err(AppleCodesignError::ReleaseConfig(message)) if message.starts_with("nothing to notarize")
+        ));
+
+        Ok(())
+    }
+}
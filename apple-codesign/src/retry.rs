@@ -0,0 +1,297 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Retrying of operations talking to network services.
+//!
+//! Time-stamp servers, the Notary API, and the notarization ticket lookup
+//! service occasionally fail for reasons that go away if you try again. This
+//! module defines [RetryPolicy], which is shared by all code talking to these
+//! services so retry behavior is configured in one place.
+
+use {
+    crate::AppleCodesignError,
+    cryptographic_message_syntax::{CmsError, TimeStampError},
+    log::warn,
+    rand::Rng,
+    std::{io::ErrorKind, sync::Arc, time::Duration},
+};
+
+/// Function deciding whether an error should be retried.
+pub type RetryClassifier = Arc<dyn Fn(&AppleCodesignError) -> bool + Send + Sync>;
+
+/// Describes how to retry failed network operations.
+///
+/// Failed attempts are retried after a delay that doubles after each attempt,
+/// up to a maximum. Jitter randomizes each delay to between half and all of
+/// its nominal value so concurrent clients don't retry in lockstep.
+///
+/// Only errors the classifier deems retryable are retried. The default
+/// classifier is [is_transient_error()].
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+    retry_on: RetryClassifier,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+            retry_on: Arc::new(is_transient_error),
+        }
+    }
+}
+
+impl std::fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("initial_delay", &self.initial_delay)
+            .field("max_delay", &self.max_delay)
+            .field("jitter", &self.jitter)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RetryPolicy {
+    /// Construct an instance that never retries.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// The maximum number of attempts, including the first.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Set the maximum number of attempts, including the first.
+    ///
+    /// Values less than 1 are treated as 1.
+    pub fn set_max_attempts(&mut self, value: u32) {
+        self.max_attempts = value.max(1);
+    }
+
+    /// Set the delay before the first retry and the maximum delay between attempts.
+    pub fn set_delays(&mut self, initial: Duration, max: Duration) {
+        self.initial_delay = initial;
+        self.max_delay = max.max(initial);
+    }
+
+    /// Set whether to randomize delays between attempts.
+    pub fn set_jitter(&mut self, value: bool) {
+        self.jitter = value;
+    }
+
+    /// Set the function deciding whether an error is retried.
+    pub fn set_retry_on(
+        &mut self,
+        classifier: impl Fn(&AppleCodesignError) -> bool + Send + Sync + 'static,
+    ) {
+        self.retry_on = Arc::new(classifier);
+    }
+
+    /// Whether an error should be retried.
+    pub fn is_retryable(&self, e: &AppleCodesignError) -> bool {
+        (self.retry_on)(e)
+    }
+
+    /// The delay before retrying after a failed attempt.
+    ///
+    /// `attempt` is the 1-based number of the attempt that failed.
+    pub fn delay_after_attempt(&self, attempt: u32) -> Duration {
+        let delay = self
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+
+        if self.jitter && !delay.is_zero() {
+            rand::thread_rng().gen_range(delay / 2..=delay)
+        } else {
+            delay
+        }
+    }
+
+    /// Run an operation, retrying it according to this policy.
+    ///
+    /// `operation` is a description of the operation used for logging.
+    pub fn run<T>(
+        &self,
+        operation: &str,
+        mut f: impl FnMut() -> Result<T, AppleCodesignError>,
+    ) -> Result<T, AppleCodesignError> {
+        let mut attempt = 1;
+
+        loop {
+            match f() {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt < self.max_attempts && self.is_retryable(&e) => {
+                    let delay = self.delay_after_attempt(attempt);
+
+                    warn!(
+                        "{} failed (attempt {}/{}): {}; retrying in {}ms",
+                        operation,
+                        attempt,
+                        self.max_attempts,
+                        e,
+                        delay.as_millis()
+                    );
+
+                    std::thread::sleep(delay);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+fn is_transient_reqwest_error(e: &reqwest::Error) -> bool {
+    e.is_timeout()
+        || e.is_connect()
+        || e.status().map_or(false, |status| {
+            status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        })
+}
+
+fn is_transient_io_error(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        ErrorKind::TimedOut
+            | ErrorKind::Interrupted
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::ConnectionRefused
+    )
+}
+
+/// Whether an error is likely a transient network failure.
+///
+/// This recognizes connection failures, timeouts, HTTP 5xx and 429 responses,
+/// and failures talking to time-stamp servers.
+pub fn is_transient_error(e: &AppleCodesignError) -> bool {
+    match e {
+        AppleCodesignError::Reqwest(e) => is_transient_reqwest_error(e),
+        AppleCodesignError::Io(e) => is_transient_io_error(e),
+        AppleCodesignError::Cms(CmsError::TimeStampProtocol(e)) => match e {
+            TimeStampError::Reqwest(e) => is_transient_reqwest_error(e),
+            TimeStampError::Io(e) => is_transient_io_error(e),
+            // Non-2xx HTTP responses.
+            TimeStampError::Http(_) => true,
+            _ => false,
+        },
+        AppleCodesignError::Anyhow(e) => {
+            #[cfg(feature = "notarize")]
+            if let Some(e) = e.downcast_ref::<app_store_connect::AppStoreConnectError>() {
+                return e.status() >= 500 || e.status() == 429;
+            }
+
+            e.downcast_ref::<reqwest::Error>()
+                .map_or(false, is_transient_reqwest_error)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, std::cell::Cell};
+
+    fn no_delay_policy(max_attempts: u32) -> RetryPolicy {
+        let mut policy = RetryPolicy::default();
+        policy.set_max_attempts(max_attempts);
+        policy.set_delays(Duration::ZERO, Duration::ZERO);
+
+        policy
+    }
+
+    fn timed_out() -> AppleCodesignError {
+        std::io::Error::new(ErrorKind::TimedOut, "timed out").into()
+    }
+
+    #[test]
+    fn retries_transient_errors() {
+        let policy = no_delay_policy(3);
+        let attempts = Cell::new(0);
+
+        let res = policy.run("test", || {
+            attempts.set(attempts.get() + 1);
+
+            if attempts.get() < 3 {
+                Err(timed_out())
+            } else {
+                Ok(42)
+            }
+        });
+
+        assert_eq!(res.unwrap(), 42);
+        assert_eq!(attempts.get(), 3);
+
+        attempts.set(0);
+        let res = policy.run("test", || -> Result<(), _> {
+            attempts.set(attempts.get() + 1);
+            Err(timed_out())
+        });
+        assert!(res.is_err());
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[test]
+    fn does_not_retry_permanent_errors() {
+        let policy = no_delay_policy(3);
+        let attempts = Cell::new(0);
+
+        let res = policy.run("test", || -> Result<(), _> {
+            attempts.set(attempts.get() + 1);
+            Err(AppleCodesignError::XarNoAdhoc)
+        });
+        assert!(res.is_err());
+        assert_eq!(attempts.get(), 1);
+
+        attempts.set(0);
+        let res = RetryPolicy::none().run("test", || -> Result<(), _> {
+            attempts.set(attempts.get() + 1);
+            Err(timed_out())
+        });
+        assert!(res.is_err());
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[test]
+    fn custom_classifier() {
+        let mut policy = no_delay_policy(2);
+        policy.set_retry_on(|e| matches!(e, AppleCodesignError::XarNoAdhoc));
+
+        assert!(policy.is_retryable(&AppleCodesignError::XarNoAdhoc));
+        assert!(!policy.is_retryable(&timed_out()));
+    }
+
+    #[test]
+    fn delays() {
+        let mut policy = RetryPolicy::default();
+        policy.set_jitter(false);
+        policy.set_delays(Duration::from_secs(1), Duration::from_secs(5));
+
+        assert_eq!(policy.delay_after_attempt(1), Duration::from_secs(1));
+        assert_eq!(policy.delay_after_attempt(2), Duration::from_secs(2));
+        assert_eq!(policy.delay_after_attempt(3), Duration::from_secs(4));
+        assert_eq!(policy.delay_after_attempt(4), Duration::from_secs(5));
+        assert_eq!(policy.delay_after_attempt(100), Duration::from_secs(5));
+
+        policy.set_jitter(true);
+        for attempt in 1..5 {
+            let delay = policy.delay_after_attempt(attempt);
+            assert!(delay <= Duration::from_secs(5));
+            assert!(delay >= Duration::from_millis(500));
+        }
+    }
+}
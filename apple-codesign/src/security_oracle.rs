@@ -0,0 +1,687 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Signature validation routines ported from Apple's open source code.
+//!
+//! Our parsers and serializers are written against the same understanding of the
+//! signature formats, so round-tripping through them can't catch us diverging
+//! from what Apple accepts. The routines in this module are independent ports of
+//! the structural checks Apple performs before trusting signature data:
+//!
+//! * `SuperBlobCore::validateBlob()` from `libsecurity_utilities/lib/superblob.h`.
+//! * `CodeDirectory::checkIntegrity()` from
+//!   `libsecurity_codesigning/lib/codedirectory.cpp`, plus the page and digest
+//!   geometry checks of the kernel's `cs_validate_codedirectory()`.
+//! * The expression walk of `Requirement::Dumper` from
+//!   `libsecurity_codesigning/lib/reqdumper.cpp`, which rejects any opcode or
+//!   operand that the requirement grammar doesn't define.
+//!
+//! They operate on raw bytes and deliberately share no code with the rest of the
+//! crate. They are used as oracles by tests that generate signatures.
+
+use scroll::Pread;
+
+const EMBEDDED_SIGNATURE_MAGIC: u32 = 0xfade0cc0;
+const REQUIREMENTS_MAGIC: u32 = 0xfade0c01;
+const REQUIREMENT_MAGIC: u32 = 0xfade0c00;
+const CODE_DIRECTORY_MAGIC: u32 = 0xfade0c02;
+
+// CodeDirectory version history from codedirectory.h.
+const EARLIEST_VERSION: u32 = 0x20001;
+const SUPPORTS_SCATTER: u32 = 0x20100;
+const SUPPORTS_TEAM_ID: u32 = 0x20200;
+const SUPPORTS_CODE_LIMIT_64: u32 = 0x20300;
+const SUPPORTS_PRE_ENCRYPT: u32 = 0x20500;
+const COMPATIBILITY_LIMIT: u32 = 0x2f000;
+
+/// Size of the fixed CodeDirectory fields present in every version.
+const CODE_DIRECTORY_BASE_SIZE: usize = 44;
+
+/// Page sizes (as log2) accepted by the kernel: 4k through 16k.
+const PAGE_SHIFT_4K: u8 = 12;
+const PAGE_SHIFT_MAX: u8 = 14;
+
+/// `Requirement::exprForm`.
+const REQUIREMENT_EXPR_FORM: u32 = 1;
+
+/// Opcode flag: unknown opcode with size field that evaluates to false.
+const OP_GENERIC_FALSE: u32 = 0x80000000;
+/// Opcode flag: unknown opcode with size field that is skipped.
+const OP_GENERIC_SKIP: u32 = 0x40000000;
+const OP_FLAG_MASK: u32 = 0xff000000;
+
+/// Expression nesting limit, to bound recursion on hostile input.
+const MAX_EXPRESSION_DEPTH: usize = 64;
+
+type OracleResult<T> = Result<T, String>;
+
+fn read_u32(data: &[u8], offset: usize) -> OracleResult<u32> {
+    data.pread_with::<u32>(offset, scroll::BE)
+        .map_err(|_| format!("u32 at offset {offset} out of range"))
+}
+
+fn read_u8(data: &[u8], offset: usize) -> OracleResult<u8> {
+    data.get(offset)
+        .copied()
+        .ok_or_else(|| format!("u8 at offset {offset} out of range"))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> OracleResult<u64> {
+    data.pread_with::<u64>(offset, scroll::BE)
+        .map_err(|_| format!("u64 at offset {offset} out of range"))
+}
+
+/// Port of `BlobCore::validateBlob(magic, minSize, maxSize)`.
+///
+/// Returns the blob's data, bounded by its declared length.
+fn validate_blob(data: &[u8], magic: u32, min_size: usize) -> OracleResult<&[u8]> {
+    let actual = read_u32(data, 0)?;
+    if actual != magic {
+        return Err(format!("bad magic {actual:#x}; expected {magic:#x}"));
+    }
+
+    let length = read_u32(data, 4)? as usize;
+    if length < min_size {
+        return Err(format!(
+            "blob length {length} smaller than minimum {min_size}"
+        ));
+    }
+    if length > data.len() {
+        return Err(format!(
+            "blob length {length} exceeds available data {}",
+            data.len()
+        ));
+    }
+
+    Ok(&data[0..length])
+}
+
+/// Port of `SuperBlobCore::validateBlob()`.
+///
+/// Returns the `(type, blob data)` of each index entry.
+fn validate_superblob(data: &[u8], magic: u32) -> OracleResult<Vec<(u32, &[u8])>> {
+    let count = read_u32(data, 8)? as usize;
+    let index_limit = 12 + count * 8;
+
+    let data = validate_blob(data, magic, index_limit)?;
+
+    let mut entries = Vec::with_capacity(count);
+
+    for i in 0..count {
+        let typ = read_u32(data, 12 + i * 8)?;
+        let offset = read_u32(data, 12 + i * 8 + 4)? as usize;
+
+        if offset < index_limit {
+            return Err(format!("index {i} offset {offset} overlaps index vector"));
+        }
+        if offset + 8 > data.len() {
+            return Err(format!("index {i} blob header out of range"));
+        }
+
+        let length = read_u32(data, offset + 4)? as usize;
+        if offset + length > data.len() {
+            return Err(format!("index {i} blob extends past end of superblob"));
+        }
+
+        entries.push((typ, &data[offset..offset + length]));
+    }
+
+    Ok(entries)
+}
+
+/// Validate an embedded signature superblob and every code directory and
+/// requirement set within it.
+pub fn check_embedded_signature(data: &[u8]) -> OracleResult<()> {
+    for (typ, blob) in validate_superblob(data, EMBEDDED_SIGNATURE_MAGIC)? {
+        match read_u32(blob, 0)? {
+            CODE_DIRECTORY_MAGIC => check_code_directory(blob)
+                .map_err(|e| format!("code directory in slot {typ:#x}: {e}"))?,
+            REQUIREMENTS_MAGIC => check_requirement_set(blob)
+                .map_err(|e| format!("requirement set in slot {typ:#x}: {e}"))?,
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Port of `CodeDirectory::stringAt()`: a NUL terminated string within the blob.
+fn string_at(data: &[u8], offset: usize) -> bool {
+    offset >= 8 && offset < data.len() && data[offset..].contains(&0)
+}
+
+/// Port of `BlobCore::contains()`.
+fn contains(data: &[u8], offset: i64, size: i64) -> bool {
+    offset >= 8 && size >= 0 && offset + size <= data.len() as i64
+}
+
+/// Port of `CodeDirectory::checkIntegrity()` and the kernel's geometry checks.
+pub fn check_code_directory(data: &[u8]) -> OracleResult<()> {
+    let data = validate_blob(data, CODE_DIRECTORY_MAGIC, CODE_DIRECTORY_BASE_SIZE)?;
+
+    let version = read_u32(data, 8)?;
+    let hash_offset = read_u32(data, 16)? as i64;
+    let ident_offset = read_u32(data, 20)? as usize;
+    let n_special_slots = read_u32(data, 24)? as i64;
+    let n_code_slots = read_u32(data, 28)? as i64;
+    let code_limit = read_u32(data, 32)? as u64;
+    let hash_size = read_u8(data, 36)? as i64;
+    let hash_type = read_u8(data, 37)?;
+    let page_size = read_u8(data, 39)?;
+
+    if version > COMPATIBILITY_LIMIT {
+        return Err(format!("version {version:#x} is too new"));
+    }
+    if version < EARLIEST_VERSION {
+        return Err(format!("version {version:#x} is too old"));
+    }
+
+    if !string_at(data, ident_offset) {
+        return Err("identifier out of blob range".into());
+    }
+
+    if version >= SUPPORTS_TEAM_ID {
+        let team_offset = read_u32(data, 48)? as usize;
+
+        if team_offset != 0 && !string_at(data, team_offset) {
+            return Err("team identifier out of blob range".into());
+        }
+    }
+
+    if !contains(
+        data,
+        hash_offset - hash_size * n_special_slots,
+        hash_size * (n_special_slots + n_code_slots),
+    ) {
+        return Err("hash array out of blob range".into());
+    }
+
+    if version >= SUPPORTS_SCATTER {
+        let scatter_offset = read_u32(data, 44)? as i64;
+
+        if scatter_offset != 0 {
+            // Each element is (count u32, base u32, target_offset u64, spare u64) and
+            // the vector is terminated by an element with count == 0.
+            let mut offset = scatter_offset;
+            let mut pages = 0i64;
+
+            loop {
+                if !contains(data, offset, 24) {
+                    return Err("scatter vector out of blob range".into());
+                }
+
+                let count = read_u32(data, offset as usize)?;
+                if count == 0 {
+                    break;
+                }
+                pages += count as i64;
+                offset += 24;
+            }
+
+            if !contains(data, hash_offset + hash_size * pages, hash_size) {
+                return Err("scatter vector references too many hash slots".into());
+            }
+        }
+    }
+
+    if version >= SUPPORTS_PRE_ENCRYPT {
+        let pre_encrypt_offset = read_u32(data, 92)? as i64;
+
+        if pre_encrypt_offset != 0 && !contains(data, pre_encrypt_offset, hash_size * n_code_slots)
+        {
+            return Err("pre-encrypt hash array out of blob range".into());
+        }
+    }
+
+    // Digest geometry: the hash type must be known and hashSize must agree with it.
+    let expected_hash_size = match hash_type {
+        // SHA-1, SHA-256 truncated.
+        1 | 3 => 20,
+        // SHA-256.
+        2 => 32,
+        // SHA-384.
+        4 => 48,
+        _ => return Err(format!("unsupported hash type {hash_type}")),
+    };
+    if hash_size != expected_hash_size {
+        return Err(format!(
+            "hash size {hash_size} does not match hash type {hash_type}"
+        ));
+    }
+
+    if !(PAGE_SHIFT_4K..=PAGE_SHIFT_MAX).contains(&page_size) {
+        return Err(format!("unsupported page size shift {page_size}"));
+    }
+
+    // Every byte up to the code limit must be covered by exactly the recorded pages.
+    let code_limit = if version >= SUPPORTS_CODE_LIMIT_64 {
+        match read_u64(data, 56)? {
+            0 => code_limit,
+            limit => limit,
+        }
+    } else {
+        code_limit
+    };
+
+    let page_bytes = 1u64 << page_size;
+    let expected_slots = (code_limit + page_bytes - 1) / page_bytes;
+    if expected_slots != n_code_slots as u64 {
+        return Err(format!(
+            "{n_code_slots} code slots do not cover code limit {code_limit}"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Validate a requirement set superblob and each requirement within.
+pub fn check_requirement_set(data: &[u8]) -> OracleResult<()> {
+    for (typ, blob) in validate_superblob(data, REQUIREMENTS_MAGIC)? {
+        check_requirement(blob).map_err(|e| format!("requirement type {typ}: {e}"))?;
+    }
+
+    Ok(())
+}
+
+/// Validate a requirement blob holding a single expression.
+pub fn check_requirement(data: &[u8]) -> OracleResult<()> {
+    let data = validate_blob(data, REQUIREMENT_MAGIC, 12)?;
+
+    let kind = read_u32(data, 8)?;
+    if kind != REQUIREMENT_EXPR_FORM {
+        return Err(format!("unsupported requirement kind {kind}"));
+    }
+
+    let mut reader = RequirementReader { data, position: 12 };
+    reader.expression(0)?;
+
+    if reader.position != data.len() {
+        return Err(format!(
+            "{} trailing bytes after expression",
+            data.len() - reader.position
+        ));
+    }
+
+    Ok(())
+}
+
+/// Port of the cursor semantics of `Requirement::Reader`.
+struct RequirementReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> RequirementReader<'a> {
+    fn get_u32(&mut self) -> OracleResult<u32> {
+        let v = read_u32(self.data, self.position)?;
+        self.position += 4;
+        Ok(v)
+    }
+
+    fn get_i64(&mut self) -> OracleResult<()> {
+        read_u64(self.data, self.position)?;
+        self.position += 8;
+        Ok(())
+    }
+
+    /// `Reader::getData()`: u32 length, bytes, then padding to a 4 byte boundary.
+    fn get_data(&mut self) -> OracleResult<&'a [u8]> {
+        let length = self.get_u32()? as usize;
+        let end = self.position + length;
+        let aligned = (end + 3) & !3;
+
+        if aligned > self.data.len() {
+            return Err(format!("data of length {length} out of range"));
+        }
+
+        let value = &self.data[self.position..end];
+        self.position = aligned;
+
+        Ok(value)
+    }
+
+    fn get_string(&mut self) -> OracleResult<()> {
+        let value = self.get_data()?;
+
+        std::str::from_utf8(value)
+            .map(|_| ())
+            .map_err(|_| "string operand is not UTF-8".to_string())
+    }
+
+    /// Port of `Dumper::expr()`.
+    fn expression(&mut self, depth: usize) -> OracleResult<()> {
+        if depth > MAX_EXPRESSION_DEPTH {
+            return Err("expression nested too deeply".into());
+        }
+
+        let op = self.get_u32()?;
+
+        match op {
+            // opFalse, opTrue, opAppleAnchor, opTrustedCerts, opAppleGenericAnchor,
+            // opNotarized, opLegacyDevID.
+            0 | 1 | 3 | 13 | 15 | 21 | 23 => Ok(()),
+            // opIdent, opNamedAnchor, opNamedCode.
+            2 | 18 | 19 => self.get_string(),
+            // opAnchorHash.
+            4 => {
+                self.get_u32()?;
+                self.get_data().map(|_| ())
+            }
+            // opInfoKeyValue.
+            5 => {
+                self.get_string()?;
+                self.get_string()
+            }
+            // opAnd, opOr.
+            6 | 7 => {
+                self.expression(depth + 1)?;
+                self.expression(depth + 1)
+            }
+            // opCDHash.
+            8 => self.get_data().map(|_| ()),
+            // opNot.
+            9 => self.expression(depth + 1),
+            // opInfoKeyField, opEntitlementField.
+            10 | 16 => {
+                self.get_string()?;
+                self.match_expression()
+            }
+            // opCertField.
+            11 => {
+                self.get_u32()?;
+                self.get_string()?;
+                self.match_expression()
+            }
+            // opTrustedCert, opPlatform.
+            12 | 20 => self.get_u32().map(|_| ()),
+            // opCertGeneric, opCertPolicy, opCertFieldDate.
+            14 | 17 | 22 => {
+                self.get_u32()?;
+                self.get_data()?;
+                self.match_expression()
+            }
+            op if op & (OP_GENERIC_FALSE | OP_GENERIC_SKIP) != 0 => {
+                // Unknown opcodes flagged as skippable carry their payload size.
+                self.get_data().map(|_| ())
+            }
+            op => Err(format!(
+                "unknown opcode {:#x} (flags {:#x})",
+                op,
+                op & OP_FLAG_MASK
+            )),
+        }
+    }
+
+    /// Port of `Dumper::match()`.
+    fn match_expression(&mut self) -> OracleResult<()> {
+        match self.get_u32()? {
+            // matchExists, matchAbsent.
+            0 | 14 => Ok(()),
+            // matchEqual, matchContains, matchBeginsWith, matchEndsWith,
+            // matchLessThan, matchGreaterThan, matchLessEqual, matchGreaterEqual.
+            1..=8 => self.get_data().map(|_| ()),
+            // matchOn, matchBefore, matchAfter, matchOnOrBefore, matchOnOrAfter.
+            9..=13 => self.get_i64(),
+            op => Err(format!("unknown match operation {op}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            certificate::{create_self_signed_code_signing_certificate, CertificateProfile},
+            code_directory::{CodeDirectoryBlob, ExecutableSegmentFlags},
+            code_requirement::{
+                CodeRequirementExpression, CodeRequirementMatchExpression, CodeRequirements,
+                RequirementType,
+            },
+            embedded_signature::{
+                Blob, CodeSigningSlot, Digest, DigestType, EntitlementsBlob, RequirementSetBlob,
+            },
+            embedded_signature_builder::EmbeddedSignatureBuilder,
+            policy::{derive_designated_requirements, ExecutionPolicy},
+            retry::RetryPolicy,
+        },
+        std::borrow::Cow,
+        x509_certificate::{EcdsaCurve, KeyAlgorithm},
+    };
+
+    fn code_directory(digest_type: DigestType, pages: usize) -> CodeDirectoryBlob<'static> {
+        let digest_size = digest_type.hash_len().unwrap();
+
+        CodeDirectoryBlob {
+            digest_size: digest_size as u8,
+            digest_type,
+            page_size: 4096,
+            code_limit: (pages * 4096 - 100) as u32,
+            ident: Cow::Borrowed("com.example.oracle"),
+            code_digests: (0..pages)
+                .map(|i| Digest {
+                    data: vec![i as u8; digest_size].into(),
+                })
+                .collect::<Vec<_>>()
+                .try_into()
+                .unwrap(),
+            ..Default::default()
+        }
+    }
+
+    /// Code directories exercising each version of the format we emit.
+    fn code_directory_variants(digest_type: DigestType) -> Vec<CodeDirectoryBlob<'static>> {
+        let base = code_directory(digest_type, 3);
+
+        let mut team = base.to_owned();
+        team.team_name = Some("team1".into());
+
+        let mut limit64 = team.to_owned();
+        limit64.code_limit_64 = Some(3 * 4096 - 100);
+
+        let mut exec_seg = team.to_owned();
+        exec_seg.exec_seg_base = Some(0);
+        exec_seg.exec_seg_limit = Some(4096);
+        exec_seg.exec_seg_flags = Some(ExecutableSegmentFlags::MAIN_BINARY);
+
+        let mut runtime = exec_seg.to_owned();
+        runtime.runtime = Some(0x0c0000);
+
+        let mut res = vec![base, team, limit64, exec_seg, runtime];
+        for cd in res.iter_mut() {
+            cd.adjust_version(None);
+            cd.clear_newer_fields();
+        }
+
+        res
+    }
+
+    fn requirement_expressions() -> Vec<CodeRequirementExpression<'static>> {
+        let mut res = vec![
+            (*ExecutionPolicy::DeveloperIdSigned).clone(),
+            (*ExecutionPolicy::DeveloperIdNotarizedExecutable).clone(),
+            (*ExecutionPolicy::DeveloperIdNotarizedInstaller).clone(),
+            CodeRequirementExpression::Not(Box::new(CodeRequirementExpression::Or(
+                Box::new(CodeRequirementExpression::CodeDirectoryHash(
+                    vec![0x42; 20].into(),
+                )),
+                Box::new(CodeRequirementExpression::InfoPlistKeyField(
+                    "CFBundleVersion".into(),
+                    CodeRequirementMatchExpression::GreaterThanEqual("1.0.1".into()),
+                )),
+            ))),
+            CodeRequirementExpression::EntitlementsKey(
+                "com.apple.security.app-sandbox".into(),
+                CodeRequirementMatchExpression::Exists,
+            ),
+            CodeRequirementExpression::Platform(1),
+        ];
+
+        for profile in [
+            CertificateProfile::DeveloperIdApplication,
+            CertificateProfile::AppleDevelopment,
+        ] {
+            let (cert, _, _) = create_self_signed_code_signing_certificate(
+                KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1),
+                profile,
+                "team1",
+                "Joe Developer",
+                "US",
+                chrono::Duration::hours(1),
+            )
+            .unwrap();
+
+            res.push(
+                derive_designated_requirements(&cert, Some("com.example.oracle".into()))
+                    .unwrap()
+                    .unwrap(),
+            );
+        }
+
+        res
+    }
+
+    #[test]
+    fn code_directory_versions() {
+        for digest_type in [DigestType::Sha1, DigestType::Sha256, DigestType::Sha384] {
+            for cd in code_directory_variants(digest_type) {
+                let data = cd.to_blob_bytes().unwrap();
+
+                check_code_directory(&data)
+                    .unwrap_or_else(|e| panic!("{digest_type} version {:#x}: {e}", cd.version));
+            }
+        }
+    }
+
+    #[test]
+    fn requirements() {
+        for expr in requirement_expressions() {
+            let data = CodeRequirements::from(vec![expr.clone()])
+                .to_blob_data()
+                .unwrap();
+
+            check_requirement(&data).unwrap_or_else(|e| panic!("{expr}: {e}"));
+
+            let mut set = RequirementSetBlob::default();
+            CodeRequirements::from(vec![expr.clone()])
+                .add_to_requirement_set(&mut set, RequirementType::Designated)
+                .unwrap();
+
+            check_requirement_set(&set.to_blob_bytes().unwrap())
+                .unwrap_or_else(|e| panic!("{expr}: {e}"));
+        }
+    }
+
+    #[test]
+    fn embedded_signature() {
+        let (cert, key, _) = create_self_signed_code_signing_certificate(
+            KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1),
+            CertificateProfile::DeveloperIdApplication,
+            "team1",
+            "Joe Developer",
+            "US",
+            chrono::Duration::hours(1),
+        )
+        .unwrap();
+
+        let mut requirements = RequirementSetBlob::default();
+        CodeRequirements::from(vec![derive_designated_requirements(
+            &cert,
+            Some("com.example.oracle".into()),
+        )
+        .unwrap()
+        .unwrap()])
+        .add_to_requirement_set(&mut requirements, RequirementType::Designated)
+        .unwrap();
+
+        let mut builder = EmbeddedSignatureBuilder::default();
+        builder
+            .add_blob(CodeSigningSlot::RequirementSet, requirements.into())
+            .unwrap();
+        builder
+            .add_blob(
+                CodeSigningSlot::Entitlements,
+                EntitlementsBlob::from_string(&"<plist/>").into(),
+            )
+            .unwrap();
+
+        let mut cds = code_directory_variants(DigestType::Sha256);
+        builder
+            .add_code_directory(CodeSigningSlot::CodeDirectory, cds.pop().unwrap())
+            .unwrap();
+        let mut alternate = code_directory_variants(DigestType::Sha384).pop().unwrap();
+        alternate.team_name = Some("team1".into());
+        builder.add_alternative_code_directory(alternate).unwrap();
+        builder
+            .create_cms_signature(&key, &cert, None, &RetryPolicy::none(), std::iter::empty())
+            .unwrap();
+
+        check_embedded_signature(&builder.create_superblob().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn rejects_malformed_code_directory() {
+        let cd = code_directory_variants(DigestType::Sha256).pop().unwrap();
+        let data = cd.to_blob_bytes().unwrap();
+
+        // Too old a version.
+        let mut bad = data.clone();
+        bad[8..12].copy_from_slice(&0x20000u32.to_be_bytes());
+        assert!(check_code_directory(&bad).is_err());
+
+        // Identifier offset past end of blob.
+        let mut bad = data.clone();
+        bad[20..24].copy_from_slice(&(data.len() as u32).to_be_bytes());
+        assert!(check_code_directory(&bad).is_err());
+
+        // More code slots than fit in the blob.
+        let mut bad = data.clone();
+        bad[28..32].copy_from_slice(&1000u32.to_be_bytes());
+        assert!(check_code_directory(&bad).is_err());
+
+        // Hash size disagreeing with hash type.
+        let mut bad = data.clone();
+        bad[37] = 1;
+        assert!(check_code_directory(&bad).is_err());
+
+        // Code limit beyond what the code slots cover.
+        let mut bad = data.clone();
+        bad[32..36].copy_from_slice(&(5 * 4096u32).to_be_bytes());
+        bad[56..64].copy_from_slice(&0u64.to_be_bytes());
+        assert!(check_code_directory(&bad).is_err());
+
+        // Length field larger than the data.
+        let mut bad = data;
+        bad.truncate(bad.len() - 1);
+        assert!(check_code_directory(&bad).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_requirements() {
+        let data =
+            CodeRequirements::from(vec![CodeRequirementExpression::Identifier("abc".into())])
+                .to_blob_data()
+                .unwrap();
+        check_requirement(&data).unwrap();
+
+        // Unknown opcode.
+        let mut bad = data.clone();
+        bad[12..16].copy_from_slice(&99u32.to_be_bytes());
+        assert!(check_requirement(&bad).is_err());
+
+        // String length running past the end of the blob.
+        let mut bad = data.clone();
+        bad[16..20].copy_from_slice(&100u32.to_be_bytes());
+        assert!(check_requirement(&bad).is_err());
+
+        // Multiple expressions are not a valid requirement.
+        let data = CodeRequirements::from(vec![
+            CodeRequirementExpression::True,
+            CodeRequirementExpression::False,
+        ])
+        .to_blob_data()
+        .unwrap();
+        assert!(check_requirement(&data).is_err());
+    }
+}
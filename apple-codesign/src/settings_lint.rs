@@ -0,0 +1,534 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Detection of contradictory signing settings.
+//!
+//! Some combinations of [SigningSettings] produce a signature, but not the
+//! signature the user wanted. e.g. requesting a time-stamp for an ad-hoc
+//! signature or signing a notarization candidate with only SHA-1 digests. This
+//! module looks for such combinations so they can be reported before signing
+//! begins.
+
+use {
+    crate::{
+        certificate::SigningDistribution,
+        code_directory::CodeSignatureFlags,
+        embedded_signature::DigestType,
+        entitlements::{validate_entitlements, EntitlementIssueKind},
+        error::AppleCodesignError,
+        macho::{MachFile, Platform},
+        provisioning_profile::ProvisioningProfile,
+        reader::PathType,
+        signing_settings::{SettingsScope, SigningSettings},
+    },
+    apple_bundles::DirectoryBundle,
+    std::{
+        fmt::{Display, Formatter},
+        path::{Path, PathBuf},
+    },
+};
+
+/// Paths of provisioning profiles embedded in bundles.
+///
+/// macOS bundles use the former. Shallow iOS bundles use the latter.
+const EMBEDDED_PROFILE_PATHS: &[&str] = &[
+    "Contents/embedded.provisionprofile",
+    "embedded.mobileprovision",
+];
+
+/// Entitlements keys only meaningful on iOS-like platforms and their macOS equivalents.
+const IOS_ONLY_ENTITLEMENTS: &[(&str, &str)] = &[
+    ("application-identifier", "com.apple.application-identifier"),
+    ("aps-environment", "com.apple.developer.aps-environment"),
+    (
+        "beta-reports-active",
+        "com.apple.developer.beta-reports-active",
+    ),
+    ("get-task-allow", "com.apple.security.get-task-allow"),
+];
+
+/// Entitlements keys granting debugger attachment.
+const GET_TASK_ALLOW_ENTITLEMENTS: &[&str] =
+    &["com.apple.security.get-task-allow", "get-task-allow"];
+
+/// Describes a kind of contradiction in signing settings.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SettingsLintKind {
+    /// A time-stamp server is configured but there is no signing key.
+    AdHocTimeStamp,
+    /// The hardened runtime is enabled alongside the `get-task-allow` entitlement.
+    HardenedRuntimeGetTaskAllow,
+    /// An iOS-only entitlement is being applied to a macOS binary.
+    IosEntitlementOnMacOs,
+    /// An entitlement is unknown, has the wrong value type, or doesn't apply
+    /// to a targeted platform.
+    InvalidEntitlement,
+    /// Only SHA-1 digests are produced for a notarization candidate.
+    Sha1OnlyNotarization,
+    /// The embedded provisioning profile doesn't grant the entitlements,
+    /// identifier, or team being signed with.
+    ProvisioningProfileMismatch,
+}
+
+/// A contradiction found in signing settings.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SettingsLint {
+    /// The kind of contradiction.
+    pub kind: SettingsLintKind,
+    /// The settings scope the contradiction was found in.
+    pub scope: SettingsScope,
+    /// Describes the contradiction.
+    pub message: String,
+    /// Describes how to resolve the contradiction.
+    pub suggestion: String,
+}
+
+impl Display for SettingsLint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "{}: {} ({})",
+            self.scope, self.message, self.suggestion
+        ))
+    }
+}
+
+/// Look for contradictions in [SigningSettings] for signing the entity at `artifact`.
+///
+/// `artifact` is used to resolve the platforms being targeted. Contradictions
+/// not depending on the platform are reported even if `artifact` targets no
+/// platform we recognize.
+///
+/// If `artifact` is a bundle with an embedded provisioning profile, the main
+/// entitlements, identifier, and team are checked against the profile.
+///
+/// An empty vector means no contradictions were found.
+pub fn lint_settings(
+    settings: &SigningSettings,
+    artifact: impl AsRef<Path>,
+) -> Result<Vec<SettingsLint>, AppleCodesignError> {
+    let artifact = artifact.as_ref();
+
+    let mut lints = lint_settings_for_platforms(settings, &resolve_platforms(artifact)?);
+
+    if let Some((profile_path, identifier)) = resolve_embedded_profile(artifact)? {
+        let profile = ProvisioningProfile::from_path(&profile_path)?;
+
+        lints.extend(lint_provisioning_profile(
+            settings,
+            &profile,
+            identifier.as_deref(),
+        ));
+    }
+
+    Ok(lints)
+}
+
+/// Look for problems signing with the entitlements of the main scope under a provisioning profile.
+///
+/// `identifier` is the bundle identifier, used if the settings don't override it.
+fn lint_provisioning_profile(
+    settings: &SigningSettings,
+    profile: &ProvisioningProfile,
+    identifier: Option<&str>,
+) -> Vec<SettingsLint> {
+    let empty = plist::Value::Dictionary(plist::Dictionary::new());
+    let entitlements = settings
+        .entitlements_plist(SettingsScope::Main)
+        .unwrap_or(&empty);
+    let identifier = settings
+        .binary_identifier(SettingsScope::Main)
+        .or(identifier);
+
+    profile
+        .check_entitlements(entitlements, identifier, settings.team_id())
+        .into_iter()
+        .map(|issue| SettingsLint {
+            kind: SettingsLintKind::ProvisioningProfileMismatch,
+            scope: SettingsScope::Main,
+            message: issue.to_string(),
+            suggestion: "the application will not launch; regenerate the profile with the \
+                needed capabilities or change the signing settings"
+                .into(),
+        })
+        .collect()
+}
+
+fn lint_settings_for_platforms(
+    settings: &SigningSettings,
+    platforms: &[Platform],
+) -> Vec<SettingsLint> {
+    let mut lints = vec![];
+
+    if settings.signing_key().is_none() && settings.time_stamp_url().is_some() {
+        lints.push(SettingsLint {
+            kind: SettingsLintKind::AdHocTimeStamp,
+            scope: SettingsScope::Main,
+            message: "time-stamp server configured for ad-hoc signature".into(),
+            suggestion: "ad-hoc signatures are not time-stamped; specify a signing key or \
+                don't request a time-stamp"
+                .into(),
+        });
+    }
+
+    if settings.signing_distribution() == Some(SigningDistribution::DeveloperId)
+        && settings.all_digests(SettingsScope::Main) == vec![DigestType::Sha1]
+    {
+        lints.push(SettingsLint {
+            kind: SettingsLintKind::Sha1OnlyNotarization,
+            scope: SettingsScope::Main,
+            message: "only SHA-1 digests requested for Developer ID distribution".into(),
+            suggestion: "Apple's notary service requires SHA-256 code directories; use \
+                SHA-256 as the digest or add it as an extra digest"
+                .into(),
+        });
+    }
+
+    let targets_macos = platforms
+        .iter()
+        .any(|platform| matches!(platform, Platform::MacOs));
+
+    for scope in settings.configured_scopes() {
+        let runtime = settings
+            .code_signature_flags(&scope)
+            .map(|flags| flags.contains(CodeSignatureFlags::RUNTIME))
+            .unwrap_or_default();
+
+        let Some(entitlements_value) = settings.entitlements_plist(&scope) else {
+            continue;
+        };
+        // Entitlements that aren't a dictionary are reported by validate_entitlements().
+        let empty = plist::Dictionary::new();
+        let entitlements = entitlements_value.as_dictionary().unwrap_or(&empty);
+
+        if runtime {
+            for key in GET_TASK_ALLOW_ENTITLEMENTS {
+                if matches!(entitlements.get(key), Some(plist::Value::Boolean(true))) {
+                    lints.push(SettingsLint {
+                        kind: SettingsLintKind::HardenedRuntimeGetTaskAllow,
+                        scope: scope.clone(),
+                        message: format!("hardened runtime enabled with {key} entitlement"),
+                        suggestion: format!(
+                            "{key} allows debuggers to attach and is rejected by notarization; \
+                            remove it for distribution builds"
+                        ),
+                    });
+                }
+            }
+        }
+
+        if targets_macos {
+            for (ios_key, macos_key) in IOS_ONLY_ENTITLEMENTS {
+                if entitlements.contains_key(ios_key) {
+                    lints.push(SettingsLint {
+                        kind: SettingsLintKind::IosEntitlementOnMacOs,
+                        scope: scope.clone(),
+                        message: format!("iOS entitlement {ios_key} applied to macOS binary"),
+                        suggestion: format!("use the macOS entitlement {macos_key} instead"),
+                    });
+                }
+            }
+        }
+
+        for issue in validate_entitlements(entitlements_value, platforms) {
+            let suggestion = match issue.kind {
+                EntitlementIssueKind::UnknownKey(Some(known)) => {
+                    format!("the key is likely misspelled; use {known}")
+                }
+                EntitlementIssueKind::UnknownKey(None) => {
+                    "the entitlement is not recognized by this tool; check the key".into()
+                }
+                EntitlementIssueKind::WrongValueType(_) => {
+                    "entitlements with the wrong value type are ignored; fix the value".into()
+                }
+                EntitlementIssueKind::UnsupportedPlatform(_) => {
+                    // iOS entitlements on macOS are reported with their replacement above.
+                    if IOS_ONLY_ENTITLEMENTS
+                        .iter()
+                        .any(|(ios_key, _)| *ios_key == issue.key)
+                    {
+                        continue;
+                    }
+
+                    "remove the entitlement or sign separately for each platform".into()
+                }
+                EntitlementIssueKind::NonDerValue => {
+                    "only booleans, integers, strings, arrays, and dictionaries are allowed".into()
+                }
+                EntitlementIssueKind::NotDictionary => {
+                    "the root of the entitlements plist must be a <dict>".into()
+                }
+            };
+
+            lints.push(SettingsLint {
+                kind: SettingsLintKind::InvalidEntitlement,
+                scope: scope.clone(),
+                message: issue.to_string(),
+                suggestion,
+            });
+        }
+    }
+
+    lints
+}
+
+/// Resolve the provisioning profile embedded in the bundle at a path.
+///
+/// Returns the path of the profile and the bundle identifier.
+fn resolve_embedded_profile(
+    path: &Path,
+) -> Result<Option<(PathBuf, Option<String>)>, AppleCodesignError> {
+    if PathType::from_path(path)? != PathType::Bundle {
+        return Ok(None);
+    }
+
+    let Some(profile_path) = EMBEDDED_PROFILE_PATHS
+        .iter()
+        .map(|relative| path.join(relative))
+        .find(|candidate| candidate.is_file())
+    else {
+        return Ok(None);
+    };
+
+    let identifier = DirectoryBundle::new_from_path(path)
+        .ok()
+        .and_then(|bundle| bundle.identifier().ok().flatten());
+
+    Ok(Some((profile_path, identifier)))
+}
+
+/// Resolve the platforms targeted by the entity at a path.
+///
+/// Bundles resolve to the platforms of their main executable. Entities without
+/// Mach-O targeting information resolve to an empty vector.
+fn resolve_platforms(path: &Path) -> Result<Vec<Platform>, AppleCodesignError> {
+    let macho_path = match PathType::from_path(path)? {
+        PathType::MachO => path.to_path_buf(),
+        PathType::Bundle => {
+            let bundle = DirectoryBundle::new_from_path(path)
+                .map_err(AppleCodesignError::DirectoryBundle)?;
+
+            match bundle
+                .files(false)
+                .map_err(AppleCodesignError::DirectoryBundle)?
+                .into_iter()
+                .find(|file| matches!(file.is_main_executable(), Ok(true)))
+            {
+                Some(file) => file.absolute_path().to_path_buf(),
+                None => return Ok(vec![]),
+            }
+        }
+        PathType::Dmg | PathType::Xar | PathType::Zip | PathType::Other => return Ok(vec![]),
+    };
+
+    let data = std::fs::read(macho_path)?;
+    let mach = MachFile::parse(&data)?;
+
+    let mut platforms = vec![];
+
+    for macho in mach.iter_macho() {
+        if let Some(target) = macho.find_targeting()? {
+            platforms.push(target.platform);
+        }
+    }
+
+    Ok(platforms)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lint_kinds(settings: &SigningSettings) -> Vec<SettingsLintKind> {
+        lint_settings_for_platforms(settings, &[Platform::MacOs])
+            .into_iter()
+            .map(|lint| lint.kind)
+            .collect()
+    }
+
+    #[test]
+    fn default_settings() {
+        assert!(lint_kinds(&SigningSettings::default()).is_empty());
+    }
+
+    #[test]
+    fn adhoc_time_stamp() -> Result<(), AppleCodesignError> {
+        let mut settings = SigningSettings::default();
+        settings.set_time_stamp_url("http://timestamp.apple.com/ts01")?;
+
+        // Resolving platforms from a path not holding Mach-O data finds none.
+        let fh = tempfile::NamedTempFile::new()?;
+        let lints = lint_settings(&settings, fh.path())?;
+
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].kind, SettingsLintKind::AdHocTimeStamp);
+        assert_eq!(lints[0].scope, SettingsScope::Main);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sha1_only_notarization() {
+        let mut settings = SigningSettings::default();
+        settings.set_digest_type(DigestType::Sha1);
+        assert!(lint_kinds(&settings).is_empty());
+
+        settings.set_signing_distribution(Some(SigningDistribution::DeveloperId));
+        assert_eq!(
+            lint_kinds(&settings),
+            vec![SettingsLintKind::Sha1OnlyNotarization]
+        );
+
+        settings.add_extra_digest(SettingsScope::Main, DigestType::Sha256);
+        assert!(lint_kinds(&settings).is_empty());
+    }
+
+    #[test]
+    fn entitlements() -> Result<(), AppleCodesignError> {
+        let scope = SettingsScope::Path("Contents/MacOS/helper".into());
+
+        let mut settings = SigningSettings::default();
+        settings.set_entitlements_xml(
+            scope.clone(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>get-task-allow</key>
+    <true/>
+</dict>
+</plist>"#,
+        )?;
+
+        assert_eq!(
+            lint_kinds(&settings),
+            vec![SettingsLintKind::IosEntitlementOnMacOs]
+        );
+        assert!(lint_settings_for_platforms(&settings, &[Platform::IOs]).is_empty());
+
+        settings.add_code_signature_flags(scope.clone(), CodeSignatureFlags::RUNTIME);
+
+        let lints = lint_settings_for_platforms(&settings, &[Platform::MacOs]);
+        assert_eq!(
+            lints.iter().map(|lint| lint.kind).collect::<Vec<_>>(),
+            vec![
+                SettingsLintKind::HardenedRuntimeGetTaskAllow,
+                SettingsLintKind::IosEntitlementOnMacOs
+            ]
+        );
+        assert!(lints.iter().all(|lint| lint.scope == scope));
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_entitlements() -> Result<(), AppleCodesignError> {
+        let mut settings = SigningSettings::default();
+        settings.set_entitlements_xml(
+            SettingsScope::Main,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>com.apple.security.app-sandbox</key>
+    <true/>
+    <key>com.apple.security.cs.disable-library-validaton</key>
+    <true/>
+</dict>
+</plist>"#,
+        )?;
+
+        let lints = lint_settings_for_platforms(&settings, &[Platform::MacOs]);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].kind, SettingsLintKind::InvalidEntitlement);
+        assert_eq!(
+            lints[0].suggestion,
+            "the key is likely misspelled; use com.apple.security.cs.disable-library-validation"
+        );
+
+        assert_eq!(
+            lint_settings_for_platforms(&settings, &[Platform::IOs])
+                .into_iter()
+                .map(|lint| lint.kind)
+                .collect::<Vec<_>>(),
+            vec![
+                SettingsLintKind::InvalidEntitlement,
+                SettingsLintKind::InvalidEntitlement
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn provisioning_profile() -> Result<(), AppleCodesignError> {
+        let profile = ProvisioningProfile::from_plist_data(
+            br#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>Name</key>
+    <string>Example</string>
+    <key>TeamIdentifier</key>
+    <array>
+        <string>ABCDE12345</string>
+    </array>
+    <key>Entitlements</key>
+    <dict>
+        <key>application-identifier</key>
+        <string>ABCDE12345.com.example.app</string>
+    </dict>
+</dict>
+</plist>"#,
+        )?;
+
+        let mut settings = SigningSettings::default();
+        settings.set_team_id("ABCDE12345");
+        assert!(lint_provisioning_profile(&settings, &profile, Some("com.example.app")).is_empty());
+
+        settings.set_entitlements_xml(
+            SettingsScope::Main,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>aps-environment</key>
+    <string>production</string>
+</dict>
+</plist>"#,
+        )?;
+        settings.set_binary_identifier(SettingsScope::Main, "org.example.app");
+
+        let lints = lint_provisioning_profile(&settings, &profile, Some("com.example.app"));
+        assert_eq!(lints.len(), 2);
+        assert!(lints
+            .iter()
+            .all(|lint| lint.kind == SettingsLintKind::ProvisioningProfileMismatch));
+        assert!(lints[0].message.contains("org.example.app"));
+        assert!(lints[1].message.contains("aps-environment"));
+
+        let td = tempfile::tempdir()?;
+        let bundle = td.path().join("App.app");
+        std::fs::create_dir_all(bundle.join("Contents"))?;
+        std::fs::write(
+            bundle.join("Contents/Info.plist"),
+            br#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>CFBundleIdentifier</key>
+    <string>com.example.app</string>
+</dict>
+</plist>"#,
+        )?;
+        assert_eq!(resolve_embedded_profile(&bundle)?, None);
+
+        std::fs::write(bundle.join("Contents/embedded.provisionprofile"), b"")?;
+        assert_eq!(
+            resolve_embedded_profile(&bundle)?,
+            Some((
+                bundle.join("Contents/embedded.provisionprofile"),
+                Some("com.example.app".into())
+            ))
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,306 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Detection of signature practices Apple is phasing out.
+//!
+//! Apple periodically tightens what its operating systems accept. Signatures
+//! with only SHA-1 code directories and entitlements lacking a DER encoding are
+//! accepted today but are deprecated, and macOS 14 recommends launch
+//! constraints for code launched by `launchd`. This module examines existing
+//! signatures for such practices so they can be fixed before an OS update
+//! starts rejecting them.
+
+use {
+    crate::{
+        embedded_signature::{CodeSigningSlot, DigestType},
+        error::AppleCodesignError,
+        macho::MachFile,
+        reader::PathType,
+    },
+    apple_bundles::DirectoryBundle,
+    std::{
+        fmt::{Display, Formatter},
+        path::Path,
+    },
+};
+
+/// Bundle directories holding executables launched by `launchd`.
+const LAUNCHD_DIRECTORIES: &[&str] = &[
+    "Contents/Library/LaunchAgents/",
+    "Contents/Library/LaunchDaemons/",
+    "Contents/Library/LaunchServices/",
+];
+
+/// Describes a kind of deprecated or discouraged signature practice.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RecommendationKind {
+    /// The signature only has SHA-1 code directories.
+    Sha1OnlyCodeDirectory,
+    /// Entitlements are only present in XML form.
+    XmlOnlyEntitlements,
+    /// A `launchd` executable doesn't have launch constraints.
+    MissingLaunchConstraints,
+}
+
+/// A signature practice that should be changed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SignatureRecommendation {
+    /// The kind of practice.
+    pub kind: RecommendationKind,
+    /// Path of the Mach-O binary having the signature.
+    ///
+    /// For bundles, this is relative to the bundle root.
+    pub path: String,
+    /// Index of the Mach-O within a universal binary.
+    pub index: Option<usize>,
+    /// Describes the practice.
+    pub message: String,
+    /// Describes how to change the practice.
+    pub suggestion: String,
+}
+
+impl Display for SignatureRecommendation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(index) = self.index {
+            f.write_fmt(format_args!("{} (slice {}): ", self.path, index))?;
+        } else {
+            f.write_fmt(format_args!("{}: ", self.path))?;
+        }
+
+        f.write_fmt(format_args!("{} ({})", self.message, self.suggestion))
+    }
+}
+
+/// Look for deprecated signature practices in the entity at `path`.
+///
+/// Mach-O binaries and bundles are examined. Unsigned Mach-O binaries and other
+/// entity types have nothing to report.
+///
+/// An empty vector means no deprecated practices were found.
+pub fn signature_recommendations(
+    path: impl AsRef<Path>,
+) -> Result<Vec<SignatureRecommendation>, AppleCodesignError> {
+    let path = path.as_ref();
+
+    match PathType::from_path(path)? {
+        PathType::MachO => {
+            let data = std::fs::read(path)?;
+
+            macho_recommendations(&data, &path.display().to_string(), false)
+        }
+        PathType::Bundle => {
+            let bundle = DirectoryBundle::new_from_path(path)
+                .map_err(AppleCodesignError::DirectoryBundle)?;
+
+            let mut res = vec![];
+
+            for file in bundle
+                .files(true)
+                .map_err(AppleCodesignError::DirectoryBundle)?
+            {
+                if file
+                    .symlink_target()
+                    .map_err(AppleCodesignError::DirectoryBundle)?
+                    .is_some()
+                    || !matches!(PathType::from_path(file.absolute_path())?, PathType::MachO)
+                {
+                    continue;
+                }
+
+                let rel = file.relative_path().to_string_lossy().replace('\\', "/");
+                let launchd = LAUNCHD_DIRECTORIES
+                    .iter()
+                    .any(|prefix| rel.starts_with(prefix));
+
+                let data = std::fs::read(file.absolute_path())?;
+                res.extend(macho_recommendations(&data, &rel, launchd)?);
+            }
+
+            Ok(res)
+        }
+        PathType::Dmg | PathType::Xar | PathType::Zip | PathType::Other => Ok(vec![]),
+    }
+}
+
+/// Look for deprecated signature practices in Mach-O data.
+///
+/// `launchd` says whether the binary is launched by `launchd`, which makes
+/// launch constraints recommended for executables.
+fn macho_recommendations(
+    data: &[u8],
+    path: &str,
+    launchd: bool,
+) -> Result<Vec<SignatureRecommendation>, AppleCodesignError> {
+    let mach = MachFile::parse(data)?;
+
+    let mut res = vec![];
+
+    for macho in mach.iter_macho() {
+        let Some(signature) = macho.code_signature()? else {
+            continue;
+        };
+
+        let mut push = |kind, message: &str, suggestion: &str| {
+            res.push(SignatureRecommendation {
+                kind,
+                path: path.to_string(),
+                index: macho.index,
+                message: message.into(),
+                suggestion: suggestion.into(),
+            });
+        };
+
+        let digests = signature
+            .all_code_directories()?
+            .into_iter()
+            .map(|(_, cd)| cd.digest_type)
+            .collect::<Vec<_>>();
+
+        if !digests.is_empty() && digests.iter().all(|digest| *digest == DigestType::Sha1) {
+            push(
+                RecommendationKind::Sha1OnlyCodeDirectory,
+                "signature only has SHA-1 code directories",
+                "SHA-1 code directories are deprecated; re-sign with SHA-256 as the digest \
+                or an extra digest",
+            );
+        }
+
+        // Like `codesign`, we only emit DER entitlements for executables.
+        if macho.is_executable()
+            && signature.find_slot(CodeSigningSlot::Entitlements).is_some()
+            && signature
+                .find_slot(CodeSigningSlot::EntitlementsDer)
+                .is_none()
+        {
+            push(
+                RecommendationKind::XmlOnlyEntitlements,
+                "entitlements are only present as XML",
+                "recent operating systems require DER entitlements; re-sign to add them",
+            );
+        }
+
+        if launchd
+            && macho.is_executable()
+            && [
+                CodeSigningSlot::LaunchConstraintsSelf,
+                CodeSigningSlot::LaunchConstraintsParent,
+                CodeSigningSlot::LaunchConstraintsResponsible,
+            ]
+            .into_iter()
+            .all(|slot| signature.find_slot(slot).is_none())
+        {
+            push(
+                RecommendationKind::MissingLaunchConstraints,
+                "executable launched by launchd has no launch constraints",
+                "macOS 14 recommends launch constraints so only launchd can launch it",
+            );
+        }
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            macho_signing::MachOSigner,
+            signing_settings::{SettingsScope, SigningSettings},
+        },
+        goblin::mach::header::{MH_BUNDLE, MH_EXECUTE},
+    };
+
+    const ENTITLEMENTS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>com.apple.security.app-sandbox</key>
+    <true/>
+</dict>
+</plist>"#;
+
+    /// Offset of the file type in a Mach-O header.
+    const FILETYPE_OFFSET: usize = 12;
+
+    fn sign(settings: &SigningSettings, filetype: u32) -> Result<Vec<u8>, AppleCodesignError> {
+        let mut macho = crate::test_support::SyntheticMachO::default().build();
+        macho[FILETYPE_OFFSET..FILETYPE_OFFSET + 4].copy_from_slice(&filetype.to_le_bytes());
+
+        let signer = MachOSigner::new(&macho)?;
+        let mut signed = vec![];
+        signer.write_signed_binary(settings, &mut signed)?;
+
+        Ok(signed)
+    }
+
+    fn kinds(data: &[u8], launchd: bool) -> Result<Vec<RecommendationKind>, AppleCodesignError> {
+        Ok(macho_recommendations(data, "binary", launchd)?
+            .into_iter()
+            .map(|rec| rec.kind)
+            .collect())
+    }
+
+    #[test]
+    fn unsigned() -> Result<(), AppleCodesignError> {
+        let macho = crate::test_support::SyntheticMachO::default().build();
+        assert!(kinds(&macho, true)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn digests() -> Result<(), AppleCodesignError> {
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(SettingsScope::Main, "binary");
+        assert!(kinds(&sign(&settings, MH_EXECUTE)?, false)?.is_empty());
+
+        settings.set_digest_type(DigestType::Sha1);
+        let signed = sign(&settings, MH_EXECUTE)?;
+        assert_eq!(
+            kinds(&signed, false)?,
+            vec![RecommendationKind::Sha1OnlyCodeDirectory]
+        );
+        assert_eq!(
+            macho_recommendations(&signed, "binary", false)?[0].to_string(),
+            "binary: signature only has SHA-1 code directories (SHA-1 code directories are \
+            deprecated; re-sign with SHA-256 as the digest or an extra digest)"
+        );
+
+        settings.add_extra_digest(SettingsScope::Main, DigestType::Sha256);
+        assert!(kinds(&sign(&settings, MH_EXECUTE)?, false)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn entitlements_and_launch_constraints() -> Result<(), AppleCodesignError> {
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(SettingsScope::Main, "binary");
+        settings.set_entitlements_xml(SettingsScope::Main, ENTITLEMENTS)?;
+
+        // DER entitlements are emitted by default.
+        let signed = sign(&settings, MH_EXECUTE)?;
+        assert!(kinds(&signed, false)?.is_empty());
+
+        // Synthetic binaries are executables.
+        assert_eq!(
+            kinds(&signed, true)?,
+            vec![RecommendationKind::MissingLaunchConstraints]
+        );
+
+        // Signing a bundle doesn't emit DER entitlements, simulating an old signer.
+        // Marking it as an executable afterwards invalidates the code digests but
+        // those don't matter here.
+        let mut signed = sign(&settings, MH_BUNDLE)?;
+        assert!(kinds(&signed, false)?.is_empty());
+        signed[FILETYPE_OFFSET..FILETYPE_OFFSET + 4].copy_from_slice(&MH_EXECUTE.to_le_bytes());
+        assert_eq!(
+            kinds(&signed, false)?,
+            vec![RecommendationKind::XmlOnlyEntitlements]
+        );
+
+        Ok(())
+    }
+}
@@ -7,10 +7,16 @@
 use {
     crate::{
         bundle_signing::BundleSigner,
+        certificate::{AppleCertificate, SignedEntityKind},
         dmg::DmgSigner,
         error::AppleCodesignError,
+        identifier_collisions::identifier_collisions,
+        info_plist::InfoPlist,
+        macho::MachFile,
         macho_signing::{write_macho_file, MachOSigner},
+        mapped_file::MappedFile,
         reader::PathType,
+        settings_lint::lint_settings,
         signing_settings::{SettingsScope, SigningSettings},
     },
     apple_xar::{reader::XarReader, signing::XarSigner},
@@ -29,7 +35,54 @@ impl<'key> UnifiedSigner<'key> {
         Self { settings }
     }
 
+    /// Whether [Self::sign_path()] can sign entities of a given type.
+    pub(crate) fn supports_path_type(kind: PathType) -> bool {
+        match kind {
+            PathType::Bundle | PathType::Dmg | PathType::MachO | PathType::Xar => true,
+            PathType::Zip | PathType::Other => false,
+        }
+    }
+
+    /// Validate the signing certificate is suitable for signing entities of a given kind.
+    ///
+    /// No-op if there is no signing certificate (ad-hoc signing) or if validation is
+    /// disabled.
+    fn validate_signing_certificate(
+        &self,
+        kind: SignedEntityKind,
+    ) -> Result<(), AppleCodesignError> {
+        if self.settings.skip_certificate_validation() {
+            return Ok(());
+        }
+
+        let scoped = self
+            .settings
+            .scoped_signing_certificates()
+            .map(|(_, cert)| cert);
+
+        for cert in self
+            .settings
+            .signing_key()
+            .map(|(_, cert)| cert)
+            .into_iter()
+            .chain(scoped)
+        {
+            cert.apple_validate_for_signing(
+                kind,
+                self.settings.signing_distribution(),
+                chrono::Utc::now(),
+            )?;
+        }
+
+        Ok(())
+    }
+
     /// Signs `input_path` and writes the signed output to `output_path`.
+    ///
+    /// Contradictions found by [lint_settings()] and, for bundles, duplicate
+    /// identifiers found by [identifier_collisions()] are logged as warnings
+    /// before signing begins. Failures to run these checks are logged too and
+    /// don't abort signing.
     pub fn sign_path(
         &self,
         input_path: impl AsRef<Path>,
@@ -37,8 +90,28 @@ impl<'key> UnifiedSigner<'key> {
     ) -> Result<(), AppleCodesignError> {
         let input_path = input_path.as_ref();
 
+        match lint_settings(&self.settings, input_path) {
+            Ok(lints) => {
+                for lint in lints {
+                    warn!("signing settings problem: {}", lint);
+                }
+            }
+            Err(e) => warn!("unable to check signing settings: {}", e),
+        }
+
         match PathType::from_path(input_path)? {
-            PathType::Bundle => self.sign_bundle(input_path, output_path),
+            PathType::Bundle => {
+                match identifier_collisions(&self.settings, input_path) {
+                    Ok(collisions) => {
+                        for collision in collisions {
+                            warn!("signing identifier collision: {}", collision);
+                        }
+                    }
+                    Err(e) => warn!("unable to check signing identifiers: {}", e),
+                }
+
+                self.sign_bundle(input_path, output_path)
+            }
             PathType::Dmg => self.sign_dmg(input_path, output_path),
             PathType::MachO => self.sign_macho(input_path, output_path),
             PathType::Xar => self.sign_xar(input_path, output_path),
@@ -66,35 +139,142 @@ impl<'key> UnifiedSigner<'key> {
         let output_path = output_path.as_ref();
 
         warn!("signing {} as a Mach-O binary", input_path.display());
-        let macho_data = std::fs::read(input_path)?;
+
+        let identifier = input_path
+            .file_name()
+            .ok_or_else(|| {
+                AppleCodesignError::CliGeneralError("unable to resolve file name of binary".into())
+            })?
+            .to_string_lossy();
 
         let mut settings = self.settings.clone();
+        settings.load_info_plist_paths(input_path)?;
+
+        // The input is mapped, so it must be unmapped before we write to a
+        // possibly identical output path.
+        let macho_data = {
+            // SAFETY: we don't modify the input while it is mapped.
+            let macho_data = unsafe { MappedFile::open(input_path)? };
+            self.sign_macho_data_with_settings(settings, &macho_data, Some(&identifier))?
+        };
+        warn!("writing Mach-O to {}", output_path.display());
+        write_macho_file(input_path, output_path, &macho_data)?;
 
-        settings.import_settings_from_macho(&macho_data)?;
+        Ok(())
+    }
 
+    /// Sign Mach-O binary data held in memory.
+    ///
+    /// This is useful for signing Mach-O payloads that aren't backed by a standalone
+    /// file, such as extension modules embedded in an application's resources which
+    /// are materialized at run-time. Signing these payloads ahead of time allows
+    /// them to be loaded by processes having the hardened runtime enabled.
+    ///
+    /// The binary identifier comes from the settings, the Mach-O's existing signature,
+    /// or the `CFBundleIdentifier` of an explicit or embedded `Info.plist`. Failing
+    /// those, dylibs derive it from their install name and other binaries from
+    /// `default_identifier`, typically the file name of the data. Signing fails with
+    /// [AppleCodesignError::NoIdentifier] if no identifier can be derived.
+    ///
+    /// `Info.plist` paths in the settings aren't read since there is no binary path
+    /// to resolve them against. Use [SigningSettings::set_info_plist_data()] instead.
+    ///
+    /// Returns the signed Mach-O data.
+    ///
+    /// Packaging tools such as `tugger-apple-codesign` (used by PyOxidizer to
+    /// embed Python extension modules in packed resources) are expected to call
+    /// this on each resource whose data parses as a Mach-O, passing the
+    /// resource's file name as the default identifier, and to store the returned
+    /// data in place of the original:
+    ///
+    /// ```no_run
+    /// use apple_codesign::{AppleCodesignError, MachFile, SigningSettings, UnifiedSigner};
+    ///
+    /// fn presign_resource(
+    ///     signer: &UnifiedSigner,
+    ///     name: &str,
+    ///     data: &mut Vec<u8>,
+    /// ) -> Result<(), AppleCodesignError> {
+    ///     if MachFile::parse(data).is_ok() {
+    ///         *data = signer.sign_macho_data(data, Some(name))?;
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    ///
+    /// let signer = UnifiedSigner::new(SigningSettings::default());
+    /// let mut data = std::fs::read("_ssl.cpython-311-darwin.so")?;
+    /// presign_resource(&signer, "_ssl.cpython-311-darwin.so", &mut data)?;
+    /// # Ok::<(), AppleCodesignError>(())
+    /// ```
+    pub fn sign_macho_data(
+        &self,
+        macho_data: &[u8],
+        default_identifier: Option<&str>,
+    ) -> Result<Vec<u8>, AppleCodesignError> {
+        self.sign_macho_data_with_settings(self.settings.clone(), macho_data, default_identifier)
+    }
+
+    fn sign_macho_data_with_settings(
+        &self,
+        mut settings: SigningSettings<'key>,
+        macho_data: &[u8],
+        default_identifier: Option<&str>,
+    ) -> Result<Vec<u8>, AppleCodesignError> {
+        self.validate_signing_certificate(SignedEntityKind::Code)?;
+
+        settings.import_settings_from_macho(macho_data)?;
+
+        // Like Apple's tooling, prefer the CFBundleIdentifier of an explicit or
+        // embedded Info.plist over the default identifier.
         if settings.binary_identifier(SettingsScope::Main).is_none() {
-            let identifier = input_path
-                .file_name()
-                .ok_or_else(|| {
-                    AppleCodesignError::CliGeneralError(
-                        "unable to resolve file name of binary".into(),
-                    )
-                })?
-                .to_string_lossy();
+            let info_plist = settings
+                .info_plist_data(SettingsScope::Main)
+                .or_else(|| settings.info_plist_data(SettingsScope::MultiArchIndex(0)))
+                .and_then(|data| InfoPlist::from_data(data.to_vec()).ok());
+
+            if let Some(identifier) = info_plist.as_ref().and_then(|info| info.identifier()) {
+                warn!(
+                    "setting binary identifier to {} (derived from CFBundleIdentifier in Info.plist)",
+                    identifier
+                );
+                settings.set_binary_identifier(SettingsScope::Main, identifier);
+            }
+        }
 
-            warn!("setting binary identifier to {}", identifier);
-            settings.set_binary_identifier(SettingsScope::Main, identifier);
+        if settings.binary_identifier(SettingsScope::Main).is_none() {
+            let mach = MachFile::parse(macho_data)?;
+            let macho = mach.nth_macho(0)?;
+            let file_type = settings
+                .macho_file_type(SettingsScope::Main)
+                .unwrap_or_else(|| macho.file_type());
+            let identifier =
+                macho.default_identifier(file_type, default_identifier.unwrap_or_default());
+
+            if !identifier.is_empty() {
+                warn!("setting binary identifier to {}", identifier);
+                settings.set_binary_identifier(SettingsScope::Main, identifier);
+            } else if mach.iter_macho().any(|macho| {
+                settings
+                    .as_nested_macho_settings(
+                        macho.index.unwrap_or(0),
+                        macho.macho.header.cputype(),
+                        macho.macho.header.cpusubtype(),
+                    )
+                    .binary_identifier(SettingsScope::Main)
+                    .is_none()
+            }) {
+                return Err(AppleCodesignError::NoIdentifier);
+            }
         }
 
         warn!("parsing Mach-O");
-        let signer = MachOSigner::new(&macho_data)?;
+        let signer = MachOSigner::new(macho_data)?;
 
-        let mut macho_data = vec![];
-        signer.write_signed_binary(&settings, &mut macho_data)?;
-        warn!("writing Mach-O to {}", output_path.display());
-        write_macho_file(input_path, output_path, &macho_data)?;
+        let mut signed_data = vec![];
+        signer.write_signed_binary(&settings, &mut signed_data)?;
 
-        Ok(())
+        Ok(signed_data)
     }
 
     /// Sign a `.dmg` file.
@@ -107,6 +287,7 @@ impl<'key> UnifiedSigner<'key> {
         let output_path = output_path.as_ref();
 
         warn!("signing {} as a DMG", input_path.display());
+        self.validate_signing_certificate(SignedEntityKind::Code)?;
 
         // There must be a binary identifier on the DMG. So try to derive one
         // from the filename if one isn't present in the settings.
@@ -162,6 +343,7 @@ impl<'key> UnifiedSigner<'key> {
     ) -> Result<(), AppleCodesignError> {
         let input_path = input_path.as_ref();
         warn!("signing bundle at {}", input_path.display());
+        self.validate_signing_certificate(SignedEntityKind::Code)?;
 
         let signer = BundleSigner::new_from_path(input_path)?;
         signer.write_signed_bundle(output_path, &self.settings)?;
@@ -197,6 +379,7 @@ impl<'key> UnifiedSigner<'key> {
             .settings
             .signing_key()
             .ok_or(AppleCodesignError::XarNoAdhoc)?;
+        self.validate_signing_certificate(SignedEntityKind::InstallerPackage)?;
 
         {
             let reader = XarReader::new(File::open(input_path)?)?;
@@ -227,3 +410,43 @@ impl<'key> UnifiedSigner<'key> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::test_support::SyntheticMachO};
+
+    #[test]
+    fn sign_macho_data() -> Result<(), AppleCodesignError> {
+        let unsigned = SyntheticMachO::default().build();
+
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(SettingsScope::Main, "com.example.signing");
+        let mut expected = vec![];
+        MachOSigner::new(&unsigned)?.write_signed_binary(&settings, &mut expected)?;
+
+        // The default identifier is used when nothing else provides one.
+        let signer = UnifiedSigner::new(SigningSettings::default());
+        assert_eq!(
+            signer.sign_macho_data(&unsigned, Some("com.example.signing"))?,
+            expected
+        );
+
+        // The identifier in the settings takes precedence.
+        let signer = UnifiedSigner::new(settings);
+        assert_eq!(
+            signer.sign_macho_data(&unsigned, Some("ignored"))?,
+            expected
+        );
+
+        // As does the identifier of an existing signature.
+        let signer = UnifiedSigner::new(SigningSettings::default());
+        assert_eq!(signer.sign_macho_data(&expected, None)?, expected);
+
+        assert!(matches!(
+            signer.sign_macho_data(&unsigned, None),
+            Err(AppleCodesignError::NoIdentifier)
+        ));
+
+        Ok(())
+    }
+}
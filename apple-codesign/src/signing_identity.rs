@@ -0,0 +1,588 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Discovery and selection of signing identities.
+//!
+//! A signing identity is an X.509 certificate, optionally paired with a private
+//! key capable of producing signatures for that certificate. Identities can come
+//! from a variety of sources: PFX/`.p12` files, PEM and DER files, the macOS
+//! keychain, smartcards, remote signers, and cloud key management services.
+//!
+//! [IdentityResolver] provides a uniform mechanism for enumerating identities
+//! from all these sources. Each identity can be described by an
+//! [IdentityDescriptor], which is what user-facing selection mechanisms should
+//! match against.
+
+use {
+    crate::{
+        certificate::AppleCertificate,
+        cloud_kms::{KmsBackend, KmsSigner},
+        cryptography::{parse_pfx_data, InMemoryPrivateKey, PrivateKey},
+        error::AppleCodesignError,
+        remote_signing::InitiatorClient,
+    },
+    log::warn,
+    serde::Serialize,
+    std::{
+        fmt::{Display, Formatter},
+        path::{Path, PathBuf},
+        sync::Arc,
+    },
+    x509_certificate::CapturedX509Certificate,
+};
+
+#[cfg(target_os = "macos")]
+use crate::macos::{keychain_find_code_signing_certificates, KeychainDomain};
+
+/// Describes where a [SigningIdentity] came from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum IdentitySource {
+    /// A PFX / `.p12` file.
+    P12File(PathBuf),
+    /// A file containing PEM encoded certificates and/or private keys.
+    PemFile(PathBuf),
+    /// A file containing a DER encoded certificate.
+    DerFile(PathBuf),
+    /// The macOS keychain. Value is the keychain domain.
+    Keychain(String),
+    /// A smartcard. Value is the hex encoded slot identifier.
+    Smartcard(String),
+    /// A remote signer. Value is the URL of the remote signing server.
+    Remote(String),
+    /// A cloud key management service. Value is the key URI.
+    Kms(String),
+}
+
+impl Display for IdentitySource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::P12File(path) => f.write_fmt(format_args!("p12:{}", path.display())),
+            Self::PemFile(path) => f.write_fmt(format_args!("pem:{}", path.display())),
+            Self::DerFile(path) => f.write_fmt(format_args!("der:{}", path.display())),
+            Self::Keychain(domain) => f.write_fmt(format_args!("keychain:{domain}")),
+            Self::Smartcard(slot) => f.write_fmt(format_args!("smartcard:{slot}")),
+            Self::Remote(url) => f.write_fmt(format_args!("remote:{url}")),
+            Self::Kms(uri) => f.write_fmt(format_args!("kms:{uri}")),
+        }
+    }
+}
+
+/// An X.509 certificate and an optional private key to sign with it.
+pub struct SigningIdentity {
+    source: IdentitySource,
+    certificate: CapturedX509Certificate,
+    private_key: Option<Box<dyn PrivateKey>>,
+}
+
+impl SigningIdentity {
+    /// Construct a new instance from its parts.
+    pub fn new(
+        source: IdentitySource,
+        certificate: CapturedX509Certificate,
+        private_key: Option<Box<dyn PrivateKey>>,
+    ) -> Self {
+        Self {
+            source,
+            certificate,
+            private_key,
+        }
+    }
+
+    /// Where this identity came from.
+    pub fn source(&self) -> &IdentitySource {
+        &self.source
+    }
+
+    /// The X.509 certificate of this identity.
+    pub fn certificate(&self) -> &CapturedX509Certificate {
+        &self.certificate
+    }
+
+    /// The private key associated with this identity, if available.
+    pub fn private_key(&self) -> Option<&dyn PrivateKey> {
+        self.private_key.as_deref()
+    }
+
+    /// Obtain a [IdentityDescriptor] describing this identity.
+    pub fn descriptor(&self) -> Result<IdentityDescriptor, AppleCodesignError> {
+        Ok(IdentityDescriptor {
+            source: self.source.to_string(),
+            subject_common_name: self.certificate.subject_common_name(),
+            team_id: self.certificate.apple_team_id(),
+            sha256_fingerprint: hex::encode(self.certificate.sha256_fingerprint()?),
+            has_private_key: self.private_key.is_some(),
+        })
+    }
+
+    /// Decompose this instance into its certificate and private key.
+    pub fn into_parts(self) -> (CapturedX509Certificate, Option<Box<dyn PrivateKey>>) {
+        (self.certificate, self.private_key)
+    }
+}
+
+/// A uniform description of a [SigningIdentity].
+///
+/// Instances are independent of the source of the identity and are suitable for
+/// presenting to end-users and for matching against user-provided selectors.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct IdentityDescriptor {
+    /// Describes where the identity came from.
+    pub source: String,
+    /// The common name (CN) of the certificate's subject.
+    pub subject_common_name: Option<String>,
+    /// The Apple team ID from the certificate.
+    pub team_id: Option<String>,
+    /// Hex encoded SHA-256 fingerprint of the certificate.
+    pub sha256_fingerprint: String,
+    /// Whether a private key is available to sign with this identity.
+    pub has_private_key: bool,
+}
+
+impl IdentityDescriptor {
+    /// Whether this identity is matched by a user-provided selector string.
+    ///
+    /// The selector matches if it is a case-insensitive prefix of the SHA-256
+    /// fingerprint or if it is equal to the subject common name or team ID.
+    pub fn matches(&self, selector: &str) -> bool {
+        let selector_lower = selector.to_ascii_lowercase();
+
+        (!selector.is_empty() && self.sha256_fingerprint.starts_with(&selector_lower))
+            || self.subject_common_name.as_deref() == Some(selector)
+            || self.team_id.as_deref() == Some(selector)
+    }
+}
+
+impl Display for IdentityDescriptor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "{} {} (team: {}; source: {}{})",
+            self.sha256_fingerprint,
+            self.subject_common_name.as_deref().unwrap_or("<no CN>"),
+            self.team_id.as_deref().unwrap_or("<none>"),
+            self.source,
+            if self.has_private_key {
+                ""
+            } else {
+                "; no private key"
+            }
+        ))
+    }
+}
+
+/// Enumerates signing identities from multiple sources.
+///
+/// Sources are registered via the various `add_*` methods. Identities are
+/// recorded in the order sources are added.
+///
+/// Certificates and private keys read from PEM files are paired by comparing
+/// public keys. This allows a certificate and its private key to be defined in
+/// separate files. Private keys that can't be paired with a certificate are
+/// retained and can be obtained via [Self::unpaired_private_keys()].
+#[derive(Default)]
+pub struct IdentityResolver {
+    identities: Vec<SigningIdentity>,
+    unpaired_keys: Vec<(IdentitySource, Box<dyn PrivateKey>)>,
+}
+
+impl IdentityResolver {
+    /// Register an already resolved identity.
+    pub fn add_identity(&mut self, identity: SigningIdentity) {
+        self.identities.push(identity);
+    }
+
+    /// Register the identity in a PFX / `.p12` file.
+    pub fn add_p12_file(
+        &mut self,
+        path: impl AsRef<Path>,
+        password: &str,
+    ) -> Result<(), AppleCodesignError> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)?;
+
+        let (cert, key) = parse_pfx_data(&data, password)?;
+
+        self.add_identity(SigningIdentity::new(
+            IdentitySource::P12File(path.to_path_buf()),
+            cert,
+            Some(Box::new(key)),
+        ));
+
+        Ok(())
+    }
+
+    /// Register identities in all `.p12` and `.pfx` files in a directory.
+    ///
+    /// All files are decrypted with the same password. Files that fail to decrypt
+    /// or parse are ignored with a warning.
+    ///
+    /// Returns the number of identities that were registered.
+    pub fn add_p12_directory(
+        &mut self,
+        path: impl AsRef<Path>,
+        password: &str,
+    ) -> Result<usize, AppleCodesignError> {
+        let mut paths = std::fs::read_dir(path.as_ref())?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        // Sort for deterministic ordering.
+        paths.sort();
+
+        let mut count = 0;
+
+        for path in paths {
+            let is_p12 = path
+                .extension()
+                .map(|ext| {
+                    let ext = ext.to_string_lossy().to_ascii_lowercase();
+                    ext == "p12" || ext == "pfx"
+                })
+                .unwrap_or(false);
+
+            if !is_p12 || !path.is_file() {
+                continue;
+            }
+
+            match self.add_p12_file(&path, password) {
+                Ok(()) => {
+                    count += 1;
+                }
+                Err(e) => {
+                    warn!("ignoring {}: {}", path.display(), e);
+                }
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Register certificates and private keys in a file containing PEM encoded data.
+    pub fn add_pem_file(&mut self, path: impl AsRef<Path>) -> Result<(), AppleCodesignError> {
+        let path = path.as_ref();
+        let source = IdentitySource::PemFile(path.to_path_buf());
+        let data = std::fs::read(path)?;
+
+        for pem in pem::parse_many(data).map_err(AppleCodesignError::CertificatePem)? {
+            match pem.tag() {
+                "CERTIFICATE" => {
+                    self.add_certificate(
+                        source.clone(),
+                        CapturedX509Certificate::from_der(pem.contents())?,
+                    );
+                }
+                "PRIVATE KEY" => {
+                    self.add_private_key(
+                        source.clone(),
+                        Box::new(InMemoryPrivateKey::from_pkcs8_der(pem.contents())?),
+                    );
+                }
+                "RSA PRIVATE KEY" => {
+                    self.add_private_key(
+                        source.clone(),
+                        Box::new(InMemoryPrivateKey::from_pkcs1_der(pem.contents())?),
+                    );
+                }
+                tag => warn!("(unhandled PEM tag {}; ignoring)", tag),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Register a certificate in a file containing DER encoded data.
+    pub fn add_der_file(&mut self, path: impl AsRef<Path>) -> Result<(), AppleCodesignError> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)?;
+
+        self.add_certificate(
+            IdentitySource::DerFile(path.to_path_buf()),
+            CapturedX509Certificate::from_der(data)?,
+        );
+
+        Ok(())
+    }
+
+    /// Register code signing identities in a macOS keychain domain.
+    #[cfg(target_os = "macos")]
+    pub fn add_keychain_domain(
+        &mut self,
+        domain: KeychainDomain,
+    ) -> Result<(), AppleCodesignError> {
+        let source = IdentitySource::Keychain(format!("{domain:?}").to_ascii_lowercase());
+
+        for cert in keychain_find_code_signing_certificates(domain, None)? {
+            self.add_identity(SigningIdentity::new(
+                source.clone(),
+                cert.as_captured_x509_certificate(),
+                Some(Box::new(cert)),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Register all identities present in a smartcard.
+    #[cfg(feature = "yubikey")]
+    pub fn add_smartcard(
+        &mut self,
+        yk: &mut crate::yubikey::YubiKey,
+    ) -> Result<(), AppleCodesignError> {
+        for (slot, _) in yk.find_certificates()? {
+            if let Some(signer) = yk.get_certificate_signer(slot)? {
+                self.add_identity(SigningIdentity::new(
+                    IdentitySource::Smartcard(hex::encode([u8::from(slot)])),
+                    signer.certificate().clone(),
+                    Some(Box::new(signer)),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Register the identity of a remote signer that has joined a signing session.
+    ///
+    /// The signer's certificate chain is registered as certificates without
+    /// private keys.
+    pub fn add_remote_signer(&mut self, url: &str, client: InitiatorClient) {
+        let source = IdentitySource::Remote(url.to_string());
+        let cert = client.signing_certificate().clone();
+        let chain = client.certificate_chain().to_vec();
+
+        self.add_identity(SigningIdentity::new(
+            source.clone(),
+            cert,
+            Some(Box::new(client)),
+        ));
+
+        for cert in chain {
+            self.add_identity(SigningIdentity::new(source.clone(), cert, None));
+        }
+    }
+
+    /// Pair a key held by a cloud KMS with a previously registered certificate.
+    ///
+    /// KMS services don't store certificates. So the key is paired with the
+    /// first registered certificate lacking a private key. That identity is moved
+    /// to the front so it is preferred for signing.
+    pub fn add_kms_key(
+        &mut self,
+        uri: &str,
+        backend: Arc<dyn KmsBackend>,
+    ) -> Result<(), AppleCodesignError> {
+        let index = self
+            .identities
+            .iter()
+            .position(|identity| identity.private_key.is_none())
+            .ok_or_else(|| AppleCodesignError::KmsNoCertificate(uri.to_string()))?;
+
+        let identity = self.identities.remove(index);
+        let signer = KmsSigner::new(backend, identity.certificate.clone())?;
+
+        self.identities.insert(
+            0,
+            SigningIdentity::new(
+                IdentitySource::Kms(uri.to_string()),
+                identity.certificate,
+                Some(Box::new(signer)),
+            ),
+        );
+
+        Ok(())
+    }
+
+    /// All resolved identities, in the order they were registered.
+    pub fn identities(&self) -> &[SigningIdentity] {
+        &self.identities
+    }
+
+    /// Private keys which couldn't be paired with a certificate.
+    pub fn unpaired_private_keys(&self) -> &[(IdentitySource, Box<dyn PrivateKey>)] {
+        &self.unpaired_keys
+    }
+
+    /// Obtain descriptors of all resolved identities.
+    pub fn descriptors(&self) -> Result<Vec<IdentityDescriptor>, AppleCodesignError> {
+        self.identities
+            .iter()
+            .map(|identity| identity.descriptor())
+            .collect::<Result<Vec<_>, _>>()
+    }
+
+    /// Find the index of the single identity matching a user-provided selector.
+    ///
+    /// See [IdentityDescriptor::matches()] for matching semantics. Only identities
+    /// having a private key are considered.
+    ///
+    /// Errors if no identities or multiple identities match.
+    pub fn position(&self, selector: &str) -> Result<usize, AppleCodesignError> {
+        let mut matches = vec![];
+
+        for (index, identity) in self.identities.iter().enumerate() {
+            if identity.private_key.is_some() && identity.descriptor()?.matches(selector) {
+                matches.push(index);
+            }
+        }
+
+        match matches.as_slice() {
+            [] => Err(AppleCodesignError::CertificateNotFound(format!(
+                "no signing identity matches {selector}"
+            ))),
+            [index] => Ok(*index),
+            _ => Err(AppleCodesignError::SigningIdentityAmbiguous(
+                selector.to_string(),
+                matches.len(),
+            )),
+        }
+    }
+
+    /// Select a single identity matching a user-provided selector.
+    ///
+    /// This is [Self::position()] but consumes self and returns the matched identity.
+    pub fn select(mut self, selector: &str) -> Result<SigningIdentity, AppleCodesignError> {
+        let index = self.position(selector)?;
+
+        Ok(self.identities.remove(index))
+    }
+
+    /// Consume self and return the resolved identities and unpaired private keys.
+    #[allow(clippy::type_complexity)]
+    pub fn into_parts(
+        self,
+    ) -> (
+        Vec<SigningIdentity>,
+        Vec<(IdentitySource, Box<dyn PrivateKey>)>,
+    ) {
+        (self.identities, self.unpaired_keys)
+    }
+
+    fn add_certificate(&mut self, source: IdentitySource, cert: CapturedX509Certificate) {
+        let public_key = cert.public_key_data();
+
+        let private_key = self
+            .unpaired_keys
+            .iter()
+            .position(|(_, key)| key.public_key_data() == public_key)
+            .map(|index| self.unpaired_keys.remove(index).1);
+
+        self.identities
+            .push(SigningIdentity::new(source, cert, private_key));
+    }
+
+    fn add_private_key(&mut self, source: IdentitySource, key: Box<dyn PrivateKey>) {
+        let public_key = key.public_key_data();
+
+        if let Some(identity) = self.identities.iter_mut().find(|identity| {
+            identity.private_key.is_none() && identity.certificate.public_key_data() == public_key
+        }) {
+            identity.private_key = Some(key);
+        } else {
+            self.unpaired_keys.push((source, key));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::certificate::{create_self_signed_code_signing_certificate, CertificateProfile},
+        x509_certificate::{EcdsaCurve, KeyAlgorithm},
+    };
+
+    fn write_identity_pems(
+        dir: &Path,
+        team_id: &str,
+    ) -> Result<(PathBuf, PathBuf), AppleCodesignError> {
+        let (cert, _, raw) = create_self_signed_code_signing_certificate(
+            KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1),
+            CertificateProfile::DeveloperIdApplication,
+            team_id,
+            "Joe Developer",
+            "US",
+            chrono::Duration::hours(1),
+        )?;
+
+        let cert_path = dir.join(format!("{team_id}.crt"));
+        let key_path = dir.join(format!("{team_id}.key"));
+
+        std::fs::write(&cert_path, cert.encode_pem())?;
+        std::fs::write(
+            &key_path,
+            pem::encode(&pem::Pem::new("PRIVATE KEY", raw.as_ref().to_vec())),
+        )?;
+
+        Ok((cert_path, key_path))
+    }
+
+    #[test]
+    fn pem_pairing_and_selection() -> Result<(), AppleCodesignError> {
+        let td = tempfile::tempdir()?;
+
+        let (cert1, key1) = write_identity_pems(td.path(), "team1")?;
+        let (cert2, key2) = write_identity_pems(td.path(), "team2")?;
+
+        let mut resolver = IdentityResolver::default();
+        // Key before certificate and certificate before key should both pair.
+        resolver.add_pem_file(&key1)?;
+        resolver.add_pem_file(&cert1)?;
+        resolver.add_pem_file(&cert2)?;
+        resolver.add_pem_file(&key2)?;
+
+        assert!(resolver.unpaired_private_keys().is_empty());
+
+        let descriptors = resolver.descriptors()?;
+        assert_eq!(descriptors.len(), 2);
+        assert!(descriptors.iter().all(|d| d.has_private_key));
+        assert_eq!(descriptors[0].team_id.as_deref(), Some("team1"));
+        assert_eq!(descriptors[1].team_id.as_deref(), Some("team2"));
+        assert_eq!(descriptors[0].source, format!("pem:{}", cert1.display()));
+
+        assert!(descriptors[1].matches(&descriptors[1].sha256_fingerprint[0..8].to_uppercase()));
+        assert!(!descriptors[1].matches(""));
+
+        let identity = resolver.select("team2")?;
+        assert_eq!(
+            identity.certificate().apple_team_id().as_deref(),
+            Some("team2")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn select_errors() -> Result<(), AppleCodesignError> {
+        let td = tempfile::tempdir()?;
+
+        let (cert1, key1) = write_identity_pems(td.path(), "team1")?;
+        let (cert2, _) = write_identity_pems(td.path(), "team2")?;
+
+        let mut resolver = IdentityResolver::default();
+        resolver.add_pem_file(&cert1)?;
+        resolver.add_pem_file(&key1)?;
+        resolver.add_pem_file(&cert2)?;
+
+        // Identities without private keys can't be selected.
+        assert!(matches!(
+            IdentityResolver::default().select("team2"),
+            Err(AppleCodesignError::CertificateNotFound(_))
+        ));
+        assert!(matches!(
+            resolver.select("team2"),
+            Err(AppleCodesignError::CertificateNotFound(_))
+        ));
+
+        let mut resolver = IdentityResolver::default();
+        resolver.add_pem_file(&cert1)?;
+        resolver.add_pem_file(&key1)?;
+        resolver.add_pem_file(&cert1)?;
+        resolver.add_pem_file(&key1)?;
+
+        assert!(matches!(
+            resolver.select("team1"),
+            Err(AppleCodesignError::SigningIdentityAmbiguous(_, 2))
+        ));
+
+        Ok(())
+    }
+}
@@ -6,26 +6,37 @@
 
 use {
     crate::{
-        certificate::AppleCertificate,
-        code_directory::CodeSignatureFlags,
-        code_requirement::CodeRequirementExpression,
-        embedded_signature::{Blob, DigestType, RequirementBlob},
+        certificate::{AppleCertificate, SigningDistribution},
+        code_directory::{CodeSignatureFlags, Scatter},
+        code_requirement::{CodeRequirementExpression, RequirementType},
+        embedded_signature::{Blob, DigestType, EmbeddedSignature, RequirementBlob},
         error::AppleCodesignError,
-        macho::{parse_version_nibbles, MachFile},
+        macho::{parse_version_nibbles, MachFile, MachOFileType},
+        metrics::{Metrics, MetricsRecorder},
+        retry::RetryPolicy,
     },
     glob::Pattern,
     goblin::mach::cputype::{
-        CpuType, CPU_TYPE_ARM, CPU_TYPE_ARM64, CPU_TYPE_ARM64_32, CPU_TYPE_X86_64,
+        CpuSubType, CpuType, CPU_SUBTYPE_ARM64_E, CPU_SUBTYPE_ARM_V7, CPU_SUBTYPE_ARM_V7S,
+        CPU_TYPE_ARM, CPU_TYPE_ARM64, CPU_TYPE_ARM64_32, CPU_TYPE_I386, CPU_TYPE_X86_64,
     },
     log::info,
     reqwest::{IntoUrl, Url},
     std::{
         collections::{BTreeMap, BTreeSet},
         fmt::Formatter,
+        path::{Path, PathBuf},
+        sync::Arc,
     },
+    unicode_normalization::UnicodeNormalization,
     x509_certificate::{CapturedX509Certificate, KeyInfoSigner},
 };
 
+/// URL of Apple's Time-Stamp Protocol server.
+///
+/// This is the server `codesign --timestamp` uses.
+pub const APPLE_TIMESTAMP_URL: &str = "http://timestamp.apple.com/ts01";
+
 /// Denotes the scope for a setting.
 ///
 /// Settings have an associated scope defined by this type. This allows settings
@@ -53,7 +64,15 @@ use {
 /// * `arm` -> [CPU_TYPE_ARM]
 /// * `arm64` -> [CPU_TYPE_ARM64]
 /// * `arm64_32` -> [CPU_TYPE_ARM64_32]
+/// * `i386` -> [CPU_TYPE_I386]
 /// * `x86_64` -> [CPU_TYPE_X86_64]
+///
+/// The following values also denote a CPU subtype and map to
+/// [SettingsScope::MultiArchCpuSubtype] and [SettingsScope::PathMultiArchCpuSubtype]:
+///
+/// * `arm64e` -> [CPU_TYPE_ARM64] + [CPU_SUBTYPE_ARM64_E]
+/// * `armv7` -> [CPU_TYPE_ARM] + [CPU_SUBTYPE_ARM_V7]
+/// * `armv7s` -> [CPU_TYPE_ARM] + [CPU_SUBTYPE_ARM_V7S]
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum SettingsScope {
     // The order of the variants is important. Instance cloning iterates keys in
@@ -88,6 +107,13 @@ pub enum SettingsScope {
     /// The binary to operate on is defined by its CPU architecture.
     MultiArchCpuType(CpuType),
 
+    /// A single Mach-O binary within a fat/universal Mach-O binary.
+    ///
+    /// The binary to operate on is defined by its CPU type and subtype. This allows
+    /// distinguishing binaries sharing a CPU type, such as `arm64` and `arm64e`.
+    /// The subtype excludes capability bits.
+    MultiArchCpuSubtype(CpuType, CpuSubType),
+
     /// Combination of [SettingsScope::Path] and [SettingsScope::MultiArchIndex].
     ///
     /// This refers to a single Mach-O binary within a fat/universal binary at a
@@ -99,6 +125,9 @@ pub enum SettingsScope {
     /// This refers to a single Mach-O binary within a fat/universal binary at a
     /// given relative path.
     PathMultiArchCpuType(String, CpuType),
+
+    /// Combination of [SettingsScope::Path] and [SettingsScope::MultiArchCpuSubtype].
+    PathMultiArchCpuSubtype(String, CpuType, CpuSubType),
 }
 
 impl std::fmt::Display for SettingsScope {
@@ -112,22 +141,35 @@ impl std::fmt::Display for SettingsScope {
             Self::MultiArchCpuType(cpu_type) => f.write_fmt(format_args!(
                 "fat/universal Mach-O binaries for CPU {cpu_type}"
             )),
+            Self::MultiArchCpuSubtype(cpu_type, cpu_subtype) => f.write_fmt(format_args!(
+                "fat/universal Mach-O binaries for CPU {cpu_type} subtype {cpu_subtype}"
+            )),
             Self::PathMultiArchIndex(path, index) => f.write_fmt(format_args!(
                 "fat/universal Mach-O binaries at index {index} under path {path}"
             )),
             Self::PathMultiArchCpuType(path, cpu_type) => f.write_fmt(format_args!(
                 "fat/universal Mach-O binaries for CPU {cpu_type} under path {path}"
             )),
+            Self::PathMultiArchCpuSubtype(path, cpu_type, cpu_subtype) => {
+                f.write_fmt(format_args!(
+                    "fat/universal Mach-O binaries for CPU {cpu_type} subtype {cpu_subtype} under path {path}"
+                ))
+            }
         }
     }
 }
 
+/// A parsed `@` expression of a [SettingsScope].
+enum AtExpr {
+    Index(usize),
+    CpuType(CpuType),
+    CpuSubtype(CpuType, CpuSubType),
+}
+
 impl SettingsScope {
-    fn parse_at_expr(
-        at_expr: &str,
-    ) -> Result<(Option<usize>, Option<CpuType>), AppleCodesignError> {
+    fn parse_at_expr(at_expr: &str) -> Result<AtExpr, AppleCodesignError> {
         match at_expr.parse::<usize>() {
-            Ok(index) => Ok((Some(index), None)),
+            Ok(index) => Ok(AtExpr::Index(index)),
             Err(_) => {
                 if at_expr.starts_with('[') && at_expr.ends_with(']') {
                     let v = &at_expr[1..at_expr.len() - 1];
@@ -146,14 +188,24 @@ impl SettingsScope {
                             "arm" => Some(CPU_TYPE_ARM),
                             "arm64" => Some(CPU_TYPE_ARM64),
                             "arm64_32" => Some(CPU_TYPE_ARM64_32),
+                            "i386" => Some(CPU_TYPE_I386),
                             "x86_64" => Some(CPU_TYPE_X86_64),
                             _ => None,
                         } {
-                            return Ok((None, Some(cpu_type)));
+                            return Ok(AtExpr::CpuType(cpu_type));
+                        }
+
+                        if let Some((cpu_type, cpu_subtype)) = match value {
+                            "arm64e" => Some((CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_E)),
+                            "armv7" => Some((CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V7)),
+                            "armv7s" => Some((CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V7S)),
+                            _ => None,
+                        } {
+                            return Ok(AtExpr::CpuSubtype(cpu_type, cpu_subtype));
                         }
 
                         match value.parse::<u32>() {
-                            Ok(cpu_type) => Ok((None, Some(cpu_type as CpuType))),
+                            Ok(cpu_type) => Ok(AtExpr::CpuType(cpu_type as CpuType)),
                             Err(_) => Err(AppleCodesignError::ParseSettingsScope(format!(
                                 "in '@{at_expr}', cpu_arch value {value} not recognized"
                             ))),
@@ -187,9 +239,11 @@ impl TryFrom<&str> for SettingsScope {
             Ok(Self::Main)
         } else if let Some(at_expr) = s.strip_prefix('@') {
             match Self::parse_at_expr(at_expr)? {
-                (Some(index), None) => Ok(Self::MultiArchIndex(index)),
-                (None, Some(cpu_type)) => Ok(Self::MultiArchCpuType(cpu_type)),
-                _ => panic!("this shouldn't happen"),
+                AtExpr::Index(index) => Ok(Self::MultiArchIndex(index)),
+                AtExpr::CpuType(cpu_type) => Ok(Self::MultiArchCpuType(cpu_type)),
+                AtExpr::CpuSubtype(cpu_type, cpu_subtype) => {
+                    Ok(Self::MultiArchCpuSubtype(cpu_type, cpu_subtype))
+                }
             }
         } else {
             // Looks like a path.
@@ -202,13 +256,15 @@ impl TryFrom<&str> for SettingsScope {
                     let (at_expr, path) = (parts[0], parts[1]);
 
                     match Self::parse_at_expr(at_expr)? {
-                        (Some(index), None) => {
+                        AtExpr::Index(index) => {
                             Ok(Self::PathMultiArchIndex(path.to_string(), index))
                         }
-                        (None, Some(cpu_type)) => {
+                        AtExpr::CpuType(cpu_type) => {
                             Ok(Self::PathMultiArchCpuType(path.to_string(), cpu_type))
                         }
-                        _ => panic!("this shouldn't happen"),
+                        AtExpr::CpuSubtype(cpu_type, cpu_subtype) => Ok(
+                            Self::PathMultiArchCpuSubtype(path.to_string(), cpu_type, cpu_subtype),
+                        ),
                     }
                 }
                 _ => panic!("this shouldn't happen"),
@@ -254,17 +310,31 @@ pub struct SigningSettings<'key> {
     time_stamp_url: Option<Url>,
     digest_type: DigestType,
     path_exclusion_patterns: Vec<Pattern>,
+    legacy_superblob: bool,
+    signing_distribution: Option<SigningDistribution>,
+    skip_certificate_validation: bool,
+    reuse_resource_seals: bool,
+    preserve_designated_requirement: bool,
+    resource_digest_threads: Option<usize>,
+    retry_policy: RetryPolicy,
+    metrics: Metrics,
 
     // Scope-specific settings.
     // These are BTreeMap so when we filter the keys, keys with higher precedence come
     // last and last write wins.
+    scoped_signing_keys:
+        BTreeMap<SettingsScope, (&'key dyn KeyInfoSigner, CapturedX509Certificate)>,
     team_id: BTreeMap<SettingsScope, String>,
     identifiers: BTreeMap<SettingsScope, String>,
     entitlements: BTreeMap<SettingsScope, plist::Value>,
+    library_constraints: BTreeMap<SettingsScope, plist::Value>,
     designated_requirement: BTreeMap<SettingsScope, DesignatedRequirementMode>,
     code_signature_flags: BTreeMap<SettingsScope, CodeSignatureFlags>,
     runtime_version: BTreeMap<SettingsScope, semver::Version>,
+    macho_file_type: BTreeMap<SettingsScope, MachOFileType>,
+    scatter: BTreeMap<SettingsScope, Vec<Scatter>>,
     info_plist_data: BTreeMap<SettingsScope, Vec<u8>>,
+    info_plist_path: BTreeMap<SettingsScope, PathBuf>,
     code_resources_data: BTreeMap<SettingsScope, Vec<u8>>,
     extra_digests: BTreeMap<SettingsScope, BTreeSet<DigestType>>,
 }
@@ -295,12 +365,54 @@ impl<'key> SigningSettings<'key> {
     /// contain digests of content. This is known as "ad-hoc" mode. Binaries lacking a
     /// cryptographic signature or signed without a key-pair issued/signed by Apple may
     /// not run in all environments.
+    ///
+    /// If the certificate is issued by Apple, the Apple CA certificates it chains to
+    /// are registered automatically (see [Self::chain_apple_certificates()]).
     pub fn set_signing_key(
         &mut self,
         private: &'key dyn KeyInfoSigner,
         public: CapturedX509Certificate,
     ) {
         self.signing_key = Some((private, public));
+        self.chain_apple_certificates();
+    }
+
+    /// Obtain signing certificates registered for scopes other than the main scope.
+    pub fn scoped_signing_certificates(
+        &self,
+    ) -> impl Iterator<Item = (&SettingsScope, &CapturedX509Certificate)> {
+        self.scoped_signing_keys
+            .iter()
+            .map(|(scope, (_, cert))| (scope, cert))
+    }
+
+    /// Set the signing key-pair for a given scope.
+    ///
+    /// This is like [Self::set_signing_key()] except the key-pair is only used
+    /// for entities in `scope`. e.g. a helper binary at a path within a bundle
+    /// or a single architecture of a universal binary. Entities outside any
+    /// scope having a key-pair use the one from [Self::set_signing_key()].
+    ///
+    /// If the certificate contains an Apple team ID, it becomes the team ID of
+    /// `scope`. Apple CA certificates are chained automatically when the
+    /// key-pair is used. Certificates added with [Self::chain_certificate()] are
+    /// included in signatures made with any key-pair.
+    pub fn set_scoped_signing_key(
+        &mut self,
+        scope: SettingsScope,
+        private: &'key dyn KeyInfoSigner,
+        public: CapturedX509Certificate,
+    ) {
+        if scope == SettingsScope::Main {
+            self.set_signing_key(private, public);
+            return;
+        }
+
+        if let Some(team_id) = public.apple_team_id() {
+            self.set_scoped_team_id(scope.clone(), team_id);
+        }
+
+        self.scoped_signing_keys.insert(scope, (private, public));
     }
 
     /// Obtain the certificate chain.
@@ -310,15 +422,21 @@ impl<'key> SigningSettings<'key> {
 
     /// Attempt to chain Apple CA certificates from a loaded Apple signed signing key.
     ///
-    /// If you are calling `set_signing_key()`, you probably want to call this immediately
-    /// afterwards, as it will automatically register Apple CA certificates if you are
-    /// using an Apple signed code signing certificate.
+    /// The chain is resolved from Apple CA certificates bundled with this crate,
+    /// so no network access is required. [Self::set_signing_key()] calls this
+    /// automatically. Certificates already registered aren't added again.
+    ///
+    /// Returns the resolved CA certificates, excluding the signing certificate.
     pub fn chain_apple_certificates(&mut self) -> Option<Vec<CapturedX509Certificate>> {
         if let Some((_, cert)) = &self.signing_key {
             if let Some(chain) = cert.apple_root_certificate_chain() {
                 // The chain starts with self.
                 let chain = chain.into_iter().skip(1).collect::<Vec<_>>();
-                self.certificates.extend(chain.clone());
+                for cert in &chain {
+                    if !self.certificates.contains(cert) {
+                        self.certificates.push(cert.clone());
+                    }
+                }
                 Some(chain)
             } else {
                 None
@@ -380,7 +498,8 @@ impl<'key> SigningSettings<'key> {
     /// Set the Time-Stamp Protocol server URL to use to generate a Time-Stamp Token.
     ///
     /// When set and a signing key-pair is defined, the server will be contacted during
-    /// signing and a Time-Stamp Token will be embedded in the cryptographic signature.
+    /// signing and a Time-Stamp Token will be embedded in the cryptographic signature
+    /// as an unsigned attribute. [APPLE_TIMESTAMP_URL] is the server Apple uses.
     /// This Time-Stamp Token is a cryptographic proof that someone in possession of
     /// the signing key-pair produced the cryptographic signature at a given time. It
     /// facilitates validation of the signing time via an independent (presumably trusted)
@@ -391,6 +510,14 @@ impl<'key> SigningSettings<'key> {
         Ok(())
     }
 
+    /// Clear the Time-Stamp Protocol server URL.
+    ///
+    /// Cryptographic signatures will not contain a Time-Stamp Token and no network
+    /// requests will be made to obtain one. This is the default.
+    pub fn clear_time_stamp_url(&mut self) {
+        self.time_stamp_url = None;
+    }
+
     /// Obtain the team identifier for signed binaries.
     pub fn team_id(&self) -> Option<&str> {
         self.team_id.get(&SettingsScope::Main).map(|x| x.as_str())
@@ -401,6 +528,16 @@ impl<'key> SigningSettings<'key> {
         self.team_id.insert(SettingsScope::Main, value.to_string());
     }
 
+    /// Obtain the team identifier for a given scope.
+    pub fn scoped_team_id(&self, scope: impl AsRef<SettingsScope>) -> Option<&str> {
+        self.team_id.get(scope.as_ref()).map(|x| x.as_str())
+    }
+
+    /// Set the team identifier for signed binaries in a given scope.
+    pub fn set_scoped_team_id(&mut self, scope: SettingsScope, value: impl ToString) {
+        self.team_id.insert(scope, value.to_string());
+    }
+
     /// Attempt to set the team ID from the signing certificate.
     ///
     /// Apple signing certificates have the team ID embedded within the certificate.
@@ -447,6 +584,129 @@ impl<'key> SigningSettings<'key> {
         Ok(())
     }
 
+    /// Whether to emit embedded signatures using the legacy `SuperBlob` magic.
+    pub fn legacy_superblob(&self) -> bool {
+        self.legacy_superblob
+    }
+
+    /// Set whether to emit embedded signatures using the legacy `SuperBlob` magic.
+    ///
+    /// When enabled, Mach-O signatures are written with the `CSMAGIC_EMBEDDED_SIGNATURE_OLD`
+    /// magic instead of the modern `CSMAGIC_EMBEDDED_SIGNATURE`. This is only useful
+    /// for interoperating with very old versions of macOS. Modern operating systems
+    /// will reject signatures written in this format.
+    pub fn set_legacy_superblob(&mut self, value: bool) {
+        self.legacy_superblob = value;
+    }
+
+    /// Obtain the distribution channel signed content is intended for.
+    pub fn signing_distribution(&self) -> Option<SigningDistribution> {
+        self.signing_distribution
+    }
+
+    /// Set the distribution channel signed content is intended for.
+    ///
+    /// When set, the signing certificate is validated to have the Apple code signing
+    /// extensions required by that channel before any signatures are produced.
+    pub fn set_signing_distribution(&mut self, value: Option<SigningDistribution>) {
+        self.signing_distribution = value;
+    }
+
+    /// Whether validation of the signing certificate is skipped.
+    pub fn skip_certificate_validation(&self) -> bool {
+        self.skip_certificate_validation
+    }
+
+    /// Set whether to skip validation of the signing certificate.
+    ///
+    /// By default, the signing certificate is verified to be within its validity
+    /// window and to have the extended key usage appropriate for the signed entity
+    /// before any signatures are produced. This disables that validation, which can
+    /// be useful when signing with certificates not issued by Apple.
+    pub fn set_skip_certificate_validation(&mut self, value: bool) {
+        self.skip_certificate_validation = value;
+    }
+
+    /// Whether bundle signing reuses digests of unchanged resource files.
+    pub fn reuse_resource_seals(&self) -> bool {
+        self.reuse_resource_seals
+    }
+
+    /// Set whether bundle signing reuses digests of unchanged resource files.
+    ///
+    /// When enabled and a bundle already has a `_CodeSignature/CodeResources`
+    /// file, regular files modified before that file was written keep their
+    /// previously recorded digests instead of being read and digested again.
+    /// This makes re-signing a large bundle after changing a few resources much
+    /// faster. Mach-O binaries are always signed again.
+    ///
+    /// Files are assumed unchanged based on their modification time. Don't
+    /// enable this if files may have been modified without updating it, e.g.
+    /// when extracting archives preserving modification times.
+    pub fn set_reuse_resource_seals(&mut self, value: bool) {
+        self.reuse_resource_seals = value;
+    }
+
+    /// Whether re-signing preserves an existing designated requirement.
+    pub fn preserve_designated_requirement(&self) -> bool {
+        self.preserve_designated_requirement
+    }
+
+    /// Set whether re-signing preserves an existing designated requirement.
+    ///
+    /// When re-signing, the binary identifier, team ID, code signature flags,
+    /// runtime version, entitlements and library constraints of an existing
+    /// signature are carried over unless set explicitly. The designated
+    /// requirement isn't by default because it often references the certificate
+    /// that made the original signature. Enabling this carries it over as well,
+    /// so rotating the signing certificate doesn't change what identifies the
+    /// code. Explicitly set designated requirements still take precedence.
+    pub fn set_preserve_designated_requirement(&mut self, value: bool) {
+        self.preserve_designated_requirement = value;
+    }
+
+    /// The number of threads used to digest bundle resource files.
+    ///
+    /// `None` means to use one thread per CPU.
+    pub fn resource_digest_threads(&self) -> Option<usize> {
+        self.resource_digest_threads
+    }
+
+    /// Set the number of threads used to digest bundle resource files.
+    ///
+    /// Sealing bundles with many files is dominated by reading and digesting
+    /// them, which is done in parallel with the `parallel-digests` feature.
+    /// `Some(1)` digests files one at a time.
+    /// The produced `CodeResources` file is the same regardless of the number
+    /// of threads.
+    pub fn set_resource_digest_threads(&mut self, threads: Option<usize>) {
+        self.resource_digest_threads = threads;
+    }
+
+    /// Obtain the policy for retrying failed network operations.
+    ///
+    /// This governs requests to the time-stamp server.
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// Set the policy for retrying failed network operations.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+
+    /// Obtain the handle receiving performance measurements.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Set a recorder to receive performance measurements of signing operations.
+    ///
+    /// Code digest computation and requests to the time-stamp server are recorded.
+    pub fn set_metrics_recorder(&mut self, recorder: Arc<dyn MetricsRecorder>) {
+        self.metrics = Metrics::new(recorder);
+    }
+
     /// Obtain the binary identifier string for a given scope.
     pub fn binary_identifier(&self, scope: impl AsRef<SettingsScope>) -> Option<&str> {
         self.identifiers.get(scope.as_ref()).map(|s| s.as_str())
@@ -510,6 +770,38 @@ impl<'key> SigningSettings<'key> {
         Ok(())
     }
 
+    /// Obtain the library constraints for a given scope.
+    ///
+    /// This is the requirements dictionary of a constraints plist file, as
+    /// accepted by `codesign --library-constraint`.
+    pub fn library_constraints(&self, scope: impl AsRef<SettingsScope>) -> Option<&plist::Value> {
+        self.library_constraints.get(scope.as_ref())
+    }
+
+    /// Set the constraints on libraries a binary may load.
+    ///
+    /// The value is the requirements dictionary of a constraints plist file.
+    /// Only binaries loaded by the signed code satisfying the requirements
+    /// are loaded.
+    pub fn set_library_constraints(&mut self, scope: SettingsScope, value: plist::Value) {
+        self.library_constraints.insert(scope, value);
+    }
+
+    /// Set the library constraints via an XML plist string.
+    pub fn set_library_constraints_xml(
+        &mut self,
+        scope: SettingsScope,
+        value: impl ToString,
+    ) -> Result<(), AppleCodesignError> {
+        let cursor = std::io::Cursor::new(value.to_string().into_bytes());
+        let value =
+            plist::Value::from_reader_xml(cursor).map_err(AppleCodesignError::PlistParseXml)?;
+
+        self.set_library_constraints(scope, value);
+
+        Ok(())
+    }
+
     /// Obtain the designated requirements for a given scope.
     pub fn designated_requirement(
         &self,
@@ -579,6 +871,15 @@ impl<'key> SigningSettings<'key> {
             .insert(scope, DesignatedRequirementMode::Auto);
     }
 
+    /// Obtain the scopes having entitlements or code signature flags defined.
+    pub(crate) fn configured_scopes(&self) -> BTreeSet<SettingsScope> {
+        self.entitlements
+            .keys()
+            .chain(self.code_signature_flags.keys())
+            .cloned()
+            .collect()
+    }
+
     /// Obtain the code signature flags for a given scope.
     pub fn code_signature_flags(
         &self,
@@ -660,6 +961,35 @@ impl<'key> SigningSettings<'key> {
         self.runtime_version.insert(scope, version);
     }
 
+    /// Obtain the Mach-O file type override for a given scope.
+    pub fn macho_file_type(&self, scope: impl AsRef<SettingsScope>) -> Option<MachOFileType> {
+        self.macho_file_type.get(scope.as_ref()).copied()
+    }
+
+    /// Treat signed Mach-O binaries as the given file type.
+    ///
+    /// By default, the file type is read from the Mach-O header and used to derive
+    /// defaults such as executable segment flags and whether to emit DER entitlements.
+    /// This overrides the detected file type.
+    pub fn set_macho_file_type(&mut self, scope: SettingsScope, file_type: MachOFileType) {
+        self.macho_file_type.insert(scope, file_type);
+    }
+
+    /// Obtain the scatter vector for a given scope.
+    pub fn scatter(&self, scope: impl AsRef<SettingsScope>) -> Option<&[Scatter]> {
+        self.scatter.get(scope.as_ref()).map(|x| x.as_slice())
+    }
+
+    /// Set the scatter vector to emit in the code directory.
+    ///
+    /// By default, code digests cover every page up to the code limit. With a
+    /// scatter vector, only the described pages are digested. Entries must
+    /// describe consecutive code slots starting at 0 and pages within the code
+    /// limit.
+    pub fn set_scatter(&mut self, scope: SettingsScope, scatter: Vec<Scatter>) {
+        self.scatter.insert(scope, scatter);
+    }
+
     /// Define the `Info.plist` content.
     ///
     /// Signatures can reference the digest of an external `Info.plist` file in
@@ -678,6 +1008,51 @@ impl<'key> SigningSettings<'key> {
         self.info_plist_data.insert(scope, data);
     }
 
+    /// Obtain the path of the `Info.plist` file registered to a given scope.
+    pub fn info_plist_path(&self, scope: impl AsRef<SettingsScope>) -> Option<&Path> {
+        self.info_plist_path
+            .get(scope.as_ref())
+            .map(|x| x.as_path())
+    }
+
+    /// Define the path of the `Info.plist` file whose digest is recorded in the Info slot.
+    ///
+    /// The file is read when a Mach-O binary the scope applies to is signed from the
+    /// filesystem, including Mach-O binaries within bundles. Relative paths are
+    /// resolved against the directory containing the binary. So `Resources/Info.plist`
+    /// scoped to a versioned framework's binary (`Versions/A/MyFramework`) refers to
+    /// `Versions/A/Resources/Info.plist`, which Apple's tooling doesn't find outside of
+    /// bundle signing.
+    ///
+    /// Data defined by [Self::set_info_plist_data()] for the same scope takes precedence.
+    pub fn set_info_plist_path(&mut self, scope: SettingsScope, path: impl AsRef<Path>) {
+        self.info_plist_path
+            .insert(scope, path.as_ref().to_path_buf());
+    }
+
+    /// Read the `Info.plist` files registered by [Self::set_info_plist_path()].
+    ///
+    /// `binary_path` is the path of the Mach-O binary being signed. Scopes having
+    /// data already keep it.
+    pub(crate) fn load_info_plist_paths(
+        &mut self,
+        binary_path: &Path,
+    ) -> Result<(), AppleCodesignError> {
+        let base = binary_path.parent().unwrap_or_else(|| Path::new(""));
+
+        for (scope, path) in self.info_plist_path.clone() {
+            if self.info_plist_data.contains_key(&scope) {
+                continue;
+            }
+
+            let path = base.join(path);
+            info!("reading Info.plist from {}", path.display());
+            self.set_info_plist_data(scope, std::fs::read(&path)?);
+        }
+
+        Ok(())
+    }
+
     /// Obtain the `CodeResources` XML file data registered to a given scope.
     pub fn code_resources_data(&self, scope: impl AsRef<SettingsScope>) -> Option<&[u8]> {
         self.code_resources_data
@@ -736,6 +1111,69 @@ impl<'key> SigningSettings<'key> {
         res
     }
 
+    /// Construct settings reproducing an existing signature.
+    ///
+    /// The binary identifier, team ID, code signature flags, runtime version,
+    /// entitlements, library constraints, designated requirement, and digest
+    /// types of `signature` are
+    /// applied to [SettingsScope::Main]. Signing with the returned settings
+    /// produces an equivalent signature. Set a signing key to re-sign with a
+    /// different identity.
+    ///
+    /// The ad-hoc and linker signed flags aren't carried over since they describe
+    /// how the signature was made rather than properties of the code.
+    ///
+    /// Designated requirements often reference the certificate or team that made
+    /// the original signature. When re-signing with a different identity, call
+    /// [Self::set_auto_designated_requirement()] to derive a new one.
+    pub fn from_existing_signature(
+        signature: &EmbeddedSignature,
+    ) -> Result<Self, AppleCodesignError> {
+        let mut settings = Self::default();
+        let scope = SettingsScope::Main;
+
+        if let Some(cd) = signature.code_directory()? {
+            settings.set_binary_identifier(scope.clone(), cd.ident);
+
+            if let Some(team_id) = cd.team_name {
+                settings.set_team_id(team_id);
+            }
+
+            let flags = cd.flags - CodeSignatureFlags::ADHOC - CodeSignatureFlags::LINKER_SIGNED;
+            if !flags.is_empty() {
+                settings.set_code_signature_flags(scope.clone(), flags);
+            }
+
+            if let Some(version) = cd.runtime {
+                settings.set_runtime_version(scope.clone(), parse_version_nibbles(version));
+            }
+
+            settings.set_digest_type(cd.digest_type);
+        }
+
+        for (_, cd) in signature.alternate_code_directories()? {
+            settings.add_extra_digest(scope.clone(), cd.digest_type);
+        }
+
+        if let Some(entitlements) = signature.entitlements()? {
+            settings.set_entitlements_xml(scope.clone(), entitlements.as_str())?;
+        }
+
+        if let Some(constraints) = signature.library_constraints()? {
+            if let Some(requirements) = constraints.requirements()? {
+                settings.set_library_constraints(scope.clone(), requirements);
+            }
+        }
+
+        if let Some(requirements) = signature.code_requirements()? {
+            if let Some(designated) = requirements.requirements.get(&RequirementType::Designated) {
+                settings.set_designated_requirement_bytes(scope, designated.to_blob_bytes()?)?;
+            }
+        }
+
+        Ok(settings)
+    }
+
     /// Import existing state from Mach-O data.
     ///
     /// This will synchronize the signing settings with the state in the Mach-O file.
@@ -751,6 +1189,10 @@ impl<'key> SigningSettings<'key> {
             let scope_main = SettingsScope::Main;
             let scope_index = SettingsScope::MultiArchIndex(index);
             let scope_arch = SettingsScope::MultiArchCpuType(macho.macho.header.cputype());
+            let scope_subtype = SettingsScope::MultiArchCpuSubtype(
+                macho.macho.header.cputype(),
+                macho.macho.header.cpusubtype(),
+            );
 
             // Older operating system versions don't have support for SHA-256 in
             // signatures. If the minimum version targeting in the binary doesn't
@@ -772,6 +1214,7 @@ impl<'key> SigningSettings<'key> {
                     self.set_digest_type(DigestType::Sha1);
                     self.add_extra_digest(scope_main.clone(), DigestType::Sha256);
                     self.extra_digests.remove(&scope_arch);
+                    self.extra_digests.remove(&scope_subtype);
                     self.extra_digests.remove(&scope_index);
                 }
             }
@@ -782,6 +1225,7 @@ impl<'key> SigningSettings<'key> {
                 if self.info_plist_data(&scope_main).is_some()
                     || self.info_plist_data(&scope_index).is_some()
                     || self.info_plist_data(&scope_arch).is_some()
+                    || self.info_plist_data(&scope_subtype).is_some()
                 {
                     info!("using Info.plist data from settings");
                 } else {
@@ -795,6 +1239,7 @@ impl<'key> SigningSettings<'key> {
                     if self.binary_identifier(&scope_main).is_some()
                         || self.binary_identifier(&scope_index).is_some()
                         || self.binary_identifier(&scope_arch).is_some()
+                        || self.binary_identifier(&scope_subtype).is_some()
                     {
                         info!("using binary identifier from settings");
                     } else {
@@ -805,6 +1250,7 @@ impl<'key> SigningSettings<'key> {
                     if self.team_id.contains_key(&scope_main)
                         || self.team_id.contains_key(&scope_index)
                         || self.team_id.contains_key(&scope_arch)
+                        || self.team_id.contains_key(&scope_subtype)
                     {
                         info!("using team ID from settings");
                     } else if let Some(team_id) = cd.team_name {
@@ -816,6 +1262,7 @@ impl<'key> SigningSettings<'key> {
                     if self.code_signature_flags(&scope_main).is_some()
                         || self.code_signature_flags(&scope_index).is_some()
                         || self.code_signature_flags(&scope_arch).is_some()
+                        || self.code_signature_flags(&scope_subtype).is_some()
                     {
                         info!("using code signature flags from settings");
                     } else if !cd.flags.is_empty() {
@@ -826,6 +1273,7 @@ impl<'key> SigningSettings<'key> {
                     if self.runtime_version(&scope_main).is_some()
                         || self.runtime_version(&scope_index).is_some()
                         || self.runtime_version(&scope_arch).is_some()
+                        || self.runtime_version(&scope_subtype).is_some()
                     {
                         info!("using runtime version from settings");
                     } else if let Some(version) = cd.runtime {
@@ -841,6 +1289,7 @@ impl<'key> SigningSettings<'key> {
                     if self.entitlements_plist(&scope_main).is_some()
                         || self.entitlements_plist(&scope_index).is_some()
                         || self.entitlements_plist(&scope_arch).is_some()
+                        || self.entitlements_plist(&scope_subtype).is_some()
                     {
                         info!("using entitlements from settings");
                     } else {
@@ -851,6 +1300,39 @@ impl<'key> SigningSettings<'key> {
                         )?;
                     }
                 }
+
+                if let Some(constraints) = sig.library_constraints()? {
+                    if self.library_constraints(&scope_main).is_some()
+                        || self.library_constraints(&scope_index).is_some()
+                        || self.library_constraints(&scope_arch).is_some()
+                        || self.library_constraints(&scope_subtype).is_some()
+                    {
+                        info!("using library constraints from settings");
+                    } else if let Some(requirements) = constraints.requirements()? {
+                        info!("preserving existing library constraints in Mach-O");
+                        self.set_library_constraints(scope_index.clone(), requirements);
+                    }
+                }
+
+                if self.preserve_designated_requirement {
+                    if self.designated_requirement.contains_key(&scope_main)
+                        || self.designated_requirement.contains_key(&scope_index)
+                        || self.designated_requirement.contains_key(&scope_arch)
+                        || self.designated_requirement.contains_key(&scope_subtype)
+                    {
+                        info!("using designated requirement from settings");
+                    } else if let Some(requirements) = sig.code_requirements()? {
+                        if let Some(designated) =
+                            requirements.requirements.get(&RequirementType::Designated)
+                        {
+                            info!("preserving existing designated requirement in Mach-O");
+                            self.set_designated_requirement_bytes(
+                                scope_index.clone(),
+                                designated.to_blob_bytes()?,
+                            )?;
+                        }
+                    }
+                }
             }
         }
 
@@ -872,13 +1354,22 @@ impl<'key> SigningSettings<'key> {
     /// Convert this instance to settings appropriate for a nested Mach-O binary.
     ///
     /// It is assumed the main scope of these settings is already targeted for
-    /// a Mach-O binary. Any scoped settings for the Mach-O binary index and CPU type
-    /// will be applied. CPU type settings take precedence over index scoped settings.
+    /// a Mach-O binary. Any scoped settings for the Mach-O binary index, CPU type,
+    /// and CPU type plus subtype will be applied. CPU subtype settings take precedence
+    /// over CPU type settings, which take precedence over index scoped settings.
+    ///
+    /// `cpu_subtype` should exclude capability bits.
     #[must_use]
-    pub fn as_nested_macho_settings(&self, index: usize, cpu_type: CpuType) -> Self {
+    pub fn as_nested_macho_settings(
+        &self,
+        index: usize,
+        cpu_type: CpuType,
+        cpu_subtype: CpuSubType,
+    ) -> Self {
         self.clone_with_filter_map(|key| {
             if key == SettingsScope::Main
                 || key == SettingsScope::MultiArchCpuType(cpu_type)
+                || key == SettingsScope::MultiArchCpuSubtype(cpu_type, cpu_subtype)
                 || key == SettingsScope::MultiArchIndex(index)
             {
                 Some(SettingsScope::Main)
@@ -890,10 +1381,18 @@ impl<'key> SigningSettings<'key> {
 
     // Clones this instance, promoting `main_path` to the main scope and stripping
     // a prefix from other keys.
+    //
+    // Paths are compared in Unicode NFC form since paths on disk may use a different
+    // normalization than paths given to settings.
     fn clone_strip_prefix(&self, main_path: &str, prefix: String) -> Self {
+        let main_path = main_path.nfc().collect::<String>();
+        let prefix = prefix.nfc().collect::<String>();
+
         self.clone_with_filter_map(|key| match key {
             SettingsScope::Main => Some(SettingsScope::Main),
             SettingsScope::Path(path) => {
+                let path = path.nfc().collect::<String>();
+
                 if path == main_path {
                     Some(SettingsScope::Main)
                 } else {
@@ -905,7 +1404,12 @@ impl<'key> SigningSettings<'key> {
             SettingsScope::MultiArchCpuType(cpu_type) => {
                 Some(SettingsScope::MultiArchCpuType(cpu_type))
             }
+            SettingsScope::MultiArchCpuSubtype(cpu_type, cpu_subtype) => {
+                Some(SettingsScope::MultiArchCpuSubtype(cpu_type, cpu_subtype))
+            }
             SettingsScope::PathMultiArchIndex(path, index) => {
+                let path = path.nfc().collect::<String>();
+
                 if path == main_path {
                     Some(SettingsScope::MultiArchIndex(index))
                 } else {
@@ -914,6 +1418,8 @@ impl<'key> SigningSettings<'key> {
                 }
             }
             SettingsScope::PathMultiArchCpuType(path, cpu_type) => {
+                let path = path.nfc().collect::<String>();
+
                 if path == main_path {
                     Some(SettingsScope::MultiArchCpuType(cpu_type))
                 } else {
@@ -921,6 +1427,21 @@ impl<'key> SigningSettings<'key> {
                         .map(|path| SettingsScope::PathMultiArchCpuType(path.to_string(), cpu_type))
                 }
             }
+            SettingsScope::PathMultiArchCpuSubtype(path, cpu_type, cpu_subtype) => {
+                let path = path.nfc().collect::<String>();
+
+                if path == main_path {
+                    Some(SettingsScope::MultiArchCpuSubtype(cpu_type, cpu_subtype))
+                } else {
+                    path.strip_prefix(&prefix).map(|path| {
+                        SettingsScope::PathMultiArchCpuSubtype(
+                            path.to_string(),
+                            cpu_type,
+                            cpu_subtype,
+                        )
+                    })
+                }
+            }
         })
     }
 
@@ -928,13 +1449,49 @@ impl<'key> SigningSettings<'key> {
         &self,
         key_map: impl Fn(SettingsScope) -> Option<SettingsScope>,
     ) -> Self {
+        let mut scoped_signing_keys = self
+            .scoped_signing_keys
+            .clone()
+            .into_iter()
+            .filter_map(|(key, value)| key_map(key).map(|key| (key, value)))
+            .collect::<BTreeMap<_, _>>();
+
+        // A key-pair scoped to the new main entity replaces the global one.
+        let mut signing_key = self.signing_key.clone();
+        let mut certificates = self.certificates.clone();
+        if let Some((private, public)) = scoped_signing_keys.remove(&SettingsScope::Main) {
+            if let Some(chain) = public.apple_root_certificate_chain() {
+                for cert in chain.into_iter().skip(1) {
+                    if !certificates.contains(&cert) {
+                        certificates.push(cert);
+                    }
+                }
+            }
+
+            signing_key = Some((private, public));
+        }
+
         Self {
-            signing_key: self.signing_key.clone(),
-            certificates: self.certificates.clone(),
+            signing_key,
+            certificates,
             time_stamp_url: self.time_stamp_url.clone(),
-            team_id: self.team_id.clone(),
+            scoped_signing_keys,
+            team_id: self
+                .team_id
+                .clone()
+                .into_iter()
+                .filter_map(|(key, value)| key_map(key).map(|key| (key, value)))
+                .collect::<BTreeMap<_, _>>(),
             digest_type: self.digest_type,
             path_exclusion_patterns: self.path_exclusion_patterns.clone(),
+            legacy_superblob: self.legacy_superblob,
+            signing_distribution: self.signing_distribution,
+            skip_certificate_validation: self.skip_certificate_validation,
+            reuse_resource_seals: self.reuse_resource_seals,
+            preserve_designated_requirement: self.preserve_designated_requirement,
+            resource_digest_threads: self.resource_digest_threads,
+            retry_policy: self.retry_policy.clone(),
+            metrics: self.metrics.clone(),
             identifiers: self
                 .identifiers
                 .clone()
@@ -947,6 +1504,12 @@ impl<'key> SigningSettings<'key> {
                 .into_iter()
                 .filter_map(|(key, value)| key_map(key).map(|key| (key, value)))
                 .collect::<BTreeMap<_, _>>(),
+            library_constraints: self
+                .library_constraints
+                .clone()
+                .into_iter()
+                .filter_map(|(key, value)| key_map(key).map(|key| (key, value)))
+                .collect::<BTreeMap<_, _>>(),
             designated_requirement: self
                 .designated_requirement
                 .clone()
@@ -965,12 +1528,30 @@ impl<'key> SigningSettings<'key> {
                 .into_iter()
                 .filter_map(|(key, value)| key_map(key).map(|key| (key, value)))
                 .collect::<BTreeMap<_, _>>(),
+            macho_file_type: self
+                .macho_file_type
+                .clone()
+                .into_iter()
+                .filter_map(|(key, value)| key_map(key).map(|key| (key, value)))
+                .collect::<BTreeMap<_, _>>(),
+            scatter: self
+                .scatter
+                .clone()
+                .into_iter()
+                .filter_map(|(key, value)| key_map(key).map(|key| (key, value)))
+                .collect::<BTreeMap<_, _>>(),
             info_plist_data: self
                 .info_plist_data
                 .clone()
                 .into_iter()
                 .filter_map(|(key, value)| key_map(key).map(|key| (key, value)))
                 .collect::<BTreeMap<_, _>>(),
+            info_plist_path: self
+                .info_plist_path
+                .clone()
+                .into_iter()
+                .filter_map(|(key, value)| key_map(key).map(|key| (key, value)))
+                .collect::<BTreeMap<_, _>>(),
             code_resources_data: self
                 .code_resources_data
                 .clone()
@@ -989,7 +1570,11 @@ impl<'key> SigningSettings<'key> {
 
 #[cfg(test)]
 mod tests {
-    use {super::*, indoc::indoc};
+    use {
+        super::*,
+        goblin::mach::cputype::{CPU_SUBTYPE_ARM64_ALL, CPU_SUBTYPE_X86_64_ALL},
+        indoc::indoc,
+    };
 
     const ENTITLEMENTS_XML: &str = indoc! {r#"
         <?xml version="1.0" encoding="UTF-8"?>
@@ -1034,10 +1619,18 @@ mod tests {
             SettingsScope::try_from("@[cpu_type=arm64_32]").unwrap(),
             SettingsScope::MultiArchCpuType(CPU_TYPE_ARM64_32)
         );
+        assert_eq!(
+            SettingsScope::try_from("@[cpu_type=i386]").unwrap(),
+            SettingsScope::MultiArchCpuType(CPU_TYPE_I386)
+        );
         assert_eq!(
             SettingsScope::try_from("@[cpu_type=x86_64]").unwrap(),
             SettingsScope::MultiArchCpuType(CPU_TYPE_X86_64)
         );
+        assert_eq!(
+            SettingsScope::try_from("@[cpu_type=armv7]").unwrap(),
+            SettingsScope::MultiArchCpuSubtype(CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V7)
+        );
         assert_eq!(
             SettingsScope::try_from("foo/bar").unwrap(),
             SettingsScope::Path("foo/bar".into())
@@ -1050,6 +1643,18 @@ mod tests {
             SettingsScope::try_from("foo/bar@[cpu_type=7]").unwrap(),
             SettingsScope::PathMultiArchCpuType("foo/bar".into(), 7_u32)
         );
+        assert_eq!(
+            SettingsScope::try_from("@[cpu_type=arm64e]").unwrap(),
+            SettingsScope::MultiArchCpuSubtype(CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_E)
+        );
+        assert_eq!(
+            SettingsScope::try_from("foo/bar@[cpu_type=arm64e]").unwrap(),
+            SettingsScope::PathMultiArchCpuSubtype(
+                "foo/bar".into(),
+                CPU_TYPE_ARM64,
+                CPU_SUBTYPE_ARM64_E
+            )
+        );
     }
 
     #[test]
@@ -1073,7 +1678,8 @@ mod tests {
             b"cpu_x86_64".to_vec(),
         );
 
-        let macho_settings = main_settings.as_nested_macho_settings(0, CPU_TYPE_ARM64);
+        let macho_settings =
+            main_settings.as_nested_macho_settings(0, CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_ALL);
         assert_eq!(
             macho_settings.binary_identifier(SettingsScope::Main),
             Some("ident")
@@ -1087,7 +1693,8 @@ mod tests {
             Some(b"index_0".as_ref())
         );
 
-        let macho_settings = main_settings.as_nested_macho_settings(0, CPU_TYPE_X86_64);
+        let macho_settings =
+            main_settings.as_nested_macho_settings(0, CPU_TYPE_X86_64, CPU_SUBTYPE_X86_64_ALL);
         assert_eq!(
             macho_settings.binary_identifier(SettingsScope::Main),
             Some("ident")
@@ -1100,6 +1707,31 @@ mod tests {
             macho_settings.info_plist_data(SettingsScope::Main),
             Some(b"cpu_x86_64".as_ref())
         );
+
+        // arm64e settings only apply to arm64e binaries and take precedence over
+        // arm64 settings.
+        main_settings.set_code_signature_flags(
+            SettingsScope::MultiArchCpuType(CPU_TYPE_ARM64),
+            CodeSignatureFlags::HOST,
+        );
+        main_settings.set_code_signature_flags(
+            SettingsScope::MultiArchCpuSubtype(CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_E),
+            CodeSignatureFlags::RUNTIME,
+        );
+
+        let macho_settings =
+            main_settings.as_nested_macho_settings(1, CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_ALL);
+        assert_eq!(
+            macho_settings.code_signature_flags(SettingsScope::Main),
+            Some(CodeSignatureFlags::HOST)
+        );
+
+        let macho_settings =
+            main_settings.as_nested_macho_settings(1, CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_E);
+        assert_eq!(
+            macho_settings.code_signature_flags(SettingsScope::Main),
+            Some(CodeSignatureFlags::RUNTIME)
+        );
     }
 
     #[test]
@@ -1143,6 +1775,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn unicode_normalization_paths() {
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(
+            SettingsScope::Path("Contents/MacOS/caf\u{e9}".into()),
+            "cafe",
+        );
+
+        // Paths on disk may be NFD normalized.
+        let macho_settings = settings.as_bundle_macho_settings("Contents/MacOS/cafe\u{301}");
+        assert_eq!(
+            macho_settings.binary_identifier(SettingsScope::Main),
+            Some("cafe")
+        );
+    }
+
     #[test]
     fn as_nested_bundle_settings() {
         let mut main_settings = SigningSettings::default();
@@ -1225,6 +1873,126 @@ mod tests {
         );
     }
 
+    #[test]
+    fn apple_certificates_chained() -> Result<(), AppleCodesignError> {
+        let (_, key, _) = crate::certificate::create_self_signed_code_signing_certificate(
+            x509_certificate::KeyAlgorithm::Ed25519,
+            crate::certificate::CertificateProfile::AppleDevelopment,
+            "team1",
+            "Joe Developer",
+            "US",
+            chrono::Duration::hours(1),
+        )?;
+        let cert = CapturedX509Certificate::from_der(
+            include_bytes!("testdata/apple-signed-apple-development.cer").to_vec(),
+        )?;
+
+        let mut settings = SigningSettings::default();
+        settings.set_signing_key(&key, cert);
+
+        let expected = vec![
+            (*crate::apple_certificates::KnownCertificate::WwdrG3).clone(),
+            (*crate::apple_certificates::KnownCertificate::AppleRootCa).clone(),
+        ];
+        assert_eq!(settings.certificate_chain(), expected.as_slice());
+
+        // Chaining again doesn't register duplicates.
+        assert_eq!(settings.chain_apple_certificates(), Some(expected.clone()));
+        assert_eq!(settings.certificate_chain(), expected.as_slice());
+
+        Ok(())
+    }
+
+    #[test]
+    fn scoped_signing_keys() -> Result<(), AppleCodesignError> {
+        let certificate = |team_id: &str| {
+            crate::certificate::create_self_signed_code_signing_certificate(
+                x509_certificate::KeyAlgorithm::Ed25519,
+                crate::certificate::CertificateProfile::DeveloperIdApplication,
+                team_id,
+                "Joe Developer",
+                "US",
+                chrono::Duration::hours(1),
+            )
+        };
+        let (main_cert, main_key, _) = certificate("MAINTEAM00")?;
+        let (helper_cert, helper_key, _) = certificate("HELPERTEAM")?;
+        let (arm64_cert, arm64_key, _) = certificate("ARM64TEAM0")?;
+
+        let helper = "Contents/MacOS/nested.app/Contents/MacOS/helper";
+
+        let mut settings = SigningSettings::default();
+        settings.set_signing_key(&main_key, main_cert.clone());
+        settings.set_team_id_from_signing_certificate();
+        settings.set_scoped_signing_key(
+            SettingsScope::Path(helper.into()),
+            &helper_key,
+            helper_cert.clone(),
+        );
+        settings.set_scoped_signing_key(
+            SettingsScope::PathMultiArchCpuType(helper.into(), CPU_TYPE_ARM64),
+            &arm64_key,
+            arm64_cert.clone(),
+        );
+        settings.set_scoped_team_id(
+            SettingsScope::Path("Contents/MacOS/other".into()),
+            "OTHERTEAM0",
+        );
+        assert_eq!(settings.scoped_signing_certificates().count(), 2);
+
+        let signing_cert = |settings: &SigningSettings| settings.signing_key().unwrap().1.clone();
+
+        // Entities without a scoped key-pair use the main one.
+        let other = settings.as_bundle_macho_settings("Contents/MacOS/other");
+        assert_eq!(signing_cert(&other), main_cert);
+        assert_eq!(other.team_id(), Some("OTHERTEAM0"));
+
+        // Scoped key-pairs follow paths into nested bundles.
+        let bundle = settings.as_nested_bundle_settings("Contents/MacOS/nested.app");
+        assert_eq!(signing_cert(&bundle), main_cert);
+        assert_eq!(bundle.team_id(), Some("MAINTEAM00"));
+
+        let macho = bundle.as_bundle_macho_settings("Contents/MacOS/helper");
+        assert_eq!(signing_cert(&macho), helper_cert);
+        assert_eq!(macho.team_id(), Some("HELPERTEAM"));
+
+        let x86_64 = macho.as_nested_macho_settings(0, CPU_TYPE_X86_64, CPU_SUBTYPE_X86_64_ALL);
+        assert_eq!(signing_cert(&x86_64), helper_cert);
+        assert_eq!(x86_64.team_id(), Some("HELPERTEAM"));
+        assert_eq!(x86_64.scoped_signing_certificates().count(), 0);
+
+        let arm64 = macho.as_nested_macho_settings(1, CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_ALL);
+        assert_eq!(signing_cert(&arm64), arm64_cert);
+        assert_eq!(arm64.team_id(), Some("ARM64TEAM0"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn time_stamp_url() -> Result<(), AppleCodesignError> {
+        let mut settings = SigningSettings::default();
+        assert!(settings.time_stamp_url().is_none());
+
+        settings.set_time_stamp_url(APPLE_TIMESTAMP_URL)?;
+        assert_eq!(
+            settings.time_stamp_url().map(|url| url.as_str()),
+            Some(APPLE_TIMESTAMP_URL)
+        );
+
+        // Nested entities are time-stamped the same way.
+        assert_eq!(
+            settings
+                .as_nested_macho_settings(0, CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_ALL)
+                .time_stamp_url(),
+            settings.time_stamp_url()
+        );
+
+        settings.clear_time_stamp_url();
+        assert!(settings.time_stamp_url().is_none());
+
+        Ok(())
+    }
+
     #[test]
     fn entitlements_handling() -> Result<(), AppleCodesignError> {
         let mut settings = SigningSettings::default();
@@ -1235,4 +2003,171 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn info_plist_path() -> Result<(), AppleCodesignError> {
+        let td = tempfile::TempDir::new()?;
+        let versions = td.path().join("Versions").join("A");
+        std::fs::create_dir_all(versions.join("Resources"))?;
+        std::fs::write(versions.join("Resources").join("Info.plist"), b"framework")?;
+
+        let mut settings = SigningSettings::default();
+        settings.set_info_plist_path(
+            SettingsScope::Path("Versions/A/MyFramework".into()),
+            "Resources/Info.plist",
+        );
+
+        // Paths follow their scope and are read relative to the binary being signed.
+        let mut macho_settings = settings.as_bundle_macho_settings("Versions/A/MyFramework");
+        assert_eq!(
+            macho_settings.info_plist_path(SettingsScope::Main),
+            Some(Path::new("Resources/Info.plist"))
+        );
+        assert!(macho_settings
+            .info_plist_data(SettingsScope::Main)
+            .is_none());
+        macho_settings.load_info_plist_paths(&versions.join("MyFramework"))?;
+        assert_eq!(
+            macho_settings.info_plist_data(SettingsScope::Main),
+            Some(b"framework".as_ref())
+        );
+
+        // Explicit data takes precedence.
+        let mut macho_settings = settings.as_bundle_macho_settings("Versions/A/MyFramework");
+        macho_settings.set_info_plist_data(SettingsScope::Main, b"explicit".to_vec());
+        macho_settings.load_info_plist_paths(&versions.join("MyFramework"))?;
+        assert_eq!(
+            macho_settings.info_plist_data(SettingsScope::Main),
+            Some(b"explicit".as_ref())
+        );
+
+        // Other binaries are unaffected.
+        let mut other_settings = settings.as_bundle_macho_settings("Versions/A/Other");
+        other_settings.load_info_plist_paths(&versions.join("Other"))?;
+        assert!(other_settings
+            .info_plist_data(SettingsScope::Main)
+            .is_none());
+
+        settings.set_info_plist_path(SettingsScope::Main, "missing.plist");
+        assert!(settings
+            .load_info_plist_paths(&versions.join("MyFramework"))
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_existing_signature() -> Result<(), AppleCodesignError> {
+        let requirement = CodeRequirementExpression::Identifier("com.example.binary".into());
+
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(SettingsScope::Main, "com.example.binary");
+        settings.set_team_id("ABCDEF");
+        settings.set_code_signature_flags(SettingsScope::Main, CodeSignatureFlags::RUNTIME);
+        settings.set_runtime_version(SettingsScope::Main, semver::Version::new(13, 0, 0));
+        settings.set_entitlements_xml(SettingsScope::Main, ENTITLEMENTS_XML)?;
+        settings.set_designated_requirement_expression(SettingsScope::Main, &requirement)?;
+        settings.set_digest_type(DigestType::Sha1);
+        settings.add_extra_digest(SettingsScope::Main, DigestType::Sha256);
+
+        let macho = crate::test_support::SyntheticMachO::default().build();
+        let mut signed = vec![];
+        crate::macho_signing::MachOSigner::new(&macho)?
+            .write_signed_binary(&settings, &mut signed)?;
+
+        let mach = MachFile::parse(&signed)?;
+        let signature = mach.nth_macho(0)?.code_signature()?.unwrap();
+        let imported = SigningSettings::from_existing_signature(&signature)?;
+
+        let scope = SettingsScope::Main;
+        assert_eq!(
+            imported.binary_identifier(&scope),
+            Some("com.example.binary")
+        );
+        assert_eq!(imported.team_id(), Some("ABCDEF"));
+        assert_eq!(
+            imported.code_signature_flags(&scope),
+            Some(CodeSignatureFlags::RUNTIME)
+        );
+        assert_eq!(
+            imported.runtime_version(&scope),
+            Some(&semver::Version::new(13, 0, 0))
+        );
+        assert_eq!(
+            imported.entitlements_plist(&scope),
+            settings.entitlements_plist(&scope)
+        );
+        assert!(matches!(
+            imported.designated_requirement(&scope),
+            DesignatedRequirementMode::Explicit(exprs) if exprs == &vec![requirement.to_bytes()?]
+        ));
+        assert_eq!(
+            imported.all_digests(scope),
+            vec![DigestType::Sha1, DigestType::Sha256]
+        );
+
+        // Ad-hoc signing adds a flag that isn't carried over.
+        assert!(signature
+            .code_directory()?
+            .unwrap()
+            .flags
+            .contains(CodeSignatureFlags::ADHOC));
+
+        Ok(())
+    }
+
+    #[test]
+    fn import_preserves_designated_requirement() -> Result<(), AppleCodesignError> {
+        let requirement = CodeRequirementExpression::Identifier("com.example.binary".into());
+
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(SettingsScope::Main, "com.example.binary");
+        settings.set_code_signature_flags(SettingsScope::Main, CodeSignatureFlags::RUNTIME);
+        settings.set_entitlements_xml(SettingsScope::Main, ENTITLEMENTS_XML)?;
+        settings.set_designated_requirement_expression(SettingsScope::Main, &requirement)?;
+
+        let macho = crate::test_support::SyntheticMachO::default().build();
+        let mut signed = vec![];
+        crate::macho_signing::MachOSigner::new(&macho)?
+            .write_signed_binary(&settings, &mut signed)?;
+
+        let scope = SettingsScope::MultiArchIndex(0);
+
+        // The designated requirement isn't preserved by default.
+        let mut resign = SigningSettings::default();
+        resign.import_settings_from_macho(&signed)?;
+        assert_eq!(resign.binary_identifier(&scope), Some("com.example.binary"));
+        assert!(resign
+            .code_signature_flags(&scope)
+            .unwrap()
+            .contains(CodeSignatureFlags::RUNTIME));
+        assert_eq!(
+            resign.entitlements_plist(&scope),
+            settings.entitlements_plist(SettingsScope::Main)
+        );
+        assert!(matches!(
+            resign.designated_requirement(&scope),
+            DesignatedRequirementMode::Auto
+        ));
+
+        let mut resign = SigningSettings::default();
+        resign.set_preserve_designated_requirement(true);
+        resign.import_settings_from_macho(&signed)?;
+        assert!(matches!(
+            resign.designated_requirement(&scope),
+            DesignatedRequirementMode::Explicit(exprs) if exprs == &vec![requirement.to_bytes()?]
+        ));
+
+        // Explicit settings take precedence.
+        let mut resign = SigningSettings::default();
+        resign.set_preserve_designated_requirement(true);
+        resign.set_auto_designated_requirement(SettingsScope::Main);
+        resign.import_settings_from_macho(&signed)?;
+        assert!(matches!(
+            resign.designated_requirement(&scope),
+            DesignatedRequirementMode::Auto
+        ));
+
+        Ok(())
+    }
 }
@@ -16,8 +16,10 @@ use {
         bundle_signing::SignedMachOInfo,
         dmg::{DmgReader, DmgSigner},
         embedded_signature::{Blob, DigestType},
+        metrics::{Metrics, MetricsRecorder, NetworkService},
         reader::PathType,
-        ticket_lookup::{default_client, lookup_notarization_ticket},
+        retry::RetryPolicy,
+        ticket_lookup::{default_client, lookup_notarization_ticket, TicketLookupResponse},
         AppleCodesignError,
     },
     apple_bundles::DirectoryBundle,
@@ -30,6 +32,7 @@ use {
         fs::File,
         io::{Read, Seek, SeekFrom, Write},
         path::Path,
+        sync::Arc,
     },
 };
 
@@ -131,16 +134,68 @@ pub fn xar_notarization_trailer(ticket_data: &[u8]) -> Result<Vec<u8>, AppleCode
     Ok(cursor.into_inner())
 }
 
+/// Find the offset of a notarization ticket trailer stapled to a XAR archive.
+///
+/// Returns the offset of the `Terminator` [XarNotarizationTrailer] denoting
+/// the end of XAR content if the stream ends with a `Ticket` trailer. Truncating
+/// the stream to this offset removes the stapled ticket.
+pub fn xar_notarization_trailer_offset<F: Read + Seek>(
+    fh: &mut F,
+) -> Result<Option<u64>, AppleCodesignError> {
+    let trailer_size = 16;
+
+    let end = fh.seek(SeekFrom::End(0))?;
+    if end < trailer_size * 2 {
+        return Ok(None);
+    }
+
+    fh.seek(SeekFrom::Start(end - trailer_size))?;
+    let ticket = fh.ioread_with::<XarNotarizationTrailer>(scroll::LE)?;
+
+    if ticket.magic != XAR_NOTARIZATION_TRAILER_MAGIC
+        || ticket.typ != XarNotarizationTrailerType::Ticket as u16
+    {
+        return Ok(None);
+    }
+
+    let offset = end
+        .checked_sub(trailer_size * 2 + ticket.length as u64)
+        .ok_or(AppleCodesignError::StapleMalformedXar)?;
+
+    fh.seek(SeekFrom::Start(offset))?;
+    let terminator = fh.ioread_with::<XarNotarizationTrailer>(scroll::LE)?;
+
+    if terminator.magic != XAR_NOTARIZATION_TRAILER_MAGIC
+        || terminator.typ != XarNotarizationTrailerType::Terminator as u16
+    {
+        return Err(AppleCodesignError::StapleMalformedXar);
+    }
+
+    Ok(Some(offset))
+}
+
 /// Handles stapling operations.
 pub struct Stapler {
     client: Client,
+    retry_policy: RetryPolicy,
+    metrics: Metrics,
 }
 
 impl Stapler {
+    /// Whether [Self::staple_path()] can staple entities of a given type.
+    pub(crate) fn supports_path_type(kind: PathType) -> bool {
+        match kind {
+            PathType::Bundle | PathType::Dmg | PathType::Xar => true,
+            PathType::MachO | PathType::Zip | PathType::Other => false,
+        }
+    }
+
     /// Construct a new instance with defaults.
     pub fn new() -> Result<Self, AppleCodesignError> {
         Ok(Self {
             client: default_client()?,
+            retry_policy: RetryPolicy::default(),
+            metrics: Metrics::default(),
         })
     }
 
@@ -149,6 +204,24 @@ impl Stapler {
         self.client = client;
     }
 
+    /// Set the policy for retrying failed ticket lookups.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+
+    /// Set a recorder to receive the latency of ticket lookups.
+    pub fn set_metrics_recorder(&mut self, recorder: Arc<dyn MetricsRecorder>) {
+        self.metrics = Metrics::new(recorder);
+    }
+
+    fn lookup_ticket(&self, record_name: &str) -> Result<TicketLookupResponse, AppleCodesignError> {
+        self.retry_policy.run("notarization ticket lookup", || {
+            self.metrics.time_request(NetworkService::TicketLookup, || {
+                lookup_notarization_ticket(&self.client, record_name)
+            })
+        })
+    }
+
     /// Look up a notarization ticket for an app bundle.
     ///
     /// This will resolve the notarization ticket record name from the contents
@@ -164,7 +237,7 @@ impl Stapler {
     ) -> Result<Vec<u8>, AppleCodesignError> {
         let record_name = record_name_from_executable_bundle(bundle)?;
 
-        let response = lookup_notarization_ticket(&self.client, &record_name)?;
+        let response = self.lookup_ticket(&record_name)?;
 
         let ticket_data = response.signed_ticket(&record_name)?;
 
@@ -202,7 +275,7 @@ impl Stapler {
 
         let record_name = format!("2/{digest_type}/{digest}");
 
-        let response = lookup_notarization_ticket(&self.client, &record_name)?;
+        let response = self.lookup_ticket(&record_name)?;
 
         response.signed_ticket(&record_name)
     }
@@ -240,7 +313,7 @@ impl Stapler {
 
         let record_name = format!("2/{digest_type}/{digest}");
 
-        let response = lookup_notarization_ticket(&self.client, &record_name)?;
+        let response = self.lookup_ticket(&record_name)?;
 
         response.signed_ticket(&record_name)
     }
@@ -250,7 +323,9 @@ impl Stapler {
     /// Takes the handle to a readable, writable, and seekable object.
     ///
     /// The stream will be opened as a XAR file. If a ticket is found, that ticket
-    /// will be appended to the end of the file.
+    /// will be appended to the end of the file. Since the stream can't be
+    /// truncated, a previously stapled ticket is left in place. See
+    /// [Self::staple_xar_file()] for replacing it.
     pub fn staple_xar<F: Read + Write + Seek + Sized + Debug>(
         &self,
         mut xar: XarReader<F>,
@@ -293,6 +368,34 @@ impl Stapler {
         Ok(())
     }
 
+    /// Staple a XAR archive backed by a file.
+    ///
+    /// This is like [Self::staple_xar()] except a previously stapled ticket is
+    /// replaced, like Apple's `stapler` tool does.
+    pub fn staple_xar_file(&self, mut xar: XarReader<File>) -> Result<(), AppleCodesignError> {
+        let ticket_data = self.lookup_ticket_for_xar(&mut xar)?;
+
+        warn!("found notarization ticket; proceeding with stapling");
+
+        let mut fh = xar.into_inner();
+
+        if let Some(offset) = xar_notarization_trailer_offset(&mut fh)? {
+            warn!("removing previously stapled notarization ticket");
+            fh.set_len(offset)?;
+        }
+
+        let trailer = xar_notarization_trailer(&ticket_data)?;
+
+        warn!(
+            "stapling notarization ticket trailer ({} bytes) to end of XAR",
+            trailer.len()
+        );
+        fh.seek(SeekFrom::End(0))?;
+        fh.write_all(&trailer)?;
+
+        Ok(())
+    }
+
     /// Attempt to staple an entity at a given filesystem path.
     ///
     /// The path will be modified on successful stapling operation.
@@ -320,7 +423,7 @@ impl Stapler {
             PathType::Xar => {
                 warn!("activating XAR stapling mode");
                 let xar = XarReader::new(File::options().read(true).write(true).open(path)?)?;
-                self.staple_xar(xar)
+                self.staple_xar_file(xar)
             }
             PathType::Zip | PathType::Other => Err(AppleCodesignError::StapleUnsupportedPath(
                 path.to_path_buf(),
@@ -328,3 +431,35 @@ impl Stapler {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use {super::*, std::io::Cursor};
+
+    #[test]
+    fn xar_trailer_offset() -> Result<(), AppleCodesignError> {
+        let xar = b"xar!".repeat(32);
+
+        assert_eq!(
+            xar_notarization_trailer_offset(&mut Cursor::new(&xar))?,
+            None
+        );
+
+        let mut stapled = xar.clone();
+        stapled.extend(xar_notarization_trailer(b"ticket")?);
+        assert_eq!(
+            xar_notarization_trailer_offset(&mut Cursor::new(&stapled))?,
+            Some(xar.len() as u64)
+        );
+
+        // A ticket trailer without a matching terminator is malformed.
+        let mut malformed = xar.clone();
+        malformed.extend(&xar_notarization_trailer(b"ticket")?[16..]);
+        assert!(matches!(
+            xar_notarization_trailer_offset(&mut Cursor::new(&malformed)),
+            Err(AppleCodesignError::StapleMalformedXar)
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Fixtures shared by the tests of this and dependent crates.
+//!
+//! This isn't part of the supported API and may change at any time.
+
+use {
+    crate::{embedded_signature::DigestType, error::AppleCodesignError},
+    goblin::mach::{
+        cputype::{CPU_SUBTYPE_X86_64_ALL, CPU_TYPE_X86_64},
+        header::MH_EXECUTE,
+    },
+};
+
+/// File offset of the `__text` section content.
+pub const TEXT_OFFSET: u32 = 0x1000;
+
+/// File offset of the `__LINKEDIT` segment.
+pub const LINKEDIT_OFFSET: u32 = 0x4000;
+
+/// Builds minimal Mach-O executables with `__TEXT` and `__LINKEDIT` segments.
+///
+/// `__TEXT` holds a single `__text` section at [TEXT_OFFSET]. `__LINKEDIT`
+/// begins at [LINKEDIT_OFFSET]. Everything else is zero filled.
+#[derive(Clone, Debug)]
+pub struct SyntheticMachO {
+    is_64: bool,
+    cputype: u32,
+    cpusubtype: u32,
+    linkedit_size: u32,
+    chained_fixups: Option<(u32, u32)>,
+    code_signature: Option<(u32, u32)>,
+}
+
+impl Default for SyntheticMachO {
+    fn default() -> Self {
+        Self::new_64(CPU_TYPE_X86_64, CPU_SUBTYPE_X86_64_ALL)
+    }
+}
+
+impl SyntheticMachO {
+    /// Construct a builder for a 64-bit (`MH_MAGIC_64`) binary.
+    pub fn new_64(cputype: u32, cpusubtype: u32) -> Self {
+        Self {
+            is_64: true,
+            cputype,
+            cpusubtype,
+            linkedit_size: 0x100,
+            chained_fixups: None,
+            code_signature: None,
+        }
+    }
+
+    /// Construct a builder for a 32-bit (`MH_MAGIC`) binary.
+    pub fn new_32(cputype: u32, cpusubtype: u32) -> Self {
+        Self {
+            is_64: false,
+            ..Self::new_64(cputype, cpusubtype)
+        }
+    }
+
+    /// Set the size of the `__LINKEDIT` segment. Defaults to 0x100.
+    pub fn linkedit_size(mut self, size: u32) -> Self {
+        self.linkedit_size = size;
+        self
+    }
+
+    /// Emit an `LC_DYLD_CHAINED_FIXUPS` load command with the given `(dataoff, datasize)`.
+    pub fn chained_fixups(mut self, location: (u32, u32)) -> Self {
+        self.chained_fixups = Some(location);
+        self
+    }
+
+    /// Emit an `LC_CODE_SIGNATURE` load command with the given `(dataoff, datasize)`.
+    pub fn code_signature(mut self, location: (u32, u32)) -> Self {
+        self.code_signature = Some(location);
+        self
+    }
+
+    /// Emit the binary.
+    pub fn build(&self) -> Vec<u8> {
+        let mut commands = vec![];
+
+        self.segment(&mut commands, b"__TEXT", 0, 0x4000, 1);
+        let mut sectname = [0u8; 32];
+        sectname[0..6].copy_from_slice(b"__text");
+        sectname[16..22].copy_from_slice(b"__TEXT");
+        commands.extend(sectname);
+        if self.is_64 {
+            // addr, size.
+            commands.extend(0x1000u64.to_le_bytes());
+            commands.extend(0x10u64.to_le_bytes());
+            // offset, align, reloff, nreloc, flags, reserved1-3.
+            for v in [TEXT_OFFSET, 0, 0, 0, 0, 0, 0, 0] {
+                commands.extend(v.to_le_bytes());
+            }
+        } else {
+            // addr, size, offset, align, reloff, nreloc, flags, reserved1-2.
+            for v in [0x1000u32, 0x10, TEXT_OFFSET, 0, 0, 0, 0, 0, 0] {
+                commands.extend(v.to_le_bytes());
+            }
+        }
+
+        self.segment(
+            &mut commands,
+            b"__LINKEDIT",
+            LINKEDIT_OFFSET,
+            self.linkedit_size,
+            0,
+        );
+
+        let mut ncmds = 2u32;
+        for (cmd, location) in [
+            (0x80000034, self.chained_fixups),
+            (0x1d, self.code_signature),
+        ] {
+            if let Some((dataoff, datasize)) = location {
+                for v in [cmd, 16, dataoff, datasize] {
+                    commands.extend(v.to_le_bytes());
+                }
+                ncmds += 1;
+            }
+        }
+
+        let mut data = vec![];
+        // magic, cputype, cpusubtype, filetype, ncmds, sizeofcmds, flags.
+        for v in [
+            if self.is_64 { 0xfeedfacf } else { 0xfeedface },
+            self.cputype,
+            self.cpusubtype,
+            MH_EXECUTE,
+            ncmds,
+            commands.len() as u32,
+            0,
+        ] {
+            data.extend(v.to_le_bytes());
+        }
+        if self.is_64 {
+            // reserved.
+            data.extend(0u32.to_le_bytes());
+        }
+        data.extend(commands);
+        data.resize((LINKEDIT_OFFSET + self.linkedit_size) as usize, 0);
+
+        data
+    }
+
+    /// Emit an `LC_SEGMENT` or `LC_SEGMENT_64` load command header.
+    fn segment(&self, data: &mut Vec<u8>, name: &[u8], offset: u32, size: u32, nsects: u32) {
+        let mut segname = [0u8; 16];
+        segname[0..name.len()].copy_from_slice(name);
+
+        if self.is_64 {
+            data.extend(0x19u32.to_le_bytes());
+            data.extend((72 + 80 * nsects).to_le_bytes());
+            data.extend(segname);
+            // vmaddr, vmsize, fileoff, filesize.
+            for v in [offset, 0x4000, offset, size] {
+                data.extend((v as u64).to_le_bytes());
+            }
+        } else {
+            data.extend(0x1u32.to_le_bytes());
+            data.extend((56 + 68 * nsects).to_le_bytes());
+            data.extend(segname);
+            // vmaddr, vmsize, fileoff, filesize.
+            for v in [offset, 0x4000, offset, size] {
+                data.extend(v.to_le_bytes());
+            }
+        }
+
+        // maxprot, initprot, nsects, flags.
+        for v in [5u32, 5, nsects, 0] {
+            data.extend(v.to_le_bytes());
+        }
+    }
+}
+
+/// Digest `data` in pages of `page_size` bytes, on multiple threads if `parallel`.
+///
+/// This exposes the page digesting used for code directories to benchmarks.
+pub fn digest_pages(
+    data: &[u8],
+    digest: DigestType,
+    page_size: usize,
+    parallel: bool,
+) -> Result<Vec<Vec<u8>>, AppleCodesignError> {
+    crate::macho::digest_pages(data, digest, page_size, parallel)
+}
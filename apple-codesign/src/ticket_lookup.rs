@@ -10,7 +10,7 @@ use {
     log::warn,
     reqwest::blocking::{Client, ClientBuilder},
     serde::{Deserialize, Serialize},
-    std::collections::HashMap,
+    std::collections::BTreeMap,
 };
 
 /// URL of HTTP service where Apple publishes stapling tickets.
@@ -98,7 +98,7 @@ pub struct TicketLookupResponseRecordSuccess {
     /// Holds data.
     ///
     /// The `signedTicket` key holds the ticket.
-    pub fields: HashMap<String, Field>,
+    pub fields: BTreeMap<String, Field>,
     pub modified: TicketRecordEvent,
     // TODO pluginFields
     pub record_change_tag: String,
@@ -195,7 +195,8 @@ pub fn lookup_notarization_tickets<'a>(
         .header("Content-Type", "application/json")
         .json(&body);
 
-    let response = req.send()?;
+    // Server errors are surfaced as HTTP errors so they can be retried.
+    let response = req.send()?.error_for_status()?;
 
     let body = response.bytes()?;
 
@@ -5,7 +5,7 @@
 //! Code signing verification.
 //!
 //! This module implements functionality for verifying code signatures on
-//! Mach-O binaries.
+//! Mach-O binaries and signatures of XAR archives (e.g. `.pkg` installers).
 //!
 //! # Verification Caveats
 //!
@@ -23,24 +23,109 @@
 
 use {
     crate::{
-        code_directory::CodeDirectoryBlob,
-        embedded_signature::{CodeSigningSlot, DigestType, EmbeddedSignature},
+        apple_certificates::KnownCertificate,
+        certificate::AppleCertificate,
+        code_directory::{CodeDirectoryBlob, CS_CDHASH_LEN},
+        code_requirement::RequirementContext,
+        embedded_signature::{
+            Blob, CodeSigningMagic, CodeSigningSlot, DetachedSignatureBlob, DigestType,
+            EmbeddedSignature,
+        },
+        embedded_signature_builder::{CD_DIGESTS_OID, CD_DIGESTS_PLIST_OID},
         error::AppleCodesignError,
-        macho::{MachFile, MachOBinary},
+        junit::{check_results, CheckOutcome, CheckResult, VerificationCheck},
+        macho::{code_page_digests, scatter_page_digests, MachFile, MachOBinary},
+        mapped_file::MappedFile,
+        ocsp::{CertificateStatus, OcspChecker},
+        reader::PathType,
+    },
+    apple_xar::reader::XarReader,
+    cryptographic_message_syntax::{asn1::rfc3161::TstInfo, CmsError, SignedData, SignerInfo},
+    goblin::mach::cputype::get_arch_name_from_types,
+    scroll::Pread,
+    std::{
+        collections::VecDeque,
+        ops::Deref,
+        path::{Path, PathBuf},
     },
-    cryptographic_message_syntax::{CmsError, SignedData},
-    std::path::{Path, PathBuf},
-    x509_certificate::{DigestAlgorithm, SignatureAlgorithm},
+    x509_certificate::{CapturedX509Certificate, DigestAlgorithm, SignatureAlgorithm},
 };
 
+#[cfg(feature = "parallel-digests")]
+use rayon::prelude::*;
+
 /// Context for a verification issue.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct VerificationContext {
     /// Path of binary.
     pub path: Option<PathBuf>,
 
     /// Index of Mach-O binary within a fat binary that is problematic.
     pub fat_index: Option<usize>,
+
+    /// Name of the CPU architecture of the Mach-O binary within a fat binary.
+    pub arch: Option<String>,
+
+    /// Signing identifier recorded in the code directory of the binary.
+    ///
+    /// Only present if the code directory could be parsed.
+    pub identifier: Option<String>,
+
+    /// Team ID recorded in the code directory of the binary.
+    pub team_id: Option<String>,
+
+    /// The region of the binary covered by the problematic code page.
+    ///
+    /// Only present for code digest mismatches.
+    pub code_page: Option<CodePageLocation>,
+
+    /// Time attested by a verified Time-Stamp Token on the CMS signature.
+    ///
+    /// Only present for problems found after the token was verified.
+    pub time_stamp: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Describes the region of a Mach-O binary covered by a code page.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CodePageLocation {
+    /// File offset of the start of the page.
+    pub start_offset: u64,
+
+    /// File offset of the end of the page (exclusive).
+    pub end_offset: u64,
+
+    /// Segments and sections the page covers.
+    ///
+    /// See [MachOBinary::file_regions()] for the format.
+    pub regions: Vec<String>,
+}
+
+impl std::fmt::Display for CodePageLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "file offsets 0x{:x}-0x{:x} in {}",
+            self.start_offset,
+            self.end_offset,
+            self.regions.join(", ")
+        ))
+    }
+}
+
+/// The class of failure a verification problem represents.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum VerificationFailure {
+    /// Signature data is missing, malformed, or doesn't bind the code.
+    SignatureInvalid,
+    /// Code or resources were modified after signing.
+    ResourcesModified,
+    /// The signature isn't made by a trusted, unrevoked certificate chain.
+    ///
+    /// Ad-hoc signatures fall in this class.
+    ChainUntrusted,
+    /// Signature data is valid but isn't laid out like Apple's tools do.
+    ///
+    /// `codesign --verify` only rejects these with `--strict`.
+    Layout,
 }
 
 /// Describes a problem with verification.
@@ -52,20 +137,118 @@ pub enum VerificationProblemType {
     MachOSignatureError(AppleCodesignError),
     LinkeditNotLastSegment,
     SignatureNotLastLinkeditData,
+    SuperblobLayout(AppleCodesignError),
     NoCryptographicSignature,
     CmsError(CmsError),
     CmsOldDigestAlgorithm(DigestAlgorithm),
     CmsOldSignatureAlgorithm(SignatureAlgorithm),
+    CmsHashAgilityError(AppleCodesignError),
+    CmsCdHashMissing(CodeSigningSlot, Vec<u8>),
+    CmsCdHashUnexpected(Vec<u8>),
+    CmsCodeDirectoryDigestMissing(CodeSigningSlot, DigestType),
+    CmsCodeDirectoryDigestUnexpected(DigestAlgorithm, Vec<u8>),
+    CmsCodeDirectoryDigestMismatch(CodeSigningSlot, Vec<u8>, Vec<u8>),
+    TimeStampTokenError(CmsError),
+    TimeStampTokenMalformed(&'static str),
+    TimeStampImprintMismatch(Vec<u8>, Vec<u8>),
+    CmsCertificateChainUntrusted(String),
+    CertificateRevoked(String, chrono::DateTime<chrono::Utc>),
+    RevocationCheckError(AppleCodesignError),
     NoCodeDirectory,
     CodeDirectoryOldDigestAlgorithm(DigestType),
     CodeDigestError(AppleCodesignError),
     CodeDigestMissingEntry(usize, Vec<u8>),
     CodeDigestExtraEntry(usize, Vec<u8>),
     CodeDigestMismatch(usize, Vec<u8>, Vec<u8>),
+    CodeLimitExceedsData(u64, u64),
+    CodeLimitMismatch(u64, u64),
     SlotDigestMissing(CodeSigningSlot),
     ExtraSlotDigest(CodeSigningSlot, Vec<u8>),
     SlotDigestMismatch(CodeSigningSlot, Vec<u8>, Vec<u8>),
     SlotDigestError(AppleCodesignError),
+    DetachedSignatureError(AppleCodesignError),
+    DetachedSignatureMissingArch(u32),
+    DesignatedRequirementUnsatisfied(String),
+    DesignatedRequirementError(AppleCodesignError),
+    EntitlementsMismatch,
+    XarError(AppleCodesignError),
+    XarTableOfContentsChecksumMismatch,
+    XarFileChecksumMismatch(String),
+    XarSignatureError(AppleCodesignError),
+}
+
+impl VerificationProblemType {
+    /// The class of failure this problem represents.
+    pub fn failure(&self) -> VerificationFailure {
+        match self {
+            Self::SuperblobLayout(_) => VerificationFailure::Layout,
+            Self::NoCryptographicSignature
+            | Self::CmsCertificateChainUntrusted(_)
+            | Self::CertificateRevoked(..)
+            | Self::RevocationCheckError(_) => VerificationFailure::ChainUntrusted,
+            Self::CodeDigestMissingEntry(..)
+            | Self::CodeDigestExtraEntry(..)
+            | Self::CodeDigestMismatch(..)
+            | Self::SlotDigestMissing(_)
+            | Self::ExtraSlotDigest(..)
+            | Self::SlotDigestMismatch(..)
+            | Self::EntitlementsMismatch
+            | Self::XarTableOfContentsChecksumMismatch
+            | Self::XarFileChecksumMismatch(_) => VerificationFailure::ResourcesModified,
+            _ => VerificationFailure::SignatureInvalid,
+        }
+    }
+
+    /// The signature slot this problem relates to, if any.
+    pub fn slot(&self) -> Option<CodeSigningSlot> {
+        match self {
+            Self::SlotDigestMissing(slot)
+            | Self::ExtraSlotDigest(slot, _)
+            | Self::SlotDigestMismatch(slot, _, _)
+            | Self::CmsCdHashMissing(slot, _)
+            | Self::CmsCodeDirectoryDigestMissing(slot, _)
+            | Self::CmsCodeDirectoryDigestMismatch(slot, _, _) => Some(*slot),
+            _ => None,
+        }
+    }
+
+    /// The index of the code page this problem relates to, if any.
+    pub fn page_index(&self) -> Option<usize> {
+        match self {
+            Self::CodeDigestMissingEntry(index, _)
+            | Self::CodeDigestExtraEntry(index, _)
+            | Self::CodeDigestMismatch(index, _, _) => Some(*index),
+            _ => None,
+        }
+    }
+
+    /// The digest recorded in the signature, if this problem relates to a digest.
+    pub fn expected_digest(&self) -> Option<&[u8]> {
+        match self {
+            Self::CodeDigestExtraEntry(_, digest)
+            | Self::CodeDigestMismatch(_, digest, _)
+            | Self::ExtraSlotDigest(_, digest)
+            | Self::SlotDigestMismatch(_, digest, _)
+            | Self::CmsCdHashUnexpected(digest)
+            | Self::CmsCodeDirectoryDigestUnexpected(_, digest)
+            | Self::CmsCodeDirectoryDigestMismatch(_, digest, _)
+            | Self::TimeStampImprintMismatch(digest, _) => Some(digest),
+            _ => None,
+        }
+    }
+
+    /// The digest computed from actual content, if this problem relates to a digest.
+    pub fn actual_digest(&self) -> Option<&[u8]> {
+        match self {
+            Self::CodeDigestMissingEntry(_, digest)
+            | Self::CodeDigestMismatch(_, _, digest)
+            | Self::SlotDigestMismatch(_, _, digest)
+            | Self::CmsCdHashMissing(_, digest)
+            | Self::CmsCodeDirectoryDigestMismatch(_, _, digest)
+            | Self::TimeStampImprintMismatch(_, digest) => Some(digest),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -76,13 +259,32 @@ pub struct VerificationProblem {
 
 impl std::fmt::Display for VerificationProblem {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let context = match (&self.context.path, &self.context.fat_index) {
+        let location = match (&self.context.path, &self.context.fat_index) {
             (None, None) => None,
             (Some(path), None) => Some(format!("{}", path.display())),
             (None, Some(index)) => Some(format!("@{index}")),
             (Some(path), Some(index)) => Some(format!("{}@{}", path.display(), index)),
         };
 
+        let location = match (location, &self.context.arch) {
+            (Some(location), Some(arch)) => Some(format!("{location}[{arch}]")),
+            (location, _) => location,
+        };
+
+        let signer = match (&self.context.identifier, &self.context.team_id) {
+            (None, None) => None,
+            (Some(identifier), None) => Some(format!("identifier={identifier}")),
+            (None, Some(team)) => Some(format!("team={team}")),
+            (Some(identifier), Some(team)) => Some(format!("identifier={identifier}; team={team}")),
+        };
+
+        let context = match (location, signer) {
+            (None, None) => None,
+            (Some(location), None) => Some(location),
+            (None, Some(signer)) => Some(format!("({signer})")),
+            (Some(location), Some(signer)) => Some(format!("{location} ({signer})")),
+        };
+
         let message = match &self.problem {
             VerificationProblemType::IoError(e) => format!("I/O error: {e}"),
             VerificationProblemType::MachOParseError(e) => format!("Mach-O parse failure: {e}"),
@@ -98,6 +300,9 @@ impl std::fmt::Display for VerificationProblem {
             VerificationProblemType::SignatureNotLastLinkeditData => {
                 "signature isn't last data in __LINKEDIT segment".to_string()
             }
+            VerificationProblemType::SuperblobLayout(e) => {
+                format!("signature blobs aren't laid out like Apple's tools do: {e}")
+            }
             VerificationProblemType::NoCryptographicSignature => {
                 "no cryptographic signature present".to_string()
             }
@@ -108,6 +313,68 @@ impl std::fmt::Display for VerificationProblem {
             VerificationProblemType::CmsOldSignatureAlgorithm(alg) => {
                 format!("insecure signature algorithm used: {alg:?}")
             }
+            VerificationProblemType::CmsHashAgilityError(e) => {
+                format!("error parsing CMS code directory digests: {e:?}")
+            }
+            VerificationProblemType::CmsCdHashMissing(slot, cdhash) => {
+                format!(
+                    "CMS cdhashes attribute missing cdhash {} of code directory in slot {:?}",
+                    hex::encode(cdhash),
+                    slot
+                )
+            }
+            VerificationProblemType::CmsCdHashUnexpected(cdhash) => {
+                format!(
+                    "CMS cdhashes attribute has cdhash {} not matching any code directory",
+                    hex::encode(cdhash)
+                )
+            }
+            VerificationProblemType::CmsCodeDirectoryDigestMissing(slot, digest_type) => {
+                format!(
+                    "CMS code directory digests attribute missing {digest_type} digest of code directory in slot {slot:?}"
+                )
+            }
+            VerificationProblemType::CmsCodeDirectoryDigestUnexpected(alg, digest) => {
+                format!(
+                    "CMS code directory digests attribute has {:?} digest {} not matching any code directory",
+                    alg,
+                    hex::encode(digest)
+                )
+            }
+            VerificationProblemType::CmsCodeDirectoryDigestMismatch(
+                slot,
+                cms_digest,
+                actual_digest,
+            ) => {
+                format!(
+                    "CMS code directory digest mismatch for slot {:?}; recorded digest {}, actual {}",
+                    slot,
+                    hex::encode(cms_digest),
+                    hex::encode(actual_digest)
+                )
+            }
+            VerificationProblemType::TimeStampTokenError(e) => {
+                format!("time-stamp token error: {e}")
+            }
+            VerificationProblemType::TimeStampTokenMalformed(reason) => {
+                format!("malformed time-stamp token: {reason}")
+            }
+            VerificationProblemType::TimeStampImprintMismatch(token_digest, actual_digest) => {
+                format!(
+                    "time-stamp token message imprint mismatch; recorded digest {}, actual {}",
+                    hex::encode(token_digest),
+                    hex::encode(actual_digest)
+                )
+            }
+            VerificationProblemType::CertificateRevoked(name, time) => {
+                format!("certificate {name} was revoked at {time}")
+            }
+            VerificationProblemType::RevocationCheckError(e) => {
+                format!("unable to check certificate revocation status: {e}")
+            }
+            VerificationProblemType::CmsCertificateChainUntrusted(name) => {
+                format!("signing certificate {name} claims to be issued by Apple but doesn't chain to an Apple root CA")
+            }
             VerificationProblemType::NoCodeDirectory => "no code directory".to_string(),
             VerificationProblemType::CodeDirectoryOldDigestAlgorithm(hash_type) => {
                 format!("insecure digest algorithm used in code directory: {hash_type:?}")
@@ -137,6 +404,16 @@ impl std::fmt::Display for VerificationProblem {
                     hex::encode(actual_digest)
                 )
             }
+            VerificationProblemType::CodeLimitExceedsData(code_limit, data_len) => {
+                format!(
+                    "code limit 0x{code_limit:x} exceeds binary size 0x{data_len:x}; binary may be truncated"
+                )
+            }
+            VerificationProblemType::CodeLimitMismatch(code_limit, expected) => {
+                format!(
+                    "code limit 0x{code_limit:x} does not match start of signature data 0x{expected:x}"
+                )
+            }
             VerificationProblemType::SlotDigestMissing(slot) => {
                 format!("missing digest for slot {slot:?}")
             }
@@ -158,6 +435,36 @@ impl std::fmt::Display for VerificationProblem {
             VerificationProblemType::SlotDigestError(e) => {
                 format!("error computing slot digest: {e:?}")
             }
+            VerificationProblemType::DetachedSignatureError(e) => {
+                format!("error parsing detached signature: {e}")
+            }
+            VerificationProblemType::DetachedSignatureMissingArch(cputype) => {
+                format!("detached signature has no signature for CPU type 0x{cputype:x}")
+            }
+            VerificationProblemType::DesignatedRequirementUnsatisfied(requirement) => {
+                format!("designated requirement not satisfied: {requirement}")
+            }
+            VerificationProblemType::DesignatedRequirementError(e) => {
+                format!("error evaluating designated requirement: {e}")
+            }
+            VerificationProblemType::EntitlementsMismatch => {
+                "XML and DER entitlements differ".to_string()
+            }
+            VerificationProblemType::XarError(e) => format!("XAR error: {e}"),
+            VerificationProblemType::XarTableOfContentsChecksumMismatch => {
+                "XAR table of contents checksum mismatch".to_string()
+            }
+            VerificationProblemType::XarFileChecksumMismatch(path) => {
+                format!("XAR file checksum mismatch for {path}")
+            }
+            VerificationProblemType::XarSignatureError(e) => {
+                format!("XAR signature verification failed: {e}")
+            }
+        };
+
+        let message = match &self.context.code_page {
+            Some(page) => format!("{message} ({page})"),
+            None => message,
         };
 
         match context {
@@ -167,6 +474,14 @@ impl std::fmt::Display for VerificationProblem {
     }
 }
 
+/// Whether [verify_path()] can verify entities of a given type.
+pub(crate) fn verify_supports_path_type(kind: PathType) -> bool {
+    match kind {
+        PathType::MachO | PathType::Xar => true,
+        PathType::Bundle | PathType::Dmg | PathType::Zip | PathType::Other => false,
+    }
+}
+
 /// Verifies a binary in a given path.
 ///
 /// Returns a vector of problems detected. An empty vector means no
@@ -176,10 +491,11 @@ pub fn verify_path(path: impl AsRef<Path>) -> Vec<VerificationProblem> {
 
     let context = VerificationContext {
         path: Some(path.to_path_buf()),
-        fat_index: None,
+        ..Default::default()
     };
 
-    let data = match std::fs::read(path) {
+    // SAFETY: we don't modify the file while it is mapped.
+    let data = match unsafe { MappedFile::open(path) } {
         Ok(data) => data,
         Err(e) => {
             return vec![VerificationProblem {
@@ -189,7 +505,11 @@ pub fn verify_path(path: impl AsRef<Path>) -> Vec<VerificationProblem> {
         }
     };
 
-    verify_macho_data_internal(data, context)
+    if data.starts_with(XAR_MAGIC) {
+        verify_xar_data_internal(&data, context)
+    } else {
+        verify_macho_data_internal(data, context)
+    }
 }
 
 /// Verifies unparsed Mach-O data.
@@ -197,226 +517,1516 @@ pub fn verify_path(path: impl AsRef<Path>) -> Vec<VerificationProblem> {
 /// Returns a vector of problems detected. An empty vector means no
 /// problems were found.
 pub fn verify_macho_data(data: impl AsRef<[u8]>) -> Vec<VerificationProblem> {
-    let context = VerificationContext {
-        path: None,
-        fat_index: None,
-    };
-
-    verify_macho_data_internal(data, context)
-}
-
-fn verify_macho_data_internal(
-    data: impl AsRef<[u8]>,
-    context: VerificationContext,
-) -> Vec<VerificationProblem> {
-    match MachFile::parse(data.as_ref()) {
-        Ok(mach) => {
-            let mut problems = vec![];
-
-            for macho in mach.into_iter() {
-                let mut context = context.clone();
-                context.fat_index = macho.index;
-
-                problems.extend(verify_macho_internal(&macho, context));
-            }
-
-            problems
-        }
-        Err(e) => {
-            vec![VerificationProblem {
-                context,
-                problem: VerificationProblemType::MachOParseError(e),
-            }]
-        }
-    }
+    verify_macho_data_internal(data, VerificationContext::default())
 }
 
-/// Verifies a parsed Mach-O binary.
+/// Verifies unparsed XAR archive data, such as a `.pkg` installer.
+///
+/// The table of contents checksum, the checksums of all file data, and the RSA
+/// and CMS signatures over the table of contents checksum are verified.
 ///
 /// Returns a vector of problems detected. An empty vector means no
 /// problems were found.
-pub fn verify_macho(macho: &MachOBinary) -> Vec<VerificationProblem> {
-    verify_macho_internal(
-        macho,
-        VerificationContext {
-            path: None,
-            fat_index: None,
-        },
-    )
+pub fn verify_xar_data(data: impl AsRef<[u8]>) -> Vec<VerificationProblem> {
+    verify_xar_data_internal(data.as_ref(), VerificationContext::default())
 }
 
-fn verify_macho_internal(
-    macho: &MachOBinary,
+/// File magic of XAR archives.
+pub(crate) const XAR_MAGIC: &[u8] = b"xar!";
+
+pub(crate) fn verify_xar_data_internal(
+    data: &[u8],
     context: VerificationContext,
 ) -> Vec<VerificationProblem> {
-    let signature_data = match macho.find_signature_data() {
-        Ok(Some(data)) => data,
-        Ok(None) => {
-            return vec![VerificationProblem {
-                context,
-                problem: VerificationProblemType::NoMachOSignatureData,
-            }];
-        }
-        Err(e) => {
-            return vec![VerificationProblem {
-                context,
-                problem: VerificationProblemType::MachOSignatureError(e),
-            }];
-        }
+    let problem = |problem| VerificationProblem {
+        context: context.clone(),
+        problem,
+    };
+
+    let mut xar = match XarReader::new(std::io::Cursor::new(data)) {
+        Ok(xar) => xar,
+        Err(e) => return vec![problem(VerificationProblemType::XarError(e.into()))],
     };
 
     let mut problems = vec![];
 
-    // __LINKEDIT segment should be the last segment.
-    if signature_data.linkedit_segment_index != macho.macho.segments.len() - 1 {
-        problems.push(VerificationProblem {
-            context: context.clone(),
-            problem: VerificationProblemType::LinkeditNotLastSegment,
-        });
+    match xar.verify_table_of_contents_checksum() {
+        Ok(true) => {}
+        Ok(false) => problems.push(problem(
+            VerificationProblemType::XarTableOfContentsChecksumMismatch,
+        )),
+        Err(e) => problems.push(problem(VerificationProblemType::XarError(e.into()))),
     }
 
-    // Signature data should be the last data in the __LINKEDIT segment.
-    if signature_data.signature_end_offset != signature_data.linkedit_segment_data.len() {
-        problems.push(VerificationProblem {
-            context: context.clone(),
-            problem: VerificationProblemType::SignatureNotLastLinkeditData,
-        });
+    match xar.verify_file_checksums() {
+        Ok(paths) => problems.extend(
+            paths
+                .into_iter()
+                .map(|path| problem(VerificationProblemType::XarFileChecksumMismatch(path))),
+        ),
+        Err(e) => problems.push(problem(VerificationProblemType::XarError(e.into()))),
     }
 
-    let signature = match macho.code_signature() {
-        Ok(Some(signature)) => signature,
-        Ok(None) => {
-            panic!("no signature should have been handled above");
-        }
-        Err(e) => {
-            problems.push(VerificationProblem {
-                context,
-                problem: VerificationProblemType::MachOSignatureError(e),
-            });
-
-            // Can't do anything more if we couldn't parse the signature data.
-            return problems;
-        }
-    };
+    let mut signed = false;
 
-    match signature.signature_data() {
-        Ok(Some(cms_blob)) => {
-            problems.extend(verify_cms_signature(cms_blob, context.clone()));
-        }
-        Ok(None) => problems.push(VerificationProblem {
-            context: context.clone(),
-            problem: VerificationProblemType::NoCryptographicSignature,
-        }),
-        Err(e) => {
-            problems.push(VerificationProblem {
-                context: context.clone(),
-                problem: VerificationProblemType::MachOSignatureError(e),
-            });
+    for verified in [
+        xar.verify_rsa_checksum_signature(),
+        xar.verify_cms_signature(),
+    ] {
+        match verified {
+            Ok(checked) => signed |= checked,
+            Err(e) => {
+                signed = true;
+                problems.push(problem(VerificationProblemType::XarSignatureError(
+                    e.into(),
+                )));
+            }
         }
     }
 
-    match signature.code_directory() {
-        Ok(Some(cd)) => {
-            problems.extend(verify_code_directory(macho, &signature, &cd, context));
-        }
-        Ok(None) => {
-            problems.push(VerificationProblem {
-                context,
-                problem: VerificationProblemType::NoCodeDirectory,
-            });
-        }
-        Err(e) => {
-            problems.push(VerificationProblem {
-                context,
-                problem: VerificationProblemType::MachOSignatureError(e),
-            });
-        }
+    if !signed {
+        problems.push(problem(VerificationProblemType::NoCryptographicSignature));
     }
 
     problems
 }
 
-fn verify_cms_signature(data: &[u8], context: VerificationContext) -> Vec<VerificationProblem> {
-    let signed_data = match SignedData::parse_ber(data) {
-        Ok(signed_data) => signed_data,
-        Err(e) => {
-            return vec![VerificationProblem {
-                context,
-                problem: VerificationProblemType::CmsError(e),
-            }];
-        }
+/// Check whether the certificates signing Mach-O or XAR data were revoked.
+///
+/// The certificate chain of every CMS signer is checked via OCSP using
+/// `checker`. This requires network access. Data that can't be parsed or isn't
+/// signed yields no problems: other verification functions report those.
+///
+/// In soft-fail mode (see [OcspChecker::set_strict()]), certificates whose
+/// revocation status can't be obtained aren't problems.
+pub fn verify_revocation(
+    data: impl AsRef<[u8]>,
+    checker: &OcspChecker,
+) -> Vec<VerificationProblem> {
+    let data = data.as_ref();
+    let context = VerificationContext::default();
+
+    let signed_datas = if data.starts_with(XAR_MAGIC) {
+        XarReader::new(std::io::Cursor::new(data))
+            .and_then(|mut xar| xar.cms_signature())
+            .ok()
+            .flatten()
+            .map(|signed_data| vec![(context.clone(), signed_data)])
+            .unwrap_or_default()
+    } else if let Ok(mach) = MachFile::parse(data) {
+        mach.iter_macho()
+            .filter_map(|macho| {
+                let signed_data = macho.code_signature().ok()??.signed_data().ok()??;
+
+                Some((slice_context(macho, &context), signed_data))
+            })
+            .collect::<Vec<_>>()
+    } else {
+        vec![]
     };
 
     let mut problems = vec![];
 
-    for signer in signed_data.signers() {
-        match signer.digest_algorithm() {
-            DigestAlgorithm::Sha1 => {
-                problems.push(VerificationProblem {
-                    context: context.clone(),
-                    problem: VerificationProblemType::CmsOldDigestAlgorithm(
-                        signer.digest_algorithm(),
-                    ),
-                });
-            }
-            DigestAlgorithm::Sha384 => {}
-            DigestAlgorithm::Sha256 => {}
-            DigestAlgorithm::Sha512 => {}
-        }
-
-        match signer.signature_algorithm() {
-            SignatureAlgorithm::RsaSha256
-            | SignatureAlgorithm::RsaSha384
-            | SignatureAlgorithm::RsaSha512
-            | SignatureAlgorithm::EcdsaSha256
-            | SignatureAlgorithm::EcdsaSha384
-            | SignatureAlgorithm::Ed25519
-            | SignatureAlgorithm::NoSignature(_) => {}
-            SignatureAlgorithm::RsaSha1 => {
-                problems.push(VerificationProblem {
-                    context: context.clone(),
-                    problem: VerificationProblemType::CmsOldSignatureAlgorithm(
-                        signer.signature_algorithm(),
-                    ),
-                });
-            }
-        }
+    for (context, signed_data) in signed_datas {
+        for signer in signed_data.signers() {
+            let chain = signer_certificate_chain(&signed_data, signer);
 
-        match signer.verify_signature_with_signed_data(&signed_data) {
-            Ok(()) => {}
-            Err(e) => {
-                problems.push(VerificationProblem {
+            match checker.check_chain(&chain) {
+                Ok(statuses) => {
+                    problems.extend(statuses.into_iter().filter_map(|(cert, status)| {
+                        if let CertificateStatus::Revoked { time, .. } = status {
+                            Some(VerificationProblem {
+                                context: context.clone(),
+                                problem: VerificationProblemType::CertificateRevoked(
+                                    cert.subject_common_name()
+                                        .unwrap_or_else(|| "<unknown>".into()),
+                                    time,
+                                ),
+                            })
+                        } else {
+                            None
+                        }
+                    }));
+                }
+                Err(e) => problems.push(VerificationProblem {
                     context: context.clone(),
-                    problem: VerificationProblemType::CmsError(e),
-                });
+                    problem: VerificationProblemType::RevocationCheckError(e),
+                }),
             }
         }
-
-        // TODO verify key length meets standards.
-        // TODO verify CA chain is fully present.
-        // TODO verify signing cert chains to Apple?
     }
 
     problems
 }
 
-fn verify_code_directory(
-    macho: &MachOBinary,
-    signature: &EmbeddedSignature,
-    cd: &CodeDirectoryBlob,
-    context: VerificationContext,
+/// Verifies a binary in a given path against a detached signature file.
+///
+/// The signature file holds either an embedded signature super blob, as
+/// produced when detached signing a single architecture binary, or a detached
+/// signature super blob holding a signature for each architecture, as produced
+/// by `codesign --detached`.
+///
+/// Returns a vector of problems detected. An empty vector means no
+/// problems were found.
+pub fn verify_path_detached(
+    path: impl AsRef<Path>,
+    signature_path: impl AsRef<Path>,
+) -> Vec<VerificationProblem> {
+    let path = path.as_ref();
+
+    let context = VerificationContext {
+        path: Some(path.to_path_buf()),
+        ..Default::default()
+    };
+
+    let (data, signature_data) =
+        match std::fs::read(path).and_then(|data| Ok((data, std::fs::read(signature_path)?))) {
+            Ok(v) => v,
+            Err(e) => {
+                return vec![VerificationProblem {
+                    context,
+                    problem: VerificationProblemType::IoError(e),
+                }];
+            }
+        };
+
+    verify_macho_data_detached_internal(data, &signature_data, context)
+}
+
+/// Verifies unparsed Mach-O data against unparsed detached signature data.
+///
+/// See [verify_path_detached()] for the supported signature data.
+pub fn verify_macho_data_detached(
+    data: impl AsRef<[u8]>,
+    signature_data: impl AsRef<[u8]>,
+) -> Vec<VerificationProblem> {
+    verify_macho_data_detached_internal(
+        data,
+        signature_data.as_ref(),
+        VerificationContext::default(),
+    )
+}
+
+fn verify_macho_data_detached_internal(
+    data: impl AsRef<[u8]>,
+    signature_data: &[u8],
+    context: VerificationContext,
+) -> Vec<VerificationProblem> {
+    match MachFile::parse(data.as_ref()) {
+        Ok(mach) => verify_macho_slices_detached(mach, signature_data, &context)
+            .into_iter()
+            .flat_map(|(_, problems)| problems)
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            vec![VerificationProblem {
+                context,
+                problem: VerificationProblemType::MachOParseError(e),
+            }]
+        }
+    }
+}
+
+fn verify_macho_data_internal(
+    data: impl AsRef<[u8]>,
+    context: VerificationContext,
+) -> Vec<VerificationProblem> {
+    match MachFile::parse(data.as_ref()) {
+        Ok(mach) => verify_macho_slices(mach, &context)
+            .into_iter()
+            .flat_map(|(_, problems)| problems)
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            vec![VerificationProblem {
+                context,
+                problem: VerificationProblemType::MachOParseError(e),
+            }]
+        }
+    }
+}
+
+/// Verify each Mach-O binary in a possibly fat binary.
+///
+/// Returns the context and problems of each Mach-O, in slice order.
+pub(crate) fn verify_macho_slices(
+    mach: MachFile,
+    context: &VerificationContext,
+) -> Vec<(VerificationContext, Vec<VerificationProblem>)> {
+    verify_slices_with(mach, context, verify_macho_internal)
+}
+
+/// Verify each Mach-O binary in a possibly fat binary against a detached signature.
+///
+/// Returns the context and problems of each Mach-O, in slice order.
+pub(crate) fn verify_macho_slices_detached(
+    mach: MachFile,
+    signature_data: &[u8],
+    context: &VerificationContext,
+) -> Vec<(VerificationContext, Vec<VerificationProblem>)> {
+    let signatures = match detached_signatures(signature_data) {
+        Ok(signatures) => signatures,
+        Err(e) => {
+            return vec![(
+                context.clone(),
+                vec![VerificationProblem {
+                    context: context.clone(),
+                    problem: VerificationProblemType::DetachedSignatureError(e),
+                }],
+            )];
+        }
+    };
+
+    let single_arch = mach.iter_macho().count() == 1;
+
+    verify_slices_with(mach, context, |macho, context| {
+        let cputype = macho.macho.header.cputype();
+
+        // A plain embedded signature applies to a single architecture binary.
+        let signature = signatures
+            .iter()
+            .find(|(typ, _)| match typ {
+                Some(typ) => *typ == cputype,
+                None => single_arch,
+            })
+            .map(|(_, signature)| signature);
+
+        match signature {
+            Some(signature) => verify_signature(macho, signature, context),
+            None => vec![VerificationProblem {
+                context,
+                problem: VerificationProblemType::DetachedSignatureMissingArch(cputype),
+            }],
+        }
+    })
+}
+
+/// Resolve the signatures in detached signature data, keyed by CPU type if known.
+fn detached_signatures(
+    data: &[u8],
+) -> Result<Vec<(Option<u32>, EmbeddedSignature<'_>)>, AppleCodesignError> {
+    let magic = data.pread_with::<u32>(0, scroll::BE)?;
+
+    if magic == u32::from(CodeSigningMagic::DetachedSignature) {
+        Ok(DetachedSignatureBlob::from_blob_bytes(data)?
+            .signatures()?
+            .into_iter()
+            .map(|(cputype, signature)| (Some(cputype), signature))
+            .collect())
+    } else {
+        Ok(vec![(None, EmbeddedSignature::from_bytes(data)?)])
+    }
+}
+
+fn verify_slices_with(
+    mach: MachFile,
+    context: &VerificationContext,
+    verify: impl Fn(&MachOBinary, VerificationContext) -> Vec<VerificationProblem> + Sync,
+) -> Vec<(VerificationContext, Vec<VerificationProblem>)> {
+    let machos = mach.into_iter().collect::<Vec<_>>();
+
+    let verify_slice = |macho: &MachOBinary| {
+        let context = slice_context(macho, context);
+
+        (context.clone(), verify(macho, context))
+    };
+
+    // Each architecture in a fat binary is verified independently, so do
+    // them in parallel. Results are collected in slice order so output is
+    // deterministic.
+    #[cfg(feature = "parallel-digests")]
+    let results = machos.par_iter().map(verify_slice).collect::<Vec<_>>();
+
+    #[cfg(not(feature = "parallel-digests"))]
+    let results = machos.iter().map(verify_slice).collect::<Vec<_>>();
+
+    results
+}
+
+/// Derive the context of a Mach-O binary within a possibly fat binary.
+fn slice_context(macho: &MachOBinary, context: &VerificationContext) -> VerificationContext {
+    let mut context = context.clone();
+    context.fat_index = macho.index;
+
+    if macho.index.is_some() {
+        context.arch = get_arch_name_from_types(
+            macho.macho.header.cputype(),
+            macho.macho.header.cpusubtype(),
+        )
+        .map(|name| name.to_string());
+    }
+
+    context
+}
+
+/// An incremental finding emitted while verifying a path.
+///
+/// See [verify_path_events()].
+#[derive(Debug)]
+pub enum VerificationEvent {
+    /// Verification of a file is starting.
+    ///
+    /// `slices` is the number of Mach-O binaries within the file. It is 0 if the
+    /// file couldn't be read or parsed.
+    FileStarted { path: PathBuf, slices: usize },
+
+    /// A problem was detected.
+    Problem(VerificationProblem),
+
+    /// The digest of a special slot of a Mach-O binary's primary code directory was checked.
+    ///
+    /// Emitted for every slot with a digest, after the problems of the binary.
+    SlotVerified {
+        context: VerificationContext,
+        slot: CodeSigningSlot,
+        valid: bool,
+    },
+
+    /// Verification of a single Mach-O binary within a file finished.
+    SliceFinished {
+        context: VerificationContext,
+        problems: usize,
+    },
+
+    /// Verification of a file finished.
+    FileFinished { path: PathBuf, problems: usize },
+}
+
+/// The file currently being verified by [VerificationEvents].
+struct VerificationFile {
+    path: PathBuf,
+    data: Vec<u8>,
+    slices: usize,
+    next_slice: usize,
+    problems: usize,
+}
+
+/// An iterator of [VerificationEvent] for a path.
+///
+/// Work is performed lazily: each call to [Iterator::next()] verifies at most
+/// one Mach-O binary. So consumers can display progress as findings arrive and
+/// can cancel verification by dropping the iterator.
+pub struct VerificationEvents {
+    walker: walkdir::IntoIter,
+    directory: bool,
+    current: Option<VerificationFile>,
+    pending: VecDeque<VerificationEvent>,
+}
+
+impl VerificationEvents {
+    fn problem(&mut self, path: &Path, problem: VerificationProblemType) {
+        self.pending
+            .push_back(VerificationEvent::Problem(VerificationProblem {
+                context: VerificationContext {
+                    path: Some(path.to_path_buf()),
+                    ..Default::default()
+                },
+                problem,
+            }));
+    }
+
+    /// Queue events for a file that couldn't be verified.
+    fn unverifiable(&mut self, path: PathBuf, problem: VerificationProblemType) {
+        self.pending.push_back(VerificationEvent::FileStarted {
+            path: path.clone(),
+            slices: 0,
+        });
+        self.problem(&path, problem);
+        self.pending
+            .push_back(VerificationEvent::FileFinished { path, problems: 1 });
+    }
+
+    fn start_file(&mut self, path: PathBuf) {
+        // Directories contain all kinds of files. Only Mach-O files in them are
+        // of interest, so sniff the magic instead of reading everything.
+        if self.directory && !matches!(PathType::from_path(&path), Ok(PathType::MachO)) {
+            return;
+        }
+
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(e) => {
+                self.unverifiable(path, VerificationProblemType::IoError(e));
+                return;
+            }
+        };
+
+        let slices = match MachFile::parse(&data) {
+            Ok(mach) => mach.iter_macho().count(),
+            Err(e) => {
+                self.unverifiable(path, VerificationProblemType::MachOParseError(e));
+                return;
+            }
+        };
+
+        self.pending.push_back(VerificationEvent::FileStarted {
+            path: path.clone(),
+            slices,
+        });
+
+        self.current = Some(VerificationFile {
+            path,
+            data,
+            slices,
+            next_slice: 0,
+            problems: 0,
+        });
+    }
+
+    fn verify_next_slice(&mut self, mut file: VerificationFile) {
+        if file.next_slice == file.slices {
+            self.pending.push_back(VerificationEvent::FileFinished {
+                path: file.path,
+                problems: file.problems,
+            });
+            return;
+        }
+
+        let context = VerificationContext {
+            path: Some(file.path.clone()),
+            ..Default::default()
+        };
+
+        let verified = MachFile::parse(&file.data).and_then(|mach| {
+            let macho = mach.nth_macho(file.next_slice)?;
+            let context = slice_context(macho, &context);
+            let problems = verify_macho_internal(macho, context.clone());
+
+            let slots = match macho.code_signature() {
+                Ok(Some(signature)) => match signature.code_directory() {
+                    Ok(Some(cd)) => slot_digest_results(&cd, &problems),
+                    _ => vec![],
+                },
+                _ => vec![],
+            };
+
+            Ok((context, problems, slots))
+        });
+
+        let (context, problems, slots) = match verified {
+            Ok(v) => v,
+            Err(e) => (
+                context.clone(),
+                vec![VerificationProblem {
+                    context,
+                    problem: VerificationProblemType::MachOParseError(e),
+                }],
+                vec![],
+            ),
+        };
+
+        file.next_slice += 1;
+        file.problems += problems.len();
+
+        let count = problems.len();
+        self.pending
+            .extend(problems.into_iter().map(VerificationEvent::Problem));
+        self.pending.extend(slots.into_iter().map(|(slot, valid)| {
+            VerificationEvent::SlotVerified {
+                context: context.clone(),
+                slot,
+                valid,
+            }
+        }));
+        self.pending.push_back(VerificationEvent::SliceFinished {
+            context,
+            problems: count,
+        });
+
+        self.current = Some(file);
+    }
+}
+
+impl Iterator for VerificationEvents {
+    type Item = VerificationEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+
+            if let Some(file) = self.current.take() {
+                self.verify_next_slice(file);
+                continue;
+            }
+
+            match self.walker.next()? {
+                Ok(entry) if entry.file_type().is_file() => self.start_file(entry.into_path()),
+                Ok(_) => {}
+                Err(e) => {
+                    let path = e.path().map(|p| p.to_path_buf()).unwrap_or_default();
+                    self.problem(&path, VerificationProblemType::IoError(e.into()));
+                }
+            }
+        }
+    }
+}
+
+/// Verifies a path, emitting findings incrementally.
+///
+/// Unlike [verify_path()], which returns all problems once verification is
+/// complete, this returns an iterator yielding [VerificationEvent] as each file
+/// and Mach-O binary is verified. This is suitable for driving progress
+/// displays and for cancelling verification of large bundles early.
+///
+/// If `path` is a directory, all Mach-O files within it are verified in file
+/// name order. Files in it that aren't Mach-O are ignored.
+pub fn verify_path_events(path: impl AsRef<Path>) -> VerificationEvents {
+    let path = path.as_ref();
+
+    VerificationEvents {
+        walker: walkdir::WalkDir::new(path).sort_by_file_name().into_iter(),
+        directory: path.is_dir(),
+        current: None,
+        pending: VecDeque::new(),
+    }
+}
+
+/// Verifies a parsed Mach-O binary.
+///
+/// Returns a vector of problems detected. An empty vector means no
+/// problems were found.
+pub fn verify_macho(macho: &MachOBinary) -> Vec<VerificationProblem> {
+    verify_macho_internal(macho, VerificationContext::default())
+}
+
+/// Structured results of verifying a Mach-O binary.
+///
+/// Unlike the problems returned by [verify_macho()], this also describes what
+/// was found to be valid, so consumers can tell a passing check from one that
+/// couldn't be performed.
+#[derive(Debug)]
+pub struct VerificationReport {
+    /// Signing identifier, team ID, and verified time-stamp of the binary.
+    pub context: VerificationContext,
+
+    /// The outcome of every check.
+    pub checks: Vec<CheckResult>,
+
+    /// Special slots of the primary code directory and whether their digests match.
+    pub slot_digests: Vec<(CodeSigningSlot, bool)>,
+
+    /// Certificate chain of the CMS signer, leaf first.
+    ///
+    /// Empty for ad-hoc signed binaries.
+    pub certificates: Vec<CapturedX509Certificate>,
+
+    /// Whether the designated requirement is satisfied.
+    ///
+    /// `None` if there is no designated requirement or it couldn't be evaluated.
+    pub designated_requirement: Option<bool>,
+
+    /// The problems found.
+    pub problems: Vec<VerificationProblem>,
+}
+
+impl VerificationReport {
+    /// Whether no problems were found.
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// The outcome of a check.
+    pub fn outcome(&self, check: VerificationCheck) -> Option<&CheckOutcome> {
+        self.checks
+            .iter()
+            .find(|result| result.check == check)
+            .map(|result| &result.outcome)
+    }
+
+    /// Whether a problem matching a predicate was found.
+    pub fn has_problem(&self, predicate: impl Fn(&VerificationProblemType) -> bool) -> bool {
+        self.problems
+            .iter()
+            .any(|problem| predicate(&problem.problem))
+    }
+}
+
+/// Resolve whether the digest of each special slot with a digest is valid.
+///
+/// A slot is valid if `problems` doesn't implicate it.
+fn slot_digest_results(
+    cd: &CodeDirectoryBlob,
+    problems: &[VerificationProblem],
+) -> Vec<(CodeSigningSlot, bool)> {
+    cd.special_digests
+        .iter()
+        .filter(|(_, digest)| !digest.is_null())
+        .map(|(slot, _)| {
+            let valid = !problems
+                .iter()
+                .any(|problem| problem.problem.slot() == Some(*slot));
+
+            (*slot, valid)
+        })
+        .collect()
+}
+
+/// Verifies a parsed Mach-O binary, producing a structured report.
+///
+/// In addition to the checks performed by [verify_macho()], this evaluates
+/// the designated requirement against the binary. Unlike Apple's tooling,
+/// [verify_macho()] doesn't do this, as self-signed binaries commonly carry
+/// requirements only Apple issued certificates can satisfy.
+pub fn verify_macho_report(macho: &MachOBinary) -> VerificationReport {
+    let problems = verify_macho(macho);
+    let checks = check_results(None, &problems);
+
+    let mut report = VerificationReport {
+        context: VerificationContext::default(),
+        checks,
+        slot_digests: vec![],
+        certificates: vec![],
+        designated_requirement: None,
+        problems,
+    };
+
+    let signature = match macho.code_signature() {
+        Ok(Some(signature)) => signature,
+        _ => {
+            report.checks.push(CheckResult {
+                check: VerificationCheck::DesignatedRequirement,
+                arch: None,
+                outcome: CheckOutcome::Skipped("signature data could not be read".into()),
+            });
+
+            return report;
+        }
+    };
+
+    if let Ok(Some(cd)) = signature.code_directory() {
+        report.context.identifier = Some(cd.ident.to_string());
+        report.context.team_id = cd.team_name.as_ref().map(|x| x.to_string());
+
+        report.slot_digests = slot_digest_results(&cd, &report.problems);
+    }
+
+    if let Ok(Some(signed_data)) = signature.signed_data() {
+        if let Some(signer) = signed_data.signers().next() {
+            report.context.time_stamp = verify_time_stamp_token(signer)
+                .ok()
+                .flatten()
+                .map(|time_stamp| time_stamp.time);
+            report.certificates = signer_certificate_chain(&signed_data, signer);
+        }
+    }
+
+    let problem = match evaluate_designated_requirement(macho, &signature) {
+        Ok(Some(satisfied)) => {
+            report.designated_requirement = Some(satisfied);
+
+            if satisfied {
+                None
+            } else {
+                Some(VerificationProblemType::DesignatedRequirementUnsatisfied(
+                    signature
+                        .designated_requirement()
+                        .ok()
+                        .flatten()
+                        .and_then(|requirement| requirement.to_requirement_text().ok())
+                        .unwrap_or_default(),
+                ))
+            }
+        }
+        Ok(None) => {
+            report.checks.push(CheckResult {
+                check: VerificationCheck::DesignatedRequirement,
+                arch: None,
+                outcome: CheckOutcome::Skipped("no designated requirement".into()),
+            });
+
+            return report;
+        }
+        Err(AppleCodesignError::RequirementUnsupported(expression)) => {
+            report.checks.push(CheckResult {
+                check: VerificationCheck::DesignatedRequirement,
+                arch: None,
+                outcome: CheckOutcome::Skipped(format!("can't evaluate {expression}")),
+            });
+
+            return report;
+        }
+        Err(e) => Some(VerificationProblemType::DesignatedRequirementError(e)),
+    };
+
+    let outcome = match problem {
+        Some(problem) => {
+            let problem = VerificationProblem {
+                context: report.context.clone(),
+                problem,
+            };
+            let outcome = CheckOutcome::Failed(vec![problem.to_string()]);
+            report.problems.push(problem);
+
+            outcome
+        }
+        None => CheckOutcome::Passed,
+    };
+
+    report.checks.push(CheckResult {
+        check: VerificationCheck::DesignatedRequirement,
+        arch: None,
+        outcome,
+    });
+
+    report
+}
+
+fn verify_macho_internal(
+    macho: &MachOBinary,
+    context: VerificationContext,
+) -> Vec<VerificationProblem> {
+    let signature_data = match macho.find_signature_data() {
+        Ok(Some(data)) => data,
+        Ok(None) => {
+            return vec![VerificationProblem {
+                context,
+                problem: VerificationProblemType::NoMachOSignatureData,
+            }];
+        }
+        Err(e) => {
+            return vec![VerificationProblem {
+                context,
+                problem: VerificationProblemType::MachOSignatureError(e),
+            }];
+        }
+    };
+
+    let mut problems = vec![];
+
+    // __LINKEDIT segment should be the last segment.
+    if signature_data.linkedit_segment_index != macho.macho.segments.len() - 1 {
+        problems.push(VerificationProblem {
+            context: context.clone(),
+            problem: VerificationProblemType::LinkeditNotLastSegment,
+        });
+    }
+
+    // Signature data should be the last data in the __LINKEDIT segment.
+    if signature_data.signature_end_offset != signature_data.linkedit_segment_data.len() {
+        problems.push(VerificationProblem {
+            context: context.clone(),
+            problem: VerificationProblemType::SignatureNotLastLinkeditData,
+        });
+    }
+
+    let signature = match macho.code_signature() {
+        Ok(Some(signature)) => signature,
+        Ok(None) => {
+            panic!("no signature should have been handled above");
+        }
+        Err(e) => {
+            problems.push(VerificationProblem {
+                context,
+                problem: VerificationProblemType::MachOSignatureError(e),
+            });
+
+            // Can't do anything more if we couldn't parse the signature data.
+            return problems;
+        }
+    };
+
+    // Apple's tools don't overlap the index with blobs or understate the length.
+    if let Err(e) = EmbeddedSignature::from_bytes_strict(signature_data.signature_data) {
+        problems.push(VerificationProblem {
+            context: context.clone(),
+            problem: VerificationProblemType::SuperblobLayout(e),
+        });
+    }
+
+    problems.extend(verify_signature(macho, &signature, context));
+
+    problems
+}
+
+/// Verify a signature's CMS signature and code directories against a Mach-O binary.
+fn verify_signature(
+    macho: &MachOBinary,
+    signature: &EmbeddedSignature,
+    mut context: VerificationContext,
+) -> Vec<VerificationProblem> {
+    let mut problems = vec![];
+
+    // Attach the signing identity to problems so consumers can attribute them.
+    // Errors resolving the code directory are reported below.
+    if let Ok(Some(cd)) = signature.code_directory() {
+        context.identifier = Some(cd.ident.to_string());
+        context.team_id = cd.team_name.as_ref().map(|x| x.to_string());
+    }
+
+    match signature.signature_data() {
+        Ok(Some(cms_blob)) => {
+            problems.extend(verify_cms_signature(signature, cms_blob, &mut context));
+        }
+        Ok(None) => problems.push(VerificationProblem {
+            context: context.clone(),
+            problem: VerificationProblemType::NoCryptographicSignature,
+        }),
+        Err(e) => {
+            problems.push(VerificationProblem {
+                context: context.clone(),
+                problem: VerificationProblemType::MachOSignatureError(e),
+            });
+        }
+    }
+
+    problems.extend(verify_entitlements(signature, context.clone()));
+
+    // Alternate code directories (e.g. SHA-384 alongside SHA-256) bind the same
+    // content, so each of them is verified independently.
+    match signature.all_code_directories() {
+        Ok(cds) if cds.is_empty() => {
+            problems.push(VerificationProblem {
+                context,
+                problem: VerificationProblemType::NoCodeDirectory,
+            });
+        }
+        Ok(cds) => {
+            let is_modern = |digest_type: DigestType| {
+                matches!(
+                    digest_type,
+                    DigestType::Sha256 | DigestType::Sha384 | DigestType::Sha512
+                )
+            };
+
+            // Signatures targeting old OS versions have a SHA-1 primary code
+            // directory for compatibility and a SHA-256 alternate that newer
+            // OS versions use instead. So an older primary digest is only a
+            // problem if no code directory uses a modern one.
+            let any_modern = cds.iter().any(|(_, cd)| is_modern(cd.digest_type));
+
+            for (slot, cd) in cds {
+                if slot == CodeSigningSlot::CodeDirectory
+                    && !is_modern(cd.digest_type)
+                    && !any_modern
+                {
+                    problems.push(VerificationProblem {
+                        context: context.clone(),
+                        problem: VerificationProblemType::CodeDirectoryOldDigestAlgorithm(
+                            cd.digest_type,
+                        ),
+                    });
+                }
+
+                problems.extend(verify_code_directory(
+                    macho,
+                    signature,
+                    &cd,
+                    context.clone(),
+                ));
+            }
+        }
+        Err(e) => {
+            problems.push(VerificationProblem {
+                context,
+                problem: VerificationProblemType::MachOSignatureError(e),
+            });
+        }
+    }
+
+    problems
+}
+
+/// Verify the DER entitlements of a signature agree with its XML entitlements.
+///
+/// The kernel consults the DER entitlements while most tooling reads the XML.
+/// Both are bound by slot digests, but nothing else ensures they are equivalent.
+fn verify_entitlements(
+    signature: &EmbeddedSignature,
+    context: VerificationContext,
+) -> Vec<VerificationProblem> {
+    let problem = |problem| vec![VerificationProblem { context, problem }];
+
+    let (xml, der) = match (signature.entitlements(), signature.entitlements_der()) {
+        (Ok(Some(xml)), Ok(Some(der))) => (xml, der),
+        (Err(e), _) | (_, Err(e)) => {
+            return problem(VerificationProblemType::MachOSignatureError(e));
+        }
+        _ => return vec![],
+    };
+
+    let xml = plist::Value::from_reader_xml(xml.as_str().as_bytes())
+        .map_err(AppleCodesignError::PlistParseXml);
+
+    match (xml, der.parse_der()) {
+        (Ok(xml), Ok(der)) if xml == der => vec![],
+        (Ok(_), Ok(_)) => problem(VerificationProblemType::EntitlementsMismatch),
+        (Err(e), _) | (_, Err(e)) => problem(VerificationProblemType::MachOSignatureError(e)),
+    }
+}
+
+/// Resolve the certificate chain of a CMS signer, leaf first.
+///
+/// Certificates missing from the CMS data are resolved from Apple's known
+/// certificates.
+pub(crate) fn signer_certificate_chain(
+    signed_data: &SignedData,
+    signer: &SignerInfo,
+) -> Vec<CapturedX509Certificate> {
+    let leaf = match signer
+        .certificate_issuer_and_serial()
+        .and_then(|(issuer, serial)| {
+            signed_data
+                .certificates()
+                .find(|cert| cert.issuer_name() == issuer && cert.serial_number_asn1() == serial)
+        }) {
+        Some(cert) => cert,
+        None => return vec![],
+    };
+
+    let mut chain = vec![leaf.clone()];
+    chain.extend(
+        leaf.resolve_signing_chain(
+            signed_data
+                .certificates()
+                .chain(KnownCertificate::all().iter().copied()),
+        )
+        .into_iter()
+        .cloned(),
+    );
+
+    chain
+}
+
+/// Resolve the properties of signed code that code requirements are evaluated against.
+pub fn requirement_context(
+    macho: &MachOBinary,
+    signature: &EmbeddedSignature,
+) -> Result<RequirementContext, AppleCodesignError> {
+    let mut context = RequirementContext {
+        cdhashes: signature
+            .cdhashes()?
+            .into_iter()
+            .map(|(_, cdhash)| cdhash)
+            .collect(),
+        ..Default::default()
+    };
+
+    if let Some(cd) = signature.code_directory()? {
+        context.identifier = Some(cd.ident.to_string());
+        context.platform = cd.platform as u32;
+    }
+
+    if let Some(signer) = signature.signer_details()? {
+        context.certificates = signer.certificate_chain;
+    }
+
+    if let Some(data) = macho.embedded_info_plist()? {
+        context.info_plist =
+            Some(plist::from_bytes(&data).map_err(AppleCodesignError::PlistParseXml)?);
+    }
+
+    if let Some(entitlements) = signature.entitlements()? {
+        context.entitlements = Some(
+            plist::from_bytes(entitlements.as_str().as_bytes())
+                .map_err(AppleCodesignError::PlistParseXml)?,
+        );
+    } else if let Some(entitlements) = signature.entitlements_der()? {
+        context.entitlements = entitlements.parse_der()?.into_dictionary();
+    }
+
+    Ok(context)
+}
+
+/// Evaluate the designated requirement of a signature against a Mach-O binary.
+///
+/// Returns `Ok(None)` if the signature has no designated requirement.
+fn evaluate_designated_requirement(
+    macho: &MachOBinary,
+    signature: &EmbeddedSignature,
+) -> Result<Option<bool>, AppleCodesignError> {
+    let requirement = match signature.designated_requirement()? {
+        Some(requirement) => requirement,
+        None => return Ok(None),
+    };
+
+    let context = requirement_context(macho, signature)?;
+
+    Ok(Some(requirement.expression()?.evaluate(&context)?))
+}
+
+/// A verified RFC 3161 Time-Stamp Token.
+#[derive(Clone, Debug)]
+pub struct VerifiedTimeStamp {
+    /// The time the Time-Stamp Authority attested to.
+    pub time: chrono::DateTime<chrono::Utc>,
+
+    /// Digest algorithm of the token's message imprint.
+    pub digest_algorithm: DigestAlgorithm,
+}
+
+/// Verify the Time-Stamp Token embedded in a CMS signer, if present.
+///
+/// The token's own CMS signature is verified and its message imprint must be
+/// the digest of the signer's signature, as that is what a Time-Stamp Authority
+/// is asked to sign. Returns `Ok(None)` if the signer has no token.
+///
+/// This does not verify the Time-Stamp Authority's certificate chain.
+pub fn verify_time_stamp_token(
+    signer: &SignerInfo,
+) -> Result<Option<VerifiedTimeStamp>, VerificationProblemType> {
+    let signed_data = match signer
+        .time_stamp_token_signed_data()
+        .map_err(VerificationProblemType::TimeStampTokenError)?
+    {
+        Some(signed_data) => signed_data,
+        None => return Ok(None),
+    };
+
+    if signed_data.signers().next().is_none() {
+        return Err(VerificationProblemType::TimeStampTokenMalformed(
+            "no signers",
+        ));
+    }
+
+    for tsa_signer in signed_data.signers() {
+        tsa_signer
+            .verify_signature_with_signed_data(&signed_data)
+            .map_err(VerificationProblemType::TimeStampTokenError)?;
+        tsa_signer
+            .verify_message_digest_with_signed_data(&signed_data)
+            .map_err(VerificationProblemType::TimeStampTokenError)?;
+    }
+
+    let content =
+        signed_data
+            .signed_content()
+            .ok_or(VerificationProblemType::TimeStampTokenMalformed(
+                "no TSTInfo content",
+            ))?;
+
+    let tst_info = bcder::decode::Constructed::decode(content, bcder::Mode::Der, |cons| {
+        TstInfo::take_from(cons)
+    })
+    .map_err(|_| VerificationProblemType::TimeStampTokenMalformed("invalid TSTInfo"))?;
+
+    let digest_algorithm = DigestAlgorithm::try_from(&tst_info.message_imprint.hash_algorithm)
+        .map_err(|_| {
+            VerificationProblemType::TimeStampTokenMalformed("unknown message imprint algorithm")
+        })?;
+
+    let recorded = tst_info.message_imprint.hashed_message.to_bytes().to_vec();
+    let actual = digest_algorithm.digest_data(signer.signature());
+
+    if recorded != actual {
+        return Err(VerificationProblemType::TimeStampImprintMismatch(
+            recorded, actual,
+        ));
+    }
+
+    Ok(Some(VerifiedTimeStamp {
+        time: tst_info.gen_time.into(),
+        digest_algorithm,
+    }))
+}
+
+fn verify_cms_signature(
+    signature: &EmbeddedSignature,
+    data: &[u8],
+    context: &mut VerificationContext,
+) -> Vec<VerificationProblem> {
+    let signed_data = match SignedData::parse_ber(data) {
+        Ok(signed_data) => signed_data,
+        Err(e) => {
+            return vec![VerificationProblem {
+                context: context.clone(),
+                problem: VerificationProblemType::CmsError(e),
+            }];
+        }
+    };
+
+    let mut problems = vec![];
+
+    for signer in signed_data.signers() {
+        match verify_time_stamp_token(signer) {
+            Ok(Some(time_stamp)) => {
+                context.time_stamp = Some(time_stamp.time);
+            }
+            Ok(None) => {}
+            Err(problem) => {
+                problems.push(VerificationProblem {
+                    context: context.clone(),
+                    problem,
+                });
+            }
+        }
+
+        match signer.digest_algorithm() {
+            DigestAlgorithm::Sha1 => {
+                problems.push(VerificationProblem {
+                    context: context.clone(),
+                    problem: VerificationProblemType::CmsOldDigestAlgorithm(
+                        signer.digest_algorithm(),
+                    ),
+                });
+            }
+            DigestAlgorithm::Sha384 => {}
+            DigestAlgorithm::Sha256 => {}
+            DigestAlgorithm::Sha512 => {}
+        }
+
+        match signer.signature_algorithm() {
+            SignatureAlgorithm::RsaSha256
+            | SignatureAlgorithm::RsaSha384
+            | SignatureAlgorithm::RsaSha512
+            | SignatureAlgorithm::EcdsaSha256
+            | SignatureAlgorithm::EcdsaSha384
+            | SignatureAlgorithm::Ed25519
+            | SignatureAlgorithm::NoSignature(_) => {}
+            SignatureAlgorithm::RsaSha1 => {
+                problems.push(VerificationProblem {
+                    context: context.clone(),
+                    problem: VerificationProblemType::CmsOldSignatureAlgorithm(
+                        signer.signature_algorithm(),
+                    ),
+                });
+            }
+        }
+
+        match signer.verify_signature_with_signed_data(&signed_data) {
+            Ok(()) => {}
+            Err(e) => {
+                problems.push(VerificationProblem {
+                    context: context.clone(),
+                    problem: VerificationProblemType::CmsError(e),
+                });
+            }
+        }
+
+        problems.extend(verify_cms_code_directories(signature, signer, context));
+
+        if let Some(problem) = verify_signer_certificate_chain(&signed_data, signer) {
+            problems.push(VerificationProblem {
+                context: context.clone(),
+                problem,
+            });
+        }
+
+        // TODO verify key length meets standards.
+    }
+
+    problems
+}
+
+/// Verify the certificate chain of a CMS signer using Apple's bundled CA certificates.
+///
+/// Signing certificates naming a known Apple CA as their issuer must chain to an
+/// Apple root CA. Certificates missing from the CMS data are resolved from the
+/// bundled certificates, so no network access is needed. Certificates issued by
+/// anyone else, such as self-signed certificates, aren't validated.
+fn verify_signer_certificate_chain(
+    signed_data: &SignedData,
+    signer: &SignerInfo,
+) -> Option<VerificationProblemType> {
+    let chain = signer_certificate_chain(signed_data, signer);
+    let leaf = chain.first()?;
+
+    if !KnownCertificate::all()
+        .iter()
+        .any(|ca| ca.subject_name() == leaf.issuer_name())
+    {
+        return None;
+    }
+
+    if chain.last().map(|cert| cert.is_apple_root_ca()) == Some(true) {
+        None
+    } else {
+        Some(VerificationProblemType::CmsCertificateChainUntrusted(
+            leaf.subject_common_name()
+                .unwrap_or_else(|| "<unknown>".into()),
+        ))
+    }
+}
+
+/// Verify the CMS signer binds the code directories in the signature.
+///
+/// The signed content is the primary code directory. Alternate code directories
+/// are bound via the hash agility attributes: a plist of cdhashes (native
+/// digests truncated to 20 bytes) and a set of (digest OID, native digest) pairs.
+/// The attributes are only checked if present, as older signatures lack them.
+/// When present, they must record exactly the code directories in the signature.
+fn verify_cms_code_directories(
+    signature: &EmbeddedSignature,
+    signer: &SignerInfo,
+    context: &VerificationContext,
+) -> Vec<VerificationProblem> {
+    let mut problems = vec![];
+
+    // Errors resolving code directories are reported by code directory verification.
+    let cds = match signature.all_code_directories() {
+        Ok(cds) => cds,
+        Err(_) => return problems,
+    };
+
+    let mut cd_digests = vec![];
+
+    for (slot, cd) in &cds {
+        let entry = signature
+            .find_slot(*slot)
+            .expect("code directory should have a blob entry");
+
+        match entry.digest_with(cd.digest_type) {
+            Ok(digest) => cd_digests.push((*slot, cd.digest_type, digest)),
+            Err(e) => problems.push(VerificationProblem {
+                context: context.clone(),
+                problem: VerificationProblemType::SlotDigestError(e),
+            }),
+        }
+    }
+
+    if let Some(entry) = signature.find_slot(CodeSigningSlot::CodeDirectory) {
+        if let Err(e) = signer.verify_message_digest_with_content(entry.data) {
+            problems.push(VerificationProblem {
+                context: context.clone(),
+                problem: VerificationProblemType::CmsError(e),
+            });
+        }
+    }
+
+    let attributes = match HashAgilityAttributes::from_signer_info(signer) {
+        Ok(v) => v,
+        Err(e) => {
+            problems.push(VerificationProblem {
+                context: context.clone(),
+                problem: VerificationProblemType::CmsHashAgilityError(e),
+            });
+
+            return problems;
+        }
+    };
+
+    if let Some(cdhashes) = &attributes.cdhashes {
+        let actual = cd_digests
+            .iter()
+            .map(|(slot, _, digest)| (*slot, &digest[0..digest.len().min(CS_CDHASH_LEN)]))
+            .collect::<Vec<_>>();
+
+        for (slot, cdhash) in &actual {
+            if !cdhashes.iter().any(|x| x == cdhash) {
+                problems.push(VerificationProblem {
+                    context: context.clone(),
+                    problem: VerificationProblemType::CmsCdHashMissing(*slot, cdhash.to_vec()),
+                });
+            }
+        }
+
+        for cdhash in cdhashes {
+            if !actual.iter().any(|(_, x)| x == cdhash) {
+                problems.push(VerificationProblem {
+                    context: context.clone(),
+                    problem: VerificationProblemType::CmsCdHashUnexpected(cdhash.clone()),
+                });
+            }
+        }
+    }
+
+    if let Some(digests) = &attributes.digests {
+        for (slot, digest_type, digest) in &cd_digests {
+            let alg = match DigestAlgorithm::try_from(*digest_type) {
+                Ok(alg) => alg,
+                Err(_) => continue,
+            };
+
+            match digests.iter().find(|(a, _)| *a == alg) {
+                Some((_, cms_digest)) => {
+                    if cms_digest != digest {
+                        problems.push(VerificationProblem {
+                            context: context.clone(),
+                            problem: VerificationProblemType::CmsCodeDirectoryDigestMismatch(
+                                *slot,
+                                cms_digest.clone(),
+                                digest.clone(),
+                            ),
+                        });
+                    }
+                }
+                None => {
+                    problems.push(VerificationProblem {
+                        context: context.clone(),
+                        problem: VerificationProblemType::CmsCodeDirectoryDigestMissing(
+                            *slot,
+                            *digest_type,
+                        ),
+                    });
+                }
+            }
+        }
+
+        for (alg, digest) in digests {
+            if !cd_digests.iter().any(|(_, digest_type, _)| {
+                DigestAlgorithm::try_from(*digest_type).ok() == Some(*alg)
+            }) {
+                problems.push(VerificationProblem {
+                    context: context.clone(),
+                    problem: VerificationProblemType::CmsCodeDirectoryDigestUnexpected(
+                        *alg,
+                        digest.clone(),
+                    ),
+                });
+            }
+        }
+    }
+
+    problems
+}
+
+/// Code directory digests recorded in a CMS signer's hash agility attributes.
+///
+/// Apple records the digests of all code directories in a signature in two
+/// signed attributes, allowing alternate code directories (e.g. SHA-256 next to
+/// a SHA-1 primary) to be bound by a single signature.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct HashAgilityAttributes {
+    /// cdhashes from the plist attribute ([CD_DIGESTS_PLIST_OID]).
+    ///
+    /// These are digests of the code directories truncated to 20 bytes.
+    /// `None` if the attribute isn't present.
+    pub cdhashes: Option<Vec<Vec<u8>>>,
+
+    /// Digests of code directories from the [CD_DIGESTS_OID] attribute.
+    ///
+    /// Digests with algorithms we don't know are ignored. `None` if the
+    /// attribute isn't present.
+    pub digests: Option<Vec<(DigestAlgorithm, Vec<u8>)>>,
+}
+
+impl HashAgilityAttributes {
+    /// Parse the hash agility attributes from a signer's signed attributes.
+    pub fn from_signer_info(signer: &SignerInfo) -> Result<Self, AppleCodesignError> {
+        let mut res = Self::default();
+
+        let sa = match signer.signed_attributes() {
+            Some(sa) => sa,
+            None => return Ok(res),
+        };
+
+        for attr in sa.attributes().iter() {
+            if attr.typ == CD_DIGESTS_PLIST_OID {
+                let mut hashes = vec![];
+
+                if let Some(data) = attr.values.first() {
+                    let plist_data = data
+                        .deref()
+                        .clone()
+                        .decode(|cons| Ok(bcder::OctetString::take_from(cons)?.into_bytes()))
+                        .map_err(|e| AppleCodesignError::Cms(e.into()))?;
+
+                    let value = plist::Value::from_reader_xml(plist_data.as_ref())
+                        .map_err(AppleCodesignError::CodeDirectoryPlist)?;
+
+                    if let Some(values) = value
+                        .as_dictionary()
+                        .and_then(|d| d.get("cdhashes"))
+                        .and_then(|v| v.as_array())
+                    {
+                        hashes.extend(
+                            values
+                                .iter()
+                                .filter_map(|v| v.as_data())
+                                .map(|v| v.to_vec()),
+                        );
+                    }
+                }
+
+                res.cdhashes = Some(hashes);
+            } else if attr.typ == CD_DIGESTS_OID {
+                let mut entries = vec![];
+
+                for value in &attr.values {
+                    // Each value is a SEQUENCE of (OID, OctetString).
+                    value
+                        .deref()
+                        .clone()
+                        .decode(|cons| {
+                            while let Some((oid, digest)) = cons.take_opt_sequence(|cons| {
+                                let oid = bcder::Oid::take_from(cons)?;
+                                let digest = bcder::OctetString::take_from(cons)?;
+
+                                Ok((oid, digest.into_bytes()))
+                            })? {
+                                // Digests for algorithms we don't know about can't be verified.
+                                if let Ok(alg) = DigestAlgorithm::try_from(&oid) {
+                                    entries.push((alg, digest.to_vec()));
+                                }
+                            }
+
+                            Ok(())
+                        })
+                        .map_err(|e| AppleCodesignError::Cms(e.into()))?;
+                }
+
+                res.digests = Some(entries);
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+/// Resolve the region of a Mach-O binary covered by a code page.
+fn code_page_location(
+    macho: &MachOBinary,
+    cd: &CodeDirectoryBlob,
+    code_limit: u64,
+    index: usize,
+) -> CodePageLocation {
+    let (start_offset, end_offset) = cd
+        .code_page_range(index, code_limit)
+        .unwrap_or((code_limit, code_limit));
+
+    CodePageLocation {
+        start_offset,
+        end_offset,
+        regions: macho.file_regions(start_offset, end_offset),
+    }
+}
+
+fn verify_code_directory(
+    macho: &MachOBinary,
+    signature: &EmbeddedSignature,
+    cd: &CodeDirectoryBlob,
+    context: VerificationContext,
 ) -> Vec<VerificationProblem> {
     let mut problems = vec![];
 
-    match cd.digest_type {
-        DigestType::Sha256 | DigestType::Sha384 => {}
-        hash_type => problems.push(VerificationProblem {
-            context: context.clone(),
-            problem: VerificationProblemType::CodeDirectoryOldDigestAlgorithm(hash_type),
-        }),
+    // Code digests cover the binary up to the code limit recorded in the code
+    // directory, which should be where the signature data begins.
+    // The 64-bit limit is only used if set.
+    let code_limit = match cd.code_limit_64 {
+        Some(limit) if limit != 0 => limit,
+        _ => cd.code_limit as u64,
+    };
+
+    if code_limit > macho.data.len() as u64 {
+        problems.push(VerificationProblem {
+            context,
+            problem: VerificationProblemType::CodeLimitExceedsData(
+                code_limit,
+                macho.data.len() as _,
+            ),
+        });
+
+        return problems;
+    }
+
+    if let Ok(expected) = macho.code_limit_binary_offset() {
+        if code_limit != expected {
+            problems.push(VerificationProblem {
+                context: context.clone(),
+                problem: VerificationProblemType::CodeLimitMismatch(code_limit, expected),
+            });
+        }
     }
 
-    match macho.code_digests(cd.digest_type, cd.page_size as _) {
+    let code_data = &macho.data[0..code_limit as usize];
+
+    // A scatter vector describes non-contiguous pages of signed code.
+    let digests = if let Some(scatter) = &cd.scatter {
+        scatter_page_digests(code_data, cd.digest_type, cd.page_size as _, scatter)
+    } else {
+        code_page_digests(code_data, cd.digest_type, cd.page_size as _)
+    };
+
+    match digests {
         Ok(digests) => {
             let mut cd_iter = cd.code_digests.iter().enumerate();
             let mut actual_iter = digests.iter().enumerate();
@@ -428,8 +2038,12 @@ fn verify_code_directory(
                     }
                     (Some((cd_index, cd_digest)), Some((_, actual_digest))) => {
                         if &cd_digest.data != actual_digest {
+                            let mut context = context.clone();
+                            context.code_page =
+                                Some(code_page_location(macho, cd, code_limit, cd_index));
+
                             problems.push(VerificationProblem {
-                                context: context.clone(),
+                                context,
                                 problem: VerificationProblemType::CodeDigestMismatch(
                                     cd_index,
                                     cd_digest.to_vec(),
@@ -545,3 +2159,1079 @@ fn verify_code_directory(
 
     problems
 }
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            certificate::{
+                create_self_signed_code_signing_certificate, AppleCertificateBuilder,
+                CertificateProfile,
+            },
+            code_directory::Scatter,
+            embedded_signature::{
+                create_superblob, Blob, Digest, EntitlementsBlob, EntitlementsDerBlob,
+            },
+            embedded_signature_builder::EmbeddedSignatureBuilder,
+            macho_signing::MachOSigner,
+            macho_universal::UniversalBinaryBuilder,
+            retry::RetryPolicy,
+            signing_settings::{SettingsScope, SigningSettings},
+            test_support::{SyntheticMachO, TEXT_OFFSET},
+        },
+        goblin::mach::cputype::{
+            CPU_SUBTYPE_ARM64_ALL, CPU_SUBTYPE_X86_64_ALL, CPU_TYPE_ARM64, CPU_TYPE_X86_64,
+        },
+        std::borrow::Cow,
+        x509_certificate::{EcdsaCurve, KeyAlgorithm},
+    };
+
+    fn settings() -> SigningSettings<'static> {
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(SettingsScope::Main, "com.example.verify");
+
+        settings
+    }
+
+    fn sign(settings: &SigningSettings, data: &[u8]) -> Result<Vec<u8>, AppleCodesignError> {
+        let mut signed = vec![];
+        MachOSigner::new(data)?.write_signed_binary(settings, &mut signed)?;
+
+        Ok(signed)
+    }
+
+    #[test]
+    fn problem_context() -> Result<(), AppleCodesignError> {
+        let mut settings = settings();
+        settings.set_team_id("team1");
+
+        let mut signed = sign(&settings, &SyntheticMachO::default().build())?;
+        signed[TEXT_OFFSET as usize] ^= 0xff;
+
+        // Problems are attributed to the identity in the code directory.
+        let problems = verify_macho_data(&signed);
+        let problem = problems
+            .iter()
+            .find(|problem| problem.problem.page_index().is_some())
+            .expect("code digest mismatch");
+
+        assert_eq!(
+            problem.context.identifier.as_deref(),
+            Some("com.example.verify")
+        );
+        assert_eq!(problem.context.team_id.as_deref(), Some("team1"));
+        assert!(problem
+            .to_string()
+            .starts_with("(identifier=com.example.verify; team=team1): "));
+        assert_eq!(
+            problem.problem.page_index(),
+            Some(TEXT_OFFSET as usize / 4096)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn universal_arch_context() -> Result<(), AppleCodesignError> {
+        let mut builder = UniversalBinaryBuilder::default();
+        for (cputype, cpusubtype) in [
+            (CPU_TYPE_X86_64, CPU_SUBTYPE_X86_64_ALL),
+            (CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_ALL),
+        ] {
+            builder.add_binary(sign(
+                &settings(),
+                &SyntheticMachO::new_64(cputype, cpusubtype).build(),
+            )?)?;
+        }
+
+        let mut universal = vec![];
+        builder.write(&mut universal)?;
+
+        // Problems are reported in slice order and attributed to their architecture.
+        let problems = verify_macho_data(&universal);
+        assert_eq!(problems.len(), 2, "{problems:?}");
+        for (index, (problem, arch)) in problems.iter().zip(["x86_64", "arm64"]).enumerate() {
+            assert_eq!(problem.context.fat_index, Some(index));
+            assert_eq!(problem.context.arch.as_deref(), Some(arch));
+            assert!(problem.to_string().starts_with(&format!(
+                "@{index}[{arch}] (identifier=com.example.verify): "
+            )));
+            assert!(matches!(
+                problem.problem,
+                VerificationProblemType::NoCryptographicSignature
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn code_directory(digest_type: DigestType, fill: u8) -> CodeDirectoryBlob<'static> {
+        let digest_size = digest_type.hash_len().unwrap();
+
+        CodeDirectoryBlob {
+            digest_size: digest_size as u8,
+            digest_type,
+            page_size: 4096,
+            code_limit: 8192,
+            ident: Cow::Borrowed("com.example.verify"),
+            code_digests: vec![
+                Digest {
+                    data: vec![fill; digest_size].into(),
+                },
+                Digest {
+                    data: vec![fill; digest_size].into(),
+                },
+            ]
+            .try_into()
+            .unwrap(),
+            ..Default::default()
+        }
+    }
+
+    fn signed_superblob() -> Vec<u8> {
+        let (cert, key, _) = create_self_signed_code_signing_certificate(
+            KeyAlgorithm::Ecdsa(EcdsaCurve::Secp384r1),
+            CertificateProfile::DeveloperIdApplication,
+            "team1",
+            "Joe Developer",
+            "US",
+            chrono::Duration::hours(1),
+        )
+        .unwrap();
+
+        let mut builder = EmbeddedSignatureBuilder::default();
+        builder
+            .add_code_directory(
+                CodeSigningSlot::CodeDirectory,
+                code_directory(DigestType::Sha256, 0x55),
+            )
+            .unwrap();
+        builder
+            .add_alternative_code_directory(code_directory(DigestType::Sha384, 0xaa))
+            .unwrap();
+        builder
+            .create_cms_signature(&key, &cert, None, &RetryPolicy::none(), std::iter::empty())
+            .unwrap();
+
+        builder.create_superblob().unwrap()
+    }
+
+    fn verify_superblob(data: &[u8]) -> Vec<VerificationProblem> {
+        let signature = EmbeddedSignature::from_bytes(data).unwrap();
+        let cms = signature.signature_data().unwrap().unwrap();
+
+        verify_cms_signature(&signature, cms, &mut VerificationContext::default())
+    }
+
+    #[test]
+    fn sha384_alternate_code_directory() {
+        let data = signed_superblob();
+        let signature = EmbeddedSignature::from_bytes(&data).unwrap();
+
+        let alternate = signature
+            .code_directory_for_digest(DigestType::Sha384)
+            .unwrap()
+            .unwrap();
+        assert_eq!(alternate.digest_size, 48);
+        assert_eq!(alternate.code_digests.get(0).unwrap().data.len(), 48);
+
+        let problems = verify_superblob(&data);
+        assert!(problems.is_empty(), "{problems:?}");
+    }
+
+    #[test]
+    fn hash_agility_attributes() {
+        let data = signed_superblob();
+        let signature = EmbeddedSignature::from_bytes(&data).unwrap();
+        let signed_data = signature.signed_data().unwrap().unwrap();
+        let signer = signed_data.signers().next().unwrap();
+
+        let attributes = HashAgilityAttributes::from_signer_info(signer).unwrap();
+
+        let cdhashes = signature
+            .cdhashes()
+            .unwrap()
+            .into_iter()
+            .map(|(_, cdhash)| cdhash.to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(attributes.cdhashes, Some(cdhashes));
+
+        let digests = attributes.digests.unwrap();
+        assert_eq!(
+            digests.iter().map(|(alg, _)| *alg).collect::<Vec<_>>(),
+            vec![DigestAlgorithm::Sha256, DigestAlgorithm::Sha384]
+        );
+        assert_eq!(digests[1].1.len(), 48);
+    }
+
+    #[test]
+    fn sha384_alternate_code_directory_tampered() {
+        let mut data = signed_superblob();
+
+        // Alter a code digest in the SHA-384 code directory without re-signing.
+        let offset = data
+            .windows(48)
+            .position(|w| w.iter().all(|b| *b == 0xaa))
+            .unwrap();
+        data[offset] = 0xab;
+
+        let problems = verify_superblob(&data);
+        assert_eq!(problems.len(), 3, "{problems:?}");
+        assert!(matches!(
+            problems[0].problem,
+            VerificationProblemType::CmsCdHashMissing(
+                CodeSigningSlot::AlternateCodeDirectory0,
+                ref cdhash
+            ) if cdhash.len() == 20
+        ));
+        // The cdhash of the original code directory no longer matches anything.
+        assert!(matches!(
+            problems[1].problem,
+            VerificationProblemType::CmsCdHashUnexpected(ref cdhash) if cdhash.len() == 20
+        ));
+        assert!(matches!(
+            problems[2].problem,
+            VerificationProblemType::CmsCodeDirectoryDigestMismatch(
+                CodeSigningSlot::AlternateCodeDirectory0,
+                ref recorded,
+                ref actual
+            ) if recorded.len() == 48 && recorded != actual
+        ));
+    }
+
+    #[test]
+    fn sign_verify_sha384() -> Result<(), AppleCodesignError> {
+        let (cert, key, _) = create_self_signed_code_signing_certificate(
+            KeyAlgorithm::Ecdsa(EcdsaCurve::Secp384r1),
+            CertificateProfile::AppleDevelopment,
+            "team1",
+            "Joe Developer",
+            "US",
+            chrono::Duration::hours(1),
+        )?;
+
+        let mut settings = settings();
+        settings.set_digest_type(DigestType::Sha384);
+        settings.set_signing_key(&key, cert);
+
+        let signed = sign(&settings, &SyntheticMachO::default().build())?;
+
+        let problems = verify_macho_data(&signed);
+        assert!(problems.is_empty(), "{problems:?}");
+
+        let macho = MachOBinary::parse(&signed)?;
+        let signature = macho.code_signature()?.unwrap();
+        let cd = signature
+            .code_directory_for_digest(DigestType::Sha384)?
+            .unwrap();
+        assert_eq!(cd.digest_size, 48);
+        let digest = cd.digest_with(DigestType::Sha384)?;
+
+        // The hash agility attributes bind the SHA-384 code directory.
+        let signed_data = signature.signed_data()?.unwrap();
+        let signer = signed_data.signers().next().unwrap();
+        let attributes = HashAgilityAttributes::from_signer_info(signer)?;
+        assert!(attributes
+            .cdhashes
+            .unwrap()
+            .contains(&digest[0..20].to_vec()));
+        assert!(attributes
+            .digests
+            .unwrap()
+            .contains(&(DigestAlgorithm::Sha384, digest)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn code_page_mismatch_location() {
+        let data = SyntheticMachO::default().build();
+
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(SettingsScope::Main, "com.example.verify");
+
+        let mut signed = vec![];
+        MachOSigner::new(&data)
+            .unwrap()
+            .write_signed_binary(&settings, &mut signed)
+            .unwrap();
+
+        // Ad-hoc signing means the only problem is the lack of a CMS signature.
+        let problems = verify_macho_data(&signed);
+        assert_eq!(problems.len(), 1, "{problems:?}");
+        assert!(matches!(
+            problems[0].problem,
+            VerificationProblemType::NoCryptographicSignature
+        ));
+        assert!(problems[0].context.code_page.is_none());
+
+        // Tamper with __TEXT,__text, which is in the 2nd page.
+        signed[0x1004] ^= 0xff;
+
+        let problems = verify_macho_data(&signed)
+            .into_iter()
+            .filter(|problem| problem.problem.page_index().is_some())
+            .collect::<Vec<_>>();
+        assert_eq!(problems.len(), 1, "{problems:?}");
+        assert!(matches!(
+            problems[0].problem,
+            VerificationProblemType::CodeDigestMismatch(1, _, _)
+        ));
+        assert_eq!(
+            problems[0].context.code_page,
+            Some(CodePageLocation {
+                start_offset: 0x1000,
+                end_offset: 0x2000,
+                regions: vec!["__TEXT".into(), "__TEXT,__text".into()],
+            })
+        );
+        assert!(problems[0]
+            .to_string()
+            .ends_with("(file offsets 0x1000-0x2000 in __TEXT, __TEXT,__text)"));
+    }
+
+    #[test]
+    fn scatter_vector() -> Result<(), AppleCodesignError> {
+        let data = SyntheticMachO::default().build();
+
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(SettingsScope::Main, "com.example.verify");
+
+        let mut signed = vec![];
+        MachOSigner::new(&data)?.write_signed_binary(&settings, &mut signed)?;
+
+        // Re-sign with a code directory covering pages 0, 1, 3, and 4, leaving
+        // page 2 unsigned.
+        let scatter = vec![Scatter::new(2, 0, 0), Scatter::new(2, 2, 0x3000)];
+        let macho = MachOBinary::parse(&signed)?;
+        let signature = macho.code_signature()?.unwrap();
+        let mut cd = signature.code_directory()?.unwrap().to_owned();
+        cd.code_digests = macho
+            .scatter_code_digests(cd.digest_type, cd.page_size as _, &scatter)?
+            .into_iter()
+            .map(Digest::from)
+            .collect::<Vec<_>>()
+            .try_into()?;
+        cd.scatter = Some(scatter);
+
+        let blobs = signature
+            .blobs
+            .iter()
+            .map(|blob| match blob.slot {
+                CodeSigningSlot::CodeDirectory => Ok((blob.slot, cd.to_blob_bytes()?)),
+                _ => Ok((blob.slot, blob.data.to_vec())),
+            })
+            .collect::<Result<Vec<_>, AppleCodesignError>>()?;
+        let detached = create_superblob(CodeSigningMagic::EmbeddedSignature, blobs.iter())?;
+
+        let parsed = EmbeddedSignature::from_bytes(&detached)?;
+        let parsed_cd = parsed.code_directory()?.unwrap();
+        assert_eq!(parsed_cd.scatter, cd.scatter);
+        assert_eq!(parsed_cd.code_page_range(2, 0x4110), Some((0x3000, 0x4000)));
+        assert_eq!(parsed_cd.code_page_range(3, 0x4110), Some((0x4000, 0x4110)));
+        assert_eq!(parsed_cd.code_page_range(4, 0x4110), None);
+
+        let code_problems = |data: &[u8]| {
+            verify_macho_data_detached(data, &detached)
+                .into_iter()
+                .filter(|problem| problem.problem.page_index().is_some())
+                .collect::<Vec<_>>()
+        };
+
+        assert!(code_problems(&signed).is_empty());
+
+        // Data outside the scatter vector isn't covered by the signature.
+        signed[0x2004] ^= 0xff;
+        assert!(code_problems(&signed).is_empty());
+
+        // But scattered pages are.
+        signed[0x3004] ^= 0xff;
+        let problems = code_problems(&signed);
+        assert_eq!(problems.len(), 1, "{problems:?}");
+        assert!(matches!(
+            problems[0].problem,
+            VerificationProblemType::CodeDigestMismatch(2, _, _)
+        ));
+        assert_eq!(
+            problems[0]
+                .context
+                .code_page
+                .as_ref()
+                .map(|page| (page.start_offset, page.end_offset)),
+            Some((0x3000, 0x4000))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn detached_signature() -> Result<(), AppleCodesignError> {
+        let mut data = SyntheticMachO::default().build();
+
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(SettingsScope::Main, "com.example.verify");
+
+        // A detached signature digests the binary as is instead of a copy with
+        // room for an embedded signature.
+        let macho = MachOBinary::parse(&data)?;
+        let cputype = macho.macho.header.cputype();
+        let signature = MachOSigner::new(&data)?.create_superblob(&settings, &macho)?;
+
+        let td = tempfile::tempdir()?;
+        let binary_path = td.path().join("binary");
+        let signature_path = td.path().join("binary.sig");
+        std::fs::write(&binary_path, &data)?;
+        std::fs::write(&signature_path, &signature)?;
+
+        let no_cms = |problems: Vec<VerificationProblem>| {
+            problems
+                .into_iter()
+                .filter(|problem| {
+                    !matches!(
+                        problem.problem,
+                        VerificationProblemType::NoCryptographicSignature
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert!(matches!(
+            verify_path(&binary_path)[..],
+            [VerificationProblem {
+                problem: VerificationProblemType::NoMachOSignatureData,
+                ..
+            }]
+        ));
+        let problems = verify_path_detached(&binary_path, &signature_path);
+        assert_eq!(problems.len(), 1, "{problems:?}");
+        assert_eq!(
+            problems[0].context.path.as_deref(),
+            Some(binary_path.as_path())
+        );
+        assert!(no_cms(problems).is_empty());
+
+        let detached = create_superblob(
+            CodeSigningMagic::DetachedSignature,
+            [(CodeSigningSlot::from(cputype), signature.clone())].iter(),
+        )?;
+        std::fs::write(&signature_path, &detached)?;
+        assert!(no_cms(verify_path_detached(&binary_path, &signature_path)).is_empty());
+
+        let other_arch = create_superblob(
+            CodeSigningMagic::DetachedSignature,
+            [(CodeSigningSlot::from(cputype + 1), signature.clone())].iter(),
+        )?;
+        let problems = verify_macho_data_detached(&data, &other_arch);
+        assert_eq!(problems.len(), 1, "{problems:?}");
+        assert!(matches!(
+            problems[0].problem,
+            VerificationProblemType::DetachedSignatureMissingArch(x) if x == cputype
+        ));
+
+        let problems = verify_macho_data_detached(&data, &signature[0..16]);
+        assert!(matches!(
+            problems[0].problem,
+            VerificationProblemType::DetachedSignatureError(_)
+        ));
+
+        assert!(matches!(
+            verify_path_detached(&binary_path, td.path().join("missing"))[..],
+            [VerificationProblem {
+                problem: VerificationProblemType::IoError(_),
+                ..
+            }]
+        ));
+
+        data[0x1004] ^= 0xff;
+        std::fs::write(&binary_path, &data)?;
+        let problems = no_cms(verify_path_detached(&binary_path, &signature_path));
+        assert_eq!(problems.len(), 1, "{problems:?}");
+        assert!(matches!(
+            problems[0].problem,
+            VerificationProblemType::CodeDigestMismatch(1, _, _)
+        ));
+
+        Ok(())
+    }
+
+    fn sign_with_requirement(requirement: &str) -> Result<Vec<u8>, AppleCodesignError> {
+        let data = SyntheticMachO::default().build();
+
+        let (cert, key, _) = create_self_signed_code_signing_certificate(
+            KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1),
+            CertificateProfile::DeveloperIdApplication,
+            "team1",
+            "Joe Developer",
+            "US",
+            chrono::Duration::hours(1),
+        )?;
+
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(SettingsScope::Main, "com.example.verify");
+        settings.set_signing_key(&key, cert);
+        let requirement =
+            crate::code_requirement::DesignatedRequirement::from_requirement_text(requirement)?;
+        settings.set_designated_requirement_expression(
+            SettingsScope::Main,
+            &requirement.expression()?,
+        )?;
+
+        let mut signed = vec![];
+        MachOSigner::new(&data)?.write_signed_binary(&settings, &mut signed)?;
+
+        Ok(signed)
+    }
+
+    #[test]
+    fn verification_report() -> Result<(), AppleCodesignError> {
+        let signed = sign_with_requirement(
+            r#"identifier "com.example.verify" and certificate leaf[subject.OU] = "team1""#,
+        )?;
+        let macho = MachOBinary::parse(&signed)?;
+        let report = verify_macho_report(&macho);
+
+        assert!(report.is_valid(), "{:?}", report.problems);
+        assert_eq!(
+            report.context.identifier.as_deref(),
+            Some("com.example.verify")
+        );
+        assert_eq!(report.designated_requirement, Some(true));
+        assert_eq!(report.certificates.len(), 1);
+        assert!(report
+            .slot_digests
+            .iter()
+            .any(|(slot, valid)| *slot == CodeSigningSlot::RequirementSet && *valid));
+        assert!(report
+            .checks
+            .iter()
+            .all(|check| check.outcome == CheckOutcome::Passed));
+
+        Ok(())
+    }
+
+    #[test]
+    fn designated_requirement_unsatisfied() -> Result<(), AppleCodesignError> {
+        for requirement in [
+            r#"identifier "com.example.other""#,
+            r#"anchor apple generic and identifier "com.example.verify""#,
+        ] {
+            let signed = sign_with_requirement(requirement)?;
+            let macho = MachOBinary::parse(&signed)?;
+            let report = verify_macho_report(&macho);
+            let requirement =
+                crate::code_requirement::DesignatedRequirement::from_requirement_text(requirement)?
+                    .to_requirement_text()?;
+
+            assert_eq!(report.designated_requirement, Some(false));
+            assert!(
+                matches!(
+                    &report.problems[..],
+                    [VerificationProblem {
+                        problem: VerificationProblemType::DesignatedRequirementUnsatisfied(text),
+                        ..
+                    }] if *text == requirement
+                ),
+                "{:?}",
+                report.problems
+            );
+            assert!(matches!(
+                report.outcome(VerificationCheck::DesignatedRequirement),
+                Some(CheckOutcome::Failed(_))
+            ));
+            assert_eq!(
+                report.outcome(VerificationCheck::CmsSignature),
+                Some(&CheckOutcome::Passed)
+            );
+        }
+
+        // Requirements that consult system state can't be evaluated and aren't problems.
+        let signed = sign_with_requirement(r#"anchor trusted"#)?;
+        let macho = MachOBinary::parse(&signed)?;
+        let report = verify_macho_report(&macho);
+        assert!(report.is_valid(), "{:?}", report.problems);
+        assert_eq!(report.designated_requirement, None);
+        assert!(matches!(
+            report.outcome(VerificationCheck::DesignatedRequirement),
+            Some(CheckOutcome::Skipped(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn code_limit() -> Result<(), AppleCodesignError> {
+        let data = SyntheticMachO::default().build();
+
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(SettingsScope::Main, "com.example.verify");
+
+        let mut signed = vec![];
+        MachOSigner::new(&data)?.write_signed_binary(&settings, &mut signed)?;
+
+        let macho = MachOBinary::parse(&signed)?;
+        let signature = macho.code_signature()?.unwrap();
+        let cd = signature.code_directory()?.unwrap();
+        assert_eq!(cd.code_limit as u64, macho.code_limit_binary_offset()?);
+
+        let verify = |cd: &CodeDirectoryBlob| {
+            verify_code_directory(&macho, &signature, cd, VerificationContext::default())
+                .into_iter()
+                .map(|problem| problem.problem)
+                .collect::<Vec<_>>()
+        };
+
+        assert!(verify(&cd).is_empty());
+
+        // A code limit beyond the data is indicative of truncation.
+        let mut truncated = cd.to_owned();
+        truncated.code_limit_64 = Some(signed.len() as u64 + 1);
+        let problems = verify(&truncated);
+        assert!(matches!(
+            problems[..],
+            [VerificationProblemType::CodeLimitExceedsData(limit, len)]
+                if limit == signed.len() as u64 + 1 && len == signed.len() as u64
+        ));
+
+        // Pages are digested up to the recorded code limit.
+        let mut short = cd.to_owned();
+        short.code_limit = 0x1000;
+        let problems = verify(&short);
+        assert!(matches!(
+            problems[0],
+            VerificationProblemType::CodeLimitMismatch(0x1000, _)
+        ));
+        assert!(matches!(
+            problems[1],
+            VerificationProblemType::CodeDigestExtraEntry(1, _)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn path_events() -> Result<(), AppleCodesignError> {
+        let data = SyntheticMachO::default().build();
+
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(SettingsScope::Main, "com.example.verify");
+
+        let mut signed = vec![];
+        MachOSigner::new(&data)?.write_signed_binary(&settings, &mut signed)?;
+
+        let td = tempfile::tempdir()?;
+        std::fs::create_dir(td.path().join("a"))?;
+        std::fs::write(td.path().join("a").join("signed"), &signed)?;
+        std::fs::write(td.path().join("b.txt"), b"not a Mach-O")?;
+        std::fs::write(td.path().join("c"), &data)?;
+
+        let summarize = |events: VerificationEvents| {
+            events
+                .map(|event| match event {
+                    VerificationEvent::FileStarted { path, slices } => format!(
+                        "start {} {}",
+                        path.file_name().unwrap().to_string_lossy(),
+                        slices
+                    ),
+                    VerificationEvent::Problem(problem) => match problem.problem {
+                        VerificationProblemType::NoCryptographicSignature => "no cms".into(),
+                        VerificationProblemType::NoMachOSignatureData => "no signature".into(),
+                        VerificationProblemType::MachOParseError(_) => "parse error".into(),
+                        problem => format!("{problem:?}"),
+                    },
+                    VerificationEvent::SlotVerified { slot, valid, .. } => {
+                        format!("slot {slot} {valid}")
+                    }
+                    VerificationEvent::SliceFinished { problems, .. } => {
+                        format!("slice {problems}")
+                    }
+                    VerificationEvent::FileFinished { path, problems } => format!(
+                        "finish {} {}",
+                        path.file_name().unwrap().to_string_lossy(),
+                        problems
+                    ),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            summarize(verify_path_events(td.path())),
+            vec![
+                "start signed 1",
+                "no cms",
+                "slot RequirementSet (2) true",
+                "slice 1",
+                "finish signed 1",
+                "start c 1",
+                "no signature",
+                "slice 1",
+                "finish c 1",
+            ]
+        );
+
+        // Non-Mach-O files are only reported when explicitly requested.
+        assert_eq!(
+            summarize(verify_path_events(td.path().join("b.txt"))),
+            vec!["start b.txt 0", "parse error", "finish b.txt 1"]
+        );
+
+        // Verification is lazy, so stopping early doesn't verify later files.
+        let mut events = verify_path_events(td.path());
+        assert!(matches!(
+            events.next(),
+            Some(VerificationEvent::FileStarted { .. })
+        ));
+        assert!(events.current.is_some());
+        assert_eq!(events.pending.len(), 0);
+
+        Ok(())
+    }
+
+    /// Serve RFC 3161 time-stamp requests from a local port.
+    ///
+    /// When `tamper` is set, the token's message imprint doesn't match the request.
+    /// The first `failures` requests are answered with a server error.
+    fn time_stamp_server(time: &'static str, tamper: bool, failures: usize) -> String {
+        use {
+            bcder::{encode::Values, Integer, Mode, OctetString, Oid},
+            cryptographic_message_syntax::{
+                asn1::rfc3161::{
+                    MessageImprint, PkiStatus, PkiStatusInfo, TimeStampReq,
+                    OID_CONTENT_TYPE_TST_INFO,
+                },
+                SignedDataBuilder, SignerBuilder,
+            },
+            std::io::{BufRead, BufReader, Read, Write},
+            x509_certificate::asn1time::GeneralizedTime,
+        };
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        let (tsa_cert, tsa_key, _) = create_self_signed_code_signing_certificate(
+            KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1),
+            CertificateProfile::DeveloperIdApplication,
+            "tsa",
+            "Time Stamp Authority",
+            "US",
+            chrono::Duration::hours(1),
+        )
+        .unwrap();
+
+        std::thread::spawn(move || {
+            for (index, stream) in listener.incoming().enumerate() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end().to_ascii_lowercase();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.strip_prefix("content-length:") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                if index < failures {
+                    stream
+                        .write_all(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                        .unwrap();
+                    continue;
+                }
+
+                let request = bcder::decode::Constructed::decode(
+                    body.as_slice(),
+                    Mode::Der,
+                    TimeStampReq::take_from,
+                )
+                .unwrap();
+
+                let mut imprint = request.message_imprint.hashed_message.to_bytes().to_vec();
+                if tamper {
+                    imprint[0] ^= 0xff;
+                }
+
+                let gen_time_der = [&[0x18, time.len() as u8], time.as_bytes()].concat();
+
+                let tst_info = TstInfo {
+                    version: Integer::from(1),
+                    policy: Oid(bytes::Bytes::from_static(&[42, 3, 4])),
+                    message_imprint: MessageImprint {
+                        hash_algorithm: request.message_imprint.hash_algorithm.clone(),
+                        hashed_message: OctetString::new(imprint.into()),
+                    },
+                    serial_number: Integer::from(42),
+                    gen_time: bcder::decode::Constructed::decode(
+                        gen_time_der.as_slice(),
+                        Mode::Der,
+                        GeneralizedTime::take_from_allow_fractional_z,
+                    )
+                    .unwrap(),
+                    accuracy: None,
+                    ordering: None,
+                    nonce: request.nonce.clone(),
+                    tsa: None,
+                    extensions: None,
+                };
+
+                let token = SignedDataBuilder::default()
+                    .content_inline(tst_info.encode_ref().to_captured(Mode::Der).to_vec())
+                    .content_type(Oid(bytes::Bytes::copy_from_slice(
+                        OID_CONTENT_TYPE_TST_INFO.as_ref(),
+                    )))
+                    .signer(SignerBuilder::new(&tsa_key, tsa_cert.clone()))
+                    .build_der()
+                    .unwrap();
+
+                // The token is embedded verbatim as ContentInfo doesn't round-trip.
+                let status = PkiStatusInfo {
+                    status: PkiStatus::Granted,
+                    status_string: None,
+                    fail_info: None,
+                };
+                let token =
+                    bcder::decode::Constructed::decode(token.as_slice(), Mode::Der, |cons| {
+                        cons.capture_one()
+                    })
+                    .unwrap();
+                let response = bcder::encode::sequence((status.encode_ref(), token))
+                    .to_captured(Mode::Der)
+                    .to_vec();
+
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/timestamp-reply\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    response.len()
+                )
+                .unwrap();
+                stream.write_all(&response).unwrap();
+            }
+        });
+
+        url
+    }
+
+    fn time_stamped_superblob(url: &str) -> Vec<u8> {
+        try_time_stamped_superblob(url, &RetryPolicy::none()).unwrap()
+    }
+
+    fn try_time_stamped_superblob(
+        url: &str,
+        retry_policy: &RetryPolicy,
+    ) -> Result<Vec<u8>, AppleCodesignError> {
+        let (cert, key, _) = create_self_signed_code_signing_certificate(
+            KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1),
+            CertificateProfile::DeveloperIdApplication,
+            "team1",
+            "Joe Developer",
+            "US",
+            chrono::Duration::hours(1),
+        )
+        .unwrap();
+
+        let mut builder = EmbeddedSignatureBuilder::default();
+        builder
+            .add_code_directory(
+                CodeSigningSlot::CodeDirectory,
+                code_directory(DigestType::Sha256, 0x55),
+            )
+            .unwrap();
+        builder.create_cms_signature(
+            &key,
+            &cert,
+            Some(&url.parse().unwrap()),
+            retry_policy,
+            std::iter::empty(),
+        )?;
+
+        builder.create_superblob()
+    }
+
+    #[test]
+    fn signer_details() {
+        let data = signed_superblob();
+        let signature = EmbeddedSignature::from_bytes(&data).unwrap();
+        let signer = signature.signer_details().unwrap().unwrap();
+
+        assert_eq!(
+            signer.common_name.as_deref(),
+            Some("Developer ID Application: Joe Developer (team1)")
+        );
+        assert_eq!(signer.team_id.as_deref(), Some("team1"));
+        assert!(signer.signing_time.is_some());
+        assert_eq!(signer.digest_algorithm, DigestAlgorithm::Sha256);
+        assert_eq!(signer.certificate_chain.len(), 1);
+        assert_eq!(
+            signer.signing_certificate().unwrap().subject_common_name(),
+            signer.common_name
+        );
+
+        let mut builder = EmbeddedSignatureBuilder::default();
+        builder
+            .add_code_directory(
+                CodeSigningSlot::CodeDirectory,
+                code_directory(DigestType::Sha256, 0x55),
+            )
+            .unwrap();
+        let data = builder.create_superblob().unwrap();
+        let signature = EmbeddedSignature::from_bytes(&data).unwrap();
+        assert!(signature.signer_details().unwrap().is_none());
+    }
+
+    #[test]
+    fn time_stamp_token() {
+        let data = time_stamped_superblob(&time_stamp_server("20230607080910Z", false, 0));
+        let signature = EmbeddedSignature::from_bytes(&data).unwrap();
+        let signed_data = signature.signed_data().unwrap().unwrap();
+        let signer = signed_data.signers().next().unwrap();
+
+        let time_stamp = verify_time_stamp_token(signer).unwrap().unwrap();
+        assert_eq!(time_stamp.time.to_rfc3339(), "2023-06-07T08:09:10+00:00");
+        assert_eq!(time_stamp.digest_algorithm, DigestAlgorithm::Sha256);
+
+        let cms = signature.signature_data().unwrap().unwrap();
+        let mut context = VerificationContext::default();
+        let problems = verify_cms_signature(&signature, cms, &mut context);
+        assert!(problems.is_empty(), "{problems:?}");
+        assert_eq!(context.time_stamp, Some(time_stamp.time));
+
+        // Signatures without a token have nothing to verify.
+        let data = signed_superblob();
+        let signature = EmbeddedSignature::from_bytes(&data).unwrap();
+        let signed_data = signature.signed_data().unwrap().unwrap();
+        assert!(
+            verify_time_stamp_token(signed_data.signers().next().unwrap())
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn time_stamp_retry() {
+        let url = time_stamp_server("20230607080910Z", false, 1);
+        assert!(matches!(
+            try_time_stamped_superblob(&url, &RetryPolicy::none()),
+            Err(AppleCodesignError::Cms(CmsError::TimeStampProtocol(
+                cryptographic_message_syntax::TimeStampError::Http(_)
+            )))
+        ));
+
+        // Only the time-stamp request is repeated. The signature is still valid.
+        let mut policy = RetryPolicy::default();
+        policy.set_delays(std::time::Duration::ZERO, std::time::Duration::ZERO);
+        let url = time_stamp_server("20230607080910Z", false, 1);
+        let data = try_time_stamped_superblob(&url, &policy).unwrap();
+
+        let problems = verify_superblob(&data);
+        assert!(problems.is_empty(), "{problems:?}");
+    }
+
+    #[test]
+    fn time_stamp_token_imprint_mismatch() {
+        let data = time_stamped_superblob(&time_stamp_server("20230607080910Z", true, 0));
+
+        let problems = verify_superblob(&data);
+        assert_eq!(problems.len(), 1, "{problems:?}");
+        assert!(matches!(
+            problems[0].problem,
+            VerificationProblemType::TimeStampImprintMismatch(_, _)
+        ));
+        assert!(problems[0].problem.expected_digest().is_some());
+        assert!(problems[0].context.time_stamp.is_none());
+    }
+
+    #[test]
+    fn entitlements_mismatch() {
+        const XML: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<plist version=\"1.0\"><dict><key>com.apple.security.get-task-allow</key><true/></dict></plist>";
+
+        let entitlements = |der: plist::Value| {
+            let blobs = [
+                (
+                    CodeSigningSlot::Entitlements,
+                    EntitlementsBlob::from_string(XML).to_blob_bytes().unwrap(),
+                ),
+                (
+                    CodeSigningSlot::EntitlementsDer,
+                    EntitlementsDerBlob::from_plist(&der)
+                        .unwrap()
+                        .to_blob_bytes()
+                        .unwrap(),
+                ),
+            ];
+
+            create_superblob(CodeSigningMagic::EmbeddedSignature, blobs.iter()).unwrap()
+        };
+
+        let mut dict = plist::Dictionary::new();
+        dict.insert("com.apple.security.get-task-allow".into(), true.into());
+
+        let data = entitlements(dict.clone().into());
+        let signature = EmbeddedSignature::from_bytes(&data).unwrap();
+        assert!(verify_entitlements(&signature, VerificationContext::default()).is_empty());
+        assert_eq!(
+            signature
+                .entitlements_der()
+                .unwrap()
+                .unwrap()
+                .parse_der()
+                .unwrap(),
+            plist::Value::Dictionary(dict.clone())
+        );
+
+        // DER entitlements granting more than the XML.
+        dict.insert(
+            "com.apple.security.cs.disable-library-validation".into(),
+            true.into(),
+        );
+        let data = entitlements(dict.into());
+        let signature = EmbeddedSignature::from_bytes(&data).unwrap();
+        let problems = verify_entitlements(&signature, VerificationContext::default());
+        assert_eq!(problems.len(), 1);
+        assert!(matches!(
+            problems[0].problem,
+            VerificationProblemType::EntitlementsMismatch
+        ));
+    }
+
+    #[test]
+    fn untrusted_apple_certificate_chain() -> Result<(), AppleCodesignError> {
+        let data = SyntheticMachO::default().build();
+
+        // A self-signed certificate impersonating an Apple intermediate CA's name.
+        let mut builder = x509_certificate::X509CertificateBuilder::new(KeyAlgorithm::Ecdsa(
+            EcdsaCurve::Secp256r1,
+        ));
+        builder.apple_certificate_profile(CertificateProfile::DeveloperIdApplication)?;
+        *builder.subject() = KnownCertificate::DeveloperIdG1.subject_name().clone();
+        let (cert, key, _) = builder.create_with_random_keypair()?;
+
+        let mut settings = SigningSettings::default();
+        settings.set_binary_identifier(SettingsScope::Main, "com.example.verify");
+        settings.set_signing_key(&key, cert);
+        assert!(settings.certificate_chain().is_empty());
+
+        let mut signed = vec![];
+        MachOSigner::new(&data)?.write_signed_binary(&settings, &mut signed)?;
+
+        let problems = verify_macho_data(&signed);
+        assert!(
+            matches!(
+                &problems[..],
+                [VerificationProblem {
+                    problem: VerificationProblemType::CmsCertificateChainUntrusted(_),
+                    ..
+                }]
+            ),
+            "{problems:?}"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,296 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Continuous signing of build outputs.
+//!
+//! On Apple Silicon, the kernel refuses to execute unsigned code and kills the
+//! process with `SIGKILL` ("killed: 9"). Linkers not from Apple often don't
+//! sign their output, so every rebuild needs to be followed by a signing
+//! operation. [WatchSigner] polls a directory and signs Mach-O binaries as
+//! they change so this happens automatically.
+
+use {
+    crate::{
+        error::AppleCodesignError, reader::PathType, signing::UnifiedSigner,
+        signing_settings::SigningSettings,
+    },
+    log::{info, warn},
+    std::{
+        collections::BTreeMap,
+        io::Write,
+        path::{Path, PathBuf},
+        time::{Duration, Instant, SystemTime},
+    },
+};
+
+/// Identifies a version of a file's content.
+type FileStamp = (SystemTime, u64);
+
+enum FileState {
+    /// File content has been seen before and doesn't need signing.
+    Known(FileStamp),
+    /// File content changed and will be signed once it settles.
+    Pending(FileStamp, Instant),
+}
+
+/// Watches a directory and signs Mach-O binaries as they change.
+///
+/// Changes are detected by polling file modification times and sizes. A
+/// changed file is only signed once it has been left alone for the settle
+/// delay, so files still being written by a linker aren't signed prematurely.
+///
+/// Signed binaries are written to a new file which replaces the original. This
+/// ensures the kernel doesn't use a cached signature of the previous content.
+///
+/// Signing settings are applied to every binary. Default settings produce
+/// ad-hoc signatures, which is what local development typically wants.
+pub struct WatchSigner<'key> {
+    signer: UnifiedSigner<'key>,
+    root: PathBuf,
+    poll_interval: Duration,
+    settle_delay: Duration,
+    sign_existing: bool,
+    files: Option<BTreeMap<PathBuf, FileState>>,
+}
+
+impl<'key> WatchSigner<'key> {
+    /// Construct an instance watching the directory at `root`.
+    pub fn new(settings: SigningSettings<'key>, root: impl AsRef<Path>) -> Self {
+        Self {
+            signer: UnifiedSigner::new(settings),
+            root: root.as_ref().to_path_buf(),
+            poll_interval: Duration::from_millis(500),
+            settle_delay: Duration::from_secs(1),
+            sign_existing: false,
+            files: None,
+        }
+    }
+
+    /// Set how often to look for changed files.
+    pub fn set_poll_interval(&mut self, value: Duration) {
+        self.poll_interval = value;
+    }
+
+    /// Set how long a changed file must go unmodified before it is signed.
+    pub fn set_settle_delay(&mut self, value: Duration) {
+        self.settle_delay = value;
+    }
+
+    /// Set whether files present when watching begins are signed.
+    ///
+    /// By default, only files changing after the first poll are signed.
+    pub fn set_sign_existing(&mut self, value: bool) {
+        self.sign_existing = value;
+    }
+
+    /// Look for changed files and sign those that have settled.
+    ///
+    /// Returns the paths of binaries that were signed. Failures to sign an
+    /// individual file are logged and the file isn't retried until it changes
+    /// again.
+    pub fn poll(&mut self) -> Result<Vec<PathBuf>, AppleCodesignError> {
+        let now = Instant::now();
+        let first_poll = self.files.is_none();
+        let previous = self.files.take().unwrap_or_default();
+
+        let mut files = BTreeMap::new();
+        let mut signed = vec![];
+
+        for entry in walkdir::WalkDir::new(&self.root).sort_by_file_name() {
+            // Build systems create and delete temporary files all the time. So
+            // failing to read an entry isn't fatal.
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    info!("error reading directory entry: {}", e);
+                    continue;
+                }
+            };
+
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let stamp = match entry.metadata() {
+                Ok(metadata) => (metadata.modified()?, metadata.len()),
+                Err(_) => continue,
+            };
+
+            let path = entry.into_path();
+
+            let state = match previous.get(&path) {
+                None if first_poll && !self.sign_existing => FileState::Known(stamp),
+                Some(FileState::Known(known)) if *known == stamp => FileState::Known(stamp),
+                Some(FileState::Pending(pending, since)) if *pending == stamp => {
+                    if now.duration_since(*since) < self.settle_delay {
+                        FileState::Pending(stamp, *since)
+                    } else {
+                        match self.sign_file(&path) {
+                            Ok(Some(stamp)) => {
+                                signed.push(path.clone());
+                                FileState::Known(stamp)
+                            }
+                            Ok(None) => FileState::Known(stamp),
+                            Err(e) => {
+                                warn!("error signing {}: {}", path.display(), e);
+                                FileState::Known(stamp)
+                            }
+                        }
+                    }
+                }
+                _ => FileState::Pending(stamp, now),
+            };
+
+            files.insert(path, state);
+        }
+
+        self.files = Some(files);
+
+        Ok(signed)
+    }
+
+    /// Poll for changes until `should_stop` returns true.
+    ///
+    /// `should_stop` is called before each poll.
+    pub fn watch(
+        &mut self,
+        mut should_stop: impl FnMut() -> bool,
+    ) -> Result<(), AppleCodesignError> {
+        warn!(
+            "watching {} for changed Mach-O binaries",
+            self.root.display()
+        );
+
+        while !should_stop() {
+            for path in self.poll()? {
+                warn!("signed {}", path.display());
+            }
+
+            std::thread::sleep(self.poll_interval);
+        }
+
+        Ok(())
+    }
+
+    /// Sign a file if it is a Mach-O binary, replacing it.
+    ///
+    /// Returns the stamp of the replaced file or `None` if it isn't a Mach-O.
+    fn sign_file(&self, path: &Path) -> Result<Option<FileStamp>, AppleCodesignError> {
+        if !matches!(PathType::from_path(path)?, PathType::MachO) {
+            return Ok(None);
+        }
+
+        let (parent, file_name) = match (path.parent(), path.file_name()) {
+            (Some(parent), Some(file_name)) => (parent, file_name.to_string_lossy()),
+            _ => {
+                return Err(AppleCodesignError::CliGeneralError(format!(
+                    "unable to resolve file name of {}",
+                    path.display()
+                )))
+            }
+        };
+
+        let macho_data = std::fs::read(path)?;
+        let signed_data = self.signer.sign_macho_data(&macho_data, Some(&file_name))?;
+
+        let permissions = std::fs::metadata(path)?.permissions();
+
+        // The temporary file is deleted if anything fails before it replaces
+        // the original.
+        let mut temp_file = tempfile::Builder::new()
+            .prefix(&format!(".{file_name}."))
+            .suffix(".rcodesign-watch")
+            .tempfile_in(parent)?;
+        temp_file.write_all(&signed_data)?;
+        std::fs::set_permissions(temp_file.path(), permissions)?;
+        temp_file.persist(path).map_err(|e| e.error)?;
+
+        let metadata = std::fs::metadata(path)?;
+
+        Ok(Some((metadata.modified()?, metadata.len())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::embedded_signature::CodeSigningSlot, crate::macho::MachFile};
+
+    fn is_signed(path: &Path) -> Result<bool, AppleCodesignError> {
+        let data = std::fs::read(path)?;
+        let mach = MachFile::parse(&data)?;
+
+        Ok(match mach.nth_macho(0)?.code_signature()? {
+            Some(sig) => sig.find_slot(CodeSigningSlot::CodeDirectory).is_some(),
+            None => false,
+        })
+    }
+
+    #[test]
+    fn signs_changed_files() -> Result<(), AppleCodesignError> {
+        let td = tempfile::tempdir()?;
+        let existing = td.path().join("existing");
+        let binary = td.path().join("binary");
+        let text = td.path().join("text");
+
+        let macho = crate::test_support::SyntheticMachO::default().build();
+        std::fs::write(&existing, &macho)?;
+
+        let mut watcher = WatchSigner::new(SigningSettings::default(), td.path());
+        watcher.set_settle_delay(Duration::ZERO);
+
+        // Files present at the first poll are left alone.
+        assert!(watcher.poll()?.is_empty());
+        assert!(watcher.poll()?.is_empty());
+        assert!(!is_signed(&existing)?);
+
+        std::fs::write(&binary, &macho)?;
+        std::fs::write(&text, b"not a Mach-O")?;
+
+        // Changes are first recorded and then signed once unchanged.
+        assert!(watcher.poll()?.is_empty());
+        assert_eq!(watcher.poll()?, vec![binary.clone()]);
+        assert!(is_signed(&binary)?);
+        assert_eq!(std::fs::read(&text)?, b"not a Mach-O");
+
+        // The signed binary replaced the original without leaving files behind.
+        assert_eq!(std::fs::read_dir(td.path())?.count(), 3);
+
+        // Our own writes don't trigger signing.
+        assert!(watcher.poll()?.is_empty());
+        assert!(watcher.poll()?.is_empty());
+
+        // Files still changing aren't signed until they settle.
+        watcher.set_settle_delay(Duration::from_secs(3600));
+        std::fs::write(&binary, &macho)?;
+        assert!(watcher.poll()?.is_empty());
+        assert!(watcher.poll()?.is_empty());
+        assert!(!is_signed(&binary)?);
+
+        watcher.set_settle_delay(Duration::ZERO);
+        assert_eq!(watcher.poll()?, vec![binary.clone()]);
+        assert!(is_signed(&binary)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sign_existing() -> Result<(), AppleCodesignError> {
+        let td = tempfile::tempdir()?;
+        let binary = td.path().join("binary");
+        std::fs::write(
+            &binary,
+            crate::test_support::SyntheticMachO::default().build(),
+        )?;
+
+        let mut watcher = WatchSigner::new(SigningSettings::default(), td.path());
+        watcher.set_settle_delay(Duration::ZERO);
+        watcher.set_sign_existing(true);
+
+        assert!(watcher.poll()?.is_empty());
+        assert_eq!(watcher.poll()?, vec![binary.clone()]);
+        assert!(is_signed(&binary)?);
+
+        Ok(())
+    }
+}
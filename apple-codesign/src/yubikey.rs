@@ -63,6 +63,20 @@ fn algorithm_from_certificate(
     }
 }
 
+/// Resolve the touch policy of the key in a slot.
+///
+/// Returns `None` if the device can't report slot metadata, which requires
+/// firmware 5.3 or newer.
+fn slot_touch_policy(yk: &mut RawYubiKey, slot: SlotId) -> Option<TouchPolicy> {
+    match yubikey::piv::metadata(yk, slot) {
+        Ok(metadata) => metadata.policy.map(|(_, touch)| touch),
+        Err(e) => {
+            log::debug!("unable to read metadata of slot {:?}: {}", slot, e);
+            None
+        }
+    }
+}
+
 /// Describes the needed authentication for an operation.
 pub enum RequiredAuthentication {
     Pin,
@@ -574,6 +588,16 @@ impl Signer<Signature> for CertificateSigner {
 
         let yk = guard.deref_mut();
 
+        if matches!(
+            slot_touch_policy(yk, self.slot),
+            Some(TouchPolicy::Always | TouchPolicy::Cached)
+        ) {
+            warn!(
+                "key in slot {} requires a physical touch; touch your YubiKey when it blinks",
+                hex::encode([u8::from(self.slot)])
+            );
+        }
+
         warn!("initial signing attempt may fail if the certificate requires a pin to unlock");
 
         attempt_authenticated_operation(
@@ -708,4 +732,16 @@ impl CertificateSigner {
     pub fn certificate(&self) -> &CapturedX509Certificate {
         &self.cert
     }
+
+    /// The touch policy of the key in this signer's slot.
+    ///
+    /// Returns `None` if the device doesn't report it.
+    pub fn touch_policy(&self) -> Result<Option<TouchPolicy>, AppleCodesignError> {
+        let mut guard = self
+            .yk
+            .lock()
+            .map_err(|_| AppleCodesignError::PoisonedLock)?;
+
+        Ok(slot_touch_policy(guard.deref_mut(), self.slot))
+    }
 }
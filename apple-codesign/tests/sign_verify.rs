@@ -0,0 +1,893 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Sign and verify round trips using only this crate.
+//!
+//! Mach-O binaries are emitted by the tests themselves so no Apple tooling
+//! or fixtures are needed and these tests run on every platform.
+
+use {
+    apple_codesign::{
+        create_self_signed_code_signing_certificate, remove_signature, test_support,
+        verify_macho_data, verify_path, verify_xar_data, AppleCodesignError, Blob,
+        CertificateProfile, CmsSigner, CodeSigningSlot, DigestType, EmbeddedSignature, MachFile,
+        MachOParseError, MachOSigner, Scatter, SettingsScope, SigningSettings, UnifiedSigner,
+        UniversalBinaryBuilder, VerificationFailure, VerificationProblem, VerificationProblemType,
+    },
+    apple_xar::{compression::Compression, table_of_contents::ChecksumType, writer::XarWriter},
+    cryptographic_message_syntax::CmsError,
+    std::{
+        io::Write,
+        process::{Command, Output, Stdio},
+    },
+    x509_certificate::{EcdsaCurve, KeyAlgorithm},
+};
+
+const CPU_TYPE_X86_64: u32 = 0x01000007;
+const CPU_TYPE_ARM64: u32 = 0x0100000c;
+
+/// File offset of the `__text` section content.
+const TEXT_OFFSET: usize = test_support::TEXT_OFFSET as usize;
+
+/// Emit a minimal 64-bit executable with `__TEXT` and `__LINKEDIT` segments.
+fn macho(cputype: u32, cpusubtype: u32) -> Vec<u8> {
+    let mut data = test_support::SyntheticMachO::new_64(cputype, cpusubtype).build();
+
+    // Give the code some content so digests are meaningful.
+    for (i, b) in data[TEXT_OFFSET..TEXT_OFFSET + 0x10].iter_mut().enumerate() {
+        *b = i as u8;
+    }
+
+    data
+}
+
+fn settings() -> SigningSettings<'static> {
+    let mut settings = SigningSettings::default();
+    settings.set_binary_identifier(SettingsScope::Main, "com.example.acceptance");
+
+    settings
+}
+
+fn sign(settings: &SigningSettings, data: &[u8]) -> Result<Vec<u8>, AppleCodesignError> {
+    let mut signed = vec![];
+    MachOSigner::new(data)?.write_signed_binary(settings, &mut signed)?;
+
+    Ok(signed)
+}
+
+fn problem_types(problems: &[VerificationProblem]) -> Vec<&VerificationProblemType> {
+    problems.iter().map(|problem| &problem.problem).collect()
+}
+
+#[test]
+fn adhoc() -> Result<(), AppleCodesignError> {
+    let signed = sign(&settings(), &macho(CPU_TYPE_X86_64, 3))?;
+
+    // Ad-hoc signatures lack a CMS signature. Everything else verifies.
+    let problems = verify_macho_data(&signed);
+    assert!(
+        matches!(
+            problem_types(&problems)[..],
+            [VerificationProblemType::NoCryptographicSignature]
+        ),
+        "{problems:?}"
+    );
+
+    // Signing is deterministic.
+    assert_eq!(sign(&settings(), &macho(CPU_TYPE_X86_64, 3))?, signed);
+
+    Ok(())
+}
+
+/// Run `rcodesign` with `stdin` piped to it.
+fn rcodesign(args: &[&str], stdin: &[u8]) -> std::io::Result<Output> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rcodesign"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    child.stdin.take().unwrap().write_all(stdin)?;
+
+    child.wait_with_output()
+}
+
+#[test]
+fn sign_stdin() -> Result<(), AppleCodesignError> {
+    let unsigned = macho(CPU_TYPE_X86_64, 3);
+    let expected = sign(&settings(), &unsigned)?;
+
+    let args = ["sign", "--binary-identifier", "com.example.acceptance", "-"];
+
+    let output = rcodesign(&args, &unsigned)?;
+    assert!(output.status.success());
+    assert_eq!(output.stdout, expected);
+
+    // Hex text is decoded.
+    let hex = unsigned
+        .chunks(30)
+        .map(hex::encode)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let output = rcodesign(&args, hex.as_bytes())?;
+    assert!(output.status.success());
+    assert_eq!(output.stdout, expected);
+
+    // The existing signature provides the identifier.
+    let output = rcodesign(&["sign", "-", "-"], &expected)?;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(output.stdout, expected);
+
+    // Without a file name, an identifier must come from somewhere else.
+    let output = rcodesign(&["sign", "-"], &unsigned)?;
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--binary-identifier is required"));
+
+    Ok(())
+}
+
+#[test]
+fn extract() -> Result<(), AppleCodesignError> {
+    const ENTITLEMENTS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>com.apple.security.get-task-allow</key>
+	<true/>
+</dict>
+</plist>"#;
+
+    let mut settings = settings();
+    settings.set_entitlements_xml(SettingsScope::Main, ENTITLEMENTS)?;
+    let mut signed = sign(&settings, &macho(CPU_TYPE_ARM64, 0))?;
+
+    let td = tempfile::tempdir()?;
+    let path = td.path().join("signed");
+    std::fs::write(&path, &signed)?;
+    let path = path.to_str().unwrap();
+
+    let extract = |args: &[&str]| -> std::io::Result<Vec<u8>> {
+        let output = rcodesign(&[&["extract"], args, &[path]].concat(), b"")?;
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        Ok(output.stdout)
+    };
+
+    let macho = MachFile::parse(&signed)?.into_iter().next().unwrap();
+    let signature = macho.code_signature()?.unwrap();
+
+    // Raw blobs are emitted verbatim, including their header.
+    assert_eq!(
+        extract(&["--data", "blob-raw", "--slot", "5"])?,
+        signature
+            .find_slot(CodeSigningSlot::Entitlements)
+            .unwrap()
+            .data
+    );
+    assert_eq!(
+        extract(&["--data", "blob-raw", "--slot", "0x7"])?,
+        signature
+            .find_slot(CodeSigningSlot::EntitlementsDer)
+            .unwrap()
+            .data
+    );
+
+    // Entitlements are printed as XML.
+    assert_eq!(
+        String::from_utf8(extract(&["--data", "entitlements"])?).unwrap(),
+        format!("{ENTITLEMENTS}\n")
+    );
+
+    let cd =
+        serde_json::from_slice::<serde_json::Value>(&extract(&["--data", "code-directory-json"])?)
+            .unwrap();
+    assert_eq!(cd["identifier"], "com.example.acceptance");
+    assert_eq!(cd["digest_type"], "sha256");
+
+    // --output writes to a file instead of stdout.
+    let output_path = td.path().join("cd.bin");
+    assert!(extract(&[
+        "--data",
+        "code-directory-raw",
+        "--output",
+        output_path.to_str().unwrap()
+    ])?
+    .is_empty());
+    assert_eq!(
+        std::fs::read(&output_path)?,
+        signature
+            .find_slot(CodeSigningSlot::CodeDirectory)
+            .unwrap()
+            .data
+    );
+
+    // No file is created for missing data.
+    let output_path = td.path().join("missing.bin");
+    extract(&[
+        "--data",
+        "blob-raw",
+        "--slot",
+        "0x1234",
+        "--output",
+        output_path.to_str().unwrap(),
+    ])?;
+    assert!(!output_path.exists());
+
+    // blob-raw requires a slot.
+    let output = rcodesign(&["extract", "--data", "blob-raw", path], b"")?;
+    assert_eq!(output.status.code(), Some(2));
+
+    // When only DER entitlements are present, they are decoded as XML.
+    let signature_offset = macho
+        .find_signature_data()?
+        .unwrap()
+        .linkedit_signature_start_offset;
+    let entry = (0..u32::from_be_bytes(
+        signed[signature_offset + 8..signature_offset + 12]
+            .try_into()
+            .unwrap(),
+    ) as usize)
+        .map(|i| signature_offset + 12 + i * 8)
+        .find(|entry| signed[*entry..*entry + 4] == 5u32.to_be_bytes())
+        .unwrap();
+    signed[entry..entry + 4].copy_from_slice(&0x1234u32.to_be_bytes());
+    std::fs::write(path, &signed)?;
+
+    let xml = extract(&["--data", "entitlements"])?;
+    assert_eq!(
+        plist::Value::from_reader_xml(xml.as_slice()).unwrap(),
+        plist::Value::from_reader_xml(ENTITLEMENTS.as_bytes()).unwrap()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn certificate() -> Result<(), AppleCodesignError> {
+    let (cert, key, _) = create_self_signed_code_signing_certificate(
+        KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1),
+        CertificateProfile::AppleDevelopment,
+        "team1",
+        "Acceptance Test",
+        "US",
+        chrono::Duration::hours(1),
+    )?;
+
+    let mut settings = settings();
+    settings.set_signing_key(&key, cert);
+
+    let signed = sign(&settings, &macho(CPU_TYPE_ARM64, 0))?;
+
+    let problems = verify_macho_data(&signed);
+    assert!(problems.is_empty(), "{problems:?}");
+
+    // Emitted signatures satisfy strict layout validation.
+    let mach = MachFile::parse(&signed)?;
+    let signature = mach.nth_macho(0)?.find_signature_data()?.unwrap();
+    EmbeddedSignature::from_bytes_strict(signature.signature_data)?;
+
+    // The implicit designated requirement is derived from the signing certificate.
+    let signature = mach.nth_macho(0)?.code_signature()?.unwrap();
+    assert_eq!(
+        signature
+            .implicit_designated_requirement()?
+            .unwrap()
+            .to_requirement_text()?,
+        "(identifier \"com.example.acceptance\") and ((anchor apple generic) and \
+         ((certificate leaf[subject.CN] = \"Apple Development: Acceptance Test (team1)\") and \
+         (certificate 1[field.1.2.840.113635.100.6.2.1] /* exists */)))"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn tampered() -> Result<(), AppleCodesignError> {
+    let mut signed = sign(&settings(), &macho(CPU_TYPE_X86_64, 3))?;
+    signed[TEXT_OFFSET] ^= 0xff;
+
+    // Code is digested in 4 KiB pages.
+    let problems = verify_macho_data(&signed);
+    assert!(
+        problem_types(&problems).iter().any(|problem| matches!(
+            problem,
+            VerificationProblemType::CodeDigestMismatch(index, _, _) if *index == TEXT_OFFSET / 4096
+        )),
+        "{problems:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn scatter() -> Result<(), AppleCodesignError> {
+    // Sign pages 0, 1, and 3, leaving page 2 unsigned.
+    let scatter = vec![Scatter::new(2, 0, 0), Scatter::new(1, 2, 0x3000)];
+    let mut settings = settings();
+    settings.set_scatter(SettingsScope::Main, scatter.clone());
+
+    let mut signed = sign(&settings, &macho(CPU_TYPE_X86_64, 3))?;
+
+    let macho = MachFile::parse(&signed)?.into_iter().next().unwrap();
+    let signature = macho.code_signature()?.unwrap();
+    let cd = signature.code_directory()?.unwrap();
+    assert_eq!(cd.scatter, Some(scatter));
+    assert_eq!(cd.code_digests.len(), 3);
+
+    let problems = verify_macho_data(&signed);
+    assert!(
+        matches!(
+            problem_types(&problems)[..],
+            [VerificationProblemType::NoCryptographicSignature]
+        ),
+        "{problems:?}"
+    );
+
+    // Pages outside the scatter vector aren't covered by the signature.
+    signed[0x2000] ^= 0xff;
+    let problems = verify_macho_data(&signed);
+    assert!(
+        matches!(
+            problem_types(&problems)[..],
+            [VerificationProblemType::NoCryptographicSignature]
+        ),
+        "{problems:?}"
+    );
+
+    // Scattered pages are.
+    signed[TEXT_OFFSET] ^= 0xff;
+    let problems = verify_macho_data(&signed);
+    assert!(
+        problem_types(&problems).iter().any(|problem| matches!(
+            problem,
+            VerificationProblemType::CodeDigestMismatch(1, _, _)
+        )),
+        "{problems:?}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn failure_classes() -> Result<(), AppleCodesignError> {
+    let failures = |data: &[u8]| {
+        verify_macho_data(data)
+            .iter()
+            .map(|problem| problem.problem.failure())
+            .collect::<Vec<_>>()
+    };
+
+    let signed = sign(&settings(), &macho(CPU_TYPE_X86_64, 3))?;
+    assert_eq!(failures(&signed), [VerificationFailure::ChainUntrusted]);
+
+    let mut tampered = signed.clone();
+    tampered[TEXT_OFFSET] ^= 0xff;
+    assert!(failures(&tampered).contains(&VerificationFailure::ResourcesModified));
+    assert!(!failures(&tampered).contains(&VerificationFailure::SignatureInvalid));
+
+    // Understating the super blob length leaves a valid signature Apple's tools
+    // wouldn't write.
+    let signature_offset = MachFile::parse(&signed)?
+        .nth_macho(0)?
+        .find_signature_data()?
+        .unwrap()
+        .linkedit_signature_start_offset;
+    let length_offset = signature_offset + 4;
+    let length = u32::from_be_bytes(signed[length_offset..length_offset + 4].try_into().unwrap());
+    let mut truncated = signed;
+    truncated[length_offset..length_offset + 4].copy_from_slice(&(length - 4).to_be_bytes());
+
+    let problems = verify_macho_data(&truncated);
+    assert!(
+        matches!(
+            problem_types(&problems)[..],
+            [
+                VerificationProblemType::SuperblobLayout(_),
+                VerificationProblemType::NoCryptographicSignature
+            ]
+        ),
+        "{problems:?}"
+    );
+    assert_eq!(
+        failures(&truncated),
+        [
+            VerificationFailure::Layout,
+            VerificationFailure::ChainUntrusted
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn universal() -> Result<(), AppleCodesignError> {
+    for cputypes in [vec![CPU_TYPE_X86_64, CPU_TYPE_ARM64], vec![CPU_TYPE_ARM64]] {
+        let mut builder = UniversalBinaryBuilder::default();
+        for cputype in &cputypes {
+            builder.add_binary(macho(*cputype, 0))?;
+        }
+        let mut universal = vec![];
+        builder.write(&mut universal)?;
+
+        let signed = sign(&settings(), &universal)?;
+
+        // Slices stay in order in a universal binary, even if there is only one.
+        let arches = match goblin::mach::Mach::parse(&signed)? {
+            goblin::mach::Mach::Fat(multiarch) => multiarch.arches()?,
+            goblin::mach::Mach::Binary(_) => panic!("signed binary should be universal"),
+        };
+        assert_eq!(
+            arches.iter().map(|arch| arch.cputype).collect::<Vec<_>>(),
+            cputypes
+        );
+        for arch in &arches {
+            assert_eq!(arch.offset % (1 << arch.align), 0);
+        }
+
+        let mach = MachFile::parse(&signed)?;
+        for (index, macho) in mach.iter_macho().enumerate() {
+            assert_eq!(macho.index, Some(index));
+            assert!(macho.code_signature()?.is_some());
+        }
+
+        let problems = verify_macho_data(&signed);
+        assert_eq!(problems.len(), cputypes.len(), "{problems:?}");
+        assert!(problems.iter().all(|problem| matches!(
+            problem.problem,
+            VerificationProblemType::NoCryptographicSignature
+        )));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn universal_path() -> Result<(), AppleCodesignError> {
+    let mut builder = UniversalBinaryBuilder::default();
+    builder.add_binary(macho(CPU_TYPE_X86_64, 3))?;
+    builder.add_binary(macho(CPU_TYPE_ARM64, 0))?;
+
+    let mut universal = vec![];
+    builder.write(&mut universal)?;
+
+    let td = tempfile::tempdir()?;
+    let unsigned_path = td.path().join("unsigned");
+    let signed_path = td.path().join("signed");
+    std::fs::write(&unsigned_path, &universal)?;
+
+    let signer = UnifiedSigner::new(settings());
+    signer.sign_path(&unsigned_path, &signed_path)?;
+
+    let problems = verify_path(&signed_path);
+    assert_eq!(problems.len(), 2, "{problems:?}");
+    for (index, problem) in problems.iter().enumerate() {
+        assert_eq!(problem.context.fat_index, Some(index));
+        assert!(matches!(
+            problem.problem,
+            VerificationProblemType::NoCryptographicSignature
+        ));
+    }
+
+    // The unsigned input has no signature to verify.
+    assert!(verify_path(&unsigned_path).iter().all(|problem| matches!(
+        problem.problem,
+        VerificationProblemType::NoMachOSignatureData
+    )));
+
+    // Inputs are memory mapped. Signing in place must not read from the
+    // mapping after the output is written.
+    signer.sign_path_in_place(&unsigned_path)?;
+    assert_eq!(std::fs::read(&unsigned_path)?, std::fs::read(&signed_path)?);
+
+    Ok(())
+}
+
+#[test]
+fn info_plist_path() -> Result<(), AppleCodesignError> {
+    // A versioned framework's Info.plist isn't next to its binary.
+    let td = tempfile::tempdir()?;
+    let versions = td.path().join("MyFramework.framework/Versions/A");
+    std::fs::create_dir_all(versions.join("Resources"))?;
+    let info_plist = b"<plist version=\"1.0\"><dict/></plist>";
+    std::fs::write(versions.join("Resources/Info.plist"), info_plist)?;
+    let binary_path = versions.join("MyFramework");
+    std::fs::write(&binary_path, macho(CPU_TYPE_ARM64, 0))?;
+
+    let mut settings = settings();
+    settings.set_info_plist_path(SettingsScope::Main, "Resources/Info.plist");
+    UnifiedSigner::new(settings).sign_path_in_place(&binary_path)?;
+
+    let signed = std::fs::read(&binary_path)?;
+    let mach = MachFile::parse(&signed)?;
+    let cd = mach
+        .nth_macho(0)?
+        .code_signature()?
+        .unwrap()
+        .code_directory()?
+        .unwrap();
+    assert_eq!(
+        cd.special_digests[&CodeSigningSlot::Info].data.as_ref(),
+        DigestType::Sha256.digest_data(info_plist)?.as_slice()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn sha384_sha512_digests() -> Result<(), AppleCodesignError> {
+    let (cert, key, _) = create_self_signed_code_signing_certificate(
+        KeyAlgorithm::Ecdsa(EcdsaCurve::Secp384r1),
+        CertificateProfile::AppleDevelopment,
+        "team1",
+        "Acceptance Test",
+        "US",
+        chrono::Duration::hours(1),
+    )?;
+
+    for (digest, extra) in [
+        (DigestType::Sha384, DigestType::Sha256),
+        (DigestType::Sha512, DigestType::Sha384),
+        (DigestType::Sha256, DigestType::Sha512),
+    ] {
+        let mut settings = settings();
+        settings.set_signing_key(&key, cert.clone());
+        settings.set_digest_type(digest);
+        settings.add_extra_digest(SettingsScope::Main, extra);
+
+        let signed = sign(&settings, &macho(CPU_TYPE_ARM64, 0))?;
+
+        let problems = verify_macho_data(&signed);
+        assert!(problems.is_empty(), "{digest}: {problems:?}");
+
+        let mach = MachFile::parse(&signed)?;
+        let signature = mach.nth_macho(0)?.code_signature()?.unwrap();
+        for digest_type in [digest, extra] {
+            let cd = signature.code_directory_for_digest(digest_type)?.unwrap();
+            assert_eq!(cd.digest_size as usize, digest_type.hash_len()?);
+            assert!(cd
+                .code_digests
+                .iter()
+                .chain(cd.special_digests.values().map(|x| x.to_owned()))
+                .all(|x| x.data.len() == cd.digest_size as usize));
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn sha1_with_sha256_alternate() -> Result<(), AppleCodesignError> {
+    let (cert, key, _) = create_self_signed_code_signing_certificate(
+        KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1),
+        CertificateProfile::AppleDevelopment,
+        "team1",
+        "Acceptance Test",
+        "US",
+        chrono::Duration::hours(1),
+    )?;
+
+    let mut settings = settings();
+    settings.set_signing_key(&key, cert);
+    settings.set_digest_type(DigestType::Sha1);
+    settings.add_extra_digest(SettingsScope::Main, DigestType::Sha256);
+
+    let signed = sign(&settings, &macho(CPU_TYPE_ARM64, 0))?;
+
+    // The SHA-256 alternate satisfies the modern digest requirement.
+    let problems = verify_macho_data(&signed);
+    assert!(problems.is_empty(), "{problems:?}");
+
+    let mach = MachFile::parse(&signed)?;
+    let signature = mach.nth_macho(0)?.code_signature()?.unwrap();
+
+    let primary = signature.code_directory()?.unwrap();
+    assert_eq!(primary.digest_type, DigestType::Sha1);
+    let alternate = signature
+        .find_slot(CodeSigningSlot::AlternateCodeDirectory0)
+        .unwrap();
+    assert_eq!(
+        signature
+            .code_directory_for_digest(DigestType::Sha256)?
+            .unwrap()
+            .to_blob_bytes()?,
+        alternate.data
+    );
+
+    // Both cdhashes are bound by the CMS hash agility attributes.
+    let signed_data = signature.signed_data()?.unwrap();
+    let signer = CmsSigner::from_signer_info_and_signed_data(
+        signed_data.signers().next().unwrap(),
+        &signed_data,
+    )?;
+    assert_eq!(signer.cdhash_digests.len(), 2);
+    assert_eq!(
+        signer
+            .cdhash_plist
+            .iter()
+            .filter(|line| line.contains("<data>"))
+            .count(),
+        2
+    );
+
+    Ok(())
+}
+
+#[test]
+fn remove() -> Result<(), AppleCodesignError> {
+    let data = macho(CPU_TYPE_X86_64, 3);
+    let signed = sign(&settings(), &data)?;
+
+    // Removing the signature restores the original binary.
+    assert_eq!(remove_signature(&signed)?, data);
+    assert_eq!(remove_signature(&data)?, data);
+
+    let mut builder = UniversalBinaryBuilder::default();
+    builder.add_binary(&data)?;
+    builder.add_binary(macho(CPU_TYPE_ARM64, 0))?;
+    let mut universal = vec![];
+    builder.write(&mut universal)?;
+
+    let unsigned = remove_signature(&sign(&settings(), &universal)?)?;
+    assert_eq!(unsigned, universal);
+
+    Ok(())
+}
+
+#[test]
+fn library_constraints() -> Result<(), AppleCodesignError> {
+    let mut requirements = plist::Dictionary::new();
+    requirements.insert("team-identifier".into(), "ABCDE12345".into());
+    let requirements = plist::Value::Dictionary(requirements);
+
+    let mut settings = settings();
+    settings.set_library_constraints(SettingsScope::Main, requirements.clone());
+
+    let signed = sign(&settings, &macho(CPU_TYPE_X86_64, 3))?;
+    let problems = verify_macho_data(&signed);
+    assert!(
+        matches!(
+            problem_types(&problems)[..],
+            [VerificationProblemType::NoCryptographicSignature]
+        ),
+        "{problems:?}"
+    );
+
+    let mach = MachFile::parse(&signed)?;
+    let signature = mach.nth_macho(0)?.code_signature()?.unwrap();
+    let constraints = signature.library_constraints()?.unwrap();
+    assert_eq!(constraints.requirements()?, Some(requirements.clone()));
+    assert!(signature
+        .code_directory()?
+        .unwrap()
+        .slot_digest(CodeSigningSlot::LibraryConstraints)
+        .is_some());
+
+    // Re-signing preserves the constraints.
+    let imported = SigningSettings::from_existing_signature(&signature)?;
+    assert_eq!(
+        imported.library_constraints(SettingsScope::Main),
+        Some(&requirements)
+    );
+
+    Ok(())
+}
+
+/// Emit an unsigned XAR archive holding a single file.
+fn xar(content: &[u8]) -> Result<Vec<u8>, AppleCodesignError> {
+    let mut writer = XarWriter::new(ChecksumType::Sha1);
+    writer.set_compression(Compression::None);
+    writer.add_file_data("Distribution", content)?;
+
+    Ok(writer.to_vec()?)
+}
+
+#[test]
+fn xar_signing() -> Result<(), AppleCodesignError> {
+    let (cert, key, _) = create_self_signed_code_signing_certificate(
+        KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1),
+        CertificateProfile::DeveloperIdInstaller,
+        "team1",
+        "Acceptance Test",
+        "US",
+        chrono::Duration::hours(1),
+    )?;
+
+    let unsigned = xar(b"<installer-gui-script/>")?;
+    assert!(matches!(
+        problem_types(&verify_xar_data(&unsigned))[..],
+        [VerificationProblemType::NoCryptographicSignature]
+    ));
+
+    let td = tempfile::tempdir()?;
+    let path = td.path().join("installer.pkg");
+    std::fs::write(&path, &unsigned)?;
+
+    let mut settings = SigningSettings::default();
+    settings.set_signing_key(&key, cert);
+    settings.set_skip_certificate_validation(true);
+    UnifiedSigner::new(settings).sign_path_in_place(&path)?;
+
+    let problems = verify_path(&path);
+    assert!(problems.is_empty(), "{problems:?}");
+
+    // Tampering with file data is detected even though signatures only cover
+    // the table of contents.
+    let mut tampered = std::fs::read(&path)?;
+    let last = tampered.len() - 1;
+    tampered[last] ^= 0xff;
+    assert!(matches!(
+        problem_types(&verify_xar_data(&tampered))[..],
+        [VerificationProblemType::XarFileChecksumMismatch(path)] if path == "Distribution"
+    ));
+
+    Ok(())
+}
+
+/// Find the file offset of a load command by its type.
+fn load_command_offset(data: &[u8], cmd: u32) -> Option<usize> {
+    let read = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+
+    let mut offset = 32;
+    for _ in 0..read(16) {
+        if read(offset) == cmd {
+            return Some(offset);
+        }
+        offset += read(offset + 4) as usize;
+    }
+
+    None
+}
+
+/// Find the file offset of a blob in an embedded signature by its slot.
+fn blob_offset(data: &[u8], signature_offset: usize, slot: u32) -> Option<usize> {
+    let read = |offset: usize| u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+
+    (0..read(signature_offset + 8) as usize).find_map(|i| {
+        let entry = signature_offset + 12 + i * 8;
+
+        (read(entry) == slot).then(|| signature_offset + read(entry + 4) as usize)
+    })
+}
+
+/// Exercise all consumers of signature data. Errors are fine; panics are not.
+fn consume(data: &[u8]) -> Vec<VerificationProblem> {
+    let problems = verify_macho_data(data);
+
+    let _ = SigningSettings::default().import_settings_from_macho(data);
+    if let Ok(signer) = MachOSigner::new(data) {
+        let _ = signer.write_signed_binary(&settings(), &mut vec![]);
+    }
+
+    problems
+}
+
+#[test]
+fn malformed_signatures() -> Result<(), AppleCodesignError> {
+    const LC_CODE_SIGNATURE: u32 = 0x1d;
+
+    let signed = sign(&settings(), &macho(CPU_TYPE_ARM64, 0))?;
+    let command = load_command_offset(&signed, LC_CODE_SIGNATURE).unwrap();
+    let signature_offset = MachFile::parse(&signed)?
+        .nth_macho(0)?
+        .find_signature_data()?
+        .unwrap()
+        .linkedit_signature_start_offset;
+
+    // Signature offset and size pointing outside __LINKEDIT.
+    for field in [command + 8, command + 12] {
+        let mut data = signed.clone();
+        data[field..field + 4].copy_from_slice(&0x7fffffffu32.to_le_bytes());
+
+        assert!(matches!(
+            MachFile::parse(&data)?.nth_macho(0)?.find_signature_data(),
+            Err(AppleCodesignError::MachOParse(
+                MachOParseError::SignatureOutsideLinkedit {
+                    linkedit_start: 0x4000,
+                    ..
+                }
+            ))
+        ));
+        assert!(!consume(&data).is_empty());
+    }
+
+    // Identifier offset pointing outside the code directory.
+    let cd = blob_offset(&signed, signature_offset, 0).unwrap();
+    let mut data = signed.clone();
+    data[cd + 20..cd + 24].copy_from_slice(&0xffffu32.to_be_bytes());
+    let err = MachFile::parse(&data)?
+        .nth_macho(0)?
+        .code_signature()?
+        .unwrap()
+        .code_directory()
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "malformed Mach-O: malformed CodeDirectory (0) blob at signature offset 0x{:x}: \
+            malformed identifier string in code directory",
+            cd - signature_offset
+        )
+    );
+    assert!(matches!(
+        err,
+        AppleCodesignError::MachOParse(MachOParseError::Blob {
+            slot: CodeSigningSlot::CodeDirectory,
+            error,
+            ..
+        }) if matches!(*error, AppleCodesignError::CodeDirectoryMalformedIdentifier)
+    ));
+    assert!(!consume(&data).is_empty());
+
+    // Arbitrary values in the header, load commands and signature index.
+    for offset in (0..0x200).chain(signature_offset..cd + 0x60).step_by(4) {
+        for value in [0u32, 0x100, 0x7fffffff, 0xffffffff] {
+            for bytes in [value.to_le_bytes(), value.to_be_bytes()] {
+                let mut data = signed.clone();
+                data[offset..offset + 4].copy_from_slice(&bytes);
+                consume(&data);
+            }
+        }
+    }
+
+    // Truncated files.
+    for len in (0..signed.len()).step_by(0x101) {
+        consume(&signed[0..len]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn malformed_cms_signature() -> Result<(), AppleCodesignError> {
+    let (cert, key, _) = create_self_signed_code_signing_certificate(
+        KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1),
+        CertificateProfile::AppleDevelopment,
+        "team1",
+        "Acceptance Test",
+        "US",
+        chrono::Duration::hours(1),
+    )?;
+
+    let mut settings = settings();
+    settings.set_signing_key(&key, cert);
+
+    let signed = sign(&settings, &macho(CPU_TYPE_ARM64, 0))?;
+    let signature_offset = MachFile::parse(&signed)?
+        .nth_macho(0)?
+        .find_signature_data()?
+        .unwrap()
+        .linkedit_signature_start_offset;
+    let cms = blob_offset(&signed, signature_offset, 0x10000).unwrap();
+    let cms_len = u32::from_be_bytes(signed[cms + 4..cms + 8].try_into().unwrap()) as usize;
+
+    // Corrupted DER is reported as a problem instead of panicking the BER
+    // decoder.
+    let mut malformed = 0;
+    for offset in (cms + 8..cms + cms_len - 4).step_by(4) {
+        let mut data = signed.clone();
+        data[offset..offset + 4].copy_from_slice(&0x7fffffffu32.to_be_bytes());
+
+        if problem_types(&consume(&data)).iter().any(|problem| {
+            matches!(
+                problem,
+                VerificationProblemType::CmsError(CmsError::DecodeErr(_))
+            )
+        }) {
+            malformed += 1;
+        }
+    }
+    assert!(malformed > 0);
+
+    Ok(())
+}
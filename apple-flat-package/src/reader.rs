@@ -102,9 +102,7 @@ impl<R: Read + Seek + Sized + Debug> PkgReader<R> {
             .into_iter()
             .filter(|(filename, _)| filename.starts_with(&prefix))
         {
-            let mut data = Vec::<u8>::with_capacity(file.size.unwrap_or(0) as _);
-            self.xar
-                .write_file_data_decoded_from_file(&file, &mut data)?;
+            let data = self.xar.get_file_data_decoded_from_file(&file)?;
 
             let filename = filename.strip_prefix(&prefix).expect("prefix should match");
 
@@ -0,0 +1,88 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Compression of file data in the heap.
+//!
+//! The encoding of each file's data is recorded in the table of contents
+//! as a media type.
+
+use {
+    crate::{Error, XarResult},
+    std::io::Write,
+};
+
+/// A compression format for file data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compression {
+    /// Data is stored as-is.
+    None,
+
+    /// zlib compression.
+    ///
+    /// The media type is arguably wrong, as there is no gzip header.
+    Gzip,
+
+    /// bzip2 compression.
+    Bzip2,
+
+    /// xz compression.
+    Xz,
+}
+
+impl Compression {
+    /// Resolve an instance from the media type recorded in a table of contents.
+    pub fn from_media_type(media_type: &str) -> XarResult<Self> {
+        match media_type {
+            "application/octet-stream" => Ok(Self::None),
+            "application/x-gzip" => Ok(Self::Gzip),
+            "application/x-bzip2" => Ok(Self::Bzip2),
+            "application/x-lzma" => Ok(Self::Xz),
+            _ => Err(Error::UnimplementedFileEncoding(media_type.to_string())),
+        }
+    }
+
+    /// The media type recorded in a table of contents for this format.
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            Self::None => "application/octet-stream",
+            Self::Gzip => "application/x-gzip",
+            Self::Bzip2 => "application/x-bzip2",
+            Self::Xz => "application/x-lzma",
+        }
+    }
+
+    /// Wrap a writer so data written to it is decompressed.
+    pub fn decoder<'a>(&self, writer: impl Write + 'a) -> Box<dyn Write + 'a> {
+        match self {
+            Self::None => Box::new(writer),
+            Self::Gzip => Box::new(flate2::write::ZlibDecoder::new(writer)),
+            Self::Bzip2 => Box::new(bzip2::write::BzDecoder::new(writer)),
+            Self::Xz => Box::new(xz2::write::XzDecoder::new(writer)),
+        }
+    }
+
+    /// Compress a slice of data.
+    pub fn compress(&self, data: &[u8]) -> XarResult<Vec<u8>> {
+        Ok(match self {
+            Self::None => data.to_vec(),
+            Self::Gzip => {
+                let mut encoder =
+                    flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()?
+            }
+            Self::Bzip2 => {
+                let mut encoder =
+                    bzip2::write::BzEncoder::new(vec![], bzip2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()?
+            }
+            Self::Xz => {
+                let mut encoder = xz2::write::XzEncoder::new(vec![], 6);
+                encoder.write_all(data)?;
+                encoder.finish()?
+            }
+        })
+    }
+}
@@ -7,6 +7,9 @@ use {
     std::fmt::{Display, Formatter},
 };
 
+/// File magic of XAR archives (`xar!`).
+pub const XAR_MAGIC: u32 = 0x78617221;
+
 /// A XAR archive header.
 ///
 /// The header effectively defines a table of contents, which
@@ -4,10 +4,12 @@
 
 /*! XAR file format */
 
+pub mod compression;
 pub mod format;
 pub mod reader;
 pub mod signing;
 pub mod table_of_contents;
+pub mod writer;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -55,6 +57,9 @@ pub enum Error {
 
     #[error("HTTP error: {0}")]
     Reqwest(#[from] reqwest::Error),
+
+    #[error("{0} exceeds limit of {1} bytes")]
+    LimitExceeded(&'static str, u64),
 }
 
 pub type XarResult<T> = std::result::Result<T, Error>;
@@ -4,11 +4,13 @@
 
 use {
     crate::{
+        compression::Compression,
         format::{XarChecksum, XarHeader},
         table_of_contents::{ChecksumType, File, FileType, SignatureStyle, TableOfContents},
         Error, XarResult,
     },
     cryptographic_message_syntax::SignedData,
+    digest::DynDigest,
     scroll::IOread,
     std::{
         cmp::min,
@@ -19,12 +21,85 @@ use {
     x509_certificate::CapturedX509Certificate,
 };
 
+/// Adapts a hasher to [Write] so data can be digested as it is read.
+struct DigestWriter(Box<dyn DynDigest>);
+
+impl Write for DigestWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Limits on resources consumed when reading an archive.
+///
+/// Sizes recorded in archives are untrusted. These limits bound the memory
+/// used to hold data from an archive so crafted archives can't trigger huge
+/// allocations. The defaults are generous enough for legitimate archives.
+/// Scanners processing untrusted archives may wish to lower them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ReaderLimits {
+    /// Maximum size in bytes of the decompressed table of contents.
+    pub max_toc_size: u64,
+
+    /// Maximum size in bytes of a single piece of data buffered in memory.
+    ///
+    /// This applies to checksums, signatures, and file data returned as a
+    /// [Vec]. It does not apply to file data written to caller provided writers.
+    pub max_buffer_size: u64,
+}
+
+impl Default for ReaderLimits {
+    fn default() -> Self {
+        Self {
+            max_toc_size: 64 * 1024 * 1024,
+            max_buffer_size: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+/// Maximum number of bytes to allocate upfront based on a size recorded in an archive.
+const MAX_PREALLOCATION: u64 = 1024 * 1024;
+
+/// A writer failing once more than a given number of bytes are written.
+struct LimitedWriter<W: Write> {
+    inner: W,
+    remaining: u64,
+}
+
+impl<W: Write> Write for LimitedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.len() as u64 > self.remaining {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "data exceeds buffer size limit",
+            ));
+        }
+
+        let count = self.inner.write(buf)?;
+        self.remaining -= count as u64;
+
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 /// Read-only interface to a single XAR archive.
 #[derive(Debug)]
 pub struct XarReader<R: Read + Seek + Sized + Debug> {
     /// Reader of raw XAR archive content.
     reader: R,
 
+    /// Limits on resources consumed by reading.
+    limits: ReaderLimits,
+
     /// Parsed file header.
     header: XarHeader,
 
@@ -37,7 +112,14 @@ pub struct XarReader<R: Read + Seek + Sized + Debug> {
 
 impl<R: Read + Seek + Sized + Debug> XarReader<R> {
     /// Construct a new XAR reader from a stream reader.
-    pub fn new(mut reader: R) -> XarResult<Self> {
+    ///
+    /// [ReaderLimits::default()] limits are applied.
+    pub fn new(reader: R) -> XarResult<Self> {
+        Self::new_with_limits(reader, ReaderLimits::default())
+    }
+
+    /// Construct a new XAR reader from a stream reader, applying resource limits.
+    pub fn new_with_limits(mut reader: R, limits: ReaderLimits) -> XarResult<Self> {
         let header = reader.ioread_with::<XarHeader>(scroll::BE)?;
 
         let mut header_extra = vec![0u8; header.size as usize - 28];
@@ -47,25 +129,47 @@ impl<R: Read + Seek + Sized + Debug> XarReader<R> {
         // Unfortunately, serde_xml_rs takes ownership of the reader and doesn't
         // allow returning it. So we have to buffer decompressed data before feeding
         // it to the XML parser.
+        //
+        // The recorded uncompressed size is untrusted. So cap what we allocate
+        // and what we decompress.
         let toc_reader = reader.take(header.toc_length_compressed);
-        let mut toc_reader = flate2::read::ZlibDecoder::new(toc_reader);
-
-        let mut toc_data = Vec::with_capacity(header.toc_length_uncompressed as _);
+        let toc_reader = flate2::read::ZlibDecoder::new(toc_reader);
+        let mut toc_reader = toc_reader.take(limits.max_toc_size + 1);
+
+        let mut toc_data = Vec::with_capacity(
+            header
+                .toc_length_uncompressed
+                .min(limits.max_toc_size)
+                .min(MAX_PREALLOCATION) as _,
+        );
         toc_reader.read_to_end(&mut toc_data)?;
 
-        let mut reader = toc_reader.into_inner().into_inner();
+        if toc_data.len() as u64 > limits.max_toc_size {
+            return Err(Error::LimitExceeded(
+                "table of contents",
+                limits.max_toc_size,
+            ));
+        }
+
+        let mut reader = toc_reader.into_inner().into_inner().into_inner();
         let heap_start_offset = reader.stream_position()?;
 
         let toc = TableOfContents::from_reader(std::io::Cursor::new(toc_data))?;
 
         Ok(Self {
             reader,
+            limits,
             header,
             toc,
             heap_start_offset,
         })
     }
 
+    /// The resource limits applied by this reader.
+    pub fn limits(&self) -> ReaderLimits {
+        self.limits
+    }
+
     /// Obtain the inner reader.
     pub fn into_inner(self) -> R {
         self.reader
@@ -100,15 +204,7 @@ impl<R: Read + Seek + Sized + Debug> XarReader<R> {
 
     /// Obtain the raw bytes holding the checksum.
     pub fn checksum_data(&mut self) -> XarResult<Vec<u8>> {
-        let mut buf = Vec::with_capacity(self.toc.checksum.size as _);
-
-        self.write_heap_slice(
-            self.toc.checksum.offset,
-            self.toc.checksum.size as _,
-            &mut buf,
-        )?;
-
-        Ok(buf)
+        self.read_heap_slice("checksum", self.toc.checksum.offset, self.toc.checksum.size)
     }
 
     /// Digest the table of contents content with the specified algorithm.
@@ -165,6 +261,23 @@ impl<R: Read + Seek + Sized + Debug> XarReader<R> {
         Ok(())
     }
 
+    /// Read a slice of the heap into memory, enforcing the buffer size limit.
+    fn read_heap_slice(
+        &mut self,
+        what: &'static str,
+        offset: u64,
+        size: u64,
+    ) -> XarResult<Vec<u8>> {
+        if size > self.limits.max_buffer_size {
+            return Err(Error::LimitExceeded(what, self.limits.max_buffer_size));
+        }
+
+        let mut data = Vec::with_capacity(size.min(MAX_PREALLOCATION) as _);
+        self.write_heap_slice(offset, size as _, &mut data)?;
+
+        Ok(data)
+    }
+
     /// Write a slice of the heap to a writer.
     fn write_heap_slice(
         &mut self,
@@ -224,20 +337,7 @@ impl<R: Read + Seek + Sized + Debug> XarReader<R> {
     ) -> XarResult<usize> {
         let data = file.data.as_ref().ok_or(Error::FileNoData)?;
 
-        let mut writer = match data.encoding.style.as_str() {
-            "application/octet-stream" => Box::new(writer) as Box<dyn Write>,
-            "application/x-bzip2" => {
-                Box::new(bzip2::write::BzDecoder::new(writer)) as Box<dyn Write>
-            }
-            // The media type is arguably wrong, as there is no gzip header.
-            "application/x-gzip" => {
-                Box::new(flate2::write::ZlibDecoder::new(writer)) as Box<dyn Write>
-            }
-            "application/x-lzma" => Box::new(xz2::write::XzDecoder::new(writer)) as Box<dyn Write>,
-            encoding => {
-                return Err(Error::UnimplementedFileEncoding(encoding.to_string()));
-            }
-        };
+        let mut writer = Compression::from_media_type(&data.encoding.style)?.decoder(writer);
 
         self.write_file_data_heap_from_file(file, &mut writer)
     }
@@ -262,13 +362,32 @@ impl<R: Read + Seek + Sized + Debug> XarReader<R> {
         self.write_file_data_decoded_from_file(&file, writer)
     }
 
+    /// Obtain decoded file data for a given file record.
+    ///
+    /// Errors if the decoded data exceeds [ReaderLimits::max_buffer_size].
+    pub fn get_file_data_decoded_from_file(&mut self, file: &File) -> XarResult<Vec<u8>> {
+        let limit = self.limits.max_buffer_size;
+
+        if file.size.unwrap_or(0) > limit {
+            return Err(Error::LimitExceeded("file data", limit));
+        }
+
+        let mut writer = LimitedWriter {
+            inner: Vec::<u8>::with_capacity(file.size.unwrap_or(0).min(MAX_PREALLOCATION) as _),
+            remaining: limit,
+        };
+
+        match self.write_file_data_decoded_from_file(file, &mut writer) {
+            Ok(_) => Ok(writer.inner),
+            Err(_) if writer.remaining == 0 => Err(Error::LimitExceeded("file data", limit)),
+            Err(e) => Err(e),
+        }
+    }
+
     /// Resolve data for a given path.
     pub fn get_file_data_from_path(&mut self, path: &str) -> XarResult<Option<Vec<u8>>> {
         if let Some(file) = self.find_file(path)? {
-            let mut buffer = Vec::<u8>::with_capacity(file.size.unwrap_or(0) as _);
-            self.write_file_data_decoded_from_file(&file, &mut buffer)?;
-
-            Ok(Some(buffer))
+            Ok(Some(self.get_file_data_decoded_from_file(&file)?))
         } else {
             Ok(None)
         }
@@ -301,12 +420,7 @@ impl<R: Read + Seek + Sized + Debug> XarReader<R> {
     ///
     /// The checksum consists of a digest format and a raw digest.
     pub fn checksum(&mut self) -> XarResult<(ChecksumType, Vec<u8>)> {
-        let mut data = Vec::<u8>::with_capacity(self.toc.checksum.size as _);
-        self.write_heap_slice(
-            self.toc.checksum.offset,
-            self.toc.checksum.size as _,
-            &mut data,
-        )?;
+        let data = self.checksum_data()?;
 
         Ok((self.toc.checksum.style, data))
     }
@@ -323,13 +437,42 @@ impl<R: Read + Seek + Sized + Debug> XarReader<R> {
         Ok(actual_digest == recorded_digest)
     }
 
+    /// Validate the recorded archived checksums of files match their heap data.
+    ///
+    /// The table of contents records a digest of the heap data of every file. The
+    /// table of contents checksum, and thus signatures, only cover these digests.
+    /// So file data must be checked against them to be trusted.
+    ///
+    /// Returns the paths of files whose data doesn't match the recorded digest.
+    /// Will `Err` if file data can't be read or the recorded digests are malformed.
+    pub fn verify_file_checksums(&mut self) -> XarResult<Vec<String>> {
+        let mut mismatches = vec![];
+
+        for (path, file) in self.files()? {
+            let Some(data) = &file.data else {
+                continue;
+            };
+
+            let recorded = hex::decode(&data.archived_checksum.checksum)
+                .map_err(|_| Error::BadChecksum("archived checksum isn't hex"))?;
+
+            let mut writer = DigestWriter(data.archived_checksum.style.hasher()?);
+            self.write_heap_slice(data.offset, data.length as _, &mut writer)?;
+
+            if writer.0.finalize().as_ref() != recorded.as_slice() {
+                mismatches.push(path);
+            }
+        }
+
+        Ok(mismatches)
+    }
+
     /// Obtain RSA signature data from this archive.
     ///
     /// The returned tuple contains the raw signature data and the embedded X.509 certificates.
     pub fn rsa_signature(&mut self) -> XarResult<Option<(Vec<u8>, Vec<CapturedX509Certificate>)>> {
         if let Some(sig) = self.toc.find_signature(SignatureStyle::Rsa).cloned() {
-            let mut data = Vec::<u8>::with_capacity(sig.size as _);
-            self.write_heap_slice(sig.offset, sig.size as _, &mut data)?;
+            let data = self.read_heap_slice("RSA signature", sig.offset, sig.size)?;
 
             let certs = sig.x509_certificates()?;
 
@@ -366,8 +509,7 @@ impl<R: Read + Seek + Sized + Debug> XarReader<R> {
     /// The data signed by the CMS signature is the raw data returned by [Self::checksum].
     pub fn cms_signature(&mut self) -> XarResult<Option<SignedData>> {
         if let Some(sig) = self.toc.find_signature(SignatureStyle::Cms).cloned() {
-            let mut data = Vec::<u8>::with_capacity(sig.size as _);
-            self.write_heap_slice(sig.offset, sig.size as _, &mut data)?;
+            let data = self.read_heap_slice("CMS signature", sig.offset, sig.size)?;
 
             Ok(Some(SignedData::parse_ber(&data)?))
         } else {
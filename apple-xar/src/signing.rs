@@ -14,6 +14,11 @@
 //! including their offset, size, and public certificates - are included in the table
 //! of contents and are digested. So care must be taken to not alter the table of contents
 //! after signature generation time.
+//!
+//! The space reserved for a signature must be known before the signature is made. RSA
+//! signatures have a fixed size. Signatures made with other key types, such as DER
+//! encoded ECDSA signatures, vary in size. So the RSA signature is only emitted for RSA
+//! keys. The CMS signature is padded and emitted for all keys.
 
 use {
     crate::{
@@ -35,7 +40,7 @@ use {
         io::{Read, Seek, Write},
     },
     url::Url,
-    x509_certificate::{CapturedX509Certificate, KeyInfoSigner},
+    x509_certificate::{CapturedX509Certificate, KeyAlgorithm, KeyInfoSigner},
 };
 
 /// Entity for signing a XAR file.
@@ -83,8 +88,15 @@ impl<R: Read + Seek + Sized + Debug> XarSigner<R> {
         let empty_digest = self.checksum_type.digest_data(&random)?;
         let digest_size = empty_digest.len() as u64;
 
-        info!("performing empty RSA signature to calculate signature length");
-        let rsa_signature_len = signing_key.try_sign(&empty_digest)?.as_ref().len();
+        // The RSA signature is only emitted for RSA keys since other keys produce
+        // signatures whose size isn't known in advance.
+        let rsa_signature_len = if signing_cert.key_algorithm() == Some(KeyAlgorithm::Rsa) {
+            info!("performing empty RSA signature to calculate signature length");
+            Some(signing_key.try_sign(&empty_digest)?.as_ref().len())
+        } else {
+            info!("signing key isn't RSA; only emitting CMS signature");
+            None
+        };
 
         info!("performing empty CMS signature to calculate data length");
         let signer =
@@ -115,25 +127,32 @@ impl<R: Read + Seek + Sized + Debug> XarSigner<R> {
             size: digest_size,
         };
 
-        let rsa_signature = Signature {
-            style: SignatureStyle::Rsa,
-            // The RSA signature goes right after the digest data.
-            offset: digest_size,
-            size: rsa_signature_len as _,
-            key_info: KeyInfo::from_certificates(chain.iter().copied())?,
-        };
+        let rsa_signature = rsa_signature_len
+            .map(|size| -> XarResult<_> {
+                Ok(Signature {
+                    style: SignatureStyle::Rsa,
+                    // The RSA signature goes right after the digest data.
+                    offset: digest_size,
+                    size: size as _,
+                    key_info: KeyInfo::from_certificates(chain.iter().copied())?,
+                })
+            })
+            .transpose()?;
 
         let cms_signature = Signature {
             style: SignatureStyle::Cms,
-            // The CMS signature goes right after the RSA signature.
-            offset: rsa_signature.offset + rsa_signature.size,
+            // The CMS signature goes right after the RSA signature, if present.
+            offset: rsa_signature
+                .as_ref()
+                .map(|sig| sig.offset + sig.size)
+                .unwrap_or(digest_size),
             size: cms_signature_len as _,
             key_info: KeyInfo::from_certificates(chain.iter().copied())?,
         };
 
         let mut current_offset = cms_signature.offset + cms_signature.size;
 
-        toc.signature = Some(rsa_signature);
+        toc.signature = rsa_signature;
         toc.x_signature = Some(cms_signature);
 
         // Now go through and update file offsets. Files are nested. So we do a pass up
@@ -169,7 +188,11 @@ impl<R: Read + Seek + Sized + Debug> XarSigner<R> {
         let toc_digest = self.checksum_type.digest_data(&toc_compressed)?;
 
         // Sign it for real.
-        let rsa_signature = signing_key.try_sign(&toc_digest)?;
+        let rsa_signature = if rsa_signature_len.is_some() {
+            Some(signing_key.try_sign(&toc_digest)?)
+        } else {
+            None
+        };
 
         let mut cms_signature = SignedDataBuilder::default()
             .content_type(Oid(OID_ID_DATA.as_ref().into()))
@@ -190,7 +213,9 @@ impl<R: Read + Seek + Sized + Debug> XarSigner<R> {
         }
 
         // Now let's write everything out.
+        // Any bytes following the fixed size header aren't preserved.
         let mut header = *self.reader.header();
+        header.size = 28;
         header.checksum_algorithm_id = XarChecksum::from(self.checksum_type).into();
         header.toc_length_compressed = toc_compressed.len() as _;
         header.toc_length_uncompressed = toc_data.len() as _;
@@ -198,7 +223,9 @@ impl<R: Read + Seek + Sized + Debug> XarSigner<R> {
         writer.iowrite_with(header, scroll::BE)?;
         writer.write_all(&toc_compressed)?;
         writer.write_all(&toc_digest)?;
-        writer.write_all(rsa_signature.as_ref())?;
+        if let Some(rsa_signature) = &rsa_signature {
+            writer.write_all(rsa_signature.as_ref())?;
+        }
         writer.write_all(&cms_signature)?;
 
         // And write all the files to the heap.
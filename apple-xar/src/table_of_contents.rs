@@ -42,6 +42,12 @@ impl DerefMut for TableOfContents {
     }
 }
 
+impl From<XarToC> for TableOfContents {
+    fn from(toc: XarToC) -> Self {
+        Self { toc }
+    }
+}
+
 impl TableOfContents {
     /// Parse XML table of contents from a reader.
     pub fn from_reader(reader: impl Read) -> XarResult<Self> {
@@ -235,15 +241,20 @@ impl From<ChecksumType> for XarChecksum {
 }
 
 impl ChecksumType {
-    /// Digest a slice of data.
-    pub fn digest_data(&self, data: &[u8]) -> XarResult<Vec<u8>> {
-        let mut h: Box<dyn DynDigest> = match self {
+    /// Obtain a hasher computing this digest.
+    pub fn hasher(&self) -> XarResult<Box<dyn DynDigest>> {
+        Ok(match self {
             Self::None => return Err(Error::Unsupported("cannot digest None checksum")),
             Self::Md5 => Box::<md5::Md5>::default(),
             Self::Sha1 => Box::<sha1::Sha1>::default(),
             Self::Sha256 => Box::<sha2::Sha256>::default(),
             Self::Sha512 => Box::<sha2::Sha512>::default(),
-        };
+        })
+    }
+
+    /// Digest a slice of data.
+    pub fn digest_data(&self, data: &[u8]) -> XarResult<Vec<u8>> {
+        let mut h = self.hasher()?;
 
         h.update(data);
 
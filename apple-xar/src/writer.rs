@@ -0,0 +1,303 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! XAR writing.
+//!
+//! A XAR archive consists of a header, a zlib compressed XML table of contents,
+//! and a heap. The heap begins with the checksum of the compressed table of
+//! contents. File data follows.
+//!
+//! Archives are assembled in memory and written out in one go. Signing an
+//! archive rewrites it. See [crate::signing::XarSigner].
+
+use {
+    crate::{
+        compression::Compression,
+        format::{XarChecksum, XarHeader, XAR_MAGIC},
+        table_of_contents::{
+            Checksum, ChecksumType, File, FileChecksum, FileData, FileEncoding, FileType,
+            TableOfContents, XarToC,
+        },
+        Error, XarResult,
+    },
+    flate2::write::ZlibEncoder,
+    scroll::IOwrite,
+    std::io::Write,
+};
+
+/// Size in bytes of the header emitted by this writer.
+const HEADER_SIZE: u16 = 28;
+
+/// Entity for creating a XAR archive.
+///
+/// Files are added by their `/` delimited path within the archive. Parent
+/// directories are created automatically.
+#[derive(Clone, Debug)]
+pub struct XarWriter {
+    checksum_type: ChecksumType,
+    compression: Compression,
+    files: Vec<File>,
+    /// File data. Offsets are relative to the end of the table of contents checksum.
+    heap: Vec<u8>,
+    next_id: u64,
+}
+
+impl XarWriter {
+    /// Create a new, empty archive.
+    ///
+    /// `checksum_type` is used for the table of contents checksum and for file
+    /// checksums. File data is compressed with [Compression::Gzip] by default.
+    pub fn new(checksum_type: ChecksumType) -> Self {
+        Self {
+            checksum_type,
+            compression: Compression::Gzip,
+            files: vec![],
+            heap: vec![],
+            next_id: 1,
+        }
+    }
+
+    /// Set the compression format used for subsequently added files.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// Add a directory to the archive.
+    ///
+    /// Adding a directory that already exists is a no-op.
+    pub fn add_directory(&mut self, path: &str) -> XarResult<()> {
+        let components = Self::path_components(path)?;
+
+        self.resolve_directory(&components)?;
+
+        Ok(())
+    }
+
+    /// Add a regular file with the given content to the archive.
+    pub fn add_file_data(&mut self, path: &str, data: &[u8]) -> XarResult<()> {
+        let components = Self::path_components(path)?;
+        let (name, parents) = components
+            .split_last()
+            .ok_or(Error::Unsupported("empty path"))?;
+
+        if self
+            .resolve_directory(parents)?
+            .iter()
+            .any(|f| f.names.iter().any(|n| n == name))
+        {
+            return Err(Error::Unsupported("path already exists in archive"));
+        }
+
+        let encoded = self.compression.compress(data)?;
+
+        let file_data = FileData {
+            offset: self.heap.len() as _,
+            size: data.len() as _,
+            length: encoded.len() as _,
+            extracted_checksum: FileChecksum {
+                style: self.checksum_type,
+                checksum: hex::encode(self.checksum_type.digest_data(data)?),
+            },
+            archived_checksum: FileChecksum {
+                style: self.checksum_type,
+                checksum: hex::encode(self.checksum_type.digest_data(&encoded)?),
+            },
+            encoding: FileEncoding {
+                style: self.compression.media_type().to_string(),
+            },
+        };
+
+        let mut file = new_file(&mut self.next_id, name, FileType::File, "0644");
+        file.size = Some(data.len() as _);
+        file.data = Some(file_data);
+
+        self.resolve_directory(parents)?.push(file);
+
+        self.heap.extend(encoded);
+
+        Ok(())
+    }
+
+    /// Write the archive.
+    pub fn write<W: Write>(&self, writer: &mut W) -> XarResult<()> {
+        let digest_size = self.checksum_type.digest_data(&[])?.len() as u64;
+
+        let mut toc = TableOfContents::from(XarToC {
+            creation_time: chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+            checksum: Checksum {
+                style: self.checksum_type,
+                offset: 0,
+                size: digest_size,
+            },
+            files: self.files.clone(),
+            signature: None,
+            x_signature: None,
+        });
+
+        toc.visit_files_mut(&|file: &mut File| {
+            if let Some(data) = &mut file.data {
+                data.offset += digest_size;
+            }
+        });
+
+        let toc_data = toc.to_xml()?;
+
+        let mut zlib = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        zlib.write_all(&toc_data)?;
+        let toc_compressed = zlib.finish()?;
+
+        let header = XarHeader {
+            magic: XAR_MAGIC,
+            size: HEADER_SIZE,
+            version: 1,
+            toc_length_compressed: toc_compressed.len() as _,
+            toc_length_uncompressed: toc_data.len() as _,
+            checksum_algorithm_id: XarChecksum::from(self.checksum_type).into(),
+        };
+
+        writer.iowrite_with(header, scroll::BE)?;
+        writer.write_all(&toc_compressed)?;
+        writer.write_all(&self.checksum_type.digest_data(&toc_compressed)?)?;
+        writer.write_all(&self.heap)?;
+
+        Ok(())
+    }
+
+    /// Write the archive to a new buffer.
+    pub fn to_vec(&self) -> XarResult<Vec<u8>> {
+        let mut data = vec![];
+        self.write(&mut data)?;
+
+        Ok(data)
+    }
+
+    fn path_components(path: &str) -> XarResult<Vec<&str>> {
+        let components = path
+            .split('/')
+            .filter(|c| !c.is_empty())
+            .collect::<Vec<_>>();
+
+        if components.iter().any(|c| *c == "." || *c == "..") {
+            return Err(Error::Unsupported(
+                "relative path components in archive paths",
+            ));
+        }
+
+        Ok(components)
+    }
+
+    /// Resolve the children of a directory, creating directories as needed.
+    fn resolve_directory(&mut self, components: &[&str]) -> XarResult<&mut Vec<File>> {
+        let Self { files, next_id, .. } = self;
+
+        let mut files = files;
+
+        for name in components {
+            let index = match files.iter().position(|f| f.names.iter().any(|n| n == name)) {
+                Some(index) => index,
+                None => {
+                    files.push(new_file(next_id, name, FileType::Directory, "0755"));
+                    files.len() - 1
+                }
+            };
+
+            let file = &mut files[index];
+            if !matches!(file.file_type, FileType::Directory) {
+                return Err(Error::Unsupported("path component is not a directory"));
+            }
+
+            files = &mut file.files;
+        }
+
+        Ok(files)
+    }
+}
+
+/// Construct a [File] record, allocating the next file ID.
+fn new_file(next_id: &mut u64, name: &str, file_type: FileType, mode: &str) -> File {
+    let id = *next_id;
+    *next_id += 1;
+
+    File {
+        id,
+        ctime: None,
+        mtime: None,
+        atime: None,
+        names: vec![name.to_string()],
+        file_type,
+        mode: Some(mode.to_string()),
+        deviceno: None,
+        inode: None,
+        uid: None,
+        gid: None,
+        user: None,
+        group: None,
+        size: None,
+        data: None,
+        ea: None,
+        finder_create_time: None,
+        files: vec![],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::reader::XarReader, std::io::Cursor};
+
+    #[test]
+    fn write_read_roundtrip() -> XarResult<()> {
+        for compression in [
+            Compression::None,
+            Compression::Gzip,
+            Compression::Bzip2,
+            Compression::Xz,
+        ] {
+            let mut writer = XarWriter::new(ChecksumType::Sha1);
+            writer.set_compression(compression);
+            writer.add_file_data("Distribution", b"<installer-gui-script/>")?;
+            writer.add_directory("app.pkg")?;
+            writer.add_file_data("app.pkg/Payload", &b"payload".repeat(1000))?;
+            writer.add_file_data("app.pkg/Scripts/postinstall", b"#!/bin/sh\n")?;
+
+            assert!(matches!(
+                writer.add_file_data("app.pkg/Payload", b""),
+                Err(Error::Unsupported(_))
+            ));
+            assert!(matches!(
+                writer.add_file_data("Distribution/nested", b""),
+                Err(Error::Unsupported(_))
+            ));
+
+            let mut reader = XarReader::new(Cursor::new(writer.to_vec()?))?;
+
+            assert_eq!(reader.header().size, HEADER_SIZE);
+            assert!(reader.verify_table_of_contents_checksum()?);
+            assert!(reader.verify_file_checksums()?.is_empty());
+            assert_eq!(
+                reader
+                    .files()?
+                    .into_iter()
+                    .map(|(path, _)| path)
+                    .collect::<Vec<_>>(),
+                vec![
+                    "Distribution",
+                    "app.pkg",
+                    "app.pkg/Payload",
+                    "app.pkg/Scripts",
+                    "app.pkg/Scripts/postinstall"
+                ]
+            );
+            assert_eq!(
+                reader.get_file_data_from_path("app.pkg/Payload")?,
+                Some(b"payload".repeat(1000))
+            );
+            assert_eq!(
+                reader.get_file_data_from_path("app.pkg/Scripts/postinstall")?,
+                Some(b"#!/bin/sh\n".to_vec())
+            );
+        }
+
+        Ok(())
+    }
+}